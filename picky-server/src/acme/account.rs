@@ -0,0 +1,521 @@
+//! ACME accounts (RFC8555 section 7.3) and the request authentication they rely on.
+//!
+//! Requests are flattened JWS signed with the account key, carrying a replay nonce previously issued
+//! by the server and the URL they're posted to (section 6.2). Only RSA account keys are supported,
+//! and accounts must be bound to an external account key (see `eab`): the account is identified by
+//! the thumbprint of its key, recorded on the binding key along with the key itself. Account
+//! creation embeds the key in the request ('jwk'), other requests reference the account URL ('kid').
+
+use crate::{
+    acme::eab::{self, EabError},
+    db::{ExternalAccountKey, PickyStorage},
+};
+use picky::{
+    jose::{jwk::Jwk, jws::Jws},
+    key::PublicKey,
+};
+use rand::RngCore;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    collections::{HashSet, VecDeque},
+    fmt,
+    sync::Mutex,
+};
+
+pub const REPLAY_NONCE_HEADER: &str = "Replay-Nonce";
+
+const NONCE_LEN: usize = 16;
+/// Oldest nonces are forgotten past this count, clients retry with a fresh one on `badNonce`
+const MAX_NONCES: usize = 10_000;
+
+/// ACME error types (RFC8555 section 6.7)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AcmeErrorType {
+    AccountDoesNotExist,
+    BadNonce,
+    BadPublicKey,
+    ExternalAccountRequired,
+    Incorrect,
+    Malformed,
+    RejectedIdentifier,
+    ServerInternal,
+    Unauthorized,
+    UnsupportedIdentifier,
+}
+
+impl AcmeErrorType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AcmeErrorType::AccountDoesNotExist => "accountDoesNotExist",
+            AcmeErrorType::BadNonce => "badNonce",
+            AcmeErrorType::BadPublicKey => "badPublicKey",
+            AcmeErrorType::ExternalAccountRequired => "externalAccountRequired",
+            AcmeErrorType::Incorrect => "incorrect",
+            AcmeErrorType::Malformed => "malformed",
+            AcmeErrorType::RejectedIdentifier => "rejectedIdentifier",
+            AcmeErrorType::ServerInternal => "serverInternal",
+            AcmeErrorType::Unauthorized => "unauthorized",
+            AcmeErrorType::UnsupportedIdentifier => "unsupportedIdentifier",
+        }
+    }
+
+    pub fn type_uri(self) -> String {
+        format!("urn:ietf:params:acme:error:{}", self.as_str())
+    }
+}
+
+#[derive(Debug)]
+pub struct AcmeProblem {
+    pub error_type: AcmeErrorType,
+    pub detail: String,
+}
+
+impl AcmeProblem {
+    pub fn new(error_type: AcmeErrorType, detail: impl Into<String>) -> Self {
+        Self {
+            error_type,
+            detail: detail.into(),
+        }
+    }
+}
+
+impl fmt::Display for AcmeProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.error_type.as_str(), self.detail)
+    }
+}
+
+/// Replay nonces issued by this instance and not used yet.
+#[derive(Default)]
+pub struct NonceStore {
+    nonces: Mutex<(HashSet<String>, VecDeque<String>)>,
+}
+
+impl NonceStore {
+    pub fn issue(&self) -> String {
+        let mut nonce = vec![0; NONCE_LEN];
+        crate::random::with_rng(|rng| rng.fill_bytes(&mut nonce));
+        let nonce = base64::encode_config(&nonce, base64::URL_SAFE_NO_PAD);
+
+        let mut guard = self.nonces.lock().expect("nonces lock");
+        let (valid, issued) = &mut *guard;
+        valid.insert(nonce.clone());
+        issued.push_back(nonce.clone());
+        while issued.len() > MAX_NONCES {
+            if let Some(oldest) = issued.pop_front() {
+                valid.remove(&oldest);
+            }
+        }
+
+        nonce
+    }
+
+    /// Nonces are valid once.
+    pub fn consume(&self, nonce: &str) -> bool {
+        self.nonces.lock().expect("nonces lock").0.remove(nonce)
+    }
+}
+
+#[derive(Deserialize)]
+struct FlattenedJws {
+    protected: String,
+    payload: String,
+    signature: String,
+}
+
+/// Authenticated request signed with the account key embedded in its protected header.
+pub struct JwkRequest {
+    pub jwk: Value,
+    pub thumbprint: String,
+    pub payload: Value,
+}
+
+/// Authenticated request signed with the key of an existing account, referenced by its URL.
+pub struct KidRequest {
+    /// Thumbprint of the account key
+    pub account_id: String,
+    /// `Value::Null` for POST-as-GET requests (RFC8555 section 6.3)
+    pub payload: Value,
+}
+
+fn malformed(detail: impl Into<String>) -> AcmeProblem {
+    AcmeProblem::new(AcmeErrorType::Malformed, detail)
+}
+
+fn decode_jws(body: &[u8]) -> Result<(FlattenedJws, Value), AcmeProblem> {
+    let jws: FlattenedJws =
+        serde_json::from_slice(body).map_err(|e| malformed(format!("invalid flattened JWS: {}", e)))?;
+
+    let protected = base64::decode_config(&jws.protected, base64::URL_SAFE_NO_PAD)
+        .map_err(|e| e.to_string())
+        .and_then(|protected| serde_json::from_slice(&protected).map_err(|e| e.to_string()))
+        .map_err(|e| malformed(format!("invalid protected header: {}", e)))?;
+
+    Ok((jws, protected))
+}
+
+/// Checks the signature, consumes the nonce and checks the request was posted to `url`. Returns the payload.
+fn verify_jws(
+    jws: &FlattenedJws,
+    protected: &Value,
+    public_key: &PublicKey,
+    nonces: &NonceStore,
+    url: &str,
+) -> Result<Vec<u8>, AcmeProblem> {
+    let compact = format!("{}.{}.{}", jws.protected, jws.payload, jws.signature);
+    let verified = Jws::decode(&compact, public_key).map_err(|e| malformed(format!("invalid JWS: {}", e)))?;
+
+    let nonce = protected["nonce"]
+        .as_str()
+        .ok_or_else(|| AcmeProblem::new(AcmeErrorType::BadNonce, "nonce is missing"))?;
+    if !nonces.consume(nonce) {
+        return Err(AcmeProblem::new(
+            AcmeErrorType::BadNonce,
+            "nonce is unknown or already used",
+        ));
+    }
+    if protected["url"].as_str() != Some(url) {
+        return Err(AcmeProblem::new(AcmeErrorType::Unauthorized, "url mismatch"));
+    }
+
+    Ok(verified.payload)
+}
+
+/// External account key bound to the account, if the account exists.
+fn binding_key(storage: &dyn PickyStorage, account_id: &str) -> Result<Option<ExternalAccountKey>, AcmeProblem> {
+    let keys = storage.get_external_account_keys().map_err(|e| {
+        AcmeProblem::new(
+            AcmeErrorType::ServerInternal,
+            format!("couldn't fetch external account keys: {}", e),
+        )
+    })?;
+    Ok(keys.into_iter().find(|key| key.account.as_deref() == Some(account_id)))
+}
+
+impl JwkRequest {
+    /// Verifies the signature of `body`, consumes its nonce and checks it was posted to `url`.
+    pub fn decode(body: &[u8], nonces: &NonceStore, url: &str) -> Result<Self, AcmeProblem> {
+        let (jws, protected) = decode_jws(body)?;
+        if !protected["kid"].is_null() {
+            return Err(malformed(
+                "request must be signed with the account key ('jwk'), not 'kid'",
+            ));
+        }
+        let account_jwk = protected["jwk"].clone();
+        let jwk: Jwk = serde_json::from_value(account_jwk.clone())
+            .map_err(|e| AcmeProblem::new(AcmeErrorType::BadPublicKey, format!("invalid account key: {}", e)))?;
+        let public_key = jwk
+            .to_public_key()
+            .map_err(|e| AcmeProblem::new(AcmeErrorType::BadPublicKey, format!("unsupported account key: {}", e)))?;
+
+        let payload = verify_jws(&jws, &protected, &public_key, nonces, url)?;
+        let payload = serde_json::from_slice(&payload).map_err(|e| malformed(format!("invalid payload: {}", e)))?;
+        let thumbprint = jwk.thumbprint().map_err(|e| {
+            AcmeProblem::new(
+                AcmeErrorType::BadPublicKey,
+                format!("couldn't compute account key thumbprint: {}", e),
+            )
+        })?;
+
+        Ok(Self {
+            jwk: account_jwk,
+            thumbprint,
+            payload,
+        })
+    }
+}
+
+impl KidRequest {
+    /// Verifies the signature of `body` with the key of the account it references, consumes its nonce
+    /// and checks it was posted to `url`. Account URLs are `account_url_prefix` followed by the account id.
+    pub fn decode(
+        body: &[u8],
+        nonces: &NonceStore,
+        storage: &dyn PickyStorage,
+        account_url_prefix: &str,
+        url: &str,
+    ) -> Result<Self, AcmeProblem> {
+        let (jws, protected) = decode_jws(body)?;
+        if !protected["jwk"].is_null() {
+            return Err(malformed(
+                "request must reference the account URL ('kid'), not embed its key ('jwk')",
+            ));
+        }
+        let kid = protected["kid"]
+            .as_str()
+            .ok_or_else(|| malformed("account URL ('kid') is missing"))?;
+        let account_id = kid
+            .strip_prefix(account_url_prefix)
+            .filter(|account_id| !account_id.is_empty())
+            .ok_or_else(|| AcmeProblem::new(AcmeErrorType::AccountDoesNotExist, format!("unknown account {}", kid)))?;
+
+        let account_key = binding_key(storage, account_id)?
+            .and_then(|key| key.account_key)
+            .ok_or_else(|| AcmeProblem::new(AcmeErrorType::AccountDoesNotExist, format!("unknown account {}", kid)))?;
+        let public_key = serde_json::from_str::<Jwk>(&account_key)
+            .map_err(|e| e.to_string())
+            .and_then(|jwk| jwk.to_public_key().map_err(|e| e.to_string()))
+            .map_err(|e| {
+                AcmeProblem::new(
+                    AcmeErrorType::ServerInternal,
+                    format!("invalid stored key for account {}: {}", account_id, e),
+                )
+            })?;
+
+        let payload = verify_jws(&jws, &protected, &public_key, nonces, url)?;
+        let payload = if payload.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice(&payload).map_err(|e| malformed(format!("invalid payload: {}", e)))?
+        };
+
+        Ok(Self {
+            account_id: account_id.to_owned(),
+            payload,
+        })
+    }
+}
+
+/// Account identified by the thumbprint of its key.
+pub struct Account {
+    pub id: String,
+    /// External account key the account is bound to
+    pub key_id: String,
+    /// Whether the account was created by this request
+    pub created: bool,
+}
+
+/// Handles a newAccount request posted to `url`: returns the account of the request key if it exists,
+/// otherwise creates it after checking its external account binding.
+pub fn new_account(storage: &dyn PickyStorage, request: &JwkRequest, url: &str) -> Result<Account, AcmeProblem> {
+    if let Some(key) = binding_key(storage, &request.thumbprint)? {
+        return Ok(Account {
+            id: request.thumbprint.clone(),
+            key_id: key.key_id,
+            created: false,
+        });
+    }
+
+    if request.payload["onlyReturnExisting"].as_bool() == Some(true) {
+        return Err(AcmeProblem::new(
+            AcmeErrorType::AccountDoesNotExist,
+            "no account exists for this key",
+        ));
+    }
+
+    let binding = &request.payload["externalAccountBinding"];
+    if binding.is_null() {
+        return Err(AcmeProblem::new(
+            AcmeErrorType::ExternalAccountRequired,
+            "accounts must be bound to an external account key",
+        ));
+    }
+    let key = eab::bind_account(storage, binding, &request.jwk, url).map_err(|e| match e {
+        EabError::Storage { .. } => AcmeProblem::new(AcmeErrorType::ServerInternal, e.to_string()),
+        e => AcmeProblem::new(AcmeErrorType::Unauthorized, e.to_string()),
+    })?;
+
+    Ok(Account {
+        id: request.thumbprint.clone(),
+        key_id: key.key_id,
+        created: true,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        acme::eab::{provision_key, tests::binding},
+        config::{BackendType, Config},
+        db::get_storage,
+    };
+    use picky::{jose::jws::JwsHeader, key::PrivateKey, pem::Pem, signature::SignatureHashType};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    const NEW_ACCOUNT_URL: &str = "https://picky.example.com/acme/new-account";
+    const NEW_ORDER_URL: &str = "https://picky.example.com/acme/new-order";
+    const ACCOUNT_URL_PREFIX: &str = "https://picky.example.com/acme/acct/";
+
+    fn account_key() -> PrivateKey {
+        let pem = include_str!("../../../test_assets/private_keys/rsa-2048-pk_4.key")
+            .parse::<Pem>()
+            .expect("pem");
+        PrivateKey::from_pem(&pem).expect("key")
+    }
+
+    fn other_key() -> PrivateKey {
+        let pem = include_str!("../../../test_assets/private_keys/rsa-2048-pk_5.key")
+            .parse::<Pem>()
+            .expect("pem");
+        PrivateKey::from_pem(&pem).expect("key")
+    }
+
+    fn storage() -> Arc<dyn PickyStorage> {
+        let mut config = Config::default();
+        config.backend = BackendType::Memory;
+        get_storage(&config).expect("storage").0
+    }
+
+    /// Flattened JWS with the account key in the protected header.
+    fn request(key: &PrivateKey, nonce: &str, url: &str, payload: &Value) -> Vec<u8> {
+        let jwk = Jwk::from_public_key(&key.to_public_key()).expect("jwk");
+        let account = json!({ "jwk": serde_json::to_value(jwk).expect("jwk json") });
+        sign(key, account, nonce, url, payload.to_string().as_bytes())
+    }
+
+    /// Flattened JWS referencing the account URL, `payload` is empty for POST-as-GET requests.
+    fn kid_request(key: &PrivateKey, kid: &str, nonce: &str, url: &str, payload: &[u8]) -> Vec<u8> {
+        sign(key, json!({ "kid": kid }), nonce, url, payload)
+    }
+
+    fn sign(key: &PrivateKey, account: Value, nonce: &str, url: &str, payload: &[u8]) -> Vec<u8> {
+        let encode = |data: &[u8]| base64::encode_config(data, base64::URL_SAFE_NO_PAD);
+        let mut protected = serde_json::to_value(JwsHeader::new(SignatureHashType::RsaSha256)).expect("header");
+        for (name, value) in account.as_object().expect("account header") {
+            protected[name] = value.clone();
+        }
+        protected["nonce"] = json!(nonce);
+        protected["url"] = json!(url);
+
+        let protected = encode(protected.to_string().as_bytes());
+        let payload = encode(payload);
+        let signature = SignatureHashType::RsaSha256
+            .sign(format!("{}.{}", protected, payload).as_bytes(), key)
+            .expect("sign");
+
+        json!({ "protected": protected, "payload": payload, "signature": encode(&signature) })
+            .to_string()
+            .into_bytes()
+    }
+
+    #[test]
+    fn request_authentication() {
+        let key = account_key();
+        let nonces = NonceStore::default();
+        let payload = json!({ "termsOfServiceAgreed": true });
+
+        let nonce = nonces.issue();
+        let body = request(&key, &nonce, NEW_ACCOUNT_URL, &payload);
+        let decoded = JwkRequest::decode(&body, &nonces, NEW_ACCOUNT_URL).unwrap();
+        assert_eq!(decoded.payload, payload);
+        assert_eq!(
+            decoded.thumbprint,
+            Jwk::from_public_key(&key.to_public_key())
+                .unwrap()
+                .thumbprint()
+                .unwrap()
+        );
+
+        // replayed
+        let err = JwkRequest::decode(&body, &nonces, NEW_ACCOUNT_URL).err().unwrap();
+        assert_eq!(err.error_type, AcmeErrorType::BadNonce);
+
+        let body = request(&key, &nonces.issue(), "https://picky.example.com/acme/other", &payload);
+        let err = JwkRequest::decode(&body, &nonces, NEW_ACCOUNT_URL).err().unwrap();
+        assert_eq!(err.error_type, AcmeErrorType::Unauthorized);
+
+        let mut tampered: Value =
+            serde_json::from_slice(&request(&key, &nonces.issue(), NEW_ACCOUNT_URL, &payload)).unwrap();
+        tampered["payload"] = json!(base64::encode_config(b"{}", base64::URL_SAFE_NO_PAD));
+        let err = JwkRequest::decode(tampered.to_string().as_bytes(), &nonces, NEW_ACCOUNT_URL)
+            .err()
+            .unwrap();
+        assert_eq!(err.error_type, AcmeErrorType::Malformed);
+    }
+
+    #[test]
+    fn accounts_require_external_account_binding() {
+        let storage = storage();
+        let nonces = NonceStore::default();
+        let key = account_key();
+        let jwk = serde_json::to_value(Jwk::from_public_key(&key.to_public_key()).unwrap()).unwrap();
+        let new_account_request = |payload: Value| {
+            let body = request(&key, &nonces.issue(), NEW_ACCOUNT_URL, &payload);
+            JwkRequest::decode(&body, &nonces, NEW_ACCOUNT_URL).unwrap()
+        };
+
+        let err = new_account(storage.as_ref(), &new_account_request(json!({})), NEW_ACCOUNT_URL)
+            .err()
+            .unwrap();
+        assert_eq!(err.error_type, AcmeErrorType::ExternalAccountRequired);
+
+        let err = new_account(
+            storage.as_ref(),
+            &new_account_request(json!({ "onlyReturnExisting": true })),
+            NEW_ACCOUNT_URL,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err.error_type, AcmeErrorType::AccountDoesNotExist);
+
+        let eab_key = provision_key(storage.as_ref()).unwrap();
+        let payload = json!({ "externalAccountBinding": binding(&eab_key, &jwk, NEW_ACCOUNT_URL) });
+        let created = new_account(storage.as_ref(), &new_account_request(payload), NEW_ACCOUNT_URL).unwrap();
+        assert!(created.created);
+        assert_eq!(created.key_id, eab_key.key_id);
+
+        // the account key is now known, no binding needed
+        let existing = new_account(
+            storage.as_ref(),
+            &new_account_request(json!({ "onlyReturnExisting": true })),
+            NEW_ACCOUNT_URL,
+        )
+        .unwrap();
+        assert!(!existing.created);
+        assert_eq!(existing.id, created.id);
+    }
+
+    #[test]
+    fn kid_request_authentication() {
+        let storage = storage();
+        let nonces = NonceStore::default();
+        let key = account_key();
+        let jwk = serde_json::to_value(Jwk::from_public_key(&key.to_public_key()).unwrap()).unwrap();
+        let eab_key = provision_key(storage.as_ref()).unwrap();
+        let payload = json!({ "externalAccountBinding": binding(&eab_key, &jwk, NEW_ACCOUNT_URL) });
+        let body = request(&key, &nonces.issue(), NEW_ACCOUNT_URL, &payload);
+        let new_account_request = JwkRequest::decode(&body, &nonces, NEW_ACCOUNT_URL).unwrap();
+        let account = new_account(storage.as_ref(), &new_account_request, NEW_ACCOUNT_URL).unwrap();
+        let kid = format!("{}{}", ACCOUNT_URL_PREFIX, account.id);
+        let decode =
+            |body: &[u8]| KidRequest::decode(body, &nonces, storage.as_ref(), ACCOUNT_URL_PREFIX, NEW_ORDER_URL);
+
+        let payload = json!({ "identifiers": [{ "type": "dns", "value": "example.com" }] });
+        let body = kid_request(
+            &key,
+            &kid,
+            &nonces.issue(),
+            NEW_ORDER_URL,
+            payload.to_string().as_bytes(),
+        );
+        let decoded = decode(&body).unwrap();
+        assert_eq!(decoded.account_id, account.id);
+        assert_eq!(decoded.payload, payload);
+
+        // POST-as-GET
+        let body = kid_request(&key, &kid, &nonces.issue(), NEW_ORDER_URL, b"");
+        assert_eq!(decode(&body).unwrap().payload, Value::Null);
+
+        let body = kid_request(&other_key(), &kid, &nonces.issue(), NEW_ORDER_URL, b"");
+        assert_eq!(decode(&body).err().unwrap().error_type, AcmeErrorType::Malformed);
+
+        let unknown = format!("{}unknown", ACCOUNT_URL_PREFIX);
+        let body = kid_request(&key, &unknown, &nonces.issue(), NEW_ORDER_URL, b"");
+        assert_eq!(
+            decode(&body).err().unwrap().error_type,
+            AcmeErrorType::AccountDoesNotExist
+        );
+
+        let body = kid_request(&key, &account.id, &nonces.issue(), NEW_ORDER_URL, b"");
+        assert_eq!(
+            decode(&body).err().unwrap().error_type,
+            AcmeErrorType::AccountDoesNotExist
+        );
+
+        let body = request(&key, &nonces.issue(), NEW_ORDER_URL, &json!({}));
+        assert_eq!(decode(&body).err().unwrap().error_type, AcmeErrorType::Malformed);
+    }
+}
@@ -0,0 +1,956 @@
+//! DNS-01 challenge validation (RFC8555 section 8.4).
+//!
+//! Challenge records are published through a `DnsProvider` when one is configured for the zone of
+//! the challenged name, then looked up before being removed. `Rfc2136Provider` talks to any
+//! authoritative server accepting dynamic updates (BIND, Knot, PowerDNS...), optionally
+//! authenticated with a TSIG key: responses to signed updates must then be signed with the same key.
+//! Each validation is recorded in the audit log.
+
+use crate::{
+    audit::{self, AuditEvent},
+    config::ConfigError,
+    db::PickyStorage,
+    utils::unix_epoch,
+};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::{
+    net::{SocketAddr, UdpSocket},
+    ops::Range,
+    time::Duration,
+};
+
+/// Publishes and removes the TXT records used by dns-01 validation.
+pub trait DnsProvider: Send + Sync {
+    fn set_txt_record(&self, name: &str, value: &str, ttl: u32) -> Result<(), String>;
+    fn remove_txt_record(&self, name: &str, value: &str) -> Result<(), String>;
+}
+
+/// Name of the TXT record validating `domain`, wildcard domains are validated on their base domain.
+pub fn challenge_record_name(domain: &str) -> String {
+    format!(
+        "_acme-challenge.{}",
+        domain.trim_start_matches("*.").trim_end_matches('.')
+    )
+}
+
+/// TXT record value: base64url-encoded SHA-256 digest of the key authorization.
+pub fn challenge_record_value(token: &str, account_thumbprint: &str) -> String {
+    let key_authorization = format!("{}.{}", token, account_thumbprint);
+    base64::encode_config(&Sha256::digest(key_authorization.as_bytes()), base64::URL_SAFE_NO_PAD)
+}
+
+const TYPE_SOA: u16 = 6;
+const TYPE_TXT: u16 = 16;
+const TYPE_TSIG: u16 = 250;
+const CLASS_IN: u16 = 1;
+const CLASS_NONE: u16 = 254;
+const CLASS_ANY: u16 = 255;
+const OPCODE_UPDATE: u16 = 5;
+const FLAG_RECURSION_DESIRED: u16 = 0x0100;
+const RCODE_NXDOMAIN: u8 = 3;
+const TSIG_ALGORITHM: &str = "hmac-sha256";
+const TSIG_FUDGE: u16 = 300;
+const RCODE_NAMES: [&str; 11] = [
+    "NOERROR", "FORMERR", "SERVFAIL", "NXDOMAIN", "NOTIMP", "REFUSED", "YXDOMAIN", "YXRRSET", "NXRRSET", "NOTAUTH",
+    "NOTZONE",
+];
+/// Published challenge records only live for the duration of the validation
+const RECORD_TTL: u32 = 60;
+/// Messages are exchanged over UDP without EDNS
+const MAX_MESSAGE_LEN: usize = 512;
+
+const fn default_timeout_secs() -> u64 {
+    5
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Dns01Config {
+    /// Server challenge records are looked up on. Defaults to the server of the provider publishing them.
+    #[serde(default)]
+    pub resolver: Option<SocketAddr>,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Zones Picky publishes challenge records in
+    #[serde(default)]
+    pub providers: Vec<Rfc2136Config>,
+}
+
+impl Default for Dns01Config {
+    fn default() -> Self {
+        Self {
+            resolver: None,
+            timeout_secs: default_timeout_secs(),
+            providers: Vec::new(),
+        }
+    }
+}
+
+impl Dns01Config {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for provider in &self.providers {
+            if let Some(key) = &provider.tsig_key {
+                key.key().map_err(|_| ConfigError::InvalidTsigKey {
+                    zone: provider.zone.clone(),
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Provider of the closest zone enclosing `name`.
+    fn provider_for(&self, name: &str) -> Option<&Rfc2136Config> {
+        self.providers
+            .iter()
+            .filter(|provider| {
+                let zone = provider.zone.trim_end_matches('.').to_ascii_lowercase();
+                name == zone || name.ends_with(&format!(".{}", zone))
+            })
+            .max_by_key(|provider| provider.zone.trim_end_matches('.').len())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Rfc2136Config {
+    /// Zone updated with the challenge records of the names it encloses
+    pub zone: String,
+    /// Authoritative server of the zone, accepting dynamic updates
+    pub server: SocketAddr,
+    /// Updates are unsigned without a key
+    #[serde(default)]
+    pub tsig_key: Option<TsigKeyConfig>,
+}
+
+impl Rfc2136Config {
+    fn provider(&self, timeout: Duration) -> Result<Rfc2136Provider, String> {
+        let provider = Rfc2136Provider::new(self.server, &self.zone).with_timeout(timeout);
+        match &self.tsig_key {
+            Some(key) => Ok(provider.with_tsig_key(
+                key.key()
+                    .map_err(|e| format!("invalid tsig key for zone {}: {}", self.zone, e))?,
+            )),
+            None => Ok(provider),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TsigKeyConfig {
+    pub name: String,
+    /// Base64-encoded HMAC-SHA256 secret
+    pub secret: String,
+}
+
+impl TsigKeyConfig {
+    fn key(&self) -> Result<TsigKey, base64::DecodeError> {
+        Ok(TsigKey {
+            name: self.name.clone(),
+            secret: base64::decode(&self.secret)?,
+        })
+    }
+}
+
+/// TSIG key (RFC8945) using HMAC-SHA256
+#[derive(Debug, Clone)]
+pub struct TsigKey {
+    pub name: String,
+    pub secret: Vec<u8>,
+}
+
+/// Dynamic update (RFC2136) client.
+#[derive(Debug, Clone)]
+pub struct Rfc2136Provider {
+    server: SocketAddr,
+    zone: String,
+    tsig_key: Option<TsigKey>,
+    timeout: Duration,
+}
+
+impl Rfc2136Provider {
+    pub fn new(server: SocketAddr, zone: &str) -> Self {
+        Self {
+            server,
+            zone: zone.to_owned(),
+            tsig_key: None,
+            timeout: Duration::from_secs(default_timeout_secs()),
+        }
+    }
+
+    pub fn with_tsig_key(mut self, key: TsigKey) -> Self {
+        self.tsig_key = Some(key);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn send(&self, name: &str, value: &str, class: u16, ttl: u32) -> Result<(), String> {
+        let id = random_id();
+        let mut message = encode_update(id, &self.zone, name, value, class, ttl)?;
+        let signature = match &self.tsig_key {
+            Some(key) => Some((key, append_tsig(&mut message, key, unix_epoch(), None)?)),
+            None => None,
+        };
+
+        let response = exchange(self.server, &message, self.timeout)?;
+        check_response(
+            id,
+            &response,
+            signature.as_ref().map(|(key, mac)| (*key, mac.as_slice())),
+            unix_epoch(),
+        )
+    }
+}
+
+impl DnsProvider for Rfc2136Provider {
+    fn set_txt_record(&self, name: &str, value: &str, ttl: u32) -> Result<(), String> {
+        self.send(name, value, CLASS_IN, ttl)
+    }
+
+    fn remove_txt_record(&self, name: &str, value: &str) -> Result<(), String> {
+        // class NONE deletes this exact record, leaving concurrent challenges for the same name alone
+        self.send(name, value, CLASS_NONE, 0)
+    }
+}
+
+/// Looks the challenge record of `domain` up, after publishing it when a provider is configured for its zone.
+pub fn validate(
+    config: &Dns01Config,
+    storage: &dyn PickyStorage,
+    domain: &str,
+    token: &str,
+    account_thumbprint: &str,
+) -> Result<(), String> {
+    let name = challenge_record_name(domain).to_ascii_lowercase();
+    let value = challenge_record_value(token, account_thumbprint);
+    let timeout = Duration::from_secs(config.timeout_secs);
+
+    let provider_config = config.provider_for(&name);
+    let provider = provider_config.map(|provider| provider.provider(timeout)).transpose()?;
+    let resolver = config
+        .resolver
+        .or_else(|| provider_config.map(|provider| provider.server))
+        .ok_or_else(|| format!("no resolver configured to look {} up", name))?;
+
+    let result = check_record(
+        provider.as_ref().map(|provider| provider as &dyn DnsProvider),
+        resolver,
+        timeout,
+        &name,
+        &value,
+    );
+
+    audit::append(
+        storage,
+        AuditEvent::ChallengeValidated,
+        json!({
+            "type": "dns-01",
+            "domain": domain,
+            "record": name,
+            "resolver": resolver.to_string(),
+            "published": provider.is_some(),
+            "valid": result.is_ok(),
+            "error": result.as_ref().err(),
+        }),
+    )
+    .map_err(|e| format!("couldn't record audit event: {}", e))?;
+
+    result.map_err(|e| format!("dns-01 validation failed for {}: {}", domain, e))
+}
+
+/// Publishes the record when a provider is given, checks `resolver` serves it and removes it.
+fn check_record(
+    provider: Option<&dyn DnsProvider>,
+    resolver: SocketAddr,
+    timeout: Duration,
+    name: &str,
+    value: &str,
+) -> Result<(), String> {
+    if let Some(provider) = provider {
+        provider
+            .set_txt_record(name, value, RECORD_TTL)
+            .map_err(|e| format!("couldn't publish {}: {}", name, e))?;
+    }
+
+    let values = query_txt(resolver, name, timeout);
+
+    if let Some(provider) = provider {
+        if let Err(e) = provider.remove_txt_record(name, value) {
+            log::warn!("couldn't remove challenge record {}: {}", name, e);
+        }
+    }
+
+    let values = values?;
+    if values.iter().any(|found| found == value) {
+        Ok(())
+    } else {
+        Err(format!(
+            "none of the {} TXT records of {} matches the key authorization",
+            values.len(),
+            name
+        ))
+    }
+}
+
+/// Looks the TXT records of `name` up, a name which doesn't exist has none.
+pub fn query_txt(server: SocketAddr, name: &str, timeout: Duration) -> Result<Vec<String>, String> {
+    let id = random_id();
+    let query = encode_query(id, name)?;
+    let response = exchange(server, &query, timeout)?;
+    parse_txt_response(id, &response)
+}
+
+fn random_id() -> u16 {
+    crate::random::with_rng(|rng| {
+        use rand::RngCore;
+        rng.next_u32() as u16
+    })
+}
+
+fn exchange(server: SocketAddr, message: &[u8], timeout: Duration) -> Result<Vec<u8>, String> {
+    let bind_address = if server.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let socket = UdpSocket::bind(bind_address).map_err(|e| format!("couldn't bind udp socket: {}", e))?;
+    socket
+        .set_read_timeout(Some(timeout))
+        .map_err(|e| format!("couldn't set udp socket timeout: {}", e))?;
+    socket
+        .connect(server)
+        .map_err(|e| format!("couldn't connect to {}: {}", server, e))?;
+    socket
+        .send(message)
+        .map_err(|e| format!("couldn't send dns message to {}: {}", server, e))?;
+
+    let mut response = vec![0; MAX_MESSAGE_LEN];
+    let len = socket
+        .recv(&mut response)
+        .map_err(|e| format!("no dns response from {}: {}", server, e))?;
+    response.truncate(len);
+    Ok(response)
+}
+
+fn encode_name(buf: &mut Vec<u8>, name: &str) -> Result<(), String> {
+    for label in name.trim_end_matches('.').split('.').filter(|label| !label.is_empty()) {
+        if label.len() > 63 {
+            return Err(format!("dns label too long: {}", label));
+        }
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label.to_ascii_lowercase().as_bytes());
+    }
+    buf.push(0);
+    Ok(())
+}
+
+fn encode_txt_rdata(value: &str) -> Vec<u8> {
+    let mut rdata = Vec::with_capacity(value.len() + 1);
+    for chunk in value.as_bytes().chunks(255) {
+        rdata.push(chunk.len() as u8);
+        rdata.extend_from_slice(chunk);
+    }
+    rdata
+}
+
+fn encode_header(id: u16, flags: u16, zone_count: u16, update_count: u16) -> Vec<u8> {
+    let mut message = Vec::with_capacity(256);
+    message.extend_from_slice(&id.to_be_bytes());
+    message.extend_from_slice(&flags.to_be_bytes());
+    message.extend_from_slice(&zone_count.to_be_bytes()); // question count for queries
+    message.extend_from_slice(&0u16.to_be_bytes()); // answer or prerequisite count
+    message.extend_from_slice(&update_count.to_be_bytes()); // authority or update count
+    message.extend_from_slice(&0u16.to_be_bytes()); // additional count
+    message
+}
+
+fn encode_query(id: u16, name: &str) -> Result<Vec<u8>, String> {
+    let mut message = encode_header(id, FLAG_RECURSION_DESIRED, 1, 0);
+    encode_name(&mut message, name)?;
+    message.extend_from_slice(&TYPE_TXT.to_be_bytes());
+    message.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(message)
+}
+
+fn encode_update(id: u16, zone: &str, name: &str, value: &str, class: u16, ttl: u32) -> Result<Vec<u8>, String> {
+    let mut message = encode_header(id, OPCODE_UPDATE << 11, 1, 1);
+
+    // zone section
+    encode_name(&mut message, zone)?;
+    message.extend_from_slice(&TYPE_SOA.to_be_bytes());
+    message.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+    // update section
+    let rdata = encode_txt_rdata(value);
+    encode_name(&mut message, name)?;
+    message.extend_from_slice(&TYPE_TXT.to_be_bytes());
+    message.extend_from_slice(&class.to_be_bytes());
+    message.extend_from_slice(&ttl.to_be_bytes());
+    message.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+    message.extend_from_slice(&rdata);
+
+    Ok(message)
+}
+
+/// MAC of a message and its TSIG variables (RFC8945 section 4.3). Responses also cover the request MAC.
+fn tsig_hmac(
+    key: &TsigKey,
+    request_mac: Option<&[u8]>,
+    message: &[u8],
+    time_signed: &[u8],
+    fudge: u16,
+) -> Result<Hmac<Sha256>, String> {
+    let mut key_name = Vec::new();
+    encode_name(&mut key_name, &key.name)?;
+    let mut algorithm = Vec::new();
+    encode_name(&mut algorithm, TSIG_ALGORITHM)?;
+
+    let mut mac = Hmac::<Sha256>::new_varkey(&key.secret).map_err(|_| "invalid tsig key".to_owned())?;
+    if let Some(request_mac) = request_mac {
+        mac.input(&(request_mac.len() as u16).to_be_bytes());
+        mac.input(request_mac);
+    }
+    mac.input(message);
+    mac.input(&key_name);
+    mac.input(&CLASS_ANY.to_be_bytes());
+    mac.input(&0u32.to_be_bytes()); // ttl
+    mac.input(&algorithm);
+    mac.input(time_signed);
+    mac.input(&fudge.to_be_bytes());
+    mac.input(&0u16.to_be_bytes()); // error
+    mac.input(&0u16.to_be_bytes()); // other len
+    Ok(mac)
+}
+
+/// Appends a TSIG record (RFC8945 section 4.2) to the message and returns its MAC.
+fn append_tsig(message: &mut Vec<u8>, key: &TsigKey, now: u64, request_mac: Option<&[u8]>) -> Result<Vec<u8>, String> {
+    let time_signed = &now.to_be_bytes()[2..];
+    let mac = tsig_hmac(key, request_mac, message, time_signed, TSIG_FUDGE)?
+        .result()
+        .code()
+        .to_vec();
+
+    let mut rdata = Vec::new();
+    encode_name(&mut rdata, TSIG_ALGORITHM)?;
+    rdata.extend_from_slice(time_signed);
+    rdata.extend_from_slice(&TSIG_FUDGE.to_be_bytes());
+    rdata.extend_from_slice(&(mac.len() as u16).to_be_bytes());
+    rdata.extend_from_slice(&mac);
+    rdata.extend_from_slice(&message[..2]); // original id
+    rdata.extend_from_slice(&0u16.to_be_bytes()); // error
+    rdata.extend_from_slice(&0u16.to_be_bytes()); // other len
+
+    encode_name(message, &key.name)?;
+    message.extend_from_slice(&TYPE_TSIG.to_be_bytes());
+    message.extend_from_slice(&CLASS_ANY.to_be_bytes());
+    message.extend_from_slice(&0u32.to_be_bytes());
+    message.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+    message.extend_from_slice(&rdata);
+
+    let additional_count = read_u16(message, 10)? + 1;
+    message[10..12].copy_from_slice(&additional_count.to_be_bytes());
+
+    Ok(mac)
+}
+
+fn truncated() -> String {
+    "truncated dns message".to_owned()
+}
+
+fn read_u16(message: &[u8], offset: usize) -> Result<u16, String> {
+    message
+        .get(offset..offset + 2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        .ok_or_else(truncated)
+}
+
+/// Offset following the (possibly compressed) name starting at `offset`.
+fn skip_name(message: &[u8], mut offset: usize) -> Result<usize, String> {
+    loop {
+        match message.get(offset).copied().ok_or_else(truncated)? {
+            0 => return Ok(offset + 1),
+            len if len & 0xc0 == 0xc0 => return Ok(offset + 2),
+            len => offset += 1 + usize::from(len),
+        }
+    }
+}
+
+/// Lowercase dotted name starting at `offset`, following compression pointers.
+fn read_name(message: &[u8], mut offset: usize) -> Result<String, String> {
+    let mut labels = Vec::new();
+    // a name can't jump more often than there are bytes to point to, unless it loops
+    let mut jumps = 0;
+    loop {
+        match message.get(offset).copied().ok_or_else(truncated)? {
+            0 => return Ok(labels.join(".")),
+            len if len & 0xc0 == 0xc0 => {
+                jumps += 1;
+                if jumps > message.len() {
+                    return Err("dns name compression loop".to_owned());
+                }
+                offset = usize::from(read_u16(message, offset)? & 0x3fff);
+            }
+            len => {
+                let label = message
+                    .get(offset + 1..offset + 1 + usize::from(len))
+                    .ok_or_else(truncated)?;
+                labels.push(String::from_utf8_lossy(label).to_ascii_lowercase());
+                offset += 1 + usize::from(len);
+            }
+        }
+    }
+}
+
+struct Record {
+    rtype: u16,
+    class: u16,
+    rdata: Range<usize>,
+}
+
+fn read_record(message: &[u8], offset: usize) -> Result<Record, String> {
+    let fixed = skip_name(message, offset)?;
+    let rtype = read_u16(message, fixed)?;
+    let class = read_u16(message, fixed + 2)?;
+    let rdata_len = usize::from(read_u16(message, fixed + 8)?);
+    let rdata = fixed + 10..fixed + 10 + rdata_len;
+    if rdata.end > message.len() {
+        return Err(truncated());
+    }
+
+    Ok(Record { rtype, class, rdata })
+}
+
+fn rcode_name(rcode: u8) -> &'static str {
+    RCODE_NAMES.get(usize::from(rcode)).copied().unwrap_or("unknown error")
+}
+
+/// Checks the response matches the request and returns its response code.
+fn check_header(id: u16, response: &[u8]) -> Result<u8, String> {
+    if response.len() < 12 {
+        return Err(truncated());
+    }
+    if response[..2] != id.to_be_bytes() {
+        return Err("dns response id mismatch".to_owned());
+    }
+    if response[2] & 0x80 == 0 {
+        return Err("dns message isn't a response".to_owned());
+    }
+
+    Ok(response[3] & 0x0f)
+}
+
+fn parse_txt_response(id: u16, response: &[u8]) -> Result<Vec<String>, String> {
+    match check_header(id, response)? {
+        0 => {}
+        RCODE_NXDOMAIN => return Ok(Vec::new()),
+        rcode => return Err(format!("dns query failed: {}", rcode_name(rcode))),
+    }
+    if response[2] & 0x02 != 0 {
+        return Err("dns response is truncated".to_owned());
+    }
+
+    let mut offset = 12;
+    for _ in 0..read_u16(response, 4)? {
+        offset = skip_name(response, offset)? + 4;
+    }
+
+    let mut values = Vec::new();
+    for _ in 0..read_u16(response, 6)? {
+        let record = read_record(response, offset)?;
+        if record.rtype == TYPE_TXT && record.class == CLASS_IN {
+            let rdata = &response[record.rdata.clone()];
+            let mut value = Vec::with_capacity(rdata.len());
+            let mut string = 0;
+            while string < rdata.len() {
+                let len = usize::from(rdata[string]);
+                value.extend_from_slice(rdata.get(string + 1..string + 1 + len).ok_or_else(truncated)?);
+                string += 1 + len;
+            }
+            values.push(String::from_utf8_lossy(&value).into_owned());
+        }
+        offset = record.rdata.end;
+    }
+
+    Ok(values)
+}
+
+/// Checks the TSIG record closing an update response (RFC8945 section 5.3).
+fn verify_response_tsig(response: &[u8], key: &TsigKey, request_mac: &[u8], now: u64) -> Result<(), String> {
+    let unsigned = || format!("dns update response isn't signed ({})", rcode_name(response[3] & 0x0f));
+
+    let additional_count = read_u16(response, 10)?;
+    if additional_count == 0 {
+        return Err(unsigned());
+    }
+    let record_count =
+        usize::from(read_u16(response, 6)?) + usize::from(read_u16(response, 8)?) + usize::from(additional_count);
+
+    let mut offset = 12;
+    for _ in 0..read_u16(response, 4)? {
+        offset = skip_name(response, offset)? + 4;
+    }
+    let mut tsig = None;
+    for _ in 0..record_count {
+        let record = read_record(response, offset)?;
+        let start = offset;
+        offset = record.rdata.end;
+        tsig = Some((start, record));
+    }
+    let (tsig_start, record) = tsig.ok_or_else(unsigned)?;
+    if record.rtype != TYPE_TSIG {
+        return Err(unsigned());
+    }
+
+    if read_name(response, tsig_start)? != key.name.trim_end_matches('.').to_ascii_lowercase() {
+        return Err("dns update response is signed with another key".to_owned());
+    }
+    if read_name(response, record.rdata.start)? != TSIG_ALGORITHM {
+        return Err("dns update response is signed with another algorithm".to_owned());
+    }
+
+    let rdata = response
+        .get(skip_name(response, record.rdata.start)?..record.rdata.end)
+        .ok_or_else(truncated)?;
+    let time_signed = rdata.get(..6).ok_or_else(truncated)?;
+    let fudge = read_u16(rdata, 6)?;
+    let mac_len = usize::from(read_u16(rdata, 8)?);
+    let mac = rdata.get(10..10 + mac_len).ok_or_else(truncated)?;
+    let original_id = rdata.get(10 + mac_len..12 + mac_len).ok_or_else(truncated)?;
+    match read_u16(rdata, 12 + mac_len)? {
+        0 => {}
+        16 => return Err("dns update rejected: tsig BADSIG".to_owned()),
+        17 => return Err("dns update rejected: tsig BADKEY".to_owned()),
+        18 => return Err("dns update rejected: tsig BADTIME".to_owned()),
+        error => return Err(format!("dns update rejected: tsig error {}", error)),
+    }
+
+    // the MAC covers the message as it was before the TSIG record was added
+    let mut signed = response[..tsig_start].to_vec();
+    signed[..2].copy_from_slice(original_id);
+    signed[10..12].copy_from_slice(&(additional_count - 1).to_be_bytes());
+    tsig_hmac(key, Some(request_mac), &signed, time_signed, fudge)?
+        .verify(mac)
+        .map_err(|_| "dns update response signature mismatch".to_owned())?;
+
+    let mut time = [0; 8];
+    time[2..].copy_from_slice(time_signed);
+    let time_signed = u64::from_be_bytes(time);
+    if time_signed.max(now) - time_signed.min(now) > u64::from(fudge) {
+        return Err("dns update response signature time is out of range".to_owned());
+    }
+
+    Ok(())
+}
+
+/// `signature` is the TSIG key and MAC of a signed request, its response must be signed too.
+fn check_response(id: u16, response: &[u8], signature: Option<(&TsigKey, &[u8])>, now: u64) -> Result<(), String> {
+    let rcode = check_header(id, response)?;
+    if let Some((key, request_mac)) = signature {
+        verify_response_tsig(response, key, request_mac, now)?;
+    }
+
+    match rcode {
+        0 => Ok(()),
+        rcode => Err(format!("dns update rejected: {}", rcode_name(rcode))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Mutex, thread};
+
+    fn tsig_key() -> TsigKey {
+        TsigKey {
+            name: "picky".to_owned(),
+            secret: b"secret".to_vec(),
+        }
+    }
+
+    /// Response to `request` echoing its zone section, as sent by an authoritative server.
+    fn update_response(request: &[u8], rcode: u8) -> Vec<u8> {
+        let zone_end = skip_name(request, 12).unwrap() + 4;
+        let mut response = request[..zone_end].to_vec();
+        response[2] |= 0x80;
+        response[3] = rcode;
+        response[8..12].copy_from_slice(&[0, 0, 0, 0]);
+        response
+    }
+
+    /// Response to `query` with a TXT record per value, owner names are compressed.
+    fn txt_response(query: &[u8], values: &[&str]) -> Vec<u8> {
+        let mut response = query.to_vec();
+        response[2] = 0x81;
+        response[3] = 0x80;
+        response[6..8].copy_from_slice(&(values.len() as u16).to_be_bytes());
+        for value in values {
+            let rdata = encode_txt_rdata(value);
+            response.extend_from_slice(&[0xc0, 12]);
+            response.extend_from_slice(&TYPE_TXT.to_be_bytes());
+            response.extend_from_slice(&CLASS_IN.to_be_bytes());
+            response.extend_from_slice(&60u32.to_be_bytes());
+            response.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+            response.extend_from_slice(&rdata);
+        }
+        response
+    }
+
+    #[test]
+    fn challenge_record() {
+        assert_eq!(challenge_record_name("*.example.com"), "_acme-challenge.example.com");
+        assert_eq!(
+            challenge_record_name("www.example.com."),
+            "_acme-challenge.www.example.com"
+        );
+
+        let value = challenge_record_value("token", "thumbprint");
+        assert_eq!(value.len(), 43);
+        assert_eq!(
+            base64::decode_config(&value, base64::URL_SAFE_NO_PAD).unwrap(),
+            Sha256::digest(b"token.thumbprint").to_vec()
+        );
+    }
+
+    #[test]
+    fn update_message() {
+        let message = encode_update(
+            0x1234,
+            "example.com",
+            "_acme-challenge.example.com",
+            "abc",
+            CLASS_IN,
+            60,
+        )
+        .unwrap();
+
+        #[rustfmt::skip]
+        let expected: &[u8] = &[
+            0x12, 0x34, 0x28, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
+            7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c', b'o', b'm', 0, 0x00, 0x06, 0x00, 0x01,
+            15, b'_', b'a', b'c', b'm', b'e', b'-', b'c', b'h', b'a', b'l', b'l', b'e', b'n', b'g', b'e',
+            7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c', b'o', b'm', 0,
+            0x00, 0x10, 0x00, 0x01, 0x00, 0x00, 0x00, 0x3c, 0x00, 0x04, 3, b'a', b'b', b'c',
+        ];
+        assert_eq!(message, expected);
+    }
+
+    #[test]
+    fn signed_update_message() {
+        let unsigned = encode_update(1, "example.com", "example.com", "abc", CLASS_NONE, 0).unwrap();
+        let mut signed = unsigned.clone();
+        let mac = append_tsig(&mut signed, &tsig_key(), 0, None).unwrap();
+
+        assert_eq!(mac.len(), 32);
+        assert_eq!(signed[10..12], [0, 1]);
+        assert_eq!(signed[12..unsigned.len()], unsigned[12..]);
+        // key name, type, class, ttl, rdlength, algorithm name, time, fudge, mac size, mac, id, error, other len
+        assert_eq!(signed.len() - unsigned.len(), 7 + 10 + 13 + 6 + 2 + 2 + 32 + 6);
+    }
+
+    #[test]
+    fn update_response_check() {
+        let request = encode_update(1, "example.com", "example.com", "abc", CLASS_IN, 60).unwrap();
+        assert!(check_response(1, &update_response(&request, 0), None, 0).is_ok());
+        assert_eq!(
+            check_response(1, &update_response(&request, 5), None, 0).unwrap_err(),
+            "dns update rejected: REFUSED"
+        );
+        assert_eq!(
+            check_response(2, &update_response(&request, 0), None, 0).unwrap_err(),
+            "dns response id mismatch"
+        );
+    }
+
+    #[test]
+    fn signed_update_response_check() {
+        let key = tsig_key();
+        let now = 1_600_000_000;
+        let mut request = encode_update(1, "example.com", "example.com", "abc", CLASS_IN, 60).unwrap();
+        let request_mac = append_tsig(&mut request, &key, now, None).unwrap();
+        let signature = Some((&key, request_mac.as_slice()));
+
+        let mut response = update_response(&request, 0);
+        append_tsig(&mut response, &key, now + 10, Some(&request_mac)).unwrap();
+        check_response(1, &response, signature, now).unwrap();
+
+        assert_eq!(
+            check_response(1, &update_response(&request, 0), signature, now).unwrap_err(),
+            "dns update response isn't signed (NOERROR)"
+        );
+
+        let mut tampered = response.clone();
+        tampered[3] = 5;
+        assert_eq!(
+            check_response(1, &tampered, signature, now).unwrap_err(),
+            "dns update response signature mismatch"
+        );
+
+        // replayed for another request
+        let other_mac = vec![0; 32];
+        assert_eq!(
+            check_response(1, &response, Some((&key, other_mac.as_slice())), now).unwrap_err(),
+            "dns update response signature mismatch"
+        );
+
+        let other_key = TsigKey {
+            name: "picky".to_owned(),
+            secret: b"other".to_vec(),
+        };
+        assert_eq!(
+            check_response(1, &response, Some((&other_key, request_mac.as_slice())), now).unwrap_err(),
+            "dns update response signature mismatch"
+        );
+
+        assert_eq!(
+            check_response(1, &response, signature, now + 1000).unwrap_err(),
+            "dns update response signature time is out of range"
+        );
+    }
+
+    #[test]
+    fn txt_lookup_response() {
+        let query = encode_query(7, "_acme-challenge.example.com").unwrap();
+
+        let long = "x".repeat(300);
+        let response = txt_response(&query, &["first", &long]);
+        assert_eq!(
+            parse_txt_response(7, &response).unwrap(),
+            vec!["first".to_owned(), long]
+        );
+
+        let mut nxdomain = txt_response(&query, &[]);
+        nxdomain[3] |= RCODE_NXDOMAIN;
+        assert!(parse_txt_response(7, &nxdomain).unwrap().is_empty());
+
+        let mut servfail = txt_response(&query, &[]);
+        servfail[3] |= 2;
+        assert_eq!(
+            parse_txt_response(7, &servfail).unwrap_err(),
+            "dns query failed: SERVFAIL"
+        );
+
+        assert_eq!(
+            parse_txt_response(7, &response[..response.len() - 1]).unwrap_err(),
+            truncated()
+        );
+    }
+
+    #[test]
+    fn compressed_names() {
+        let query = encode_query(7, "_acme-challenge.Example.com").unwrap();
+        let mut response = txt_response(&query, &["value"]);
+        let answer = query.len();
+        assert_eq!(read_name(&response, answer).unwrap(), "_acme-challenge.example.com");
+        assert_eq!(skip_name(&response, answer).unwrap(), answer + 2);
+
+        // pointer to itself
+        response[answer + 1] = answer as u8;
+        assert!(read_name(&response, answer).is_err());
+    }
+
+    #[derive(Default)]
+    struct RecordingProvider {
+        records: Mutex<Vec<(String, String, bool)>>,
+    }
+
+    impl DnsProvider for RecordingProvider {
+        fn set_txt_record(&self, name: &str, value: &str, _: u32) -> Result<(), String> {
+            self.records
+                .lock()
+                .unwrap()
+                .push((name.to_owned(), value.to_owned(), true));
+            Ok(())
+        }
+
+        fn remove_txt_record(&self, name: &str, value: &str) -> Result<(), String> {
+            self.records
+                .lock()
+                .unwrap()
+                .push((name.to_owned(), value.to_owned(), false));
+            Ok(())
+        }
+    }
+
+    /// Answers a single TXT query with `values`.
+    fn resolver(values: Vec<String>) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            let values = values.iter().map(String::as_str).collect::<Vec<_>>();
+            let mut query = vec![0; MAX_MESSAGE_LEN];
+            let (len, client) = socket.recv_from(&mut query).unwrap();
+            socket.send_to(&txt_response(&query[..len], &values), client).unwrap();
+        });
+        address
+    }
+
+    #[test]
+    fn record_check() {
+        let name = challenge_record_name("*.example.com");
+        let value = challenge_record_value("token", "thumbprint");
+        let timeout = Duration::from_secs(5);
+        let provider = RecordingProvider::default();
+
+        let published = resolver(vec!["other".to_owned(), value.clone()]);
+        check_record(Some(&provider), published, timeout, &name, &value).unwrap();
+        assert_eq!(
+            *provider.records.lock().unwrap(),
+            vec![
+                (name.clone(), value.clone(), true),
+                (name.clone(), value.clone(), false)
+            ]
+        );
+
+        let err = check_record(None, resolver(vec!["other".to_owned()]), timeout, &name, &value).unwrap_err();
+        assert_eq!(
+            err,
+            "none of the 1 TXT records of _acme-challenge.example.com matches the key authorization"
+        );
+    }
+
+    #[test]
+    fn provider_selection() {
+        let provider = |zone: &str| Rfc2136Config {
+            zone: zone.to_owned(),
+            server: "192.0.2.53:53".parse().unwrap(),
+            tsig_key: None,
+        };
+        let config = Dns01Config {
+            providers: vec![provider("example.com."), provider("internal.example.com")],
+            ..Dns01Config::default()
+        };
+
+        let zone = |name: &str| config.provider_for(name).map(|provider| provider.zone.as_str());
+        assert_eq!(zone("_acme-challenge.example.com"), Some("example.com."));
+        assert_eq!(
+            zone("_acme-challenge.www.internal.example.com"),
+            Some("internal.example.com")
+        );
+        assert_eq!(zone("_acme-challenge.notexample.com"), None);
+    }
+
+    #[test]
+    fn tsig_key_config() {
+        let mut config = Dns01Config {
+            providers: vec![Rfc2136Config {
+                zone: "example.com".to_owned(),
+                server: "192.0.2.53:53".parse().unwrap(),
+                tsig_key: Some(TsigKeyConfig {
+                    name: "picky".to_owned(),
+                    secret: base64::encode(b"secret"),
+                }),
+            }],
+            ..Dns01Config::default()
+        };
+        config.validate().unwrap();
+        let provider = config.providers[0].provider(Duration::from_secs(1)).unwrap();
+        assert_eq!(provider.tsig_key.unwrap().secret, b"secret");
+
+        config.providers[0].tsig_key.as_mut().unwrap().secret = "not base64!".to_owned();
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::InvalidTsigKey { zone }) if zone == "example.com"
+        ));
+    }
+}
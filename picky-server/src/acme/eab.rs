@@ -0,0 +1,361 @@
+//! External account binding (RFC8555 section 7.3.4).
+//!
+//! Administrators provision MAC keys out of band. An ACME client proves it holds one by signing its
+//! account key with it when creating the account, so only our own fleet can register accounts.
+
+use crate::{
+    db::{ExternalAccountKey, PickyStorage, StorageError},
+    utils::unix_epoch,
+};
+use hmac::{Hmac, Mac};
+use picky::jose::jwk::{Jwk, JwkError};
+use rand::RngCore;
+use serde_json::Value;
+use sha2::{Sha256, Sha384, Sha512};
+use snafu::Snafu;
+
+const KEY_ID_LEN: usize = 16;
+const HMAC_KEY_LEN: usize = 32;
+
+#[derive(Debug, Snafu)]
+pub enum EabError {
+    /// storage backend failed
+    #[snafu(display("{}: {}", context, source))]
+    Storage {
+        context: &'static str,
+        source: StorageError,
+    },
+
+    /// no key has this identifier
+    #[snafu(display("unknown external account key {}", key_id))]
+    UnknownKey { key_id: String },
+
+    /// key was revoked by an administrator
+    #[snafu(display("external account key {} is revoked", key_id))]
+    RevokedKey { key_id: String },
+
+    /// key already binds another account
+    #[snafu(display("external account key {} is already bound", key_id))]
+    AlreadyBound { key_id: String },
+
+    /// member of the binding JWS is missing
+    #[snafu(display("external account binding '{}' is missing", name))]
+    MissingMember { name: String },
+
+    /// member of the binding JWS isn't base64url
+    #[snafu(display("invalid base64 {}: {}", field, source))]
+    InvalidBase64 {
+        field: &'static str,
+        source: base64::DecodeError,
+    },
+
+    /// decoded member isn't valid JSON
+    #[snafu(display("invalid {}: {}", field, source))]
+    InvalidJson {
+        field: &'static str,
+        source: serde_json::Error,
+    },
+
+    /// protected header has no key id
+    #[snafu(display("external account binding key id is missing"))]
+    MissingKeyId,
+
+    /// protected header names another key
+    #[snafu(display("external account binding key id mismatch"))]
+    KeyIdMismatch,
+
+    /// protected header names another URL than the newAccount one
+    #[snafu(display("external account binding url mismatch"))]
+    UrlMismatch,
+
+    /// protected header has a nonce (RFC8555 section 7.3.4)
+    #[snafu(display("external account binding must not contain a nonce"))]
+    UnexpectedNonce,
+
+    /// payload isn't the account key of the outer JWS
+    #[snafu(display("external account binding doesn't sign the account key"))]
+    AccountKeyMismatch,
+
+    /// MAC algorithm isn't HS256, HS384 nor HS512
+    #[snafu(display("unsupported external account binding algorithm: {:?}", algorithm))]
+    UnsupportedAlgorithm { algorithm: Option<String> },
+
+    /// stored MAC key can't be used
+    #[snafu(display("invalid external account key"))]
+    InvalidKey,
+
+    /// MAC doesn't verify
+    #[snafu(display("invalid external account binding signature"))]
+    InvalidSignature,
+
+    /// account key thumbprint couldn't be computed
+    #[snafu(display("couldn't compute account key thumbprint: {}", source))]
+    Thumbprint { source: JwkError },
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0; len];
+    crate::random::with_rng(|rng| rng.fill_bytes(&mut bytes));
+    bytes
+}
+
+fn decode_b64(field: &'static str, value: &str) -> Result<Vec<u8>, EabError> {
+    base64::decode_config(value, base64::URL_SAFE_NO_PAD).map_err(|source| EabError::InvalidBase64 { field, source })
+}
+
+/// Generates and stores a new external account binding key.
+pub fn provision_key(storage: &dyn PickyStorage) -> Result<ExternalAccountKey, EabError> {
+    let key = ExternalAccountKey {
+        key_id: hex::encode(random_bytes(KEY_ID_LEN)),
+        hmac_key: base64::encode_config(&random_bytes(HMAC_KEY_LEN), base64::URL_SAFE_NO_PAD),
+        created_at: unix_epoch(),
+        account: None,
+        revoked: false,
+        account_key: None,
+    };
+    storage
+        .store_external_account_key(key.clone())
+        .map_err(|source| EabError::Storage {
+            context: "couldn't store external account key",
+            source,
+        })?;
+    Ok(key)
+}
+
+/// Prevents any further use of the key. Accounts already bound are left untouched.
+pub fn revoke_key(storage: &dyn PickyStorage, key_id: &str) -> Result<(), EabError> {
+    let mut key = fetch_key(storage, key_id)?;
+    key.revoked = true;
+    storage
+        .store_external_account_key(key)
+        .map_err(|source| EabError::Storage {
+            context: "couldn't store external account key",
+            source,
+        })
+}
+
+fn fetch_key(storage: &dyn PickyStorage, key_id: &str) -> Result<ExternalAccountKey, EabError> {
+    storage
+        .get_external_account_key(key_id)
+        .map_err(|source| EabError::Storage {
+            context: "couldn't fetch external account key",
+            source,
+        })?
+        .ok_or_else(|| EabError::UnknownKey {
+            key_id: key_id.to_owned(),
+        })
+}
+
+macro_rules! verify_mac {
+    ($hash:ty, $key:expr, $data:expr, $signature:expr) => {{
+        let mut mac = Hmac::<$hash>::new_varkey($key).map_err(|_| EabError::InvalidKey)?;
+        mac.input($data);
+        mac.verify($signature).is_ok()
+    }};
+}
+
+/// Checks the `externalAccountBinding` JWS (flattened JSON serialization) of a newAccount request.
+///
+/// `account_jwk` is the account key from the outer JWS and `url` the newAccount URL it was posted to.
+pub fn verify_binding(
+    binding: &Value,
+    account_jwk: &Value,
+    url: &str,
+    key: &ExternalAccountKey,
+) -> Result<(), EabError> {
+    let field = |name: &str| {
+        binding[name]
+            .as_str()
+            .ok_or_else(|| EabError::MissingMember { name: name.to_owned() })
+    };
+    let protected_b64 = field("protected")?;
+    let payload_b64 = field("payload")?;
+    let signature = decode_b64("signature", field("signature")?)?;
+
+    let protected: Value =
+        serde_json::from_slice(&decode_b64("protected header", protected_b64)?).map_err(|source| {
+            EabError::InvalidJson {
+                field: "protected header",
+                source,
+            }
+        })?;
+    if protected["kid"].as_str() != Some(key.key_id.as_str()) {
+        return Err(EabError::KeyIdMismatch);
+    }
+    if protected["url"].as_str() != Some(url) {
+        return Err(EabError::UrlMismatch);
+    }
+    if !protected["nonce"].is_null() {
+        return Err(EabError::UnexpectedNonce);
+    }
+
+    let payload: Value =
+        serde_json::from_slice(&decode_b64("payload", payload_b64)?).map_err(|source| EabError::InvalidJson {
+            field: "payload",
+            source,
+        })?;
+    if &payload != account_jwk {
+        return Err(EabError::AccountKeyMismatch);
+    }
+
+    let hmac_key = decode_b64("external account key", &key.hmac_key)?;
+    let signing_input = format!("{}.{}", protected_b64, payload_b64);
+    let valid = match protected["alg"].as_str() {
+        Some("HS256") => verify_mac!(Sha256, &hmac_key, signing_input.as_bytes(), &signature),
+        Some("HS384") => verify_mac!(Sha384, &hmac_key, signing_input.as_bytes(), &signature),
+        Some("HS512") => verify_mac!(Sha512, &hmac_key, signing_input.as_bytes(), &signature),
+        unsupported => {
+            return Err(EabError::UnsupportedAlgorithm {
+                algorithm: unsupported.map(str::to_owned),
+            })
+        }
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(EabError::InvalidSignature)
+    }
+}
+
+/// Verifies the binding and marks the key as used by the account, identified by its JWK thumbprint.
+/// The account key is recorded along to authenticate the requests of the account.
+///
+/// A key binds a single account and can't be used once revoked.
+pub fn bind_account(
+    storage: &dyn PickyStorage,
+    binding: &Value,
+    account_jwk: &Value,
+    url: &str,
+) -> Result<ExternalAccountKey, EabError> {
+    let protected: Value = binding["protected"]
+        .as_str()
+        .ok_or_else(|| EabError::MissingMember {
+            name: "protected".to_owned(),
+        })
+        .and_then(|protected| decode_b64("protected header", protected))
+        .and_then(|protected| {
+            serde_json::from_slice(&protected).map_err(|source| EabError::InvalidJson {
+                field: "protected header",
+                source,
+            })
+        })?;
+    let key_id = protected["kid"].as_str().ok_or(EabError::MissingKeyId)?;
+
+    let mut key = fetch_key(storage, key_id)?;
+    if key.revoked {
+        return Err(EabError::RevokedKey {
+            key_id: key_id.to_owned(),
+        });
+    }
+    if key.account.is_some() {
+        return Err(EabError::AlreadyBound {
+            key_id: key_id.to_owned(),
+        });
+    }
+
+    verify_binding(binding, account_jwk, url, &key)?;
+
+    let jwk: Jwk = serde_json::from_value(account_jwk.clone()).map_err(|source| EabError::InvalidJson {
+        field: "account key",
+        source,
+    })?;
+    key.account = Some(jwk.thumbprint().map_err(|source| EabError::Thumbprint { source })?);
+    key.account_key = Some(account_jwk.to_string());
+    storage
+        .store_external_account_key(key.clone())
+        .map_err(|source| EabError::Storage {
+            context: "couldn't store external account key",
+            source,
+        })?;
+
+    Ok(key)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{
+        config::{BackendType, Config},
+        db::get_storage,
+    };
+    use picky::{key::PrivateKey, pem::Pem};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    const NEW_ACCOUNT_URL: &str = "https://picky.example.com/acme/new-account";
+
+    fn account_jwk() -> Value {
+        let pem = include_str!("../../../test_assets/private_keys/rsa-2048-pk_4.key")
+            .parse::<Pem>()
+            .expect("pem");
+        let key = PrivateKey::from_pem(&pem).expect("key");
+        serde_json::to_value(Jwk::from_public_key(&key.to_public_key()).expect("jwk")).expect("jwk json")
+    }
+
+    pub(crate) fn binding(key: &ExternalAccountKey, jwk: &Value, url: &str) -> Value {
+        let encode = |data: &[u8]| base64::encode_config(data, base64::URL_SAFE_NO_PAD);
+        let protected = encode(
+            json!({ "alg": "HS256", "kid": key.key_id, "url": url })
+                .to_string()
+                .as_bytes(),
+        );
+        let payload = encode(jwk.to_string().as_bytes());
+
+        let mut mac = Hmac::<Sha256>::new_varkey(&decode_b64("key", &key.hmac_key).unwrap()).unwrap();
+        mac.input(format!("{}.{}", protected, payload).as_bytes());
+        let signature = encode(&mac.result().code());
+
+        json!({ "protected": protected, "payload": payload, "signature": signature })
+    }
+
+    fn storage() -> Arc<dyn PickyStorage> {
+        let mut config = Config::default();
+        config.backend = BackendType::Memory;
+        get_storage(&config).expect("storage").0
+    }
+
+    #[test]
+    fn binding_verification() {
+        let storage = storage();
+        let key = provision_key(storage.as_ref()).unwrap();
+        let jwk = account_jwk();
+
+        verify_binding(&binding(&key, &jwk, NEW_ACCOUNT_URL), &jwk, NEW_ACCOUNT_URL, &key).unwrap();
+
+        let err = verify_binding(
+            &binding(&key, &jwk, "https://evil.example.com"),
+            &jwk,
+            NEW_ACCOUNT_URL,
+            &key,
+        );
+        assert!(matches!(err, Err(EabError::UrlMismatch)));
+
+        let other_key = provision_key(storage.as_ref()).unwrap();
+        let mut forged = binding(&other_key, &jwk, NEW_ACCOUNT_URL);
+        forged["protected"] = binding(&key, &jwk, NEW_ACCOUNT_URL)["protected"].clone();
+        let err = verify_binding(&forged, &jwk, NEW_ACCOUNT_URL, &key);
+        assert!(matches!(err, Err(EabError::InvalidSignature)));
+    }
+
+    #[test]
+    fn key_binds_a_single_account() {
+        let storage = storage();
+        let key = provision_key(storage.as_ref()).unwrap();
+        let jwk = account_jwk();
+        let first_binding = binding(&key, &jwk, NEW_ACCOUNT_URL);
+
+        let bound = bind_account(storage.as_ref(), &first_binding, &jwk, NEW_ACCOUNT_URL).unwrap();
+        assert!(bound.account.is_some());
+        assert_eq!(bound.account_key, Some(jwk.to_string()));
+
+        let err = bind_account(storage.as_ref(), &first_binding, &jwk, NEW_ACCOUNT_URL).unwrap_err();
+        assert!(matches!(err, EabError::AlreadyBound { key_id } if key_id == key.key_id));
+
+        let revoked = provision_key(storage.as_ref()).unwrap();
+        revoke_key(storage.as_ref(), &revoked.key_id).unwrap();
+        let revoked_binding = binding(&revoked, &jwk, NEW_ACCOUNT_URL);
+        let err = bind_account(storage.as_ref(), &revoked_binding, &jwk, NEW_ACCOUNT_URL).unwrap_err();
+        assert!(matches!(err, EabError::RevokedKey { key_id } if key_id == revoked.key_id));
+    }
+}
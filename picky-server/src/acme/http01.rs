@@ -0,0 +1,240 @@
+//! HTTP-01 challenge validation (RFC8555 section 8.3).
+//!
+//! Validation requests leave from the configured source addresses, which lets firewalls identify
+//! them, and challenged names can be resolved to internal addresses for split-horizon deployments.
+//! Each validation transcript is appended to the audit log.
+
+use crate::{
+    audit::{self, AuditEvent},
+    db::PickyStorage,
+};
+use reqwest::{header, RedirectPolicy};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{collections::HashMap, io::Read, net::IpAddr, time::Duration};
+
+const fn default_timeout_secs() -> u64 {
+    10
+}
+
+const fn default_max_redirects() -> usize {
+    10
+}
+
+/// Response bodies are key authorizations: anything longer isn't worth reading
+const MAX_BODY_LEN: u64 = 8192;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Http01Config {
+    /// Local addresses validation requests are sent from. Validation must succeed from each of them.
+    #[serde(default)]
+    pub source_addresses: Vec<IpAddr>,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Redirects are only followed to HTTP and HTTPS on their default ports. 0 disables redirects.
+    #[serde(default = "default_max_redirects")]
+    pub max_redirects: usize,
+    /// Addresses used instead of DNS resolution for the given names
+    #[serde(default)]
+    pub resolve: HashMap<String, IpAddr>,
+}
+
+impl Default for Http01Config {
+    fn default() -> Self {
+        Self {
+            source_addresses: Vec::new(),
+            timeout_secs: default_timeout_secs(),
+            max_redirects: default_max_redirects(),
+            resolve: HashMap::new(),
+        }
+    }
+}
+
+fn is_valid_token(token: &str) -> bool {
+    !token.is_empty() && token.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+fn is_valid_domain(domain: &str) -> bool {
+    !domain.is_empty()
+        && domain
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+}
+
+/// Returns the URL to request and, when the name is resolved using an override, the Host header to send.
+fn challenge_url(config: &Http01Config, domain: &str, token: &str) -> (String, Option<String>) {
+    match config.resolve.get(domain) {
+        Some(IpAddr::V4(address)) => (
+            format!("http://{}/.well-known/acme-challenge/{}", address, token),
+            Some(domain.to_owned()),
+        ),
+        Some(IpAddr::V6(address)) => (
+            format!("http://[{}]/.well-known/acme-challenge/{}", address, token),
+            Some(domain.to_owned()),
+        ),
+        None => (format!("http://{}/.well-known/acme-challenge/{}", domain, token), None),
+    }
+}
+
+/// Clients may append whitespace to the key authorization.
+fn matches_key_authorization(body: &str, key_authorization: &str) -> bool {
+    body.trim_end() == key_authorization
+}
+
+fn redirect_policy(max_redirects: usize) -> RedirectPolicy {
+    if max_redirects == 0 {
+        return RedirectPolicy::none();
+    }
+
+    RedirectPolicy::custom(move |attempt| {
+        let scheme = attempt.url().scheme();
+        let port = attempt.url().port_or_known_default();
+        let allowed = (scheme == "http" && port == Some(80)) || (scheme == "https" && port == Some(443));
+
+        if attempt.previous().len() > max_redirects {
+            attempt.too_many_redirects()
+        } else if allowed {
+            attempt.follow()
+        } else {
+            attempt.stop()
+        }
+    })
+}
+
+fn fetch(
+    config: &Http01Config,
+    source_address: Option<IpAddr>,
+    url: &str,
+    host: Option<&str>,
+    key_authorization: &str,
+) -> (bool, Value) {
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(config.timeout_secs))
+        .redirect(redirect_policy(config.max_redirects))
+        .local_address(source_address)
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => return (false, json!({ "error": format!("couldn't build http client: {}", e) })),
+    };
+
+    let mut request = client.get(url);
+    if let Some(host) = host {
+        request = request.header(header::HOST, host);
+    }
+
+    match request.send() {
+        Ok(response) => {
+            let status = response.status();
+            let final_url = response.url().to_string();
+            let mut body = String::new();
+            let read = response.take(MAX_BODY_LEN).read_to_string(&mut body);
+            let valid = status.is_success() && read.is_ok() && matches_key_authorization(&body, key_authorization);
+            (
+                valid,
+                json!({
+                    "status": status.as_u16(),
+                    "final_url": final_url,
+                    "body": body,
+                }),
+            )
+        }
+        Err(e) => (false, json!({ "error": e.to_string() })),
+    }
+}
+
+/// Fetches `http://<domain>/.well-known/acme-challenge/<token>` from each source address and checks that
+/// the response is the expected key authorization.
+pub fn validate(
+    config: &Http01Config,
+    storage: &dyn PickyStorage,
+    domain: &str,
+    token: &str,
+    key_authorization: &str,
+) -> Result<(), String> {
+    if !is_valid_token(token) {
+        return Err(format!("invalid challenge token: {}", token));
+    }
+    if !is_valid_domain(domain) {
+        return Err(format!("invalid challenge domain: {}", domain));
+    }
+
+    let (url, host) = challenge_url(config, domain, token);
+    let source_addresses = if config.source_addresses.is_empty() {
+        vec![None]
+    } else {
+        config.source_addresses.iter().copied().map(Some).collect()
+    };
+
+    let mut valid = true;
+    let mut attempts = Vec::with_capacity(source_addresses.len());
+    for source_address in source_addresses {
+        let (attempt_valid, mut transcript) = fetch(config, source_address, &url, host.as_deref(), key_authorization);
+        transcript["source_address"] = json!(source_address);
+        transcript["valid"] = json!(attempt_valid);
+        attempts.push(transcript);
+        valid &= attempt_valid;
+    }
+
+    audit::append(
+        storage,
+        AuditEvent::ChallengeValidated,
+        json!({
+            "type": "http-01",
+            "domain": domain,
+            "url": url,
+            "host": host,
+            "valid": valid,
+            "attempts": attempts,
+        }),
+    )
+    .map_err(|e| format!("couldn't record audit event: {}", e))?;
+
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("http-01 validation failed for {}", domain))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn challenge_url_resolution() {
+        let mut config = Http01Config::default();
+        config
+            .resolve
+            .insert("internal.example.com".to_owned(), "10.0.0.1".parse().unwrap());
+        config
+            .resolve
+            .insert("v6.example.com".to_owned(), "fd00::1".parse().unwrap());
+
+        assert_eq!(
+            challenge_url(&config, "www.example.com", "tok"),
+            ("http://www.example.com/.well-known/acme-challenge/tok".to_owned(), None)
+        );
+        assert_eq!(
+            challenge_url(&config, "internal.example.com", "tok"),
+            (
+                "http://10.0.0.1/.well-known/acme-challenge/tok".to_owned(),
+                Some("internal.example.com".to_owned())
+            )
+        );
+        assert_eq!(
+            challenge_url(&config, "v6.example.com", "tok").0,
+            "http://[fd00::1]/.well-known/acme-challenge/tok"
+        );
+    }
+
+    #[test]
+    fn challenge_inputs() {
+        assert!(is_valid_token("evaGxfADs6pSRb2LAv9IZf17Dt3juxGJ-PCt92wr-oA"));
+        assert!(!is_valid_token("../../admin"));
+        assert!(!is_valid_domain("example.com/evil"));
+
+        assert!(matches_key_authorization("token.thumbprint\r\n", "token.thumbprint"));
+        assert!(!matches_key_authorization(" token.thumbprint", "token.thumbprint"));
+    }
+}
@@ -0,0 +1,27 @@
+//! ACME (RFC8555) server.
+//!
+//! The directory, replay nonces, account creation behind external account binding, orders,
+//! authorizations and challenges (dns-01 and http-01) are served. Orders can't be finalized yet.
+
+pub mod account;
+pub mod dns01;
+pub mod eab;
+pub mod http01;
+pub mod order;
+
+use crate::config::ConfigError;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct AcmeConfig {
+    #[serde(default)]
+    pub dns01: dns01::Dns01Config,
+    #[serde(default)]
+    pub http01: http01::Http01Config,
+}
+
+impl AcmeConfig {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.dns01.validate()
+    }
+}
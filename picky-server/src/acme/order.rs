@@ -0,0 +1,576 @@
+//! ACME orders, authorizations and challenges (RFC8555 sections 7.1.3 to 7.1.5 and 7.4).
+//!
+//! They are kept in memory like replay nonces: orders are only known to the instance which created
+//! them, and are forgotten once expired or when the server restarts, clients then place a new order.
+//! Each authorization has a dns-01 challenge, and an http-01 one unless it is for a wildcard domain.
+//! Challenges are validated synchronously when the client responds to them. Validating one of the
+//! challenges of an authorization validates it, and the order is ready once all its authorizations
+//! are valid.
+
+use crate::{
+    acme::{
+        account::{AcmeErrorType, AcmeProblem},
+        dns01, http01, AcmeConfig,
+    },
+    db::PickyStorage,
+};
+use chrono::{SecondsFormat, TimeZone, Utc};
+use rand::RngCore;
+use serde_json::{json, Value};
+use std::{collections::HashMap, sync::Mutex};
+
+/// Clients are expected to complete their orders well within a day
+const ORDER_LIFETIME_SECS: u64 = 24 * 60 * 60;
+const ID_LEN: usize = 16;
+/// RFC8555 section 8.1: tokens carry at least 128 bits of entropy
+const TOKEN_LEN: usize = 32;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Status {
+    Pending,
+    Ready,
+    Valid,
+    Invalid,
+}
+
+impl Status {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Pending => "pending",
+            Status::Ready => "ready",
+            Status::Valid => "valid",
+            Status::Invalid => "invalid",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChallengeType {
+    Dns01,
+    Http01,
+}
+
+impl ChallengeType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChallengeType::Dns01 => "dns-01",
+            ChallengeType::Http01 => "http-01",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Challenge {
+    pub id: String,
+    pub challenge_type: ChallengeType,
+    pub token: String,
+    pub status: Status,
+    /// Time of the successful validation (seconds since UNIX epoch)
+    pub validated: Option<u64>,
+    /// Why validation failed
+    pub error: Option<String>,
+}
+
+impl Challenge {
+    fn new(challenge_type: ChallengeType) -> Self {
+        Self {
+            id: random_id(ID_LEN),
+            challenge_type,
+            token: random_id(TOKEN_LEN),
+            status: Status::Pending,
+            validated: None,
+            error: None,
+        }
+    }
+
+    /// Challenge object, challenges are served at `url_prefix` followed by their id.
+    pub fn to_json(&self, url_prefix: &str) -> Value {
+        let mut challenge = json!({
+            "type": self.challenge_type.as_str(),
+            "url": format!("{}{}", url_prefix, self.id),
+            "status": self.status.as_str(),
+            "token": self.token,
+        });
+        if let Some(validated) = self.validated {
+            challenge["validated"] = json!(timestamp(validated));
+        }
+        if let Some(error) = &self.error {
+            challenge["error"] = json!({
+                "type": AcmeErrorType::Incorrect.type_uri(),
+                "detail": error,
+            });
+        }
+        challenge
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Authorization {
+    pub id: String,
+    /// Thumbprint of the account key
+    pub account_id: String,
+    /// Domain name, without the wildcard label
+    pub domain: String,
+    pub wildcard: bool,
+    pub status: Status,
+    /// Seconds since UNIX epoch
+    pub expires: u64,
+    pub challenges: Vec<Challenge>,
+}
+
+impl Authorization {
+    /// Authorization object, challenges are served at `challenge_url_prefix` followed by their id.
+    pub fn to_json(&self, challenge_url_prefix: &str) -> Value {
+        let mut authorization = json!({
+            "identifier": { "type": "dns", "value": self.domain },
+            "status": self.status.as_str(),
+            "expires": timestamp(self.expires),
+            "challenges": self
+                .challenges
+                .iter()
+                .map(|challenge| challenge.to_json(challenge_url_prefix))
+                .collect::<Vec<Value>>(),
+        });
+        if self.wildcard {
+            authorization["wildcard"] = json!(true);
+        }
+        authorization
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Order {
+    pub id: String,
+    /// Thumbprint of the account key
+    pub account_id: String,
+    /// Domain names, wildcards included
+    pub identifiers: Vec<String>,
+    /// Authorization ids, one per identifier
+    pub authorizations: Vec<String>,
+    /// Derived from the authorizations when the order is fetched
+    pub status: Status,
+    /// Seconds since UNIX epoch
+    pub expires: u64,
+}
+
+impl Order {
+    /// Order object, authorizations are served at `authorization_url_prefix` followed by their id.
+    pub fn to_json(&self, authorization_url_prefix: &str) -> Value {
+        json!({
+            "status": self.status.as_str(),
+            "expires": timestamp(self.expires),
+            "identifiers": self
+                .identifiers
+                .iter()
+                .map(|identifier| json!({ "type": "dns", "value": identifier }))
+                .collect::<Vec<Value>>(),
+            "authorizations": self
+                .authorizations
+                .iter()
+                .map(|id| format!("{}{}", authorization_url_prefix, id))
+                .collect::<Vec<String>>(),
+        })
+    }
+}
+
+fn random_id(len: usize) -> String {
+    let mut id = vec![0; len];
+    crate::random::with_rng(|rng| rng.fill_bytes(&mut id));
+    base64::encode_config(&id, base64::URL_SAFE_NO_PAD)
+}
+
+/// RFC3339 date of a UNIX timestamp
+fn timestamp(secs: u64) -> String {
+    Utc.timestamp(secs as i64, 0).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn is_valid_domain(domain: &str) -> bool {
+    domain.len() <= 253
+        && domain.contains('.')
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// Lowercase domain name of a `dns` identifier, with its wildcard label if any.
+fn parse_identifier(identifier: &Value) -> Result<String, AcmeProblem> {
+    if identifier["type"].as_str() != Some("dns") {
+        return Err(AcmeProblem::new(
+            AcmeErrorType::UnsupportedIdentifier,
+            format!("unsupported identifier type: {}", identifier["type"]),
+        ));
+    }
+    let value = identifier["value"]
+        .as_str()
+        .ok_or_else(|| AcmeProblem::new(AcmeErrorType::Malformed, "identifier value is missing"))?
+        .trim_end_matches('.')
+        .to_ascii_lowercase();
+
+    if !is_valid_domain(value.strip_prefix("*.").unwrap_or(&value)) {
+        return Err(AcmeProblem::new(
+            AcmeErrorType::RejectedIdentifier,
+            format!("invalid domain name: {}", value),
+        ));
+    }
+
+    Ok(value)
+}
+
+fn not_found(resource: &str, id: &str) -> AcmeProblem {
+    AcmeProblem::new(AcmeErrorType::Malformed, format!("unknown {} {}", resource, id))
+}
+
+fn check_owner(resource: &str, id: &str, owner: &str, account_id: &str) -> Result<(), AcmeProblem> {
+    if owner == account_id {
+        Ok(())
+    } else {
+        Err(AcmeProblem::new(
+            AcmeErrorType::Unauthorized,
+            format!("{} {} belongs to another account", resource, id),
+        ))
+    }
+}
+
+#[derive(Default)]
+struct Orders {
+    orders: HashMap<String, Order>,
+    authorizations: HashMap<String, Authorization>,
+    /// Challenge id to the id of the authorization holding it
+    challenges: HashMap<String, String>,
+}
+
+impl Orders {
+    fn forget_expired(&mut self, now: u64) {
+        self.orders.retain(|_, order| order.expires >= now);
+        self.authorizations
+            .retain(|_, authorization| authorization.expires >= now);
+        let authorizations = &self.authorizations;
+        self.challenges
+            .retain(|_, authorization_id| authorizations.contains_key(authorization_id));
+    }
+
+    fn order_status(&self, order: &Order) -> Status {
+        let mut status = Status::Ready;
+        for id in &order.authorizations {
+            match self.authorizations.get(id).map(|authorization| authorization.status) {
+                Some(Status::Valid) => {}
+                Some(Status::Pending) => status = Status::Pending,
+                _ => return Status::Invalid,
+            }
+        }
+        status
+    }
+}
+
+/// Orders of this instance which didn't expire yet.
+#[derive(Default)]
+pub struct OrderStore {
+    orders: Mutex<Orders>,
+}
+
+impl OrderStore {
+    /// Creates an order for the identifiers of a newOrder payload, with an authorization per identifier.
+    pub fn new_order(&self, account_id: &str, payload: &Value, now: u64) -> Result<Order, AcmeProblem> {
+        let identifiers = payload["identifiers"]
+            .as_array()
+            .filter(|identifiers| !identifiers.is_empty())
+            .ok_or_else(|| AcmeProblem::new(AcmeErrorType::Malformed, "identifiers are missing"))?;
+        let mut domains = Vec::with_capacity(identifiers.len());
+        for identifier in identifiers {
+            let domain = parse_identifier(identifier)?;
+            if !domains.contains(&domain) {
+                domains.push(domain);
+            }
+        }
+
+        let expires = now + ORDER_LIFETIME_SECS;
+        let mut orders = self.orders.lock().expect("orders lock");
+        orders.forget_expired(now);
+
+        let mut order = Order {
+            id: random_id(ID_LEN),
+            account_id: account_id.to_owned(),
+            identifiers: domains.clone(),
+            authorizations: Vec::with_capacity(domains.len()),
+            status: Status::Pending,
+            expires,
+        };
+        for domain in domains {
+            let wildcard = domain.starts_with("*.");
+            // wildcard domains can only be validated using dns-01 (RFC8555 section 7.1.3)
+            let challenges = if wildcard {
+                vec![Challenge::new(ChallengeType::Dns01)]
+            } else {
+                vec![
+                    Challenge::new(ChallengeType::Http01),
+                    Challenge::new(ChallengeType::Dns01),
+                ]
+            };
+            let authorization = Authorization {
+                id: random_id(ID_LEN),
+                account_id: account_id.to_owned(),
+                domain: domain.trim_start_matches("*.").to_owned(),
+                wildcard,
+                status: Status::Pending,
+                expires,
+                challenges,
+            };
+            for challenge in &authorization.challenges {
+                orders.challenges.insert(challenge.id.clone(), authorization.id.clone());
+            }
+            order.authorizations.push(authorization.id.clone());
+            orders.authorizations.insert(authorization.id.clone(), authorization);
+        }
+        orders.orders.insert(order.id.clone(), order.clone());
+
+        Ok(order)
+    }
+
+    pub fn order(&self, account_id: &str, id: &str, now: u64) -> Result<Order, AcmeProblem> {
+        let mut orders = self.orders.lock().expect("orders lock");
+        orders.forget_expired(now);
+
+        let mut order = orders.orders.get(id).cloned().ok_or_else(|| not_found("order", id))?;
+        check_owner("order", id, &order.account_id, account_id)?;
+        order.status = orders.order_status(&order);
+        Ok(order)
+    }
+
+    pub fn authorization(&self, account_id: &str, id: &str, now: u64) -> Result<Authorization, AcmeProblem> {
+        let mut orders = self.orders.lock().expect("orders lock");
+        orders.forget_expired(now);
+
+        let authorization = orders
+            .authorizations
+            .get(id)
+            .cloned()
+            .ok_or_else(|| not_found("authorization", id))?;
+        check_owner("authorization", id, &authorization.account_id, account_id)?;
+        Ok(authorization)
+    }
+
+    /// Challenge and the authorization holding it.
+    pub fn challenge(&self, account_id: &str, id: &str, now: u64) -> Result<(Authorization, Challenge), AcmeProblem> {
+        let authorization_id = {
+            let mut orders = self.orders.lock().expect("orders lock");
+            orders.forget_expired(now);
+            orders
+                .challenges
+                .get(id)
+                .cloned()
+                .ok_or_else(|| not_found("challenge", id))?
+        };
+        let authorization = self.authorization(account_id, &authorization_id, now)?;
+        let challenge = authorization
+            .challenges
+            .iter()
+            .find(|challenge| challenge.id == id)
+            .cloned()
+            .ok_or_else(|| not_found("challenge", id))?;
+        Ok((authorization, challenge))
+    }
+
+    /// Records the outcome of a challenge validation, its authorization follows it. Returns the updated
+    /// challenge and authorization.
+    pub fn complete_challenge(
+        &self,
+        id: &str,
+        result: Result<(), String>,
+        now: u64,
+    ) -> Result<(Authorization, Challenge), AcmeProblem> {
+        let mut orders = self.orders.lock().expect("orders lock");
+        let authorization_id = orders
+            .challenges
+            .get(id)
+            .cloned()
+            .ok_or_else(|| not_found("challenge", id))?;
+        let authorization = orders
+            .authorizations
+            .get_mut(&authorization_id)
+            .ok_or_else(|| not_found("challenge", id))?;
+        let challenge = authorization
+            .challenges
+            .iter_mut()
+            .find(|challenge| challenge.id == id)
+            .ok_or_else(|| not_found("challenge", id))?;
+
+        match result {
+            Ok(()) => {
+                challenge.status = Status::Valid;
+                challenge.validated = Some(now);
+            }
+            Err(error) => {
+                challenge.status = Status::Invalid;
+                challenge.error = Some(error);
+            }
+        }
+        let challenge = challenge.clone();
+        authorization.status = challenge.status;
+
+        Ok((authorization.clone(), challenge))
+    }
+}
+
+/// Checks the client fulfilled the challenge for the domain of the authorization.
+pub fn validate(
+    config: &AcmeConfig,
+    storage: &dyn PickyStorage,
+    authorization: &Authorization,
+    challenge: &Challenge,
+) -> Result<(), String> {
+    match challenge.challenge_type {
+        ChallengeType::Dns01 => dns01::validate(
+            &config.dns01,
+            storage,
+            &authorization.domain,
+            &challenge.token,
+            &authorization.account_id,
+        ),
+        ChallengeType::Http01 => http01::validate(
+            &config.http01,
+            storage,
+            &authorization.domain,
+            &challenge.token,
+            &format!("{}.{}", challenge.token, authorization.account_id),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_600_000_000;
+
+    fn new_order(store: &OrderStore, identifiers: &[&str]) -> Result<Order, AcmeProblem> {
+        let identifiers = identifiers
+            .iter()
+            .map(|value| json!({ "type": "dns", "value": value }))
+            .collect::<Vec<Value>>();
+        store.new_order("account", &json!({ "identifiers": identifiers }), NOW)
+    }
+
+    #[test]
+    fn identifiers() {
+        let store = OrderStore::default();
+
+        let order = new_order(&store, &["WWW.example.com.", "*.example.com", "www.example.com"]).unwrap();
+        assert_eq!(order.identifiers, vec!["www.example.com", "*.example.com"]);
+        assert_eq!(order.authorizations.len(), 2);
+
+        let wildcard = store.authorization("account", &order.authorizations[1], NOW).unwrap();
+        assert_eq!(wildcard.domain, "example.com");
+        assert!(wildcard.wildcard);
+        assert_eq!(wildcard.challenges.len(), 1);
+        assert_eq!(wildcard.challenges[0].challenge_type, ChallengeType::Dns01);
+
+        let www = store.authorization("account", &order.authorizations[0], NOW).unwrap();
+        let types = www
+            .challenges
+            .iter()
+            .map(|challenge| challenge.challenge_type)
+            .collect::<Vec<_>>();
+        assert_eq!(types, vec![ChallengeType::Http01, ChallengeType::Dns01]);
+
+        for rejected in &[
+            "*.*.example.com",
+            "www.*.example.com",
+            "-www.example.com",
+            "localhost",
+            "",
+        ] {
+            let err = new_order(&store, &[rejected]).unwrap_err();
+            assert_eq!(err.error_type, AcmeErrorType::RejectedIdentifier, "{}", rejected);
+        }
+
+        let err = store
+            .new_order(
+                "account",
+                &json!({ "identifiers": [{ "type": "ip", "value": "192.0.2.1" }] }),
+                NOW,
+            )
+            .unwrap_err();
+        assert_eq!(err.error_type, AcmeErrorType::UnsupportedIdentifier);
+
+        let err = store.new_order("account", &json!({}), NOW).unwrap_err();
+        assert_eq!(err.error_type, AcmeErrorType::Malformed);
+    }
+
+    #[test]
+    fn order_follows_its_authorizations() {
+        let store = OrderStore::default();
+        let order = new_order(&store, &["a.example.com", "b.example.com"]).unwrap();
+        let challenge_id = |authorization_id: &str| {
+            store
+                .authorization("account", authorization_id, NOW)
+                .unwrap()
+                .challenges[0]
+                .id
+                .clone()
+        };
+        let first = challenge_id(&order.authorizations[0]);
+        let second = challenge_id(&order.authorizations[1]);
+
+        let (authorization, challenge) = store.complete_challenge(&first, Ok(()), NOW + 1).unwrap();
+        assert_eq!(authorization.status, Status::Valid);
+        assert_eq!(challenge.validated, Some(NOW + 1));
+        assert_eq!(store.order("account", &order.id, NOW).unwrap().status, Status::Pending);
+
+        store.complete_challenge(&second, Ok(()), NOW + 1).unwrap();
+        assert_eq!(store.order("account", &order.id, NOW).unwrap().status, Status::Ready);
+
+        let order = new_order(&store, &["c.example.com"]).unwrap();
+        let challenge = challenge_id(&order.authorizations[0]);
+        let (authorization, challenge) = store
+            .complete_challenge(&challenge, Err("no record".to_owned()), NOW)
+            .unwrap();
+        assert_eq!(authorization.status, Status::Invalid);
+        assert_eq!(
+            challenge.to_json("https://picky.example.com/acme/chall/")["error"]["detail"],
+            "no record"
+        );
+        assert_eq!(store.order("account", &order.id, NOW).unwrap().status, Status::Invalid);
+    }
+
+    #[test]
+    fn orders_are_private_and_expire() {
+        let store = OrderStore::default();
+        let order = new_order(&store, &["www.example.com"]).unwrap();
+        let authorization = store.authorization("account", &order.authorizations[0], NOW).unwrap();
+
+        let err = store.order("other", &order.id, NOW).unwrap_err();
+        assert_eq!(err.error_type, AcmeErrorType::Unauthorized);
+        let err = store
+            .challenge("other", &authorization.challenges[0].id, NOW)
+            .unwrap_err();
+        assert_eq!(err.error_type, AcmeErrorType::Unauthorized);
+
+        let expired = NOW + ORDER_LIFETIME_SECS + 1;
+        assert!(store.order("account", &order.id, expired).is_err());
+        assert!(store
+            .challenge("account", &authorization.challenges[0].id, expired)
+            .is_err());
+    }
+
+    #[test]
+    fn order_object() {
+        let store = OrderStore::default();
+        let order = new_order(&store, &["www.example.com"]).unwrap();
+        let order_json = order.to_json("https://picky.example.com/acme/authz/");
+
+        assert_eq!(order_json["status"], "pending");
+        assert_eq!(order_json["expires"], "2020-09-14T12:26:40Z");
+        assert_eq!(
+            order_json["identifiers"],
+            json!([{ "type": "dns", "value": "www.example.com" }])
+        );
+        assert_eq!(
+            order_json["authorizations"][0],
+            format!("https://picky.example.com/acme/authz/{}", order.authorizations[0])
+        );
+    }
+}
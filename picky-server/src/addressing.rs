@@ -1,29 +1,106 @@
 use multibase::Base;
-use multihash::{Hash, Multihash};
+use multihash::{DecodeOwnedError, EncodeError, Hash, Multihash};
+use serde::Serialize;
+use snafu::Snafu;
+use std::fmt;
 
 pub const CANONICAL_HASH: Hash = Hash::SHA2256;
 pub const CANONICAL_BASE: Base = Base::Base64UrlUpperNoPad;
 
-pub fn encode_to_canonical_address(data: &[u8]) -> Result<String, String> {
-    let hash = multihash::encode(CANONICAL_HASH, data).map_err(|e| e.to_string())?;
+#[derive(Debug, Snafu)]
+pub enum AddressingError {
+    /// content couldn't be hashed
+    #[snafu(display("couldn't hash using {:?}: {}", hash, source))]
+    Hashing { hash: Hash, source: EncodeError },
+
+    /// address isn't multibase encoded
+    #[snafu(display("invalid multibase: {}", source))]
+    InvalidMultibase { source: multibase::Error },
+
+    /// address isn't a multihash
+    #[snafu(display("invalid multihash: {}", source))]
+    InvalidMultihash { source: DecodeOwnedError },
+}
+
+fn hash(hash: Hash, data: &[u8]) -> Result<Multihash, AddressingError> {
+    multihash::encode(hash, data).map_err(|source| AddressingError::Hashing { hash, source })
+}
+
+pub fn encode_to_canonical_address(data: &[u8]) -> Result<String, AddressingError> {
+    let hash = hash(CANONICAL_HASH, data)?;
     Ok(multibase::encode(CANONICAL_BASE, hash.as_bytes()))
 }
 
 const ALTERNATIVE_HASHES: [Hash; 1] = [Hash::SHA1];
-pub fn encode_to_alternative_addresses(data: &[u8]) -> Result<Vec<String>, String> {
+pub fn encode_to_alternative_addresses(data: &[u8]) -> Result<Vec<String>, AddressingError> {
     let mut addresses = Vec::with_capacity(ALTERNATIVE_HASHES.len());
 
-    for hash in ALTERNATIVE_HASHES.iter() {
-        let address = multihash::encode(*hash, data).map_err(|e| format!("couldn't hash using {:?}: {}", hash, e))?;
+    for alternative_hash in ALTERNATIVE_HASHES.iter() {
+        let address = hash(*alternative_hash, data)?;
         addresses.push(multibase::encode(CANONICAL_BASE, address.as_bytes()))
     }
 
     Ok(addresses)
 }
 
-pub fn convert_to_canonical_base(multibase_multihash_address: &str) -> Result<(String, Hash), String> {
-    let (_, raw_multi) = multibase::decode(multibase_multihash_address).map_err(|e| e.to_string())?;
-    let multi = Multihash::from_bytes(raw_multi).map_err(|e| e.to_string())?;
+/// All the addresses a content can be referenced with
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Addresses {
+    pub address: String,
+    pub alternative_addresses: Vec<String>,
+}
+
+pub fn encode_to_addresses(data: &[u8]) -> Result<Addresses, AddressingError> {
+    Ok(Addresses {
+        address: encode_to_canonical_address(data)?,
+        alternative_addresses: encode_to_alternative_addresses(data)?,
+    })
+}
+
+/// Dedicated namespaces for hash-addressed revocation artifacts.
+///
+/// Artifacts are immutable: an updated CRL or OCSP response gets a new address
+/// and the "latest" pointer of its namespace is moved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArtifactNamespace {
+    Crl,
+    Ocsp,
+}
+
+impl ArtifactNamespace {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ArtifactNamespace::Crl => "crl",
+            ArtifactNamespace::Ocsp => "ocsp",
+        }
+    }
+
+    pub fn parse(namespace: &str) -> Option<Self> {
+        match namespace {
+            "crl" => Some(ArtifactNamespace::Crl),
+            "ocsp" => Some(ArtifactNamespace::Ocsp),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ArtifactNamespace::Crl => "application/pkix-crl",
+            ArtifactNamespace::Ocsp => "application/ocsp-response",
+        }
+    }
+}
+
+impl fmt::Display for ArtifactNamespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+pub fn convert_to_canonical_base(multibase_multihash_address: &str) -> Result<(String, Hash), AddressingError> {
+    let (_, raw_multi) = multibase::decode(multibase_multihash_address)
+        .map_err(|source| AddressingError::InvalidMultibase { source })?;
+    let multi = Multihash::from_bytes(raw_multi).map_err(|source| AddressingError::InvalidMultihash { source })?;
     Ok((multibase::encode(CANONICAL_BASE, multi.as_bytes()), multi.algorithm()))
 }
 
@@ -62,4 +139,16 @@ mod tests {
             assert_eq!(canonical, "uEiCcvAfD-ZFyWDajqipYHKICkZiqQgudmbwOEx2fPiy-Rw");
         }
     }
+
+    #[test]
+    fn encode_all_addresses() {
+        let addresses = encode_to_addresses(b"multihash").expect("encode addresses");
+        assert_eq!(
+            serde_json::to_value(&addresses).expect("json"),
+            serde_json::json!({
+                "address": "uEiCcvAfD-ZFyWDajqipYHKICkZiqQgudmbwOEx2fPiy-Rw",
+                "alternative_addresses": ["uERSIwvEfss45KstbKYbmQCEcRpAHPg"],
+            })
+        );
+    }
 }
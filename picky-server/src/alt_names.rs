@@ -0,0 +1,558 @@
+//! Subject alternative names requested for issued leaf certificates besides the subject common name.
+//!
+//! DNS names are issued as requested in their ASCII form (lowercased, punycode for internationalized
+//! names, without trailing dot), IP addresses and URIs only within the ranges and schemes allowed by
+//! the `alt_name_policy` configuration. Names requested within the CSR are only honored when the
+//! policy allows it, and their DNS names must belong to one of its `csr_dns_subtrees`, as must the
+//! DNS names token requesters ask for besides the ones their token vouches for.
+
+use picky::x509::{
+    csr::CsrError,
+    hostname::{dns_name_in_subtree, normalize_dns_name, HostnameError},
+    name::GeneralName,
+    Csr,
+};
+use picky_asn1::restricted_string::CharSetError;
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+#[derive(Debug, Snafu)]
+pub enum AltNameError {
+    /// subject alternative name extension of the CSR couldn't be read
+    #[snafu(display("{}", source))]
+    CsrExtension { source: CsrError },
+
+    /// IP address SAN is neither IPv4 nor IPv6
+    #[snafu(display("invalid IP address SAN of {} bytes", len))]
+    InvalidIpAddressLength { len: usize },
+
+    /// DNS name isn't valid
+    #[snafu(display("{}", source))]
+    InvalidDnsName { source: HostnameError },
+
+    /// DNS name can't be encoded in a certificate
+    #[snafu(display("invalid DNS name '{}': {}", dns_name, source))]
+    DnsNameEncoding { dns_name: String, source: CharSetError },
+
+    /// URI isn't absolute or contains whitespaces
+    #[snafu(display("invalid URI '{}'", uri))]
+    InvalidUri { uri: String },
+
+    /// URI can't be encoded in a certificate
+    #[snafu(display("invalid URI '{}': {}", uri, source))]
+    UriEncoding { uri: String, source: CharSetError },
+
+    /// IP range isn't in CIDR notation
+    #[snafu(display("invalid IP range '{}', expected CIDR notation", range))]
+    InvalidIpRange { range: String },
+
+    /// IP address isn't within the allowed ranges
+    #[snafu(display("IP address {} is not allowed by policy", ip_address))]
+    IpAddressNotAllowed { ip_address: IpAddr },
+
+    /// URI scheme isn't one of the allowed schemes
+    #[snafu(display("URI scheme of '{}' is not allowed by policy", uri))]
+    UriSchemeNotAllowed { uri: String },
+
+    /// DNS name requested within a CSR isn't within the allowed subtrees
+    #[snafu(display("DNS name {} requested within CSR is not allowed by policy", dns_name))]
+    CsrDnsNameNotAllowed { dns_name: String },
+
+    /// DNS name isn't vouched for by the token nor within the allowed subtrees
+    #[snafu(display("DNS name {} is neither vouched for by the token nor allowed by policy", dns_name))]
+    DnsNameNotAllowed { dns_name: String },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct AltNames {
+    #[serde(default)]
+    pub dns_names: Vec<String>,
+    #[serde(default)]
+    pub ip_addresses: Vec<IpAddr>,
+    #[serde(default)]
+    pub uris: Vec<String>,
+}
+
+impl AltNames {
+    pub fn is_empty(&self) -> bool {
+        self.dns_names.is_empty() && self.ip_addresses.is_empty() && self.uris.is_empty()
+    }
+
+    /// Adds the names of `other` which are not already present, DNS names being compared in their ASCII form.
+    pub fn extend(&mut self, other: AltNames) {
+        fn extend_unique<T: PartialEq>(names: &mut Vec<T>, other: Vec<T>) {
+            for name in other {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+
+        for dns_name in other.dns_names {
+            let ascii_dns_name = normalize_dns_name(&dns_name).ok();
+            let already_present = self.dns_names.iter().any(|name| match &ascii_dns_name {
+                Some(ascii_dns_name) => normalize_dns_name(name).ok().as_ref() == Some(ascii_dns_name),
+                None => name == &dns_name,
+            });
+            if !already_present {
+                self.dns_names.push(dns_name);
+            }
+        }
+        extend_unique(&mut self.ip_addresses, other.ip_addresses);
+        extend_unique(&mut self.uris, other.uris);
+    }
+
+    /// Names requested through the subject alternative name extension of `csr`. Only DNS names, IP
+    /// addresses and URIs are taken, other kinds of names are ignored.
+    pub fn from_csr(csr: &Csr) -> Result<Self, AltNameError> {
+        let mut alt_names = Self::default();
+
+        let general_names = match csr
+            .subject_alt_names()
+            .map_err(|source| AltNameError::CsrExtension { source })?
+        {
+            Some(general_names) => general_names.into_general_names(),
+            None => return Ok(alt_names),
+        };
+
+        for general_name in general_names {
+            match general_name {
+                GeneralName::DNSName(dns_name) => alt_names.dns_names.push(dns_name.to_string()),
+                GeneralName::IpAddress(octets) => {
+                    let ip_address = ip_address_from_octets(&octets)
+                        .ok_or_else(|| AltNameError::InvalidIpAddressLength { len: octets.len() })?;
+                    alt_names.ip_addresses.push(ip_address);
+                }
+                GeneralName::URI(uri) => alt_names.uris.push(uri.to_string()),
+                _ => {}
+            }
+        }
+
+        Ok(alt_names)
+    }
+
+    /// DNS names, then IP addresses, then URIs.
+    pub fn to_general_names(&self) -> Result<Vec<GeneralName>, AltNameError> {
+        let mut general_names = Vec::with_capacity(self.dns_names.len() + self.ip_addresses.len() + self.uris.len());
+
+        for dns_name in &self.dns_names {
+            let ascii_dns_name =
+                normalize_dns_name(dns_name).map_err(|source| AltNameError::InvalidDnsName { source })?;
+            let general_name =
+                GeneralName::new_dns_name(ascii_dns_name).map_err(|source| AltNameError::DnsNameEncoding {
+                    dns_name: dns_name.clone(),
+                    source,
+                })?;
+            if !general_names.contains(&general_name) {
+                general_names.push(general_name);
+            }
+        }
+
+        for ip_address in &self.ip_addresses {
+            general_names.push(GeneralName::new_ip_address(match ip_address {
+                IpAddr::V4(ip) => ip.octets().to_vec(),
+                IpAddr::V6(ip) => ip.octets().to_vec(),
+            }));
+        }
+
+        for uri in &self.uris {
+            general_names.push(
+                GeneralName::new_uri(uri.as_str()).map_err(|source| AltNameError::UriEncoding {
+                    uri: uri.clone(),
+                    source,
+                })?,
+            );
+        }
+
+        Ok(general_names)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct AltNamePolicy {
+    /// CIDR ranges (e.g. `10.0.0.0/8`, `fd00::/8`) IP address SANs must belong to, none are issued if empty
+    #[serde(default)]
+    pub ip_ranges: Vec<String>,
+    /// Schemes URI SANs may use (e.g. `spiffe`), none are issued if empty
+    #[serde(default)]
+    pub uri_schemes: Vec<String>,
+    /// Honor the subject alternative names requested within CSRs, they are ignored otherwise
+    #[serde(default)]
+    pub honor_csr_alt_names: bool,
+    /// DNS subtrees (e.g. `example.com`, `.example.com`) DNS names requested within CSRs (or by token
+    /// requesters of server-side key generation) must belong to, none are issued if empty (`""` allows any name)
+    #[serde(default)]
+    pub csr_dns_subtrees: Vec<String>,
+}
+
+impl AltNamePolicy {
+    pub fn validate(&self) -> Result<(), AltNameError> {
+        for range in &self.ip_ranges {
+            parse_range(range)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that the DNS names of `alt_names` are valid, and its IP addresses and URIs against this policy.
+    pub fn check(&self, alt_names: &AltNames) -> Result<(), AltNameError> {
+        for dns_name in &alt_names.dns_names {
+            normalize_dns_name(dns_name).map_err(|source| AltNameError::InvalidDnsName { source })?;
+        }
+
+        let ranges = self
+            .ip_ranges
+            .iter()
+            .map(|range| parse_range(range))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for ip_address in &alt_names.ip_addresses {
+            if !ranges.iter().any(|range| in_range(ip_address, range)) {
+                return Err(AltNameError::IpAddressNotAllowed {
+                    ip_address: *ip_address,
+                });
+            }
+        }
+
+        for uri in &alt_names.uris {
+            let scheme = uri_scheme(uri).ok_or_else(|| AltNameError::InvalidUri { uri: uri.clone() })?;
+            if !self
+                .uri_schemes
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(scheme))
+            {
+                return Err(AltNameError::UriSchemeNotAllowed { uri: uri.clone() });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the DNS names requested within a CSR against `csr_dns_subtrees`, the other names are
+    /// checked along with the names requested another way.
+    pub fn check_csr_dns_names(&self, csr_alt_names: &AltNames) -> Result<(), AltNameError> {
+        for dns_name in &csr_alt_names.dns_names {
+            if !self
+                .csr_dns_subtrees
+                .iter()
+                .any(|subtree| dns_name_in_subtree(dns_name, subtree))
+            {
+                return Err(AltNameError::CsrDnsNameNotAllowed {
+                    dns_name: dns_name.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the DNS names a token requester asked for besides the ones its token vouches for
+    /// against `csr_dns_subtrees`.
+    pub fn check_requested_dns_names(&self, requested: &[String], vouched: &[String]) -> Result<(), AltNameError> {
+        let vouched = vouched
+            .iter()
+            .filter_map(|dns_name| normalize_dns_name(dns_name).ok())
+            .collect::<Vec<_>>();
+
+        for dns_name in requested {
+            let normalized = normalize_dns_name(dns_name).map_err(|source| AltNameError::InvalidDnsName { source })?;
+            if vouched.contains(&normalized) {
+                continue;
+            }
+
+            if !self
+                .csr_dns_subtrees
+                .iter()
+                .any(|subtree| dns_name_in_subtree(&normalized, subtree))
+            {
+                return Err(AltNameError::DnsNameNotAllowed {
+                    dns_name: dns_name.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+fn ip_address_from_octets(octets: &[u8]) -> Option<IpAddr> {
+    if octets.len() == 4 {
+        let mut ipv4 = [0; 4];
+        ipv4.copy_from_slice(octets);
+        Some(IpAddr::V4(Ipv4Addr::from(ipv4)))
+    } else if octets.len() == 16 {
+        let mut ipv6 = [0; 16];
+        ipv6.copy_from_slice(octets);
+        Some(IpAddr::V6(Ipv6Addr::from(ipv6)))
+    } else {
+        None
+    }
+}
+
+fn parse_range(range: &str) -> Result<(IpAddr, u8), AltNameError> {
+    let invalid = || AltNameError::InvalidIpRange {
+        range: range.to_owned(),
+    };
+
+    let mut split = range.splitn(2, '/');
+    let network = split
+        .next()
+        .and_then(|network| network.parse::<IpAddr>().ok())
+        .ok_or_else(invalid)?;
+    let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+    let prefix = match split.next() {
+        Some(prefix) => prefix.parse::<u8>().map_err(|_| invalid())?,
+        None => max_prefix,
+    };
+
+    if prefix > max_prefix {
+        return Err(invalid());
+    }
+
+    Ok((network, prefix))
+}
+
+fn in_range(ip_address: &IpAddr, (network, prefix): &(IpAddr, u8)) -> bool {
+    let (ip_address, network, bits) = match (ip_address, network) {
+        (IpAddr::V4(ip_address), IpAddr::V4(network)) => {
+            (u128::from(u32::from(*ip_address)), u128::from(u32::from(*network)), 32)
+        }
+        (IpAddr::V6(ip_address), IpAddr::V6(network)) => (u128::from(*ip_address), u128::from(*network), 128),
+        _ => return false,
+    };
+
+    // a /0 range matches everything, and shifting by 128 bits would overflow
+    let host_bits = bits - u32::from(*prefix);
+    host_bits == 128 || ip_address >> host_bits == network >> host_bits
+}
+
+/// Scheme of an absolute URI (RFC 3986, section 3.1), the URI must not contain spaces.
+fn uri_scheme(uri: &str) -> Option<&str> {
+    let colon = uri.find(':')?;
+    let scheme = &uri[..colon];
+    let mut chars = scheme.chars();
+    let valid_scheme = chars.next().map_or(false, |c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.');
+
+    if valid_scheme && colon + 1 < uri.len() && !uri.chars().any(char::is_whitespace) {
+        Some(scheme)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use picky::{
+        key::PrivateKey,
+        pem::Pem,
+        signature::SignatureHashType,
+        x509::{
+            name::{DirectoryName, GeneralNames},
+            Extension,
+        },
+    };
+
+    fn policy() -> AltNamePolicy {
+        AltNamePolicy {
+            ip_ranges: vec!["10.1.0.0/16".to_owned(), "fd00::/8".to_owned(), "192.0.2.7".to_owned()],
+            uri_schemes: vec!["spiffe".to_owned()],
+            ..AltNamePolicy::default()
+        }
+    }
+
+    fn ips(ip_addresses: &[&str]) -> AltNames {
+        AltNames {
+            ip_addresses: ip_addresses.iter().map(|ip| ip.parse().unwrap()).collect(),
+            ..AltNames::default()
+        }
+    }
+
+    #[test]
+    fn ip_address_policy() {
+        let policy = policy();
+        policy.validate().expect("valid policy");
+
+        policy
+            .check(&ips(&["10.1.2.3", "10.1.255.255", "fd12::1", "192.0.2.7"]))
+            .expect("allowed addresses");
+        assert_eq!(
+            policy
+                .check(&ips(&["10.2.0.1"]))
+                .err()
+                .expect("out of range")
+                .to_string(),
+            "IP address 10.2.0.1 is not allowed by policy"
+        );
+        assert!(policy.check(&ips(&["192.0.2.8"])).is_err());
+        assert!(policy.check(&ips(&["::ffff:10.1.2.3"])).is_err());
+        assert!(AltNamePolicy::default().check(&ips(&["10.1.2.3"])).is_err());
+
+        let any = AltNamePolicy {
+            ip_ranges: vec!["0.0.0.0/0".to_owned()],
+            ..AltNamePolicy::default()
+        };
+        any.check(&ips(&["203.0.113.1"])).expect("any IPv4 address");
+
+        assert_eq!(
+            AltNamePolicy {
+                ip_ranges: vec!["10.0.0.0/33".to_owned()],
+                ..AltNamePolicy::default()
+            }
+            .validate()
+            .err()
+            .expect("invalid prefix")
+            .to_string(),
+            "invalid IP range '10.0.0.0/33', expected CIDR notation"
+        );
+    }
+
+    #[test]
+    fn uri_policy() {
+        let policy = policy();
+        let uris = |uris: &[&str]| AltNames {
+            uris: uris.iter().map(|uri| (*uri).to_owned()).collect(),
+            ..AltNames::default()
+        };
+
+        policy
+            .check(&uris(&["spiffe://example.org/payments", "SPIFFE://example.org/api"]))
+            .expect("allowed URIs");
+        assert_eq!(
+            policy
+                .check(&uris(&["https://example.org"]))
+                .err()
+                .expect("scheme")
+                .to_string(),
+            "URI scheme of 'https://example.org' is not allowed by policy"
+        );
+        assert_eq!(
+            policy
+                .check(&uris(&["example.org/payments"]))
+                .err()
+                .expect("relative")
+                .to_string(),
+            "invalid URI 'example.org/payments'"
+        );
+        assert!(policy.check(&uris(&["spiffe://example.org/a b"])).is_err());
+    }
+
+    #[test]
+    fn dns_name_policy() {
+        let dns_names = |dns_names: &[&str]| AltNames {
+            dns_names: dns_names.iter().map(|dns_name| (*dns_name).to_owned()).collect(),
+            ..AltNames::default()
+        };
+
+        AltNamePolicy::default()
+            .check(&dns_names(&["service.example.com.", "*.bücher.example"]))
+            .expect("valid DNS names");
+        assert_eq!(
+            AltNamePolicy::default()
+                .check(&dns_names(&["service..example.com"]))
+                .err()
+                .expect("empty label")
+                .to_string(),
+            "invalid DNS name 'service..example.com'"
+        );
+    }
+
+    #[test]
+    fn csr_alt_names() {
+        let key = PrivateKey::from_pem(&crate::test_files::RSA_2048_PK_1.parse::<Pem>().unwrap()).unwrap();
+        let mut general_names = GeneralNames::new(GeneralName::new_dns_name("www.example.com").unwrap());
+        general_names.add_name(GeneralName::new_ip_address(vec![10, 1, 2, 3]));
+        general_names.add_name(GeneralName::new_rfc822_name("admin@example.com").unwrap());
+        general_names.add_name(GeneralName::new_uri("spiffe://example.com/web").unwrap());
+        let csr = Csr::generate_with_extensions(
+            DirectoryName::new_common_name("example.com"),
+            &key,
+            SignatureHashType::RsaSha256,
+            vec![Extension::new_subject_alt_names(general_names)].into(),
+        )
+        .unwrap();
+
+        let alt_names = AltNames::from_csr(&csr).expect("CSR alt names");
+        assert_eq!(
+            alt_names,
+            AltNames {
+                dns_names: vec!["www.example.com".to_owned()],
+                ip_addresses: vec!["10.1.2.3".parse().unwrap()],
+                uris: vec!["spiffe://example.com/web".to_owned()],
+            }
+        );
+
+        let policy = AltNamePolicy {
+            honor_csr_alt_names: true,
+            csr_dns_subtrees: vec!["example.com".to_owned()],
+            ..policy()
+        };
+        policy.check_csr_dns_names(&alt_names).expect("DNS name in subtree");
+        policy.check(&alt_names).expect("allowed names");
+        assert_eq!(
+            AltNamePolicy {
+                csr_dns_subtrees: vec!["example.org".to_owned()],
+                ..policy
+            }
+            .check_csr_dns_names(&alt_names)
+            .err()
+            .expect("DNS name out of subtree")
+            .to_string(),
+            "DNS name www.example.com requested within CSR is not allowed by policy"
+        );
+        assert!(AltNamePolicy::default().check_csr_dns_names(&alt_names).is_err());
+
+        // names requested by token requesters
+        let vouched = vec!["api.example.org".to_owned()];
+        policy
+            .check_requested_dns_names(&["API.example.org.".to_owned(), "www.example.com".to_owned()], &vouched)
+            .expect("vouched name and name in subtree");
+        assert_eq!(
+            policy
+                .check_requested_dns_names(&["www.example.org".to_owned()], &vouched)
+                .err()
+                .expect("name neither vouched nor in subtree")
+                .to_string(),
+            "DNS name www.example.org is neither vouched for by the token nor allowed by policy"
+        );
+        assert!(AltNamePolicy::default()
+            .check_requested_dns_names(&["www.example.com".to_owned()], &[])
+            .is_err());
+
+        let csr = Csr::generate(
+            DirectoryName::new_common_name("example.com"),
+            &key,
+            SignatureHashType::RsaSha256,
+        )
+        .unwrap();
+        assert!(AltNames::from_csr(&csr).unwrap().is_empty());
+    }
+
+    #[test]
+    fn general_names() {
+        let mut alt_names = AltNames {
+            dns_names: vec!["service.example.com".to_owned()],
+            ..ips(&["10.1.2.3"])
+        };
+        alt_names.extend(AltNames {
+            dns_names: vec![
+                "Service.Example.com.".to_owned(),
+                "bücher.example".to_owned(),
+                "xn--bcher-kva.example".to_owned(),
+            ],
+            ip_addresses: vec!["fd00::1".parse().unwrap()],
+            uris: vec!["spiffe://example.org/service".to_owned()],
+        });
+
+        let general_names = alt_names.to_general_names().expect("general names");
+        assert_eq!(
+            general_names,
+            vec![
+                GeneralName::new_dns_name("service.example.com").unwrap(),
+                GeneralName::new_dns_name("xn--bcher-kva.example").unwrap(),
+                GeneralName::new_ip_address(vec![10, 1, 2, 3]),
+                GeneralName::new_ip_address(vec![0xfd, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]),
+                GeneralName::new_uri("spiffe://example.org/service").unwrap(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,253 @@
+//! Tamper-evident audit log.
+//!
+//! Each record carries the hash of the previous one, so rewriting or dropping a record breaks every
+//! subsequent link. Auditors fetch the records on `/audit/proof` and recompute the chain themselves.
+
+use crate::{
+    addressing::{encode_to_canonical_address, AddressingError},
+    db::{AuditRecord, PickyStorage, StorageError},
+    utils::unix_epoch,
+};
+use serde_json::Value;
+use snafu::Snafu;
+use std::sync::{Mutex, PoisonError};
+
+lazy_static::lazy_static! {
+    // reading the head and appending the next record must not interleave
+    static ref APPEND_LOCK: Mutex<()> = Mutex::new(());
+}
+
+#[derive(Debug, Snafu)]
+pub enum AuditError {
+    /// record content couldn't be serialized to be hashed
+    #[snafu(display("couldn't encode audit record {}: {}", sequence, source))]
+    RecordEncoding { sequence: u64, source: serde_json::Error },
+
+    /// record content couldn't be hashed
+    #[snafu(display("couldn't hash audit record {}: {}", sequence, source))]
+    RecordHashing { sequence: u64, source: AddressingError },
+
+    /// last record of the log couldn't be fetched
+    #[snafu(display("couldn't fetch audit log head: {}", source))]
+    HeadUnavailable { source: StorageError },
+
+    /// record couldn't be stored
+    #[snafu(display("couldn't store audit record {}: {}", sequence, source))]
+    RecordStorage { sequence: u64, source: StorageError },
+
+    /// first record of a chain isn't the start of the log
+    #[snafu(display("audit record {} has no predecessor", sequence))]
+    MissingPredecessor { sequence: u64 },
+
+    /// record isn't the successor of the previous one
+    #[snafu(display("audit record {} found where {} was expected", sequence, expected))]
+    UnexpectedSequence { sequence: u64, expected: u64 },
+
+    /// record doesn't carry the hash of the previous one
+    #[snafu(display("audit record {} isn't linked to its predecessor", sequence))]
+    BrokenLink { sequence: u64 },
+
+    /// record content was changed after being hashed
+    #[snafu(display("audit record {} doesn't match its hash", sequence))]
+    HashMismatch { sequence: u64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditEvent {
+    CertificateIssued,
+    CertificatePushed,
+    CertificateRevoked,
+    SigningRequestQueued,
+    SigningRequestApproved,
+    SigningRequestDenied,
+    RotationStateUpdated,
+    ExternalAccountKeyProvisioned,
+    ExternalAccountKeyRevoked,
+    AcmeAccountCreated,
+    ChallengeValidated,
+}
+
+impl AuditEvent {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuditEvent::CertificateIssued => "certificate_issued",
+            AuditEvent::CertificatePushed => "certificate_pushed",
+            AuditEvent::CertificateRevoked => "certificate_revoked",
+            AuditEvent::SigningRequestQueued => "signing_request_queued",
+            AuditEvent::SigningRequestApproved => "signing_request_approved",
+            AuditEvent::SigningRequestDenied => "signing_request_denied",
+            AuditEvent::RotationStateUpdated => "rotation_state_updated",
+            AuditEvent::ExternalAccountKeyProvisioned => "external_account_key_provisioned",
+            AuditEvent::ExternalAccountKeyRevoked => "external_account_key_revoked",
+            AuditEvent::AcmeAccountCreated => "acme_account_created",
+            AuditEvent::ChallengeValidated => "challenge_validated",
+        }
+    }
+}
+
+/// Canonical address of the JSON array `[sequence, timestamp, event, detail, previous_hash]`.
+pub fn record_hash(record: &AuditRecord) -> Result<String, AuditError> {
+    let sequence = record.sequence;
+    let content = serde_json::to_vec(&(
+        record.sequence,
+        record.timestamp,
+        &record.event,
+        &record.detail,
+        &record.previous_hash,
+    ))
+    .map_err(|source| AuditError::RecordEncoding { sequence, source })?;
+    encode_to_canonical_address(&content).map_err(|source| AuditError::RecordHashing { sequence, source })
+}
+
+/// Appends an event to the audit log and returns the new record.
+pub fn append(storage: &dyn PickyStorage, event: AuditEvent, detail: Value) -> Result<AuditRecord, AuditError> {
+    let _guard = APPEND_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+
+    let (sequence, previous_hash) = match storage
+        .get_audit_head()
+        .map_err(|source| AuditError::HeadUnavailable { source })?
+    {
+        Some(head) => (head.sequence + 1, head.hash),
+        None => (0, String::new()),
+    };
+
+    let mut record = AuditRecord {
+        sequence,
+        timestamp: unix_epoch(),
+        event: event.as_str().to_owned(),
+        detail: detail.to_string(),
+        previous_hash,
+        hash: String::new(),
+    };
+    record.hash = record_hash(&record)?;
+
+    storage
+        .store_audit_record(record.clone())
+        .map_err(|source| AuditError::RecordStorage { sequence, source })?;
+
+    Ok(record)
+}
+
+/// Checks that `records` are contiguous, that each one is linked to its predecessor and that their hashes
+/// match their content.
+///
+/// `previous` is the record preceding the first one, if the first one isn't the start of the log.
+pub fn verify_chain(previous: Option<&AuditRecord>, records: &[AuditRecord]) -> Result<(), AuditError> {
+    let mut previous = previous;
+
+    for record in records {
+        let (expected_sequence, expected_previous_hash) = match previous {
+            Some(previous) => (previous.sequence + 1, previous.hash.as_str()),
+            None => (record.sequence, ""),
+        };
+
+        let sequence = record.sequence;
+
+        if previous.is_none() && sequence != 0 {
+            return Err(AuditError::MissingPredecessor { sequence });
+        }
+
+        if sequence != expected_sequence {
+            return Err(AuditError::UnexpectedSequence {
+                sequence,
+                expected: expected_sequence,
+            });
+        }
+
+        if record.previous_hash != expected_previous_hash {
+            return Err(AuditError::BrokenLink { sequence });
+        }
+
+        if record.hash != record_hash(record)? {
+            return Err(AuditError::HashMismatch { sequence });
+        }
+
+        previous = Some(record);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{BackendType, Config},
+        db::get_storage,
+    };
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn memory_storage() -> Arc<dyn PickyStorage> {
+        let mut config = Config::default();
+        config.backend = BackendType::Memory;
+        get_storage(&config).expect("storage").0
+    }
+
+    #[test]
+    fn records_are_chained() {
+        let storage = memory_storage();
+        let first = append(
+            storage.as_ref(),
+            AuditEvent::SigningRequestQueued,
+            json!({ "id": "01" }),
+        )
+        .unwrap();
+        let second = append(
+            storage.as_ref(),
+            AuditEvent::SigningRequestApproved,
+            json!({ "id": "01" }),
+        )
+        .unwrap();
+
+        assert_eq!(first.sequence, 0);
+        assert_eq!(first.previous_hash, "");
+        assert_eq!(second.sequence, 1);
+        assert_eq!(second.previous_hash, first.hash);
+        assert_eq!(storage.get_audit_head().unwrap(), Some(second.clone()));
+
+        let records = storage.get_audit_records(0).unwrap();
+        assert_eq!(records, vec![first.clone(), second.clone()]);
+        verify_chain(None, &records).unwrap();
+        verify_chain(Some(&first), &records[1..]).unwrap();
+    }
+
+    #[test]
+    fn tampering_is_detected() {
+        let storage = memory_storage();
+        for id in 0..3 {
+            append(storage.as_ref(), AuditEvent::CertificateIssued, json!({ "id": id })).unwrap();
+        }
+        let records = storage.get_audit_records(0).unwrap();
+
+        let mut rewritten = records.clone();
+        rewritten[1].detail = json!({ "id": 42 }).to_string();
+        assert!(matches!(
+            verify_chain(None, &rewritten),
+            Err(AuditError::HashMismatch { sequence: 1 })
+        ));
+
+        let mut rehashed = rewritten;
+        rehashed[1].hash = record_hash(&rehashed[1]).unwrap();
+        assert!(matches!(
+            verify_chain(None, &rehashed),
+            Err(AuditError::BrokenLink { sequence: 2 })
+        ));
+
+        let dropped = vec![records[0].clone(), records[2].clone()];
+        let err = verify_chain(None, &dropped).unwrap_err();
+        assert!(matches!(
+            err,
+            AuditError::UnexpectedSequence {
+                sequence: 2,
+                expected: 1
+            }
+        ));
+        assert_eq!(err.to_string(), "audit record 2 found where 1 was expected");
+
+        assert!(matches!(
+            verify_chain(None, &records[1..]),
+            Err(AuditError::MissingPredecessor { sequence: 1 })
+        ));
+    }
+}
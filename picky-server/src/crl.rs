@@ -0,0 +1,322 @@
+//! Certificate revocation lists signed by the issuing intermediate CAs, the default one and those of `issuers`.
+//!
+//! CRLs are stored as artifacts keyed by the CA key identifier (and partition, see
+//! `leaf_extensions.crl_partitions`), and list the revocations recorded for this key identifier.
+//! The leader regenerates them every `refresh_interval_secs`, and they are generated on demand
+//! when missing or expired.
+
+use crate::{
+    addressing::ArtifactNamespace,
+    cert_cache,
+    config::{crl_partition, Config, ConfigError},
+    db::{PickyStorage, RevocationEntry, StorageError},
+    signer::{CaSigner, SignerError},
+    utils::{self, unix_epoch},
+};
+use chrono::{Duration, TimeZone, Utc};
+use picky::x509::{
+    certificate::CertError,
+    crl::{Crl, CrlError, RevokedCertificate},
+    date::UTCDate,
+    extension::{CrlReason, IssuingDistributionPoint},
+    Cert,
+};
+use picky_asn1::{restricted_string::CharSetError, wrapper::IntegerAsn1};
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::sync::{Arc, RwLock};
+
+#[derive(Debug, Snafu)]
+pub enum CrlGenerationError {
+    /// issuer isn't configured
+    #[snafu(display("{}", source))]
+    UnknownIssuer { source: ConfigError },
+
+    /// storage backend failed
+    #[snafu(display("{}: {}", context, source))]
+    Storage { context: String, source: StorageError },
+
+    /// stored CA certificate couldn't be parsed
+    #[snafu(display("couldn't deserialize CA cert: {}", source))]
+    InvalidCaCert { source: CertError },
+
+    /// CA certificate has no subject key identifier
+    #[snafu(display("couldn't get CA key identifier: {}", source))]
+    CaKeyIdentifier { source: CertError },
+
+    /// stored revocation has a serial number which isn't hex-encoded
+    #[snafu(display("invalid revoked serial number {}: {}", serial_number, source))]
+    InvalidSerialNumber {
+        serial_number: String,
+        source: hex::FromHexError,
+    },
+
+    /// distribution point URL can't be encoded
+    #[snafu(display("invalid CRL URL {}: {}", url, source))]
+    InvalidUrl { url: String, source: CharSetError },
+
+    /// CRL couldn't be signed
+    #[snafu(display("couldn't generate CRL: {}", source))]
+    Signing { source: SignerError },
+
+    /// CRL couldn't be encoded
+    #[snafu(display("couldn't encode CRL: {}", source))]
+    Encoding { source: CrlError },
+}
+
+impl CrlGenerationError {
+    /// Wraps a storage error with a description of the failed operation.
+    fn storage(context: &str) -> impl FnOnce(StorageError) -> Self + '_ {
+        move |source| CrlGenerationError::Storage {
+            context: context.to_owned(),
+            source,
+        }
+    }
+}
+
+const fn default_refresh_interval_secs() -> u64 {
+    60 * 60
+}
+
+const fn default_validity_secs() -> u64 {
+    24 * 60 * 60
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CrlConfig {
+    /// Delay between two regenerations of the CRLs
+    #[serde(default = "default_refresh_interval_secs")]
+    pub refresh_interval_secs: u64,
+    /// Delay between the generation of a CRL and its announced next update
+    #[serde(default = "default_validity_secs")]
+    pub validity_secs: u64,
+}
+
+impl Default for CrlConfig {
+    fn default() -> Self {
+        Self {
+            refresh_interval_secs: default_refresh_interval_secs(),
+            validity_secs: default_validity_secs(),
+        }
+    }
+}
+
+impl CrlConfig {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.refresh_interval_secs == 0 {
+            return Err(ConfigError::ValueTooSmall {
+                field: "refresh_interval_secs",
+                min: 1,
+            });
+        }
+
+        if self.validity_secs < self.refresh_interval_secs {
+            return Err(ConfigError::ShorterThan {
+                field: "validity_secs",
+                other: "refresh_interval_secs",
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Hex-encoded key identifier of `ca_cert`, identifying the issuer of revoked certificates.
+pub fn ca_key_identifier(ca_cert: &Cert) -> Result<String, CrlGenerationError> {
+    ca_cert
+        .subject_key_identifier()
+        .map(hex::encode)
+        .map_err(|source| CrlGenerationError::CaKeyIdentifier { source })
+}
+
+/// Key of the "latest" pointer of the CRL issued by `ca_cert` for `partition` (the complete CRL if `None`).
+pub fn latest_key(ca_cert: &Cert, partition: Option<u32>) -> Result<String, CrlGenerationError> {
+    let key_identifier = ca_key_identifier(ca_cert)?;
+
+    Ok(match partition {
+        Some(partition) => format!("{}-{}", key_identifier, partition),
+        None => key_identifier,
+    })
+}
+
+/// Key identifier of the CA whose CRL is pointed to by `latest_key`, see `latest_key`.
+pub fn latest_key_issuer(latest_key: &str) -> &str {
+    latest_key.splitn(2, '-').next().unwrap_or(latest_key)
+}
+
+/// Whether a stored CRL can still be served, i.e. its next update isn't due yet.
+pub fn is_current(crl_der: &[u8]) -> bool {
+    Crl::from_der(crl_der)
+        .ok()
+        .and_then(|crl| crl.next_update())
+        .map_or(false, |next_update| next_update > UTCDate::now())
+}
+
+/// Generates and stores the CRL of the intermediate CA of `issuer` for `partition` (the complete CRL if `None`).
+///
+/// Returns the address of the stored CRL along with its DER encoding.
+pub fn generate(
+    config: &Config,
+    storage: &dyn PickyStorage,
+    signer: &CaSigner,
+    issuer: Option<&str>,
+    partition: Option<u32>,
+) -> Result<(String, Vec<u8>), CrlGenerationError> {
+    let ca_name = config
+        .issuer_ca_name(issuer)
+        .map_err(|source| CrlGenerationError::UnknownIssuer { source })?;
+    let ca_hash = storage
+        .get_addressing_hash_by_name(&ca_name)
+        .map_err(CrlGenerationError::storage("couldn't fetch CA"))?;
+    let ca_cert_der = storage
+        .get_cert_by_addressing_hash(&ca_hash)
+        .map_err(CrlGenerationError::storage("couldn't get CA cert der"))?;
+    let ca_cert = cert_cache::parse(&ca_cert_der).map_err(|source| CrlGenerationError::InvalidCaCert { source })?;
+    let ca_key_identifier = ca_key_identifier(&ca_cert)?;
+    let revocations = storage
+        .revoked_since(0)
+        .map_err(CrlGenerationError::storage("couldn't fetch revocations"))?;
+    let partitions = config.leaf_extensions.crl_partitions;
+    let mut revoked_certificates = Vec::with_capacity(revocations.len());
+    for entry in revocations
+        .into_iter()
+        .filter(|entry| entry.issuer == ca_key_identifier)
+    {
+        let serial_number =
+            hex::decode(&entry.serial_number).map_err(|source| CrlGenerationError::InvalidSerialNumber {
+                serial_number: entry.serial_number.clone(),
+                source,
+            })?;
+        if partition.map_or(true, |partition| crl_partition(&serial_number, partitions) == partition) {
+            revoked_certificates.push(revoked_certificate(serial_number, &entry));
+        }
+    }
+
+    let now = utils::now();
+    let idp = match config.crl_url(issuer, partition) {
+        Some(url) => Some(
+            IssuingDistributionPoint::new()
+                .uri(url.as_str())
+                .map_err(|source| CrlGenerationError::InvalidUrl {
+                    url: url.clone(),
+                    source,
+                })?
+                .only_contains_user_certs(true),
+        ),
+        None => None,
+    };
+    let crl = signer
+        .sign_crl(&ca_hash, &ca_cert, |builder| {
+            builder
+                .this_update(UTCDate::from(now))
+                .next_update(UTCDate::from(now + Duration::seconds(config.crl.validity_secs as i64)))
+                .signature_hash_type(config.issuer_leaf_signing_algorithm(&ca_name))
+                .crl_number(crl_number(unix_epoch()))
+                .revoked_certificates(revoked_certificates);
+            if let Some(idp) = idp {
+                builder.issuing_distribution_point(idp);
+            }
+        })
+        .map_err(|source| CrlGenerationError::Signing { source })?;
+    let crl_der = crl.to_der().map_err(|source| CrlGenerationError::Encoding { source })?;
+
+    let address = storage
+        .store_artifact(
+            ArtifactNamespace::Crl,
+            &latest_key(&ca_cert, partition)?,
+            crl_der.clone(),
+        )
+        .map_err(CrlGenerationError::storage("couldn't store CRL"))?;
+
+    Ok((address, crl_der))
+}
+
+/// Generates, for every issuer, the complete CRL and, if partitioned, the CRL of each partition.
+///
+/// Returns the addresses of the stored CRLs, the complete one of each issuer first.
+pub fn generate_all(
+    config: &Config,
+    storage: &dyn PickyStorage,
+    signer: &CaSigner,
+) -> Result<Vec<String>, CrlGenerationError> {
+    let mut partitions = vec![None];
+    if config.leaf_extensions.crl_partitions > 1 {
+        partitions.extend((0..config.leaf_extensions.crl_partitions).map(Some));
+    }
+
+    let mut addresses = Vec::new();
+    for issuer in config.all_issuers() {
+        for partition in &partitions {
+            let (address, _) = generate(config, storage, signer, issuer, *partition)?;
+            addresses.push(address);
+        }
+    }
+
+    Ok(addresses)
+}
+
+pub fn spawn_crl_refresher(config: Arc<RwLock<Config>>, storage: Arc<dyn PickyStorage>, signer: Arc<CaSigner>) {
+    std::thread::spawn(move || loop {
+        let config = config.read().expect("config lock").clone();
+
+        // a single instance of a replicated deployment signs CRLs
+        match storage.is_leader() {
+            Ok(true) => {
+                if let Err(e) = generate_all(&config, storage.as_ref(), signer.as_ref()) {
+                    log::error!("couldn't refresh CRLs: {}", e);
+                }
+            }
+            Ok(false) => {}
+            Err(e) => log::error!("CRL refresher: couldn't check leadership: {}", e),
+        }
+
+        std::thread::sleep(std::time::Duration::from_secs(config.crl.refresh_interval_secs));
+    });
+}
+
+fn revoked_certificate(serial_number: Vec<u8>, entry: &RevocationEntry) -> RevokedCertificate {
+    let revoked = RevokedCertificate::new(
+        IntegerAsn1::from_unsigned_bytes_be(serial_number),
+        UTCDate::from(Utc.timestamp(entry.revoked_at as i64, 0)),
+    );
+
+    match entry.reason.and_then(CrlReason::from_code) {
+        Some(reason) => revoked.with_reason(reason),
+        None => revoked,
+    }
+}
+
+/// CRL numbers must increase with each CRL, the generation time does.
+fn crl_number(timestamp: u64) -> Vec<u8> {
+    let bytes = timestamp.to_be_bytes();
+    let start = bytes.iter().position(|byte| *byte != 0).unwrap_or(bytes.len() - 1);
+    bytes[start..].to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crl_numbers() {
+        assert_eq!(crl_number(0), vec![0x00]);
+        assert_eq!(crl_number(1_600_000_000), vec![0x5F, 0x5E, 0x10, 0x00]);
+    }
+
+    #[test]
+    fn validation() {
+        assert!(CrlConfig::default().validate().is_ok());
+
+        let config = CrlConfig {
+            refresh_interval_secs: 3600,
+            validity_secs: 60,
+        };
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::ShorterThan {
+                field: "validity_secs",
+                other: "refresh_interval_secs",
+            })
+        ));
+    }
+}
@@ -0,0 +1,441 @@
+use crate::{
+    cert_cache,
+    config::Config,
+    db::{CertificateEntry, PickyStorage, StorageError, StorageObserver},
+    notifier::{notify, NotificationEvent},
+};
+use picky::x509::{certificate::CertError, hostname::normalize_dns_name, name::GeneralName, Cert};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use snafu::Snafu;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, PoisonError, RwLock},
+    time::Duration,
+};
+
+const fn default_poll_interval_secs() -> u64 {
+    60
+}
+
+const fn default_batch_size() -> u64 {
+    256
+}
+
+const X509_ENTRY_TYPE: u16 = 0;
+const PRECERT_ENTRY_TYPE: u16 = 1;
+
+lazy_static::lazy_static! {
+    // key identifiers of the CAs stored since startup, see `IssuerTracker`
+    static ref STORED_ISSUERS: RwLock<HashSet<Vec<u8>>> = RwLock::new(HashSet::new());
+}
+
+/// Certificate Transparency logs monitoring, used to detect certificates issued
+/// for our domains by another certificate authority.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CtMonitorConfig {
+    /// Base URLs of the monitored CT logs (e.g. "https://ct.googleapis.com/logs/argon2020/")
+    pub logs: Vec<String>,
+    /// Domains in our namespace (subdomains included)
+    pub domains: Vec<String>,
+    #[serde(default = "default_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+    /// Maximum number of entries fetched per get-entries request
+    #[serde(default = "default_batch_size")]
+    pub batch_size: u64,
+    /// URL receiving a JSON POST request for each detected certificate
+    #[serde(default)]
+    pub alert_webhook: Option<String>,
+}
+
+#[derive(Debug, Snafu)]
+enum CtMonitorError {
+    /// log didn't return its signed tree head
+    #[snafu(display("couldn't get signed tree head: {}", source))]
+    SignedTreeHead { source: reqwest::Error },
+
+    /// log didn't return the requested entries
+    #[snafu(display("couldn't get entries {}..={}: {}", start, end, source))]
+    Entries {
+        start: u64,
+        end: u64,
+        source: reqwest::Error,
+    },
+
+    /// entry ends in the middle of a length prefix
+    #[snafu(display("truncated length prefix"))]
+    TruncatedLengthPrefix,
+
+    /// entry is shorter than its length prefix
+    #[snafu(display("truncated data (expected {} bytes)", len))]
+    TruncatedData { len: usize },
+
+    /// leaf input isn't base64
+    #[snafu(display("invalid leaf input: {}", source))]
+    InvalidLeafInput { source: base64::DecodeError },
+
+    /// leaf input is shorter than a merkle tree leaf header
+    #[snafu(display("truncated merkle tree leaf"))]
+    TruncatedLeaf,
+
+    /// extra data isn't base64
+    #[snafu(display("invalid extra data: {}", source))]
+    InvalidExtraData { source: base64::DecodeError },
+
+    /// logged (pre-)certificate couldn't be parsed
+    #[snafu(display("couldn't parse certificate: {}", source))]
+    InvalidCertificate { source: CertError },
+
+    /// storage backend failed
+    #[snafu(display("{}: {}", context, source))]
+    Storage {
+        context: &'static str,
+        source: StorageError,
+    },
+
+    /// stored CA certificate couldn't be parsed
+    #[snafu(display("couldn't deserialize CA cert: {}", source))]
+    InvalidCaCert { source: CertError },
+}
+
+#[derive(Deserialize, Debug)]
+struct SignedTreeHead {
+    tree_size: u64,
+}
+
+#[derive(Deserialize, Debug)]
+struct LogEntries {
+    entries: Vec<LogEntry>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LogEntry {
+    leaf_input: String,
+    extra_data: String,
+}
+
+fn log_url(log: &str, endpoint: &str) -> String {
+    format!("{}/ct/v1/{}", log.trim_end_matches('/'), endpoint)
+}
+
+fn get_tree_size(client: &reqwest::Client, log: &str) -> Result<u64, CtMonitorError> {
+    let sth: SignedTreeHead = client
+        .get(&log_url(log, "get-sth"))
+        .send()
+        .and_then(|mut res| res.json())
+        .map_err(|source| CtMonitorError::SignedTreeHead { source })?;
+    Ok(sth.tree_size)
+}
+
+fn get_entries(client: &reqwest::Client, log: &str, start: u64, end: u64) -> Result<Vec<LogEntry>, CtMonitorError> {
+    let entries: LogEntries = client
+        .get(&format!("{}?start={}&end={}", log_url(log, "get-entries"), start, end))
+        .send()
+        .and_then(|mut res| res.json())
+        .map_err(|source| CtMonitorError::Entries { start, end, source })?;
+    Ok(entries.entries)
+}
+
+fn read_u24_prefixed(data: &[u8]) -> Result<&[u8], CtMonitorError> {
+    if data.len() < 3 {
+        return Err(CtMonitorError::TruncatedLengthPrefix);
+    }
+
+    let len = (usize::from(data[0]) << 16) | (usize::from(data[1]) << 8) | usize::from(data[2]);
+    data.get(3..3 + len).ok_or(CtMonitorError::TruncatedData { len })
+}
+
+/// Extracts the (pre-)certificate from a CT log entry as defined by RFC 6962 section 4.6
+fn parse_log_entry(entry: &LogEntry) -> Result<Option<Cert>, CtMonitorError> {
+    let leaf_input = base64::decode(&entry.leaf_input).map_err(|source| CtMonitorError::InvalidLeafInput { source })?;
+
+    // MerkleTreeLeaf: version (1 byte), leaf type (1 byte), timestamp (8 bytes), entry type (2 bytes)
+    if leaf_input.len() < 12 {
+        return Err(CtMonitorError::TruncatedLeaf);
+    }
+    let entry_type = u16::from_be_bytes([leaf_input[10], leaf_input[11]]);
+
+    let der = match entry_type {
+        X509_ENTRY_TYPE => read_u24_prefixed(&leaf_input[12..])?.to_vec(),
+        PRECERT_ENTRY_TYPE => {
+            // the complete pre-certificate is found in extra data (PrecertChainEntry)
+            let extra_data =
+                base64::decode(&entry.extra_data).map_err(|source| CtMonitorError::InvalidExtraData { source })?;
+            read_u24_prefixed(&extra_data)?.to_vec()
+        }
+        _ => return Ok(None),
+    };
+
+    Cert::from_der(&der)
+        .map(Some)
+        .map_err(|source| CtMonitorError::InvalidCertificate { source })
+}
+
+fn dns_names(cert: &Cert) -> Vec<String> {
+    let mut names = Vec::new();
+
+    if let Some(common_name) = cert.subject_name().find_common_name() {
+        names.push(common_name.to_string());
+    }
+
+    if let Ok(san) = cert.subject_alt_names() {
+        for name in san.into_general_names() {
+            if let GeneralName::DNSName(dns_name) = name {
+                names.push(dns_name.to_string());
+            }
+        }
+    }
+
+    names
+}
+
+fn is_in_namespace(name: &str, domains: &[String]) -> bool {
+    // names are compared in their ASCII form, internationalized domains may be configured in Unicode
+    let ascii_name = |name: &str| normalize_dns_name(name).unwrap_or_else(|_| name.to_lowercase());
+    let name = ascii_name(name.trim_start_matches("*."));
+    domains.iter().any(|domain| {
+        let domain = ascii_name(domain.trim_start_matches('.'));
+        name == domain || name.ends_with(&format!(".{}", domain))
+    })
+}
+
+fn is_issued_by(cert: &Cert, ca_cert: &Cert) -> bool {
+    match (cert.authority_key_identifier(), ca_cert.subject_key_identifier()) {
+        (Ok(aki), Ok(ski)) if aki.key_identifier().is_some() => aki.key_identifier() == Some(ski),
+        _ => cert.issuer_name() == ca_cert.subject_name(),
+    }
+}
+
+fn is_issued_by_stored_ca(cert: &Cert) -> bool {
+    let key_identifier = cert
+        .authority_key_identifier()
+        .ok()
+        .and_then(|aki| aki.key_identifier().map(|key_identifier| key_identifier.to_vec()));
+    key_identifier.map_or(false, |key_identifier| {
+        STORED_ISSUERS
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains(&key_identifier)
+    })
+}
+
+fn fetch_ca_cert(config: &Config, storage: &dyn PickyStorage) -> Result<Arc<Cert>, CtMonitorError> {
+    let ca_name = format!("{} Authority", config.realm);
+    let ca_hash = storage
+        .get_addressing_hash_by_name(&ca_name)
+        .map_err(|source| CtMonitorError::Storage {
+            context: "couldn't fetch CA",
+            source,
+        })?;
+    let ca_cert_der = storage
+        .get_cert_by_addressing_hash(&ca_hash)
+        .map_err(|source| CtMonitorError::Storage {
+            context: "couldn't get CA cert der",
+            source,
+        })?;
+    cert_cache::parse(&ca_cert_der).map_err(|source| CtMonitorError::InvalidCaCert { source })
+}
+
+fn alert(client: &reqwest::Client, config: &Config, ct_config: &CtMonitorConfig, log: &str, index: u64, cert: &Cert) {
+    let domains = dns_names(cert)
+        .into_iter()
+        .filter(|name| is_in_namespace(name, &ct_config.domains))
+        .collect::<Vec<String>>();
+    let issuer = cert.issuer_name().to_string();
+
+    log::warn!(
+        "certificate for {:?} issued by {} found in CT log {} at index {}",
+        domains,
+        issuer,
+        log,
+        index
+    );
+
+    if let Some(webhook) = &ct_config.alert_webhook {
+        let body = json!({
+            "log": log,
+            "index": index,
+            "domains": domains,
+            "issuer": issuer,
+            "serial_number": hex::encode(cert.serial_number().as_unsigned_bytes_be()),
+        });
+        if let Err(e) = client.post(webhook).json(&body).send() {
+            log::error!("couldn't call CT monitor alert webhook: {}", e);
+        }
+    }
+
+    notify(
+        config,
+        NotificationEvent::ForeignIssuance {
+            domains,
+            issuer,
+            log: log.to_owned(),
+        },
+    );
+}
+
+fn poll_log(
+    client: &reqwest::Client,
+    config: &Config,
+    ct_config: &CtMonitorConfig,
+    ca_cert: &Cert,
+    log: &str,
+    next_index: &mut u64,
+) -> Result<(), CtMonitorError> {
+    let tree_size = get_tree_size(client, log)?;
+
+    while *next_index < tree_size {
+        let end = (*next_index + ct_config.batch_size.max(1) - 1).min(tree_size - 1);
+        let entries = get_entries(client, log, *next_index, end)?;
+        if entries.is_empty() {
+            break;
+        }
+
+        for entry in entries.iter() {
+            match parse_log_entry(entry) {
+                Ok(Some(cert)) => {
+                    let in_namespace = dns_names(&cert)
+                        .iter()
+                        .any(|name| is_in_namespace(name, &ct_config.domains));
+                    if in_namespace && !is_issued_by(&cert, ca_cert) && !is_issued_by_stored_ca(&cert) {
+                        alert(client, config, ct_config, log, *next_index, &cert);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => log::debug!("skipped CT log {} entry {}: {}", log, *next_index, e),
+            }
+            *next_index += 1;
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the observer recording the CAs whose certificates aren't foreign issuances, if CT logs are monitored.
+pub fn issuer_tracker(config: &Config) -> Option<Arc<dyn StorageObserver>> {
+    config
+        .ct_monitor
+        .as_ref()
+        .map(|_| Arc::new(IssuerTracker) as Arc<dyn StorageObserver>)
+}
+
+/// Storage observer recording the CAs stored while running besides the default intermediate CA (e.g.
+/// `issuers` added by a configuration reload), so that the certificates they issue aren't reported.
+struct IssuerTracker;
+
+impl StorageObserver for IssuerTracker {
+    fn on_store(&self, _: &dyn PickyStorage, entry: &CertificateEntry) {
+        // private keys are only stored along CA certificates
+        if entry.key.is_none() {
+            return;
+        }
+
+        match hex::decode(&entry.key_identifier) {
+            Ok(key_identifier) => {
+                STORED_ISSUERS
+                    .write()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .insert(key_identifier);
+            }
+            Err(e) => log::error!("invalid key identifier of {}: {}", entry.name, e),
+        }
+    }
+}
+
+/// Tails configured CT logs in a background thread. Only entries appended after startup are inspected.
+pub fn spawn_ct_monitor(config: Arc<RwLock<Config>>, storage: Arc<dyn PickyStorage>) {
+    std::thread::spawn(move || {
+        let client = reqwest::Client::new();
+        let mut next_indexes: HashMap<String, u64> = HashMap::new();
+
+        loop {
+            let config = config.read().expect("config lock").clone();
+            let ct_config = match &config.ct_monitor {
+                Some(ct_config) => ct_config,
+                None => {
+                    std::thread::sleep(Duration::from_secs(default_poll_interval_secs()));
+                    continue;
+                }
+            };
+
+            // a single instance of a replicated deployment sends alerts
+            match storage.is_leader() {
+                Ok(true) => {}
+                Ok(false) => {
+                    std::thread::sleep(Duration::from_secs(ct_config.poll_interval_secs));
+                    continue;
+                }
+                Err(e) => {
+                    log::error!("CT monitor: couldn't check leadership: {}", e);
+                    std::thread::sleep(Duration::from_secs(ct_config.poll_interval_secs));
+                    continue;
+                }
+            }
+
+            match fetch_ca_cert(&config, storage.as_ref()) {
+                Ok(ca_cert) => {
+                    for log in ct_config.logs.iter() {
+                        if !next_indexes.contains_key(log) {
+                            match get_tree_size(&client, log) {
+                                Ok(tree_size) => {
+                                    next_indexes.insert(log.clone(), tree_size);
+                                }
+                                Err(e) => {
+                                    log::error!("couldn't start monitoring CT log {}: {}", log, e);
+                                    continue;
+                                }
+                            }
+                        }
+                        let next_index = next_indexes.get_mut(log).expect("inserted above");
+
+                        if let Err(e) = poll_log(&client, &config, ct_config, &ca_cert, log, next_index) {
+                            log::error!("couldn't poll CT log {}: {}", log, e);
+                        }
+                    }
+                }
+                Err(e) => log::error!("CT monitor: {}", e),
+            }
+
+            std::thread::sleep(Duration::from_secs(ct_config.poll_interval_secs));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn namespace_matching() {
+        let domains = vec![
+            "example.com".to_owned(),
+            ".Contoso.local".to_owned(),
+            "bücher.example".to_owned(),
+        ];
+        assert!(is_in_namespace("example.com", &domains));
+        assert!(is_in_namespace("www.EXAMPLE.com", &domains));
+        assert!(is_in_namespace("*.example.com", &domains));
+        assert!(is_in_namespace("host.contoso.local", &domains));
+        assert!(is_in_namespace("host.contoso.local.", &domains));
+        assert!(is_in_namespace("shop.xn--bcher-kva.example", &domains));
+        assert!(!is_in_namespace("notexample.com", &domains));
+        assert!(!is_in_namespace("example.com.evil.org", &domains));
+    }
+
+    #[test]
+    fn u24_length_prefix() {
+        assert_eq!(read_u24_prefixed(&[0, 0, 2, 0xAB, 0xCD, 0xEF]).unwrap(), &[0xAB, 0xCD]);
+        assert!(read_u24_prefixed(&[0, 0, 4, 0xAB]).is_err());
+        assert!(read_u24_prefixed(&[0, 0]).is_err());
+    }
+
+    #[test]
+    fn skip_unknown_entry_type() {
+        let leaf_input = base64::encode(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 7]);
+        let entry = LogEntry {
+            leaf_input,
+            extra_data: String::new(),
+        };
+        assert!(parse_log_entry(&entry).unwrap().is_none());
+    }
+}
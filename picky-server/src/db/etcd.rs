@@ -0,0 +1,666 @@
+//! etcd v3 storage, for Kubernetes deployments running picky-server with no database besides the
+//! cluster's own etcd.
+//!
+//! Requests go through the etcd JSON gateway (`/v3/kv/*`, `/v3/lease/*`), keys and values are
+//! base64-encoded as required by the gateway. Every entry lives under the `picky/` prefix, in one
+//! key space per collection. Collections without a secondary index (requesters, labels,
+//! revocations, ...) are scanned by prefix.
+//!
+//! The leader of a replicated deployment is elected using a lease-based lock: the leader key is
+//! created bound to the holder lease, kept alive in the background, and released by etcd when the
+//! holder stops renewing it.
+
+use crate::{
+    addressing::{encode_to_alternative_addresses, encode_to_canonical_address, ArtifactNamespace},
+    config::Config,
+    db::{
+        AuditRecord, CertificateEntry, ExternalAccountKey, KeyUsageEntry, PickyStorage, PrivateKeyLocker,
+        RevocationEntry, RotationState, SigningRequestEntry, SigningRequestStatus, StorageCapabilities, StorageError,
+    },
+    labels::{self, Labels},
+};
+use rand::RngCore;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use snafu::{ResultExt, Snafu};
+use std::{
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+const KEY_PREFIX: &str = "picky/";
+
+const COLLECTION_NAME: &str = "name";
+const COLLECTION_CERTIFICATE: &str = "certificate";
+const COLLECTION_KEY: &str = "key";
+const COLLECTION_KEY_IDENTIFIER: &str = "key_identifier";
+const COLLECTION_HASH_LOOKUP: &str = "hash_lookup";
+const COLLECTION_REQUESTER: &str = "requester";
+const COLLECTION_LABELS: &str = "labels";
+const COLLECTION_SIGNING_REQUEST: &str = "signing_request";
+const COLLECTION_ARTIFACT: &str = "artifact";
+const COLLECTION_LATEST_ARTIFACT: &str = "latest_artifact";
+const COLLECTION_REVOCATION: &str = "revocation";
+const COLLECTION_AUDIT: &str = "audit";
+const COLLECTION_EXTERNAL_ACCOUNT_KEY: &str = "external_account_key";
+const COLLECTION_KEY_USAGE: &str = "key_usage";
+
+const ROTATION_STATE_KEY: &str = "rotation_state";
+const LEADER_KEY: &str = "leader";
+/// Leases are renewed at a third of their TTL, but never more often than this
+const MIN_LEASE_RENEWAL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Snafu)]
+pub enum EtcdStorageError {
+    #[snafu(display("etcd request to {} failed: {}", endpoint, source))]
+    Request { endpoint: String, source: reqwest::Error },
+
+    #[snafu(display("json error: {}", source))]
+    Json { source: serde_json::Error },
+
+    #[snafu(display("{}", description))]
+    NotFound { description: String },
+
+    #[snafu(display("generic error: {}", description))]
+    Other { description: String },
+}
+
+impl From<String> for EtcdStorageError {
+    fn from(description: String) -> Self {
+        Self::Other { description }
+    }
+}
+
+/// Key/value pair returned by a range request
+struct KeyValue {
+    key: String,
+    value: Vec<u8>,
+}
+
+pub struct EtcdStorage {
+    client: reqwest::Client,
+    url: String,
+    lease_ttl_secs: u64,
+    /// Identifies this instance as holder of the leader key
+    holder: String,
+    /// Lease the leader key is bound to, kept alive by a background thread
+    lease: Arc<Mutex<Option<i64>>>,
+}
+
+impl EtcdStorage {
+    pub fn new(config: &Config) -> Self {
+        let mut holder = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut holder);
+
+        let storage = Self {
+            client: reqwest::Client::new(),
+            url: config.etcd_url.trim_end_matches('/').to_owned(),
+            lease_ttl_secs: config.etcd_lease_ttl_secs.max(2),
+            holder: hex::encode(holder),
+            lease: Arc::new(Mutex::new(None)),
+        };
+        storage.health().expect("couldn't reach etcd");
+        storage
+    }
+
+    fn call(&self, endpoint: &str, body: &Value) -> Result<Value, EtcdStorageError> {
+        call(&self.client, &self.url, endpoint, body)
+    }
+
+    fn range(&self, key: &str) -> Result<Option<Vec<u8>>, EtcdStorageError> {
+        let res = self.call("/v3/kv/range", &json!({ "key": encode_key(key) }))?;
+        Ok(key_values(&res)?.into_iter().next().map(|kv| kv.value))
+    }
+
+    fn range_prefix(&self, prefix: &str) -> Result<Vec<KeyValue>, EtcdStorageError> {
+        let res = self.call(
+            "/v3/kv/range",
+            &json!({
+                "key": encode_key(prefix),
+                "range_end": base64::encode(&prefix_range_end(&full_key(prefix))),
+            }),
+        )?;
+        key_values(&res)
+    }
+
+    fn get(&self, collection: &str, key: &str) -> Result<Option<Vec<u8>>, EtcdStorageError> {
+        self.range(&collection_key(collection, key))
+    }
+
+    fn get_string(&self, collection: &str, key: &str) -> Result<Option<String>, EtcdStorageError> {
+        self.get(collection, key)?
+            .map(|value| String::from_utf8(value).map_err(|e| format!("invalid string value: {}", e).into()))
+            .transpose()
+    }
+
+    fn get_json<T: DeserializeOwned>(&self, collection: &str, key: &str) -> Result<Option<T>, EtcdStorageError> {
+        self.get(collection, key)?
+            .map(|value| serde_json::from_slice(&value).context(Json))
+            .transpose()
+    }
+
+    fn get_all_json<T: DeserializeOwned>(&self, collection: &str) -> Result<Vec<T>, EtcdStorageError> {
+        self.range_prefix(&collection_key(collection, ""))?
+            .into_iter()
+            .map(|kv| serde_json::from_slice(&kv.value).context(Json))
+            .collect()
+    }
+
+    fn put(&self, collection: &str, key: &str, value: &[u8]) -> Result<(), EtcdStorageError> {
+        self.call("/v3/kv/put", &put_request(&collection_key(collection, key), value))?;
+        Ok(())
+    }
+
+    fn put_json<T: Serialize>(&self, collection: &str, key: &str, value: &T) -> Result<(), EtcdStorageError> {
+        self.put(collection, key, &serde_json::to_vec(value).context(Json)?)
+    }
+
+    /// Writes all `puts` atomically.
+    fn put_all(&self, puts: Vec<(String, Vec<u8>)>) -> Result<(), EtcdStorageError> {
+        let success = puts
+            .iter()
+            .map(|(key, value)| json!({ "request_put": put_request(key, value) }))
+            .collect::<Vec<Value>>();
+        let res = self.call("/v3/kv/txn", &json!({ "success": success }))?;
+        if res.get("succeeded").and_then(Value::as_bool).unwrap_or(false) {
+            Ok(())
+        } else {
+            Err("transaction wasn't committed".to_owned().into())
+        }
+    }
+
+    /// Returns the lease bound to the leader key, granting a new one if the previous one expired.
+    fn leader_lease(&self) -> Result<i64, EtcdStorageError> {
+        let mut lease = self.lease.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(id) = *lease {
+            return Ok(id);
+        }
+
+        let res = self.call("/v3/lease/grant", &json!({ "TTL": self.lease_ttl_secs }))?;
+        let id = int_field(&res, "ID").ok_or_else(|| "lease grant response without ID".to_owned())?;
+        *lease = Some(id);
+        spawn_lease_keeper(
+            self.client.clone(),
+            self.url.clone(),
+            Arc::clone(&self.lease),
+            id,
+            self.lease_ttl_secs,
+        );
+
+        Ok(id)
+    }
+}
+
+fn call(client: &reqwest::Client, url: &str, endpoint: &str, body: &Value) -> Result<Value, EtcdStorageError> {
+    client
+        .post(&format!("{}{}", url, endpoint))
+        .json(body)
+        .send()
+        .and_then(|res| res.error_for_status())
+        .and_then(|mut res| res.json())
+        .context(Request { endpoint })
+}
+
+/// Renews `id` at a third of its TTL (see `MIN_LEASE_RENEWAL_INTERVAL`) until it expires or is replaced.
+fn spawn_lease_keeper(client: reqwest::Client, url: String, lease: Arc<Mutex<Option<i64>>>, id: i64, ttl_secs: u64) {
+    let renewal_interval = Duration::from_millis(ttl_secs * 1000 / 3).max(MIN_LEASE_RENEWAL_INTERVAL);
+    std::thread::spawn(move || loop {
+        std::thread::sleep(renewal_interval);
+
+        let alive = match call(&client, &url, "/v3/lease/keepalive", &json!({ "ID": id })) {
+            Ok(res) => {
+                res.get("result")
+                    .and_then(|result| int_field(result, "TTL"))
+                    .unwrap_or(0)
+                    > 0
+            }
+            Err(e) => {
+                log::warn!("couldn't renew etcd lease {}: {}", id, e);
+                false
+            }
+        };
+
+        if !alive {
+            let mut lease = lease.lock().unwrap_or_else(PoisonError::into_inner);
+            if *lease == Some(id) {
+                log::warn!("etcd lease {} expired, leadership lost", id);
+                *lease = None;
+            }
+            return;
+        }
+    });
+}
+
+fn full_key(key: &str) -> String {
+    format!("{}{}", KEY_PREFIX, key)
+}
+
+fn encode_key(key: &str) -> String {
+    base64::encode(full_key(key).as_bytes())
+}
+
+fn collection_key(collection: &str, key: &str) -> String {
+    format!("{}/{}", collection, key)
+}
+
+fn put_request(key: &str, value: &[u8]) -> Value {
+    json!({ "key": encode_key(key), "value": base64::encode(value) })
+}
+
+/// End of the range covering every key starting with `prefix` (the prefix with its last byte incremented).
+fn prefix_range_end(prefix: &str) -> Vec<u8> {
+    let mut end = prefix.as_bytes().to_vec();
+    while let Some(last) = end.pop() {
+        if last < 0xff {
+            end.push(last + 1);
+            return end;
+        }
+    }
+    // every key
+    vec![0]
+}
+
+/// The JSON gateway encodes 64-bit integers as strings.
+fn int_field(value: &Value, field: &str) -> Option<i64> {
+    match value.get(field)? {
+        Value::String(s) => s.parse().ok(),
+        Value::Number(n) => n.as_i64(),
+        _ => None,
+    }
+}
+
+fn key_values(res: &Value) -> Result<Vec<KeyValue>, EtcdStorageError> {
+    let kvs = match res.get("kvs").and_then(Value::as_array) {
+        Some(kvs) => kvs,
+        None => return Ok(Vec::new()),
+    };
+
+    let decode = |kv: &Value, field: &str| -> Result<Vec<u8>, EtcdStorageError> {
+        // empty values are omitted by the gateway
+        match kv.get(field).and_then(Value::as_str) {
+            Some(encoded) => base64::decode(encoded).map_err(|e| format!("invalid base64 {}: {}", field, e).into()),
+            None => Ok(Vec::new()),
+        }
+    };
+
+    kvs.iter()
+        .map(|kv| -> Result<KeyValue, EtcdStorageError> {
+            let key = String::from_utf8(decode(kv, "key")?).map_err(|e| format!("invalid key: {}", e))?;
+            Ok(KeyValue {
+                key: key.trim_start_matches(KEY_PREFIX).to_owned(),
+                value: decode(kv, "value")?,
+            })
+        })
+        .collect()
+}
+
+impl PickyStorage for EtcdStorage {
+    fn health(&self) -> Result<(), StorageError> {
+        let res: Value = self
+            .client
+            .get(&format!("{}/health", self.url))
+            .send()
+            .and_then(|mut res| res.json())
+            .context(Request { endpoint: "/health" })?;
+
+        match res.get("health").and_then(Value::as_str) {
+            Some("true") => Ok(()),
+            _ => Err(EtcdStorageError::Other {
+                description: format!("etcd is unhealthy: {}", res),
+            }
+            .into()),
+        }
+    }
+
+    fn capabilities(&self) -> StorageCapabilities {
+        StorageCapabilities {
+            transactions: true,
+            revocation_records: true,
+            ttl: true,
+            ..StorageCapabilities::default()
+        }
+    }
+
+    fn is_leader(&self) -> Result<bool, StorageError> {
+        let lease = self.leader_lease()?;
+        let key = encode_key(LEADER_KEY);
+
+        // the leader key is created only if it doesn't exist yet
+        let res = self.call(
+            "/v3/kv/txn",
+            &json!({
+                "compare": [{ "key": key, "result": "EQUAL", "target": "CREATE", "create_revision": "0" }],
+                "success": [{ "request_put": { "key": key, "value": base64::encode(&self.holder), "lease": lease.to_string() } }],
+            }),
+        )?;
+        if res.get("succeeded").and_then(Value::as_bool).unwrap_or(false) {
+            log::info!("elected as leader (etcd lease {})", lease);
+            return Ok(true);
+        }
+
+        Ok(self.range(LEADER_KEY)?.as_deref() == Some(self.holder.as_bytes()))
+    }
+
+    fn store(&self, entry: CertificateEntry) -> Result<(), StorageError> {
+        let addressing_hash = encode_to_canonical_address(&entry.cert).map_err(|e| EtcdStorageError::Other {
+            description: format!("couldn't hash certificate: {}", e),
+        })?;
+
+        let alternative_addresses =
+            encode_to_alternative_addresses(&entry.cert).map_err(|e| EtcdStorageError::Other {
+                description: format!("couldn't encode alternative addresses: {}", e),
+            })?;
+
+        let mut puts = vec![
+            (
+                collection_key(COLLECTION_NAME, &entry.name),
+                addressing_hash.clone().into_bytes(),
+            ),
+            (collection_key(COLLECTION_CERTIFICATE, &addressing_hash), entry.cert),
+            (
+                collection_key(COLLECTION_KEY_IDENTIFIER, &entry.key_identifier),
+                addressing_hash.clone().into_bytes(),
+            ),
+        ];
+
+        for alternative_address in alternative_addresses {
+            puts.push((
+                collection_key(COLLECTION_HASH_LOOKUP, &alternative_address),
+                addressing_hash.clone().into_bytes(),
+            ));
+        }
+
+        if let Some(requested_by) = entry.requested_by {
+            puts.push((
+                collection_key(COLLECTION_REQUESTER, &addressing_hash),
+                requested_by.into_bytes(),
+            ));
+        }
+
+        if !entry.labels.is_empty() {
+            puts.push((
+                collection_key(COLLECTION_LABELS, &addressing_hash),
+                serde_json::to_vec(&entry.labels).context(Json)?,
+            ));
+        }
+
+        if let Some(key) = entry.key {
+            puts.push((collection_key(COLLECTION_KEY, &addressing_hash), key));
+        }
+
+        self.put_all(puts)?;
+        Ok(())
+    }
+
+    fn get_cert_by_addressing_hash(&self, hash: &str) -> Result<Vec<u8>, StorageError> {
+        Ok(self
+            .get(COLLECTION_CERTIFICATE, hash)?
+            .ok_or_else(|| EtcdStorageError::NotFound {
+                description: "cert not found".to_owned(),
+            })?)
+    }
+
+    fn get_addressing_hash_by_name(&self, name: &str) -> Result<String, StorageError> {
+        Ok(self
+            .get_string(COLLECTION_NAME, name)?
+            .ok_or_else(|| EtcdStorageError::NotFound {
+                description: format!("hash not found using name {}", name),
+            })?)
+    }
+
+    fn get_addressing_hash_by_key_identifier(&self, key_identifier: &str) -> Result<String, StorageError> {
+        Ok(self
+            .get_string(COLLECTION_KEY_IDENTIFIER, key_identifier)?
+            .ok_or_else(|| EtcdStorageError::NotFound {
+                description: format!("addressing hash not found by key identifier \"{}\"", key_identifier),
+            })?)
+    }
+
+    fn lookup_addressing_hash(&self, lookup_key: &str) -> Result<String, StorageError> {
+        Ok(self
+            .get_string(COLLECTION_HASH_LOOKUP, lookup_key)?
+            .ok_or_else(|| EtcdStorageError::NotFound {
+                description: format!("addressing hash not found using lookup key \"{}\"", lookup_key),
+            })?)
+    }
+
+    fn get_addressing_hashes_by_requester(&self, requested_by: &str) -> Result<Vec<String>, StorageError> {
+        let prefix = collection_key(COLLECTION_REQUESTER, "");
+        let mut hashes = self
+            .range_prefix(&prefix)?
+            .into_iter()
+            .filter(|kv| kv.value == requested_by.as_bytes())
+            .map(|kv| kv.key.trim_start_matches(&prefix).to_owned())
+            .collect::<Vec<String>>();
+        hashes.sort();
+        Ok(hashes)
+    }
+
+    fn get_labels(&self, hash: &str) -> Result<Labels, StorageError> {
+        Ok(self.get_json(COLLECTION_LABELS, hash)?.unwrap_or_default())
+    }
+
+    fn get_addressing_hashes_by_labels(&self, selector: &Labels) -> Result<Vec<String>, StorageError> {
+        let prefix = collection_key(COLLECTION_LABELS, "");
+        let mut hashes = Vec::new();
+        for kv in self.range_prefix(&prefix)? {
+            let labels: Labels = serde_json::from_slice(&kv.value).context(Json)?;
+            if labels::matches(&labels, selector) {
+                hashes.push(kv.key.trim_start_matches(&prefix).to_owned());
+            }
+        }
+        hashes.sort();
+        Ok(hashes)
+    }
+
+    fn store_signing_request(&self, entry: SigningRequestEntry) -> Result<(), StorageError> {
+        Ok(self.put_json(COLLECTION_SIGNING_REQUEST, &entry.id, &entry)?)
+    }
+
+    fn get_signing_request(&self, id: &str) -> Result<SigningRequestEntry, StorageError> {
+        Ok(self
+            .get_json(COLLECTION_SIGNING_REQUEST, id)?
+            .ok_or_else(|| EtcdStorageError::NotFound {
+                description: format!("signing request {} not found", id),
+            })?)
+    }
+
+    fn get_signing_requests(&self) -> Result<Vec<SigningRequestEntry>, StorageError> {
+        Ok(self.get_all_json(COLLECTION_SIGNING_REQUEST)?)
+    }
+
+    fn update_signing_request_status(
+        &self,
+        id: &str,
+        expected: &SigningRequestStatus,
+        status: SigningRequestStatus,
+    ) -> Result<bool, StorageError> {
+        let key = collection_key(COLLECTION_SIGNING_REQUEST, id);
+        let stored = self.range(&key)?.ok_or_else(|| EtcdStorageError::NotFound {
+            description: format!("signing request {} not found", id),
+        })?;
+        let mut entry: SigningRequestEntry = serde_json::from_slice(&stored).context(Json)?;
+        if entry.status != *expected {
+            return Ok(false);
+        }
+
+        // the entry is only replaced if it wasn't modified since it was read
+        entry.status = status;
+        let value = serde_json::to_vec(&entry).context(Json)?;
+        let res = self.call(
+            "/v3/kv/txn",
+            &json!({
+                "compare": [{ "key": encode_key(&key), "result": "EQUAL", "target": "VALUE", "value": base64::encode(&stored) }],
+                "success": [{ "request_put": put_request(&key, &value) }],
+            }),
+        )?;
+        Ok(res.get("succeeded").and_then(Value::as_bool).unwrap_or(false))
+    }
+
+    fn store_artifact(
+        &self,
+        namespace: ArtifactNamespace,
+        latest_key: &str,
+        artifact: Vec<u8>,
+    ) -> Result<String, StorageError> {
+        let addressing_hash = encode_to_canonical_address(&artifact).map_err(|e| EtcdStorageError::Other {
+            description: format!("couldn't hash {} artifact: {}", namespace, e),
+        })?;
+
+        self.put_all(vec![
+            (
+                collection_key(COLLECTION_ARTIFACT, &format!("{}/{}", namespace, addressing_hash)),
+                artifact,
+            ),
+            (
+                collection_key(COLLECTION_LATEST_ARTIFACT, &format!("{}/{}", namespace, latest_key)),
+                addressing_hash.clone().into_bytes(),
+            ),
+        ])?;
+
+        Ok(addressing_hash)
+    }
+
+    fn get_artifact_by_addressing_hash(
+        &self,
+        namespace: ArtifactNamespace,
+        hash: &str,
+    ) -> Result<Vec<u8>, StorageError> {
+        Ok(self
+            .get(COLLECTION_ARTIFACT, &format!("{}/{}", namespace, hash))?
+            .ok_or_else(|| EtcdStorageError::NotFound {
+                description: format!("{} artifact not found", namespace),
+            })?)
+    }
+
+    fn get_latest_artifact_addressing_hash(
+        &self,
+        namespace: ArtifactNamespace,
+        latest_key: &str,
+    ) -> Result<String, StorageError> {
+        Ok(self
+            .get_string(COLLECTION_LATEST_ARTIFACT, &format!("{}/{}", namespace, latest_key))?
+            .ok_or_else(|| EtcdStorageError::Other {
+                description: format!("no {} artifact found for {}", namespace, latest_key),
+            })?)
+    }
+
+    fn store_revocation(&self, entry: RevocationEntry) -> Result<(), StorageError> {
+        Ok(self.put_json(
+            COLLECTION_REVOCATION,
+            &format!("{}/{}", entry.issuer, entry.serial_number),
+            &entry,
+        )?)
+    }
+
+    fn get_revocation(&self, issuer: &str, serial_number: &str) -> Result<Option<RevocationEntry>, StorageError> {
+        Ok(self.get_json(COLLECTION_REVOCATION, &format!("{}/{}", issuer, serial_number))?)
+    }
+
+    fn revoked_since(&self, timestamp: u64) -> Result<Vec<RevocationEntry>, StorageError> {
+        let mut entries = self
+            .get_all_json::<RevocationEntry>(COLLECTION_REVOCATION)?
+            .into_iter()
+            .filter(|entry| entry.revoked_at >= timestamp)
+            .collect::<Vec<RevocationEntry>>();
+        entries.sort_by_key(|entry| entry.revoked_at);
+        Ok(entries)
+    }
+
+    fn store_audit_record(&self, record: AuditRecord) -> Result<(), StorageError> {
+        // zero-padded so that records are listed in sequence order
+        Ok(self.put_json(COLLECTION_AUDIT, &format!("{:020}", record.sequence), &record)?)
+    }
+
+    fn get_audit_head(&self) -> Result<Option<AuditRecord>, StorageError> {
+        Ok(self.get_all_json::<AuditRecord>(COLLECTION_AUDIT)?.pop())
+    }
+
+    fn get_audit_records(&self, from_sequence: u64) -> Result<Vec<AuditRecord>, StorageError> {
+        Ok(self
+            .get_all_json::<AuditRecord>(COLLECTION_AUDIT)?
+            .into_iter()
+            .filter(|record| record.sequence >= from_sequence)
+            .collect())
+    }
+
+    fn store_external_account_key(&self, key: ExternalAccountKey) -> Result<(), StorageError> {
+        Ok(self.put_json(COLLECTION_EXTERNAL_ACCOUNT_KEY, &key.key_id, &key)?)
+    }
+
+    fn get_external_account_key(&self, key_id: &str) -> Result<Option<ExternalAccountKey>, StorageError> {
+        Ok(self.get_json(COLLECTION_EXTERNAL_ACCOUNT_KEY, key_id)?)
+    }
+
+    fn get_external_account_keys(&self) -> Result<Vec<ExternalAccountKey>, StorageError> {
+        let mut keys = self.get_all_json::<ExternalAccountKey>(COLLECTION_EXTERNAL_ACCOUNT_KEY)?;
+        keys.sort_by_key(|key| key.created_at);
+        Ok(keys)
+    }
+
+    fn store_rotation_state(&self, state: RotationState) -> Result<(), StorageError> {
+        let json = serde_json::to_vec(&state).context(Json)?;
+        self.call("/v3/kv/put", &put_request(ROTATION_STATE_KEY, &json))?;
+        Ok(())
+    }
+
+    fn get_rotation_state(&self) -> Result<RotationState, StorageError> {
+        match self.range(ROTATION_STATE_KEY)? {
+            Some(json) => Ok(serde_json::from_slice(&json).context(Json)?),
+            None => Ok(RotationState::default()),
+        }
+    }
+
+    fn store_key_usage(&self, entry: KeyUsageEntry) -> Result<(), StorageError> {
+        Ok(self.put_json(COLLECTION_KEY_USAGE, &entry.key_identifier, &entry)?)
+    }
+
+    fn get_key_usage(&self, key_identifier: &str) -> Result<Option<KeyUsageEntry>, StorageError> {
+        Ok(self.get_json(COLLECTION_KEY_USAGE, key_identifier)?)
+    }
+}
+
+impl PrivateKeyLocker for EtcdStorage {
+    fn get_key_by_addressing_hash(&self, hash: &str) -> Result<Vec<u8>, StorageError> {
+        Ok(self
+            .get(COLLECTION_KEY, hash)?
+            .ok_or_else(|| EtcdStorageError::NotFound {
+                description: "key not found".to_owned(),
+            })?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_ranges() {
+        assert_eq!(prefix_range_end("picky/name/"), b"picky/name0".to_vec());
+        assert_eq!(prefix_range_end("a\u{7f}"), b"a\x80".to_vec());
+        assert_eq!(prefix_range_end(""), vec![0]);
+    }
+
+    #[test]
+    fn range_response() {
+        let res = json!({
+            "header": { "revision": "12" },
+            "kvs": [
+                { "key": encode_key("certificate/abc"), "value": base64::encode(b"der"), "create_revision": "3" },
+                { "key": encode_key("labels/def") },
+            ],
+            "count": "2",
+        });
+
+        let kvs = key_values(&res).expect("key values");
+        assert_eq!(kvs.len(), 2);
+        assert_eq!(kvs[0].key, "certificate/abc");
+        assert_eq!(kvs[0].value, b"der");
+        assert!(kvs[1].value.is_empty());
+        assert!(key_values(&json!({ "count": "0" })).expect("no kvs").is_empty());
+
+        assert_eq!(int_field(&json!({ "ID": "7587847878" }), "ID"), Some(7_587_847_878));
+        assert_eq!(int_field(&json!({ "TTL": 10 }), "TTL"), Some(10));
+        assert_eq!(int_field(&json!({}), "TTL"), None);
+    }
+}
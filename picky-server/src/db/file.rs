@@ -1,20 +1,32 @@
 use crate::db::config::DatabaseConfig;
 use crate::{
-    addressing::{encode_to_alternative_addresses, encode_to_canonical_address},
+    addressing::{encode_to_alternative_addresses, encode_to_canonical_address, ArtifactNamespace},
     config::Config,
-    db::{CertificateEntry, PickyStorage, StorageError, SCHEMA_LAST_VERSION},
+    db::{
+        AuditRecord, CertificateEntry, ExternalAccountKey, KeyUsageEntry, PickyStorage, PrivateKeyLocker,
+        RevocationEntry, RotationState, SigningRequestEntry, SigningRequestStatus, StorageCapabilities, StorageError,
+        SCHEMA_LAST_VERSION,
+    },
+    labels::{self, Labels},
 };
 use snafu::Snafu;
 use std::{
     fs::File,
     io::{Read, Write},
     path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
 };
 
 #[derive(Debug, Snafu)]
 pub enum FileStorageError {
+    #[snafu(display("{}", description))]
+    NotFound { description: String },
+
     #[snafu(display("generic error: {}", description))]
     Other { description: String },
+
+    #[snafu(display("invalid latest artifact key: {:?}", key))]
+    InvalidLatestKey { key: String },
 }
 
 impl From<String> for FileStorageError {
@@ -81,17 +93,42 @@ const REPO_KEY: &str = "key_store/";
 const REPO_CERT_NAME: &str = "name_store/";
 const REPO_KEY_IDENTIFIER: &str = "key_identifier_store/";
 const REPO_HASH_LOOKUP_TABLE: &str = "hash_lookup_store/";
+const REPO_REQUESTER: &str = "requester_store/";
+const REPO_LABELS: &str = "label_store/";
+const REPO_SIGNING_REQUEST: &str = "signing_request_store/";
+const REPO_CRL: &str = "crl_store/";
+const REPO_OCSP: &str = "ocsp_store/";
+const REPO_LATEST_ARTIFACT: &str = "latest_artifact_store/";
+const REPO_REVOCATION: &str = "revocation_store/";
+const REPO_AUDIT: &str = "audit_store/";
+const REPO_EXTERNAL_ACCOUNT_KEY: &str = "eab_store/";
+const REPO_KEY_USAGE: &str = "key_usage_store/";
 const TXT_EXT: &str = ".txt";
 const DER_EXT: &str = ".der";
+const JSON_EXT: &str = ".json";
 
 const CONFIG_FILE_NAME: &str = "config.json";
+const ROTATION_STATE_FILE_NAME: &str = "rotation_state.json";
 
 pub struct FileStorage {
+    rotation_state_path: PathBuf,
     name: FileRepo<String>,
     cert: FileRepo<Vec<u8>>,
     keys: FileRepo<Vec<u8>>,
     key_identifiers: FileRepo<String>,
     hash_lookup: FileRepo<String>,
+    requesters: FileRepo<String>,
+    labels: FileRepo<Vec<u8>>,
+    signing_requests: FileRepo<Vec<u8>>,
+    /// Held while a signing request status is compared and replaced, the file backend serving a single process
+    signing_request_lock: Mutex<()>,
+    crl: FileRepo<Vec<u8>>,
+    ocsp: FileRepo<Vec<u8>>,
+    latest_artifacts: FileRepo<String>,
+    revocations: FileRepo<Vec<u8>>,
+    audit_records: FileRepo<Vec<u8>>,
+    external_account_keys: FileRepo<Vec<u8>>,
+    key_usage: FileRepo<Vec<u8>>,
 }
 
 impl FileStorage {
@@ -121,6 +158,7 @@ impl FileStorage {
         }
 
         FileStorage {
+            rotation_state_path: config.file_backend_path.join(ROTATION_STATE_FILE_NAME),
             name: FileRepo::new(&config.file_backend_path, REPO_CERT_NAME).expect("couldn't initialize name repo"),
             cert: FileRepo::new(&config.file_backend_path, REPO_CERTIFICATE).expect("couldn't initialize cert repo"),
             keys: FileRepo::new(&config.file_backend_path, REPO_KEY).expect("couldn't initialize keys repo"),
@@ -128,7 +166,69 @@ impl FileStorage {
                 .expect("couldn't initialize key identifiers repo"),
             hash_lookup: FileRepo::new(&config.file_backend_path, REPO_HASH_LOOKUP_TABLE)
                 .expect("couldn't initialize hash lookup table repo"),
+            requesters: FileRepo::new(&config.file_backend_path, REPO_REQUESTER)
+                .expect("couldn't initialize requesters repo"),
+            labels: FileRepo::new(&config.file_backend_path, REPO_LABELS).expect("couldn't initialize labels repo"),
+            signing_requests: FileRepo::new(&config.file_backend_path, REPO_SIGNING_REQUEST)
+                .expect("couldn't initialize signing requests repo"),
+            signing_request_lock: Mutex::new(()),
+            crl: FileRepo::new(&config.file_backend_path, REPO_CRL).expect("couldn't initialize crl repo"),
+            ocsp: FileRepo::new(&config.file_backend_path, REPO_OCSP).expect("couldn't initialize ocsp repo"),
+            latest_artifacts: FileRepo::new(&config.file_backend_path, REPO_LATEST_ARTIFACT)
+                .expect("couldn't initialize latest artifacts repo"),
+            revocations: FileRepo::new(&config.file_backend_path, REPO_REVOCATION)
+                .expect("couldn't initialize revocations repo"),
+            audit_records: FileRepo::new(&config.file_backend_path, REPO_AUDIT)
+                .expect("couldn't initialize audit repo"),
+            external_account_keys: FileRepo::new(&config.file_backend_path, REPO_EXTERNAL_ACCOUNT_KEY)
+                .expect("couldn't initialize external account keys repo"),
+            key_usage: FileRepo::new(&config.file_backend_path, REPO_KEY_USAGE)
+                .expect("couldn't initialize key usage repo"),
+        }
+    }
+
+    fn artifact_repo(&self, namespace: ArtifactNamespace) -> &FileRepo<Vec<u8>> {
+        match namespace {
+            ArtifactNamespace::Crl => &self.crl,
+            ArtifactNamespace::Ocsp => &self.ocsp,
+        }
+    }
+
+    fn read_audit_record(&self, file: &str) -> Result<AuditRecord, FileStorageError> {
+        let json = std::fs::read(self.audit_records.folder_path.join(file))
+            .map_err(|e| format!("couldn't read audit record {}: {}", file, e))?;
+        Ok(serde_json::from_slice(&json).map_err(|e| format!("couldn't decode audit record {}: {}", file, e))?)
+    }
+
+    fn read_external_account_key(&self, file: &str) -> Result<ExternalAccountKey, FileStorageError> {
+        let json = std::fs::read(self.external_account_keys.folder_path.join(file))
+            .map_err(|e| format!("couldn't read external account key {}: {}", file, e))?;
+        Ok(serde_json::from_slice(&json)
+            .map_err(|e| format!("couldn't decode external account key {}: {}", file, e))?)
+    }
+
+    fn read_labels(&self, file: &str) -> Result<Labels, FileStorageError> {
+        let json = std::fs::read(self.labels.folder_path.join(file))
+            .map_err(|e| format!("couldn't read labels {}: {}", file, e))?;
+        Ok(serde_json::from_slice(&json).map_err(|e| format!("couldn't decode labels {}: {}", file, e))?)
+    }
+
+    fn read_revocation(&self, file: &str) -> Result<Option<RevocationEntry>, FileStorageError> {
+        let file_path = self.revocations.folder_path.join(file);
+        if !file_path.exists() {
+            return Ok(None);
         }
+
+        let json = std::fs::read(&file_path).map_err(|e| format!("couldn't read revocation {}: {}", file, e))?;
+        Ok(Some(
+            serde_json::from_slice(&json).map_err(|e| format!("couldn't decode revocation {}: {}", file, e))?,
+        ))
+    }
+
+    fn read_key_usage(&self, file: &str) -> Result<KeyUsageEntry, FileStorageError> {
+        let json = std::fs::read(self.key_usage.folder_path.join(file))
+            .map_err(|e| format!("couldn't read key usage {}: {}", file, e))?;
+        Ok(serde_json::from_slice(&json).map_err(|e| format!("couldn't decode key usage {}: {}", file, e))?)
     }
 
     fn h_get(&self, hash: &str, repo: &FileRepo<Vec<u8>>, type_err: &'static str) -> Result<Vec<u8>, FileStorageError> {
@@ -136,7 +236,7 @@ impl FileStorage {
         let repo_collection = if let Ok(repo_collection) = repo.get_collection() {
             repo_collection
         } else {
-            return Err(FileStorageError::Other {
+            return Err(FileStorageError::NotFound {
                 description: format!("{} not found", type_err),
             });
         };
@@ -153,7 +253,7 @@ impl FileStorage {
         }
 
         if found_item.is_empty() {
-            Err(FileStorageError::Other {
+            Err(FileStorageError::NotFound {
                 description: format!("{} file not found", type_err),
             })
         } else {
@@ -162,16 +262,53 @@ impl FileStorage {
     }
 }
 
+/// Latest keys end up in file names: only accept what `crl::latest_key` produces, a hex key identifier
+/// optionally followed by a partition number.
+fn check_latest_key(latest_key: &str) -> Result<(), FileStorageError> {
+    let mut parts = latest_key.splitn(2, '-');
+    let key_identifier = parts.next().unwrap_or_default();
+    let valid_key_identifier = !key_identifier.is_empty() && key_identifier.bytes().all(|b| b.is_ascii_hexdigit());
+    let valid_partition = parts.next().map_or(true, |partition| {
+        !partition.is_empty() && partition.bytes().all(|b| b.is_ascii_digit())
+    });
+
+    if valid_key_identifier && valid_partition {
+        Ok(())
+    } else {
+        Err(FileStorageError::InvalidLatestKey {
+            key: latest_key.to_owned(),
+        })
+    }
+}
+
+fn audit_record_name(sequence: u64) -> String {
+    format!("{:020}", sequence)
+}
+
 impl PickyStorage for FileStorage {
     fn health(&self) -> Result<(), StorageError> {
         Ok(())
     }
 
+    fn capabilities(&self) -> StorageCapabilities {
+        StorageCapabilities {
+            revocation_records: true,
+            ..StorageCapabilities::default()
+        }
+    }
+
+    fn is_leader(&self) -> Result<bool, StorageError> {
+        // single node
+        Ok(true)
+    }
+
     fn store(&self, entry: CertificateEntry) -> Result<(), StorageError> {
         let name = entry.name;
         let cert = entry.cert;
         let key_identifier = entry.key_identifier;
         let key = entry.key;
+        let requested_by = entry.requested_by;
+        let labels = entry.labels;
 
         let addressing_hash = encode_to_canonical_address(&cert).map_err(|e| FileStorageError::Other {
             description: format!("couldn't hash certificate der: {}", e),
@@ -193,6 +330,18 @@ impl PickyStorage for FileStorage {
                 .insert(&format!("{}{}", alternative_address, TXT_EXT), &addressing_hash)?;
         }
 
+        if let Some(requested_by) = requested_by {
+            self.requesters
+                .insert(&format!("{}{}", addressing_hash, TXT_EXT), &requested_by)?;
+        }
+
+        if !labels.is_empty() {
+            let json = serde_json::to_vec(&labels).map_err(|e| FileStorageError::Other {
+                description: format!("couldn't encode labels: {}", e),
+            })?;
+            self.labels.insert(&format!("{}{}", addressing_hash, JSON_EXT), &json)?;
+        }
+
         if let Some(key) = key {
             self.keys
                 .insert(&format!("{}{}", addressing_hash, DER_EXT), &key.to_vec())?;
@@ -206,11 +355,6 @@ impl PickyStorage for FileStorage {
         Ok(cert)
     }
 
-    fn get_key_by_addressing_hash(&self, hash: &str) -> Result<Vec<u8>, StorageError> {
-        let key = self.h_get(hash, &self.keys, "Key")?;
-        Ok(key)
-    }
-
     fn get_addressing_hash_by_name(&self, name: &str) -> Result<String, StorageError> {
         let name = format!("{}{}", name, TXT_EXT).replace(" ", "_");
         let file = self
@@ -218,7 +362,7 @@ impl PickyStorage for FileStorage {
             .get_collection()?
             .into_iter()
             .find(|filename| filename.eq(&name))
-            .ok_or_else(|| FileStorageError::Other {
+            .ok_or_else(|| FileStorageError::NotFound {
                 description: format!("'{}' not found", name),
             })?;
         let file_path = self.name.folder_path.join(file);
@@ -236,7 +380,7 @@ impl PickyStorage for FileStorage {
             .get_collection()?
             .into_iter()
             .find(|filename| filename.eq(&key_identifier))
-            .ok_or_else(|| FileStorageError::Other {
+            .ok_or_else(|| FileStorageError::NotFound {
                 description: format!("'{}' not found", key_identifier),
             })?;
         let file_path = self.key_identifiers.folder_path.join(file);
@@ -254,7 +398,7 @@ impl PickyStorage for FileStorage {
             .get_collection()?
             .into_iter()
             .find(|filename| filename.eq(&lookup_key_file))
-            .ok_or_else(|| FileStorageError::Other {
+            .ok_or_else(|| FileStorageError::NotFound {
                 description: format!("'{}' not found", lookup_key_file),
             })?;
         let file_path = self.hash_lookup.folder_path.join(file);
@@ -264,4 +408,317 @@ impl PickyStorage for FileStorage {
             })?,
         )
     }
+
+    fn get_addressing_hashes_by_requester(&self, requested_by: &str) -> Result<Vec<String>, StorageError> {
+        let mut hashes = Vec::new();
+        for file in self.requesters.get_collection()? {
+            let file_path = self.requesters.folder_path.join(&file);
+            let requester = std::fs::read_to_string(&file_path).map_err(|e| FileStorageError::Other {
+                description: format!("error reading file '{}': {}", file_path.to_string_lossy(), e),
+            })?;
+            if requester == requested_by {
+                hashes.push(file.trim_end_matches(TXT_EXT).to_owned());
+            }
+        }
+        hashes.sort();
+        Ok(hashes)
+    }
+
+    fn get_labels(&self, hash: &str) -> Result<Labels, StorageError> {
+        let file_name = format!("{}{}", hash, JSON_EXT);
+        if !self.labels.get_collection()?.contains(&file_name) {
+            return Ok(Labels::new());
+        }
+
+        Ok(self.read_labels(&file_name)?)
+    }
+
+    fn get_addressing_hashes_by_labels(&self, selector: &Labels) -> Result<Vec<String>, StorageError> {
+        let mut hashes = Vec::new();
+        for file in self.labels.get_collection()? {
+            if labels::matches(&self.read_labels(&file)?, selector) {
+                hashes.push(file.trim_end_matches(JSON_EXT).to_owned());
+            }
+        }
+        hashes.sort();
+        Ok(hashes)
+    }
+
+    fn store_signing_request(&self, entry: SigningRequestEntry) -> Result<(), StorageError> {
+        let json = serde_json::to_vec(&entry).map_err(|e| FileStorageError::Other {
+            description: format!("couldn't encode signing request {}: {}", entry.id, e),
+        })?;
+        self.signing_requests
+            .insert(&format!("{}{}", entry.id, JSON_EXT), &json)?;
+        Ok(())
+    }
+
+    fn get_signing_request(&self, id: &str) -> Result<SigningRequestEntry, StorageError> {
+        let file_path = self.signing_requests.folder_path.join(format!("{}{}", id, JSON_EXT));
+        let json = std::fs::read(&file_path).map_err(|e| FileStorageError::NotFound {
+            description: format!("signing request {} not found: {}", id, e),
+        })?;
+        Ok(serde_json::from_slice(&json).map_err(|e| FileStorageError::Other {
+            description: format!("couldn't decode signing request {}: {}", id, e),
+        })?)
+    }
+
+    fn get_signing_requests(&self) -> Result<Vec<SigningRequestEntry>, StorageError> {
+        let mut entries = Vec::new();
+        for file in self.signing_requests.get_collection()? {
+            let id = file.trim_end_matches(JSON_EXT);
+            entries.push(self.get_signing_request(id)?);
+        }
+        Ok(entries)
+    }
+
+    fn update_signing_request_status(
+        &self,
+        id: &str,
+        expected: &SigningRequestStatus,
+        status: SigningRequestStatus,
+    ) -> Result<bool, StorageError> {
+        let _guard = self.signing_request_lock.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let mut entry = self.get_signing_request(id)?;
+        if entry.status != *expected {
+            return Ok(false);
+        }
+
+        entry.status = status;
+        self.store_signing_request(entry)?;
+        Ok(true)
+    }
+
+    fn store_artifact(
+        &self,
+        namespace: ArtifactNamespace,
+        latest_key: &str,
+        artifact: Vec<u8>,
+    ) -> Result<String, StorageError> {
+        check_latest_key(latest_key)?;
+
+        let addressing_hash = encode_to_canonical_address(&artifact).map_err(|e| FileStorageError::Other {
+            description: format!("couldn't hash {} artifact: {}", namespace, e),
+        })?;
+
+        self.artifact_repo(namespace)
+            .insert(&format!("{}{}", addressing_hash, DER_EXT), &artifact)?;
+        self.latest_artifacts
+            .insert(&format!("{}_{}{}", namespace, latest_key, TXT_EXT), &addressing_hash)?;
+
+        Ok(addressing_hash)
+    }
+
+    fn get_artifact_by_addressing_hash(
+        &self,
+        namespace: ArtifactNamespace,
+        hash: &str,
+    ) -> Result<Vec<u8>, StorageError> {
+        let artifact = self.h_get(hash, self.artifact_repo(namespace), namespace.as_str())?;
+        Ok(artifact)
+    }
+
+    fn get_latest_artifact_addressing_hash(
+        &self,
+        namespace: ArtifactNamespace,
+        latest_key: &str,
+    ) -> Result<String, StorageError> {
+        check_latest_key(latest_key)?;
+
+        let file_path = self
+            .latest_artifacts
+            .folder_path
+            .join(format!("{}_{}{}", namespace, latest_key, TXT_EXT));
+        Ok(
+            std::fs::read_to_string(&file_path).map_err(|e| FileStorageError::Other {
+                description: format!("no {} artifact found for {}: {}", namespace, latest_key, e),
+            })?,
+        )
+    }
+
+    fn store_revocation(&self, entry: RevocationEntry) -> Result<(), StorageError> {
+        let json = serde_json::to_vec(&entry).map_err(|e| FileStorageError::Other {
+            description: format!("couldn't encode revocation {}: {}", entry.serial_number, e),
+        })?;
+        self.revocations
+            .insert(&format!("{}_{}{}", entry.issuer, entry.serial_number, JSON_EXT), &json)?;
+        Ok(())
+    }
+
+    fn get_revocation(&self, issuer: &str, serial_number: &str) -> Result<Option<RevocationEntry>, StorageError> {
+        Ok(self.read_revocation(&format!("{}_{}{}", issuer, serial_number, JSON_EXT))?)
+    }
+
+    fn revoked_since(&self, timestamp: u64) -> Result<Vec<RevocationEntry>, StorageError> {
+        // Full scan: the file backend isn't meant for large deployments.
+        let mut entries = Vec::new();
+        for file in self.revocations.get_collection()? {
+            if let Some(entry) = self.read_revocation(&file)? {
+                if entry.revoked_at >= timestamp {
+                    entries.push(entry);
+                }
+            }
+        }
+        entries.sort_by_key(|entry| entry.revoked_at);
+        Ok(entries)
+    }
+
+    fn store_audit_record(&self, record: AuditRecord) -> Result<(), StorageError> {
+        let json = serde_json::to_vec(&record).map_err(|e| FileStorageError::Other {
+            description: format!("couldn't encode audit record {}: {}", record.sequence, e),
+        })?;
+        self.audit_records
+            .insert(&format!("{}{}", audit_record_name(record.sequence), JSON_EXT), &json)?;
+        Ok(())
+    }
+
+    fn get_audit_head(&self) -> Result<Option<AuditRecord>, StorageError> {
+        // zero-padded names sort like sequence numbers
+        match self.audit_records.get_collection()?.into_iter().max() {
+            Some(file) => Ok(Some(self.read_audit_record(&file)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn get_audit_records(&self, from_sequence: u64) -> Result<Vec<AuditRecord>, StorageError> {
+        let from = audit_record_name(from_sequence);
+        let mut files = self
+            .audit_records
+            .get_collection()?
+            .into_iter()
+            .filter(|file| file.trim_end_matches(JSON_EXT) >= from.as_str())
+            .collect::<Vec<String>>();
+        files.sort();
+
+        let mut records = Vec::with_capacity(files.len());
+        for file in files {
+            records.push(self.read_audit_record(&file)?);
+        }
+        Ok(records)
+    }
+
+    fn store_external_account_key(&self, key: ExternalAccountKey) -> Result<(), StorageError> {
+        let json = serde_json::to_vec(&key).map_err(|e| FileStorageError::Other {
+            description: format!("couldn't encode external account key {}: {}", key.key_id, e),
+        })?;
+        self.external_account_keys
+            .insert(&format!("{}{}", key.key_id, JSON_EXT), &json)?;
+        Ok(())
+    }
+
+    fn get_external_account_key(&self, key_id: &str) -> Result<Option<ExternalAccountKey>, StorageError> {
+        let file_name = format!("{}{}", key_id, JSON_EXT);
+        if !self.external_account_keys.get_collection()?.contains(&file_name) {
+            return Ok(None);
+        }
+
+        Ok(Some(self.read_external_account_key(&file_name)?))
+    }
+
+    fn get_external_account_keys(&self) -> Result<Vec<ExternalAccountKey>, StorageError> {
+        let mut keys = self
+            .external_account_keys
+            .get_collection()?
+            .iter()
+            .map(|file| self.read_external_account_key(file))
+            .collect::<Result<Vec<ExternalAccountKey>, FileStorageError>>()?;
+        keys.sort_by_key(|key| key.created_at);
+        Ok(keys)
+    }
+
+    fn store_rotation_state(&self, state: RotationState) -> Result<(), StorageError> {
+        let json = serde_json::to_vec_pretty(&state).map_err(|e| FileStorageError::Other {
+            description: format!("couldn't encode rotation state: {}", e),
+        })?;
+        std::fs::write(&self.rotation_state_path, json).map_err(|e| FileStorageError::Other {
+            description: format!("couldn't write rotation state: {}", e),
+        })?;
+        Ok(())
+    }
+
+    fn get_rotation_state(&self) -> Result<RotationState, StorageError> {
+        if !self.rotation_state_path.exists() {
+            return Ok(RotationState::default());
+        }
+
+        let json = std::fs::read(&self.rotation_state_path).map_err(|e| FileStorageError::Other {
+            description: format!("couldn't read rotation state: {}", e),
+        })?;
+        Ok(serde_json::from_slice(&json).map_err(|e| FileStorageError::Other {
+            description: format!("couldn't decode rotation state: {}", e),
+        })?)
+    }
+
+    fn store_key_usage(&self, entry: KeyUsageEntry) -> Result<(), StorageError> {
+        let json = serde_json::to_vec(&entry).map_err(|e| FileStorageError::Other {
+            description: format!("couldn't encode key usage {}: {}", entry.key_identifier, e),
+        })?;
+        self.key_usage
+            .insert(&format!("{}{}", entry.key_identifier, JSON_EXT), &json)?;
+        Ok(())
+    }
+
+    fn get_key_usage(&self, key_identifier: &str) -> Result<Option<KeyUsageEntry>, StorageError> {
+        let file_name = format!("{}{}", key_identifier, JSON_EXT);
+        if !self.key_usage.get_collection()?.contains(&file_name) {
+            return Ok(None);
+        }
+
+        Ok(Some(self.read_key_usage(&file_name)?))
+    }
+}
+
+impl PrivateKeyLocker for FileStorage {
+    fn get_key_by_addressing_hash(&self, hash: &str) -> Result<Vec<u8>, StorageError> {
+        let key = self.h_get(hash, &self.keys, "Key")?;
+        Ok(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TestDir;
+
+    #[test]
+    fn latest_keys() {
+        assert!(check_latest_key("0a1b2c").is_ok());
+        assert!(check_latest_key("0A1B2C-3").is_ok());
+
+        for invalid in &[
+            "", "-3", "0a1b-", "0a1b-x", "0a1b-1-2", "../0a1b", "0a/1b", "0a1b\\..", "ca_ocsp",
+        ] {
+            match check_latest_key(invalid) {
+                Err(FileStorageError::InvalidLatestKey { key }) => assert_eq!(key, *invalid),
+                other => panic!("{:?} was accepted: {:?}", invalid, other),
+            }
+        }
+    }
+
+    #[test]
+    fn latest_artifacts_stay_in_their_repo() {
+        let dir = TestDir::new("file_latest_artifacts");
+        let mut config = Config::default();
+        config.file_backend_path = dir.join("file");
+        let storage = FileStorage::new(&config);
+
+        let hash = storage
+            .store_artifact(ArtifactNamespace::Crl, "0a1b-1", b"crl".to_vec())
+            .unwrap();
+        assert_eq!(
+            storage
+                .get_latest_artifact_addressing_hash(ArtifactNamespace::Crl, "0a1b-1")
+                .unwrap(),
+            hash
+        );
+
+        assert!(storage
+            .store_artifact(ArtifactNamespace::Crl, "../../escaped", b"crl".to_vec())
+            .is_err());
+        assert!(!dir.join("escaped.txt").exists());
+        assert!(storage
+            .get_latest_artifact_addressing_hash(ArtifactNamespace::Crl, "../../config")
+            .is_err());
+    }
 }
@@ -1,16 +1,23 @@
 use crate::{
-    addressing::{encode_to_alternative_addresses, encode_to_canonical_address},
-    db::{CertificateEntry, PickyStorage, StorageError},
+    addressing::{encode_to_alternative_addresses, encode_to_canonical_address, ArtifactNamespace},
+    db::{
+        AuditRecord, CertificateEntry, ExternalAccountKey, KeyUsageEntry, PickyStorage, PrivateKeyLocker,
+        RevocationEntry, RotationState, SigningRequestEntry, SigningRequestStatus, StorageCapabilities, StorageError,
+    },
+    labels::{self, Labels},
 };
 use snafu::Snafu;
 use std::{
     collections::HashMap,
     hash::Hash,
-    sync::{RwLock, RwLockReadGuard},
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
 };
 
 #[derive(Debug, Snafu)]
 pub enum MemoryStorageError {
+    #[snafu(display("{}", description))]
+    NotFound { description: String },
+
     #[snafu(display("generic error: {}", description))]
     Other { description: String },
 }
@@ -34,6 +41,10 @@ where
         self.repo.read().expect("couldn't get read lock on repo (poisoned)")
     }
 
+    fn get_collection_mut(&'a self) -> RwLockWriteGuard<'a, HashMap<String, T>> {
+        self.repo.write().expect("couldn't get write lock on repo (poisoned)")
+    }
+
     fn insert(&self, key: String, value: T) {
         if self
             .repo
@@ -54,8 +65,20 @@ pub struct MemoryStorage {
     keys: MemoryRepository<Vec<u8>>,
     key_identifiers: MemoryRepository<String>,
     hash_lookup: MemoryRepository<String>,
+    requesters: MemoryRepository<String>,
+    labels: MemoryRepository<Labels>,
+    signing_requests: MemoryRepository<SigningRequestEntry>,
+    artifacts: MemoryRepository<Vec<u8>>,
+    latest_artifacts: MemoryRepository<String>,
+    revocations: MemoryRepository<RevocationEntry>,
+    audit_records: MemoryRepository<AuditRecord>,
+    external_account_keys: MemoryRepository<ExternalAccountKey>,
+    rotation_state: MemoryRepository<RotationState>,
+    key_usage: MemoryRepository<KeyUsageEntry>,
 }
 
+const ROTATION_STATE_KEY: &str = "rotation_state";
+
 impl MemoryStorage {
     pub fn new() -> Self {
         Self::default()
@@ -67,11 +90,25 @@ impl PickyStorage for MemoryStorage {
         Ok(())
     }
 
+    fn capabilities(&self) -> StorageCapabilities {
+        StorageCapabilities {
+            revocation_records: true,
+            ..StorageCapabilities::default()
+        }
+    }
+
+    fn is_leader(&self) -> Result<bool, StorageError> {
+        // single node
+        Ok(true)
+    }
+
     fn store(&self, entry: CertificateEntry) -> Result<(), StorageError> {
         let name = entry.name;
         let cert = entry.cert;
         let key_identifier = entry.key_identifier;
         let key = entry.key;
+        let requested_by = entry.requested_by;
+        let labels = entry.labels;
 
         let addressing_hash = encode_to_canonical_address(&cert).map_err(|e| MemoryStorageError::Other {
             description: format!("couldn't hash certificate: {}", e),
@@ -89,6 +126,14 @@ impl PickyStorage for MemoryStorage {
             self.hash_lookup.insert(alternative_address, addressing_hash.clone());
         }
 
+        if let Some(requested_by) = requested_by {
+            self.requesters.insert(addressing_hash.clone(), requested_by);
+        }
+
+        if !labels.is_empty() {
+            self.labels.insert(addressing_hash.clone(), labels);
+        }
+
         if let Some(key) = key {
             self.keys.insert(addressing_hash, key);
         }
@@ -102,29 +147,18 @@ impl PickyStorage for MemoryStorage {
             .get_collection()
             .get(hash)
             .cloned()
-            .ok_or_else(|| MemoryStorageError::Other {
+            .ok_or_else(|| MemoryStorageError::NotFound {
                 description: "cert not found".to_owned(),
             })?)
     }
 
-    fn get_key_by_addressing_hash(&self, hash: &str) -> Result<Vec<u8>, StorageError> {
-        Ok(self
-            .keys
-            .get_collection()
-            .get(hash)
-            .cloned()
-            .ok_or_else(|| MemoryStorageError::Other {
-                description: "key not found".to_owned(),
-            })?)
-    }
-
     fn get_addressing_hash_by_name(&self, name: &str) -> Result<String, StorageError> {
         Ok(self
             .name
             .get_collection()
             .get(name)
             .cloned()
-            .ok_or_else(|| MemoryStorageError::Other {
+            .ok_or_else(|| MemoryStorageError::NotFound {
                 description: format!("hash not found using name {}", name),
             })?)
     }
@@ -135,7 +169,7 @@ impl PickyStorage for MemoryStorage {
             .get_collection()
             .get(key_identifier)
             .cloned()
-            .ok_or_else(|| MemoryStorageError::Other {
+            .ok_or_else(|| MemoryStorageError::NotFound {
                 description: "hash not found".to_owned(),
             })?)
     }
@@ -146,8 +180,378 @@ impl PickyStorage for MemoryStorage {
             .get_collection()
             .get(lookup_key)
             .cloned()
-            .ok_or_else(|| MemoryStorageError::Other {
+            .ok_or_else(|| MemoryStorageError::NotFound {
                 description: "hash not found".to_owned(),
             })?)
     }
+
+    fn get_addressing_hashes_by_requester(&self, requested_by: &str) -> Result<Vec<String>, StorageError> {
+        let mut hashes = self
+            .requesters
+            .get_collection()
+            .iter()
+            .filter(|(_, requester)| requester.as_str() == requested_by)
+            .map(|(hash, _)| hash.clone())
+            .collect::<Vec<String>>();
+        hashes.sort();
+        Ok(hashes)
+    }
+
+    fn get_labels(&self, hash: &str) -> Result<Labels, StorageError> {
+        Ok(self.labels.get_collection().get(hash).cloned().unwrap_or_default())
+    }
+
+    fn get_addressing_hashes_by_labels(&self, selector: &Labels) -> Result<Vec<String>, StorageError> {
+        let mut hashes = self
+            .labels
+            .get_collection()
+            .iter()
+            .filter(|(_, labels)| labels::matches(labels, selector))
+            .map(|(hash, _)| hash.clone())
+            .collect::<Vec<String>>();
+        hashes.sort();
+        Ok(hashes)
+    }
+
+    fn store_signing_request(&self, entry: SigningRequestEntry) -> Result<(), StorageError> {
+        self.signing_requests.insert(entry.id.clone(), entry);
+        Ok(())
+    }
+
+    fn get_signing_request(&self, id: &str) -> Result<SigningRequestEntry, StorageError> {
+        Ok(self
+            .signing_requests
+            .get_collection()
+            .get(id)
+            .cloned()
+            .ok_or_else(|| MemoryStorageError::NotFound {
+                description: format!("signing request {} not found", id),
+            })?)
+    }
+
+    fn get_signing_requests(&self) -> Result<Vec<SigningRequestEntry>, StorageError> {
+        Ok(self.signing_requests.get_collection().values().cloned().collect())
+    }
+
+    fn update_signing_request_status(
+        &self,
+        id: &str,
+        expected: &SigningRequestStatus,
+        status: SigningRequestStatus,
+    ) -> Result<bool, StorageError> {
+        let mut signing_requests = self.signing_requests.get_collection_mut();
+        let entry = signing_requests
+            .get_mut(id)
+            .ok_or_else(|| MemoryStorageError::NotFound {
+                description: format!("signing request {} not found", id),
+            })?;
+
+        if entry.status != *expected {
+            return Ok(false);
+        }
+
+        entry.status = status;
+        Ok(true)
+    }
+
+    fn store_artifact(
+        &self,
+        namespace: ArtifactNamespace,
+        latest_key: &str,
+        artifact: Vec<u8>,
+    ) -> Result<String, StorageError> {
+        let addressing_hash = encode_to_canonical_address(&artifact).map_err(|e| MemoryStorageError::Other {
+            description: format!("couldn't hash {} artifact: {}", namespace, e),
+        })?;
+
+        self.artifacts
+            .insert(format!("{}/{}", namespace, addressing_hash), artifact);
+        self.latest_artifacts
+            .insert(format!("{}/{}", namespace, latest_key), addressing_hash.clone());
+
+        Ok(addressing_hash)
+    }
+
+    fn get_artifact_by_addressing_hash(
+        &self,
+        namespace: ArtifactNamespace,
+        hash: &str,
+    ) -> Result<Vec<u8>, StorageError> {
+        Ok(self
+            .artifacts
+            .get_collection()
+            .get(&format!("{}/{}", namespace, hash))
+            .cloned()
+            .ok_or_else(|| MemoryStorageError::NotFound {
+                description: format!("{} artifact not found", namespace),
+            })?)
+    }
+
+    fn get_latest_artifact_addressing_hash(
+        &self,
+        namespace: ArtifactNamespace,
+        latest_key: &str,
+    ) -> Result<String, StorageError> {
+        Ok(self
+            .latest_artifacts
+            .get_collection()
+            .get(&format!("{}/{}", namespace, latest_key))
+            .cloned()
+            .ok_or_else(|| MemoryStorageError::Other {
+                description: format!("no {} artifact found for {}", namespace, latest_key),
+            })?)
+    }
+
+    fn store_revocation(&self, entry: RevocationEntry) -> Result<(), StorageError> {
+        self.revocations
+            .insert(format!("{}/{}", entry.issuer, entry.serial_number), entry);
+        Ok(())
+    }
+
+    fn get_revocation(&self, issuer: &str, serial_number: &str) -> Result<Option<RevocationEntry>, StorageError> {
+        Ok(self
+            .revocations
+            .get_collection()
+            .get(&format!("{}/{}", issuer, serial_number))
+            .cloned())
+    }
+
+    fn revoked_since(&self, timestamp: u64) -> Result<Vec<RevocationEntry>, StorageError> {
+        let mut entries = self
+            .revocations
+            .get_collection()
+            .values()
+            .filter(|entry| entry.revoked_at >= timestamp)
+            .cloned()
+            .collect::<Vec<RevocationEntry>>();
+        entries.sort_by_key(|entry| entry.revoked_at);
+        Ok(entries)
+    }
+
+    fn store_audit_record(&self, record: AuditRecord) -> Result<(), StorageError> {
+        self.audit_records.insert(record.sequence.to_string(), record);
+        Ok(())
+    }
+
+    fn get_audit_head(&self) -> Result<Option<AuditRecord>, StorageError> {
+        Ok(self
+            .audit_records
+            .get_collection()
+            .values()
+            .max_by_key(|record| record.sequence)
+            .cloned())
+    }
+
+    fn get_audit_records(&self, from_sequence: u64) -> Result<Vec<AuditRecord>, StorageError> {
+        let mut records = self
+            .audit_records
+            .get_collection()
+            .values()
+            .filter(|record| record.sequence >= from_sequence)
+            .cloned()
+            .collect::<Vec<AuditRecord>>();
+        records.sort_by_key(|record| record.sequence);
+        Ok(records)
+    }
+
+    fn store_external_account_key(&self, key: ExternalAccountKey) -> Result<(), StorageError> {
+        self.external_account_keys.insert(key.key_id.clone(), key);
+        Ok(())
+    }
+
+    fn get_external_account_key(&self, key_id: &str) -> Result<Option<ExternalAccountKey>, StorageError> {
+        Ok(self.external_account_keys.get_collection().get(key_id).cloned())
+    }
+
+    fn get_external_account_keys(&self) -> Result<Vec<ExternalAccountKey>, StorageError> {
+        let mut keys = self
+            .external_account_keys
+            .get_collection()
+            .values()
+            .cloned()
+            .collect::<Vec<ExternalAccountKey>>();
+        keys.sort_by_key(|key| key.created_at);
+        Ok(keys)
+    }
+
+    fn store_rotation_state(&self, state: RotationState) -> Result<(), StorageError> {
+        self.rotation_state.insert(ROTATION_STATE_KEY.to_owned(), state);
+        Ok(())
+    }
+
+    fn get_rotation_state(&self) -> Result<RotationState, StorageError> {
+        Ok(self
+            .rotation_state
+            .get_collection()
+            .get(ROTATION_STATE_KEY)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    fn store_key_usage(&self, entry: KeyUsageEntry) -> Result<(), StorageError> {
+        self.key_usage.insert(entry.key_identifier.clone(), entry);
+        Ok(())
+    }
+
+    fn get_key_usage(&self, key_identifier: &str) -> Result<Option<KeyUsageEntry>, StorageError> {
+        Ok(self.key_usage.get_collection().get(key_identifier).cloned())
+    }
+}
+
+impl PrivateKeyLocker for MemoryStorage {
+    fn get_key_by_addressing_hash(&self, hash: &str) -> Result<Vec<u8>, StorageError> {
+        Ok(self
+            .keys
+            .get_collection()
+            .get(hash)
+            .cloned()
+            .ok_or_else(|| MemoryStorageError::NotFound {
+                description: "key not found".to_owned(),
+            })?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn revocation(issuer: &str, serial_number: &str, revoked_at: u64) -> RevocationEntry {
+        RevocationEntry {
+            issuer: issuer.to_owned(),
+            serial_number: serial_number.to_owned(),
+            revoked_at,
+            reason: None,
+        }
+    }
+
+    #[test]
+    fn capabilities() {
+        let mut config = Config::default();
+        let capabilities = MemoryStorage::new().capabilities();
+        assert!(capabilities.revocation_records);
+        assert!(!capabilities.transactions);
+        capabilities
+            .check(&config)
+            .expect("CRL distribution points are supported");
+
+        let without_revocation = StorageCapabilities {
+            revocation_records: false,
+            ..capabilities
+        };
+        assert!(without_revocation.check(&config).is_err());
+        config.leaf_extensions.crl_distribution_point = false;
+        without_revocation
+            .check(&config)
+            .expect("CRL distribution points are disabled");
+    }
+
+    #[test]
+    fn certificates_by_requester() {
+        let storage = MemoryStorage::new();
+        for (name, requested_by) in [("a", Some("token:ci")), ("b", None), ("c", Some("api-key"))].iter() {
+            storage
+                .store(CertificateEntry {
+                    name: (*name).to_owned(),
+                    cert: name.as_bytes().to_vec(),
+                    key_identifier: (*name).to_owned(),
+                    key: None,
+                    requested_by: requested_by.map(str::to_owned),
+                    labels: Labels::new(),
+                })
+                .unwrap();
+        }
+
+        let hashes = storage.get_addressing_hashes_by_requester("token:ci").unwrap();
+        assert_eq!(hashes, vec![storage.get_addressing_hash_by_name("a").unwrap()]);
+        assert!(storage
+            .get_addressing_hashes_by_requester("token:cd")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn private_keys_only_through_locker() {
+        let storage = MemoryStorage::new();
+        storage
+            .store(CertificateEntry {
+                name: "ca".to_owned(),
+                cert: b"certificate".to_vec(),
+                key_identifier: "ca".to_owned(),
+                key: Some(b"private key".to_vec()),
+                requested_by: None,
+                labels: Labels::new(),
+            })
+            .unwrap();
+
+        let storage_view: &dyn PickyStorage = &storage;
+        let hash = storage_view.get_addressing_hash_by_name("ca").unwrap();
+        assert_eq!(storage_view.get_cert_by_addressing_hash(&hash).unwrap(), b"certificate");
+
+        let locker: &dyn PrivateKeyLocker = &storage;
+        assert_eq!(locker.get_key_by_addressing_hash(&hash).unwrap(), b"private key");
+        assert!(locker.get_key_by_addressing_hash("unknown").is_err());
+    }
+
+    #[test]
+    fn signing_request_status_compare_and_swap() {
+        let storage = MemoryStorage::new();
+        storage
+            .store_signing_request(SigningRequestEntry {
+                id: "request".to_owned(),
+                subject_name: "test".to_owned(),
+                csr: Vec::new(),
+                submitted_at: 0,
+                requested_by: None,
+                labels: Labels::new(),
+                alt_names: Default::default(),
+                profile: None,
+                issuer: None,
+                status: SigningRequestStatus::Pending,
+            })
+            .unwrap();
+
+        assert!(storage
+            .update_signing_request_status("request", &SigningRequestStatus::Pending, SigningRequestStatus::Issuing)
+            .unwrap());
+        // a concurrent approval or denial loses the race
+        assert!(!storage
+            .update_signing_request_status("request", &SigningRequestStatus::Pending, SigningRequestStatus::Issuing)
+            .unwrap());
+        assert!(!storage
+            .update_signing_request_status("request", &SigningRequestStatus::Pending, SigningRequestStatus::Denied)
+            .unwrap());
+        assert_eq!(
+            storage.get_signing_request("request").unwrap().status,
+            SigningRequestStatus::Issuing
+        );
+
+        assert!(storage
+            .update_signing_request_status("missing", &SigningRequestStatus::Pending, SigningRequestStatus::Denied)
+            .unwrap_err()
+            .is_not_found());
+    }
+
+    #[test]
+    fn revocation_queries() {
+        let storage = MemoryStorage::new();
+        storage.store_revocation(revocation("ca", "0a", 300)).unwrap();
+        storage.store_revocation(revocation("ca", "0b", 100)).unwrap();
+        storage.store_revocation(revocation("other-ca", "0c", 200)).unwrap();
+
+        assert_eq!(
+            storage.get_revocation("ca", "0b").unwrap(),
+            Some(revocation("ca", "0b", 100))
+        );
+        assert_eq!(storage.get_revocation("ca", "0d").unwrap(), None);
+        assert_eq!(storage.get_revocation("ca", "0c").unwrap(), None);
+
+        let serials = storage
+            .revoked_since(200)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.serial_number)
+            .collect::<Vec<String>>();
+        assert_eq!(serials, vec!["0c", "0a"]);
+    }
 }
@@ -1,17 +1,33 @@
 mod config;
+mod etcd;
 mod file;
 mod memory;
 mod mongodb;
+mod observer;
+mod sqlite;
+
+pub use observer::{ObservedStorage, StorageObserver};
 
 use crate::{
-    config::{BackendType, Config},
+    addressing::ArtifactNamespace,
+    alt_names::AltNames,
+    cdn, cert_cache,
+    config::{BackendType, Config, ConfigError},
+    ct_monitor,
     db::{
+        etcd::{EtcdStorage, EtcdStorageError},
         file::{FileStorage, FileStorageError},
         memory::{MemoryStorage, MemoryStorageError},
         mongodb::{MongoStorage, MongoStorageError},
+        sqlite::{SqliteStorage, SqliteStorageError},
     },
+    key_usage,
+    labels::Labels,
+    ldap, notifier,
 };
+use serde::{Deserialize, Serialize};
 use snafu::Snafu;
+use std::sync::Arc;
 
 pub const SCHEMA_LAST_VERSION: u8 = 1;
 
@@ -25,6 +41,36 @@ pub enum StorageError {
 
     #[snafu(display("memory storage error: {}", source))]
     Memory { source: MemoryStorageError },
+
+    #[snafu(display("sqlite storage error: {}", source))]
+    Sqlite { source: SqliteStorageError },
+
+    #[snafu(display("etcd storage error: {}", source))]
+    Etcd { source: EtcdStorageError },
+}
+
+impl StorageError {
+    /// Whether the requested entry doesn't exist, as opposed to the storage failing.
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            StorageError::Mongo {
+                source: MongoStorageError::NotFound { .. },
+            }
+            | StorageError::File {
+                source: FileStorageError::NotFound { .. },
+            }
+            | StorageError::Memory {
+                source: MemoryStorageError::NotFound { .. },
+            }
+            | StorageError::Sqlite {
+                source: SqliteStorageError::NotFound { .. },
+            }
+            | StorageError::Etcd {
+                source: EtcdStorageError::NotFound { .. },
+            } => true,
+            _ => false,
+        }
+    }
 }
 
 impl From<MongoStorageError> for StorageError {
@@ -45,14 +91,56 @@ impl From<MemoryStorageError> for StorageError {
     }
 }
 
-pub type BoxedPickyStorage = Box<dyn PickyStorage>;
+impl From<SqliteStorageError> for StorageError {
+    fn from(source: SqliteStorageError) -> Self {
+        Self::Sqlite { source }
+    }
+}
+
+impl From<EtcdStorageError> for StorageError {
+    fn from(source: EtcdStorageError) -> Self {
+        Self::Etcd { source }
+    }
+}
+
+/// Returns two views on the same backend: the storage shared by the whole server and the private key
+/// locker reserved to `signer::CaSigner`.
+///
+/// Observers of the key usage counters, the certificate cache and the subsystems enabled by `config` are
+/// registered on the shared storage.
+pub fn get_storage(config: &Config) -> Result<(Arc<dyn PickyStorage>, Arc<dyn PrivateKeyLocker>), StorageError> {
+    fn split<T: PickyStorage + PrivateKeyLocker + 'static>(
+        backend: T,
+    ) -> (Arc<dyn PickyStorage>, Arc<dyn PrivateKeyLocker>) {
+        let backend = Arc::new(backend);
+        (backend.clone(), backend)
+    }
+
+    let (storage, key_locker) = match config.backend {
+        BackendType::MongoDb => split(MongoStorage::new(config)?),
+        BackendType::Memory => split(MemoryStorage::new()),
+        BackendType::File => split(FileStorage::new(config)),
+        BackendType::Sqlite => split(SqliteStorage::new(config)),
+        BackendType::Etcd => split(EtcdStorage::new(config)),
+    };
 
-pub fn get_storage(config: &Config) -> BoxedPickyStorage {
-    match config.backend {
-        BackendType::MongoDb => Box::new(MongoStorage::new(config)),
-        BackendType::Memory => Box::new(MemoryStorage::new()),
-        BackendType::File => Box::new(FileStorage::new(config)),
+    let mut observed = ObservedStorage::new(storage);
+    observed.register(Arc::new(key_usage::SignatureCounter));
+    observed.register(Arc::new(cert_cache::CacheInvalidator));
+    if let Some(notifier) = notifier::ca_issuance_notifier(config) {
+        observed.register(notifier);
     }
+    if let Some(tracker) = ct_monitor::issuer_tracker(config) {
+        observed.register(tracker);
+    }
+    if let Some(replicator) = cdn::replicator(config) {
+        observed.register(replicator);
+    }
+    if let Some(publisher) = ldap::publisher(config) {
+        observed.register(publisher);
+    }
+
+    Ok((observed.into_storage(), key_locker))
 }
 
 #[derive(Debug, Clone)]
@@ -61,14 +149,273 @@ pub struct CertificateEntry {
     pub cert: Vec<u8>,
     pub key_identifier: String,
     pub key: Option<Vec<u8>>,
+    /// Identity that requested the certificate: `api-key` or `token:<subject>`
+    pub requested_by: Option<String>,
+    pub labels: Labels,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum SigningRequestStatus {
+    Pending,
+    /// Approved by an administrator, the certificate is being issued
+    Issuing,
+    Approved { cert: Vec<u8> },
+    Denied,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SigningRequestEntry {
+    pub id: String,
+    pub subject_name: String,
+    pub csr: Vec<u8>,
+    pub submitted_at: u64,
+    /// Identity that submitted the request, attributed to the certificate once approved
+    #[serde(default)]
+    pub requested_by: Option<String>,
+    /// Labels attached to the certificate once approved
+    #[serde(default)]
+    pub labels: Labels,
+    /// Subject alternative names requested besides the subject common name
+    #[serde(default)]
+    pub alt_names: AltNames,
+    /// Certificate profile the certificate is issued with once approved
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Issuing intermediate CA, the default one if unset
+    #[serde(default)]
+    pub issuer: Option<String>,
+    #[serde(flatten)]
+    pub status: SigningRequestStatus,
+}
+
+/// CA rotation state: intermediate CA certificates still served alongside the default one
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct RotationState {
+    /// Addressing hashes of the additional intermediate (or cross-signed) CA certificates
+    pub intermediates: Vec<String>,
+}
+
+/// Revoked certificate, looked up by issuer and serial number when answering OCSP requests and generating CRLs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RevocationEntry {
+    /// Hex-encoded key identifier of the issuing CA, serial numbers being only unique per issuer
+    pub issuer: String,
+    /// Hex-encoded certificate serial number
+    pub serial_number: String,
+    /// Revocation time (seconds since UNIX epoch)
+    pub revoked_at: u64,
+    /// CRL reason code (RFC5280 section 5.3.1)
+    pub reason: Option<u8>,
+}
+
+/// Audit log record, chained to the previous record through its hash
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AuditRecord {
+    /// Position in the log, starting at 0
+    pub sequence: u64,
+    /// Time of the event (seconds since UNIX epoch)
+    pub timestamp: u64,
+    pub event: String,
+    /// JSON-encoded event details
+    pub detail: String,
+    /// Hash of the previous record (empty for the first record)
+    pub previous_hash: String,
+    /// Canonical address of the record content, see `audit::record_hash`
+    pub hash: String,
+}
+
+/// ACME external account binding key, provisioned by an administrator (RFC8555 section 7.3.4)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ExternalAccountKey {
+    pub key_id: String,
+    /// Base64url-encoded MAC key
+    pub hmac_key: String,
+    /// Creation time (seconds since UNIX epoch)
+    pub created_at: u64,
+    /// Thumbprint of the account key bound using this key, if any
+    pub account: Option<String>,
+    /// JSON Web Key of the bound account, authenticating the requests it signs once created
+    pub account_key: Option<String>,
+    pub revoked: bool,
+}
+
+/// Number of signatures performed by a CA key, checked against the configured key usage limits
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KeyUsageEntry {
+    /// Hex-encoded subject key identifier of the CA certificate
+    pub key_identifier: String,
+    pub signatures: u64,
+}
+
+/// Optional features of a storage backend.
+///
+/// Features depending on them are refused at startup on backends lacking them instead of failing
+/// once in use.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StorageCapabilities {
+    /// Several writes can be committed atomically
+    pub transactions: bool,
+    /// Collections can be iterated without loading them whole in memory
+    pub streaming: bool,
+    /// Revocation records are kept and can be queried by serial number and date
+    pub revocation_records: bool,
+    /// Entries can be expired by the backend itself
+    pub ttl: bool,
+}
+
+impl StorageCapabilities {
+    /// Checks that the features enabled by `config` are supported.
+    pub fn check(&self, config: &Config) -> Result<(), ConfigError> {
+        if config.leaf_extensions.crl_distribution_point && !self.revocation_records {
+            return Err(ConfigError::NoRevocationRecords {
+                backend: config.backend.clone(),
+            });
+        }
+
+        Ok(())
+    }
 }
 
 pub trait PickyStorage: Send + Sync {
     fn health(&self) -> Result<(), StorageError>;
+    fn capabilities(&self) -> StorageCapabilities;
+    /// Whether this instance leads the deployment. Background tasks which must run once per
+    /// deployment check it before each run.
+    fn is_leader(&self) -> Result<bool, StorageError>;
     fn store(&self, entry: CertificateEntry) -> Result<(), StorageError>;
     fn get_cert_by_addressing_hash(&self, hash: &str) -> Result<Vec<u8>, StorageError>;
-    fn get_key_by_addressing_hash(&self, hash: &str) -> Result<Vec<u8>, StorageError>;
     fn get_addressing_hash_by_name(&self, name: &str) -> Result<String, StorageError>;
     fn get_addressing_hash_by_key_identifier(&self, key_identifier: &str) -> Result<String, StorageError>;
     fn lookup_addressing_hash(&self, lookup_key: &str) -> Result<String, StorageError>;
+    /// Returns addressing hashes of all certificates requested by `requested_by`.
+    fn get_addressing_hashes_by_requester(&self, requested_by: &str) -> Result<Vec<String>, StorageError>;
+    /// Returns the labels attached to the certificate at issuance (empty if none).
+    fn get_labels(&self, hash: &str) -> Result<Labels, StorageError>;
+    /// Returns addressing hashes of all certificates carrying every label of `selector`.
+    fn get_addressing_hashes_by_labels(&self, selector: &Labels) -> Result<Vec<String>, StorageError>;
+    fn store_signing_request(&self, entry: SigningRequestEntry) -> Result<(), StorageError>;
+    fn get_signing_request(&self, id: &str) -> Result<SigningRequestEntry, StorageError>;
+    fn get_signing_requests(&self) -> Result<Vec<SigningRequestEntry>, StorageError>;
+    /// Atomically replaces the status of a signing request if it is still `expected`.
+    /// Returns `false` if the status was changed in the meantime.
+    fn update_signing_request_status(
+        &self,
+        id: &str,
+        expected: &SigningRequestStatus,
+        status: SigningRequestStatus,
+    ) -> Result<bool, StorageError>;
+    /// Stores a revocation artifact at its canonical address and moves the "latest" pointer identified by
+    /// `latest_key` (e.g. the issuer key identifier for CRLs or the certificate serial number for OCSP responses).
+    /// Returns the artifact address.
+    fn store_artifact(
+        &self,
+        namespace: ArtifactNamespace,
+        latest_key: &str,
+        artifact: Vec<u8>,
+    ) -> Result<String, StorageError>;
+    fn get_artifact_by_addressing_hash(
+        &self,
+        namespace: ArtifactNamespace,
+        hash: &str,
+    ) -> Result<Vec<u8>, StorageError>;
+    fn get_latest_artifact_addressing_hash(
+        &self,
+        namespace: ArtifactNamespace,
+        latest_key: &str,
+    ) -> Result<String, StorageError>;
+    fn store_revocation(&self, entry: RevocationEntry) -> Result<(), StorageError>;
+    /// Returns `None` if the certificate issued by the CA with key identifier `issuer` isn't revoked.
+    fn get_revocation(&self, issuer: &str, serial_number: &str) -> Result<Option<RevocationEntry>, StorageError>;
+    /// Returns entries revoked at or after `timestamp` (seconds since UNIX epoch), oldest first.
+    fn revoked_since(&self, timestamp: u64) -> Result<Vec<RevocationEntry>, StorageError>;
+    /// Appends a record to the audit log. Records are never updated.
+    fn store_audit_record(&self, record: AuditRecord) -> Result<(), StorageError>;
+    /// Returns the last record of the audit log, if any.
+    fn get_audit_head(&self) -> Result<Option<AuditRecord>, StorageError>;
+    /// Returns records starting at `from_sequence`, in log order.
+    fn get_audit_records(&self, from_sequence: u64) -> Result<Vec<AuditRecord>, StorageError>;
+    /// Inserts or updates an external account binding key.
+    fn store_external_account_key(&self, key: ExternalAccountKey) -> Result<(), StorageError>;
+    /// Returns `None` if no key was provisioned with this identifier.
+    fn get_external_account_key(&self, key_id: &str) -> Result<Option<ExternalAccountKey>, StorageError>;
+    fn get_external_account_keys(&self) -> Result<Vec<ExternalAccountKey>, StorageError>;
+    fn store_rotation_state(&self, state: RotationState) -> Result<(), StorageError>;
+    /// Returns an empty rotation state if no rotation is in progress.
+    fn get_rotation_state(&self) -> Result<RotationState, StorageError>;
+    /// Inserts or updates the signature counter of a CA key.
+    fn store_key_usage(&self, entry: KeyUsageEntry) -> Result<(), StorageError>;
+    /// Returns `None` if the key hasn't signed anything yet.
+    fn get_key_usage(&self, key_identifier: &str) -> Result<Option<KeyUsageEntry>, StorageError>;
+}
+
+/// Read access to the private keys stored alongside CA certificates.
+///
+/// Deliberately kept out of `PickyStorage`: HTTP handlers only ever get a `PickyStorage`, so no endpoint
+/// can leak a private key, even by mistake. Only `signer::CaSigner` holds a `PrivateKeyLocker`.
+pub trait PrivateKeyLocker: Send + Sync {
+    fn get_key_by_addressing_hash(&self, hash: &str) -> Result<Vec<u8>, StorageError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TestDir;
+    use picky::{
+        key::PrivateKey,
+        proptest_support::{certificate, private_key},
+        x509::Cert,
+    };
+    use proptest::{prelude::*, test_runner::TestCaseError};
+
+    /// Stores `cert` and `key`, then checks they're read back byte for byte.
+    fn check_round_trip<S: PickyStorage + PrivateKeyLocker>(
+        storage: &S,
+        cert: &Cert,
+        key: &PrivateKey,
+    ) -> Result<(), TestCaseError> {
+        let cert_der = cert.to_der().unwrap();
+        let key_der = key.to_pkcs8().unwrap();
+        let serial_number = hex::encode(cert.serial_number().as_unsigned_bytes_be());
+
+        storage
+            .store(CertificateEntry {
+                name: format!("cert-{}", serial_number),
+                cert: cert_der.clone(),
+                key_identifier: serial_number.clone(),
+                key: Some(key_der.clone()),
+                requested_by: None,
+                labels: Labels::new(),
+            })
+            .unwrap();
+
+        let hash = storage
+            .get_addressing_hash_by_name(&format!("cert-{}", serial_number))
+            .unwrap();
+        let stored_der = storage.get_cert_by_addressing_hash(&hash).unwrap();
+        prop_assert_eq!(&stored_der, &cert_der);
+        prop_assert_eq!(&Cert::from_der(&stored_der).unwrap(), cert);
+
+        let stored_key = storage.get_key_by_addressing_hash(&hash).unwrap();
+        prop_assert_eq!(&stored_key, &key_der);
+        prop_assert_eq!(&PrivateKey::from_pkcs8(&stored_key).unwrap(), key);
+
+        Ok(())
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(16))]
+
+        #[test]
+        fn backends_keep_der_intact(cert in certificate(), key in private_key()) {
+            let dir = TestDir::new("storage_round_trip");
+            let mut config = Config::default();
+            config.file_backend_path = dir.join("file");
+            config.sqlite_path = dir.join("picky.db");
+
+            check_round_trip(&MemoryStorage::new(), &cert, &key)?;
+            check_round_trip(&FileStorage::new(&config), &cert, &key)?;
+            check_round_trip(&SqliteStorage::new(&config), &cert, &key)?;
+        }
+    }
 }
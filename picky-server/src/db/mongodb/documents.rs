@@ -0,0 +1,372 @@
+//! BSON encoding of the storage entries.
+//!
+//! bson 0.13 refuses to serialize unsigned integers and encodes `Vec<u8>` as an array of integers, so
+//! entries are built field by field instead of going through serde: integers as `Bson::I64` and bytes
+//! as `Bson::Binary`.
+
+use crate::db::{
+    mongodb::MongoStorageError, AuditRecord, ExternalAccountKey, KeyUsageEntry, RevocationEntry, SigningRequestEntry,
+    SigningRequestStatus,
+};
+use bson::{from_bson, spec::BinarySubtype, to_bson, Bson, Document};
+use serde::de::DeserializeOwned;
+use std::convert::TryFrom;
+
+pub trait MongoDocument: Sized {
+    fn to_document(&self) -> Result<Document, MongoStorageError>;
+    fn from_document(doc: &Document) -> Result<Self, MongoStorageError>;
+}
+
+/// Encodes `entry` as the value of a model.
+pub fn to_value<T: MongoDocument>(entry: &T) -> Result<Bson, MongoStorageError> {
+    Ok(Bson::Document(entry.to_document()?))
+}
+
+/// Decodes the value of a model.
+pub fn from_value<T: MongoDocument>(value: Bson) -> Result<T, MongoStorageError> {
+    match value {
+        Bson::Document(doc) => T::from_document(&doc),
+        unexpected => Err(MongoStorageError::Other {
+            description: format!("expected document DB content but got {}", unexpected),
+        }),
+    }
+}
+
+impl MongoDocument for RevocationEntry {
+    fn to_document(&self) -> Result<Document, MongoStorageError> {
+        let mut doc = Document::new();
+        doc.insert("issuer", self.issuer.clone());
+        doc.insert("serial_number", self.serial_number.clone());
+        doc.insert("revoked_at", encode_u64(self.revoked_at)?);
+        doc.insert(
+            "reason",
+            self.reason.map_or(Bson::Null, |reason| Bson::I32(i32::from(reason))),
+        );
+        Ok(doc)
+    }
+
+    fn from_document(doc: &Document) -> Result<Self, MongoStorageError> {
+        let reason = match doc.get("reason") {
+            None | Some(Bson::Null) => None,
+            Some(_) => {
+                let reason = decode_u64(doc, "reason")?;
+                Some(u8::try_from(reason).map_err(|_| invalid_field("reason", reason))?)
+            }
+        };
+
+        Ok(Self {
+            issuer: decode_string(doc, "issuer")?,
+            serial_number: decode_string(doc, "serial_number")?,
+            revoked_at: decode_u64(doc, "revoked_at")?,
+            reason,
+        })
+    }
+}
+
+/// Fields of a signing request holding its status, same layout as the flattened serde representation
+/// used by the other backends.
+pub fn encode_status(status: &SigningRequestStatus) -> Document {
+    let mut doc = Document::new();
+    match status {
+        SigningRequestStatus::Pending => {
+            doc.insert("status", "pending");
+        }
+        SigningRequestStatus::Issuing => {
+            doc.insert("status", "issuing");
+        }
+        SigningRequestStatus::Approved { cert } => {
+            doc.insert("status", "approved");
+            doc.insert("cert", encode_binary(cert));
+        }
+        SigningRequestStatus::Denied => {
+            doc.insert("status", "denied");
+        }
+    }
+    doc
+}
+
+impl MongoDocument for SigningRequestEntry {
+    fn to_document(&self) -> Result<Document, MongoStorageError> {
+        let mut doc = Document::new();
+        doc.insert("id", self.id.clone());
+        doc.insert("subject_name", self.subject_name.clone());
+        doc.insert("csr", encode_binary(&self.csr));
+        doc.insert("submitted_at", encode_u64(self.submitted_at)?);
+        doc.insert("requested_by", encode_optional_string(&self.requested_by));
+        doc.insert("labels", to_bson(&self.labels)?);
+        doc.insert("alt_names", to_bson(&self.alt_names)?);
+        doc.insert("profile", encode_optional_string(&self.profile));
+        doc.insert("issuer", encode_optional_string(&self.issuer));
+        for (key, value) in encode_status(&self.status) {
+            doc.insert(key, value);
+        }
+        Ok(doc)
+    }
+
+    fn from_document(doc: &Document) -> Result<Self, MongoStorageError> {
+        let status = match decode_string(doc, "status")?.as_str() {
+            "pending" => SigningRequestStatus::Pending,
+            "issuing" => SigningRequestStatus::Issuing,
+            "approved" => SigningRequestStatus::Approved {
+                cert: decode_binary(doc, "cert")?,
+            },
+            "denied" => SigningRequestStatus::Denied,
+            unexpected => return Err(invalid_field("status", unexpected)),
+        };
+
+        Ok(Self {
+            id: decode_string(doc, "id")?,
+            subject_name: decode_string(doc, "subject_name")?,
+            csr: decode_binary(doc, "csr")?,
+            submitted_at: decode_u64(doc, "submitted_at")?,
+            requested_by: decode_optional_string(doc, "requested_by")?,
+            labels: decode_or_default(doc, "labels")?,
+            alt_names: decode_or_default(doc, "alt_names")?,
+            profile: decode_optional_string(doc, "profile")?,
+            issuer: decode_optional_string(doc, "issuer")?,
+            status,
+        })
+    }
+}
+
+impl MongoDocument for AuditRecord {
+    fn to_document(&self) -> Result<Document, MongoStorageError> {
+        let mut doc = Document::new();
+        doc.insert("sequence", encode_u64(self.sequence)?);
+        doc.insert("timestamp", encode_u64(self.timestamp)?);
+        doc.insert("event", self.event.clone());
+        doc.insert("detail", self.detail.clone());
+        doc.insert("previous_hash", self.previous_hash.clone());
+        doc.insert("hash", self.hash.clone());
+        Ok(doc)
+    }
+
+    fn from_document(doc: &Document) -> Result<Self, MongoStorageError> {
+        Ok(Self {
+            sequence: decode_u64(doc, "sequence")?,
+            timestamp: decode_u64(doc, "timestamp")?,
+            event: decode_string(doc, "event")?,
+            detail: decode_string(doc, "detail")?,
+            previous_hash: decode_string(doc, "previous_hash")?,
+            hash: decode_string(doc, "hash")?,
+        })
+    }
+}
+
+impl MongoDocument for ExternalAccountKey {
+    fn to_document(&self) -> Result<Document, MongoStorageError> {
+        let mut doc = Document::new();
+        doc.insert("key_id", self.key_id.clone());
+        doc.insert("hmac_key", self.hmac_key.clone());
+        doc.insert("created_at", encode_u64(self.created_at)?);
+        doc.insert("account", encode_optional_string(&self.account));
+        doc.insert("revoked", self.revoked);
+        doc.insert("account_key", encode_optional_string(&self.account_key));
+        Ok(doc)
+    }
+
+    fn from_document(doc: &Document) -> Result<Self, MongoStorageError> {
+        Ok(Self {
+            key_id: decode_string(doc, "key_id")?,
+            hmac_key: decode_string(doc, "hmac_key")?,
+            created_at: decode_u64(doc, "created_at")?,
+            account: decode_optional_string(doc, "account")?,
+            revoked: doc.get_bool("revoked").map_err(|e| access_error("revoked", e))?,
+            account_key: decode_optional_string(doc, "account_key")?,
+        })
+    }
+}
+
+impl MongoDocument for KeyUsageEntry {
+    fn to_document(&self) -> Result<Document, MongoStorageError> {
+        let mut doc = Document::new();
+        doc.insert("key_identifier", self.key_identifier.clone());
+        doc.insert("signatures", encode_u64(self.signatures)?);
+        Ok(doc)
+    }
+
+    fn from_document(doc: &Document) -> Result<Self, MongoStorageError> {
+        Ok(Self {
+            key_identifier: decode_string(doc, "key_identifier")?,
+            signatures: decode_u64(doc, "signatures")?,
+        })
+    }
+}
+
+fn encode_u64(value: u64) -> Result<Bson, MongoStorageError> {
+    i64::try_from(value)
+        .map(Bson::I64)
+        .map_err(|_| MongoStorageError::Other {
+            description: format!("{} doesn't fit in a BSON integer", value),
+        })
+}
+
+fn encode_binary(bytes: &[u8]) -> Bson {
+    Bson::Binary(BinarySubtype::Generic, bytes.to_vec())
+}
+
+fn encode_optional_string(value: &Option<String>) -> Bson {
+    value.clone().map_or(Bson::Null, Bson::String)
+}
+
+fn decode_u64(doc: &Document, key: &str) -> Result<u64, MongoStorageError> {
+    // documents written by other tools may hold 32-bit integers
+    let value = match doc.get(key) {
+        Some(Bson::I64(value)) => *value,
+        Some(Bson::I32(value)) => i64::from(*value),
+        Some(unexpected) => return Err(invalid_field(key, unexpected)),
+        None => return Err(missing_field(key)),
+    };
+    u64::try_from(value).map_err(|_| invalid_field(key, value))
+}
+
+fn decode_string(doc: &Document, key: &str) -> Result<String, MongoStorageError> {
+    doc.get_str(key).map(str::to_owned).map_err(|e| access_error(key, e))
+}
+
+fn decode_optional_string(doc: &Document, key: &str) -> Result<Option<String>, MongoStorageError> {
+    match doc.get(key) {
+        None | Some(Bson::Null) => Ok(None),
+        Some(Bson::String(value)) => Ok(Some(value.clone())),
+        Some(unexpected) => Err(invalid_field(key, unexpected)),
+    }
+}
+
+fn decode_binary(doc: &Document, key: &str) -> Result<Vec<u8>, MongoStorageError> {
+    doc.get_binary_generic(key)
+        .map(Clone::clone)
+        .map_err(|e| access_error(key, e))
+}
+
+fn decode_or_default<T: DeserializeOwned + Default>(doc: &Document, key: &str) -> Result<T, MongoStorageError> {
+    match doc.get(key) {
+        None | Some(Bson::Null) => Ok(T::default()),
+        Some(value) => Ok(from_bson(value.clone())?),
+    }
+}
+
+fn access_error(key: &str, e: bson::ordered::ValueAccessError) -> MongoStorageError {
+    MongoStorageError::Other {
+        description: format!("couldn't read document field {}: {:?}", key, e),
+    }
+}
+
+fn missing_field(key: &str) -> MongoStorageError {
+    MongoStorageError::Other {
+        description: format!("document field {} is missing", key),
+    }
+}
+
+fn invalid_field<V: std::fmt::Display>(key: &str, value: V) -> MongoStorageError {
+    MongoStorageError::Other {
+        description: format!("invalid document field {}: {}", key, value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt::Debug;
+
+    /// Goes through the wire format, which rejects what the driver would reject.
+    fn check_round_trip<T: MongoDocument + PartialEq + Debug>(entry: T) {
+        let mut encoded = Vec::new();
+        bson::encode_document(&mut encoded, &entry.to_document().expect("to document")).expect("encode document");
+        let doc = bson::decode_document(&mut encoded.as_slice()).expect("decode document");
+        assert_eq!(from_value::<T>(Bson::Document(doc)).expect("from document"), entry);
+    }
+
+    #[test]
+    fn revocation_entry_round_trip() {
+        check_round_trip(RevocationEntry {
+            issuer: "0123456789abcdef".to_owned(),
+            serial_number: "00ff".to_owned(),
+            revoked_at: 1_600_000_000,
+            reason: Some(1),
+        });
+        check_round_trip(RevocationEntry {
+            issuer: "0123456789abcdef".to_owned(),
+            serial_number: "0a".to_owned(),
+            revoked_at: 0,
+            reason: None,
+        });
+    }
+
+    #[test]
+    fn signing_request_entry_round_trip() {
+        let mut labels = crate::labels::Labels::new();
+        labels.insert("team".to_owned(), "infra".to_owned());
+        let pending = SigningRequestEntry {
+            id: "8f14e45f".to_owned(),
+            subject_name: "bushido.example.com".to_owned(),
+            csr: vec![0x30, 0x82, 0x00, 0xff],
+            submitted_at: 1_600_000_000,
+            requested_by: Some("token:bushido".to_owned()),
+            labels,
+            alt_names: crate::alt_names::AltNames {
+                dns_names: vec!["www.bushido.example.com".to_owned()],
+                ip_addresses: vec!["10.0.0.1".parse().expect("ip address")],
+                uris: Vec::new(),
+            },
+            profile: Some("server".to_owned()),
+            issuer: None,
+            status: SigningRequestStatus::Pending,
+        };
+        check_round_trip(pending.clone());
+        check_round_trip(SigningRequestEntry {
+            status: SigningRequestStatus::Issuing,
+            ..pending.clone()
+        });
+        check_round_trip(SigningRequestEntry {
+            status: SigningRequestStatus::Approved {
+                cert: vec![0x30, 0x03, 0x02, 0x01, 0x00],
+            },
+            ..pending.clone()
+        });
+        check_round_trip(SigningRequestEntry {
+            status: SigningRequestStatus::Denied,
+            ..pending
+        });
+    }
+
+    #[test]
+    fn audit_record_round_trip() {
+        check_round_trip(AuditRecord {
+            sequence: 42,
+            timestamp: 1_600_000_000,
+            event: "certificate_issued".to_owned(),
+            detail: "{}".to_owned(),
+            previous_hash: "QmPrevious".to_owned(),
+            hash: "QmRecord".to_owned(),
+        });
+    }
+
+    #[test]
+    fn external_account_key_round_trip() {
+        check_round_trip(ExternalAccountKey {
+            key_id: "kid-1".to_owned(),
+            hmac_key: "c2VjcmV0".to_owned(),
+            created_at: 1_600_000_000,
+            account: Some("thumbprint".to_owned()),
+            revoked: false,
+            account_key: Some(r#"{"kty":"RSA","n":"AQAB","e":"AQAB"}"#.to_owned()),
+        });
+    }
+
+    #[test]
+    fn key_usage_entry_round_trip() {
+        check_round_trip(KeyUsageEntry {
+            key_identifier: "a1b2c3".to_owned(),
+            signatures: u64::from(u32::MAX) + 1,
+        });
+    }
+
+    #[test]
+    fn out_of_range_integers_are_refused() {
+        let entry = KeyUsageEntry {
+            key_identifier: "a1b2c3".to_owned(),
+            signatures: u64::MAX,
+        };
+        assert!(entry.to_document().is_err());
+    }
+}
@@ -1,24 +1,38 @@
+mod documents;
 mod mongo_connection;
 mod mongo_repository;
 
 use crate::{
-    addressing::{encode_to_alternative_addresses, encode_to_canonical_address},
+    addressing::{encode_to_alternative_addresses, encode_to_canonical_address, ArtifactNamespace},
     config::Config,
     db::{
         mongodb::{
+            documents::{encode_status, from_value, to_value},
             mongo_connection::MongoConnection,
             mongo_repository::{
-                CertificateModel, CertificateStoreRepository, ConfigStoreRepository, HashLookupTableStoreRepository,
-                KeyIdentifierModel, KeyIdentifierStoreRepository, KeyModel, KeyStoreRepository, NameModel,
-                NameStoreRepository, CERTIFICATE_COLLECTION_NAME, CONFIG_COLLECTION_NAME,
+                ArtifactModel, ArtifactStoreRepository, AuditModel, AuditStoreRepository, CertificateModel,
+                CertificateStoreRepository, ConfigStoreRepository, ExternalAccountKeyModel,
+                ExternalAccountKeyStoreRepository, HashLookupTableStoreRepository, KeyIdentifierModel,
+                KeyIdentifierStoreRepository, KeyModel, KeyStoreRepository, KeyUsageModel, KeyUsageStoreRepository,
+                LabelsModel, LabelsStoreRepository, LatestArtifactModel, LatestArtifactStoreRepository, NameModel,
+                NameStoreRepository, RequesterModel, RequesterStoreRepository, RevocationModel,
+                RevocationStoreRepository, RotationStateModel, RotationStateStoreRepository, SigningRequestModel,
+                SigningRequestStoreRepository, AUDIT_COLLECTION_NAME, CERTIFICATE_COLLECTION_NAME,
+                CONFIG_COLLECTION_NAME, CRL_COLLECTION_NAME, EXTERNAL_ACCOUNT_KEY_COLLECTION_NAME,
                 HASH_LOOKUP_TABLE_COLLECTION_NAME, KEY_IDENTIFIER_COLLECTION_NAME, KEY_STORE_COLLECTION_NAME,
-                NAME_STORE_COLLECTION_NAME,
+                KEY_USAGE_COLLECTION_NAME, LABELS_COLLECTION_NAME, LATEST_ARTIFACT_COLLECTION_NAME,
+                NAME_STORE_COLLECTION_NAME, OCSP_COLLECTION_NAME, REQUESTER_COLLECTION_NAME,
+                REVOCATION_COLLECTION_NAME, ROTATION_STATE_COLLECTION_NAME, SIGNING_REQUEST_COLLECTION_NAME,
             },
         },
-        CertificateEntry, PickyStorage, StorageError, SCHEMA_LAST_VERSION,
+        AuditRecord, CertificateEntry, ExternalAccountKey, KeyUsageEntry, PickyStorage, PrivateKeyLocker,
+        RevocationEntry, RotationState, SigningRequestEntry, SigningRequestStatus, StorageCapabilities, StorageError,
+        SCHEMA_LAST_VERSION,
     },
+    labels::Labels,
 };
-use bson::{bson, doc, from_bson, spec::BinarySubtype, Bson};
+use bson::{bson, doc, from_bson, spec::BinarySubtype, to_bson, Bson, Document};
+use mongodb::coll::options::FindOptions;
 use picky::x509::Cert;
 use snafu::Snafu;
 use std::{collections::HashMap, convert::TryFrom};
@@ -40,12 +54,37 @@ pub enum MongoStorageError {
         source: mongodb::Error,
     },
 
+    #[snafu(display("couldn't parse connection string: {}", source))]
+    ConnectionString {
+        source: mongodb::Error,
+    },
+
+    #[snafu(display("couldn't create r2d2 connection pool: {}", source))]
+    ConnectionPool {
+        source: r2d2::Error,
+    },
+
+    #[snafu(display("couldn't get mongo connection from r2d2: {}", source))]
+    Connection {
+        source: r2d2::Error,
+    },
+
+    #[snafu(display("couldn't ping: {}", source))]
+    Ping {
+        source: mongodb::Error,
+    },
+
     // insert error
     InsertError,
 
     /// update error
     UpdateError,
 
+    #[snafu(display("{}", description))]
+    NotFound {
+        description: String,
+    },
+
     #[snafu(display("generic error: {}", description))]
     Other {
         description: String,
@@ -107,11 +146,28 @@ pub struct MongoStorage {
     key_store: KeyStoreRepository,
     name_store: NameStoreRepository,
     hash_lookup: HashLookupTableStoreRepository,
+    requester_store: RequesterStoreRepository,
+    labels_store: LabelsStoreRepository,
+    signing_request_store: SigningRequestStoreRepository,
+    crl_store: ArtifactStoreRepository,
+    ocsp_store: ArtifactStoreRepository,
+    latest_artifact_store: LatestArtifactStoreRepository,
+    revocation_store: RevocationStoreRepository,
+    audit_store: AuditStoreRepository,
+    external_account_key_store: ExternalAccountKeyStoreRepository,
+    rotation_state_store: RotationStateStoreRepository,
+    key_usage_store: KeyUsageStoreRepository,
+}
+
+const ROTATION_STATE_KEY: &str = "rotation_state";
+
+fn audit_record_key(sequence: u64) -> String {
+    format!("{:020}", sequence)
 }
 
 impl MongoStorage {
-    pub fn new(config: &Config) -> Self {
-        let db = MongoConnection::new(&config.database_url).expect("build mongo connection");
+    pub fn new(config: &Config) -> Result<Self, MongoStorageError> {
+        let db = MongoConnection::new(&config.database_url)?;
 
         let storage = MongoStorage {
             mongo_conn: db.clone(),
@@ -120,8 +176,34 @@ impl MongoStorage {
             key_store: KeyStoreRepository::new(db.clone(), KEY_STORE_COLLECTION_NAME),
             name_store: NameStoreRepository::new(db.clone(), NAME_STORE_COLLECTION_NAME),
             hash_lookup: HashLookupTableStoreRepository::new(db.clone(), HASH_LOOKUP_TABLE_COLLECTION_NAME),
+            requester_store: RequesterStoreRepository::new(db.clone(), REQUESTER_COLLECTION_NAME),
+            labels_store: LabelsStoreRepository::new(db.clone(), LABELS_COLLECTION_NAME),
+            signing_request_store: SigningRequestStoreRepository::new(db.clone(), SIGNING_REQUEST_COLLECTION_NAME),
+            crl_store: ArtifactStoreRepository::new(db.clone(), CRL_COLLECTION_NAME),
+            ocsp_store: ArtifactStoreRepository::new(db.clone(), OCSP_COLLECTION_NAME),
+            latest_artifact_store: LatestArtifactStoreRepository::new(db.clone(), LATEST_ARTIFACT_COLLECTION_NAME),
+            revocation_store: RevocationStoreRepository::new(db.clone(), REVOCATION_COLLECTION_NAME),
+            audit_store: AuditStoreRepository::new(db.clone(), AUDIT_COLLECTION_NAME),
+            external_account_key_store: ExternalAccountKeyStoreRepository::new(
+                db.clone(),
+                EXTERNAL_ACCOUNT_KEY_COLLECTION_NAME,
+            ),
+            rotation_state_store: RotationStateStoreRepository::new(db.clone(), ROTATION_STATE_COLLECTION_NAME),
+            key_usage_store: KeyUsageStoreRepository::new(db.clone(), KEY_USAGE_COLLECTION_NAME),
         };
 
+        // serial number lookups (OCSP) and revocation time range scans (CRL, delta CRL)
+        storage.revocation_store.create_index(doc!("key": 1), true)?;
+        storage
+            .revocation_store
+            .create_index(doc!("value.revoked_at": 1), false)?;
+
+        // certificate inventory lookups by requester
+        storage.requester_store.create_index(doc!("value": 1), false)?;
+
+        // keys are zero-padded sequence numbers: the unique index prevents forking the audit log
+        storage.audit_store.create_index(doc!("key": 1), true)?;
+
         let config = ConfigStoreRepository::new(db, CONFIG_COLLECTION_NAME);
         let config_collection = config.get_collection().expect("config collection");
         let mut config_cursor = config_collection.find(None, None).expect("find config doc");
@@ -214,6 +296,8 @@ impl MongoStorage {
                             cert: cert_der,
                             key_identifier: hex::encode(cert.subject_key_identifier().expect("cert key id")),
                             key: key_pkcs10,
+                            requested_by: None,
+                            labels: Labels::new(),
                         })
                         .expect("couldn't store certificate (migration from v0 schema)");
                 }
@@ -228,7 +312,14 @@ impl MongoStorage {
                 .expect("insert config doc");
         }
 
-        storage
+        Ok(storage)
+    }
+
+    fn artifact_store(&self, namespace: ArtifactNamespace) -> &ArtifactStoreRepository {
+        match namespace {
+            ArtifactNamespace::Crl => &self.crl_store,
+            ArtifactNamespace::Ocsp => &self.ocsp_store,
+        }
     }
 }
 
@@ -240,11 +331,28 @@ impl PickyStorage for MongoStorage {
         Ok(())
     }
 
+    fn capabilities(&self) -> StorageCapabilities {
+        // the driver doesn't expose multi-document transactions
+        StorageCapabilities {
+            transactions: false,
+            streaming: true,
+            revocation_records: true,
+            ttl: true,
+        }
+    }
+
+    fn is_leader(&self) -> Result<bool, StorageError> {
+        // no leader election, background tasks run on every instance
+        Ok(true)
+    }
+
     fn store(&self, entry: CertificateEntry) -> Result<(), StorageError> {
         let name = entry.name;
         let cert = entry.cert;
         let key_identifier = entry.key_identifier;
         let key = entry.key;
+        let requested_by = entry.requested_by;
+        let labels = entry.labels;
 
         let addressing_hash = encode_to_canonical_address(&cert).map_err(|e| MongoStorageError::Other {
             description: format!("couldn't get certificate multihash: {}", e),
@@ -276,6 +384,19 @@ impl PickyStorage for MongoStorage {
                 .update_with_options(alternative_hash_doc, alternative_hash_item, true)?;
         }
 
+        if let Some(requested_by) = requested_by {
+            let requester_doc = doc!("key": addressing_hash.clone());
+            let requester_item = RequesterModel::new(addressing_hash.clone(), requested_by);
+            self.requester_store
+                .update_with_options(requester_doc, requester_item, true)?;
+        }
+
+        if !labels.is_empty() {
+            let labels_doc = doc!("key": addressing_hash.clone());
+            let labels_item = LabelsModel::new(addressing_hash.clone(), to_bson(&labels)?);
+            self.labels_store.update_with_options(labels_doc, labels_item, true)?;
+        }
+
         if let Some(key) = key {
             let key_doc = doc!("key": addressing_hash.clone());
             let key_item = KeyModel::new(addressing_hash, Bson::Binary(BinarySubtype::Generic, key));
@@ -300,7 +421,7 @@ impl PickyStorage for MongoStorage {
         let cert = self
             .certificate_store
             .get(doc!("key": hash))?
-            .ok_or_else(|| MongoStorageError::Other {
+            .ok_or_else(|| MongoStorageError::NotFound {
                 description: "cert not found".to_owned(),
             })?;
 
@@ -313,27 +434,11 @@ impl PickyStorage for MongoStorage {
         }
     }
 
-    fn get_key_by_addressing_hash(&self, hash: &str) -> Result<Vec<u8>, StorageError> {
-        let key = self
-            .key_store
-            .get(doc!("key": hash))?
-            .ok_or_else(|| MongoStorageError::Other {
-                description: "key not found".to_owned(),
-            })?;
-        match key.value {
-            Bson::Binary(BinarySubtype::Generic, bin) => Ok(bin),
-            unexpected => Err(MongoStorageError::Other {
-                description: format!("expected binary DB content but got {}", unexpected),
-            }
-            .into()),
-        }
-    }
-
     fn get_addressing_hash_by_key_identifier(&self, key_identifier: &str) -> Result<String, StorageError> {
         Ok(self
             .key_identifier_store
             .get(doc!("key": key_identifier))?
-            .ok_or_else(|| MongoStorageError::Other {
+            .ok_or_else(|| MongoStorageError::NotFound {
                 description: format!("addressing hash not found by key identifier \"{}\"", key_identifier),
             })?
             .value)
@@ -343,9 +448,316 @@ impl PickyStorage for MongoStorage {
         Ok(self
             .hash_lookup
             .get(doc!("key": lookup_key))?
-            .ok_or_else(|| MongoStorageError::Other {
+            .ok_or_else(|| MongoStorageError::NotFound {
                 description: format!("addressing hash not found using lookup key \"{}\"", lookup_key),
             })?
             .value)
     }
+
+    fn get_addressing_hashes_by_requester(&self, requested_by: &str) -> Result<Vec<String>, StorageError> {
+        let collection = self.requester_store.get_collection()?;
+        let options = FindOptions {
+            sort: Some(doc!("key": 1)),
+            ..FindOptions::new()
+        };
+
+        let mut hashes = Vec::new();
+        for doc in collection.find(Some(doc!("value": requested_by)), Some(options))? {
+            let model: RequesterModel = from_bson(Bson::Document(doc?))?;
+            hashes.push(model.key);
+        }
+        Ok(hashes)
+    }
+
+    fn get_labels(&self, hash: &str) -> Result<Labels, StorageError> {
+        match self.labels_store.get(doc!("key": hash))? {
+            Some(model) => Ok(from_bson(model.value)?),
+            None => Ok(Labels::new()),
+        }
+    }
+
+    fn get_addressing_hashes_by_labels(&self, selector: &Labels) -> Result<Vec<String>, StorageError> {
+        let collection = self.labels_store.get_collection()?;
+        let options = FindOptions {
+            sort: Some(doc!("key": 1)),
+            ..FindOptions::new()
+        };
+
+        // label keys are validated on issuance, they can't contain '.' or '$'
+        let mut filter = Document::new();
+        for (key, value) in selector {
+            filter.insert(format!("value.{}", key), value.clone());
+        }
+
+        let mut hashes = Vec::new();
+        for doc in collection.find(Some(filter), Some(options))? {
+            let model: LabelsModel = from_bson(Bson::Document(doc?))?;
+            hashes.push(model.key);
+        }
+        Ok(hashes)
+    }
+
+    fn store_signing_request(&self, entry: SigningRequestEntry) -> Result<(), StorageError> {
+        let signing_request_doc = doc!("key": entry.id.clone());
+        let signing_request_item = SigningRequestModel::new(entry.id.clone(), to_value(&entry)?);
+        self.signing_request_store
+            .update_with_options(signing_request_doc, signing_request_item, true)?;
+        Ok(())
+    }
+
+    fn get_signing_request(&self, id: &str) -> Result<SigningRequestEntry, StorageError> {
+        let model = self
+            .signing_request_store
+            .get(doc!("key": id))?
+            .ok_or_else(|| MongoStorageError::NotFound {
+                description: format!("signing request {} not found", id),
+            })?;
+        Ok(from_value(model.value)?)
+    }
+
+    fn update_signing_request_status(
+        &self,
+        id: &str,
+        expected: &SigningRequestStatus,
+        status: SigningRequestStatus,
+    ) -> Result<bool, StorageError> {
+        let mut entry: SigningRequestEntry = self.get_signing_request(id)?;
+        if entry.status != *expected {
+            return Ok(false);
+        }
+
+        // the entry is only replaced if its status is still the expected one
+        let mut filter = doc!("key": id);
+        for (field, value) in encode_status(expected) {
+            filter.insert(format!("value.{}", field), value);
+        }
+
+        entry.status = status;
+        let signing_request_item = SigningRequestModel::new(id.to_owned(), to_value(&entry)?);
+        Ok(self.signing_request_store.replace(filter, signing_request_item)?)
+    }
+
+    fn get_signing_requests(&self) -> Result<Vec<SigningRequestEntry>, StorageError> {
+        let collection = self.signing_request_store.get_collection()?;
+        let mut entries = Vec::new();
+        for doc in collection.find(None, None)? {
+            let model: SigningRequestModel = from_bson(Bson::Document(doc?))?;
+            entries.push(from_value(model.value)?);
+        }
+        Ok(entries)
+    }
+
+    fn store_artifact(
+        &self,
+        namespace: ArtifactNamespace,
+        latest_key: &str,
+        artifact: Vec<u8>,
+    ) -> Result<String, StorageError> {
+        let addressing_hash = encode_to_canonical_address(&artifact).map_err(|e| MongoStorageError::Other {
+            description: format!("couldn't get {} artifact multihash: {}", namespace, e),
+        })?;
+
+        let artifact_doc = doc!("key": addressing_hash.clone());
+        let artifact_item = ArtifactModel::new(addressing_hash.clone(), Bson::Binary(BinarySubtype::Generic, artifact));
+        self.artifact_store(namespace)
+            .update_with_options(artifact_doc, artifact_item, true)?;
+
+        let latest_key = format!("{}/{}", namespace, latest_key);
+        let latest_doc = doc!("key": latest_key.clone());
+        let latest_item = LatestArtifactModel::new(latest_key, addressing_hash.clone());
+        self.latest_artifact_store
+            .update_with_options(latest_doc, latest_item, true)?;
+
+        Ok(addressing_hash)
+    }
+
+    fn get_artifact_by_addressing_hash(
+        &self,
+        namespace: ArtifactNamespace,
+        hash: &str,
+    ) -> Result<Vec<u8>, StorageError> {
+        let artifact =
+            self.artifact_store(namespace)
+                .get(doc!("key": hash))?
+                .ok_or_else(|| MongoStorageError::NotFound {
+                    description: format!("{} artifact not found", namespace),
+                })?;
+
+        match artifact.value {
+            Bson::Binary(BinarySubtype::Generic, bin) => Ok(bin),
+            unexpected => Err(MongoStorageError::Other {
+                description: format!("expected binary DB content but got {}", unexpected),
+            }
+            .into()),
+        }
+    }
+
+    fn get_latest_artifact_addressing_hash(
+        &self,
+        namespace: ArtifactNamespace,
+        latest_key: &str,
+    ) -> Result<String, StorageError> {
+        Ok(self
+            .latest_artifact_store
+            .get(doc!("key": format!("{}/{}", namespace, latest_key)))?
+            .ok_or_else(|| MongoStorageError::Other {
+                description: format!("no {} artifact found for {}", namespace, latest_key),
+            })?
+            .value)
+    }
+
+    fn store_revocation(&self, entry: RevocationEntry) -> Result<(), StorageError> {
+        let key = format!("{}/{}", entry.issuer, entry.serial_number);
+        let revocation_doc = doc!("key": key.clone());
+        let revocation_item = RevocationModel::new(key, to_value(&entry)?);
+        self.revocation_store
+            .update_with_options(revocation_doc, revocation_item, true)?;
+        Ok(())
+    }
+
+    fn get_revocation(&self, issuer: &str, serial_number: &str) -> Result<Option<RevocationEntry>, StorageError> {
+        match self
+            .revocation_store
+            .get(doc!("key": format!("{}/{}", issuer, serial_number)))?
+        {
+            Some(model) => Ok(Some(from_value(model.value)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn revoked_since(&self, timestamp: u64) -> Result<Vec<RevocationEntry>, StorageError> {
+        let timestamp = i64::try_from(timestamp).map_err(|e| MongoStorageError::Other {
+            description: format!("invalid revocation timestamp {}: {}", timestamp, e),
+        })?;
+        let collection = self.revocation_store.get_collection()?;
+        let filter = doc!("value.revoked_at": { "$gte": timestamp });
+        let options = FindOptions {
+            sort: Some(doc!("value.revoked_at": 1)),
+            ..FindOptions::new()
+        };
+
+        let mut entries = Vec::new();
+        for doc in collection.find(Some(filter), Some(options))? {
+            let model: RevocationModel = from_bson(Bson::Document(doc?))?;
+            entries.push(from_value(model.value)?);
+        }
+        Ok(entries)
+    }
+
+    fn store_audit_record(&self, record: AuditRecord) -> Result<(), StorageError> {
+        let audit_item = AuditModel::new(audit_record_key(record.sequence), to_value(&record)?);
+        self.audit_store.insert(audit_item)?;
+        Ok(())
+    }
+
+    fn get_audit_head(&self) -> Result<Option<AuditRecord>, StorageError> {
+        let collection = self.audit_store.get_collection()?;
+        let options = FindOptions {
+            sort: Some(doc!("key": -1)),
+            limit: Some(1),
+            ..FindOptions::new()
+        };
+
+        match collection.find(None, Some(options))?.next() {
+            Some(doc) => {
+                let model: AuditModel = from_bson(Bson::Document(doc?))?;
+                Ok(Some(from_value(model.value)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn get_audit_records(&self, from_sequence: u64) -> Result<Vec<AuditRecord>, StorageError> {
+        let collection = self.audit_store.get_collection()?;
+        let filter = doc!("key": { "$gte": audit_record_key(from_sequence) });
+        let options = FindOptions {
+            sort: Some(doc!("key": 1)),
+            ..FindOptions::new()
+        };
+
+        let mut records = Vec::new();
+        for doc in collection.find(Some(filter), Some(options))? {
+            let model: AuditModel = from_bson(Bson::Document(doc?))?;
+            records.push(from_value(model.value)?);
+        }
+        Ok(records)
+    }
+
+    fn store_external_account_key(&self, key: ExternalAccountKey) -> Result<(), StorageError> {
+        let key_doc = doc!("key": key.key_id.clone());
+        let key_item = ExternalAccountKeyModel::new(key.key_id.clone(), to_value(&key)?);
+        self.external_account_key_store
+            .update_with_options(key_doc, key_item, true)?;
+        Ok(())
+    }
+
+    fn get_external_account_key(&self, key_id: &str) -> Result<Option<ExternalAccountKey>, StorageError> {
+        match self.external_account_key_store.get(doc!("key": key_id))? {
+            Some(model) => Ok(Some(from_value(model.value)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn get_external_account_keys(&self) -> Result<Vec<ExternalAccountKey>, StorageError> {
+        let collection = self.external_account_key_store.get_collection()?;
+        let options = FindOptions {
+            sort: Some(doc!("value.created_at": 1)),
+            ..FindOptions::new()
+        };
+
+        let mut keys = Vec::new();
+        for doc in collection.find(None, Some(options))? {
+            let model: ExternalAccountKeyModel = from_bson(Bson::Document(doc?))?;
+            keys.push(from_value(model.value)?);
+        }
+        Ok(keys)
+    }
+
+    fn store_rotation_state(&self, state: RotationState) -> Result<(), StorageError> {
+        let rotation_state_doc = doc!("key": ROTATION_STATE_KEY);
+        let rotation_state_item = RotationStateModel::new(ROTATION_STATE_KEY.to_owned(), to_bson(&state)?);
+        self.rotation_state_store
+            .update_with_options(rotation_state_doc, rotation_state_item, true)?;
+        Ok(())
+    }
+
+    fn get_rotation_state(&self) -> Result<RotationState, StorageError> {
+        match self.rotation_state_store.get(doc!("key": ROTATION_STATE_KEY))? {
+            Some(model) => Ok(from_bson(model.value)?),
+            None => Ok(RotationState::default()),
+        }
+    }
+
+    fn store_key_usage(&self, entry: KeyUsageEntry) -> Result<(), StorageError> {
+        let key_usage_doc = doc!("key": entry.key_identifier.clone());
+        let key_usage_item = KeyUsageModel::new(entry.key_identifier.clone(), to_value(&entry)?);
+        self.key_usage_store
+            .update_with_options(key_usage_doc, key_usage_item, true)?;
+        Ok(())
+    }
+
+    fn get_key_usage(&self, key_identifier: &str) -> Result<Option<KeyUsageEntry>, StorageError> {
+        match self.key_usage_store.get(doc!("key": key_identifier))? {
+            Some(model) => Ok(Some(from_value(model.value)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl PrivateKeyLocker for MongoStorage {
+    fn get_key_by_addressing_hash(&self, hash: &str) -> Result<Vec<u8>, StorageError> {
+        let key = self
+            .key_store
+            .get(doc!("key": hash))?
+            .ok_or_else(|| MongoStorageError::NotFound {
+                description: "key not found".to_owned(),
+            })?;
+        match key.value {
+            Bson::Binary(BinarySubtype::Generic, bin) => Ok(bin),
+            unexpected => Err(MongoStorageError::Other {
+                description: format!("expected binary DB content but got {}", unexpected),
+            }
+            .into()),
+        }
+    }
 }
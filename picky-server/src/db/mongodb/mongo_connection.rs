@@ -1,3 +1,4 @@
+use crate::db::mongodb::MongoStorageError;
 use bson::{bson, doc};
 use mongodb::{
     common::{ReadMode, ReadPreference},
@@ -18,8 +19,8 @@ pub struct MongoConnection {
 }
 
 impl MongoConnection {
-    pub fn new(mongo_url: &str) -> Result<Self, String> {
-        let conn_str = connstring::parse(mongo_url).map_err(|e| format!("couldn't parse connection string: {}", e))?;
+    pub fn new(mongo_url: &str) -> Result<Self, MongoStorageError> {
+        let conn_str = connstring::parse(mongo_url).map_err(|source| MongoStorageError::ConnectionString { source })?;
 
         let mut client_options = match conn_str.options.as_ref().and_then(|options| options.options.get("ssl")) {
             Some(value) if value.eq("true") => ClientOptions::with_unauthenticated_ssl(None, false),
@@ -37,22 +38,22 @@ impl MongoConnection {
             .idle_timeout(Some(Duration::from_secs(CONNECTION_IDLE_TIMEOUT_SECS)))
             .connection_timeout(Duration::from_secs(DB_CONNECTION_TIMEOUT_SECS))
             .build(manager)
-            .map_err(|e| format!("couldn't create r2d2 connection pool: {}", e))?;
+            .map_err(|source| MongoStorageError::ConnectionPool { source })?;
 
         Ok(MongoConnection { pool })
     }
 
-    pub fn get(&self) -> Result<r2d2::PooledConnection<r2d2_mongo::MongoConnectionManager>, String> {
+    pub fn get(&self) -> Result<r2d2::PooledConnection<r2d2_mongo::MongoConnectionManager>, MongoStorageError> {
         self.pool
             .get()
-            .map_err(|e| format!("couldn't get mongo connection from r2d2: {}", e))
+            .map_err(|source| MongoStorageError::Connection { source })
     }
 
-    pub fn ping(&self) -> Result<(), String> {
+    pub fn ping(&self) -> Result<(), MongoStorageError> {
         let cmd = doc! { "ping": 1 };
         self.get()?
             .command(cmd, CommandType::Suppressed, None)
-            .map_err(|e| format!("couldn't ping: {}", e))?;
+            .map_err(|source| MongoStorageError::Ping { source })?;
         Ok(())
     }
 }
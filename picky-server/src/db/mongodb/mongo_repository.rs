@@ -1,6 +1,9 @@
 use crate::db::mongodb::{mongo_connection::MongoConnection, MongoStorageError};
 use bson::{from_bson, oid::ObjectId, to_bson, Bson, Document};
-use mongodb::{coll::options::ReplaceOptions, db::ThreadedDatabase};
+use mongodb::{
+    coll::options::{IndexOptions, ReplaceOptions},
+    db::ThreadedDatabase,
+};
 use serde::{Deserialize, Serialize};
 
 pub type NameModel = Model<String>;
@@ -23,6 +26,47 @@ pub type ConfigModel = Model<Bson>;
 pub type ConfigStoreRepository = MongoRepository<ConfigModel>;
 pub const CONFIG_COLLECTION_NAME: &str = "configuration";
 
+pub type SigningRequestModel = Model<Bson>;
+pub type SigningRequestStoreRepository = MongoRepository<SigningRequestModel>;
+pub const SIGNING_REQUEST_COLLECTION_NAME: &str = "signing_request_store";
+
+pub type ArtifactModel = Model<Bson>;
+pub type ArtifactStoreRepository = MongoRepository<ArtifactModel>;
+pub const CRL_COLLECTION_NAME: &str = "crl_store";
+pub const OCSP_COLLECTION_NAME: &str = "ocsp_store";
+
+pub type LatestArtifactModel = Model<String>;
+pub type LatestArtifactStoreRepository = MongoRepository<LatestArtifactModel>;
+pub const LATEST_ARTIFACT_COLLECTION_NAME: &str = "latest_artifact_store";
+
+pub type RevocationModel = Model<Bson>;
+pub type RevocationStoreRepository = MongoRepository<RevocationModel>;
+pub const REVOCATION_COLLECTION_NAME: &str = "revocation_store";
+
+pub type AuditModel = Model<Bson>;
+pub type AuditStoreRepository = MongoRepository<AuditModel>;
+pub const AUDIT_COLLECTION_NAME: &str = "audit_store";
+
+pub type ExternalAccountKeyModel = Model<Bson>;
+pub type ExternalAccountKeyStoreRepository = MongoRepository<ExternalAccountKeyModel>;
+pub const EXTERNAL_ACCOUNT_KEY_COLLECTION_NAME: &str = "eab_store";
+
+pub type RotationStateModel = Model<Bson>;
+pub type RotationStateStoreRepository = MongoRepository<RotationStateModel>;
+pub const ROTATION_STATE_COLLECTION_NAME: &str = "rotation_state_store";
+
+pub type KeyUsageModel = Model<Bson>;
+pub type KeyUsageStoreRepository = MongoRepository<KeyUsageModel>;
+pub const KEY_USAGE_COLLECTION_NAME: &str = "key_usage_store";
+
+pub type LabelsModel = Model<Bson>;
+pub type LabelsStoreRepository = MongoRepository<LabelsModel>;
+pub const LABELS_COLLECTION_NAME: &str = "label_store";
+
+pub type RequesterModel = Model<String>;
+pub type RequesterStoreRepository = MongoRepository<RequesterModel>;
+pub const REQUESTER_COLLECTION_NAME: &str = "requester_store";
+
 pub type HashLookupTableModel = Model<String>;
 pub type HashLookupTableStoreRepository = MongoRepository<HashLookupTableModel>;
 pub const HASH_LOOKUP_TABLE_COLLECTION_NAME: &str = "hash_lookup_table";
@@ -66,6 +110,27 @@ impl<Model: serde::ser::Serialize> MongoRepository<Model> {
         Ok(self.mongo_conn.get()?.collection(self.collection_name))
     }
 
+    /// Creates the index if it doesn't exist yet.
+    pub fn create_index(&self, keys: Document, unique: bool) -> Result<(), MongoStorageError> {
+        self.get_collection()?.create_index(
+            keys,
+            Some(IndexOptions {
+                unique: Some(unique),
+                ..IndexOptions::new()
+            }),
+        )?;
+        Ok(())
+    }
+
+    pub fn insert(&self, model: Model) -> Result<(), MongoStorageError> {
+        if let Bson::Document(document) = to_bson(&model)? {
+            self.get_collection()?.insert_one(document, None)?;
+            Ok(())
+        } else {
+            Err(MongoStorageError::InsertError)
+        }
+    }
+
     pub fn update_with_options(&self, doc: Document, model: Model, upsert: bool) -> Result<(), MongoStorageError> {
         let serialized_model = to_bson(&model)?;
 
@@ -87,6 +152,17 @@ impl<Model: serde::ser::Serialize> MongoRepository<Model> {
             Err(MongoStorageError::UpdateError)
         }
     }
+
+    /// Replaces the document matching `doc`, if any. Returns whether a document matched.
+    pub fn replace(&self, doc: Document, model: Model) -> Result<bool, MongoStorageError> {
+        if let Bson::Document(mut document) = to_bson(&model)? {
+            document.remove("_id");
+            let result = self.get_collection()?.replace_one(doc, document, None)?;
+            Ok(result.matched_count > 0)
+        } else {
+            Err(MongoStorageError::UpdateError)
+        }
+    }
 }
 
 impl<Model: serde::de::DeserializeOwned + serde::ser::Serialize> MongoRepository<Model> {
@@ -0,0 +1,308 @@
+//! Storage events.
+//!
+//! Subsystems reacting to saved data (e.g. CDN replication) register a `StorageObserver` instead of
+//! decorating the storage themselves. Observers are notified synchronously once the write succeeded,
+//! they must hand long-running work over to a background thread.
+
+use crate::{
+    addressing::{encode_to_canonical_address, ArtifactNamespace},
+    db::{
+        AuditRecord, CertificateEntry, ExternalAccountKey, KeyUsageEntry, PickyStorage, RevocationEntry, RotationState,
+        SigningRequestEntry, SigningRequestStatus, StorageCapabilities, StorageError,
+    },
+    labels::Labels,
+};
+use std::sync::Arc;
+
+/// Callbacks invoked after a successful write. `storage` is the observed storage, for observers
+/// needing to look up related entries.
+pub trait StorageObserver: Send + Sync {
+    /// A certificate was stored.
+    fn on_store(&self, _storage: &dyn PickyStorage, _entry: &CertificateEntry) {}
+
+    /// A certificate which wasn't stored yet was stored. Unlike `on_store`, this isn't called again when
+    /// the same certificate is stored twice (e.g. CA certificates provided by the settings, injected at
+    /// every startup).
+    fn on_insert(&self, _storage: &dyn PickyStorage, _entry: &CertificateEntry) {}
+
+    /// A revocation artifact was stored at `hash` and is now the latest one for `latest_key`.
+    fn on_store_artifact(
+        &self,
+        _storage: &dyn PickyStorage,
+        _namespace: ArtifactNamespace,
+        _latest_key: &str,
+        _hash: &str,
+        _artifact: &[u8],
+    ) {
+    }
+
+    /// A certificate was revoked.
+    fn on_revoke(&self, _storage: &dyn PickyStorage, _entry: &RevocationEntry) {}
+}
+
+/// Storage decorator notifying the registered observers of every successful write
+pub struct ObservedStorage {
+    inner: Arc<dyn PickyStorage>,
+    observers: Vec<Arc<dyn StorageObserver>>,
+}
+
+impl ObservedStorage {
+    pub fn new(inner: Arc<dyn PickyStorage>) -> Self {
+        Self {
+            inner,
+            observers: Vec::new(),
+        }
+    }
+
+    /// Observers are notified in registration order.
+    pub fn register(&mut self, observer: Arc<dyn StorageObserver>) {
+        self.observers.push(observer);
+    }
+
+    /// Returns the inner storage unchanged if no observer was registered.
+    pub fn into_storage(self) -> Arc<dyn PickyStorage> {
+        if self.observers.is_empty() {
+            self.inner
+        } else {
+            Arc::new(self)
+        }
+    }
+}
+
+impl PickyStorage for ObservedStorage {
+    fn health(&self) -> Result<(), StorageError> {
+        self.inner.health()
+    }
+
+    fn capabilities(&self) -> StorageCapabilities {
+        self.inner.capabilities()
+    }
+
+    fn is_leader(&self) -> Result<bool, StorageError> {
+        self.inner.is_leader()
+    }
+
+    fn store(&self, entry: CertificateEntry) -> Result<(), StorageError> {
+        // stored certificates are immutable: a certificate found at its address was stored before
+        let is_new = encode_to_canonical_address(&entry.cert)
+            .map(|hash| matches!(self.inner.get_cert_by_addressing_hash(&hash), Err(e) if e.is_not_found()))
+            .unwrap_or(false);
+
+        self.inner.store(entry.clone())?;
+        for observer in &self.observers {
+            observer.on_store(self.inner.as_ref(), &entry);
+            if is_new {
+                observer.on_insert(self.inner.as_ref(), &entry);
+            }
+        }
+        Ok(())
+    }
+
+    fn get_cert_by_addressing_hash(&self, hash: &str) -> Result<Vec<u8>, StorageError> {
+        self.inner.get_cert_by_addressing_hash(hash)
+    }
+
+    fn get_addressing_hash_by_name(&self, name: &str) -> Result<String, StorageError> {
+        self.inner.get_addressing_hash_by_name(name)
+    }
+
+    fn get_addressing_hash_by_key_identifier(&self, key_identifier: &str) -> Result<String, StorageError> {
+        self.inner.get_addressing_hash_by_key_identifier(key_identifier)
+    }
+
+    fn lookup_addressing_hash(&self, lookup_key: &str) -> Result<String, StorageError> {
+        self.inner.lookup_addressing_hash(lookup_key)
+    }
+
+    fn get_addressing_hashes_by_requester(&self, requested_by: &str) -> Result<Vec<String>, StorageError> {
+        self.inner.get_addressing_hashes_by_requester(requested_by)
+    }
+
+    fn get_labels(&self, hash: &str) -> Result<Labels, StorageError> {
+        self.inner.get_labels(hash)
+    }
+
+    fn get_addressing_hashes_by_labels(&self, selector: &Labels) -> Result<Vec<String>, StorageError> {
+        self.inner.get_addressing_hashes_by_labels(selector)
+    }
+
+    fn store_signing_request(&self, entry: SigningRequestEntry) -> Result<(), StorageError> {
+        self.inner.store_signing_request(entry)
+    }
+
+    fn get_signing_request(&self, id: &str) -> Result<SigningRequestEntry, StorageError> {
+        self.inner.get_signing_request(id)
+    }
+
+    fn get_signing_requests(&self) -> Result<Vec<SigningRequestEntry>, StorageError> {
+        self.inner.get_signing_requests()
+    }
+
+    fn update_signing_request_status(
+        &self,
+        id: &str,
+        expected: &SigningRequestStatus,
+        status: SigningRequestStatus,
+    ) -> Result<bool, StorageError> {
+        self.inner.update_signing_request_status(id, expected, status)
+    }
+
+    fn store_artifact(
+        &self,
+        namespace: ArtifactNamespace,
+        latest_key: &str,
+        artifact: Vec<u8>,
+    ) -> Result<String, StorageError> {
+        let hash = self.inner.store_artifact(namespace, latest_key, artifact.clone())?;
+        for observer in &self.observers {
+            observer.on_store_artifact(self.inner.as_ref(), namespace, latest_key, &hash, &artifact);
+        }
+        Ok(hash)
+    }
+
+    fn get_artifact_by_addressing_hash(
+        &self,
+        namespace: ArtifactNamespace,
+        hash: &str,
+    ) -> Result<Vec<u8>, StorageError> {
+        self.inner.get_artifact_by_addressing_hash(namespace, hash)
+    }
+
+    fn get_latest_artifact_addressing_hash(
+        &self,
+        namespace: ArtifactNamespace,
+        latest_key: &str,
+    ) -> Result<String, StorageError> {
+        self.inner.get_latest_artifact_addressing_hash(namespace, latest_key)
+    }
+
+    fn store_revocation(&self, entry: RevocationEntry) -> Result<(), StorageError> {
+        self.inner.store_revocation(entry.clone())?;
+        for observer in &self.observers {
+            observer.on_revoke(self.inner.as_ref(), &entry);
+        }
+        Ok(())
+    }
+
+    fn get_revocation(&self, issuer: &str, serial_number: &str) -> Result<Option<RevocationEntry>, StorageError> {
+        self.inner.get_revocation(issuer, serial_number)
+    }
+
+    fn revoked_since(&self, timestamp: u64) -> Result<Vec<RevocationEntry>, StorageError> {
+        self.inner.revoked_since(timestamp)
+    }
+
+    fn store_audit_record(&self, record: AuditRecord) -> Result<(), StorageError> {
+        self.inner.store_audit_record(record)
+    }
+
+    fn get_audit_head(&self) -> Result<Option<AuditRecord>, StorageError> {
+        self.inner.get_audit_head()
+    }
+
+    fn get_audit_records(&self, from_sequence: u64) -> Result<Vec<AuditRecord>, StorageError> {
+        self.inner.get_audit_records(from_sequence)
+    }
+
+    fn store_external_account_key(&self, key: ExternalAccountKey) -> Result<(), StorageError> {
+        self.inner.store_external_account_key(key)
+    }
+
+    fn get_external_account_key(&self, key_id: &str) -> Result<Option<ExternalAccountKey>, StorageError> {
+        self.inner.get_external_account_key(key_id)
+    }
+
+    fn get_external_account_keys(&self) -> Result<Vec<ExternalAccountKey>, StorageError> {
+        self.inner.get_external_account_keys()
+    }
+
+    fn store_rotation_state(&self, state: RotationState) -> Result<(), StorageError> {
+        self.inner.store_rotation_state(state)
+    }
+
+    fn get_rotation_state(&self) -> Result<RotationState, StorageError> {
+        self.inner.get_rotation_state()
+    }
+
+    fn store_key_usage(&self, entry: KeyUsageEntry) -> Result<(), StorageError> {
+        self.inner.store_key_usage(entry)
+    }
+
+    fn get_key_usage(&self, key_identifier: &str) -> Result<Option<KeyUsageEntry>, StorageError> {
+        self.inner.get_key_usage(key_identifier)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::memory::MemoryStorage;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl Recorder {
+        fn record(&self, event: String) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    impl StorageObserver for Recorder {
+        fn on_store(&self, storage: &dyn PickyStorage, entry: &CertificateEntry) {
+            let hash = storage.get_addressing_hash_by_name(&entry.name).unwrap();
+            self.record(format!("store {} at {}", entry.name, hash));
+        }
+
+        fn on_insert(&self, _: &dyn PickyStorage, entry: &CertificateEntry) {
+            self.record(format!("insert {}", entry.name));
+        }
+
+        fn on_revoke(&self, _: &dyn PickyStorage, entry: &RevocationEntry) {
+            self.record(format!("revoke {}", entry.serial_number));
+        }
+    }
+
+    #[test]
+    fn observers_notified_after_writes() {
+        let recorder = Arc::new(Recorder::default());
+        let mut observed = ObservedStorage::new(Arc::new(MemoryStorage::new()));
+        observed.register(recorder.clone());
+        let storage = observed.into_storage();
+
+        let entry = CertificateEntry {
+            name: "leaf".to_owned(),
+            cert: b"certificate".to_vec(),
+            key_identifier: "leaf".to_owned(),
+            key: None,
+            requested_by: None,
+            labels: Labels::new(),
+        };
+        storage.store(entry.clone()).unwrap();
+        storage.store(entry).unwrap();
+        storage
+            .store_revocation(RevocationEntry {
+                issuer: "ca".to_owned(),
+                serial_number: "0a".to_owned(),
+                revoked_at: 100,
+                reason: None,
+            })
+            .unwrap();
+        storage
+            .store_artifact(ArtifactNamespace::Crl, "ca", b"crl".to_vec())
+            .unwrap();
+
+        let hash = storage.get_addressing_hash_by_name("leaf").unwrap();
+        assert_eq!(
+            *recorder.events.lock().unwrap(),
+            vec![
+                format!("store leaf at {}", hash),
+                "insert leaf".to_owned(),
+                format!("store leaf at {}", hash),
+                "revoke 0a".to_owned()
+            ]
+        );
+    }
+}
@@ -0,0 +1,892 @@
+//! SQLite storage, for single-node deployments needing durable storage without a database server.
+//!
+//! Picky request handlers are synchronous, so connections are taken from an r2d2 pool just like
+//! the MongoDB backend does. The schema version is tracked using the `user_version` pragma.
+
+use crate::{
+    addressing::{encode_to_alternative_addresses, encode_to_canonical_address, ArtifactNamespace},
+    config::Config,
+    db::{
+        AuditRecord, CertificateEntry, ExternalAccountKey, KeyUsageEntry, PickyStorage, PrivateKeyLocker,
+        RevocationEntry, RotationState, SigningRequestEntry, SigningRequestStatus, StorageCapabilities, StorageError,
+        SCHEMA_LAST_VERSION,
+    },
+    labels::Labels,
+};
+use r2d2::{Pool, PooledConnection};
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, OptionalExtension, Row, TransactionBehavior, NO_PARAMS};
+use snafu::{ResultExt, Snafu};
+use std::time::Duration;
+
+const DB_CONNECTION_TIMEOUT_SECS: u64 = 15;
+const BUSY_TIMEOUT_MILLIS: u64 = 5000;
+
+const SCHEMA: &str = "
+CREATE TABLE certificates (
+    addressing_hash TEXT PRIMARY KEY NOT NULL,
+    cert BLOB NOT NULL,
+    requested_by TEXT
+);
+CREATE INDEX certificates_requested_by ON certificates (requested_by);
+
+CREATE TABLE certificate_names (
+    name TEXT PRIMARY KEY NOT NULL,
+    addressing_hash TEXT NOT NULL
+);
+CREATE INDEX certificate_names_addressing_hash ON certificate_names (addressing_hash);
+
+CREATE TABLE key_identifiers (
+    key_identifier TEXT PRIMARY KEY NOT NULL,
+    addressing_hash TEXT NOT NULL
+);
+
+CREATE TABLE hash_lookup (
+    lookup_key TEXT PRIMARY KEY NOT NULL,
+    addressing_hash TEXT NOT NULL
+);
+
+CREATE TABLE certificate_labels (
+    addressing_hash TEXT NOT NULL,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (addressing_hash, key)
+);
+CREATE INDEX certificate_labels_key_value ON certificate_labels (key, value);
+
+CREATE TABLE private_keys (
+    addressing_hash TEXT PRIMARY KEY NOT NULL,
+    key BLOB NOT NULL
+);
+
+CREATE TABLE signing_requests (
+    id TEXT PRIMARY KEY NOT NULL,
+    submitted_at INTEGER NOT NULL,
+    entry TEXT NOT NULL
+);
+CREATE INDEX signing_requests_submitted_at ON signing_requests (submitted_at);
+
+CREATE TABLE artifacts (
+    namespace TEXT NOT NULL,
+    addressing_hash TEXT NOT NULL,
+    artifact BLOB NOT NULL,
+    PRIMARY KEY (namespace, addressing_hash)
+);
+
+CREATE TABLE latest_artifacts (
+    namespace TEXT NOT NULL,
+    latest_key TEXT NOT NULL,
+    addressing_hash TEXT NOT NULL,
+    PRIMARY KEY (namespace, latest_key)
+);
+
+CREATE TABLE revocations (
+    issuer TEXT NOT NULL,
+    serial_number TEXT NOT NULL,
+    revoked_at INTEGER NOT NULL,
+    reason INTEGER,
+    PRIMARY KEY (issuer, serial_number)
+);
+CREATE INDEX revocations_revoked_at ON revocations (revoked_at);
+
+CREATE TABLE audit_records (
+    sequence INTEGER PRIMARY KEY NOT NULL,
+    timestamp INTEGER NOT NULL,
+    event TEXT NOT NULL,
+    detail TEXT NOT NULL,
+    previous_hash TEXT NOT NULL,
+    hash TEXT NOT NULL
+);
+
+CREATE TABLE external_account_keys (
+    key_id TEXT PRIMARY KEY NOT NULL,
+    hmac_key TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    account TEXT,
+    revoked INTEGER NOT NULL,
+    account_key TEXT
+);
+CREATE INDEX external_account_keys_created_at ON external_account_keys (created_at);
+
+CREATE TABLE rotation_state (
+    id INTEGER PRIMARY KEY NOT NULL CHECK (id = 0),
+    state TEXT NOT NULL
+);
+
+CREATE TABLE key_usage (
+    key_identifier TEXT PRIMARY KEY NOT NULL,
+    signatures INTEGER NOT NULL
+);
+";
+
+#[derive(Debug, Snafu)]
+pub enum SqliteStorageError {
+    #[snafu(display("sqlite error: {}", source))]
+    Sqlite { source: rusqlite::Error },
+
+    #[snafu(display("couldn't get sqlite connection from r2d2: {}", source))]
+    Connection { source: r2d2::Error },
+
+    #[snafu(display("json error: {}", source))]
+    Json { source: serde_json::Error },
+
+    #[snafu(display("{}", description))]
+    NotFound { description: String },
+
+    #[snafu(display("generic error: {}", description))]
+    Other { description: String },
+}
+
+impl From<String> for SqliteStorageError {
+    fn from(description: String) -> Self {
+        Self::Other { description }
+    }
+}
+
+pub struct SqliteStorage {
+    pool: Pool<SqliteConnectionManager>,
+}
+
+impl SqliteStorage {
+    pub fn new(config: &Config) -> Self {
+        if let Some(parent) = config.sqlite_path.parent() {
+            std::fs::create_dir_all(parent).expect("create sqlite database directory");
+        }
+
+        let manager = SqliteConnectionManager::file(&config.sqlite_path).with_init(|conn| {
+            conn.busy_timeout(Duration::from_millis(BUSY_TIMEOUT_MILLIS))?;
+            conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;")
+        });
+        let pool = Pool::builder()
+            .connection_timeout(Duration::from_secs(DB_CONNECTION_TIMEOUT_SECS))
+            .build(manager)
+            .expect("couldn't create sqlite connection pool");
+
+        let storage = Self { pool };
+        storage.init_schema().expect("couldn't initialize sqlite schema");
+        storage
+    }
+
+    fn init_schema(&self) -> Result<(), SqliteStorageError> {
+        let mut conn = self.conn()?;
+        let schema_version: i64 = conn
+            .query_row("PRAGMA user_version", NO_PARAMS, |row| row.get(0))
+            .context(Sqlite)?;
+
+        match schema_version {
+            0 => {
+                log::info!("fresh new database using v{} schema", SCHEMA_LAST_VERSION);
+                let tx = conn.transaction().context(Sqlite)?;
+                tx.execute_batch(SCHEMA).context(Sqlite)?;
+                tx.execute_batch(&format!("PRAGMA user_version = {}", SCHEMA_LAST_VERSION))
+                    .context(Sqlite)?;
+                tx.commit().context(Sqlite)
+            }
+            supported if supported == i64::from(SCHEMA_LAST_VERSION) => Ok(()),
+            unsupported => panic!("unsupported schema version: {}", unsupported),
+        }
+    }
+
+    fn conn(&self) -> Result<PooledConnection<SqliteConnectionManager>, SqliteStorageError> {
+        self.pool.get().context(Connection)
+    }
+
+    fn get_addressing_hash(&self, table: &str, column: &str, key: &str) -> Result<Option<String>, SqliteStorageError> {
+        self.conn()?
+            .query_row(
+                &format!("SELECT addressing_hash FROM {} WHERE {} = ?1", table, column),
+                params![key],
+                |row| row.get(0),
+            )
+            .optional()
+            .context(Sqlite)
+    }
+
+    fn get_addressing_hashes(&self, sql: &str, values: &[&str]) -> Result<Vec<String>, SqliteStorageError> {
+        let conn = self.conn()?;
+        let mut stmt = conn.prepare(sql).context(Sqlite)?;
+        let hashes = stmt
+            .query_map(values, |row| row.get(0))
+            .context(Sqlite)?
+            .collect::<Result<Vec<String>, _>>()
+            .context(Sqlite)?;
+        Ok(hashes)
+    }
+}
+
+fn revocation_from_row(row: &Row<'_>) -> rusqlite::Result<RevocationEntry> {
+    Ok(RevocationEntry {
+        issuer: row.get(0)?,
+        serial_number: row.get(1)?,
+        revoked_at: row.get::<_, i64>(2)? as u64,
+        reason: row.get(3)?,
+    })
+}
+
+fn audit_record_from_row(row: &Row<'_>) -> rusqlite::Result<AuditRecord> {
+    Ok(AuditRecord {
+        sequence: row.get::<_, i64>(0)? as u64,
+        timestamp: row.get::<_, i64>(1)? as u64,
+        event: row.get(2)?,
+        detail: row.get(3)?,
+        previous_hash: row.get(4)?,
+        hash: row.get(5)?,
+    })
+}
+
+fn external_account_key_from_row(row: &Row<'_>) -> rusqlite::Result<ExternalAccountKey> {
+    Ok(ExternalAccountKey {
+        key_id: row.get(0)?,
+        hmac_key: row.get(1)?,
+        created_at: row.get::<_, i64>(2)? as u64,
+        account: row.get(3)?,
+        revoked: row.get(4)?,
+        account_key: row.get(5)?,
+    })
+}
+
+const AUDIT_RECORD_COLUMNS: &str = "sequence, timestamp, event, detail, previous_hash, hash";
+const EXTERNAL_ACCOUNT_KEY_COLUMNS: &str = "key_id, hmac_key, created_at, account, revoked, account_key";
+
+impl PickyStorage for SqliteStorage {
+    fn health(&self) -> Result<(), StorageError> {
+        self.conn()?
+            .query_row("SELECT 1", NO_PARAMS, |_| Ok(()))
+            .context(Sqlite)?;
+        Ok(())
+    }
+
+    fn capabilities(&self) -> StorageCapabilities {
+        StorageCapabilities {
+            transactions: true,
+            revocation_records: true,
+            ..StorageCapabilities::default()
+        }
+    }
+
+    fn is_leader(&self) -> Result<bool, StorageError> {
+        // single node
+        Ok(true)
+    }
+
+    fn store(&self, entry: CertificateEntry) -> Result<(), StorageError> {
+        let addressing_hash = encode_to_canonical_address(&entry.cert).map_err(|e| SqliteStorageError::Other {
+            description: format!("couldn't hash certificate: {}", e),
+        })?;
+
+        let alternative_addresses =
+            encode_to_alternative_addresses(&entry.cert).map_err(|e| SqliteStorageError::Other {
+                description: format!("couldn't encode alternative addresses: {}", e),
+            })?;
+
+        let mut conn = self.conn()?;
+        let tx = conn.transaction().context(Sqlite)?;
+
+        tx.execute(
+            "INSERT OR REPLACE INTO certificates (addressing_hash, cert, requested_by) VALUES (?1, ?2, ?3)",
+            params![addressing_hash, entry.cert, entry.requested_by],
+        )
+        .context(Sqlite)?;
+        tx.execute(
+            "INSERT OR REPLACE INTO certificate_names (name, addressing_hash) VALUES (?1, ?2)",
+            params![entry.name, addressing_hash],
+        )
+        .context(Sqlite)?;
+        tx.execute(
+            "INSERT OR REPLACE INTO key_identifiers (key_identifier, addressing_hash) VALUES (?1, ?2)",
+            params![entry.key_identifier, addressing_hash],
+        )
+        .context(Sqlite)?;
+
+        for alternative_address in alternative_addresses {
+            tx.execute(
+                "INSERT OR REPLACE INTO hash_lookup (lookup_key, addressing_hash) VALUES (?1, ?2)",
+                params![alternative_address, addressing_hash],
+            )
+            .context(Sqlite)?;
+        }
+
+        if !entry.labels.is_empty() {
+            tx.execute(
+                "DELETE FROM certificate_labels WHERE addressing_hash = ?1",
+                params![addressing_hash],
+            )
+            .context(Sqlite)?;
+            for (key, value) in &entry.labels {
+                tx.execute(
+                    "INSERT INTO certificate_labels (addressing_hash, key, value) VALUES (?1, ?2, ?3)",
+                    params![addressing_hash, key, value],
+                )
+                .context(Sqlite)?;
+            }
+        }
+
+        if let Some(key) = entry.key {
+            tx.execute(
+                "INSERT OR REPLACE INTO private_keys (addressing_hash, key) VALUES (?1, ?2)",
+                params![addressing_hash, key],
+            )
+            .context(Sqlite)?;
+        }
+
+        tx.commit().context(Sqlite)?;
+        Ok(())
+    }
+
+    fn get_cert_by_addressing_hash(&self, hash: &str) -> Result<Vec<u8>, StorageError> {
+        Ok(self
+            .conn()?
+            .query_row(
+                "SELECT cert FROM certificates WHERE addressing_hash = ?1",
+                params![hash],
+                |row| row.get(0),
+            )
+            .optional()
+            .context(Sqlite)?
+            .ok_or_else(|| SqliteStorageError::NotFound {
+                description: "cert not found".to_owned(),
+            })?)
+    }
+
+    fn get_addressing_hash_by_name(&self, name: &str) -> Result<String, StorageError> {
+        Ok(self
+            .get_addressing_hash("certificate_names", "name", name)?
+            .ok_or_else(|| SqliteStorageError::NotFound {
+                description: format!("hash not found using name {}", name),
+            })?)
+    }
+
+    fn get_addressing_hash_by_key_identifier(&self, key_identifier: &str) -> Result<String, StorageError> {
+        Ok(self
+            .get_addressing_hash("key_identifiers", "key_identifier", key_identifier)?
+            .ok_or_else(|| SqliteStorageError::NotFound {
+                description: format!("addressing hash not found by key identifier \"{}\"", key_identifier),
+            })?)
+    }
+
+    fn lookup_addressing_hash(&self, lookup_key: &str) -> Result<String, StorageError> {
+        Ok(self
+            .get_addressing_hash("hash_lookup", "lookup_key", lookup_key)?
+            .ok_or_else(|| SqliteStorageError::NotFound {
+                description: format!("addressing hash not found using lookup key \"{}\"", lookup_key),
+            })?)
+    }
+
+    fn get_addressing_hashes_by_requester(&self, requested_by: &str) -> Result<Vec<String>, StorageError> {
+        Ok(self.get_addressing_hashes(
+            "SELECT addressing_hash FROM certificates WHERE requested_by = ?1 ORDER BY addressing_hash",
+            &[requested_by],
+        )?)
+    }
+
+    fn get_labels(&self, hash: &str) -> Result<Labels, StorageError> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare("SELECT key, value FROM certificate_labels WHERE addressing_hash = ?1")
+            .context(Sqlite)?;
+        let labels = stmt
+            .query_map(params![hash], |row| Ok((row.get(0)?, row.get(1)?)))
+            .context(Sqlite)?
+            .collect::<Result<Labels, _>>()
+            .context(Sqlite)?;
+        Ok(labels)
+    }
+
+    fn get_addressing_hashes_by_labels(&self, selector: &Labels) -> Result<Vec<String>, StorageError> {
+        if selector.is_empty() {
+            return Ok(self.get_addressing_hashes(
+                "SELECT DISTINCT addressing_hash FROM certificate_labels ORDER BY addressing_hash",
+                &[],
+            )?);
+        }
+
+        // a certificate matches when every label of the selector is found among its labels
+        let conditions = (0..selector.len())
+            .map(|i| format!("(key = ?{} AND value = ?{})", 2 * i + 1, 2 * i + 2))
+            .collect::<Vec<String>>()
+            .join(" OR ");
+        let sql = format!(
+            "SELECT addressing_hash FROM certificate_labels WHERE {} \
+             GROUP BY addressing_hash HAVING COUNT(*) = {} ORDER BY addressing_hash",
+            conditions,
+            selector.len()
+        );
+        let values = selector
+            .iter()
+            .flat_map(|(key, value)| vec![key.as_str(), value.as_str()])
+            .collect::<Vec<&str>>();
+
+        Ok(self.get_addressing_hashes(&sql, &values)?)
+    }
+
+    fn store_signing_request(&self, entry: SigningRequestEntry) -> Result<(), StorageError> {
+        let json = serde_json::to_string(&entry).context(Json)?;
+        self.conn()?
+            .execute(
+                "INSERT OR REPLACE INTO signing_requests (id, submitted_at, entry) VALUES (?1, ?2, ?3)",
+                params![entry.id, entry.submitted_at as i64, json],
+            )
+            .context(Sqlite)?;
+        Ok(())
+    }
+
+    fn get_signing_request(&self, id: &str) -> Result<SigningRequestEntry, StorageError> {
+        let json: String = self
+            .conn()?
+            .query_row("SELECT entry FROM signing_requests WHERE id = ?1", params![id], |row| {
+                row.get(0)
+            })
+            .optional()
+            .context(Sqlite)?
+            .ok_or_else(|| SqliteStorageError::NotFound {
+                description: format!("signing request {} not found", id),
+            })?;
+        Ok(serde_json::from_str(&json).context(Json)?)
+    }
+
+    fn update_signing_request_status(
+        &self,
+        id: &str,
+        expected: &SigningRequestStatus,
+        status: SigningRequestStatus,
+    ) -> Result<bool, StorageError> {
+        let mut conn = self.conn()?;
+        // takes the write lock right away, so that no other writer slips in between the read and the update
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .context(Sqlite)?;
+
+        let json: String = tx
+            .query_row("SELECT entry FROM signing_requests WHERE id = ?1", params![id], |row| {
+                row.get(0)
+            })
+            .optional()
+            .context(Sqlite)?
+            .ok_or_else(|| SqliteStorageError::NotFound {
+                description: format!("signing request {} not found", id),
+            })?;
+        let mut entry: SigningRequestEntry = serde_json::from_str(&json).context(Json)?;
+        if entry.status != *expected {
+            return Ok(false);
+        }
+
+        entry.status = status;
+        tx.execute(
+            "UPDATE signing_requests SET entry = ?1 WHERE id = ?2",
+            params![serde_json::to_string(&entry).context(Json)?, id],
+        )
+        .context(Sqlite)?;
+        tx.commit().context(Sqlite)?;
+
+        Ok(true)
+    }
+
+    fn get_signing_requests(&self) -> Result<Vec<SigningRequestEntry>, StorageError> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare("SELECT entry FROM signing_requests ORDER BY submitted_at")
+            .context(Sqlite)?;
+        let entries = stmt
+            .query_map(NO_PARAMS, |row| row.get::<_, String>(0))
+            .context(Sqlite)?
+            .collect::<Result<Vec<String>, _>>()
+            .context(Sqlite)?;
+
+        let mut signing_requests = Vec::with_capacity(entries.len());
+        for json in entries {
+            signing_requests.push(serde_json::from_str(&json).context(Json)?);
+        }
+        Ok(signing_requests)
+    }
+
+    fn store_artifact(
+        &self,
+        namespace: ArtifactNamespace,
+        latest_key: &str,
+        artifact: Vec<u8>,
+    ) -> Result<String, StorageError> {
+        let addressing_hash = encode_to_canonical_address(&artifact).map_err(|e| SqliteStorageError::Other {
+            description: format!("couldn't hash {} artifact: {}", namespace, e),
+        })?;
+        let namespace = namespace.to_string();
+
+        let mut conn = self.conn()?;
+        let tx = conn.transaction().context(Sqlite)?;
+        tx.execute(
+            "INSERT OR REPLACE INTO artifacts (namespace, addressing_hash, artifact) VALUES (?1, ?2, ?3)",
+            params![namespace, addressing_hash, artifact],
+        )
+        .context(Sqlite)?;
+        tx.execute(
+            "INSERT OR REPLACE INTO latest_artifacts (namespace, latest_key, addressing_hash) VALUES (?1, ?2, ?3)",
+            params![namespace, latest_key, addressing_hash],
+        )
+        .context(Sqlite)?;
+        tx.commit().context(Sqlite)?;
+
+        Ok(addressing_hash)
+    }
+
+    fn get_artifact_by_addressing_hash(
+        &self,
+        namespace: ArtifactNamespace,
+        hash: &str,
+    ) -> Result<Vec<u8>, StorageError> {
+        Ok(self
+            .conn()?
+            .query_row(
+                "SELECT artifact FROM artifacts WHERE namespace = ?1 AND addressing_hash = ?2",
+                params![namespace.to_string(), hash],
+                |row| row.get(0),
+            )
+            .optional()
+            .context(Sqlite)?
+            .ok_or_else(|| SqliteStorageError::NotFound {
+                description: format!("{} artifact not found", namespace),
+            })?)
+    }
+
+    fn get_latest_artifact_addressing_hash(
+        &self,
+        namespace: ArtifactNamespace,
+        latest_key: &str,
+    ) -> Result<String, StorageError> {
+        Ok(self
+            .conn()?
+            .query_row(
+                "SELECT addressing_hash FROM latest_artifacts WHERE namespace = ?1 AND latest_key = ?2",
+                params![namespace.to_string(), latest_key],
+                |row| row.get(0),
+            )
+            .optional()
+            .context(Sqlite)?
+            .ok_or_else(|| SqliteStorageError::Other {
+                description: format!("no {} artifact found for {}", namespace, latest_key),
+            })?)
+    }
+
+    fn store_revocation(&self, entry: RevocationEntry) -> Result<(), StorageError> {
+        self.conn()?
+            .execute(
+                "INSERT OR REPLACE INTO revocations (issuer, serial_number, revoked_at, reason) \
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![entry.issuer, entry.serial_number, entry.revoked_at as i64, entry.reason],
+            )
+            .context(Sqlite)?;
+        Ok(())
+    }
+
+    fn get_revocation(&self, issuer: &str, serial_number: &str) -> Result<Option<RevocationEntry>, StorageError> {
+        Ok(self
+            .conn()?
+            .query_row(
+                "SELECT issuer, serial_number, revoked_at, reason FROM revocations \
+                 WHERE issuer = ?1 AND serial_number = ?2",
+                params![issuer, serial_number],
+                revocation_from_row,
+            )
+            .optional()
+            .context(Sqlite)?)
+    }
+
+    fn revoked_since(&self, timestamp: u64) -> Result<Vec<RevocationEntry>, StorageError> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT issuer, serial_number, revoked_at, reason FROM revocations WHERE revoked_at >= ?1 \
+                 ORDER BY revoked_at",
+            )
+            .context(Sqlite)?;
+        let entries = stmt
+            .query_map(params![timestamp as i64], revocation_from_row)
+            .context(Sqlite)?
+            .collect::<Result<Vec<RevocationEntry>, _>>()
+            .context(Sqlite)?;
+        Ok(entries)
+    }
+
+    fn store_audit_record(&self, record: AuditRecord) -> Result<(), StorageError> {
+        self.conn()?
+            .execute(
+                &format!(
+                    "INSERT OR REPLACE INTO audit_records ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    AUDIT_RECORD_COLUMNS
+                ),
+                params![
+                    record.sequence as i64,
+                    record.timestamp as i64,
+                    record.event,
+                    record.detail,
+                    record.previous_hash,
+                    record.hash
+                ],
+            )
+            .context(Sqlite)?;
+        Ok(())
+    }
+
+    fn get_audit_head(&self) -> Result<Option<AuditRecord>, StorageError> {
+        Ok(self
+            .conn()?
+            .query_row(
+                &format!(
+                    "SELECT {} FROM audit_records ORDER BY sequence DESC LIMIT 1",
+                    AUDIT_RECORD_COLUMNS
+                ),
+                NO_PARAMS,
+                audit_record_from_row,
+            )
+            .optional()
+            .context(Sqlite)?)
+    }
+
+    fn get_audit_records(&self, from_sequence: u64) -> Result<Vec<AuditRecord>, StorageError> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {} FROM audit_records WHERE sequence >= ?1 ORDER BY sequence",
+                AUDIT_RECORD_COLUMNS
+            ))
+            .context(Sqlite)?;
+        let records = stmt
+            .query_map(params![from_sequence as i64], audit_record_from_row)
+            .context(Sqlite)?
+            .collect::<Result<Vec<AuditRecord>, _>>()
+            .context(Sqlite)?;
+        Ok(records)
+    }
+
+    fn store_external_account_key(&self, key: ExternalAccountKey) -> Result<(), StorageError> {
+        self.conn()?
+            .execute(
+                &format!(
+                    "INSERT OR REPLACE INTO external_account_keys ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    EXTERNAL_ACCOUNT_KEY_COLUMNS
+                ),
+                params![
+                    key.key_id,
+                    key.hmac_key,
+                    key.created_at as i64,
+                    key.account,
+                    key.revoked,
+                    key.account_key
+                ],
+            )
+            .context(Sqlite)?;
+        Ok(())
+    }
+
+    fn get_external_account_key(&self, key_id: &str) -> Result<Option<ExternalAccountKey>, StorageError> {
+        Ok(self
+            .conn()?
+            .query_row(
+                &format!(
+                    "SELECT {} FROM external_account_keys WHERE key_id = ?1",
+                    EXTERNAL_ACCOUNT_KEY_COLUMNS
+                ),
+                params![key_id],
+                external_account_key_from_row,
+            )
+            .optional()
+            .context(Sqlite)?)
+    }
+
+    fn get_external_account_keys(&self) -> Result<Vec<ExternalAccountKey>, StorageError> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {} FROM external_account_keys ORDER BY created_at",
+                EXTERNAL_ACCOUNT_KEY_COLUMNS
+            ))
+            .context(Sqlite)?;
+        let keys = stmt
+            .query_map(NO_PARAMS, external_account_key_from_row)
+            .context(Sqlite)?
+            .collect::<Result<Vec<ExternalAccountKey>, _>>()
+            .context(Sqlite)?;
+        Ok(keys)
+    }
+
+    fn store_rotation_state(&self, state: RotationState) -> Result<(), StorageError> {
+        let json = serde_json::to_string(&state).context(Json)?;
+        self.conn()?
+            .execute(
+                "INSERT OR REPLACE INTO rotation_state (id, state) VALUES (0, ?1)",
+                params![json],
+            )
+            .context(Sqlite)?;
+        Ok(())
+    }
+
+    fn get_rotation_state(&self) -> Result<RotationState, StorageError> {
+        let json: Option<String> = self
+            .conn()?
+            .query_row("SELECT state FROM rotation_state WHERE id = 0", NO_PARAMS, |row| {
+                row.get(0)
+            })
+            .optional()
+            .context(Sqlite)?;
+
+        match json {
+            Some(json) => Ok(serde_json::from_str(&json).context(Json)?),
+            None => Ok(RotationState::default()),
+        }
+    }
+
+    fn store_key_usage(&self, entry: KeyUsageEntry) -> Result<(), StorageError> {
+        self.conn()?
+            .execute(
+                "INSERT OR REPLACE INTO key_usage (key_identifier, signatures) VALUES (?1, ?2)",
+                params![entry.key_identifier, entry.signatures as i64],
+            )
+            .context(Sqlite)?;
+        Ok(())
+    }
+
+    fn get_key_usage(&self, key_identifier: &str) -> Result<Option<KeyUsageEntry>, StorageError> {
+        Ok(self
+            .conn()?
+            .query_row(
+                "SELECT key_identifier, signatures FROM key_usage WHERE key_identifier = ?1",
+                params![key_identifier],
+                |row| {
+                    Ok(KeyUsageEntry {
+                        key_identifier: row.get(0)?,
+                        signatures: row.get::<_, i64>(1)? as u64,
+                    })
+                },
+            )
+            .optional()
+            .context(Sqlite)?)
+    }
+}
+
+impl PrivateKeyLocker for SqliteStorage {
+    fn get_key_by_addressing_hash(&self, hash: &str) -> Result<Vec<u8>, StorageError> {
+        Ok(self
+            .conn()?
+            .query_row(
+                "SELECT key FROM private_keys WHERE addressing_hash = ?1",
+                params![hash],
+                |row| row.get(0),
+            )
+            .optional()
+            .context(Sqlite)?
+            .ok_or_else(|| SqliteStorageError::NotFound {
+                description: "key not found".to_owned(),
+            })?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TestDir;
+
+    fn storage(dir: &TestDir) -> SqliteStorage {
+        let mut config = Config::default();
+        config.sqlite_path = dir.join("picky.db");
+        SqliteStorage::new(&config)
+    }
+
+    #[test]
+    fn certificates_and_labels() {
+        let dir = TestDir::new("sqlite_certificates");
+        let storage = storage(&dir);
+        let mut labels = Labels::new();
+        labels.insert("team".to_owned(), "payments".to_owned());
+        labels.insert("env".to_owned(), "prod".to_owned());
+
+        storage
+            .store(CertificateEntry {
+                name: "ca".to_owned(),
+                cert: b"certificate".to_vec(),
+                key_identifier: "0a0b".to_owned(),
+                key: Some(b"private key".to_vec()),
+                requested_by: Some("token:ci".to_owned()),
+                labels: labels.clone(),
+            })
+            .unwrap();
+
+        let hash = storage.get_addressing_hash_by_name("ca").unwrap();
+        assert_eq!(storage.get_addressing_hash_by_key_identifier("0a0b").unwrap(), hash);
+        assert_eq!(storage.get_cert_by_addressing_hash(&hash).unwrap(), b"certificate");
+        assert_eq!(storage.get_key_by_addressing_hash(&hash).unwrap(), b"private key");
+        assert_eq!(
+            storage.get_addressing_hashes_by_requester("token:ci").unwrap(),
+            vec![hash.clone()]
+        );
+        assert_eq!(storage.get_labels(&hash).unwrap(), labels);
+
+        let mut selector = Labels::new();
+        selector.insert("team".to_owned(), "payments".to_owned());
+        assert_eq!(storage.get_addressing_hashes_by_labels(&selector).unwrap(), vec![hash]);
+        selector.insert("env".to_owned(), "dev".to_owned());
+        assert!(storage.get_addressing_hashes_by_labels(&selector).unwrap().is_empty());
+
+        let err = storage
+            .get_cert_by_addressing_hash("missing")
+            .err()
+            .expect("missing cert");
+        assert!(err.is_not_found());
+        assert_eq!(err.to_string(), "sqlite storage error: cert not found");
+    }
+
+    #[test]
+    fn revocation_and_audit_queries() {
+        let dir = TestDir::new("sqlite_revocations");
+        let storage = storage(&dir);
+        for (issuer, serial_number, revoked_at) in
+            [("ca", "0a", 300), ("ca", "0b", 100), ("other-ca", "0c", 200)].iter()
+        {
+            storage
+                .store_revocation(RevocationEntry {
+                    issuer: (*issuer).to_owned(),
+                    serial_number: (*serial_number).to_owned(),
+                    revoked_at: *revoked_at,
+                    reason: Some(1),
+                })
+                .unwrap();
+        }
+
+        assert_eq!(
+            storage
+                .get_revocation("ca", "0b")
+                .unwrap()
+                .map(|entry| entry.revoked_at),
+            Some(100)
+        );
+        assert_eq!(storage.get_revocation("ca", "0d").unwrap(), None);
+        assert_eq!(storage.get_revocation("ca", "0c").unwrap(), None);
+        let serials = storage
+            .revoked_since(200)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.serial_number)
+            .collect::<Vec<String>>();
+        assert_eq!(serials, vec!["0c", "0a"]);
+
+        assert_eq!(storage.get_audit_head().unwrap(), None);
+        for sequence in 0..3 {
+            storage
+                .store_audit_record(AuditRecord {
+                    sequence,
+                    timestamp: 1000 + sequence,
+                    event: "issued".to_owned(),
+                    detail: "{}".to_owned(),
+                    previous_hash: String::new(),
+                    hash: format!("hash{}", sequence),
+                })
+                .unwrap();
+        }
+        assert_eq!(storage.get_audit_head().unwrap().map(|record| record.sequence), Some(2));
+        assert_eq!(storage.get_audit_records(1).unwrap().len(), 2);
+
+        assert_eq!(storage.get_rotation_state().unwrap(), RotationState::default());
+        let state = RotationState {
+            intermediates: vec!["hash".to_owned()],
+        };
+        storage.store_rotation_state(state.clone()).unwrap();
+        assert_eq!(storage.get_rotation_state().unwrap(), state);
+    }
+}
@@ -0,0 +1,104 @@
+//! !!! TESTING PURPOSE ONLY !!!
+//!
+//! Seeded generation mode making certificates issued by `Picky::generate_*` byte-stable across runs,
+//! so that generated certificates can be compared byte for byte. Serial numbers, private keys and validity periods become
+//! entirely predictable: this feature must never be enabled in production builds.
+
+use chrono::{DateTime, Utc};
+use rand::{CryptoRng, Error, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use std::cell::RefCell;
+
+/// Seeded random number generator. **Not** suitable for production use.
+pub struct DeterministicRng(ChaCha20Rng);
+
+impl DeterministicRng {
+    pub fn from_seed(seed: u64) -> Self {
+        Self(ChaCha20Rng::seed_from_u64(seed))
+    }
+}
+
+impl RngCore for DeterministicRng {
+    fn next_u32(&mut self) -> u32 {
+        self.0.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.0.try_fill_bytes(dest)
+    }
+}
+
+// Only pretends to be cryptographically secure so it can be used for key generation in tests.
+impl CryptoRng for DeterministicRng {}
+
+struct DeterministicState {
+    rng: DeterministicRng,
+    now: DateTime<Utc>,
+}
+
+thread_local! {
+    static STATE: RefCell<Option<DeterministicState>> = RefCell::new(None);
+}
+
+/// Enables seeded generation for the current thread: `now` is used as the issuance date.
+#[cfg_attr(not(test), allow(dead_code))] // only enabled by tests
+pub fn enable(seed: u64, now: DateTime<Utc>) {
+    log::warn!("DETERMINISTIC GENERATION ENABLED. DON'T USE THIS BUILD IN PRODUCTION.");
+    STATE.with(|state| {
+        *state.borrow_mut() = Some(DeterministicState {
+            rng: DeterministicRng::from_seed(seed),
+            now,
+        })
+    });
+}
+
+/// Restores regular generation for the current thread.
+#[cfg_attr(not(test), allow(dead_code))] // only enabled by tests
+pub fn disable() {
+    STATE.with(|state| *state.borrow_mut() = None);
+}
+
+pub(crate) fn now() -> Option<DateTime<Utc>> {
+    STATE.with(|state| state.borrow().as_ref().map(|state| state.now))
+}
+
+pub(crate) fn with_rng<F, T>(f: F) -> Option<T>
+where
+    F: FnOnce(&mut DeterministicRng) -> T,
+{
+    STATE.with(|state| state.borrow_mut().as_mut().map(|state| f(&mut state.rng)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_output() {
+        let mut a = DeterministicRng::from_seed(42);
+        let mut b = DeterministicRng::from_seed(42);
+        assert_eq!(a.next_u64(), b.next_u64());
+
+        let mut c = DeterministicRng::from_seed(43);
+        assert_ne!(a.next_u64(), c.next_u64());
+    }
+
+    #[test]
+    fn thread_local_mode() {
+        assert!(now().is_none());
+        let date = Utc::now();
+        enable(1, date);
+        assert_eq!(now(), Some(date));
+        assert!(with_rng(|rng| rng.next_u32()).is_some());
+        disable();
+        assert!(with_rng(|rng| rng.next_u32()).is_none());
+    }
+}
@@ -0,0 +1,121 @@
+//! Validation of the stored CA hierarchy.
+//!
+//! The root and intermediate CA certificates are checked on startup and on configuration reload,
+//! so that a broken hierarchy is reported once instead of failing every issuance request.
+
+use crate::{
+    cert_cache,
+    config::Config,
+    db::PickyStorage,
+    signer::{CaSigner, SignerError},
+};
+use picky::{
+    signature::SignatureHashType,
+    x509::{date::UTCDate, Cert},
+};
+use serde::Serialize;
+use std::{iter, sync::Arc};
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct HierarchyReport {
+    pub valid: bool,
+    pub problems: Vec<String>,
+}
+
+/// Checks that:
+/// - root and intermediate CA certificates are currently valid and use a supported signature algorithm,
+/// - the intermediate CA verifies under the root CA and doesn't outlive it,
+/// - stored private keys match their certificate (the root CA key may be missing, e.g. when offline).
+pub fn check(config: &Config, storage: &dyn PickyStorage, signer: &CaSigner) -> HierarchyReport {
+    let now = UTCDate::now();
+    let mut problems = Vec::new();
+
+    let root = check_ca(
+        &format!("{} Root CA", config.realm),
+        false,
+        storage,
+        signer,
+        &now,
+        &mut problems,
+    );
+    let intermediate = check_ca(
+        &format!("{} Authority", config.realm),
+        true,
+        storage,
+        signer,
+        &now,
+        &mut problems,
+    );
+
+    // chain checks would only repeat problems found on the certificates themselves
+    if let (Some(root), Some(intermediate), true) = (root, intermediate, problems.is_empty()) {
+        if let Err(e) = root.verify_chain(iter::once(root.as_ref()), &now) {
+            problems.push(format!("root CA isn't a valid self-signed certificate: {}", e));
+        }
+        if let Err(e) = intermediate.verify_chain(iter::once(root.as_ref()), &now) {
+            problems.push(format!("intermediate CA doesn't verify under root CA: {}", e));
+        }
+        if intermediate.valid_not_after() > root.valid_not_after() {
+            problems.push(format!(
+                "intermediate CA expires after root CA ({} > {})",
+                intermediate.valid_not_after(),
+                root.valid_not_after()
+            ));
+        }
+    }
+
+    HierarchyReport {
+        valid: problems.is_empty(),
+        problems,
+    }
+}
+
+fn check_ca(
+    name: &str,
+    key_required: bool,
+    storage: &dyn PickyStorage,
+    signer: &CaSigner,
+    now: &UTCDate,
+    problems: &mut Vec<String>,
+) -> Option<Arc<Cert>> {
+    let hash = match storage.get_addressing_hash_by_name(name) {
+        Ok(hash) => hash,
+        Err(e) => {
+            problems.push(format!("couldn't fetch {} certificate: {}", name, e));
+            return None;
+        }
+    };
+    let cert = match storage
+        .get_cert_by_addressing_hash(&hash)
+        .map_err(|e| e.to_string())
+        .and_then(|der| cert_cache::parse(&der).map_err(|e| e.to_string()))
+    {
+        Ok(cert) => cert,
+        Err(e) => {
+            problems.push(format!("couldn't load {} certificate: {}", name, e));
+            return None;
+        }
+    };
+
+    if cert.valid_not_before() >= cert.valid_not_after() {
+        problems.push(format!("{} certificate has an empty validity period", name));
+    } else if let Err(e) = cert.verify(now) {
+        problems.push(format!("{} certificate is not valid: {}", name, e));
+    }
+
+    if let Err(e) = SignatureHashType::from_algorithm_identifier(cert.signature_algorithm()) {
+        problems.push(format!(
+            "{} certificate signature algorithm is unsupported: {}",
+            name, e
+        ));
+    }
+
+    match signer.key_matches(&hash, cert.public_key()) {
+        Ok(true) => {}
+        Ok(false) => problems.push(format!("{} private key doesn't match its certificate", name)),
+        Err(SignerError::KeyUnavailable { .. }) if !key_required => {}
+        Err(e) => problems.push(format!("{}: {}", name, e)),
+    }
+
+    Some(cert)
+}
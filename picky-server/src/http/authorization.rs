@@ -1,21 +1,68 @@
-use crate::{
-    config::Config,
-    utils::{unix_epoch, PathOr},
-};
+use crate::{alt_names::AltNames, config::Config, labels::Labels, utils::PathOr};
 use picky::{
-    jose::jwt::{Jwt, JwtDate, JwtValidator},
-    key::PublicKey,
-    pem::Pem,
+    jose::jwt::{Jwt, JwtDate, JwtError, JwtValidator},
+    key::{KeyError, PublicKey},
+    pem::{Pem, PemError},
 };
 use saphir::{header, SyncRequest};
 use serde::{Deserialize, Serialize};
-use std::borrow::Cow;
+use sha2::{Digest, Sha256};
+use snafu::Snafu;
+use std::{borrow::Cow, io};
+
+#[derive(Debug, Snafu)]
+pub enum AuthorizationError {
+    /// request has no Authorization header
+    #[snafu(display("Authorization header is missing"))]
+    MissingHeader,
+
+    /// Authorization header isn't visible ASCII
+    #[snafu(display("Authorization header can't be converted in string"))]
+    InvalidHeader,
+
+    /// Authorization header isn't a method followed by credentials
+    #[snafu(display("Authorization header wrong format: {}", header))]
+    MalformedHeader { header: String },
+
+    /// authorization method isn't Bearer
+    #[snafu(display("Unknown authorization method: {}", method))]
+    UnknownMethod { method: String },
+
+    /// bearer token is neither the API key nor a valid provisioner token
+    #[snafu(display("couldn't validate json web token: {}", source))]
+    InvalidToken { source: JwtError },
+
+    /// no provisioner public key is configured
+    #[snafu(display("provisioner public key is missing"))]
+    MissingProvisionerKey,
+
+    /// provisioner public key file couldn't be read
+    #[snafu(display("couldn't read provisioner public key: {}", source))]
+    ReadProvisionerKey { source: io::Error },
+
+    /// provisioner public key file isn't PEM
+    #[snafu(display("couldn't parse provisioner public key pem: {}", source))]
+    InvalidProvisionerKeyPem { source: PemError },
+
+    /// provisioner public key couldn't be decoded
+    #[snafu(display("couldn't parse provisioner public key: {}", source))]
+    InvalidProvisionerKey { source: KeyError },
+}
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct CsrClaims {
     pub sub: String,
     pub nbf: u64,
     pub exp: u64,
+    /// Labels attached to the issued certificate
+    #[serde(default)]
+    pub labels: Labels,
+    /// Subject alternative names (`dns_names`, `ip_addresses`, `uris`) the issued certificate may carry
+    #[serde(flatten)]
+    pub alt_names: AltNames,
+    /// Certificate profile the issued certificate must be of, see `profiles`
+    #[serde(default)]
+    pub profile: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -24,10 +71,18 @@ pub enum AuthorizationMethod {
     Unknown,
 }
 
+/// Identity recorded for certificates requested with the API key
+pub const API_KEY_REQUESTER: &str = "api-key";
+
+/// Identity recorded for certificates requested with a bearer token of the given subject
+pub fn token_requester(subject: &str) -> String {
+    format!("token:{}", subject)
+}
+
 #[derive(Debug, Clone)]
 pub enum Authorized {
     ApiKey,
-    Token(Jwt<'static, serde_json::Value>),
+    Token(Jwt<'static, CsrClaims>),
 }
 
 impl From<&str> for AuthorizationMethod {
@@ -40,71 +95,92 @@ impl From<&str> for AuthorizationMethod {
     }
 }
 
-pub fn check_authorization(config: &Config, req: &SyncRequest) -> Result<Authorized, String> {
+pub fn check_authorization(config: &Config, req: &SyncRequest) -> Result<Authorized, AuthorizationError> {
     let header = match req.headers_map().get(header::AUTHORIZATION) {
         Some(h) => h,
-        None => return Err("Authorization header is missing".to_owned()),
+        None => return Err(AuthorizationError::MissingHeader),
     };
 
     let auth_str = match header.to_str() {
         Ok(s) => s,
-        Err(_e) => return Err("Authorization header can't be converted in string".to_owned()),
+        Err(_e) => return Err(AuthorizationError::InvalidHeader),
     };
 
     let auth_vec = auth_str.split(' ').collect::<Vec<&str>>();
     if auth_vec.len() < 2 {
-        return Err(format!("Authorization header wrong format: {}", auth_str));
+        return Err(AuthorizationError::MalformedHeader {
+            header: auth_str.to_owned(),
+        });
     }
     let method = AuthorizationMethod::from(auth_vec[0]);
     match method {
         AuthorizationMethod::Bearer => {
             let token = auth_vec[1];
-            if token == config.api_key {
+            if is_api_key(config, token) {
                 return Ok(Authorized::ApiKey);
             }
 
-            // try JWT
-            let public_key = match config
-                .provisioner_public_key
-                .as_ref()
-                .ok_or_else(|| "provisioner public key is missing".to_owned())?
-            {
-                PathOr::Path(path) => {
-                    let pem_str = std::fs::read_to_string(path)
-                        .map_err(|e| format!("couldn't read provisioner public key: {}", e))?;
-                    let pem = pem_str
-                        .parse::<Pem>()
-                        .map_err(|e| format!("couldn't parse provisioner public key pem: {}", e))?;
-                    Cow::Owned(
-                        PublicKey::from_pem(&pem)
-                            .map_err(|e| format!("couldn't parse provisioner public key: {}", e))?,
-                    )
-                }
-                PathOr::Some(key) => Cow::Borrowed(key),
-            };
+            // try JWT, claims are decoded straight into `CsrClaims` which requires `sub`
+            let public_key = provisioner_public_key(config)?;
 
             Ok(Authorized::Token(
                 Jwt::decode(
                     auth_vec[1],
-                    &JwtValidator::strict(&public_key, &JwtDate::new_with_leeway(unix_epoch() as i64, 10)),
+                    &JwtValidator::strict(&public_key, &JwtDate::now_with_leeway(10)),
                 )
-                .map_err(|e| format!("couldn't validate json web token: {}", e))?,
+                .map_err(|source| AuthorizationError::InvalidToken { source })?,
             ))
         }
-        AuthorizationMethod::Unknown => Err(format!("Unknown authorization method: {}", auth_vec[0])),
+        AuthorizationMethod::Unknown => Err(AuthorizationError::UnknownMethod {
+            method: auth_vec[0].to_owned(),
+        }),
+    }
+}
+
+/// Public key used to validate bearer tokens issued by the provisioner.
+pub fn provisioner_public_key(config: &Config) -> Result<Cow<PublicKey>, AuthorizationError> {
+    match config
+        .provisioner_public_key
+        .as_ref()
+        .ok_or(AuthorizationError::MissingProvisionerKey)?
+    {
+        PathOr::Path(path) => {
+            let pem_str =
+                std::fs::read_to_string(path).map_err(|source| AuthorizationError::ReadProvisionerKey { source })?;
+            let pem = pem_str
+                .parse::<Pem>()
+                .map_err(|source| AuthorizationError::InvalidProvisionerKeyPem { source })?;
+            Ok(Cow::Owned(PublicKey::from_pem(&pem).map_err(|source| {
+                AuthorizationError::InvalidProvisionerKey { source }
+            })?))
+        }
+        PathOr::Some(key) => Ok(Cow::Borrowed(key)),
     }
 }
 
+/// Compares digests so the time taken doesn't depend on how much of the key the token matches.
+fn is_api_key(config: &Config, token: &str) -> bool {
+    if config.api_key.is_empty() {
+        return false;
+    }
+
+    let expected = Sha256::digest(config.api_key.as_bytes());
+    let actual = Sha256::digest(token.as_bytes());
+    expected.iter().zip(actual.iter()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{config::BackendType, utils::unix_epoch};
     use http::{request, Method};
     use picky::{
+        clock::{self, FixedClock},
         key::{PrivateKey, PublicKey},
         pem::Pem,
         signature::SignatureHashType,
     };
+    use std::sync::Arc;
 
     fn get_private_key_1() -> PrivateKey {
         let pem = include_str!("../../../test_assets/private_keys/rsa-2048-pk_4.key")
@@ -125,6 +201,9 @@ mod tests {
             sub: "CoolSubject".to_owned(),
             nbf: unix_epoch(),
             exp: unix_epoch() + 10,
+            labels: Labels::new(),
+            alt_names: AltNames::default(),
+            profile: None,
         };
         let jwt = Jwt::new(SignatureHashType::RsaSha256, claims);
         jwt.encode(&private_key).expect("jwt encode")
@@ -156,19 +235,33 @@ mod tests {
         let saphir_req = build_saphir_req(&token);
         let config = config(Some(key.to_public_key()));
         match check_authorization(&config, &saphir_req).expect("auth") {
-            Authorized::Token(_) => {}
+            Authorized::Token(jwt) => assert_eq!(jwt.view_claims().sub, "CoolSubject"),
             unexpected => panic!("expected token, got {:?}", unexpected),
         }
     }
 
+    #[test]
+    fn token_unauthorized_missing_subject() {
+        let key = get_private_key_1();
+        let claims = serde_json::json!({ "nbf": unix_epoch(), "exp": unix_epoch() + 10 });
+        let token = Jwt::new(SignatureHashType::RsaSha256, claims)
+            .encode(&key)
+            .expect("jwt encode");
+        let saphir_req = build_saphir_req(&token);
+        let config = config(Some(key.to_public_key()));
+        let err = check_authorization(&config, &saphir_req).err().expect("auth err");
+        assert!(matches!(err, AuthorizationError::InvalidToken { .. }));
+    }
+
     #[test]
     fn token_unauthorized_bad_signature() {
         let token = get_csr_token(&get_private_key_1());
         let saphir_req = build_saphir_req(&token);
         let config = config(Some(get_private_key_2().to_public_key()));
         let err = check_authorization(&config, &saphir_req).err().expect("auth err");
+        assert!(matches!(err, AuthorizationError::InvalidToken { .. }));
         assert_eq!(
-            err,
+            err.to_string(),
             "couldn\'t validate json web token: signature error: invalid signature"
         );
     }
@@ -179,6 +272,46 @@ mod tests {
         let saphir_req = build_saphir_req(&token);
         let config = config(None);
         let err = check_authorization(&config, &saphir_req).err().expect("auth err");
-        assert_eq!(err, "provisioner public key is missing");
+        assert!(matches!(err, AuthorizationError::MissingProvisionerKey));
+    }
+
+    #[test]
+    fn token_unauthorized_expired() {
+        let key = get_private_key_1();
+        let token = get_csr_token(&key);
+        let saphir_req = build_saphir_req(&token);
+        let config = config(Some(key.to_public_key()));
+
+        let clock = Arc::new(FixedClock::new(unix_epoch() as i64 + 60));
+        let err = clock::with_clock(clock, || check_authorization(&config, &saphir_req))
+            .err()
+            .expect("auth err");
+        assert!(
+            err.to_string()
+                .starts_with("couldn't validate json web token: token expired"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn api_key_authorized() {
+        let mut config = config(None);
+        config.api_key = "s3cr3t".to_owned();
+
+        match check_authorization(&config, &build_saphir_req("s3cr3t")).expect("auth") {
+            Authorized::ApiKey => {}
+            unexpected => panic!("expected api key, got {:?}", unexpected),
+        }
+        assert!(check_authorization(&config, &build_saphir_req("s3cr3u")).is_err());
+    }
+
+    #[test]
+    fn empty_api_key_never_matches() {
+        let config = config(None);
+        let err = check_authorization(&config, &build_saphir_req(""))
+            .err()
+            .expect("auth err");
+        assert!(matches!(err, AuthorizationError::MissingProvisionerKey));
     }
 }
@@ -0,0 +1,56 @@
+use crate::http::utils::SyncRequestUtil;
+use saphir::{header, StatusCode, SyncRequest, SyncResponse};
+
+/// Content-addressed resources never change
+pub const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+/// The default chain changes when the intermediate CA is rotated
+pub const CHAIN_CACHE_CONTROL: &str = "public, max-age=300";
+
+/// Strong entity tag of a content-addressed resource served as `representation` (e.g. "pem" or "der").
+pub fn entity_tag(address: &str, representation: &str) -> String {
+    format!("\"{}.{}\"", address, representation)
+}
+
+/// Writes caching headers for a content-addressed resource and answers with `304 Not Modified`
+/// when the client already holds it.
+///
+/// Returns `true` if the response is complete.
+pub fn write_immutable_headers(req: &SyncRequest, res: &mut SyncResponse, etag: &str) -> bool {
+    res.header(header::CACHE_CONTROL, IMMUTABLE_CACHE_CONTROL);
+    res.header(header::ETAG, etag.to_owned());
+
+    if if_none_match(req.get_header_string_value("If-None-Match").as_deref(), etag) {
+        res.status(StatusCode::NOT_MODIFIED);
+        true
+    } else {
+        false
+    }
+}
+
+/// https://tools.ietf.org/html/rfc7232#section-3.2 (weak comparison)
+fn if_none_match(header_value: Option<&str>, etag: &str) -> bool {
+    header_value.map_or(false, |value| {
+        value
+            .split(',')
+            .map(str::trim)
+            .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn if_none_match_weak_comparison() {
+        let etag = entity_tag("uEiCcvAfD", "pem");
+        assert_eq!(etag, "\"uEiCcvAfD.pem\"");
+
+        assert!(!if_none_match(None, &etag));
+        assert!(if_none_match(Some("\"uEiCcvAfD.pem\""), &etag));
+        assert!(if_none_match(Some("W/\"uEiCcvAfD.pem\""), &etag));
+        assert!(if_none_match(Some("\"other\", \"uEiCcvAfD.pem\""), &etag));
+        assert!(if_none_match(Some("*"), &etag));
+        assert!(!if_none_match(Some("\"uEiCcvAfD.der\""), &etag));
+    }
+}
@@ -1,32 +1,97 @@
 use crate::{
-    addressing::{convert_to_canonical_base, CANONICAL_HASH},
-    config::{CertKeyPair, Config},
-    db::{get_storage, BoxedPickyStorage, CertificateEntry, PickyStorage},
+    acme::{
+        account::{self, AcmeErrorType, AcmeProblem, JwkRequest, KidRequest, NonceStore, REPLAY_NONCE_HEADER},
+        eab,
+        order::{self, OrderStore, Status},
+    },
+    addressing::{
+        convert_to_canonical_base, encode_to_addresses, encode_to_canonical_address, ArtifactNamespace, CANONICAL_HASH,
+    },
+    alt_names::AltNames,
+    audit::{self, AuditEvent},
+    cert_cache,
+    config::{CertKeyPair, Config, KeyParameters},
+    crl::{self, spawn_crl_refresher},
+    ct_monitor::spawn_ct_monitor,
+    db::{
+        get_storage, CertificateEntry, PickyStorage, RevocationEntry, RotationState, SigningRequestEntry,
+        SigningRequestStatus,
+    },
+    hierarchy::{self, HierarchyReport},
     http::{
-        authorization::{check_authorization, Authorized, CsrClaims},
-        utils::SyncRequestUtil,
+        authorization::{check_authorization, provisioner_public_key, token_requester, Authorized, API_KEY_REQUESTER},
+        caching::{entity_tag, write_immutable_headers, CHAIN_CACHE_CONTROL},
+        error::{CaSetupError, ServerError, StartupError},
+        problem::{
+            new_request_id, write_problem, write_problem_with_extensions, ErrorCode, PROBLEM_JSON_CONTENT_TYPE,
+            REQUEST_ID_HEADER,
+        },
+        request_log::RequestLogEntry,
+        response_signing::{ResponseSigner, ResponseSigningError, JWS_SIGNATURE_HEADER},
+        utils::{forwarded_base_url, percent_decode, public_path_prefix, SyncRequestUtil},
+        versioning,
     },
+    issuance_log,
+    key_usage::{self, KeyUsageReport},
+    labels::{self, Labels},
+    lint,
     logging::build_logger_config,
-    picky_controller::Picky,
-    utils::{GreedyError, PathOr},
+    notifier::{spawn_ca_expiry_watcher, to_chrono},
+    ocsp,
+    picky_controller::{self, IssuerOptions, LeafUrls, Picky, SerialNumber},
+    profiles,
+    random::{set_random_source, DeviceRng},
+    self_test::{self, SelfTestReport},
+    signer::CaSigner,
+    spool::{self, spawn_spool_flusher},
+    timings::{self, Phase},
+    utils::{unix_epoch, PathOr},
 };
 use log4rs::Handle;
 use picky::{
+    cms::SignedData,
+    jose::jwk::{Jwk, JwkKeyOps, JwkPubKeyUse, JwkSet},
+    key::PrivateKey,
     pem::{parse_pem, to_pem, Pem},
-    x509::{Cert, Csr},
+    signature::SignatureHashType,
+    x509::{
+        date::UTCDate,
+        extension::CrlReason,
+        name::DirectoryName,
+        ocsp::{OcspResponse, OcspResponseStatus},
+        pkcs12::Pkcs12,
+        Cert, Csr,
+    },
 };
-use saphir::{Controller, ControllerDispatch, Method, StatusCode, SyncRequest, SyncResponse};
-use serde_json::{self, Value};
+use rand::Rng;
+use saphir::{
+    header::{self, HeaderValue},
+    Controller, ControllerDispatch, Method, StatusCode, SyncRequest, SyncResponse,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{self, json, Value};
+use snafu::Snafu;
 use std::{
     borrow::Cow,
-    fmt,
-    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+    collections::BTreeMap,
+    fmt, iter,
+    net::IpAddr,
+    path::Path,
+    sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
 };
 
 struct ControllerData {
-    storage: BoxedPickyStorage,
-    config: RwLock<Config>,
+    storage: Arc<dyn PickyStorage>,
+    /// Signs on behalf of the CAs, their private keys are out of reach of the handlers
+    signer: Arc<CaSigner>,
+    config: Arc<RwLock<Config>>,
     log_handle: Handle,
+    self_test: Option<SelfTestReport>,
+    /// Validated on startup and reload, issuance is refused while invalid
+    hierarchy: RwLock<HierarchyReport>,
+    response_signer: Option<ResponseSigner>,
+    acme_nonces: NonceStore,
+    acme_orders: OrderStore,
 }
 
 impl ControllerData {
@@ -41,54 +106,196 @@ impl ControllerData {
 
 pub struct ServerController {
     dispatch: ControllerDispatch<ControllerData>,
+    config: Arc<RwLock<Config>>,
+    /// Routes are mounted under it, changes are only applied on restart
+    base_path: String,
 }
 
 impl ServerController {
-    pub fn new(config: Config, log_handle: Handle) -> Result<Self, String> {
-        let storage = get_storage(&config);
+    pub fn new(config: Config, log_handle: Handle) -> Result<Self, StartupError> {
+        config.validate()?;
+
+        if let Some(path) = &config.random_source {
+            set_random_source(Box::new(DeviceRng::open(path)?));
+            log::info!("using random source {}", path.display());
+        }
+
+        let self_test = if config.self_test {
+            Some(run_self_test(&config)?)
+        } else {
+            None
+        };
+
+        let response_signer = ResponseSigner::from_config(&config)?;
+
+        let (storage, key_locker) =
+            get_storage(&config).map_err(|source| StartupError::StorageUnavailable { source })?;
+        storage.capabilities().check(&config)?;
+        let signer = Arc::new(CaSigner::new(key_locker, &config.storage_encryption_key));
 
-        init_storage_from_config(storage.as_ref(), &config)?;
+        init_storage_from_config(storage.as_ref(), &signer, &config)?;
+        let hierarchy = check_hierarchy(&config, storage.as_ref(), &signer);
+
+        let base_path = config.base_path.clone();
+        let legacy_routes = config.legacy_routes.enabled;
+        let config = Arc::new(RwLock::new(config));
+        spawn_ca_expiry_watcher(Arc::clone(&config), Arc::clone(&storage));
+        spawn_ct_monitor(Arc::clone(&config), Arc::clone(&storage));
+        spawn_spool_flusher(Arc::clone(&config), Arc::clone(&storage));
+        spawn_crl_refresher(Arc::clone(&config), Arc::clone(&storage), Arc::clone(&signer));
 
         let controller_data = ControllerData {
             storage,
-            config: RwLock::new(config),
+            signer,
+            config: Arc::clone(&config),
             log_handle,
+            self_test,
+            hierarchy: RwLock::new(hierarchy),
+            response_signer,
+            acme_nonces: NonceStore::default(),
+            acme_orders: OrderStore::default(),
         };
 
         let dispatch = ControllerDispatch::new(controller_data);
+        let routes = Routes {
+            dispatch: &dispatch,
+            legacy_routes,
+        };
+
+        routes.add(Method::GET, "/chain", get_default_chain);
+        routes.add(Method::GET, "/cacerts", get_ca_certs);
+        routes.add(Method::GET, "/root.pem", get_root_pem);
+        routes.add(Method::GET, "/intermediate.pem", get_intermediate_pem);
+        routes.add(Method::GET, "/bundle.p12", get_pkcs12_bundle);
+        routes.add(Method::POST, "/rotation", post_rotation_state);
+        routes.add(Method::POST, "/sign", cert_signature_request);
+        routes.add(Method::POST, "/generate", generate_key_and_certificate);
+        routes.add(Method::GET, "/health", health);
+        routes.add(Method::GET, "/metrics", metrics);
+        routes.add(Method::GET, "/.well-known/jwks.json", get_jwks);
+        routes.add(Method::GET, "/cert/<multihash>", get_cert);
+        routes.add(Method::POST, "/cert", post_cert);
+        routes.add(Method::GET, "/certs", get_certs);
+        routes.add(Method::POST, "/revoke", revoke);
+        routes.add(Method::POST, "/revoke/batch", revoke_batch);
+        routes.add(Method::GET, "/crl", get_crl);
+        routes.add(Method::GET, "/crl/<partition>", get_crl);
+        routes.add(Method::POST, "/crl", regenerate_crls);
+        routes.add(Method::POST, "/ocsp", post_ocsp);
+        routes.add(Method::GET, "/ocsp/<request>", get_ocsp);
+        routes.add(Method::GET, "/artifacts/<namespace>/<multihash>", get_artifact);
+        routes.add(Method::GET, "/reload", reload_yaml_conf);
+        routes.add(Method::GET, "/requests", get_signing_requests);
+        routes.add(Method::GET, "/requests/<id>", get_signing_request);
+        routes.add(Method::POST, "/requests/<id>/approve", approve_signing_request);
+        routes.add(Method::POST, "/requests/<id>/deny", deny_signing_request);
+        routes.add(Method::GET, "/audit/proof", get_audit_proof);
+        routes.add(Method::GET, "/log/sth", get_log_sth);
+        routes.add(Method::GET, "/log/proof/<hash>", get_log_proof);
+        routes.add(Method::GET, "/log/consistency", get_log_consistency);
+        routes.add(Method::GET, "/acme/directory", get_acme_directory);
+        routes.add(Method::HEAD, "/acme/new-nonce", new_acme_nonce);
+        routes.add(Method::GET, "/acme/new-nonce", new_acme_nonce);
+        routes.add(Method::POST, "/acme/new-account", new_acme_account);
+        routes.add(Method::POST, "/acme/new-order", new_acme_order);
+        routes.add(Method::POST, "/acme/order/<id>", get_acme_order);
+        routes.add(Method::POST, "/acme/authz/<id>", get_acme_authorization);
+        routes.add(Method::POST, "/acme/chall/<id>", respond_acme_challenge);
+        routes.add(Method::GET, "/acme/eab", get_external_account_keys);
+        routes.add(Method::POST, "/acme/eab", post_external_account_key);
+        routes.add(Method::POST, "/acme/eab/<key_id>/revoke", revoke_external_account_key);
+
+        Ok(ServerController {
+            dispatch,
+            config,
+            base_path,
+        })
+    }
+}
+
+type Handler = fn(&ControllerData, &SyncRequest, &mut SyncResponse);
+
+/// Routes are served under the current API version and, unless `legacy_routes` is disabled, on
+/// their deprecated unprefixed paths.
+struct Routes<'a> {
+    dispatch: &'a ControllerDispatch<ControllerData>,
+    legacy_routes: bool,
+}
 
-        dispatch.add(Method::GET, "/chain", get_default_chain);
-        dispatch.add(Method::POST, "/sign", cert_signature_request);
-        dispatch.add(Method::GET, "/health", health);
-        dispatch.add(Method::GET, "/cert/<multihash>", get_cert);
-        dispatch.add(Method::POST, "/cert", post_cert);
-        dispatch.add(Method::GET, "/reload", reload_yaml_conf);
+impl Routes<'_> {
+    fn add(&self, method: Method, route: &str, handler: Handler) {
+        if versioning::is_unversioned(route) {
+            self.dispatch.add(method, route, handler);
+            return;
+        }
 
-        Ok(ServerController { dispatch })
+        self.dispatch
+            .add(method.clone(), &versioning::versioned(route), handler);
+        if self.legacy_routes {
+            self.dispatch.add(method, route, move |controller_data, req, res| {
+                handler(controller_data, req, res);
+                versioning::write_deprecation_headers(&controller_data.read_conf(), req, res);
+            });
+        }
     }
 }
 
 impl Controller for ServerController {
     fn handle(&self, req: &mut SyncRequest, res: &mut SyncResponse) {
+        let request_id = match req.get_header_string_value(REQUEST_ID_HEADER) {
+            Some(request_id) => request_id,
+            None => {
+                let request_id = new_request_id();
+                if let Ok(value) = HeaderValue::from_str(&request_id) {
+                    req.headers_map_mut().insert(REQUEST_ID_HEADER, value);
+                }
+                request_id
+            }
+        };
+        res.header(REQUEST_ID_HEADER, request_id);
+
+        let request_log_entry = RequestLogEntry::start();
         self.dispatch.dispatch(req, res);
+        request_log_entry.finish(&self.config.read().expect("config lock"), req, res);
     }
 
     fn base_path(&self) -> &str {
-        "/"
+        &self.base_path
     }
 }
 
 // === helper macros === //
 
 macro_rules! saphir_try {
-    ( $result:expr ) => {
-        saphir_try!($result, "Error")
+    ( $req:ident, $res:ident, $code:expr, $result:expr ) => {
+        saphir_try!($req, $res, $code, $result, "Error")
+    };
+    ( $req:ident, $res:ident, $code:expr, $result:expr , $context:literal $(,)? ) => {
+        match $result {
+            Ok(value) => value,
+            Err(e) => {
+                let detail = format!(concat!($context, ": {}"), e);
+                log::error!("{}", detail);
+                write_problem($req, $res, $code, detail);
+                return;
+            }
+        }
     };
-    ( $result:expr , $context:literal $(,)? ) => {
+}
+
+/// Same as `saphir_try` for `ServerError`s, the error code is given by the error kind.
+macro_rules! server_try {
+    ( $req:ident, $res:ident, $result:expr $(,)? ) => {
+        server_try!($req, $res, $result, "Error")
+    };
+    ( $req:ident, $res:ident, $result:expr , $context:literal $(,)? ) => {
         match $result {
             Ok(value) => value,
             Err(e) => {
-                log::error!(concat!($context, ": {}"), e);
+                let e = ServerError::from(e);
+                let detail = format!(concat!($context, ": {}"), e);
+                log::error!("{}", detail);
+                write_problem_with_extensions($req, $res, e.code(), detail, e.extensions());
                 return;
             }
         }
@@ -96,17 +303,38 @@ macro_rules! saphir_try {
 }
 
 macro_rules! unwrap_opt {
-    ( $opt:expr , $error:literal $(,)? ) => {
+    ( $req:ident, $res:ident, $code:expr, $opt:expr , $error:literal $(,)? ) => {
         match $opt {
             Some(value) => value,
             None => {
                 log::error!($error);
+                write_problem($req, $res, $code, $error.to_owned());
                 return;
             }
         }
     };
 }
 
+// === json responses === //
+
+/// Writes a JSON body, signed if response signing is enabled.
+///
+/// Nothing is written when signing fails: clients relying on signed responses must not receive an
+/// unsigned one.
+fn write_json(
+    controller_data: &ControllerData,
+    res: &mut SyncResponse,
+    body: String,
+) -> Result<(), ResponseSigningError> {
+    if let Some(signer) = &controller_data.response_signer {
+        res.header(JWS_SIGNATURE_HEADER, signer.sign(body.as_bytes())?);
+    }
+
+    res.header(header::CONTENT_TYPE, "application/json");
+    res.body(body);
+    Ok(())
+}
+
 // === header format === //
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
@@ -117,6 +345,10 @@ enum Format {
     PkixCertBase64,
     Pkcs10Binary,
     Pkcs10Base64,
+    Pkcs7Binary,
+    Pkcs7Base64,
+    PkixCrl,
+    X509CaCert,
 }
 
 impl fmt::Display for Format {
@@ -128,26 +360,111 @@ impl fmt::Display for Format {
             Format::PkixCertBase64 => write!(f, "base64-encoded pkix-cert"),
             Format::Pkcs10Binary => write!(f, "binary-encoded pkcs10"),
             Format::Pkcs10Base64 => write!(f, "base64-encoded pkcs10"),
+            Format::Pkcs7Binary => write!(f, "binary-encoded pkcs7"),
+            Format::Pkcs7Base64 => write!(f, "base64-encoded pkcs7"),
+            Format::PkixCrl => write!(f, "pkix-crl"),
+            Format::X509CaCert => write!(f, "x509-ca-cert"),
         }
     }
 }
 
+/// Format of a request or response body couldn't be determined from its headers
+#[derive(Debug, Snafu, PartialEq)]
+enum FormatError {
+    #[snafu(display("Content-Type header is missing and body format couldn't be detected"))]
+    Undetectable,
+
+    #[snafu(display("Accept header is missing"))]
+    MissingAccept,
+
+    #[snafu(display("format encoding for {} is missing", format))]
+    MissingEncoding { format: &'static str },
+
+    #[snafu(display("unsupported encoding format for {}: {}", format, encoding))]
+    UnsupportedEncoding { format: &'static str, encoding: String },
+
+    #[snafu(display("unsupported format: {}", format))]
+    UnsupportedFormat { format: String },
+}
+
 impl Format {
-    fn request_format(req: &SyncRequest) -> Result<Self, String> {
+    fn request_format(req: &SyncRequest) -> Result<Self, FormatError> {
         let content_type_opt = req.get_header_string_value("Content-Type");
         let content_transfert_encoding_opt = req.get_header_string_value("Content-Transfer-Encoding");
 
-        if let Some(content_type) = content_type_opt {
-            Self::new(
+        match content_type_opt {
+            // many embedded clients can't set a precise content type
+            Some(content_type) if content_type != "application/octet-stream" => Self::new(
                 content_type.as_str(),
                 content_transfert_encoding_opt.as_ref().map(|s| s.as_str()),
-            )
+            ),
+            _ => Self::sniff(req.body()),
+        }
+    }
+
+    /// Same as `request_format`, but when `strict` is set the declared Content-Type must match the
+    /// format detected from the body itself.
+    fn checked_request_format(req: &SyncRequest, strict: bool) -> Result<Self, ServerError> {
+        let format = Self::request_format(req).map_err(|e| ServerError::InvalidRequest {
+            description: e.to_string(),
+        })?;
+
+        if strict {
+            // bodies whose format can't be detected are left to the parser to reject
+            if let Ok(detected) = Self::sniff(req.body()) {
+                if detected != format {
+                    return Err(ServerError::FormatMismatch {
+                        expected: format.to_string(),
+                        detected: detected.to_string(),
+                    });
+                }
+            }
+        }
+
+        Ok(format)
+    }
+
+    /// Detects the format of a request body sent without a precise Content-Type.
+    fn sniff(body: &[u8]) -> Result<Self, FormatError> {
+        let text_start = body
+            .iter()
+            .position(|byte| !byte.is_ascii_whitespace())
+            .unwrap_or_else(|| body.len());
+        let text = &body[text_start..];
+
+        if text.starts_with(b"-----BEGIN") {
+            return Ok(Self::PemFile);
+        }
+        if text.starts_with(b"{") {
+            return Ok(Self::Json);
+        }
+        if let Some(format) = Self::sniff_der(body, false) {
+            return Ok(format);
+        }
+        if let Some(format) = base64::decode(body).ok().and_then(|der| Self::sniff_der(&der, true)) {
+            return Ok(format);
+        }
+
+        Err(FormatError::Undetectable)
+    }
+
+    fn sniff_der(der: &[u8], base64: bool) -> Option<Self> {
+        if Cert::from_der(der).is_ok() {
+            Some(if base64 {
+                Self::PkixCertBase64
+            } else {
+                Self::PkixCertBinary
+            })
+        } else if Csr::from_der(der).is_ok() {
+            Some(if base64 { Self::Pkcs10Base64 } else { Self::Pkcs10Binary })
+        } else if SignedData::from_der(der).is_ok() {
+            Some(if base64 { Self::Pkcs7Base64 } else { Self::Pkcs7Binary })
         } else {
-            Err("Content-Type header is missing".to_string())
+            None
         }
     }
 
-    fn response_format(req: &SyncRequest) -> Result<Self, String> {
+    fn response_format(req: &SyncRequest) -> Result<Self, FormatError> {
         let accept_opt = req.get_header_string_value("Accept").map(|s| {
             // cannot panic
             s.split(',').next().unwrap().split(';').next().unwrap().to_owned()
@@ -160,284 +477,925 @@ impl Format {
         if let Some(accept) = accept_opt {
             Self::new(accept.as_str(), accept_encoding_opt.as_ref().map(|s| s.as_str()))
         } else {
-            Err("Accept header is missing".to_string())
+            Err(FormatError::MissingAccept)
         }
     }
 
-    fn new(format: &str, encoding: Option<&str>) -> Result<Self, String> {
+    fn new(format: &str, encoding: Option<&str>) -> Result<Self, FormatError> {
+        // cannot panic
+        let format = format.split(';').next().unwrap().trim();
         match (format, encoding) {
             ("application/x-pem-file", _) => Ok(Self::PemFile),
             ("application/json", _) => Ok(Self::Json),
             ("application/pkix-cert", Some("binary")) => Ok(Self::PkixCertBinary),
             ("application/pkix-cert", Some("base64")) => Ok(Self::PkixCertBase64),
-            ("application/pkix-cert", Some(unsupported)) => {
-                Err(format!("unsupported encoding format for pkix-cert: {}", unsupported))
-            }
-            ("application/pkix-cert", None) => Err("format encoding for pkix-cert is missing".to_owned()),
+            ("application/pkix-cert", Some(unsupported)) => Err(FormatError::UnsupportedEncoding {
+                format: "pkix-cert",
+                encoding: unsupported.to_owned(),
+            }),
+            ("application/pkix-cert", None) => Err(FormatError::MissingEncoding { format: "pkix-cert" }),
             ("application/pkcs10", Some("binary")) => Ok(Self::Pkcs10Binary),
             ("application/pkcs10", Some("base64")) => Ok(Self::Pkcs10Base64),
-            ("application/pkcs10", Some(unsupported)) => {
-                Err(format!("unsupported encoding format for pkcs10: {}", unsupported))
+            ("application/pkcs10", Some(unsupported)) => Err(FormatError::UnsupportedEncoding {
+                format: "pkcs10",
+                encoding: unsupported.to_owned(),
+            }),
+            ("application/pkcs10", None) => Err(FormatError::MissingEncoding { format: "pkcs10" }),
+            ("application/pkcs7-mime", Some("base64")) | ("application/x-pkcs7-certificates", Some("base64")) => {
+                Ok(Self::Pkcs7Base64)
             }
-            ("application/pkcs10", None) => Err("format encoding for pkcs10 is missing".to_owned()),
-            (unsupported, _) => Err(format!("unsupported format: {}", unsupported)),
+            ("application/pkcs7-mime", Some("binary")) | ("application/x-pkcs7-certificates", Some("binary")) => {
+                Ok(Self::Pkcs7Binary)
+            }
+            ("application/pkcs7-mime", None) | ("application/x-pkcs7-certificates", None) => Ok(Self::Pkcs7Binary),
+            ("application/pkcs7-mime", Some(unsupported)) | ("application/x-pkcs7-certificates", Some(unsupported)) => {
+                Err(FormatError::UnsupportedEncoding {
+                    format: "pkcs7",
+                    encoding: unsupported.to_owned(),
+                })
+            }
+            ("application/pkix-crl", _) => Ok(Self::PkixCrl),
+            ("application/x-x509-ca-cert", _) => Ok(Self::X509CaCert),
+            (unsupported, _) => Err(FormatError::UnsupportedFormat {
+                format: unsupported.to_owned(),
+            }),
         }
     }
 }
 
 // === health === //
 
-fn health(controller_data: &ControllerData, _req: &SyncRequest, res: &mut SyncResponse) {
-    if controller_data.storage.health().is_ok() {
-        res.status(StatusCode::OK).body("Everything should be alright!");
-    } else {
-        res.status(StatusCode::SERVICE_UNAVAILABLE);
+fn health(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    match controller_data.storage.health() {
+        Ok(()) => {
+            let hierarchy = controller_data.hierarchy.read().expect("hierarchy lock").clone();
+            if Format::response_format(req) == Ok(Format::Json) {
+                res.header(header::CONTENT_TYPE, "application/json");
+                res.body(
+                    json!({
+                        "status": if hierarchy.valid { "ok" } else { "degraded" },
+                        "self_test": controller_data.self_test,
+                        "ca_hierarchy": hierarchy,
+                        "ca_keys": ca_key_usage(controller_data),
+                        "spooled_certificates": spooled_certificates(controller_data),
+                        "storage_capabilities": controller_data.storage.capabilities(),
+                    })
+                    .to_string(),
+                );
+            } else if hierarchy.valid {
+                res.body("Everything should be alright!");
+            } else {
+                res.body(format!(
+                    "CA hierarchy is broken, issuance is disabled: {}",
+                    hierarchy.problems.join("; ")
+                ));
+            }
+
+            if hierarchy.valid {
+                res.status(StatusCode::OK);
+            } else {
+                res.status(StatusCode::SERVICE_UNAVAILABLE);
+            }
+        }
+        Err(e) => write_problem(
+            req,
+            res,
+            ErrorCode::StorageUnavailable,
+            format!("unhealthy storage: {}", e),
+        ),
     }
 }
 
-// === post_cert === //
-
-fn post_cert(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
-    res.status(StatusCode::BAD_REQUEST);
+fn metrics(controller_data: &ControllerData, _: &SyncRequest, res: &mut SyncResponse) {
+    res.header(header::CONTENT_TYPE, "text/plain; version=0.0.4");
+    res.body(key_usage::prometheus_metrics(&ca_key_usage(controller_data)));
+    res.status(StatusCode::OK);
+}
 
-    let cert = saphir_try!(extract_cert_from_request(req));
+/// Number of certificates waiting in the storage spool, if enabled.
+fn spooled_certificates(controller_data: &ControllerData) -> Option<usize> {
+    let config = controller_data.read_conf();
+    let spool_config = config.storage_spool.as_ref()?;
+    match spool::pending(spool_config) {
+        Ok(pending) => Some(pending),
+        Err(e) => {
+            log::warn!("couldn't count spooled certificates: {}", e);
+            None
+        }
+    }
+}
 
-    let ski = hex::encode(saphir_try!(cert.subject_key_identifier(), "couldn't fetch SKI"));
+/// Usage of the root and intermediate CA keys. CAs which can't be fetched are skipped.
+fn ca_key_usage(controller_data: &ControllerData) -> Vec<KeyUsageReport> {
+    let config = controller_data.read_conf();
+    let storage = controller_data.storage.as_ref();
+
+    let mut reports = Vec::new();
+    for ca_name in [
+        format!("{} Root CA", config.realm),
+        format!("{} Authority", config.realm),
+    ]
+    .iter()
+    {
+        let report = storage
+            .get_addressing_hash_by_name(ca_name)
+            .and_then(|hash| storage.get_cert_by_addressing_hash(&hash))
+            .map_err(|e| format!("couldn't fetch CA cert: {}", e))
+            .and_then(|der| cert_cache::parse(&der).map_err(|e| format!("couldn't parse CA cert: {}", e)))
+            .and_then(|cert| {
+                key_usage::report(storage, ca_name, &cert, &config.key_usage_limits).map_err(|e| e.to_string())
+            });
+
+        match report {
+            Ok(report) => reports.push(report),
+            Err(e) => log::warn!("couldn't report {} key usage: {}", ca_name, e),
+        }
+    }
 
-    let issuer_name = unwrap_opt!(
-        cert.issuer_name().find_common_name(),
-        "couldn't find issuer common name"
-    )
-    .to_string();
+    reports
+}
 
-    if issuer_name != format!("{} Authority", &controller_data.read_conf().realm) {
-        log::error!("this certificate was not signed by the CA of this server.");
-        return;
+fn run_self_test(config: &Config) -> Result<SelfTestReport, StartupError> {
+    let report = self_test::run(&[
+        config.root_signing_algorithm(),
+        config.intermediate_signing_algorithm(),
+        config.leaf_signing_algorithm(),
+    ]);
+
+    for result in &report.results {
+        match &result.detail {
+            Some(detail) => log::error!("self-test {}: FAILED ({})", result.name, detail),
+            None => log::info!("self-test {}: passed", result.name),
+        }
     }
 
-    let der = saphir_try!(cert.to_der(), "couldn't serialize certificate into der");
-    let subject_name = unwrap_opt!(
-        cert.subject_name().find_common_name(),
-        "couldn't find subject issuer common name"
-    )
-    .to_string();
-
-    if let Err(e) = controller_data.storage.store(CertificateEntry {
-        name: subject_name.clone(),
-        cert: der,
-        key_identifier: ski,
-        key: None,
-    }) {
-        log::error!("insertion failed for leaf {}: {}", subject_name, e);
+    if report.passed {
+        Ok(report)
     } else {
-        res.status(StatusCode::OK);
+        let failures = report.failures().map(|result| result.name.clone()).collect();
+        Err(StartupError::SelfTestFailed { failures })
     }
 }
 
-fn extract_cert_from_request(req: &SyncRequest) -> Result<Cert, GreedyError> {
-    let request_format = Format::request_format(req)?;
-    match request_format {
-        Format::PemFile => {
-            let pem = parse_pem(req.body())?;
-            Ok(Cert::from_der(pem.data())?)
-        }
-        Format::Json => {
-            let json = serde_json::from_slice::<Value>(req.body())?;
-            let pem = json["certificate"]
-                .to_string()
-                .trim_matches('"')
-                .replace("\\n", "\n")
-                .parse::<Pem>()?;
-            Ok(Cert::from_der(pem.data())?)
-        }
-        Format::PkixCertBinary => Ok(Cert::from_der(req.body())?),
-        Format::PkixCertBase64 => {
-            let der = base64::decode(&req.body())?;
-            Ok(Cert::from_der(&der)?)
+fn check_hierarchy(config: &Config, storage: &dyn PickyStorage, signer: &CaSigner) -> HierarchyReport {
+    let report = hierarchy::check(config, storage, signer);
+
+    if report.valid {
+        log::info!("CA hierarchy: valid");
+    } else {
+        for problem in &report.problems {
+            log::error!("CA hierarchy: {}", problem);
         }
-        unexpected => Err(GreedyError(format!("unexpected request format: {}", unexpected))),
+        log::error!("CA hierarchy is broken, issuance is disabled");
     }
+
+    report
 }
 
-// === cert_signature_request ===
+/// Refuses issuance while the stored CA hierarchy is broken.
+fn check_hierarchy_valid(controller_data: &ControllerData) -> Result<(), ServerError> {
+    let hierarchy = controller_data.hierarchy.read().expect("hierarchy lock");
+    if hierarchy.valid {
+        Ok(())
+    } else {
+        Err(ServerError::CaUnavailable {
+            description: format!("CA hierarchy is broken: {}", hierarchy.problems.join("; ")),
+        })
+    }
+}
 
-fn cert_signature_request(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
-    res.status(StatusCode::BAD_REQUEST);
+// === jwks === //
 
-    let locked_subject_name: Option<String> = match check_authorization(&controller_data.read_conf(), req) {
-        Ok(Authorized::ApiKey) => None,
-        Ok(Authorized::Token(token)) => {
-            let csr_claims: CsrClaims = saphir_try!(serde_json::from_value(token.into_claims()));
-            Some(csr_claims.sub)
-        }
-        Err(e) => {
-            log::error!("authorization failed: {}", e);
-            res.status(StatusCode::UNAUTHORIZED);
-            return;
-        }
-    };
+fn get_jwks(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    let config = controller_data.read_conf();
+
+    let mut keys = Vec::new();
+    if config.provisioner_public_key.is_some() {
+        let public_key = saphir_try!(req, res, ErrorCode::InternalError, provisioner_public_key(&config));
+        let jwk = saphir_try!(
+            req,
+            res,
+            ErrorCode::InternalError,
+            Jwk::from_public_key(&public_key),
+            "couldn't convert provisioner public key to JWK"
+        );
+        let key_id = saphir_try!(
+            req,
+            res,
+            ErrorCode::InternalError,
+            jwk.thumbprint(),
+            "couldn't compute JWK thumbprint"
+        );
+        keys.push(
+            jwk.with_pub_key_use(JwkPubKeyUse::Signature)
+                .with_key_operations(vec![JwkKeyOps::Verify])
+                .with_key_id(key_id),
+        );
+    }
 
-    let csr = saphir_try!(extract_csr_from_request(req));
+    if let Some(signer) = &controller_data.response_signer {
+        keys.push(signer.jwk().clone());
+    }
 
-    if let Some(locked_subject_name) = locked_subject_name {
-        let subject_name = unwrap_opt!(
-            csr.subject_name().find_common_name(),
-            "couldn't find signed CSR subject common name"
-        )
-        .to_string();
+    let body = saphir_try!(
+        req,
+        res,
+        ErrorCode::InternalError,
+        JwkSet { keys }.to_json(),
+        "couldn't serialize JWK set"
+    );
+    res.header(header::CONTENT_TYPE, "application/json");
+    res.body(body);
+    res.status(StatusCode::OK);
+}
 
-        if locked_subject_name != subject_name {
-            log::error!(
-                "Requested a certificate with an unauthorized subject name: {}, expected: {}",
-                subject_name,
-                locked_subject_name
-            );
-            res.status(StatusCode::UNAUTHORIZED);
+// === post_cert === //
+
+fn post_cert(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    let strict_formats = controller_data.read_conf().strict_formats;
+    let certs = server_try!(req, res, extract_certs_from_request(req, strict_formats));
+
+    // PEM bundles and PKCS#7 sets come in any order
+    let certs = server_try!(req, res, order_chain_links(&certs), "invalid certificate chain");
+
+    let realm = controller_data.read_conf().realm.clone();
+    server_try!(
+        req,
+        res,
+        verify_chain_to_ca(controller_data.storage.as_ref(), &realm, &certs),
+        "this certificate was not signed by the CA of this server"
+    );
+
+    // only the leaf is linted, CA certificates of this server are trusted as is
+    let lint_policy = controller_data.read_conf().lint_policy.clone();
+    let lint_findings = lint::lint_cert(&certs[0], &lint_policy);
+    saphir_try!(
+        req,
+        res,
+        ErrorCode::PolicyViolation,
+        lint::check(&lint_findings, &lint_policy),
+        "refused by lint policy"
+    );
+
+    // every certificate is checked before anything is stored
+    let mut leaf_addresses = None;
+    let mut entries = Vec::new();
+    for cert in certs.iter() {
+        let der = saphir_try!(
+            req,
+            res,
+            ErrorCode::InvalidRequest,
+            cert.to_der(),
+            "couldn't serialize certificate into der"
+        );
+        let addresses = saphir_try!(
+            req,
+            res,
+            ErrorCode::InternalError,
+            encode_to_addresses(&der),
+            "couldn't compute certificate addresses"
+        );
+
+        if controller_data
+            .storage
+            .get_cert_by_addressing_hash(&addresses.address)
+            .is_ok()
+        {
+            log::info!("certificate {} is already stored", addresses.address);
+        } else {
+            let ski = hex::encode(saphir_try!(
+                req,
+                res,
+                ErrorCode::InvalidRequest,
+                cert.subject_key_identifier(),
+                "couldn't fetch SKI"
+            ));
+            let subject_name = unwrap_opt!(
+                req,
+                res,
+                ErrorCode::InvalidRequest,
+                cert.subject_name().find_common_name(),
+                "couldn't find subject issuer common name"
+            )
+            .to_string();
+
+            entries.push(CertificateEntry {
+                name: subject_name,
+                cert: der,
+                key_identifier: ski,
+                key: None,
+                requested_by: None,
+                labels: Labels::new(),
+            });
+        }
+
+        leaf_addresses.get_or_insert(addresses);
+    }
+
+    // issuers first, so that a failure never leaves a certificate without its issuer
+    for entry in entries.into_iter().rev() {
+        let subject_name = entry.name.clone();
+        if let Err(e) = controller_data.storage.store(entry) {
+            let detail = format!("insertion failed for {}: {}", subject_name, e);
+            log::error!("{}", detail);
+            write_problem(req, res, ErrorCode::StorageUnavailable, detail);
             return;
         }
     }
 
-    // Sign CSR
-    let conf = controller_data.read_conf();
-    let signed_cert = saphir_try!(sign_certificate(
-        &format!("{} Authority", &conf.realm),
-        csr,
-        &conf,
-        controller_data.storage.as_ref()
-    ));
-    drop(conf); // release lock early
+    server_try!(
+        req,
+        res,
+        audit::append(
+            controller_data.storage.as_ref(),
+            AuditEvent::CertificatePushed,
+            json!({
+                "subject": certs[0].subject_name().to_string(),
+                "serial_number": hex::encode(certs[0].serial_number().as_unsigned_bytes_be()),
+                "address": leaf_addresses.as_ref().map(|addresses| &addresses.address),
+                "lint": lint_findings,
+            }),
+        ),
+        "couldn't record audit event"
+    );
 
-    let response_format = Format::response_format(req).unwrap_or(Format::PemFile);
-    match response_format {
-        Format::PemFile => {
-            let pem = saphir_try!(signed_cert.to_pem(), "couldn't get certificate pem");
-            res.body(pem.to_string());
+    let body = saphir_try!(
+        req,
+        res,
+        ErrorCode::InternalError,
+        serde_json::to_string(&leaf_addresses),
+        "couldn't serialize certificate addresses"
+    );
+    server_try!(
+        req,
+        res,
+        write_json(controller_data, res, body),
+        "couldn't sign response"
+    );
+    res.status(StatusCode::OK);
+}
+
+/// Orders `certs` leaf first, each certificate followed by its issuer.
+///
+/// Duplicates are dropped, and every other certificate must be part of the single path from the leaf.
+fn order_chain_links(certs: &[Cert]) -> Result<Vec<Cert>, ServerError> {
+    let mut remaining = Vec::<Cert>::with_capacity(certs.len());
+    for cert in certs {
+        if !remaining.contains(cert) {
+            remaining.push(cert.clone());
         }
-        Format::PkixCertBinary => {
-            let der = saphir_try!(signed_cert.to_der(), "couldn't get certificate der");
-            res.body(der);
+    }
+
+    let is_parent_of = |parent: &Cert, child: &Cert| parent != child && parent.is_parent_of(child).is_ok();
+    let mut leaves = remaining
+        .iter()
+        .enumerate()
+        .filter(|(_, cert)| !remaining.iter().any(|other| is_parent_of(cert, other)))
+        .map(|(index, _)| index);
+    let leaf_index = match (leaves.next(), leaves.next()) {
+        (Some(leaf_index), None) => leaf_index,
+        (None, _) => {
+            return Err(ServerError::InvalidRequest {
+                description: "certificate chain has no leaf".to_owned(),
+            })
         }
-        Format::PkixCertBase64 => {
-            let der = saphir_try!(signed_cert.to_der(), "couldn't get certificate der");
-            res.body(base64::encode(&der));
+        (Some(_), Some(_)) => {
+            return Err(ServerError::InvalidRequest {
+                description: "certificate chain has more than one leaf".to_owned(),
+            })
         }
-        unexpected => {
-            log::error!("unexpected response format: {}", unexpected);
-            return;
+    };
+
+    let mut chain = vec![remaining.remove(leaf_index)];
+    while !remaining.is_empty() {
+        let child = chain.last().expect("chain starts with the leaf");
+        let mut parents = remaining
+            .iter()
+            .enumerate()
+            .filter(|(_, cert)| is_parent_of(cert, child))
+            .map(|(index, _)| index);
+        match (parents.next(), parents.next()) {
+            (Some(parent_index), None) => {
+                let parent = remaining.remove(parent_index);
+                chain.push(parent);
+            }
+            (None, _) => {
+                return Err(ServerError::InvalidRequest {
+                    description: format!(
+                        "certificate {} isn't part of the chain of {}",
+                        remaining[0].subject_name(),
+                        chain[0].subject_name()
+                    ),
+                })
+            }
+            (Some(_), Some(_)) => {
+                return Err(ServerError::InvalidRequest {
+                    description: format!("certificate {} has more than one issuer", child.subject_name()),
+                })
+            }
         }
     }
 
-    res.status(StatusCode::OK);
+    Ok(chain)
 }
 
-fn extract_csr_from_request(req: &SyncRequest) -> Result<Csr, GreedyError> {
-    let request_format = Format::request_format(req)?;
-    match request_format {
-        Format::PemFile => {
-            let pem = parse_pem(req.body())?;
-            Ok(Csr::from_der(pem.data())?)
+/// Cryptographically verifies that the posted chain ends at the root CA of this server,
+/// completing it with the issuing CA certificates from storage.
+fn verify_chain_to_ca(storage: &dyn PickyStorage, realm: &str, certs: &[Cert]) -> Result<(), ServerError> {
+    let root_name = format!("{} Root CA", realm);
+    let root_hash = storage
+        .get_addressing_hash_by_name(&root_name)
+        .map_err(ServerError::storage(format!(
+            "couldn't fetch CA hash id for {}",
+            root_name
+        )))?;
+    let root_der = storage
+        .get_cert_by_addressing_hash(&root_hash)
+        .map_err(ServerError::storage("couldn't fetch root CA certificate der"))?;
+    let root = cert_cache::parse(&root_der).map_err(|e| ServerError::Internal {
+        description: format!("couldn't deserialize root CA certificate: {}", e),
+    })?;
+
+    let top = certs.last().ok_or_else(|| ServerError::InvalidRequest {
+        description: "empty certificate chain".to_owned(),
+    })?;
+    let issuer_key_id = hex::encode(
+        top.authority_key_identifier()
+            .map_err(|e| ServerError::PolicyViolation {
+                description: format!("couldn't fetch authority key identifier: {}", e),
+            })?
+            .key_identifier()
+            .ok_or_else(|| ServerError::PolicyViolation {
+                description: "authority key identifier not found".to_owned(),
+            })?,
+    );
+    let issuer_hash = match storage.get_addressing_hash_by_key_identifier(&issuer_key_id) {
+        Ok(issuer_hash) => issuer_hash,
+        Err(e) if e.is_not_found() => {
+            return Err(ServerError::PolicyViolation {
+                description: format!("issuing CA {} not found: {}", issuer_key_id, e),
+            })
+        }
+        Err(e) => {
+            return Err(ServerError::storage(format!(
+                "couldn't fetch issuing CA {}",
+                issuer_key_id
+            ))(e))
         }
+    };
+
+    let mut chain = certs.to_vec();
+    for cert_pem in find_chain_by_addressing_hash(storage, &issuer_hash)? {
+        let pem = cert_pem.parse::<Pem>().map_err(|e| ServerError::Internal {
+            description: format!("couldn't parse CA certificate pem: {}", e),
+        })?;
+        let ca_cert = cert_cache::parse(pem.data()).map_err(|e| ServerError::Internal {
+            description: format!("couldn't deserialize CA certificate: {}", e),
+        })?;
+        chain.push(Cert::clone(&ca_cert));
+    }
+    // the posted chain may already contain the CA certificates
+    chain.dedup();
+
+    if chain.last() != Some(&*root) {
+        return Err(ServerError::PolicyViolation {
+            description: "chain doesn't end at the root CA".to_owned(),
+        });
+    }
+
+    chain[0]
+        .verify_chain(chain[1..].iter(), &UTCDate::now())
+        .map_err(|e| ServerError::PolicyViolation {
+            description: e.to_string(),
+        })
+}
+
+fn extract_certs_from_request(req: &SyncRequest, strict_formats: bool) -> Result<Vec<Cert>, ServerError> {
+    let request_format = Format::checked_request_format(req, strict_formats)?;
+    let ders = match request_format {
+        Format::PemFile => pem_bundle_to_ders(req.body())?,
         Format::Json => {
             let json = serde_json::from_slice::<Value>(req.body())?;
-            let pem = json["csr"]
-                .to_string()
-                .trim_matches('"')
-                .replace("\\n", "\n")
-                .parse::<Pem>()?;
-            Ok(Csr::from_der(pem.data())?)
+            let pem = json["certificate"].to_string().trim_matches('"').replace("\\n", "\n");
+            pem_bundle_to_ders(pem.as_bytes())?
         }
-        Format::Pkcs10Binary => Ok(Csr::from_der(req.body())?),
-        Format::Pkcs10Base64 => {
-            let der = base64::decode(&req.body())?;
-            Ok(Csr::from_der(&der)?)
+        Format::PkixCertBinary => vec![req.body().to_vec()],
+        Format::PkixCertBase64 => vec![base64::decode(&req.body())?],
+        Format::Pkcs7Binary => parse_pkcs7_certs(req.body())?,
+        Format::Pkcs7Base64 => parse_pkcs7_certs(&base64::decode(&req.body())?)?,
+        unexpected => {
+            return Err(ServerError::InvalidRequest {
+                description: format!("unexpected request format: {}", unexpected),
+            })
         }
-        unexpected => Err(GreedyError(format!("unexpected request format: {}", unexpected))),
+    };
+
+    if ders.is_empty() {
+        return Err(ServerError::InvalidRequest {
+            description: "no certificate found in request body".to_owned(),
+        });
+    }
+
+    let mut certs = Vec::with_capacity(ders.len());
+    for der in ders {
+        certs.push(Cert::from_der(&der).map_err(|e| ServerError::InvalidRequest {
+            description: format!("cert: {}", e),
+        })?);
     }
+    Ok(certs)
 }
 
-fn sign_certificate(ca_name: &str, csr: Csr, config: &Config, storage: &dyn PickyStorage) -> Result<Cert, String> {
-    let ca_hash = storage
-        .get_addressing_hash_by_name(ca_name)
-        .map_err(|e| format!("couldn't fetch CA: {}", e))?;
+/// Decodes every PEM block of a bundle; a PKCS7 block contributes all of its certificates.
+fn pem_bundle_to_ders(bundle: &[u8]) -> Result<Vec<Vec<u8>>, ServerError> {
+    const PEM_FOOTER: &[u8] = b"-----END";
+
+    let mut ders = Vec::new();
+    let mut remaining = bundle;
+    while let Some(footer_idx) = remaining.windows(PEM_FOOTER.len()).position(|w| w == PEM_FOOTER) {
+        let after_footer = footer_idx + PEM_FOOTER.len();
+        let block_end = remaining[after_footer..]
+            .windows(5)
+            .position(|w| w == b"-----")
+            .map(|idx| after_footer + idx + 5)
+            .ok_or_else(|| ServerError::InvalidRequest {
+                description: "pem: invalid footer".to_owned(),
+            })?;
+
+        let pem = parse_pem(&remaining[..block_end])?;
+        if pem.label() == "PKCS7" {
+            ders.extend(parse_pkcs7_certs(pem.data())?);
+        } else {
+            ders.push(pem.into_data().into_owned());
+        }
 
-    let ca_cert_der = storage
-        .get_cert_by_addressing_hash(&ca_hash)
-        .map_err(|e| format!("couldn't get CA cert der: {}", e))?;
-    let ca_cert = Cert::from_der(&ca_cert_der).map_err(|e| format!("couldn't deserialize CA cert: {}", e))?;
+        remaining = &remaining[block_end..];
+    }
 
-    let ca_pk_der = storage
-        .get_key_by_addressing_hash(&ca_hash)
-        .map_err(|e| format!("couldn't fetch CA private key: {}", e))?;
-    let ca_pk = Picky::parse_pk_from_magic_der(&ca_pk_der).map_err(|e| e.to_string())?;
+    if ders.is_empty() {
+        // surface the parser error for malformed input
+        parse_pem(bundle)?;
+    }
 
-    let dns_name = csr
-        .subject_name()
-        .find_common_name()
-        .ok_or_else(|| "couldn't find signed cert subject common name")?
-        .to_string();
+    Ok(ders)
+}
 
-    let signed_cert = Picky::generate_leaf_from_csr(csr, &ca_cert, &ca_pk, config.signing_algorithm, &dns_name)
-        .map_err(|e| format!("couldn't generate leaf certificate: {}", e))?;
+fn parse_pkcs7_certs(der: &[u8]) -> Result<Vec<Vec<u8>>, ServerError> {
+    let certs = SignedData::from_der(der)
+        .and_then(|signed_data| signed_data.certs())
+        .map_err(|e| ServerError::InvalidRequest {
+            description: format!("couldn't parse pkcs7: {}", e),
+        })?;
+    certs
+        .iter()
+        .map(|cert| {
+            cert.to_der().map_err(|e| ServerError::InvalidRequest {
+                description: format!("couldn't encode pkcs7 certificate: {}", e),
+            })
+        })
+        .collect()
+}
 
-    if config.save_certificate {
-        let cert_der = signed_cert
-            .to_der()
-            .map_err(|e| format!("couldn't serialize certificate to der: {}", e))?;
-        let ski = hex::encode(
-            signed_cert
-                .subject_key_identifier()
-                .map_err(|e| format!("couldn't get SKI: {}", e))?,
-        );
+// === cert_signature_request ===
 
-        storage
-            .store(CertificateEntry {
-                name: dns_name.clone(),
-                cert: cert_der,
-                key_identifier: ski,
-                key: None,
-            })
-            .map_err(|e| format!("insertion error for leaf {}: {}", dns_name, e))?;
+/// Returns the subject name a token is restricted to (if any) along with the requester identity and
+/// the subject alternative names vouched for by the token.
+fn issuance_requester(
+    config: &Config,
+    req: &SyncRequest,
+) -> Result<(Option<String>, IssuanceOrigin, AltNames), ServerError> {
+    match check_authorization(config, req).map_err(|e| ServerError::Unauthorized {
+        description: e.to_string(),
+    })? {
+        Authorized::ApiKey => Ok((
+            None,
+            IssuanceOrigin {
+                requested_by: Some(API_KEY_REQUESTER.to_owned()),
+                labels: Labels::new(),
+                base_url: forwarded_base_url(config, req),
+                profile: None,
+            },
+            AltNames::default(),
+        )),
+        Authorized::Token(token) => {
+            let csr_claims = token.into_claims();
+            let origin = IssuanceOrigin {
+                requested_by: Some(token_requester(&csr_claims.sub)),
+                labels: csr_claims.labels,
+                base_url: forwarded_base_url(config, req),
+                profile: csr_claims.profile,
+            };
+            Ok((Some(csr_claims.sub), origin, csr_claims.alt_names))
+        }
     }
-
-    Ok(signed_cert)
 }
 
-// === get_cert === //
+const SERVER_TIMING_HEADER: &str = "Server-Timing";
 
-fn get_cert(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
-    res.status(StatusCode::BAD_REQUEST);
+fn cert_signature_request(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    let issuance_timings = controller_data.read_conf().issuance_timings;
+    if issuance_timings {
+        timings::start();
+    }
 
-    let addressing_hash_any_base = unwrap_opt!(req.captures().get("multihash"), "multihash is missing");
-    let (addressing_hash, hash) = saphir_try!(convert_to_canonical_base(addressing_hash_any_base));
-    let canonical_address = if hash == CANONICAL_HASH {
-        addressing_hash
-    } else {
-        let converted = saphir_try!(controller_data.storage.lookup_addressing_hash(&addressing_hash));
-        log::info!("converted cert address {} -> {}", addressing_hash_any_base, converted);
-        converted
+    let (locked_subject_name, mut origin, mut alt_names) = server_try!(
+        req,
+        res,
+        timings::measure(Phase::Authorization, || issuance_requester(
+            &controller_data.read_conf(),
+            req
+        )),
+        "authorization failed"
+    );
+
+    let strict_formats = controller_data.read_conf().strict_formats;
+    let csr = server_try!(req, res, extract_csr_from_request(req, strict_formats));
+    let body_labels = server_try!(req, res, extract_json_field(req, "labels"), "invalid labels");
+    origin.labels = labels::merge(body_labels, origin.labels);
+    saphir_try!(
+        req,
+        res,
+        ErrorCode::InvalidRequest,
+        labels::validate(&origin.labels),
+        "invalid labels"
+    );
+
+    if let Some(profile) = server_try!(req, res, query_param(req, "profile"), "invalid profile") {
+        match &origin.profile {
+            // a token bound to a profile can't be used to request another one
+            Some(token_profile) if *token_profile != profile => {
+                let detail = format!(
+                    "Requested a certificate of an unauthorized profile: {}, expected: {}",
+                    profile, token_profile
+                );
+                log::error!("{}", detail);
+                write_problem(req, res, ErrorCode::Unauthorized, detail);
+                return;
+            }
+            _ => origin.profile = Some(profile),
+        }
+    }
+
+    let requested_issuer = server_try!(req, res, query_param(req, "issuer"), "invalid issuer");
+
+    let (alt_name_policy, validity_days, issuer, ca_name) = {
+        let conf = controller_data.read_conf();
+        let profile = saphir_try!(
+            req,
+            res,
+            ErrorCode::InvalidRequest,
+            profiles::select(&conf, origin.profile.as_deref()),
+            "invalid certificate profile"
+        );
+
+        let issuer = match (requested_issuer, profile.and_then(|profile| profile.issuer.clone())) {
+            (Some(requested_issuer), Some(profile_issuer)) if requested_issuer != profile_issuer => {
+                let detail = format!(
+                    "Requested issuer {} but certificates of profile {} are issued by {}",
+                    requested_issuer,
+                    origin.profile.as_deref().unwrap_or_default(),
+                    profile_issuer
+                );
+                log::error!("{}", detail);
+                write_problem(req, res, ErrorCode::PolicyViolation, detail);
+                return;
+            }
+            (requested_issuer, profile_issuer) => requested_issuer.or(profile_issuer),
+        };
+        let ca_name = saphir_try!(
+            req,
+            res,
+            ErrorCode::InvalidRequest,
+            conf.issuer_ca_name(issuer.as_deref()),
+            "invalid issuer"
+        );
+
+        (
+            profiles::alt_name_policy(&conf, profile).clone(),
+            profiles::validity_days(profile),
+            issuer,
+            ca_name,
+        )
     };
 
-    let cert_der = match controller_data.storage.get_cert_by_addressing_hash(&canonical_address) {
-        Ok(cert_der) => cert_der,
-        Err(e) => {
-            log::error!("couldn't fetch certificate using hash {}: {}", canonical_address, e);
+    let body_alt_names = AltNames {
+        dns_names: Vec::new(),
+        ip_addresses: server_try!(
+            req,
+            res,
+            extract_json_field(req, "ip_addresses"),
+            "invalid IP addresses"
+        ),
+        uris: server_try!(req, res, extract_json_field(req, "uris"), "invalid URIs"),
+    };
+    alt_names.extend(body_alt_names);
+
+    if alt_name_policy.honor_csr_alt_names {
+        let csr_alt_names = saphir_try!(
+            req,
+            res,
+            ErrorCode::InvalidRequest,
+            AltNames::from_csr(&csr),
+            "invalid subject alternative names in CSR"
+        );
+        saphir_try!(
+            req,
+            res,
+            ErrorCode::PolicyViolation,
+            alt_name_policy.check_csr_dns_names(&csr_alt_names),
+            "subject alternative names refused"
+        );
+        alt_names.extend(csr_alt_names);
+    }
+
+    saphir_try!(
+        req,
+        res,
+        ErrorCode::PolicyViolation,
+        timings::measure(Phase::Policy, || alt_name_policy.check(&alt_names)),
+        "subject alternative names refused"
+    );
+
+    if let Some(locked_subject_name) = locked_subject_name {
+        let subject_name = unwrap_opt!(
+            req,
+            res,
+            ErrorCode::InvalidRequest,
+            csr.subject_name().find_common_name(),
+            "couldn't find signed CSR subject common name"
+        )
+        .to_string();
+
+        if locked_subject_name != subject_name {
+            let detail = format!(
+                "Requested a certificate with an unauthorized subject name: {}, expected: {}",
+                subject_name, locked_subject_name
+            );
+            log::error!("{}", detail);
+            write_problem(req, res, ErrorCode::CsrSubjectMismatch, detail);
             return;
         }
-    };
+    }
+
+    if controller_data.read_conf().approval_required {
+        // checked again on approval, this only refuses requests which can't be signed early
+        let lint_policy = controller_data.read_conf().lint_policy.clone();
+        let lint_findings = lint::lint_csr(&csr, &alt_names, validity_days, &lint_policy);
+        saphir_try!(
+            req,
+            res,
+            ErrorCode::PolicyViolation,
+            lint::check(&lint_findings, &lint_policy),
+            "refused by lint policy"
+        );
+
+        let entry = SigningRequestEntry {
+            id: new_request_id(),
+            subject_name: csr.subject_name().to_string(),
+            csr: saphir_try!(
+                req,
+                res,
+                ErrorCode::InvalidRequest,
+                csr.to_der(),
+                "couldn't serialize csr into der"
+            ),
+            submitted_at: unix_epoch(),
+            requested_by: origin.requested_by,
+            labels: origin.labels,
+            alt_names,
+            profile: origin.profile,
+            issuer,
+            status: SigningRequestStatus::Pending,
+        };
+        let id = entry.id.clone();
+
+        server_try!(
+            req,
+            res,
+            controller_data.storage.store_signing_request(entry),
+            "couldn't queue signing request"
+        );
+        log::info!("signing request {} is pending approval", id);
+        server_try!(
+            req,
+            res,
+            audit::append(
+                controller_data.storage.as_ref(),
+                AuditEvent::SigningRequestQueued,
+                json!({ "id": id, "subject": csr.subject_name().to_string(), "lint": lint_findings }),
+            ),
+            "couldn't record audit event"
+        );
+
+        let location = format!(
+            "{}{}/{}",
+            public_path_prefix(&controller_data.read_conf(), req),
+            versioning::versioned("/requests"),
+            id
+        );
+        server_try!(
+            req,
+            res,
+            write_json(
+                controller_data,
+                res,
+                json!({ "id": id, "status": "pending" }).to_string(),
+            ),
+            "couldn't sign response"
+        );
+        res.header(header::LOCATION, location);
+        res.status(StatusCode::ACCEPTED);
+        return;
+    }
+
+    // Sign CSR
+    server_try!(req, res, check_hierarchy_valid(controller_data), "issuance disabled");
+    let conf = controller_data.read_conf();
+    let signed_cert = server_try!(
+        req,
+        res,
+        sign_certificate(
+            &ca_name,
+            csr,
+            &alt_names,
+            &conf,
+            controller_data.storage.as_ref(),
+            &controller_data.signer,
+            origin
+        )
+    );
+    drop(conf); // release lock early
+
+    let issuance_timings = if issuance_timings { timings::finish() } else { None };
+    if let Some(issuance_timings) = &issuance_timings {
+        res.header(SERVER_TIMING_HEADER, issuance_timings.server_timing());
+    }
 
     let response_format = Format::response_format(req).unwrap_or(Format::PemFile);
     match response_format {
         Format::PemFile => {
-            res.body(to_pem("CERTIFICATE", &cert_der));
+            let pem = saphir_try!(
+                req,
+                res,
+                ErrorCode::IssuanceFailed,
+                signed_cert.to_pem(),
+                "couldn't get certificate pem"
+            );
+            res.body(pem.to_string());
         }
         Format::PkixCertBinary => {
-            res.body(cert_der);
+            let der = saphir_try!(
+                req,
+                res,
+                ErrorCode::IssuanceFailed,
+                signed_cert.to_der(),
+                "couldn't get certificate der"
+            );
+            res.body(der);
         }
         Format::PkixCertBase64 => {
-            res.body(base64::encode(&cert_der));
+            let der = saphir_try!(
+                req,
+                res,
+                ErrorCode::IssuanceFailed,
+                signed_cert.to_der(),
+                "couldn't get certificate der"
+            );
+            res.body(base64::encode(&der));
+        }
+        Format::Json => {
+            let der = saphir_try!(
+                req,
+                res,
+                ErrorCode::IssuanceFailed,
+                signed_cert.to_der(),
+                "couldn't get certificate der"
+            );
+            let addresses = saphir_try!(
+                req,
+                res,
+                ErrorCode::InternalError,
+                encode_to_addresses(&der),
+                "couldn't compute certificate addresses"
+            );
+            let mut body = json!({
+                "certificate": to_pem("CERTIFICATE", &der),
+                "address": addresses.address,
+                "alternative_addresses": addresses.alternative_addresses,
+            });
+            if let Some(issuance_timings) = &issuance_timings {
+                body["timings"] = issuance_timings.to_json();
+            }
+            server_try!(
+                req,
+                res,
+                write_json(controller_data, res, body.to_string()),
+                "couldn't sign response"
+            );
         }
         unexpected => {
-            log::error!("unexpected response format: {}", unexpected);
+            let detail = format!("unexpected response format: {}", unexpected);
+            log::error!("{}", detail);
+            write_problem(req, res, ErrorCode::UnsupportedFormat, detail);
             return;
         }
     }
@@ -445,371 +1403,4192 @@ fn get_cert(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncR
     res.status(StatusCode::OK);
 }
 
-// === chain ===
+/// Optional field of JSON requests (labels, subject alternative names, ...), other formats can't
+/// carry them.
+fn extract_json_field<T: DeserializeOwned + Default>(req: &SyncRequest, field: &str) -> Result<T, ServerError> {
+    if Format::request_format(req) != Ok(Format::Json) {
+        return Ok(T::default());
+    }
 
-fn get_default_chain(controller_data: &ControllerData, _: &SyncRequest, res: &mut SyncResponse) {
-    res.status(StatusCode::BAD_REQUEST);
-    let ca = format!("{} Authority", &controller_data.read_conf().realm);
-    let chain = saphir_try!(find_ca_chain(controller_data.storage.as_ref(), &ca));
-    res.body(chain.join("\n"));
-    res.status(StatusCode::OK);
+    let json = serde_json::from_slice::<Value>(req.body())?;
+    match json.get(field) {
+        Some(value) => serde_json::from_value(value.clone()).map_err(|e| ServerError::InvalidRequest {
+            description: format!("invalid '{}': {}", field, e),
+        }),
+        None => Ok(T::default()),
+    }
 }
 
-fn find_ca_chain(storage: &dyn PickyStorage, ca_name: &str) -> Result<Vec<String>, String> {
-    let ca_hash = storage
-        .get_addressing_hash_by_name(ca_name)
-        .map_err(|e| format!("couldn't fetch CA hash id for {}: {}", ca_name, e))?;
+fn extract_csr_from_request(req: &SyncRequest, strict_formats: bool) -> Result<Csr, ServerError> {
+    let request_format = Format::checked_request_format(req, strict_formats)?;
+    match request_format {
+        Format::PemFile => {
+            let pem = parse_pem(req.body())?;
+            Ok(Csr::from_der(pem.data())?)
+        }
+        Format::Json => {
+            let json = serde_json::from_slice::<Value>(req.body())?;
+            let pem = json["csr"]
+                .to_string()
+                .trim_matches('"')
+                .replace("\\n", "\n")
+                .parse::<Pem>()?;
+            Ok(Csr::from_der(pem.data())?)
+        }
+        Format::Pkcs10Binary => Ok(Csr::from_der(req.body())?),
+        Format::Pkcs10Base64 => {
+            let der = base64::decode(&req.body())?;
+            Ok(Csr::from_der(&der)?)
+        }
+        unexpected => Err(ServerError::InvalidRequest {
+            description: format!("unexpected request format: {}", unexpected),
+        }),
+    }
+}
 
-    let mut cert_der = storage
-        .get_cert_by_addressing_hash(&ca_hash)
-        .map_err(|e| format!("couldn't fetch CA certificate der: {}", e))?;
-    let mut chain = vec![to_pem("CERTIFICATE", &cert_der)];
-    let mut current_key_id = String::default();
-    loop {
-        let cert = Cert::from_der(&cert_der).map_err(|e| format!("couldn't deserialize certificate: {}", e))?;
+// === generate_key_and_certificate === //
+
+const GENERATED_KEY_BITS: usize = 2048;
+const GENERATED_PASSWORD_LEN: usize = 24;
+const GENERATED_PASSWORD_CHARSET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz23456789";
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GenerateRequest {
+    /// Subject common name, also included in the subject alternative names
+    subject: String,
+    /// Additional DNS subject alternative names, which token requesters only get when vouched for by
+    /// their token or within the `csr_dns_subtrees`
+    #[serde(default)]
+    dns_names: Vec<String>,
+    /// IP address subject alternative names, subject to the `alt_name_policy`
+    #[serde(default)]
+    ip_addresses: Vec<IpAddr>,
+    /// URI subject alternative names, subject to the `alt_name_policy`
+    #[serde(default)]
+    uris: Vec<String>,
+    /// Password protecting the PKCS#12 archive, generated if missing
+    password: Option<String>,
+    /// Labels attached to the certificate, completed by the ones of the token claims
+    #[serde(default)]
+    labels: Labels,
+}
 
-        let parent_key_id = hex::encode(
-            cert.authority_key_identifier()
-                .map_err(|e| format!("couldn't fetch authority key identifier: {}", e))?
-                .key_identifier()
-                .ok_or_else(|| "parent key identifier not found".to_owned())?,
+fn generate_key_and_certificate(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    let (locked_subject_name, mut origin, mut alt_names) = server_try!(
+        req,
+        res,
+        issuance_requester(&controller_data.read_conf(), req),
+        "authorization failed"
+    );
+
+    let request: GenerateRequest = saphir_try!(
+        req,
+        res,
+        ErrorCode::InvalidRequest,
+        serde_json::from_slice(req.body()),
+        "invalid key generation request"
+    );
+
+    origin.labels = labels::merge(request.labels, origin.labels);
+    saphir_try!(
+        req,
+        res,
+        ErrorCode::InvalidRequest,
+        labels::validate(&origin.labels),
+        "invalid labels"
+    );
+
+    // like names requested within CSRs, a token requester only gets the DNS names vouched for by its
+    // token or belonging to the `csr_dns_subtrees`
+    if locked_subject_name.is_some() {
+        saphir_try!(
+            req,
+            res,
+            ErrorCode::PolicyViolation,
+            controller_data
+                .read_conf()
+                .alt_name_policy
+                .check_requested_dns_names(&request.dns_names, &alt_names.dns_names),
+            "subject alternative names refused"
         );
+    }
 
-        if current_key_id == parent_key_id {
-            // The authority is itself. It is a root.
-            break;
+    alt_names.extend(AltNames {
+        dns_names: request.dns_names,
+        ip_addresses: request.ip_addresses,
+        uris: request.uris,
+    });
+    saphir_try!(
+        req,
+        res,
+        ErrorCode::PolicyViolation,
+        controller_data.read_conf().alt_name_policy.check(&alt_names),
+        "subject alternative names refused"
+    );
+
+    if let Some(locked_subject_name) = locked_subject_name {
+        if locked_subject_name != request.subject {
+            let detail = format!(
+                "Requested a certificate with an unauthorized subject name: {}, expected: {}",
+                request.subject, locked_subject_name
+            );
+            log::error!("{}", detail);
+            write_problem(req, res, ErrorCode::CsrSubjectMismatch, detail);
+            return;
         }
+    }
 
-        let hash_address = storage
-            .get_addressing_hash_by_key_identifier(&parent_key_id)
-            .map_err(|e| format!("couldn't fetch hash: {}", e))?;
+    if controller_data.read_conf().approval_required {
+        // the private key is never persisted, so it can't wait for an approval
+        let detail = "server-side key generation is unavailable when approval is required";
+        log::error!("{}", detail);
+        write_problem(req, res, ErrorCode::PolicyViolation, detail.to_owned());
+        return;
+    }
 
-        cert_der = storage
-            .get_cert_by_addressing_hash(&hash_address)
-            .map_err(|e| format!("couldn't fetch certificate der: {}", e))?;
+    let (pk, chain) = server_try!(
+        req,
+        res,
+        generate_identity(controller_data, &request.subject, &alt_names, origin)
+    );
 
-        chain.push(to_pem("CERTIFICATE", &cert_der));
+    let (password, generated) = match request.password {
+        Some(password) => (password, false),
+        None => (generate_password(), true),
+    };
 
-        current_key_id = parent_key_id;
-    }
+    let archive = server_try!(
+        req,
+        res,
+        build_pkcs12(Some(pk), &chain, Some(&request.subject), &password)
+    );
 
-    Ok(chain)
+    // the password is only sent back when the server generated it
+    server_try!(
+        req,
+        res,
+        write_json(
+            controller_data,
+            res,
+            json!({
+                "pkcs12": base64::encode(&archive),
+                "password": if generated { Some(password) } else { None },
+            })
+            .to_string(),
+        ),
+        "couldn't sign response"
+    );
+    res.status(StatusCode::OK);
 }
 
-// === generate root CA === //
+/// Generates a private key and issues a certificate for `subject` by the intermediate CA.
+///
+/// Returns the key along with the DER certificate chain, leaf first.
+fn generate_identity(
+    controller_data: &ControllerData,
+    subject: &str,
+    alt_names: &AltNames,
+    origin: IssuanceOrigin,
+) -> Result<(PrivateKey, Vec<Vec<u8>>), ServerError> {
+    check_hierarchy_valid(controller_data)?;
+
+    let pk = Picky::generate_private_key(GENERATED_KEY_BITS).map_err(|source| ServerError::Issuance {
+        context: "couldn't generate private key".to_owned(),
+        source,
+    })?;
+    let csr = Csr::generate(
+        DirectoryName::new_common_name(subject),
+        &pk,
+        SignatureHashType::RsaSha256,
+    )
+    .map_err(|e| ServerError::Internal {
+        description: format!("couldn't generate csr: {}", e),
+    })?;
 
-fn generate_root_ca(config: &Config, storage: &dyn PickyStorage) -> Result<bool, String> {
-    let name = format!("{} Root CA", config.realm);
+    let conf = controller_data.read_conf();
+    let ca_name = format!("{} Authority", &conf.realm);
+    let signed_cert = sign_certificate(
+        &ca_name,
+        csr,
+        alt_names,
+        &conf,
+        controller_data.storage.as_ref(),
+        &controller_data.signer,
+        origin,
+    )?;
+    drop(conf); // release lock early
 
-    if let Ok(certs) = storage.get_addressing_hash_by_name(&name) {
-        if !certs.is_empty() {
-            // already exists
-            return Ok(false);
-        }
-    }
+    let leaf = signed_cert.to_der().map_err(|e| ServerError::Internal {
+        description: format!("couldn't get certificate der: {}", e),
+    })?;
+    let mut chain = current_ca_chain_der(controller_data)?;
+    chain.insert(0, leaf);
 
-    let pk = Picky::generate_private_key(4096).map_err(|e| format!("couldn't generate private key: {}", e))?;
-    let root = Picky::generate_root(&name, &pk, config.signing_algorithm)
-        .map_err(|e| format!("couldn't generate root certificate: {}", e))?;
-    let ski = root
-        .subject_key_identifier()
-        .map_err(|e| format!("couldn't fetch subject key identifier: {}", e))?;
+    Ok((pk, chain))
+}
 
-    let cert_der = root
-        .to_der()
-        .map_err(|e| format!("couldn't serialize root certificate into der: {}", e))?;
+fn generate_password() -> String {
+    crate::random::with_rng(|rng| {
+        (0..GENERATED_PASSWORD_LEN)
+            .map(|_| char::from(GENERATED_PASSWORD_CHARSET[rng.gen_range(0, GENERATED_PASSWORD_CHARSET.len())]))
+            .collect()
+    })
+}
 
-    let pk_pkcs8 = pk
-        .to_pkcs8()
-        .map_err(|e| format!("couldn't get private key pkcs8: {}", e))?;
+// === sign_certificate === //
+
+/// Who requested a certificate and the labels attached to it, stored alongside the certificate
+#[derive(Debug, Clone, Default)]
+struct IssuanceOrigin {
+    /// `api-key` or `token:<subject>`
+    requested_by: Option<String>,
+    labels: Labels,
+    /// Base URL of the request derived from trusted forwarded headers, see `forwarded_base_url`
+    base_url: Option<String>,
+    /// Certificate profile the certificate is issued with, the default leaf shape if unset
+    profile: Option<String>,
+}
 
-    storage
-        .store(CertificateEntry {
-            name,
-            cert: cert_der,
-            key_identifier: hex::encode(ski),
-            key: Some(pk_pkcs8),
+/// `alt_names` are added to the subject alternative names along with the subject common name, which
+/// is an IP address SAN if it parses as one. When the CSR subject has no common name, the first
+/// requested name stands for it.
+fn sign_certificate(
+    ca_name: &str,
+    csr: Csr,
+    alt_names: &AltNames,
+    config: &Config,
+    storage: &dyn PickyStorage,
+    signer: &CaSigner,
+    origin: IssuanceOrigin,
+) -> Result<Cert, ServerError> {
+    let profile = profiles::select(config, origin.profile.as_deref()).map_err(|e| ServerError::InvalidRequest {
+        description: e.to_string(),
+    })?;
+
+    let (ca_hash, ca_cert_der) = timings::measure(Phase::Storage, || {
+        let ca_hash = storage
+            .get_addressing_hash_by_name(ca_name)
+            .map_err(ServerError::storage("couldn't fetch CA"))?;
+        let ca_cert_der = storage
+            .get_cert_by_addressing_hash(&ca_hash)
+            .map_err(ServerError::storage("couldn't get CA cert der"))?;
+        Ok::<_, ServerError>((ca_hash, ca_cert_der))
+    })?;
+    let ca_cert = cert_cache::parse(&ca_cert_der).map_err(|e| ServerError::Internal {
+        description: format!("couldn't deserialize CA cert: {}", e),
+    })?;
+
+    timings::measure(Phase::Policy, || {
+        key_usage::check_before_signing(storage, ca_name, &ca_cert, &config.key_usage_limits)
+    })?;
+
+    let lint_findings = timings::measure(Phase::Policy, || {
+        // the common name is authorized along with the subject, only requested names are subject to the policy
+        profiles::alt_name_policy(config, profile)
+            .check(alt_names)
+            .map_err(|e| ServerError::PolicyViolation {
+                description: e.to_string(),
+            })?;
+
+        let lint_findings = lint::lint_csr(&csr, alt_names, profiles::validity_days(profile), &config.lint_policy);
+        lint::check(&lint_findings, &config.lint_policy).map_err(|e| ServerError::PolicyViolation {
+            description: format!("refused by lint policy: {}", e),
+        })?;
+
+        Ok::<_, ServerError>(lint_findings)
+    })?;
+
+    // the subject common name is the primary name, SAN-only requests may omit it
+    let mut leaf_alt_names = AltNames::default();
+    let dns_name = match csr.subject_name().find_common_name() {
+        Some(common_name) => {
+            let common_name = common_name.to_string();
+            if profile.map_or(true, |profile| profile.common_name_alt_name) {
+                match common_name.parse::<IpAddr>() {
+                    Ok(ip_address) => leaf_alt_names.ip_addresses.push(ip_address),
+                    Err(_) => leaf_alt_names.dns_names.push(common_name.clone()),
+                }
+            }
+            common_name
+        }
+        None => alt_names
+            .dns_names
+            .first()
+            .cloned()
+            .or_else(|| alt_names.ip_addresses.first().map(IpAddr::to_string))
+            .or_else(|| alt_names.uris.first().cloned())
+            .ok_or_else(|| ServerError::InvalidRequest {
+                description: "couldn't find signed cert subject common name nor any subject alternative name"
+                    .to_owned(),
+            })?,
+    };
+    leaf_alt_names.extend(alt_names.clone());
+
+    let serial_number = picky_controller::serial_number().map_err(|source| ServerError::Issuance {
+        context: "couldn't generate serial number".to_owned(),
+        source,
+    })?;
+    let serial_number_hex = hex::encode(&serial_number);
+    let issuer = config.issuer_of_ca(ca_name);
+    let ocsp_url = config.leaf_ocsp_url(origin.base_url.as_deref());
+    let ca_issuers_url = config.leaf_ca_issuers_url(issuer, origin.base_url.as_deref());
+    let crl_url = config.leaf_crl_url(issuer, &serial_number, origin.base_url.as_deref());
+    let base_options = match profile {
+        Some(profile) => profile.issuer_options().map_err(|e| ServerError::Internal {
+            description: e.to_string(),
+        })?,
+        None => IssuerOptions::leaf(),
+    };
+    let options = IssuerOptions {
+        alt_names: leaf_alt_names
+            .to_general_names()
+            .map_err(|e| ServerError::InvalidRequest {
+                description: e.to_string(),
+            })?,
+        empty_subject: config.empty_leaf_subject,
+        serial_number: SerialNumber::Fixed(serial_number),
+        urls: LeafUrls {
+            ocsp: ocsp_url.as_deref(),
+            ca_issuers: ca_issuers_url.as_deref(),
+            crl: crl_url.as_deref(),
+        },
+        ..base_options
+    };
+    let signed_cert = timings::measure(Phase::Signing, || {
+        signer.sign_leaf(
+            &ca_hash,
+            &ca_cert,
+            csr,
+            config.issuer_leaf_signing_algorithm(ca_name),
+            options,
+        )
+    })?;
+
+    let cert_der = signed_cert.to_der().map_err(|e| ServerError::Internal {
+        description: format!("couldn't serialize certificate to der: {}", e),
+    })?;
+    let address = encode_to_canonical_address(&cert_der).map_err(|e| ServerError::Internal {
+        description: format!("couldn't compute certificate address: {}", e),
+    })?;
+
+    timings::measure(Phase::Storage, || {
+        // the issuance log is made of these records, see `issuance_log`: a certificate missing from the log
+        // mustn't be handed out
+        audit::append(
+            storage,
+            AuditEvent::CertificateIssued,
+            json!({
+                "issuer": ca_name,
+                "subject": dns_name,
+                "serial_number": serial_number_hex,
+                "address": address,
+                "log_leaf_hash": base64::encode(&issuance_log::leaf_hash(&cert_der)),
+                "requested_by": origin.requested_by,
+                "labels": origin.labels,
+                "profile": origin.profile,
+                "lint": lint_findings,
+            }),
+        )
+        .map_err(|e| ServerError::Internal {
+            description: format!("couldn't append certificate to the issuance log: {}", e),
         })
-        .map_err(|e| format!("couldn't store generated root certificate: {}", e))?;
+    })?;
 
-    Ok(true)
-}
-
-// === generate intermediate CA === //
+    if config.save_certificate {
+        let ski = hex::encode(
+            signed_cert
+                .subject_key_identifier()
+                .map_err(|e| ServerError::Internal {
+                    description: format!("couldn't get SKI: {}", e),
+                })?,
+        );
 
-fn generate_intermediate_ca(config: &Config, storage: &dyn PickyStorage) -> Result<bool, String> {
-    let root_name = format!("{} Root CA", config.realm);
-    let intermediate_name = format!("{} Authority", config.realm);
+        let entry = CertificateEntry {
+            name: dns_name.clone(),
+            cert: cert_der,
+            key_identifier: ski,
+            key: None,
+            requested_by: origin.requested_by,
+            labels: origin.labels,
+        };
 
-    if let Ok(certs) = storage.get_addressing_hash_by_name(&intermediate_name) {
-        if !certs.is_empty() {
-            // already exists
-            return Ok(false);
+        if let Err(e) = timings::measure(Phase::Storage, || storage.store(entry.clone())) {
+            match &config.storage_spool {
+                Some(spool_config) => {
+                    log::warn!("couldn't save leaf {}, spooling it: {}", dns_name, e);
+                    spool::spool(spool_config, entry).map_err(|e| ServerError::Internal {
+                        description: e.to_string(),
+                    })?;
+                }
+                None => {
+                    return Err(ServerError::storage(format!("insertion error for leaf {}", dns_name))(
+                        e,
+                    ))
+                }
+            }
         }
+    } else {
+        // stored certificates are counted by the `key_usage::SignatureCounter` storage observer
+        key_usage::record_signature(storage, &ca_cert);
     }
 
-    let (root_cert_der, root_key_der) = match storage.get_addressing_hash_by_name(&root_name) {
-        Ok(root_hash) => (
-            storage
-                .get_cert_by_addressing_hash(&root_hash)
-                .map_err(|e| format!("couldn't fetch root CA: {}", e))?,
-            storage
-                .get_key_by_addressing_hash(&root_hash)
-                .map_err(|e| format!("couldn't fetch root CA private key: {}", e))?,
-        ),
-        Err(e) => {
-            return Err(format!("error while fetching root: {}", e));
-        }
-    };
+    Ok(signed_cert)
+}
 
-    let pk = Picky::generate_private_key(2048).map_err(|e| e.to_string())?;
-    let root_cert = Cert::from_der(&root_cert_der).map_err(|e| format!("couldn't parse root cert from der: {}", e))?;
-    let root_key = Picky::parse_pk_from_magic_der(&root_key_der).map_err(|e| e.to_string())?;
+// === get_cert === //
 
-    let intermediate_cert = Picky::generate_intermediate(
-        &intermediate_name,
-        pk.to_public_key(),
-        &root_cert,
-        &root_key,
-        config.signing_algorithm,
-    )
-    .map_err(|e| format!("couldn't generate intermediate certificate: {}", e))?;
+fn get_cert(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    let addressing_hash_any_base = unwrap_opt!(
+        req,
+        res,
+        ErrorCode::InvalidRequest,
+        req.captures().get("multihash"),
+        "multihash is missing"
+    );
+    let canonical_address = server_try!(
+        req,
+        res,
+        canonical_cert_address(controller_data.storage.as_ref(), addressing_hash_any_base)
+    );
 
-    let ski = intermediate_cert
-        .subject_key_identifier()
-        .map_err(|e| format!("couldn't fetch key id: {}", e))?;
+    let cert_der = server_try!(
+        req,
+        res,
+        controller_data
+            .storage
+            .get_cert_by_addressing_hash(&canonical_address)
+            .map_err(ServerError::storage(format!(
+                "couldn't fetch certificate using hash {}",
+                canonical_address
+            )))
+    );
 
-    let cert_der = intermediate_cert
-        .to_der()
-        .map_err(|e| format!("couldn't serialize intermediate certificate into der: {}", e))?;
+    res.header(header::VARY, "Accept");
+    if let Some(representation) = cert_representation(req) {
+        if write_immutable_headers(req, res, &entity_tag(&canonical_address, representation)) {
+            return;
+        }
+    }
 
-    let pk_pkcs8 = pk
-        .to_pkcs8()
-        .map_err(|e| format!("couldn't get private key pkcs8: {}", e))?;
+    write_cert(req, res, cert_der);
+}
 
-    storage
-        .store(CertificateEntry {
-            name: intermediate_name,
-            cert: cert_der,
-            key_identifier: hex::encode(ski),
-            key: Some(pk_pkcs8),
-        })
-        .map_err(|e| format!("couldn't store generated intermediate certificate: {}", e))?;
+/// Converts a certificate multihash in any supported base and hash to its canonical address.
+fn canonical_cert_address(storage: &dyn PickyStorage, address_any_base: &str) -> Result<String, ServerError> {
+    let (addressing_hash, hash) =
+        convert_to_canonical_base(address_any_base).map_err(|e| ServerError::InvalidRequest {
+            description: format!("invalid address: {}", e),
+        })?;
 
-    Ok(true)
+    if hash == CANONICAL_HASH {
+        return Ok(addressing_hash);
+    }
+
+    let converted = storage
+        .lookup_addressing_hash(&addressing_hash)
+        .map_err(ServerError::storage("couldn't convert address"))?;
+    log::info!("converted cert address {} -> {}", address_any_base, converted);
+    Ok(converted)
 }
 
-// === inject config provided certificates in picky storage === //
+fn cert_representation(req: &SyncRequest) -> Option<&'static str> {
+    match Format::response_format(req).unwrap_or(Format::PemFile) {
+        Format::PemFile => Some("pem"),
+        Format::PkixCertBinary => Some("der"),
+        Format::PkixCertBase64 => Some("base64"),
+        _ => None,
+    }
+}
 
-fn inject_config_provided_cert(
-    expected_subject_name: &str,
-    cert_key_pair: &CertKeyPair,
-    storage: &dyn PickyStorage,
-) -> Result<(), String> {
-    let (cert, cert_der) = match &cert_key_pair.cert {
-        PathOr::Path(path) => {
-            let pem_str = std::fs::read_to_string(path).map_err(|e| format!("couldn't read cert: {}", e))?;
-            let pem = pem_str
-                .parse::<Pem>()
-                .map_err(|e| format!("couldn't parse cert pem: {}", e))?;
-            let cert = Cert::from_pem(&pem).map_err(|e| format!("couldn't parse cert: {}", e))?;
-            (Cow::Owned(cert), pem.into_data().into_owned())
+fn write_cert(req: &SyncRequest, res: &mut SyncResponse, cert_der: Vec<u8>) {
+    let response_format = Format::response_format(req).unwrap_or(Format::PemFile);
+    match response_format {
+        Format::PemFile => {
+            res.body(to_pem("CERTIFICATE", &cert_der));
+        }
+        Format::PkixCertBinary => {
+            res.body(cert_der);
+        }
+        Format::PkixCertBase64 => {
+            res.body(base64::encode(&cert_der));
+        }
+        unexpected => {
+            let detail = format!("unexpected response format: {}", unexpected);
+            log::error!("{}", detail);
+            write_problem(req, res, ErrorCode::UnsupportedFormat, detail);
+            return;
+        }
+    }
+
+    res.status(StatusCode::OK);
+}
+
+// === get_artifact === //
+
+fn get_artifact(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    let namespace = unwrap_opt!(
+        req,
+        res,
+        ErrorCode::NotFound,
+        req.captures()
+            .get("namespace")
+            .and_then(|namespace| ArtifactNamespace::parse(namespace)),
+        "unknown artifact namespace"
+    );
+    let addressing_hash_any_base = unwrap_opt!(
+        req,
+        res,
+        ErrorCode::InvalidRequest,
+        req.captures().get("multihash"),
+        "multihash is missing"
+    );
+    let (addressing_hash, hash) = saphir_try!(
+        req,
+        res,
+        ErrorCode::InvalidRequest,
+        convert_to_canonical_base(addressing_hash_any_base),
+        "invalid address"
+    );
+    if hash != CANONICAL_HASH {
+        write_problem(
+            req,
+            res,
+            ErrorCode::InvalidRequest,
+            format!("artifacts are only addressed using {:?}", CANONICAL_HASH),
+        );
+        return;
+    }
+
+    let artifact = server_try!(
+        req,
+        res,
+        controller_data
+            .storage
+            .get_artifact_by_addressing_hash(namespace, &addressing_hash),
+        "couldn't fetch artifact"
+    );
+
+    if write_immutable_headers(req, res, &entity_tag(&addressing_hash, "der")) {
+        return;
+    }
+
+    res.header(header::CONTENT_TYPE, namespace.content_type());
+    res.body(artifact);
+    res.status(StatusCode::OK);
+}
+
+// === signing requests approval === //
+
+fn check_admin_authorization(controller_data: &ControllerData, req: &SyncRequest) -> Result<(), ServerError> {
+    match check_authorization(&controller_data.read_conf(), req).map_err(|e| ServerError::Unauthorized {
+        description: e.to_string(),
+    })? {
+        Authorized::ApiKey => Ok(()),
+        Authorized::Token(_) => Err(ServerError::Unauthorized {
+            description: "administrator API key is required".to_owned(),
+        }),
+    }
+}
+
+fn find_signing_request(storage: &dyn PickyStorage, id: &str) -> Result<SigningRequestEntry, ServerError> {
+    // request ids are generated by the server and are always hexadecimal strings
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(ServerError::InvalidRequest {
+            description: format!("invalid signing request id: {}", id),
+        });
+    }
+
+    storage
+        .get_signing_request(id)
+        .map_err(ServerError::storage(format!("couldn't fetch signing request {}", id)))
+}
+
+fn get_signing_requests(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    server_try!(
+        req,
+        res,
+        check_admin_authorization(controller_data, req),
+        "authorization failed"
+    );
+
+    let entries = server_try!(
+        req,
+        res,
+        controller_data.storage.get_signing_requests(),
+        "couldn't fetch signing requests"
+    );
+
+    let pending = entries
+        .into_iter()
+        .filter(|entry| entry.status == SigningRequestStatus::Pending)
+        .map(|entry| {
+            json!({
+                "id": entry.id,
+                "subject_name": entry.subject_name,
+                "submitted_at": entry.submitted_at,
+                "csr": to_pem("CERTIFICATE REQUEST", &entry.csr),
+            })
+        })
+        .collect::<Vec<Value>>();
+
+    server_try!(
+        req,
+        res,
+        write_json(controller_data, res, Value::Array(pending).to_string()),
+        "couldn't sign response"
+    );
+    res.status(StatusCode::OK);
+}
+
+fn get_signing_request(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    let id = unwrap_opt!(
+        req,
+        res,
+        ErrorCode::InvalidRequest,
+        req.captures().get("id"),
+        "signing request id is missing"
+    );
+    let entry = server_try!(
+        req,
+        res,
+        find_signing_request(controller_data.storage.as_ref(), id),
+        "couldn't find signing request"
+    );
+
+    match entry.status {
+        SigningRequestStatus::Pending => {
+            server_try!(
+                req,
+                res,
+                write_json(
+                    controller_data,
+                    res,
+                    json!({ "id": entry.id, "status": "pending" }).to_string(),
+                ),
+                "couldn't sign response"
+            );
+            res.status(StatusCode::ACCEPTED);
+        }
+        SigningRequestStatus::Issuing => {
+            server_try!(
+                req,
+                res,
+                write_json(
+                    controller_data,
+                    res,
+                    json!({ "id": entry.id, "status": "issuing" }).to_string(),
+                ),
+                "couldn't sign response"
+            );
+            res.status(StatusCode::ACCEPTED);
+        }
+        SigningRequestStatus::Approved { cert } => write_cert(req, res, cert),
+        SigningRequestStatus::Denied => write_problem(
+            req,
+            res,
+            ErrorCode::RequestDenied,
+            format!("signing request {} was denied", entry.id),
+        ),
+    }
+}
+
+fn approve_signing_request(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    server_try!(
+        req,
+        res,
+        check_admin_authorization(controller_data, req),
+        "authorization failed"
+    );
+
+    let id = unwrap_opt!(
+        req,
+        res,
+        ErrorCode::InvalidRequest,
+        req.captures().get("id"),
+        "signing request id is missing"
+    );
+    let entry = server_try!(
+        req,
+        res,
+        find_signing_request(controller_data.storage.as_ref(), id),
+        "couldn't find signing request"
+    );
+
+    // claimed before issuing, so that a concurrent approval or denial can't act on it too
+    let claimed = server_try!(
+        req,
+        res,
+        controller_data.storage.update_signing_request_status(
+            id,
+            &SigningRequestStatus::Pending,
+            SigningRequestStatus::Issuing
+        ),
+        "couldn't update signing request"
+    );
+    if !claimed {
+        write_not_pending(req, res, id);
+        return;
+    }
+
+    let issued = issue_signing_request(controller_data, req, &entry);
+    // the request is pending again if the certificate couldn't be issued
+    let status = match &issued {
+        Ok(cert_der) => SigningRequestStatus::Approved { cert: cert_der.clone() },
+        Err(_) => SigningRequestStatus::Pending,
+    };
+    server_try!(
+        req,
+        res,
+        controller_data
+            .storage
+            .update_signing_request_status(id, &SigningRequestStatus::Issuing, status),
+        "couldn't update signing request"
+    );
+    let cert_der = server_try!(req, res, issued, "couldn't approve signing request");
+    log::info!("signing request {} approved", id);
+    server_try!(
+        req,
+        res,
+        audit::append(
+            controller_data.storage.as_ref(),
+            AuditEvent::SigningRequestApproved,
+            json!({ "id": id }),
+        ),
+        "couldn't record audit event"
+    );
+
+    write_cert(req, res, cert_der);
+}
+
+/// Issues the certificate of a queued signing request, DER encoded.
+fn issue_signing_request(
+    controller_data: &ControllerData,
+    req: &SyncRequest,
+    entry: &SigningRequestEntry,
+) -> Result<Vec<u8>, ServerError> {
+    let csr = Csr::from_der(&entry.csr)?;
+
+    check_hierarchy_valid(controller_data)?;
+    let conf = controller_data.read_conf();
+    let ca_name = conf
+        .issuer_ca_name(entry.issuer.as_deref())
+        .map_err(|e| ServerError::InvalidRequest {
+            description: format!("invalid issuer: {}", e),
+        })?;
+    let signed_cert = sign_certificate(
+        &ca_name,
+        csr,
+        &entry.alt_names,
+        &conf,
+        controller_data.storage.as_ref(),
+        &controller_data.signer,
+        IssuanceOrigin {
+            requested_by: entry.requested_by.clone(),
+            labels: entry.labels.clone(),
+            base_url: forwarded_base_url(&conf, req),
+            profile: entry.profile.clone(),
+        },
+    )?;
+    drop(conf); // release lock early
+
+    signed_cert.to_der().map_err(|e| ServerError::Internal {
+        description: format!("couldn't get certificate der: {}", e),
+    })
+}
+
+fn write_not_pending(req: &SyncRequest, res: &mut SyncResponse, id: &str) {
+    let detail = format!("signing request {} is not pending", id);
+    log::error!("{}", detail);
+    write_problem(req, res, ErrorCode::PolicyViolation, detail);
+}
+
+fn deny_signing_request(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    server_try!(
+        req,
+        res,
+        check_admin_authorization(controller_data, req),
+        "authorization failed"
+    );
+
+    let id = unwrap_opt!(
+        req,
+        res,
+        ErrorCode::InvalidRequest,
+        req.captures().get("id"),
+        "signing request id is missing"
+    );
+    server_try!(
+        req,
+        res,
+        find_signing_request(controller_data.storage.as_ref(), id),
+        "couldn't find signing request"
+    );
+
+    let denied = server_try!(
+        req,
+        res,
+        controller_data.storage.update_signing_request_status(
+            id,
+            &SigningRequestStatus::Pending,
+            SigningRequestStatus::Denied
+        ),
+        "couldn't update signing request"
+    );
+    if !denied {
+        write_not_pending(req, res, id);
+        return;
+    }
+    log::info!("signing request {} denied", id);
+    server_try!(
+        req,
+        res,
+        audit::append(
+            controller_data.storage.as_ref(),
+            AuditEvent::SigningRequestDenied,
+            json!({ "id": id }),
+        ),
+        "couldn't record audit event"
+    );
+
+    res.status(StatusCode::OK);
+}
+
+// === certs inventory === //
+
+fn get_certs(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    server_try!(
+        req,
+        res,
+        check_admin_authorization(controller_data, req),
+        "authorization failed"
+    );
+
+    let requested_by = server_try!(
+        req,
+        res,
+        query_param(req, "requested_by"),
+        "invalid 'requested_by' query parameter"
+    );
+    let selector = match server_try!(req, res, query_param(req, "labels"), "invalid 'labels' query parameter") {
+        Some(selector) => Some(saphir_try!(
+            req,
+            res,
+            ErrorCode::InvalidRequest,
+            labels::parse_selector(&selector),
+            "invalid 'labels' query parameter"
+        )),
+        None => None,
+    };
+
+    let addresses = server_try!(
+        req,
+        res,
+        inventory_addresses(
+            controller_data.storage.as_ref(),
+            requested_by.as_deref(),
+            selector.as_ref()
+        ),
+        "couldn't fetch certificates"
+    );
+
+    server_try!(
+        req,
+        res,
+        write_json(
+            controller_data,
+            res,
+            json!({ "requested_by": requested_by, "labels": selector, "certificates": addresses }).to_string(),
+        ),
+        "couldn't sign response"
+    );
+    res.status(StatusCode::OK);
+}
+
+/// Addresses of the certificates requested by `requested_by` and carrying every label of `selector`.
+fn inventory_addresses(
+    storage: &dyn PickyStorage,
+    requested_by: Option<&str>,
+    selector: Option<&Labels>,
+) -> Result<Vec<String>, ServerError> {
+    let by_requester = match requested_by {
+        Some(requested_by) => Some(storage.get_addressing_hashes_by_requester(requested_by).map_err(
+            ServerError::storage(format!("couldn't fetch certificates requested by {}", requested_by)),
+        )?),
+        None => None,
+    };
+
+    let by_labels = match selector {
+        Some(selector) => Some(
+            storage
+                .get_addressing_hashes_by_labels(selector)
+                .map_err(ServerError::storage("couldn't fetch certificates by labels"))?,
+        ),
+        None => None,
+    };
+
+    match (by_requester, by_labels) {
+        (Some(by_requester), Some(by_labels)) => Ok(by_requester
+            .into_iter()
+            .filter(|address| by_labels.contains(address))
+            .collect()),
+        (Some(addresses), None) | (None, Some(addresses)) => Ok(addresses),
+        (None, None) => Err(ServerError::InvalidRequest {
+            description: "'requested_by' or 'labels' query parameter is required".to_owned(),
+        }),
+    }
+}
+
+// === revocation === //
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct RevocationRequest {
+    /// Multihash of the certificate, in any supported base and hash
+    address: Option<String>,
+    /// Hex-encoded serial number
+    serial_number: Option<String>,
+    /// Issuer (see `issuers`) of the certificate selected by serial number, defaults to the default issuing CA
+    issuer: Option<String>,
+    /// CRL reason code (RFC5280 section 5.3.1)
+    reason: Option<u8>,
+    /// Revocation time (seconds since UNIX epoch), defaults to now
+    revoked_at: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct BatchRevocationRequest {
+    /// Hex-encoded serial numbers
+    #[serde(default)]
+    serial_numbers: Vec<String>,
+    /// Issuer (see `issuers`) of the certificates selected by serial number, defaults to the default issuing CA
+    issuer: Option<String>,
+    /// Hex-encoded subject key identifiers
+    #[serde(default)]
+    key_identifiers: Vec<String>,
+    /// Selects every certificate issued to this identity (see certificate inventory)
+    requested_by: Option<String>,
+    /// Selects every certificate carrying all of these labels
+    #[serde(default)]
+    labels: Labels,
+    /// CRL reason code (RFC5280 section 5.3.1)
+    reason: Option<u8>,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RevocationTarget {
+    /// Key identifier of the issuing CA
+    issuer: String,
+    serial_number: String,
+    /// Unknown when the certificate was selected by serial number only and isn't stored
+    address: Option<String>,
+    subject_name: Option<String>,
+    /// Start of the validity period (seconds since UNIX epoch)
+    issued_at: Option<u64>,
+}
+
+fn revocation_target_from_address(storage: &dyn PickyStorage, address: &str) -> Result<RevocationTarget, ServerError> {
+    let cert_der = storage
+        .get_cert_by_addressing_hash(address)
+        .map_err(ServerError::storage(format!("couldn't fetch certificate {}", address)))?;
+    let cert = Cert::from_der(&cert_der).map_err(|e| ServerError::Internal {
+        description: format!("couldn't parse certificate {}: {}", address, e),
+    })?;
+
+    if let Ok(basic_constraints) = cert.basic_constraints() {
+        if basic_constraints.ca() == Some(true) {
+            return Err(ServerError::PolicyViolation {
+                description: format!("certificate {} is a CA certificate", address),
+            });
+        }
+    }
+
+    let issuer = cert
+        .authority_key_identifier()
+        .ok()
+        .and_then(|aki| aki.key_identifier().map(hex::encode))
+        .ok_or_else(|| ServerError::Internal {
+            description: format!("certificate {} has no authority key identifier", address),
+        })?;
+
+    Ok(RevocationTarget {
+        issuer,
+        serial_number: hex::encode(cert.serial_number().as_unsigned_bytes_be()),
+        address: Some(address.to_owned()),
+        subject_name: Some(cert.subject_name().to_string()),
+        issued_at: Some(to_chrono(&cert.valid_not_before()).timestamp().max(0) as u64),
+    })
+}
+
+/// Selects a certificate by serial number, which mustn't be the one of a CA certificate of this server
+/// (see `ca_serial_numbers`).
+fn revocation_target_from_serial_number(
+    issuer: &str,
+    serial_number: &str,
+    ca_serial_numbers: &[String],
+) -> Result<RevocationTarget, ServerError> {
+    let serial_number = normalize_serial_number(serial_number)?;
+    if ca_serial_numbers.contains(&serial_number) {
+        return Err(ServerError::PolicyViolation {
+            description: format!("serial number {} is the one of a CA certificate", serial_number),
+        });
+    }
+
+    Ok(RevocationTarget {
+        issuer: issuer.to_owned(),
+        serial_number,
+        address: None,
+        subject_name: None,
+        issued_at: None,
+    })
+}
+
+/// Encodes a serial number as it's found in certificates: lowercase hex without leading zero bytes.
+fn normalize_serial_number(serial_number: &str) -> Result<String, ServerError> {
+    let padded = if serial_number.len() % 2 == 1 {
+        format!("0{}", serial_number)
+    } else {
+        serial_number.to_owned()
+    };
+    let bytes = hex::decode(&padded).map_err(|e| ServerError::InvalidRequest {
+        description: format!("invalid serial number {}: {}", serial_number, e),
+    })?;
+    if bytes.is_empty() {
+        return Err(ServerError::InvalidRequest {
+            description: "empty serial number".to_owned(),
+        });
+    }
+
+    let significant = bytes.iter().position(|byte| *byte != 0).unwrap_or(bytes.len() - 1);
+    Ok(hex::encode(&bytes[significant..]))
+}
+
+/// Serial numbers of the CA certificates of this server: the root CA and the CA of every issuer.
+fn ca_serial_numbers(config: &Config, storage: &dyn PickyStorage) -> Result<Vec<String>, ServerError> {
+    let mut ca_names = vec![format!("{} Root CA", config.realm)];
+    for issuer in config.all_issuers() {
+        ca_names.push(config.issuer_ca_name(issuer).map_err(|e| ServerError::Internal {
+            description: e.to_string(),
+        })?);
+    }
+
+    ca_names
+        .iter()
+        .map(|ca_name| {
+            let ca_cert = fetch_ca_cert(ca_name, storage).map_err(|e| ServerError::CaUnavailable {
+                description: e.to_string(),
+            })?;
+            Ok(hex::encode(ca_cert.serial_number().as_unsigned_bytes_be()))
+        })
+        .collect()
+}
+
+/// Checks a requested CRL reason code (RFC5280 section 5.3.1).
+fn check_revocation_reason(reason: Option<u8>) -> Result<(), ServerError> {
+    let code = match reason {
+        Some(code) => code,
+        None => return Ok(()),
+    };
+
+    match CrlReason::from_code(code) {
+        Some(CrlReason::RemoveFromCrl) => Err(ServerError::InvalidRequest {
+            description: "reason code 8 (removeFromCRL) only applies to delta CRLs".to_owned(),
+        }),
+        Some(_) => Ok(()),
+        None => Err(ServerError::InvalidRequest {
+            description: format!("invalid reason code {}", code),
+        }),
+    }
+}
+
+/// Revocation time of a revocation request, `now` by default.
+///
+/// The requested time can't be in the future, nor before the issuance of the certificate when it's known.
+fn revocation_time(revoked_at: Option<u64>, target: &RevocationTarget, now: u64) -> Result<u64, ServerError> {
+    let revoked_at = match revoked_at {
+        Some(revoked_at) => revoked_at,
+        None => return Ok(now),
+    };
+
+    if revoked_at > now {
+        return Err(ServerError::InvalidRequest {
+            description: format!("revocation time {} is in the future", revoked_at),
+        });
+    }
+    if let Some(issued_at) = target.issued_at {
+        if revoked_at < issued_at {
+            return Err(ServerError::InvalidRequest {
+                description: format!(
+                    "revocation time {} is before the issuance of the certificate ({})",
+                    revoked_at, issued_at
+                ),
+            });
+        }
+    }
+
+    Ok(revoked_at)
+}
+
+/// Key identifier of the CA of `issuer` (see `issuers`), the default issuing CA if `None`.
+fn issuer_key_identifier(
+    config: &Config,
+    storage: &dyn PickyStorage,
+    issuer: Option<&str>,
+) -> Result<String, ServerError> {
+    let ca_name = config.issuer_ca_name(issuer).map_err(|e| ServerError::InvalidRequest {
+        description: e.to_string(),
+    })?;
+    let ca_cert = fetch_ca_cert(&ca_name, storage).map_err(|e| ServerError::CaUnavailable {
+        description: e.to_string(),
+    })?;
+    crl::ca_key_identifier(&ca_cert).map_err(ServerError::from)
+}
+
+/// Resolves the certificate selected by a revocation request.
+fn revocation_target(
+    config: &Config,
+    storage: &dyn PickyStorage,
+    request: &RevocationRequest,
+) -> Result<RevocationTarget, ServerError> {
+    match (&request.address, &request.serial_number) {
+        (Some(address), None) => revocation_target_from_address(storage, &canonical_cert_address(storage, address)?),
+        (None, Some(serial_number)) => revocation_target_from_serial_number(
+            &issuer_key_identifier(config, storage, request.issuer.as_deref())?,
+            serial_number,
+            &ca_serial_numbers(config, storage)?,
+        ),
+        _ => Err(ServerError::InvalidRequest {
+            description: "either 'address' or 'serial_number' must be provided".to_owned(),
+        }),
+    }
+}
+
+/// Resolves the certificates selected by a batch revocation request, ordered by issuer and serial number.
+fn collect_revocation_targets(
+    config: &Config,
+    storage: &dyn PickyStorage,
+    request: &BatchRevocationRequest,
+) -> Result<Vec<RevocationTarget>, ServerError> {
+    if request.serial_numbers.is_empty()
+        && request.key_identifiers.is_empty()
+        && request.requested_by.is_none()
+        && request.labels.is_empty()
+    {
+        return Err(ServerError::InvalidRequest {
+            description: "no serial number, key identifier, requester or labels provided".to_owned(),
+        });
+    }
+    check_revocation_reason(request.reason)?;
+
+    let mut addresses = Vec::new();
+    for key_identifier in request.key_identifiers.iter() {
+        addresses.push(
+            storage
+                .get_addressing_hash_by_key_identifier(&key_identifier.to_lowercase())
+                .map_err(ServerError::storage(format!(
+                    "couldn't find certificate by key identifier {}",
+                    key_identifier
+                )))?,
+        );
+    }
+    if let Some(requested_by) = &request.requested_by {
+        addresses.extend(
+            storage
+                .get_addressing_hashes_by_requester(requested_by)
+                .map_err(ServerError::storage(format!(
+                    "couldn't fetch certificates requested by {}",
+                    requested_by
+                )))?,
+        );
+    }
+    if !request.labels.is_empty() {
+        addresses.extend(
+            storage
+                .get_addressing_hashes_by_labels(&request.labels)
+                .map_err(ServerError::storage("couldn't fetch certificates by labels"))?,
+        );
+    }
+
+    let mut targets = BTreeMap::new();
+    for address in addresses.iter() {
+        let target = revocation_target_from_address(storage, address)?;
+        targets.insert((target.issuer.clone(), target.serial_number.clone()), target);
+    }
+    if !request.serial_numbers.is_empty() {
+        let issuer = issuer_key_identifier(config, storage, request.issuer.as_deref())?;
+        let ca_serial_numbers = ca_serial_numbers(config, storage)?;
+        for serial_number in request.serial_numbers.iter() {
+            let target = revocation_target_from_serial_number(&issuer, serial_number, &ca_serial_numbers)?;
+            targets
+                .entry((target.issuer.clone(), target.serial_number.clone()))
+                .or_insert(target);
+        }
+    }
+
+    Ok(targets.values().cloned().collect())
+}
+
+fn revoke(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    server_try!(
+        req,
+        res,
+        check_admin_authorization(controller_data, req),
+        "authorization failed"
+    );
+
+    let request = saphir_try!(
+        req,
+        res,
+        ErrorCode::InvalidRequest,
+        serde_json::from_slice::<RevocationRequest>(req.body()),
+        "invalid revocation request"
+    );
+    server_try!(
+        req,
+        res,
+        check_revocation_reason(request.reason),
+        "invalid revocation request"
+    );
+
+    let storage = controller_data.storage.as_ref();
+    let target = server_try!(
+        req,
+        res,
+        revocation_target(&controller_data.read_conf(), storage, &request),
+        "couldn't select certificate to revoke"
+    );
+    let revoked_at = server_try!(
+        req,
+        res,
+        revocation_time(request.revoked_at, &target, unix_epoch()),
+        "invalid revocation request"
+    );
+
+    // an existing revocation is kept as is, its time and reason are reported back
+    let existing = server_try!(
+        req,
+        res,
+        storage.get_revocation(&target.issuer, &target.serial_number),
+        "couldn't fetch revocation status"
+    );
+    let already_revoked = existing.is_some();
+    let entry = match existing {
+        Some(entry) => entry,
+        None => {
+            let entry = RevocationEntry {
+                issuer: target.issuer.clone(),
+                serial_number: target.serial_number.clone(),
+                revoked_at,
+                reason: request.reason,
+            };
+            server_try!(
+                req,
+                res,
+                storage.store_revocation(entry.clone()),
+                "couldn't store revocation"
+            );
+            log::info!("revoked certificate {}", entry.serial_number);
+            server_try!(
+                req,
+                res,
+                audit::append(
+                    storage,
+                    AuditEvent::CertificateRevoked,
+                    json!({
+                        "issuer": entry.issuer,
+                        "serial_number": entry.serial_number,
+                        "revoked_at": entry.revoked_at,
+                        "reason": entry.reason,
+                    }),
+                ),
+                "couldn't record audit event"
+            );
+            entry
+        }
+    };
+
+    server_try!(
+        req,
+        res,
+        write_json(
+            controller_data,
+            res,
+            json!({
+                "serial_number": entry.serial_number,
+                "address": target.address,
+                "subject_name": target.subject_name,
+                "revoked_at": entry.revoked_at,
+                "reason": entry.reason,
+                "already_revoked": already_revoked,
+            })
+            .to_string(),
+        ),
+        "couldn't sign response"
+    );
+    res.status(StatusCode::OK);
+}
+
+fn revoke_batch(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    server_try!(
+        req,
+        res,
+        check_admin_authorization(controller_data, req),
+        "authorization failed"
+    );
+
+    let request = saphir_try!(
+        req,
+        res,
+        ErrorCode::InvalidRequest,
+        serde_json::from_slice::<BatchRevocationRequest>(req.body()),
+        "invalid batch revocation request"
+    );
+
+    let storage = controller_data.storage.as_ref();
+    let targets = server_try!(
+        req,
+        res,
+        collect_revocation_targets(&controller_data.read_conf(), storage, &request),
+        "couldn't select certificates to revoke"
+    );
+
+    let mut certificates = Vec::with_capacity(targets.len());
+    let revoked_at = unix_epoch();
+    for target in targets.into_iter() {
+        let already_revoked = server_try!(
+            req,
+            res,
+            storage.get_revocation(&target.issuer, &target.serial_number),
+            "couldn't fetch revocation status"
+        )
+        .is_some();
+
+        if !request.dry_run && !already_revoked {
+            server_try!(
+                req,
+                res,
+                storage.store_revocation(RevocationEntry {
+                    issuer: target.issuer.clone(),
+                    serial_number: target.serial_number.clone(),
+                    revoked_at,
+                    reason: request.reason,
+                }),
+                "couldn't store revocation"
+            );
+            server_try!(
+                req,
+                res,
+                audit::append(
+                    storage,
+                    AuditEvent::CertificateRevoked,
+                    json!({
+                        "issuer": target.issuer,
+                        "serial_number": target.serial_number,
+                        "reason": request.reason,
+                    }),
+                ),
+                "couldn't record audit event"
+            );
+        }
+
+        certificates.push(json!({
+            "serial_number": target.serial_number,
+            "address": target.address,
+            "subject_name": target.subject_name,
+            "already_revoked": already_revoked,
+        }));
+    }
+
+    if !request.dry_run {
+        log::info!("batch revocation of {} certificate(s)", certificates.len());
+    }
+
+    server_try!(
+        req,
+        res,
+        write_json(
+            controller_data,
+            res,
+            json!({ "dry_run": request.dry_run, "certificates": certificates }).to_string(),
+        ),
+        "couldn't sign response"
+    );
+    res.status(StatusCode::OK);
+}
+
+// === crl === //
+
+fn get_crl(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    let config = controller_data.read_conf().clone();
+
+    let issuer = server_try!(req, res, query_param(req, "issuer"), "invalid issuer");
+
+    let partition = match req.captures().get("partition") {
+        Some(partition) => {
+            let partition = saphir_try!(
+                req,
+                res,
+                ErrorCode::NotFound,
+                partition.parse::<u32>(),
+                "invalid CRL partition"
+            );
+            if partition >= config.leaf_extensions.crl_partitions || config.leaf_extensions.crl_partitions == 1 {
+                write_problem(
+                    req,
+                    res,
+                    ErrorCode::NotFound,
+                    format!("CRL partition {} doesn't exist", partition),
+                );
+                return;
+            }
+            Some(partition)
+        }
+        None => None,
+    };
+
+    let crl_der = server_try!(
+        req,
+        res,
+        current_crl(controller_data, &config, issuer.as_deref(), partition),
+        "couldn't get CRL"
+    );
+
+    // relying parties following the CRL distribution point expect DER
+    let response_format = Format::response_format(req).unwrap_or(Format::PkixCrl);
+    match response_format {
+        Format::PemFile => {
+            res.header(header::CONTENT_TYPE, "application/x-pem-file");
+            res.body(to_pem("X509 CRL", &crl_der));
+        }
+        Format::PkixCrl => {
+            res.header(header::CONTENT_TYPE, "application/pkix-crl");
+            res.body(crl_der);
+        }
+        unexpected => {
+            let detail = format!("unexpected response format: {}", unexpected);
+            log::error!("{}", detail);
+            write_problem(req, res, ErrorCode::UnsupportedFormat, detail);
+            return;
+        }
+    }
+
+    res.header(header::VARY, "Accept");
+    res.header(
+        header::CACHE_CONTROL,
+        format!("public, max-age={}", config.crl.refresh_interval_secs),
+    );
+    res.status(StatusCode::OK);
+}
+
+/// Latest CRL of `issuer` for `partition`, generated if missing or expired.
+fn current_crl(
+    controller_data: &ControllerData,
+    config: &Config,
+    issuer: Option<&str>,
+    partition: Option<u32>,
+) -> Result<Vec<u8>, ServerError> {
+    let storage = controller_data.storage.as_ref();
+
+    let ca_name = config.issuer_ca_name(issuer).map_err(|e| ServerError::NotFound {
+        description: e.to_string(),
+    })?;
+    let ca_hash = storage
+        .get_addressing_hash_by_name(&ca_name)
+        .map_err(ServerError::storage("couldn't fetch CA"))?;
+    let ca_cert_der = storage
+        .get_cert_by_addressing_hash(&ca_hash)
+        .map_err(ServerError::storage("couldn't get CA cert der"))?;
+    let ca_cert = cert_cache::parse(&ca_cert_der).map_err(|e| ServerError::Internal {
+        description: format!("couldn't deserialize CA cert: {}", e),
+    })?;
+    let latest_key = crl::latest_key(&ca_cert, partition)?;
+
+    match storage.get_latest_artifact_addressing_hash(ArtifactNamespace::Crl, &latest_key) {
+        Ok(address) => {
+            let crl_der = storage
+                .get_artifact_by_addressing_hash(ArtifactNamespace::Crl, &address)
+                .map_err(ServerError::storage("couldn't fetch CRL"))?;
+            if crl::is_current(&crl_der) {
+                return Ok(crl_der);
+            }
+        }
+        Err(e) if !e.is_not_found() => return Err(ServerError::storage("couldn't fetch latest CRL")(e)),
+        Err(_) => {}
+    }
+
+    crl::generate(config, storage, &controller_data.signer, issuer, partition)
+        .map(|(_, crl_der)| crl_der)
+        .map_err(ServerError::from)
+}
+
+fn regenerate_crls(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    server_try!(
+        req,
+        res,
+        check_admin_authorization(controller_data, req),
+        "authorization failed"
+    );
+
+    let config = controller_data.read_conf().clone();
+    let addresses = saphir_try!(
+        req,
+        res,
+        ErrorCode::InternalError,
+        crl::generate_all(&config, controller_data.storage.as_ref(), &controller_data.signer),
+        "couldn't regenerate CRLs"
+    );
+    log::info!("regenerated {} CRL(s)", addresses.len());
+
+    server_try!(
+        req,
+        res,
+        write_json(controller_data, res, json!({ "addresses": addresses }).to_string()),
+        "couldn't sign response"
+    );
+    res.status(StatusCode::OK);
+}
+
+// === ocsp === //
+
+fn post_ocsp(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    let config = controller_data.read_conf().clone();
+    let response = ocsp::respond(
+        &config,
+        controller_data.storage.as_ref(),
+        &controller_data.signer,
+        req.body(),
+    );
+    write_ocsp_response(req, res, response, None);
+}
+
+fn get_ocsp(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    let config = controller_data.read_conf().clone();
+    let encoded = unwrap_opt!(
+        req,
+        res,
+        ErrorCode::InvalidRequest,
+        req.captures().get("request"),
+        "OCSP request is missing"
+    );
+
+    let response = match ocsp::decode_get_request(encoded) {
+        Ok(request_der) => ocsp::respond(
+            &config,
+            controller_data.storage.as_ref(),
+            &controller_data.signer,
+            &request_der,
+        ),
+        Err(e) => {
+            log::debug!("malformed OCSP GET request: {}", e);
+            OcspResponse::new_error(OcspResponseStatus::MalformedRequest)
+        }
+    };
+
+    // unlike POST, GET requests can be answered by HTTP caches
+    write_ocsp_response(req, res, response, Some(config.ocsp.validity_secs));
+}
+
+/// OCSP errors are reported in the response itself, with a 200 status.
+fn write_ocsp_response(req: &SyncRequest, res: &mut SyncResponse, response: OcspResponse, max_age: Option<u64>) {
+    let successful = response
+        .status()
+        .map_or(false, |status| status == OcspResponseStatus::Successful);
+    let der = saphir_try!(
+        req,
+        res,
+        ErrorCode::InternalError,
+        response.to_der(),
+        "couldn't encode OCSP response"
+    );
+
+    res.header(header::CONTENT_TYPE, "application/ocsp-response");
+    match max_age {
+        Some(max_age) if successful => res.header(header::CACHE_CONTROL, format!("public, max-age={}", max_age)),
+        _ => res.header(header::CACHE_CONTROL, "no-store"),
+    };
+    res.body(der);
+    res.status(StatusCode::OK);
+}
+
+// === audit === //
+
+fn get_audit_proof(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    server_try!(
+        req,
+        res,
+        check_admin_authorization(controller_data, req),
+        "authorization failed"
+    );
+
+    let from = match server_try!(req, res, query_param(req, "from"), "invalid 'from' sequence number") {
+        Some(from) => saphir_try!(
+            req,
+            res,
+            ErrorCode::InvalidRequest,
+            from.parse::<u64>(),
+            "invalid 'from' sequence number"
+        ),
+        None => 0,
+    };
+
+    // the record preceding the requested range is needed to check the first link
+    let mut records = server_try!(
+        req,
+        res,
+        controller_data.storage.get_audit_records(from.saturating_sub(1)),
+        "couldn't fetch audit records"
+    );
+    let previous = if from > 0 && !records.is_empty() && records[0].sequence < from {
+        Some(records.remove(0))
+    } else {
+        None
+    };
+
+    let verification = audit::verify_chain(previous.as_ref(), &records);
+    if let Err(e) = &verification {
+        log::error!("audit log verification failed: {}", e);
+    }
+
+    let head = records
+        .last()
+        .map(|record| json!({ "sequence": record.sequence, "hash": record.hash }));
+    server_try!(
+        req,
+        res,
+        write_json(
+            controller_data,
+            res,
+            json!({
+                "head": head,
+                "previous_hash": previous.map(|record| record.hash),
+                "verified": verification.is_ok(),
+                "records": records,
+            })
+            .to_string(),
+        ),
+        "couldn't sign response"
+    );
+    res.status(StatusCode::OK);
+}
+
+// === issuance log === //
+
+fn get_log_sth(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    let leaves = server_try!(req, res, issuance_log::leaves(controller_data.storage.as_ref()));
+    let config = controller_data.read_conf();
+    let tree_head = server_try!(
+        req,
+        res,
+        issuance_log::tree_head(
+            &config,
+            controller_data.storage.as_ref(),
+            &controller_data.signer,
+            &leaves
+        )
+    );
+
+    server_try!(
+        req,
+        res,
+        write_json(controller_data, res, json!(tree_head).to_string()),
+        "couldn't sign response"
+    );
+    res.status(StatusCode::OK);
+}
+
+fn get_log_consistency(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    let leaves = server_try!(req, res, issuance_log::leaves(controller_data.storage.as_ref()));
+
+    let first = server_try!(req, res, log_tree_size_param(req, "first", 0));
+    let second = server_try!(req, res, log_tree_size_param(req, "second", leaves.len()));
+    let proof = server_try!(
+        req,
+        res,
+        issuance_log::consistency_proof(&leaves, first, second).ok_or_else(|| ServerError::InvalidRequest {
+            description: format!(
+                "no consistency proof from tree size {} to {} in the issuance log ({})",
+                first,
+                second,
+                leaves.len()
+            ),
+        })
+    );
+
+    server_try!(
+        req,
+        res,
+        write_json(controller_data, res, json!(proof).to_string()),
+        "couldn't sign response"
+    );
+    res.status(StatusCode::OK);
+}
+
+/// Tree size given by the `name` query parameter, `default` if absent.
+fn log_tree_size_param(req: &SyncRequest, name: &str, default: usize) -> Result<usize, ServerError> {
+    match query_param(req, name)? {
+        Some(tree_size) => tree_size.parse::<usize>().map_err(|e| ServerError::InvalidRequest {
+            description: format!("invalid '{}': {}", name, e),
+        }),
+        None => Ok(default),
+    }
+}
+
+fn get_log_proof(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    let address_any_base = unwrap_opt!(
+        req,
+        res,
+        ErrorCode::InvalidRequest,
+        req.captures().get("hash"),
+        "hash is missing"
+    );
+    let address = server_try!(
+        req,
+        res,
+        canonical_cert_address(controller_data.storage.as_ref(), address_any_base)
+    );
+
+    let leaves = server_try!(req, res, issuance_log::leaves(controller_data.storage.as_ref()));
+
+    // proofs may be requested against a previously fetched tree head
+    let tree_size = server_try!(req, res, log_tree_size_param(req, "tree_size", leaves.len()));
+    if tree_size > leaves.len() {
+        let detail = format!(
+            "tree size {} is larger than the issuance log ({})",
+            tree_size,
+            leaves.len()
+        );
+        log::error!("{}", detail);
+        write_problem(req, res, ErrorCode::InvalidRequest, detail);
+        return;
+    }
+
+    let proof = server_try!(
+        req,
+        res,
+        issuance_log::inclusion_proof(&leaves, &address, tree_size).ok_or_else(|| ServerError::NotFound {
+            description: format!(
+                "certificate {} isn't in the issuance log of size {}",
+                address, tree_size
+            ),
+        })
+    );
+
+    server_try!(
+        req,
+        res,
+        write_json(controller_data, res, json!(proof).to_string()),
+        "couldn't sign response"
+    );
+    res.status(StatusCode::OK);
+}
+
+// === acme === //
+
+/// URL of an ACME resource, clients only follow the URLs listed in the directory.
+fn acme_url(controller_data: &ControllerData, req: &SyncRequest, resource: &str) -> Result<String, AcmeProblem> {
+    let conf = controller_data.read_conf();
+    conf.acme_url(forwarded_base_url(&conf, req).as_deref(), resource)
+        .ok_or_else(|| {
+            AcmeProblem::new(
+                AcmeErrorType::ServerInternal,
+                "ACME requires 'external_base_url' or trusted forwarded headers",
+            )
+        })
+}
+
+/// Every ACME response carries a fresh nonce for the next request of the client.
+fn write_acme_nonce(controller_data: &ControllerData, res: &mut SyncResponse) {
+    res.header(REPLAY_NONCE_HEADER, controller_data.acme_nonces.issue());
+    res.header(header::CACHE_CONTROL, "no-store");
+}
+
+/// ACME clients expect the error types of RFC8555 section 6.7 rather than our problem codes.
+fn write_acme_problem(controller_data: &ControllerData, res: &mut SyncResponse, problem: AcmeProblem) {
+    log::error!("ACME request failed: {}", problem);
+    let status = match problem.error_type {
+        AcmeErrorType::Unauthorized => StatusCode::FORBIDDEN,
+        AcmeErrorType::ServerInternal => StatusCode::INTERNAL_SERVER_ERROR,
+        _ => StatusCode::BAD_REQUEST,
+    };
+
+    write_acme_nonce(controller_data, res);
+    res.header(header::CONTENT_TYPE, PROBLEM_JSON_CONTENT_TYPE);
+    res.body(
+        json!({
+            "type": problem.error_type.type_uri(),
+            "detail": problem.detail,
+            "status": status.as_u16(),
+        })
+        .to_string(),
+    );
+    res.status(status);
+}
+
+/// Authenticates a request signed by an existing account and posted to the URL of `resource`.
+fn decode_kid_request(
+    controller_data: &ControllerData,
+    req: &SyncRequest,
+    resource: &str,
+) -> Result<KidRequest, AcmeProblem> {
+    let url = acme_url(controller_data, req, resource)?;
+    let account_url_prefix = acme_url(controller_data, req, "acct/")?;
+    KidRequest::decode(
+        req.body(),
+        &controller_data.acme_nonces,
+        controller_data.storage.as_ref(),
+        &account_url_prefix,
+        &url,
+    )
+}
+
+fn write_acme_json(controller_data: &ControllerData, res: &mut SyncResponse, body: Value) {
+    write_acme_nonce(controller_data, res);
+    res.header(header::CONTENT_TYPE, "application/json");
+    res.body(body.to_string());
+    res.status(StatusCode::OK);
+}
+
+macro_rules! acme_try {
+    ( $controller_data:ident, $res:ident, $result:expr $(,)? ) => {
+        match $result {
+            Ok(value) => value,
+            Err(problem) => {
+                write_acme_problem($controller_data, $res, problem);
+                return;
+            }
+        }
+    };
+}
+
+fn get_acme_directory(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    let new_nonce = acme_try!(controller_data, res, acme_url(controller_data, req, "new-nonce"));
+    let new_account = acme_try!(controller_data, res, acme_url(controller_data, req, "new-account"));
+    let new_order = acme_try!(controller_data, res, acme_url(controller_data, req, "new-order"));
+
+    res.header(header::CONTENT_TYPE, "application/json");
+    res.body(
+        json!({
+            "newNonce": new_nonce,
+            "newAccount": new_account,
+            "newOrder": new_order,
+            "meta": { "externalAccountRequired": true },
+        })
+        .to_string(),
+    );
+    res.status(StatusCode::OK);
+}
+
+fn new_acme_nonce(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    write_acme_nonce(controller_data, res);
+    // RFC8555 section 7.2: 200 for HEAD, 204 for GET
+    if *req.method() == Method::HEAD {
+        res.status(StatusCode::OK);
+    } else {
+        res.status(StatusCode::NO_CONTENT);
+    }
+}
+
+fn new_acme_account(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    let url = acme_try!(controller_data, res, acme_url(controller_data, req, "new-account"));
+    let request = acme_try!(
+        controller_data,
+        res,
+        JwkRequest::decode(req.body(), &controller_data.acme_nonces, &url)
+    );
+    let account = acme_try!(
+        controller_data,
+        res,
+        account::new_account(controller_data.storage.as_ref(), &request, &url)
+    );
+    let location = acme_try!(
+        controller_data,
+        res,
+        acme_url(controller_data, req, &format!("acct/{}", account.id))
+    );
+
+    if account.created {
+        log::info!(
+            "ACME account {} bound to external account key {}",
+            account.id,
+            account.key_id
+        );
+        acme_try!(
+            controller_data,
+            res,
+            audit::append(
+                controller_data.storage.as_ref(),
+                AuditEvent::AcmeAccountCreated,
+                json!({ "account": account.id, "key_id": account.key_id }),
+            )
+            .map_err(|e| AcmeProblem::new(
+                AcmeErrorType::ServerInternal,
+                format!("couldn't record audit event: {}", e)
+            ))
+        );
+    }
+
+    write_acme_nonce(controller_data, res);
+    res.header(header::LOCATION, location);
+    res.header(header::CONTENT_TYPE, "application/json");
+    res.body(
+        json!({
+            "status": "valid",
+            "contact": request.payload["contact"].as_array().cloned().unwrap_or_default(),
+        })
+        .to_string(),
+    );
+    res.status(if account.created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    });
+}
+
+fn new_acme_order(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    let request = acme_try!(
+        controller_data,
+        res,
+        decode_kid_request(controller_data, req, "new-order")
+    );
+    let order = acme_try!(
+        controller_data,
+        res,
+        controller_data
+            .acme_orders
+            .new_order(&request.account_id, &request.payload, unix_epoch())
+    );
+    let location = acme_try!(
+        controller_data,
+        res,
+        acme_url(controller_data, req, &format!("order/{}", order.id))
+    );
+    let authorization_url_prefix = acme_try!(controller_data, res, acme_url(controller_data, req, "authz/"));
+
+    log::info!(
+        "ACME order {} placed by account {} for {}",
+        order.id,
+        order.account_id,
+        order.identifiers.join(", ")
+    );
+
+    write_acme_json(controller_data, res, order.to_json(&authorization_url_prefix));
+    res.header(header::LOCATION, location);
+    res.status(StatusCode::CREATED);
+}
+
+fn get_acme_order(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    let id = req.captures().get("id").cloned().unwrap_or_default();
+    let request = acme_try!(
+        controller_data,
+        res,
+        decode_kid_request(controller_data, req, &format!("order/{}", id))
+    );
+    let order = acme_try!(
+        controller_data,
+        res,
+        controller_data
+            .acme_orders
+            .order(&request.account_id, &id, unix_epoch())
+    );
+    let authorization_url_prefix = acme_try!(controller_data, res, acme_url(controller_data, req, "authz/"));
+
+    write_acme_json(controller_data, res, order.to_json(&authorization_url_prefix));
+}
+
+fn get_acme_authorization(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    let id = req.captures().get("id").cloned().unwrap_or_default();
+    let request = acme_try!(
+        controller_data,
+        res,
+        decode_kid_request(controller_data, req, &format!("authz/{}", id))
+    );
+    let authorization = acme_try!(
+        controller_data,
+        res,
+        controller_data
+            .acme_orders
+            .authorization(&request.account_id, &id, unix_epoch())
+    );
+    let challenge_url_prefix = acme_try!(controller_data, res, acme_url(controller_data, req, "chall/"));
+
+    write_acme_json(controller_data, res, authorization.to_json(&challenge_url_prefix));
+}
+
+/// POST-as-GET fetches the challenge, posting an object asks for its validation (RFC8555 section 7.5.1).
+fn respond_acme_challenge(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    let id = req.captures().get("id").cloned().unwrap_or_default();
+    let request = acme_try!(
+        controller_data,
+        res,
+        decode_kid_request(controller_data, req, &format!("chall/{}", id))
+    );
+    let (mut authorization, mut challenge) = acme_try!(
+        controller_data,
+        res,
+        controller_data
+            .acme_orders
+            .challenge(&request.account_id, &id, unix_epoch())
+    );
+
+    if request.payload.is_object() && authorization.status == Status::Pending && challenge.status == Status::Pending {
+        // validators reach out to the network, the config lock isn't held meanwhile
+        let acme_config = controller_data.read_conf().acme.clone();
+        let result = order::validate(
+            &acme_config,
+            controller_data.storage.as_ref(),
+            &authorization,
+            &challenge,
+        );
+        if let Err(e) = &result {
+            log::warn!("ACME challenge {} failed: {}", id, e);
+        }
+
+        let (validated_authorization, validated_challenge) = acme_try!(
+            controller_data,
+            res,
+            controller_data
+                .acme_orders
+                .complete_challenge(&id, result, unix_epoch())
+        );
+        authorization = validated_authorization;
+        challenge = validated_challenge;
+    }
+
+    let authorization_url = acme_try!(
+        controller_data,
+        res,
+        acme_url(controller_data, req, &format!("authz/{}", authorization.id))
+    );
+    let challenge_url_prefix = acme_try!(controller_data, res, acme_url(controller_data, req, "chall/"));
+
+    write_acme_json(controller_data, res, challenge.to_json(&challenge_url_prefix));
+    res.header(header::LINK, format!("<{}>;rel=\"up\"", authorization_url));
+}
+
+// === acme external account binding === //
+
+fn get_external_account_keys(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    server_try!(
+        req,
+        res,
+        check_admin_authorization(controller_data, req),
+        "authorization failed"
+    );
+
+    let keys = server_try!(
+        req,
+        res,
+        controller_data.storage.get_external_account_keys(),
+        "couldn't fetch external account keys"
+    );
+
+    // MAC keys are only disclosed once, when provisioned
+    let keys = keys
+        .into_iter()
+        .map(|key| {
+            json!({
+                "key_id": key.key_id,
+                "created_at": key.created_at,
+                "account": key.account,
+                "revoked": key.revoked,
+            })
+        })
+        .collect::<Vec<Value>>();
+
+    server_try!(
+        req,
+        res,
+        write_json(controller_data, res, Value::Array(keys).to_string()),
+        "couldn't sign response"
+    );
+    res.status(StatusCode::OK);
+}
+
+fn post_external_account_key(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    server_try!(
+        req,
+        res,
+        check_admin_authorization(controller_data, req),
+        "authorization failed"
+    );
+
+    let key = saphir_try!(
+        req,
+        res,
+        ErrorCode::StorageUnavailable,
+        eab::provision_key(controller_data.storage.as_ref()),
+        "couldn't provision external account key"
+    );
+    server_try!(
+        req,
+        res,
+        audit::append(
+            controller_data.storage.as_ref(),
+            AuditEvent::ExternalAccountKeyProvisioned,
+            json!({ "key_id": key.key_id }),
+        ),
+        "couldn't record audit event"
+    );
+
+    server_try!(
+        req,
+        res,
+        write_json(
+            controller_data,
+            res,
+            json!({ "key_id": key.key_id, "hmac_key": key.hmac_key }).to_string(),
+        ),
+        "couldn't sign response"
+    );
+    res.status(StatusCode::CREATED);
+}
+
+fn revoke_external_account_key(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    server_try!(
+        req,
+        res,
+        check_admin_authorization(controller_data, req),
+        "authorization failed"
+    );
+
+    let key_id = unwrap_opt!(
+        req,
+        res,
+        ErrorCode::InvalidRequest,
+        req.captures().get("key_id"),
+        "external account key id is missing"
+    );
+    saphir_try!(
+        req,
+        res,
+        ErrorCode::NotFound,
+        eab::revoke_key(controller_data.storage.as_ref(), key_id),
+        "couldn't revoke external account key"
+    );
+    server_try!(
+        req,
+        res,
+        audit::append(
+            controller_data.storage.as_ref(),
+            AuditEvent::ExternalAccountKeyRevoked,
+            json!({ "key_id": key_id }),
+        ),
+        "couldn't record audit event"
+    );
+
+    res.status(StatusCode::OK);
+}
+
+// === rotation ===
+
+fn post_rotation_state(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    server_try!(
+        req,
+        res,
+        check_admin_authorization(controller_data, req),
+        "authorization failed"
+    );
+
+    let state = saphir_try!(
+        req,
+        res,
+        ErrorCode::InvalidRequest,
+        serde_json::from_slice::<RotationState>(req.body()),
+        "invalid rotation state"
+    );
+
+    for hash in state.intermediates.iter() {
+        server_try!(
+            req,
+            res,
+            find_chain_by_addressing_hash(controller_data.storage.as_ref(), hash),
+            "couldn't find chain of rotated intermediate"
+        );
+    }
+
+    let intermediates = state.intermediates.clone();
+    server_try!(
+        req,
+        res,
+        controller_data.storage.store_rotation_state(state),
+        "couldn't store rotation state"
+    );
+    server_try!(
+        req,
+        res,
+        audit::append(
+            controller_data.storage.as_ref(),
+            AuditEvent::RotationStateUpdated,
+            json!({ "intermediates": intermediates }),
+        ),
+        "couldn't record audit event"
+    );
+
+    res.status(StatusCode::OK);
+}
+
+// === chain ===
+
+fn get_default_chain(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    let storage = controller_data.storage.as_ref();
+    let ca = format!("{} Authority", &controller_data.read_conf().realm);
+    let default_hash = server_try!(req, res, storage.get_addressing_hash_by_name(&ca), "couldn't find CA");
+
+    // while a CA rotation is in progress, other intermediates may be requested
+    let rotation_state = server_try!(req, res, storage.get_rotation_state(), "couldn't fetch rotation state");
+
+    if server_try!(req, res, query_param(req, "all"), "invalid 'all' query parameter").as_deref() == Some("true") {
+        let mut hashes = vec![default_hash];
+        for hash in rotation_state.intermediates {
+            if !hashes.contains(&hash) {
+                hashes.push(hash);
+            }
+        }
+
+        let mut chains = Vec::with_capacity(hashes.len());
+        for hash in hashes {
+            let chain = server_try!(
+                req,
+                res,
+                find_chain_by_addressing_hash(storage, &hash),
+                "couldn't find CA chain"
+            );
+            chains.push(json!({
+                "intermediate": hash,
+                "chain": chain,
+            }));
+        }
+
+        res.header(header::CACHE_CONTROL, CHAIN_CACHE_CONTROL);
+        server_try!(
+            req,
+            res,
+            write_json(controller_data, res, json!({ "chains": chains }).to_string()),
+            "couldn't sign response"
+        );
+        res.status(StatusCode::OK);
+        return;
+    }
+
+    let issuer = server_try!(req, res, query_param(req, "issuer"), "invalid issuer");
+    let intermediate = server_try!(req, res, query_param(req, "intermediate"), "invalid intermediate");
+    let hash = match (issuer, intermediate) {
+        // other issuing intermediates of the realm are requested by name
+        (Some(issuer), _) => {
+            let ca_name = match controller_data.read_conf().issuer_ca_name(Some(&issuer)) {
+                Ok(ca_name) => ca_name,
+                Err(e) => {
+                    write_problem(req, res, ErrorCode::NotFound, e.to_string());
+                    return;
+                }
+            };
+            server_try!(
+                req,
+                res,
+                storage.get_addressing_hash_by_name(&ca_name),
+                "couldn't find issuer CA"
+            )
+        }
+        (None, Some(hash)) if hash == default_hash || rotation_state.intermediates.contains(&hash) => hash,
+        (None, Some(hash)) => {
+            write_problem(
+                req,
+                res,
+                ErrorCode::NotFound,
+                format!("intermediate {} isn't served by this CA", hash),
+            );
+            return;
+        }
+        (None, None) => default_hash,
+    };
+
+    let chain = server_try!(
+        req,
+        res,
+        find_chain_by_addressing_hash(storage, &hash),
+        "couldn't find CA chain"
+    );
+
+    // some appliances only import CA bundles of a specific type
+    let response_format = Format::response_format(req).unwrap_or(Format::PemFile);
+    match response_format {
+        Format::PemFile => {
+            res.header(header::CONTENT_TYPE, "application/x-pem-file");
+            res.body(chain.join("\n"));
+        }
+        Format::PkixCertBinary | Format::X509CaCert => {
+            let der = server_try!(req, res, concat_chain_der(&chain), "couldn't encode CA chain");
+            let content_type = if response_format == Format::X509CaCert {
+                "application/x-x509-ca-cert"
+            } else {
+                "application/pkix-cert"
+            };
+            res.header(header::CONTENT_TYPE, content_type);
+            res.body(der);
+        }
+        unexpected => {
+            let detail = format!("unexpected response format: {}", unexpected);
+            log::error!("{}", detail);
+            write_problem(req, res, ErrorCode::UnsupportedFormat, detail);
+            return;
+        }
+    }
+
+    res.header(header::CACHE_CONTROL, CHAIN_CACHE_CONTROL);
+    res.status(StatusCode::OK);
+}
+
+/// DER certificates of a PEM chain, concatenated in the same order.
+fn concat_chain_der(chain: &[String]) -> Result<Vec<u8>, ServerError> {
+    let mut der = Vec::new();
+    for cert_pem in chain {
+        let pem = cert_pem.parse::<Pem>().map_err(|e| ServerError::Internal {
+            description: format!("couldn't parse CA certificate pem: {}", e),
+        })?;
+        der.extend_from_slice(pem.data());
+    }
+    Ok(der)
+}
+
+// === PKCS#12 bundle === //
+
+const PKCS12_CONTENT_TYPE: &str = "application/x-pkcs12";
+
+/// Intermediate CA chain in a password-protected PKCS#12 archive, for appliances which can only
+/// import PFX files.
+///
+/// With a `subject` query parameter, a key is generated and a certificate issued for it, the
+/// archive then holding this server identity too. Only administrators can request a bundle.
+fn get_pkcs12_bundle(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    server_try!(
+        req,
+        res,
+        check_admin_authorization(controller_data, req),
+        "authorization failed"
+    );
+
+    let password = unwrap_opt!(
+        req,
+        res,
+        ErrorCode::InvalidRequest,
+        server_try!(req, res, query_param(req, "password"), "invalid password"),
+        "password query parameter is required"
+    );
+
+    let archive = match server_try!(req, res, query_param(req, "subject"), "invalid subject") {
+        Some(subject) => {
+            let origin = IssuanceOrigin {
+                requested_by: Some(API_KEY_REQUESTER.to_owned()),
+                labels: Labels::new(),
+                base_url: forwarded_base_url(&controller_data.read_conf(), req),
+                profile: None,
+            };
+            let (pk, chain) = server_try!(
+                req,
+                res,
+                generate_identity(controller_data, &subject, &AltNames::default(), origin)
+            );
+            server_try!(req, res, build_pkcs12(Some(pk), &chain, Some(&subject), &password))
+        }
+        None => {
+            let chain = server_try!(
+                req,
+                res,
+                current_ca_chain_der(controller_data),
+                "couldn't find CA chain"
+            );
+            server_try!(req, res, build_pkcs12(None, &chain, None, &password))
+        }
+    };
+
+    res.header(header::CONTENT_TYPE, PKCS12_CONTENT_TYPE);
+    res.header(header::CACHE_CONTROL, "no-store");
+    res.body(archive);
+    res.status(StatusCode::OK);
+}
+
+/// Encodes a PKCS#12 archive of DER certificates, leaf first, protected by `password`.
+///
+/// The private key, if any, is bound to the leaf which is labeled with `friendly_name`.
+fn build_pkcs12(
+    private_key: Option<PrivateKey>,
+    chain: &[Vec<u8>],
+    friendly_name: Option<&str>,
+    password: &str,
+) -> Result<Vec<u8>, ServerError> {
+    let mut certs = ders_to_certs(chain)?.into_iter();
+
+    let builder = Pkcs12::builder();
+    if let Some(leaf) = certs.next() {
+        builder.cert(leaf);
+    }
+    builder.chain(certs.collect());
+    if let Some(private_key) = private_key {
+        builder.private_key(private_key);
+    }
+    if let Some(friendly_name) = friendly_name {
+        builder.friendly_name(friendly_name);
+    }
+
+    builder
+        .build()
+        .and_then(|archive| archive.to_der(password))
+        .map_err(|e| ServerError::Internal {
+            description: format!("couldn't build PKCS#12 archive: {}", e),
+        })
+}
+
+fn ders_to_certs(ders: &[Vec<u8>]) -> Result<Vec<Cert>, ServerError> {
+    ders.iter()
+        .map(|der| {
+            Cert::from_der(der).map_err(|e| ServerError::Internal {
+                description: format!("couldn't decode certificate: {}", e),
+            })
+        })
+        .collect()
+}
+
+/// Percent-decoded value of the `name` query parameter.
+fn query_param(req: &SyncRequest, name: &str) -> Result<Option<String>, ServerError> {
+    req.get_query_param(name)
+        .map(|value| decode_query_param(&value))
+        .transpose()
+}
+
+fn decode_query_param(value: &str) -> Result<String, ServerError> {
+    let decoded = percent_decode(value).map_err(|e| ServerError::InvalidRequest {
+        description: e.to_string(),
+    })?;
+    String::from_utf8(decoded).map_err(|_| ServerError::InvalidRequest {
+        description: "not valid UTF-8".to_owned(),
+    })
+}
+
+// === well-known CA issuers === //
+
+const PKCS7_CERTS_ONLY_CONTENT_TYPE: &str = "application/pkcs7-mime; smime-type=certs-only";
+
+/// DER certificates of the current hierarchy, intermediate CA first.
+fn current_ca_chain_der(controller_data: &ControllerData) -> Result<Vec<Vec<u8>>, ServerError> {
+    issuer_ca_chain_der(controller_data, None)
+}
+
+/// DER certificates of the hierarchy of `issuer`, its intermediate CA first.
+fn issuer_ca_chain_der(controller_data: &ControllerData, issuer: Option<&str>) -> Result<Vec<Vec<u8>>, ServerError> {
+    let ca_name = controller_data
+        .read_conf()
+        .issuer_ca_name(issuer)
+        .map_err(|e| ServerError::NotFound {
+            description: e.to_string(),
+        })?;
+    find_ca_chain(controller_data.storage.as_ref(), &ca_name)?
+        .iter()
+        .map(|cert_pem| {
+            cert_pem
+                .parse::<Pem>()
+                .map(|pem| pem.data().to_vec())
+                .map_err(|e| ServerError::Internal {
+                    description: format!("couldn't parse CA certificate pem: {}", e),
+                })
+        })
+        .collect()
+}
+
+fn get_ca_certs(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    let issuer = server_try!(req, res, query_param(req, "issuer"), "invalid issuer");
+    let chain = server_try!(
+        req,
+        res,
+        issuer_ca_chain_der(controller_data, issuer.as_deref()),
+        "couldn't find CA chain"
+    );
+
+    let bundle = server_try!(
+        req,
+        res,
+        ders_to_certs(&chain).and_then(|certs| {
+            SignedData::from_certs(&certs)
+                .and_then(|signed_data| signed_data.to_der())
+                .map_err(|e| ServerError::Internal {
+                    description: format!("couldn't encode pkcs7 bundle: {}", e),
+                })
+        })
+    );
+
+    res.header(header::CONTENT_TYPE, PKCS7_CERTS_ONLY_CONTENT_TYPE);
+    res.header(header::CACHE_CONTROL, CHAIN_CACHE_CONTROL);
+    res.body(bundle);
+    res.status(StatusCode::OK);
+}
+
+fn write_ca_pem(req: &SyncRequest, res: &mut SyncResponse, cert_der: Option<&[u8]>) {
+    let cert_der = unwrap_opt!(req, res, ErrorCode::NotFound, cert_der, "CA certificate not found");
+    res.header(header::CONTENT_TYPE, "application/x-pem-file");
+    res.header(header::CACHE_CONTROL, CHAIN_CACHE_CONTROL);
+    res.body(to_pem("CERTIFICATE", cert_der));
+    res.status(StatusCode::OK);
+}
+
+fn get_root_pem(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    let chain = server_try!(
+        req,
+        res,
+        current_ca_chain_der(controller_data),
+        "couldn't find CA chain"
+    );
+    write_ca_pem(req, res, chain.last().map(Vec::as_slice));
+}
+
+fn get_intermediate_pem(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    let chain = server_try!(
+        req,
+        res,
+        current_ca_chain_der(controller_data),
+        "couldn't find CA chain"
+    );
+    write_ca_pem(req, res, chain.first().map(Vec::as_slice));
+}
+
+fn find_ca_chain(storage: &dyn PickyStorage, ca_name: &str) -> Result<Vec<String>, ServerError> {
+    let ca_hash = storage
+        .get_addressing_hash_by_name(ca_name)
+        .map_err(ServerError::storage(format!(
+            "couldn't fetch CA hash id for {}",
+            ca_name
+        )))?;
+    find_chain_by_addressing_hash(storage, &ca_hash)
+}
+
+fn find_chain_by_addressing_hash(storage: &dyn PickyStorage, ca_hash: &str) -> Result<Vec<String>, ServerError> {
+    let mut cert_der = storage
+        .get_cert_by_addressing_hash(&ca_hash)
+        .map_err(ServerError::storage("couldn't fetch CA certificate der"))?;
+    let mut chain = vec![to_pem("CERTIFICATE", &cert_der)];
+    let mut current_key_id = String::default();
+    loop {
+        let cert = cert_cache::parse(&cert_der).map_err(|e| ServerError::Internal {
+            description: format!("couldn't deserialize certificate: {}", e),
+        })?;
+
+        let parent_key_id = hex::encode(
+            cert.authority_key_identifier()
+                .map_err(|e| ServerError::Internal {
+                    description: format!("couldn't fetch authority key identifier: {}", e),
+                })?
+                .key_identifier()
+                .ok_or_else(|| ServerError::Internal {
+                    description: "parent key identifier not found".to_owned(),
+                })?,
+        );
+
+        if current_key_id == parent_key_id {
+            // The authority is itself. It is a root.
+            break;
+        }
+
+        let hash_address = storage
+            .get_addressing_hash_by_key_identifier(&parent_key_id)
+            .map_err(ServerError::storage("couldn't fetch hash"))?;
+
+        cert_der = storage
+            .get_cert_by_addressing_hash(&hash_address)
+            .map_err(ServerError::storage("couldn't fetch certificate der"))?;
+
+        chain.push(to_pem("CERTIFICATE", &cert_der));
+
+        current_key_id = parent_key_id;
+    }
+
+    Ok(chain)
+}
+
+// === generate root CA === //
+
+fn generate_ca_key(parameters: KeyParameters) -> Result<PrivateKey, CaSetupError> {
+    match parameters {
+        KeyParameters::Rsa { size } => {
+            Picky::generate_private_key(size).map_err(|source| CaSetupError::KeyGeneration { source })
+        }
+        KeyParameters::Ec { curve } => Err(CaSetupError::UnsupportedKeyGeneration { curve }),
+    }
+}
+
+fn generate_root_ca(config: &Config, storage: &dyn PickyStorage, signer: &CaSigner) -> Result<bool, CaSetupError> {
+    let name = format!("{} Root CA", config.realm);
+
+    if let Ok(certs) = storage.get_addressing_hash_by_name(&name) {
+        if !certs.is_empty() {
+            // already exists
+            return Ok(false);
+        }
+    }
+
+    let pk = generate_ca_key(config.ca_keys.root)?;
+    let root = Picky::generate_root(&name, &pk, config.root_signing_algorithm(), IssuerOptions::root())
+        .map_err(|source| CaSetupError::RootGeneration { source })?;
+    let ski = root
+        .subject_key_identifier()
+        .map_err(|source| CaSetupError::InvalidCert { source })?;
+
+    let cert_der = root.to_der().map_err(|source| CaSetupError::InvalidCert { source })?;
+
+    let pk_pkcs8 = pk.to_pkcs8().map_err(|source| CaSetupError::KeyEncoding { source })?;
+    let sealed_key = signer
+        .seal_key(&pk_pkcs8)
+        .map_err(|source| CaSetupError::KeySealing { source })?;
+
+    storage
+        .store(CertificateEntry {
+            name,
+            cert: cert_der,
+            key_identifier: hex::encode(ski),
+            key: Some(sealed_key),
+            requested_by: None,
+            labels: Labels::new(),
+        })
+        .map_err(CaSetupError::storage("couldn't store generated root certificate"))?;
+
+    Ok(true)
+}
+
+// === generate intermediate CA === //
+
+fn generate_intermediate_ca(
+    config: &Config,
+    storage: &dyn PickyStorage,
+    signer: &CaSigner,
+) -> Result<bool, CaSetupError> {
+    generate_issuing_ca(
+        config,
+        storage,
+        signer,
+        format!("{} Authority", config.realm),
+        config.ca_keys.intermediate,
+    )
+}
+
+/// Generates an intermediate CA named `intermediate_name` signed by the root CA, unless it exists.
+fn generate_issuing_ca(
+    config: &Config,
+    storage: &dyn PickyStorage,
+    signer: &CaSigner,
+    intermediate_name: String,
+    key_parameters: KeyParameters,
+) -> Result<bool, CaSetupError> {
+    let root_name = format!("{} Root CA", config.realm);
+
+    if let Ok(certs) = storage.get_addressing_hash_by_name(&intermediate_name) {
+        if !certs.is_empty() {
+            // already exists
+            return Ok(false);
+        }
+    }
+
+    let root_hash = storage
+        .get_addressing_hash_by_name(&root_name)
+        .map_err(CaSetupError::storage("error while fetching root"))?;
+    let root_cert_der = storage
+        .get_cert_by_addressing_hash(&root_hash)
+        .map_err(CaSetupError::storage("couldn't fetch root CA"))?;
+
+    let pk = generate_ca_key(key_parameters)?;
+    let root_cert = Cert::from_der(&root_cert_der).map_err(|source| CaSetupError::InvalidCert { source })?;
+
+    key_usage::check_before_signing(storage, &root_name, &root_cert, &config.key_usage_limits)
+        .map_err(|source| CaSetupError::KeyUsage { source })?;
+
+    let intermediate_cert = signer
+        .sign_intermediate(
+            &root_hash,
+            &root_cert,
+            &intermediate_name,
+            pk.to_public_key(),
+            config.intermediate_signing_algorithm(),
+            IssuerOptions::intermediate(),
+        )
+        .map_err(|source| CaSetupError::IntermediateSigning { source })?;
+
+    let ski = intermediate_cert
+        .subject_key_identifier()
+        .map_err(|source| CaSetupError::InvalidCert { source })?;
+
+    let cert_der = intermediate_cert
+        .to_der()
+        .map_err(|source| CaSetupError::InvalidCert { source })?;
+
+    let pk_pkcs8 = pk.to_pkcs8().map_err(|source| CaSetupError::KeyEncoding { source })?;
+    let sealed_key = signer
+        .seal_key(&pk_pkcs8)
+        .map_err(|source| CaSetupError::KeySealing { source })?;
+
+    storage
+        .store(CertificateEntry {
+            name: intermediate_name,
+            cert: cert_der,
+            key_identifier: hex::encode(ski),
+            key: Some(sealed_key),
+            requested_by: None,
+            labels: Labels::new(),
+        })
+        .map_err(CaSetupError::storage(
+            "couldn't store generated intermediate certificate",
+        ))?;
+
+    Ok(true)
+}
+
+// === offline root CA === //
+
+fn fetch_ca_cert(name: &str, storage: &dyn PickyStorage) -> Result<Arc<Cert>, CaSetupError> {
+    let hash = storage
+        .get_addressing_hash_by_name(name)
+        .map_err(CaSetupError::storage(format!("couldn't find {}", name)))?;
+
+    let cert_der = storage
+        .get_cert_by_addressing_hash(&hash)
+        .map_err(CaSetupError::storage(format!("couldn't fetch {} certificate", name)))?;
+
+    cert_cache::parse(&cert_der).map_err(|source| CaSetupError::InvalidCert { source })
+}
+
+// === inject config provided certificates in picky storage === //
+
+fn read_pem(what: &'static str, path: &Path) -> Result<Pem<'static>, CaSetupError> {
+    let pem_str = std::fs::read_to_string(path).map_err(|source| CaSetupError::ReadFile {
+        what,
+        path: path.to_owned(),
+        source,
+    })?;
+    pem_str
+        .parse::<Pem>()
+        .map_err(|source| CaSetupError::InvalidPem { what, source })
+}
+
+/// Stores a CA certificate and its key after checking that they are consistent: the certificate is
+/// issued by `issuer` (self-signed when `None`) with a supported signature algorithm, and the key is
+/// the one it certifies. The key is sealed by `signer` before being stored.
+fn inject_config_provided_cert(
+    expected_subject_name: &str,
+    cert_key_pair: &CertKeyPair,
+    issuer: Option<&Cert>,
+    storage: &dyn PickyStorage,
+    signer: &CaSigner,
+) -> Result<(), CaSetupError> {
+    let (cert, cert_der) = match &cert_key_pair.cert {
+        PathOr::Path(path) => {
+            let pem = read_pem("cert", path)?;
+            let cert = Cert::from_pem(&pem).map_err(|source| CaSetupError::InvalidCert { source })?;
+            (Cow::Owned(cert), pem.into_data().into_owned())
+        }
+        PathOr::Some(cert) => {
+            let cert_der = cert.to_der().map_err(|source| CaSetupError::InvalidCert { source })?;
+            (Cow::Borrowed(cert), cert_der)
+        }
+    };
+
+    let ski = hex::encode(
+        cert.subject_key_identifier()
+            .map_err(|source| CaSetupError::InvalidCert { source })?,
+    );
+    let subject_name = cert
+        .subject_name()
+        .find_common_name()
+        .ok_or(CaSetupError::MissingCommonName)?
+        .to_string();
+
+    if subject_name != expected_subject_name {
+        return Err(CaSetupError::UnexpectedSubjectName {
+            expected: expected_subject_name.to_owned(),
+            actual: subject_name,
+        });
+    }
+
+    SignatureHashType::from_algorithm_identifier(cert.signature_algorithm())
+        .map_err(|source| CaSetupError::UnsupportedSignatureAlgorithm { source })?;
+
+    let key_der = match &cert_key_pair.key {
+        Some(PathOr::Path(path)) => {
+            let pem = read_pem("key", path)?;
+            let key =
+                Picky::parse_pk_from_magic_der(pem.data()).map_err(|source| CaSetupError::InvalidKey { source })?;
+            Some((Cow::Owned(key), pem.into_data().into_owned()))
+        }
+        Some(PathOr::Some(key)) => Some((
+            Cow::Borrowed(key),
+            key.to_pkcs8().map_err(|source| CaSetupError::KeyEncoding { source })?,
+        )),
+        None => None,
+    };
+
+    if let Some((key, _)) = &key_der {
+        if key.to_public_key() != *cert.public_key() {
+            return Err(CaSetupError::KeyMismatch);
+        }
+    }
+
+    match issuer {
+        Some(issuer) => cert
+            .verify_chain(iter::once(issuer), &UTCDate::now())
+            .map_err(|source| CaSetupError::NotIssuedBy {
+                issuer: issuer.subject_name().to_string(),
+                source,
+            })?,
+        None => cert
+            .verify_chain(iter::empty(), &UTCDate::now())
+            .map_err(|source| CaSetupError::NotSelfSigned { source })?,
+    }
+
+    let sealed_key = key_der
+        .map(|(_, key_der)| signer.seal_key(&key_der))
+        .transpose()
+        .map_err(|source| CaSetupError::KeySealing { source })?;
+
+    storage
+        .store(CertificateEntry {
+            name: subject_name,
+            cert: cert_der,
+            key_identifier: ski,
+            key: sealed_key,
+            requested_by: None,
+            labels: Labels::new(),
+        })
+        .map_err(CaSetupError::storage("couldn't store certificate"))?;
+
+    Ok(())
+}
+
+// === config management === //
+
+fn reload_yaml_conf(controller_data: &ControllerData, req: &SyncRequest, res: &mut SyncResponse) {
+    match reload_yaml_conf_impl(controller_data) {
+        Ok(()) => {
+            res.body("Config reloaded successfully!");
+            res.status(StatusCode::OK);
         }
-        PathOr::Some(cert) => {
-            let cert_der = cert
-                .to_der()
-                .map_err(|e| format!("couldn't encode cert to der: {}", e))?;
-            (Cow::Borrowed(cert), cert_der)
+        Err(e) => {
+            let detail = format!("couldn't reload config: {}", e);
+            log::error!("{}", detail);
+            write_problem(req, res, ErrorCode::ConfigReloadFailed, detail);
+        }
+    }
+}
+
+fn reload_yaml_conf_impl(controller_data: &ControllerData) -> Result<(), StartupError> {
+    let overlay = controller_data.read_conf().overlay.clone();
+    match Config::init(overlay.as_deref()) {
+        Ok(new_conf) => {
+            log::info!("new config: {:#?}", new_conf);
+
+            new_conf.validate()?;
+            controller_data.storage.capabilities().check(&new_conf)?;
+
+            init_storage_from_config(controller_data.storage.as_ref(), &controller_data.signer, &new_conf)?;
+            *controller_data.hierarchy.write().expect("hierarchy lock") =
+                check_hierarchy(&new_conf, controller_data.storage.as_ref(), &controller_data.signer);
+
+            match build_logger_config(&new_conf) {
+                Ok(logger_config) => controller_data.log_handle.set_config(logger_config),
+                Err(e) => {
+                    log::warn!("couldn't reload logger configuration: {}", e);
+                }
+            }
+
+            let mut old_conf = controller_data.write_conf();
+            if old_conf.database_url != new_conf.database_url {
+                log::warn!("'database_url' modification require service restart");
+            }
+            if old_conf.file_backend_path != new_conf.file_backend_path {
+                log::warn!("'file_backend_path' modification require service restart");
+            }
+            if old_conf.backend != new_conf.backend {
+                log::warn!("'backend' modification require service restart");
+            }
+            *old_conf = new_conf;
+
+            log::info!("reloaded successfully");
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn init_storage_from_config(
+    storage: &dyn PickyStorage,
+    signer: &CaSigner,
+    config: &Config,
+) -> Result<(), CaSetupError> {
+    log::info!("init storage from config");
+
+    let root_name = format!("{} Root CA", config.realm);
+    if let Some(root_cert_key_pair) = &config.root {
+        log::info!("inject root CA provided by settings");
+        inject_config_provided_cert(&root_name, root_cert_key_pair, None, storage, signer)
+            .map_err(CaSetupError::invalid_provided_ca(&root_name))?;
+    } else if config.root_offline {
+        log::info!("root CA (offline)...");
+        fetch_ca_cert(&root_name, storage).map_err(CaSetupError::missing_offline_ca(&root_name))?;
+        log::info!("already exists");
+    } else {
+        log::info!("root CA...");
+        let created = generate_root_ca(&config, storage, signer).map_err(CaSetupError::generation(&root_name))?;
+        if created {
+            log::info!("created");
+        } else {
+            log::info!("already exists");
+        }
+    }
+
+    if config.root_offline {
+        let root_hash = storage
+            .get_addressing_hash_by_name(&root_name)
+            .map_err(CaSetupError::storage("couldn't fetch root CA"))?;
+        if signer.has_key(&root_hash) {
+            log::warn!("root CA is offline but its private key is still present in storage");
+        }
+    }
+
+    let root = fetch_ca_cert(&root_name, storage)?;
+
+    let intermediate_name = format!("{} Authority", config.realm);
+    if let Some(intermediate_cert_key_pair) = &config.intermediate {
+        log::info!("inject intermediate CA provided by settings");
+        inject_config_provided_cert(
+            &intermediate_name,
+            intermediate_cert_key_pair,
+            Some(&*root),
+            storage,
+            signer,
+        )
+        .map_err(CaSetupError::invalid_provided_ca(&intermediate_name))?;
+    } else if config.root_offline {
+        log::info!("intermediate CA...");
+        if let Err(e) = fetch_ca_cert(&intermediate_name, storage) {
+            log::error!("the intermediate CA certificate is signed offline with the `sign-intermediate` command");
+            return Err(CaSetupError::missing_offline_ca(&intermediate_name)(e));
+        }
+        log::info!("already exists");
+    } else {
+        log::info!("intermediate CA...");
+        let created =
+            generate_intermediate_ca(&config, storage, signer).map_err(CaSetupError::generation(&intermediate_name))?;
+        if created {
+            log::info!("created");
+        } else {
+            log::info!("already exists");
+        }
+    }
+
+    for (issuer, issuer_config) in &config.issuers {
+        let ca_name = config
+            .issuer_ca_name(Some(issuer))
+            .map_err(|source| CaSetupError::InvalidConfig { source })?;
+        log::info!("{}...", ca_name);
+        if let Some(cert_key_pair) = &issuer_config.ca {
+            inject_config_provided_cert(&ca_name, cert_key_pair, Some(&*root), storage, signer)
+                .map_err(CaSetupError::invalid_provided_ca(&ca_name))?;
+            log::info!("provided by settings");
+        } else if config.root_offline {
+            fetch_ca_cert(&ca_name, storage).map_err(CaSetupError::missing_offline_ca(&ca_name))?;
+            log::info!("already exists");
+        } else {
+            let created = generate_issuing_ca(
+                config,
+                storage,
+                signer,
+                ca_name.clone(),
+                issuer_config.key.unwrap_or(config.ca_keys.intermediate),
+            )
+            .map_err(CaSetupError::generation(&ca_name))?;
+            if created {
+                log::info!("created");
+            } else {
+                log::info!("already exists");
+            }
         }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{BackendType, IssuerConfig};
+    use picky::{
+        oids,
+        x509::{
+            extension::CrlReason,
+            name::GeneralName,
+            ocsp::{CertId, CertStatus, OcspRequest},
+            Crl,
+        },
     };
 
-    let ski = hex::encode(
-        cert.subject_key_identifier()
-            .map_err(|e| format!("couldn't parse fetch subject key identifier: {}", e))?,
-    );
-    let subject_name = cert
-        .subject_name()
-        .find_common_name()
-        .ok_or_else(|| "couldn't find subject common name".to_owned())?
-        .to_string();
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.backend = BackendType::Memory;
+        config
+    }
+
+    fn storage_and_signer(config: &Config) -> (Arc<dyn PickyStorage>, CaSigner) {
+        let (storage, key_locker) = get_storage(config).expect("storage");
+        (storage, CaSigner::new(key_locker, &config.storage_encryption_key))
+    }
+
+    #[test]
+    fn ca_keys_sealed_at_rest() {
+        let mut config = config();
+        config.storage_encryption_key = "s3cr3t".to_owned();
+        let root_name = format!("{} Root CA", config.realm);
+
+        let (storage, key_locker) = get_storage(&config).expect("storage");
+        let signer = CaSigner::new(key_locker.clone(), &config.storage_encryption_key);
+        generate_root_ca(&config, storage.as_ref(), &signer).expect("couldn't generate root ca");
+        let root_hash = storage.get_addressing_hash_by_name(&root_name).expect("root hash");
+        let root = Cert::from_der(&storage.get_cert_by_addressing_hash(&root_hash).expect("root cert")).expect("root");
+
+        let stored_key = key_locker.get_key_by_addressing_hash(&root_hash).expect("stored key");
+        assert!(Picky::parse_pk_from_magic_der(&stored_key).is_err());
+        assert!(signer
+            .key_matches(&root_hash, root.public_key())
+            .expect("open sealed key"));
+        assert!(CaSigner::new(key_locker.clone(), "")
+            .key_matches(&root_hash, root.public_key())
+            .is_err());
+        assert!(CaSigner::new(key_locker, "wrong")
+            .key_matches(&root_hash, root.public_key())
+            .is_err());
+
+        // keys stored before encryption was enabled are still usable
+        let (storage, key_locker) = get_storage(&config).expect("storage");
+        let clear_signer = CaSigner::new(key_locker.clone(), "");
+        generate_root_ca(&config, storage.as_ref(), &clear_signer).expect("couldn't generate root ca");
+        let root_hash = storage.get_addressing_hash_by_name(&root_name).expect("root hash");
+        let root = Cert::from_der(&storage.get_cert_by_addressing_hash(&root_hash).expect("root cert")).expect("root");
+        assert!(CaSigner::new(key_locker, &config.storage_encryption_key)
+            .key_matches(&root_hash, root.public_key())
+            .expect("read clear key"));
+    }
+
+    #[test]
+    fn generate_chain_and_verify() {
+        let config = config();
+        let (storage, signer) = storage_and_signer(&config);
+
+        let ca_name = format!("{} Authority", config.realm);
+
+        generate_root_ca(&config, storage.as_ref(), &signer).expect("couldn't generate root ca");
+        generate_intermediate_ca(&config, storage.as_ref(), &signer).expect("couldn't generate intermediate ca");
+
+        let pk = Picky::generate_private_key(2048).expect("couldn't generate private key");
+        let csr = Csr::generate(
+            DirectoryName::new_common_name("Mister Bushido"),
+            &pk,
+            SignatureHashType::RsaSha384,
+        )
+        .expect("couldn't generate csr");
+
+        let signed_cert = sign_certificate(
+            &ca_name,
+            csr,
+            &AltNames::default(),
+            &config,
+            storage.as_ref(),
+            &signer,
+            IssuanceOrigin::default(),
+        )
+        .expect("couldn't sign certificate");
+
+        let issuer_name = signed_cert.issuer_name().find_common_name().unwrap().to_string();
+        let chain_pem = find_ca_chain(storage.as_ref(), &issuer_name).expect("couldn't fetch CA chain");
+
+        assert_eq!(chain_pem.len(), 2);
+
+        let chain = chain_pem
+            .iter()
+            .map(|cert_pem| {
+                let pem = cert_pem.parse::<Pem>().expect("couldn't parse cert pem");
+                Cert::from_der(pem.data()).expect("couldn't parse cert from der")
+            })
+            .collect::<Vec<Cert>>();
+
+        assert_eq!(chain[0].subject_name().to_string(), "CN=Picky Authority");
+        assert_eq!(chain[1].subject_name().to_string(), "CN=Picky Root CA");
+
+        signed_cert
+            .verify_chain(chain.iter(), &UTCDate::now())
+            .expect("couldn't validate ca chain");
+    }
+
+    #[test]
+    fn extra_dns_names() {
+        let config = config();
+        let (storage, signer) = storage_and_signer(&config);
+
+        let ca_name = format!("{} Authority", config.realm);
+        generate_root_ca(&config, storage.as_ref(), &signer).expect("couldn't generate root ca");
+        generate_intermediate_ca(&config, storage.as_ref(), &signer).expect("couldn't generate intermediate ca");
+
+        let pk = Picky::generate_private_key(2048).expect("couldn't generate private key");
+        let csr = Csr::generate(
+            DirectoryName::new_common_name("bushido.example.com"),
+            &pk,
+            SignatureHashType::RsaSha256,
+        )
+        .expect("couldn't generate csr");
+        let alt_names = AltNames {
+            dns_names: vec!["bushido.example.com".to_owned(), "www.bushido.example.com".to_owned()],
+            ..AltNames::default()
+        };
+        let signed_cert = sign_certificate(
+            &ca_name,
+            csr,
+            &alt_names,
+            &config,
+            storage.as_ref(),
+            &signer,
+            IssuanceOrigin::default(),
+        )
+        .expect("couldn't sign certificate");
+
+        let san = signed_cert.subject_alt_names().expect("couldn't find SAN");
+        assert_eq!(san.find_dns_name().unwrap().to_string(), "bushido.example.com");
+        assert_eq!(san.into_general_names().len(), 2);
+    }
+
+    #[test]
+    fn enforced_lint_policy() {
+        let mut config = config();
+        config.lint_policy.enforce = true;
+        let (storage, signer) = storage_and_signer(&config);
+
+        let ca_name = format!("{} Authority", config.realm);
+        generate_root_ca(&config, storage.as_ref(), &signer).expect("couldn't generate root ca");
+        generate_intermediate_ca(&config, storage.as_ref(), &signer).expect("couldn't generate intermediate ca");
+
+        let pk = Picky::generate_private_key(2048).expect("couldn't generate private key");
+        let csr = Csr::generate(
+            DirectoryName::new_common_name("bushido.example.com"),
+            &pk,
+            SignatureHashType::RsaSha1,
+        )
+        .expect("couldn't generate csr");
+        let err = sign_certificate(
+            &ca_name,
+            csr,
+            &AltNames::default(),
+            &config,
+            storage.as_ref(),
+            &signer,
+            IssuanceOrigin::default(),
+        )
+        .err()
+        .expect("SHA-1 signed csr");
+        assert_eq!(err.code(), ErrorCode::PolicyViolation);
+        assert_eq!(err.to_string(), "refused by lint policy: signed using SHA-1");
+
+        // the common name only request is merely warned about
+        let csr = Csr::generate(
+            DirectoryName::new_common_name("bushido.example.com"),
+            &pk,
+            SignatureHashType::RsaSha256,
+        )
+        .expect("couldn't generate csr");
+        sign_certificate(
+            &ca_name,
+            csr,
+            &AltNames::default(),
+            &config,
+            storage.as_ref(),
+            &signer,
+            IssuanceOrigin::default(),
+        )
+        .expect("couldn't sign certificate");
+    }
+
+    #[test]
+    fn san_only_leaf() {
+        let mut config = config();
+        config.empty_leaf_subject = true;
+        let (storage, signer) = storage_and_signer(&config);
+
+        let ca_name = format!("{} Authority", config.realm);
+        generate_root_ca(&config, storage.as_ref(), &signer).expect("couldn't generate root ca");
+        generate_intermediate_ca(&config, storage.as_ref(), &signer).expect("couldn't generate intermediate ca");
+
+        let pk = Picky::generate_private_key(2048).expect("couldn't generate private key");
+        let csr = Csr::generate(DirectoryName::new_empty(), &pk, SignatureHashType::RsaSha256)
+            .expect("couldn't generate csr");
+        let err = sign_certificate(
+            &ca_name,
+            csr.clone(),
+            &AltNames::default(),
+            &config,
+            storage.as_ref(),
+            &signer,
+            IssuanceOrigin::default(),
+        )
+        .err()
+        .expect("no name at all");
+        assert_eq!(err.code(), ErrorCode::InvalidRequest);
+        assert_eq!(
+            err.to_string(),
+            "couldn't find signed cert subject common name nor any subject alternative name"
+        );
+
+        let signed_cert = sign_certificate(
+            &ca_name,
+            csr,
+            &AltNames {
+                dns_names: vec!["bushido.example.com".to_owned()],
+                ..AltNames::default()
+            },
+            &config,
+            storage.as_ref(),
+            &signer,
+            IssuanceOrigin::default(),
+        )
+        .expect("couldn't sign certificate");
+
+        assert!(signed_cert.subject_name().is_empty());
+        let san = signed_cert.subject_alt_names().expect("couldn't find SAN");
+        assert_eq!(san.find_dns_name().unwrap().to_string(), "bushido.example.com");
+        assert!(signed_cert
+            .extensions()
+            .iter()
+            .any(|extension| extension.extn_id().0 == oids::subject_alternative_name() && extension.critical()));
+    }
+
+    #[test]
+    fn ip_and_uri_alt_names() {
+        let mut config = config();
+        let (storage, signer) = storage_and_signer(&config);
+
+        let ca_name = format!("{} Authority", config.realm);
+        generate_root_ca(&config, storage.as_ref(), &signer).expect("couldn't generate root ca");
+        generate_intermediate_ca(&config, storage.as_ref(), &signer).expect("couldn't generate intermediate ca");
+
+        let pk = Picky::generate_private_key(2048).expect("couldn't generate private key");
+        let csr = Csr::generate(
+            DirectoryName::new_common_name("10.1.0.1"),
+            &pk,
+            SignatureHashType::RsaSha256,
+        )
+        .expect("couldn't generate csr");
+        let alt_names = AltNames {
+            dns_names: Vec::new(),
+            ip_addresses: vec!["10.1.0.2".parse().unwrap()],
+            uris: vec!["spiffe://example.org/payments".to_owned()],
+        };
+
+        let err = sign_certificate(
+            &ca_name,
+            csr.clone(),
+            &alt_names,
+            &config,
+            storage.as_ref(),
+            &signer,
+            IssuanceOrigin::default(),
+        )
+        .err()
+        .expect("refused by default policy");
+        assert_eq!(err.code(), ErrorCode::PolicyViolation);
+        assert_eq!(err.to_string(), "IP address 10.1.0.2 is not allowed by policy");
+
+        config.alt_name_policy.ip_ranges = vec!["10.1.0.0/16".to_owned()];
+        config.alt_name_policy.uri_schemes = vec!["spiffe".to_owned()];
+        let signed_cert = sign_certificate(
+            &ca_name,
+            csr,
+            &alt_names,
+            &config,
+            storage.as_ref(),
+            &signer,
+            IssuanceOrigin::default(),
+        )
+        .expect("couldn't sign certificate");
+
+        let general_names = signed_cert
+            .subject_alt_names()
+            .expect("couldn't find SAN")
+            .into_general_names();
+        assert_eq!(
+            general_names,
+            vec![
+                GeneralName::new_ip_address(vec![10, 1, 0, 1]),
+                GeneralName::new_ip_address(vec![10, 1, 0, 2]),
+                GeneralName::new_uri("spiffe://example.org/payments").unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn certificate_profile() {
+        let mut config = config();
+        config.profiles.insert(
+            "code-signing".to_owned(),
+            serde_yaml::from_str(
+                "{ key_usage: [digital_signature], extended_key_usages: [code_signing], common_name_alt_name: false }",
+            )
+            .expect("profile"),
+        );
+        let (storage, signer) = storage_and_signer(&config);
+
+        let ca_name = format!("{} Authority", config.realm);
+        generate_root_ca(&config, storage.as_ref(), &signer).expect("couldn't generate root ca");
+        generate_intermediate_ca(&config, storage.as_ref(), &signer).expect("couldn't generate intermediate ca");
+
+        let pk = Picky::generate_private_key(2048).expect("couldn't generate private key");
+        let csr = Csr::generate(
+            DirectoryName::new_common_name("Release Signing"),
+            &pk,
+            SignatureHashType::RsaSha256,
+        )
+        .expect("couldn't generate csr");
+
+        let err = sign_certificate(
+            &ca_name,
+            csr.clone(),
+            &AltNames::default(),
+            &config,
+            storage.as_ref(),
+            &signer,
+            IssuanceOrigin {
+                profile: Some("server-tls".to_owned()),
+                ..IssuanceOrigin::default()
+            },
+        )
+        .err()
+        .expect("unknown profile");
+        assert_eq!(err.code(), ErrorCode::InvalidRequest);
+        assert_eq!(err.to_string(), "unknown certificate profile 'server-tls'");
+
+        let signed_cert = sign_certificate(
+            &ca_name,
+            csr,
+            &AltNames::default(),
+            &config,
+            storage.as_ref(),
+            &signer,
+            IssuanceOrigin {
+                profile: Some("code-signing".to_owned()),
+                ..IssuanceOrigin::default()
+            },
+        )
+        .expect("couldn't sign certificate");
+
+        let key_usage = signed_cert.key_usage().expect("key usage");
+        assert!(key_usage.digital_signature());
+        assert!(!key_usage.key_encipherment());
+        let eku = signed_cert.extended_key_usage().expect("extended key usage");
+        assert!(eku.contains(oids::kp_code_signing()));
+        assert!(!eku.contains(oids::kp_server_auth()));
+        // the common name isn't a host name
+        assert!(signed_cert.subject_alt_names().is_err());
+    }
+
+    #[test]
+    fn issuing_intermediates() {
+        let mut config = config();
+        config.issuers.insert("staging".to_owned(), IssuerConfig::default());
+        config.external_base_url = Some("https://picky.example.com".to_owned());
+        let (storage, signer) = storage_and_signer(&config);
+
+        let ca_name = config.issuer_ca_name(Some("staging")).expect("issuer");
+        generate_root_ca(&config, storage.as_ref(), &signer).expect("couldn't generate root ca");
+        generate_intermediate_ca(&config, storage.as_ref(), &signer).expect("couldn't generate intermediate ca");
+        let created = generate_issuing_ca(
+            &config,
+            storage.as_ref(),
+            &signer,
+            ca_name.clone(),
+            config.ca_keys.intermediate,
+        )
+        .expect("couldn't generate issuing ca");
+        assert!(created);
+
+        let pk = Picky::generate_private_key(2048).expect("couldn't generate private key");
+        let csr = Csr::generate(
+            DirectoryName::new_common_name("staging.example.com"),
+            &pk,
+            SignatureHashType::RsaSha256,
+        )
+        .expect("couldn't generate csr");
+        let signed_cert = sign_certificate(
+            &ca_name,
+            csr,
+            &AltNames::default(),
+            &config,
+            storage.as_ref(),
+            &signer,
+            IssuanceOrigin::default(),
+        )
+        .expect("couldn't sign certificate");
+        assert_eq!(signed_cert.issuer_name().to_string(), "CN=Picky staging Authority");
+
+        // URLs point to the CRL and hierarchy of the issuer
+        let aia = signed_cert.authority_info_access().expect("authority info access");
+        assert_eq!(aia.ocsp_uris()[0].to_string(), "https://picky.example.com/ocsp");
+        assert_eq!(
+            aia.ca_issuers_uris()[0].to_string(),
+            "https://picky.example.com/cacerts?issuer=staging"
+        );
+        let crl_dp = signed_cert.crl_distribution_points().expect("crl distribution points");
+        assert_eq!(
+            crl_dp.uris()[0].to_string(),
+            "https://picky.example.com/crl?issuer=staging"
+        );
+
+        let chain = find_ca_chain(storage.as_ref(), &ca_name)
+            .expect("couldn't fetch CA chain")
+            .iter()
+            .map(|cert_pem| Cert::from_pem(&cert_pem.parse::<Pem>().unwrap()).unwrap())
+            .collect::<Vec<Cert>>();
+        assert_eq!(chain[0].subject_name().to_string(), "CN=Picky staging Authority");
+        assert_eq!(chain[1].subject_name().to_string(), "CN=Picky Root CA");
+        signed_cert
+            .verify_chain(chain.iter(), &UTCDate::now())
+            .expect("couldn't validate ca chain");
+
+        // the OCSP responder and the CRL of the issuer know about its revocations
+        let ca_cert = &chain[0];
+        let cert_id = CertId::new(&signed_cert, ca_cert).expect("cert id");
+        storage
+            .store_revocation(RevocationEntry {
+                issuer: crl::ca_key_identifier(ca_cert).expect("CA key identifier"),
+                serial_number: hex::encode(signed_cert.serial_number().as_unsigned_bytes_be()),
+                revoked_at: 1_600_000_000,
+                reason: None,
+            })
+            .expect("couldn't store revocation");
+
+        let request = OcspRequest::new(vec![cert_id.clone()], None).expect("request");
+        let response = ocsp::respond(
+            &config,
+            storage.as_ref(),
+            &signer,
+            &request.to_der().expect("request der"),
+        );
+        let basic_response = response.basic_response().expect("basic response");
+        basic_response
+            .verify_issuer(ca_cert, &UTCDate::now())
+            .expect("couldn't verify response");
+        assert!(matches!(
+            basic_response
+                .find_response(&cert_id)
+                .expect("single response")
+                .cert_status(),
+            CertStatus::Revoked { .. }
+        ));
+
+        let (_, crl_der) =
+            crl::generate(&config, storage.as_ref(), &signer, Some("staging"), None).expect("couldn't generate CRL");
+        let crl = Crl::from_der(&crl_der).expect("couldn't parse CRL");
+        crl.verify_issuer(ca_cert).expect("couldn't verify CRL");
+        assert_eq!(crl.revoked_certificates().len(), 1);
+        let (_, default_crl_der) =
+            crl::generate(&config, storage.as_ref(), &signer, None, None).expect("couldn't generate CRL");
+        assert!(Crl::from_der(&default_crl_der)
+            .expect("couldn't parse CRL")
+            .revoked_certificates()
+            .is_empty());
+    }
+
+    #[test]
+    fn issuance_log_tree_head() {
+        let config = config();
+        let (storage, signer) = storage_and_signer(&config);
+
+        let ca_name = format!("{} Authority", config.realm);
+        generate_root_ca(&config, storage.as_ref(), &signer).expect("couldn't generate root ca");
+        generate_intermediate_ca(&config, storage.as_ref(), &signer).expect("couldn't generate intermediate ca");
+        let ca_hash = storage.get_addressing_hash_by_name(&ca_name).expect("CA hash");
+        let ca_cert =
+            Cert::from_der(&storage.get_cert_by_addressing_hash(&ca_hash).expect("CA cert")).expect("CA cert");
+
+        let pk = Picky::generate_private_key(2048).expect("couldn't generate private key");
+        let csr = Csr::generate(
+            DirectoryName::new_common_name("log.example.com"),
+            &pk,
+            SignatureHashType::RsaSha256,
+        )
+        .expect("couldn't generate csr");
+        let leaf = sign_certificate(
+            &ca_name,
+            csr,
+            &AltNames::default(),
+            &config,
+            storage.as_ref(),
+            &signer,
+            IssuanceOrigin::default(),
+        )
+        .expect("couldn't sign certificate");
+
+        let leaves = issuance_log::leaves(storage.as_ref()).expect("couldn't fetch issuance log");
+        assert_eq!(leaves.len(), 1);
+        assert_eq!(
+            leaves[0].leaf_hash,
+            issuance_log::leaf_hash(&leaf.to_der().expect("leaf der"))
+        );
 
-    if subject_name != expected_subject_name {
-        return Err(format!(
-            "unexpected subject name: {} ; expected: {}",
-            subject_name, expected_subject_name
-        ));
+        let tree_head =
+            issuance_log::tree_head(&config, storage.as_ref(), &signer, &leaves).expect("couldn't sign tree head");
+        assert_eq!(tree_head.tree_size, 1);
+        let root_hash = base64::decode(&tree_head.sha256_root_hash).expect("root hash");
+        let signature = base64::decode(&tree_head.tree_head_signature).expect("signature");
+        config
+            .leaf_signing_algorithm()
+            .verify(
+                ca_cert.public_key(),
+                &issuance_log::tree_head_signature_input(1, tree_head.timestamp, &root_hash),
+                &signature,
+            )
+            .expect("couldn't verify tree head signature");
+        assert!(config
+            .leaf_signing_algorithm()
+            .verify(
+                ca_cert.public_key(),
+                &issuance_log::tree_head_signature_input(2, tree_head.timestamp, &root_hash),
+                &signature,
+            )
+            .is_err());
     }
 
-    let key_der = match &cert_key_pair.key {
-        PathOr::Path(path) => {
-            let pem_str = std::fs::read_to_string(path).map_err(|e| format!("couldn't read key: {}", e))?;
-            let pem = pem_str
-                .parse::<Pem>()
-                .map_err(|e| format!("couldn't parse key pem: {}", e))?;
-            pem.into_data().into_owned()
-        }
-        PathOr::Some(key) => key
-            .to_pkcs8()
-            .map_err(|e| format!("couldn't convert key to pkcs8: {}", e))?,
-    };
+    #[test]
+    fn generated_password() {
+        let password = generate_password();
+        assert_eq!(password.len(), GENERATED_PASSWORD_LEN);
+        assert!(password.bytes().all(|byte| GENERATED_PASSWORD_CHARSET.contains(&byte)));
+        assert_ne!(password, generate_password());
+    }
 
-    storage
-        .store(CertificateEntry {
-            name: subject_name,
-            cert: cert_der,
-            key_identifier: ski,
-            key: Some(key_der),
-        })
-        .map_err(|e| format!("couldn't store certificate: {}", e))?;
+    #[test]
+    fn ca_hierarchy_validation() {
+        let config = config();
+        let (storage, signer) = storage_and_signer(&config);
+        init_storage_from_config(storage.as_ref(), &signer, &config).expect("init storage");
+        let report = hierarchy::check(&config, storage.as_ref(), &signer);
+        assert!(report.valid, "{:?}", report.problems);
+
+        // intermediate CA issued by another root
+        let (storage, signer) = storage_and_signer(&config);
+        generate_root_ca(&config, storage.as_ref(), &signer).expect("couldn't generate root ca");
+        let key = |pem: &str| PrivateKey::from_pem(&pem.parse::<Pem>().expect("pem")).expect("private key");
+        let other_root_key = key(crate::test_files::RSA_2048_PK_1);
+        let intermediate_key = key(crate::test_files::RSA_2048_PK_2);
+        let other_root = Picky::generate_root(
+            "Other Root CA",
+            &other_root_key,
+            SignatureHashType::RsaSha256,
+            IssuerOptions::root(),
+        )
+        .expect("generate other root");
+        let intermediate = Picky::generate_intermediate(
+            "Picky Authority",
+            intermediate_key.to_public_key(),
+            &other_root,
+            &other_root_key,
+            SignatureHashType::RsaSha256,
+            IssuerOptions::intermediate(),
+        )
+        .expect("generate intermediate");
+        storage
+            .store(CertificateEntry {
+                name: "Picky Authority".to_owned(),
+                cert: intermediate.to_der().expect("intermediate der"),
+                key_identifier: hex::encode(intermediate.subject_key_identifier().expect("ski")),
+                key: Some(intermediate_key.to_pkcs8().expect("pkcs8")),
+                requested_by: None,
+                labels: Labels::new(),
+            })
+            .expect("store intermediate");
 
-    Ok(())
-}
+        let report = hierarchy::check(&config, storage.as_ref(), &signer);
+        assert!(!report.valid);
+        assert_eq!(report.problems.len(), 1);
+        assert!(report.problems[0].starts_with("intermediate CA doesn't verify under root CA"));
+    }
 
-// === config management === //
+    #[test]
+    fn config_provided_ca_validation() {
+        let key = |pem: &str| PrivateKey::from_pem(&pem.parse::<Pem>().expect("pem")).expect("private key");
+        let root_key = key(crate::test_files::RSA_2048_PK_1);
+        let intermediate_key = key(crate::test_files::RSA_2048_PK_2);
+        let other_key = key(crate::test_files::RSA_2048_PK_3);
+        let root = Picky::generate_root(
+            "Picky Root CA",
+            &root_key,
+            SignatureHashType::RsaSha256,
+            IssuerOptions::root(),
+        )
+        .expect("generate root");
+        let intermediate = |issuer: &Cert, issuer_key: &PrivateKey| {
+            Picky::generate_intermediate(
+                "Picky Authority",
+                intermediate_key.to_public_key(),
+                issuer,
+                issuer_key,
+                SignatureHashType::RsaSha256,
+                IssuerOptions::intermediate(),
+            )
+            .expect("generate intermediate")
+        };
+        let pair = |cert: Cert, key: &PrivateKey| CertKeyPair {
+            cert: PathOr::Some(cert),
+            key: Some(PathOr::Some(key.clone())),
+        };
 
-fn reload_yaml_conf(controller_data: &ControllerData, _: &SyncRequest, res: &mut SyncResponse) {
-    match reload_yaml_conf_impl(controller_data) {
-        Ok(()) => {
-            res.body("Config reloaded successfully!");
-            res.status(StatusCode::OK);
+        let mut config = config();
+        config.root = Some(pair(root.clone(), &root_key));
+        config.intermediate = Some(pair(intermediate(&root, &root_key), &intermediate_key));
+        let (storage, signer) = storage_and_signer(&config);
+        init_storage_from_config(storage.as_ref(), &signer, &config).expect("init storage");
+
+        // key not matching the certificate
+        config.root = Some(pair(root.clone(), &other_key));
+        let (storage, signer) = storage_and_signer(&config);
+        let err = init_storage_from_config(storage.as_ref(), &signer, &config).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "couldn't inject Picky Root CA: private key doesn't match the certificate public key"
+        );
+        match err.root_cause() {
+            CaSetupError::KeyMismatch => {}
+            err => panic!("unexpected error: {}", err),
         }
-        Err(e) => {
-            log::error!("couldn't reload config: {}", e);
-            res.body("Couldn't reload config... See logs");
-            res.status(StatusCode::BAD_REQUEST);
+        assert!(storage.get_addressing_hash_by_name("Picky Root CA").is_err());
+
+        // intermediate CA issued by another root
+        let other_root = Picky::generate_root(
+            "Other Root CA",
+            &other_key,
+            SignatureHashType::RsaSha256,
+            IssuerOptions::root(),
+        )
+        .expect("generate other root");
+        config.root = Some(pair(root, &root_key));
+        config.intermediate = Some(pair(intermediate(&other_root, &other_key), &intermediate_key));
+        let (storage, signer) = storage_and_signer(&config);
+        let err = init_storage_from_config(storage.as_ref(), &signer, &config).unwrap_err();
+        match err {
+            CaSetupError::InvalidProvidedCa { ca, source } => {
+                assert_eq!(ca, "Picky Authority");
+                match *source {
+                    CaSetupError::NotIssuedBy { issuer, .. } => assert_eq!(issuer, "CN=Picky Root CA"),
+                    err => panic!("unexpected error: {}", err),
+                }
+            }
+            err => panic!("unexpected error: {}", err),
         }
+        assert!(storage.get_addressing_hash_by_name("Picky Authority").is_err());
     }
-}
 
-fn reload_yaml_conf_impl(controller_data: &ControllerData) -> Result<(), String> {
-    match Config::init_yaml() {
-        Ok(new_conf) => {
-            log::info!("new config: {:#?}", new_conf);
+    #[test]
+    fn uploaded_chain_links() {
+        let config = config();
+        let (storage, signer) = storage_and_signer(&config);
 
-            init_storage_from_config(controller_data.storage.as_ref(), &new_conf)?;
+        let ca_name = format!("{} Authority", config.realm);
+        generate_root_ca(&config, storage.as_ref(), &signer).expect("couldn't generate root ca");
+        generate_intermediate_ca(&config, storage.as_ref(), &signer).expect("couldn't generate intermediate ca");
 
-            match build_logger_config(&new_conf) {
-                Ok(logger_config) => controller_data.log_handle.set_config(logger_config),
-                Err(e) => {
-                    log::warn!("couldn't reload logger configuration: {}", e);
-                }
-            }
+        let pk = Picky::generate_private_key(2048).expect("couldn't generate private key");
+        let csr = Csr::generate(
+            DirectoryName::new_common_name("Mister Bushido"),
+            &pk,
+            SignatureHashType::RsaSha256,
+        )
+        .expect("couldn't generate csr");
+        let leaf = sign_certificate(
+            &ca_name,
+            csr,
+            &AltNames::default(),
+            &config,
+            storage.as_ref(),
+            &signer,
+            IssuanceOrigin::default(),
+        )
+        .expect("couldn't sign certificate");
+        let leaf_der = leaf.to_der().expect("couldn't encode leaf");
+
+        let chain_pem = find_ca_chain(storage.as_ref(), &ca_name).expect("couldn't fetch CA chain");
+        let intermediate_der = chain_pem[0]
+            .parse::<Pem>()
+            .expect("couldn't parse pem")
+            .into_data()
+            .into_owned();
+
+        let bundle = format!("{}\n{}", to_pem("CERTIFICATE", &leaf_der), chain_pem[0]);
+        let ders = pem_bundle_to_ders(bundle.as_bytes()).expect("couldn't parse pem bundle");
+        assert_eq!(ders, vec![leaf_der.clone(), intermediate_der.clone()]);
+
+        let pkcs7_bundle = SignedData::from_certs(&[Cert::from_der(&leaf_der).unwrap()])
+            .and_then(|signed_data| signed_data.to_pem())
+            .expect("couldn't encode pkcs7")
+            .to_string();
+        assert_eq!(
+            pem_bundle_to_ders(pkcs7_bundle.as_bytes()).expect("couldn't parse pkcs7 pem"),
+            vec![leaf_der.clone()]
+        );
 
-            let mut old_conf = controller_data.write_conf();
-            if old_conf.database_url != new_conf.database_url {
-                log::warn!("'database_url' modification require service restart");
-            }
-            if old_conf.file_backend_path != new_conf.file_backend_path {
-                log::warn!("'file_backend_path' modification require service restart");
-            }
-            if old_conf.backend != new_conf.backend {
-                log::warn!("'backend' modification require service restart");
-            }
-            *old_conf = new_conf;
+        let leaf = Cert::from_der(&leaf_der).unwrap();
+        let intermediate = Cert::from_der(&intermediate_der).unwrap();
+        let chain = vec![leaf.clone(), intermediate.clone()];
+        assert_eq!(
+            order_chain_links(&[leaf.clone(), intermediate.clone()]).expect("chain links should be valid"),
+            chain
+        );
+        assert_eq!(
+            order_chain_links(&[intermediate.clone(), leaf.clone(), intermediate.clone()])
+                .expect("chain links should be valid"),
+            chain
+        );
+        assert_eq!(
+            order_chain_links(&[intermediate.clone()]).expect("chain links should be valid"),
+            vec![intermediate.clone()]
+        );
 
-            log::info!("reloaded successfully");
-            Ok(())
-        }
-        Err(e) => Err(format!("couldn't reload config: {}", e)),
+        verify_chain_to_ca(storage.as_ref(), &config.realm, &[leaf.clone()]).expect("leaf should chain to the CA");
+        verify_chain_to_ca(storage.as_ref(), &config.realm, &[leaf.clone(), intermediate])
+            .expect("leaf and intermediate should chain to the CA");
+
+        let spoofed = Picky::generate_root(&ca_name, &pk, SignatureHashType::RsaSha256, IssuerOptions::root())
+            .expect("couldn't generate root");
+        // the spoofed root has the name of the intermediate CA but not its key
+        assert!(order_chain_links(&[spoofed.clone(), leaf]).is_err());
+        let err = verify_chain_to_ca(storage.as_ref(), &config.realm, &[spoofed])
+            .err()
+            .expect("spoofed root");
+        assert_eq!(err.code(), ErrorCode::PolicyViolation);
     }
-}
 
-fn init_storage_from_config(storage: &dyn PickyStorage, config: &Config) -> Result<(), String> {
-    log::info!("init storage from config");
+    #[test]
+    fn revocation_target_selection() {
+        let mut config = config();
+        config.save_certificate = true;
+        let (storage, signer) = storage_and_signer(&config);
 
-    if let Some(root_cert_key_pair) = &config.root {
-        log::info!("inject root CA provided by settings");
-        if let Err(e) = inject_config_provided_cert(&format!("{} Root CA", config.realm), root_cert_key_pair, storage) {
-            return Err(format!("couldn't inject root CA: {}", e));
-        }
-    } else {
-        log::info!("root CA...");
-        let created = generate_root_ca(&config, storage).map_err(|e| format!("couldn't generate root CA: {}", e))?;
-        if created {
-            log::info!("created");
-        } else {
-            log::info!("already exists");
+        let ca_name = format!("{} Authority", config.realm);
+        generate_root_ca(&config, storage.as_ref(), &signer).expect("couldn't generate root ca");
+        generate_intermediate_ca(&config, storage.as_ref(), &signer).expect("couldn't generate intermediate ca");
+
+        let pk = Picky::generate_private_key(2048).expect("couldn't generate private key");
+        let csr = Csr::generate(
+            DirectoryName::new_common_name("Mister Bushido"),
+            &pk,
+            SignatureHashType::RsaSha256,
+        )
+        .expect("couldn't generate csr");
+        let signed_cert = sign_certificate(
+            &ca_name,
+            csr,
+            &AltNames::default(),
+            &config,
+            storage.as_ref(),
+            &signer,
+            IssuanceOrigin::default(),
+        )
+        .expect("couldn't sign certificate");
+        let serial_number = hex::encode(signed_cert.serial_number().as_unsigned_bytes_be());
+        let addresses = encode_to_addresses(&signed_cert.to_der().expect("der")).expect("addresses");
+        let issuer = issuer_key_identifier(&config, storage.as_ref(), None).expect("issuer key identifier");
+        assert_eq!(
+            signed_cert
+                .authority_key_identifier()
+                .expect("aki")
+                .key_identifier()
+                .map(hex::encode),
+            Some(issuer.clone())
+        );
+
+        for address in std::iter::once(&addresses.address).chain(addresses.alternative_addresses.iter()) {
+            let request = RevocationRequest {
+                address: Some(address.clone()),
+                ..RevocationRequest::default()
+            };
+            let target = revocation_target(&config, storage.as_ref(), &request).expect("couldn't select certificate");
+            assert_eq!(target.issuer, issuer);
+            assert_eq!(target.serial_number, serial_number);
+            assert_eq!(target.address.as_deref(), Some(addresses.address.as_str()));
+            assert_eq!(target.subject_name.as_deref(), Some("CN=Mister Bushido"));
         }
+
+        let target = revocation_target_from_address(storage.as_ref(), &addresses.address).expect("target");
+        let issued_at = to_chrono(&signed_cert.valid_not_before()).timestamp() as u64;
+        assert_eq!(target.issued_at, Some(issued_at));
+        let now = issued_at + 3600;
+        assert_eq!(revocation_time(None, &target, now).unwrap(), now);
+        assert_eq!(revocation_time(Some(issued_at), &target, now).unwrap(), issued_at);
+        assert!(revocation_time(Some(issued_at - 1), &target, now).is_err());
+        assert!(revocation_time(Some(now + 1), &target, now).is_err());
+        assert!(check_revocation_reason(Some(1)).is_ok());
+        assert!(check_revocation_reason(Some(7)).is_err());
+
+        let request = RevocationRequest {
+            serial_number: Some("0A0B".to_owned()),
+            ..RevocationRequest::default()
+        };
+        let target = revocation_target(&config, storage.as_ref(), &request).expect("couldn't select certificate");
+        assert_eq!(target.issuer, issuer);
+        assert_eq!(target.serial_number, "0a0b");
+        assert_eq!(target.address, None);
+
+        let request = RevocationRequest {
+            serial_number: Some("0A0B".to_owned()),
+            issuer: Some("unknown".to_owned()),
+            ..RevocationRequest::default()
+        };
+        let err = revocation_target(&config, storage.as_ref(), &request).err().unwrap();
+        assert_eq!(err.code(), ErrorCode::InvalidRequest);
+
+        let request = RevocationRequest {
+            address: Some(addresses.address.clone()),
+            serial_number: Some(serial_number),
+            ..RevocationRequest::default()
+        };
+        let err = revocation_target(&config, storage.as_ref(), &request).err().unwrap();
+        assert_eq!(err.code(), ErrorCode::InvalidRequest);
+        assert_eq!(err.to_string(), "either 'address' or 'serial_number' must be provided");
+
+        let request = RevocationRequest {
+            serial_number: Some("not hex".to_owned()),
+            ..RevocationRequest::default()
+        };
+        let err = revocation_target(&config, storage.as_ref(), &request).err().unwrap();
+        assert_eq!(err.code(), ErrorCode::InvalidRequest);
     }
 
-    if let Some(intermediate_cert_key_pair) = &config.intermediate {
-        log::info!("inject intermediate CA provided by settings");
-        if let Err(e) = inject_config_provided_cert(
-            &format!("{} Authority", config.realm),
-            intermediate_cert_key_pair,
-            storage,
-        ) {
-            return Err(format!("couldn't inject intermediate CA: {}", e));
-        }
-    } else {
-        log::info!("intermediate CA...");
-        let created = generate_intermediate_ca(&config, storage)
-            .map_err(|e| format!("couldn't generate intermediate CA: {}", e))?;
-        if created {
-            log::info!("created");
-        } else {
-            log::info!("already exists");
+    #[test]
+    fn crl_generation() {
+        let mut config = config();
+        config.external_base_url = Some("https://picky.example.com".to_owned());
+        config.leaf_extensions.crl_partitions = 2;
+        let (storage, signer) = storage_and_signer(&config);
+
+        let ca_name = format!("{} Authority", config.realm);
+        generate_root_ca(&config, storage.as_ref(), &signer).expect("couldn't generate root ca");
+        generate_intermediate_ca(&config, storage.as_ref(), &signer).expect("couldn't generate intermediate ca");
+
+        let issuer = issuer_key_identifier(&config, storage.as_ref(), None).expect("issuer key identifier");
+        for (issuer, serial_number, reason) in [
+            (issuer.as_str(), "ffffffff", Some(1)),
+            (issuer.as_str(), "123456", None),
+            // revoked certificate of another CA
+            ("0123456789abcdef", "0c", None),
+        ]
+        .iter()
+        {
+            storage
+                .store_revocation(RevocationEntry {
+                    issuer: (*issuer).to_owned(),
+                    serial_number: (*serial_number).to_owned(),
+                    revoked_at: 1_600_000_000,
+                    reason: *reason,
+                })
+                .expect("couldn't store revocation");
         }
-    }
 
-    Ok(())
-}
+        let addresses = crl::generate_all(&config, storage.as_ref(), &signer).expect("couldn't generate CRLs");
+        assert_eq!(addresses.len(), 3);
+
+        let ca_hash = storage.get_addressing_hash_by_name(&ca_name).expect("CA hash");
+        let ca_cert =
+            Cert::from_der(&storage.get_cert_by_addressing_hash(&ca_hash).expect("CA cert")).expect("CA cert");
+        let revoked_serial_numbers = |address: &str| {
+            let crl_der = storage
+                .get_artifact_by_addressing_hash(ArtifactNamespace::Crl, address)
+                .expect("couldn't fetch CRL");
+            assert!(crl::is_current(&crl_der));
+            let crl = Crl::from_der(&crl_der).expect("couldn't parse CRL");
+            crl.verify_issuer(&ca_cert).expect("couldn't verify CRL");
+            let mut serial_numbers = crl
+                .revoked_certificates()
+                .iter()
+                .map(|revoked| hex::encode(revoked.serial_number().as_unsigned_bytes_be()))
+                .collect::<Vec<String>>();
+            serial_numbers.sort();
+            serial_numbers
+        };
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::config::BackendType;
-    use picky::{
-        signature::SignatureHashType,
-        x509::{date::UTCDate, name::DirectoryName},
-    };
+        assert_eq!(revoked_serial_numbers(&addresses[0]), vec!["123456", "ffffffff"]);
+        assert_eq!(revoked_serial_numbers(&addresses[1]), vec!["123456"]);
+        assert_eq!(revoked_serial_numbers(&addresses[2]), vec!["ffffffff"]);
 
-    fn config() -> Config {
-        let mut config = Config::default();
-        config.backend = BackendType::Memory;
-        config
+        let latest_key = crl::latest_key(&ca_cert, Some(1)).expect("latest key");
+        assert_eq!(
+            storage
+                .get_latest_artifact_addressing_hash(ArtifactNamespace::Crl, &latest_key)
+                .expect("latest CRL"),
+            addresses[2]
+        );
     }
 
     #[test]
-    fn generate_chain_and_verify() {
+    fn ocsp_responder() {
         let config = config();
-        let storage = get_storage(&config);
+        let (storage, signer) = storage_and_signer(&config);
 
         let ca_name = format!("{} Authority", config.realm);
-
-        generate_root_ca(&config, storage.as_ref()).expect("couldn't generate root ca");
-        generate_intermediate_ca(&config, storage.as_ref()).expect("couldn't generate intermediate ca");
+        generate_root_ca(&config, storage.as_ref(), &signer).expect("couldn't generate root ca");
+        generate_intermediate_ca(&config, storage.as_ref(), &signer).expect("couldn't generate intermediate ca");
+        let ca_hash = storage.get_addressing_hash_by_name(&ca_name).expect("CA hash");
+        let ca_cert =
+            Cert::from_der(&storage.get_cert_by_addressing_hash(&ca_hash).expect("CA cert")).expect("CA cert");
+        let root_hash = storage
+            .get_addressing_hash_by_name(&format!("{} Root CA", config.realm))
+            .expect("root hash");
+        let root_cert =
+            Cert::from_der(&storage.get_cert_by_addressing_hash(&root_hash).expect("root cert")).expect("root cert");
 
         let pk = Picky::generate_private_key(2048).expect("couldn't generate private key");
         let csr = Csr::generate(
             DirectoryName::new_common_name("Mister Bushido"),
             &pk,
-            SignatureHashType::RsaSha384,
+            SignatureHashType::RsaSha256,
         )
         .expect("couldn't generate csr");
+        let leaf = sign_certificate(
+            &ca_name,
+            csr,
+            &AltNames::default(),
+            &config,
+            storage.as_ref(),
+            &signer,
+            IssuanceOrigin::default(),
+        )
+        .expect("couldn't sign certificate");
+        let cert_id = CertId::new(&leaf, &ca_cert).expect("cert id");
+
+        let cert_status = || {
+            let request = OcspRequest::new(vec![cert_id.clone()], Some(vec![1, 2, 3, 4])).expect("request");
+            let response = ocsp::respond(
+                &config,
+                storage.as_ref(),
+                &signer,
+                &request.to_der().expect("request der"),
+            );
+            let basic_response = response.basic_response().expect("basic response");
+            basic_response
+                .verify_issuer(&ca_cert, &UTCDate::now())
+                .expect("couldn't verify response");
+            assert_eq!(basic_response.nonce(), Some(vec![1, 2, 3, 4]));
+            basic_response
+                .find_response(&cert_id)
+                .expect("single response")
+                .cert_status()
+                .clone()
+        };
 
-        let signed_cert =
-            sign_certificate(&ca_name, csr, &config, storage.as_ref()).expect("couldn't sign certificate");
+        assert_eq!(cert_status(), CertStatus::Good);
 
-        let issuer_name = signed_cert.issuer_name().find_common_name().unwrap().to_string();
-        let chain_pem = find_ca_chain(storage.as_ref(), &issuer_name).expect("couldn't fetch CA chain");
+        // same serial number issued by another CA
+        let serial_number = hex::encode(leaf.serial_number().as_unsigned_bytes_be());
+        storage
+            .store_revocation(RevocationEntry {
+                issuer: "0123456789abcdef".to_owned(),
+                serial_number: serial_number.clone(),
+                revoked_at: 1_600_000_000,
+                reason: Some(1),
+            })
+            .expect("couldn't store revocation");
+        assert_eq!(cert_status(), CertStatus::Good);
 
-        assert_eq!(chain_pem.len(), 2);
+        storage
+            .store_revocation(RevocationEntry {
+                issuer: crl::ca_key_identifier(&ca_cert).expect("CA key identifier"),
+                serial_number,
+                revoked_at: 1_600_000_000,
+                reason: Some(1),
+            })
+            .expect("couldn't store revocation");
+        assert_eq!(
+            cert_status(),
+            CertStatus::Revoked {
+                revocation_time: UTCDate::new(2020, 9, 13, 12, 26, 40).unwrap(),
+                reason: Some(CrlReason::KeyCompromise),
+            }
+        );
 
-        let chain = chain_pem
+        // the intermediate CA isn't issued by itself
+        let request = OcspRequest::new(vec![CertId::new(&ca_cert, &root_cert).expect("cert id")], None)
+            .expect("request")
+            .to_der()
+            .expect("request der");
+        let response = ocsp::respond(&config, storage.as_ref(), &signer, &request);
+        assert_eq!(response.status().unwrap(), OcspResponseStatus::Unauthorized);
+
+        let response = ocsp::respond(&config, storage.as_ref(), &signer, b"garbage");
+        assert_eq!(response.status().unwrap(), OcspResponseStatus::MalformedRequest);
+    }
+
+    #[test]
+    fn batch_revocation_targets() {
+        let mut config = config();
+        config.save_certificate = true;
+        let (storage, signer) = storage_and_signer(&config);
+
+        let ca_name = format!("{} Authority", config.realm);
+        generate_root_ca(&config, storage.as_ref(), &signer).expect("couldn't generate root ca");
+        generate_intermediate_ca(&config, storage.as_ref(), &signer).expect("couldn't generate intermediate ca");
+
+        let pk = Picky::generate_private_key(2048).expect("couldn't generate private key");
+        let csr = Csr::generate(
+            DirectoryName::new_common_name("Mister Bushido"),
+            &pk,
+            SignatureHashType::RsaSha256,
+        )
+        .expect("couldn't generate csr");
+        let signed_cert = sign_certificate(
+            &ca_name,
+            csr,
+            &AltNames::default(),
+            &config,
+            storage.as_ref(),
+            &signer,
+            IssuanceOrigin {
+                requested_by: Some(token_requester("ci")),
+                labels: labels::parse_selector("team=payments,env=prod").expect("labels"),
+                base_url: None,
+                profile: None,
+            },
+        )
+        .expect("couldn't sign certificate");
+        let serial_number = hex::encode(signed_cert.serial_number().as_unsigned_bytes_be());
+
+        let request = BatchRevocationRequest {
+            serial_numbers: vec!["0A0B".to_owned(), "000a0b".to_owned(), "A0B".to_owned()],
+            requested_by: Some("token:ci".to_owned()),
+            ..BatchRevocationRequest::default()
+        };
+        let targets =
+            collect_revocation_targets(&config, storage.as_ref(), &request).expect("couldn't collect targets");
+        assert_eq!(targets.len(), 2);
+        let target = targets
             .iter()
-            .map(|cert_pem| {
-                let pem = cert_pem.parse::<Pem>().expect("couldn't parse cert pem");
-                Cert::from_der(pem.data()).expect("couldn't parse cert from der")
+            .find(|target| target.serial_number == serial_number)
+            .expect("issued certificate not selected");
+        assert_eq!(target.subject_name.as_deref(), Some("CN=Mister Bushido"));
+        assert!(targets
+            .iter()
+            .any(|target| target.serial_number == "0a0b" && target.address.is_none()));
+
+        let err = collect_revocation_targets(&config, storage.as_ref(), &BatchRevocationRequest::default())
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), ErrorCode::InvalidRequest);
+        assert_eq!(
+            err.to_string(),
+            "no serial number, key identifier, requester or labels provided"
+        );
+
+        let request = BatchRevocationRequest {
+            labels: labels::parse_selector("env=prod").expect("selector"),
+            ..BatchRevocationRequest::default()
+        };
+        let targets =
+            collect_revocation_targets(&config, storage.as_ref(), &request).expect("couldn't collect targets");
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].serial_number, serial_number);
+
+        let request = BatchRevocationRequest {
+            labels: labels::parse_selector("env=dev").expect("selector"),
+            ..BatchRevocationRequest::default()
+        };
+        assert!(collect_revocation_targets(&config, storage.as_ref(), &request)
+            .expect("couldn't collect targets")
+            .is_empty());
+
+        let addresses = inventory_addresses(
+            storage.as_ref(),
+            Some("token:ci"),
+            Some(&labels::parse_selector("team=payments").expect("selector")),
+        )
+        .expect("inventory");
+        assert_eq!(addresses.len(), 1);
+        assert_eq!(
+            storage.get_labels(&addresses[0]).expect("labels"),
+            labels::parse_selector("env=prod,team=payments").expect("labels")
+        );
+
+        let intermediate_ski = hex::encode(
+            find_ca_chain(storage.as_ref(), &ca_name)
+                .map(|chain| {
+                    let pem = chain[0].parse::<Pem>().expect("couldn't parse cert pem");
+                    Cert::from_der(pem.data())
+                        .expect("couldn't parse cert")
+                        .subject_key_identifier()
+                        .expect("ski")
+                        .to_vec()
+                })
+                .expect("couldn't fetch CA chain"),
+        );
+        let request = BatchRevocationRequest {
+            key_identifiers: vec![intermediate_ski],
+            ..BatchRevocationRequest::default()
+        };
+        assert!(collect_revocation_targets(&config, storage.as_ref(), &request).is_err());
+
+        let intermediate_serial_number = hex::encode(
+            fetch_ca_cert(&ca_name, storage.as_ref())
+                .expect("intermediate CA")
+                .serial_number()
+                .as_unsigned_bytes_be(),
+        );
+        let request = BatchRevocationRequest {
+            serial_numbers: vec![format!("00{}", intermediate_serial_number.to_uppercase())],
+            ..BatchRevocationRequest::default()
+        };
+        let err = collect_revocation_targets(&config, storage.as_ref(), &request)
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), ErrorCode::PolicyViolation);
+
+        for reason in &[7, 8, 11] {
+            let request = BatchRevocationRequest {
+                serial_numbers: vec!["0a0b".to_owned()],
+                reason: Some(*reason),
+                ..BatchRevocationRequest::default()
+            };
+            let err = collect_revocation_targets(&config, storage.as_ref(), &request)
+                .err()
+                .unwrap();
+            assert_eq!(err.code(), ErrorCode::InvalidRequest);
+        }
+    }
+
+    #[test]
+    fn signing_request_lookup() {
+        let config = config();
+        let (storage, _) = get_storage(&config).expect("storage");
+
+        let id = new_request_id();
+        storage
+            .store_signing_request(SigningRequestEntry {
+                id: id.clone(),
+                subject_name: "CN=Mister Bushido".to_owned(),
+                csr: vec![],
+                submitted_at: 0,
+                requested_by: None,
+                labels: Labels::new(),
+                alt_names: AltNames::default(),
+                profile: None,
+                issuer: None,
+                status: SigningRequestStatus::Pending,
             })
-            .collect::<Vec<Cert>>();
+            .expect("couldn't store signing request");
 
-        assert_eq!(chain[0].subject_name().to_string(), "CN=Picky Authority");
-        assert_eq!(chain[1].subject_name().to_string(), "CN=Picky Root CA");
+        let entry = find_signing_request(storage.as_ref(), &id).expect("couldn't find signing request");
+        assert_eq!(entry.status, SigningRequestStatus::Pending);
 
-        signed_cert
-            .verify_chain(chain.iter(), &UTCDate::now())
-            .expect("couldn't validate ca chain");
+        let err = find_signing_request(storage.as_ref(), "../config").err().unwrap();
+        assert_eq!(err.code(), ErrorCode::InvalidRequest);
+        assert_eq!(err.to_string(), "invalid signing request id: ../config");
+
+        let err = find_signing_request(storage.as_ref(), "0a0b").err().unwrap();
+        assert_eq!(err.code(), ErrorCode::NotFound);
     }
 
     fn new_saphir_request(headers: Vec<(&str, &str)>) -> SyncRequest {
@@ -848,15 +5627,60 @@ mod tests {
         assert_eq!(format, Format::Pkcs10Base64);
     }
 
+    #[test]
+    fn request_format_sniffing() {
+        let pk = Picky::generate_private_key(2048).expect("couldn't generate private key");
+        let cert_der = Picky::generate_root(
+            "Picky Root CA",
+            &pk,
+            SignatureHashType::RsaSha256,
+            IssuerOptions::root(),
+        )
+        .expect("couldn't generate certificate")
+        .to_der()
+        .expect("couldn't encode certificate");
+        let csr_der = Csr::generate(
+            DirectoryName::new_common_name("Mister Bushido"),
+            &pk,
+            SignatureHashType::RsaSha256,
+        )
+        .expect("couldn't generate csr")
+        .to_der()
+        .expect("couldn't encode csr");
+
+        let sniff = |body: Vec<u8>| {
+            let (parts, _) = saphir::Request::builder()
+                .header("Content-Type", "application/octet-stream")
+                .body(())
+                .unwrap()
+                .into_parts();
+            Format::request_format(&SyncRequest::new(parts, body))
+        };
+
+        assert_eq!(
+            sniff(to_pem("CERTIFICATE", &cert_der).into_bytes()),
+            Ok(Format::PemFile)
+        );
+        assert_eq!(sniff(b"\r\n{\"csr\": \"\"}".to_vec()), Ok(Format::Json));
+        assert_eq!(sniff(cert_der.clone()), Ok(Format::PkixCertBinary));
+        assert_eq!(sniff(csr_der.clone()), Ok(Format::Pkcs10Binary));
+        assert_eq!(
+            sniff(base64::encode(&cert_der).into_bytes()),
+            Ok(Format::PkixCertBase64)
+        );
+        assert_eq!(sniff(base64::encode(&csr_der).into_bytes()), Ok(Format::Pkcs10Base64));
+        assert!(sniff(b"garbage".to_vec()).is_err());
+    }
+
     #[test]
     fn request_format_err() {
         let err = Format::request_format(&new_saphir_request(vec![])).err().unwrap();
-        assert_eq!(err, "Content-Type header is missing");
+        assert_eq!(err, FormatError::Undetectable);
 
         let err = Format::request_format(&new_saphir_request(vec![("Content-Type", "application/pkcs10")]))
             .err()
             .unwrap();
-        assert_eq!(err, "format encoding for pkcs10 is missing");
+        assert_eq!(err, FormatError::MissingEncoding { format: "pkcs10" });
 
         let err = Format::request_format(&new_saphir_request(vec![
             ("Content-Type", "application/unknown"),
@@ -864,7 +5688,12 @@ mod tests {
         ]))
         .err()
         .unwrap();
-        assert_eq!(err, "unsupported format: application/unknown");
+        assert_eq!(
+            err,
+            FormatError::UnsupportedFormat {
+                format: "application/unknown".to_owned()
+            }
+        );
 
         let err = Format::request_format(&new_saphir_request(vec![
             ("Content-Type", "application/pkcs10"),
@@ -872,7 +5701,48 @@ mod tests {
         ]))
         .err()
         .unwrap();
-        assert_eq!(err, "unsupported encoding format for pkcs10: unknown");
+        assert_eq!(
+            err,
+            FormatError::UnsupportedEncoding {
+                format: "pkcs10",
+                encoding: "unknown".to_owned()
+            }
+        );
+        assert_eq!(err.to_string(), "unsupported encoding format for pkcs10: unknown");
+    }
+
+    #[test]
+    fn strict_request_format() {
+        let request = |content_type: &str, body: &[u8]| {
+            let (parts, _) = saphir::Request::builder()
+                .header("Content-Type", content_type)
+                .body(())
+                .unwrap()
+                .into_parts();
+            SyncRequest::new(parts, body.to_vec())
+        };
+
+        let json_as_pem = request("application/x-pem-file", b"{\"csr\": \"\"}");
+        assert_eq!(
+            Format::checked_request_format(&json_as_pem, false).unwrap(),
+            Format::PemFile
+        );
+        let err = Format::checked_request_format(&json_as_pem, true).err().unwrap();
+        assert_eq!(err.code(), ErrorCode::UnsupportedFormat);
+        assert_eq!(
+            err.to_string(),
+            "request body is json but Content-Type declares pem file"
+        );
+        let extensions = err.extensions();
+        assert_eq!(extensions["expected_format"], "pem file");
+        assert_eq!(extensions["detected_format"], "json");
+
+        let json = request("application/json", b"{\"csr\": \"\"}");
+        assert_eq!(Format::checked_request_format(&json, true).unwrap(), Format::Json);
+
+        // undetectable bodies are left to the parser
+        let garbage = request("application/x-pem-file", b"garbage");
+        assert_eq!(Format::checked_request_format(&garbage, true).unwrap(), Format::PemFile);
     }
 
     #[test]
@@ -903,11 +5773,15 @@ mod tests {
         ]))
         .unwrap();
         assert_eq!(format, Format::Pkcs10Base64);
+
+        let format =
+            Format::response_format(&new_saphir_request(vec![("Accept", "application/x-x509-ca-cert")])).unwrap();
+        assert_eq!(format, Format::X509CaCert);
     }
 
     #[test]
     fn response_format_err() {
         let err = Format::response_format(&new_saphir_request(vec![])).err().unwrap();
-        assert_eq!(err, "Accept header is missing");
+        assert_eq!(err, FormatError::MissingAccept);
     }
 }
@@ -0,0 +1,509 @@
+//! Errors of the request handlers, and of the CA setup they depend on.
+//!
+//! Each error kind maps to a single problem details error code, so that the HTTP status of a
+//! failure doesn't depend on the handler reporting it.
+
+use crate::{
+    audit::AuditError,
+    config::{ConfigError, ConfigLoadError, EcCurve},
+    crl::CrlGenerationError,
+    db::StorageError,
+    http::{problem::ErrorCode, response_signing::ResponseSigningError},
+    issuance_log::LogError,
+    key_usage::KeyUsageError,
+    picky_controller::PickyError,
+    random::RandomSourceError,
+    signer::SignerError,
+};
+use base64::DecodeError;
+use picky::{
+    key::KeyError,
+    pem::PemError,
+    signature::SignatureError,
+    x509::{certificate::CertError, csr::CsrError},
+};
+use serde_json::{Map, Value};
+use snafu::Snafu;
+use std::{io, path::PathBuf};
+
+#[derive(Debug, Snafu)]
+pub enum ServerError {
+    /// request body, path or query couldn't be understood
+    #[snafu(display("{}", description))]
+    InvalidRequest { description: String },
+
+    /// request body doesn't match its declared Content-Type
+    #[snafu(display("request body is {} but Content-Type declares {}", detected, expected))]
+    FormatMismatch { expected: String, detected: String },
+
+    /// request isn't authorized
+    #[snafu(display("{}", description))]
+    Unauthorized { description: String },
+
+    /// request is rejected by the CA policy
+    #[snafu(display("{}", description))]
+    PolicyViolation { description: String },
+
+    /// requested resource doesn't exist
+    #[snafu(display("{}", description))]
+    NotFound { description: String },
+
+    /// storage backend failed
+    #[snafu(display("{}: {}", context, source))]
+    Storage { context: String, source: StorageError },
+
+    /// certificate couldn't be issued
+    #[snafu(display("{}: {}", context, source))]
+    Issuance { context: String, source: PickyError },
+
+    /// stored CA hierarchy is broken
+    #[snafu(display("{}", description))]
+    CaUnavailable { description: String },
+
+    /// server failed unexpectedly
+    #[snafu(display("{}", description))]
+    Internal { description: String },
+}
+
+impl ServerError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ServerError::InvalidRequest { .. } => ErrorCode::InvalidRequest,
+            ServerError::FormatMismatch { .. } => ErrorCode::UnsupportedFormat,
+            ServerError::Unauthorized { .. } => ErrorCode::Unauthorized,
+            ServerError::PolicyViolation { .. } => ErrorCode::PolicyViolation,
+            ServerError::NotFound { .. } => ErrorCode::NotFound,
+            ServerError::Storage { source, .. } if source.is_not_found() => ErrorCode::NotFound,
+            ServerError::Storage { .. } => ErrorCode::StorageUnavailable,
+            ServerError::Issuance {
+                source: PickyError::InvalidCsr { .. },
+                ..
+            } => ErrorCode::InvalidRequest,
+            ServerError::Issuance { .. } => ErrorCode::IssuanceFailed,
+            ServerError::CaUnavailable { .. } => ErrorCode::CaUnavailable,
+            ServerError::Internal { .. } => ErrorCode::InternalError,
+        }
+    }
+
+    /// Problem details extension members giving more context about the error.
+    pub fn extensions(&self) -> Map<String, Value> {
+        let mut extensions = Map::new();
+        if let ServerError::FormatMismatch { expected, detected } = self {
+            extensions.insert("expected_format".to_owned(), Value::from(expected.as_str()));
+            extensions.insert("detected_format".to_owned(), Value::from(detected.as_str()));
+        }
+        extensions
+    }
+
+    /// Wraps a storage error with a description of the failed operation.
+    pub fn storage<C: Into<String>>(context: C) -> impl FnOnce(StorageError) -> Self {
+        let context = context.into();
+        move |source| ServerError::Storage { context, source }
+    }
+}
+
+impl From<StorageError> for ServerError {
+    fn from(source: StorageError) -> Self {
+        ServerError::Storage {
+            context: "storage error".to_owned(),
+            source,
+        }
+    }
+}
+
+impl From<SignerError> for ServerError {
+    fn from(e: SignerError) -> Self {
+        match e {
+            SignerError::KeyUnavailable { source } => ServerError::Storage {
+                context: "couldn't fetch CA private key".to_owned(),
+                source,
+            },
+            SignerError::InvalidKey { source } => ServerError::Issuance {
+                context: "couldn't parse CA private key".to_owned(),
+                source,
+            },
+            SignerError::CertificateSigning { source } => ServerError::Issuance {
+                context: "couldn't sign certificate".to_owned(),
+                source,
+            },
+            e => ServerError::Internal {
+                description: e.to_string(),
+            },
+        }
+    }
+}
+
+impl From<CrlGenerationError> for ServerError {
+    fn from(e: CrlGenerationError) -> Self {
+        match e {
+            CrlGenerationError::UnknownIssuer { source } => ServerError::NotFound {
+                description: source.to_string(),
+            },
+            CrlGenerationError::Storage { context, source } => ServerError::Storage { context, source },
+            e => ServerError::Internal {
+                description: e.to_string(),
+            },
+        }
+    }
+}
+
+impl From<LogError> for ServerError {
+    fn from(e: LogError) -> Self {
+        match e {
+            LogError::Storage { source } => ServerError::Storage {
+                context: "couldn't fetch issuance log".to_owned(),
+                source,
+            },
+            LogError::Signing { source } => source.into(),
+            e => ServerError::Internal {
+                description: e.to_string(),
+            },
+        }
+    }
+}
+
+impl From<AuditError> for ServerError {
+    fn from(e: AuditError) -> Self {
+        match e {
+            AuditError::HeadUnavailable { source } => ServerError::Storage {
+                context: "couldn't fetch audit log head".to_owned(),
+                source,
+            },
+            AuditError::RecordStorage { sequence, source } => ServerError::Storage {
+                context: format!("couldn't store audit record {}", sequence),
+                source,
+            },
+            e => ServerError::Internal {
+                description: e.to_string(),
+            },
+        }
+    }
+}
+
+impl From<ResponseSigningError> for ServerError {
+    fn from(e: ResponseSigningError) -> Self {
+        ServerError::Internal {
+            description: e.to_string(),
+        }
+    }
+}
+
+impl From<KeyUsageError> for ServerError {
+    fn from(e: KeyUsageError) -> Self {
+        match e {
+            KeyUsageError::UsageUnavailable { key_identifier, source } => ServerError::Storage {
+                context: format!("couldn't fetch usage of key {}", key_identifier),
+                source,
+            },
+            KeyUsageError::UsageStorage { key_identifier, source } => ServerError::Storage {
+                context: format!("couldn't store usage of key {}", key_identifier),
+                source,
+            },
+            e @ KeyUsageError::LimitsExceeded { .. } => ServerError::PolicyViolation {
+                description: e.to_string(),
+            },
+            e => ServerError::Internal {
+                description: e.to_string(),
+            },
+        }
+    }
+}
+
+/// CA hierarchy couldn't be set up from the configuration, on startup or reload
+#[derive(Debug, Snafu)]
+pub enum CaSetupError {
+    /// file provided by the configuration couldn't be read
+    #[snafu(display("couldn't read {} '{}': {}", what, path.display(), source))]
+    ReadFile {
+        what: &'static str,
+        path: PathBuf,
+        source: io::Error,
+    },
+
+    /// file provided by the configuration isn't PEM
+    #[snafu(display("couldn't parse {} pem: {}", what, source))]
+    InvalidPem { what: &'static str, source: PemError },
+
+    /// certificate couldn't be decoded or lacks a required extension
+    #[snafu(display("invalid certificate: {}", source))]
+    InvalidCert { source: CertError },
+
+    /// private key couldn't be decoded
+    #[snafu(display("invalid private key: {}", source))]
+    InvalidKey { source: PickyError },
+
+    /// private key couldn't be encoded for storage
+    #[snafu(display("couldn't convert key to pkcs8: {}", source))]
+    KeyEncoding { source: KeyError },
+
+    /// private key couldn't be sealed for storage
+    #[snafu(display("couldn't seal private key: {}", source))]
+    KeySealing { source: SignerError },
+
+    /// certificate subject has no common name
+    #[snafu(display("couldn't find subject common name"))]
+    MissingCommonName,
+
+    /// certificate isn't the one of the CA it is provided for
+    #[snafu(display("unexpected subject name: {} ; expected: {}", actual, expected))]
+    UnexpectedSubjectName { expected: String, actual: String },
+
+    /// certificate signature algorithm isn't supported
+    #[snafu(display("unsupported certificate signature algorithm: {}", source))]
+    UnsupportedSignatureAlgorithm { source: SignatureError },
+
+    /// private key isn't the one certified by the certificate
+    #[snafu(display("private key doesn't match the certificate public key"))]
+    KeyMismatch,
+
+    /// root CA certificate isn't a currently valid self-signed CA
+    #[snafu(display("certificate isn't a valid self-signed CA: {}", source))]
+    NotSelfSigned { source: CertError },
+
+    /// certificate isn't a currently valid CA issued by `issuer`
+    #[snafu(display("certificate doesn't verify under {}: {}", issuer, source))]
+    NotIssuedBy { issuer: String, source: CertError },
+
+    /// storage backend failed
+    #[snafu(display("{}: {}", context, source))]
+    CaStorage { context: String, source: StorageError },
+
+    /// configuration refers to an unknown issuer
+    #[snafu(display("{}", source))]
+    InvalidConfig { source: ConfigError },
+
+    /// CA private key couldn't be generated
+    #[snafu(display("couldn't generate private key: {}", source))]
+    KeyGeneration { source: PickyError },
+
+    /// CA private keys of this type can't be generated
+    #[snafu(display("{:?} keys can't be generated", curve))]
+    UnsupportedKeyGeneration { curve: EcCurve },
+
+    /// self-signed root CA certificate couldn't be generated
+    #[snafu(display("couldn't generate root certificate: {}", source))]
+    RootGeneration { source: PickyError },
+
+    /// intermediate CA certificate couldn't be signed by the root CA
+    #[snafu(display("couldn't generate intermediate certificate: {}", source))]
+    IntermediateSigning { source: SignerError },
+
+    /// root CA key can't sign anymore
+    #[snafu(display("{}", source))]
+    KeyUsage { source: KeyUsageError },
+
+    /// CA couldn't be generated
+    #[snafu(display("couldn't generate {}: {}", ca, source))]
+    CaGeneration {
+        ca: String,
+        #[snafu(source(from(CaSetupError, Box::new)))]
+        source: Box<CaSetupError>,
+    },
+
+    /// CA certificate or key provided by the configuration is invalid
+    #[snafu(display("couldn't inject {}: {}", ca, source))]
+    InvalidProvidedCa {
+        ca: String,
+        #[snafu(source(from(CaSetupError, Box::new)))]
+        source: Box<CaSetupError>,
+    },
+
+    /// CA has to be in storage already because the root CA is offline
+    #[snafu(display("{} must be provided when root is offline: {}", ca, source))]
+    MissingOfflineCa {
+        ca: String,
+        #[snafu(source(from(CaSetupError, Box::new)))]
+        source: Box<CaSetupError>,
+    },
+}
+
+impl CaSetupError {
+    /// Wraps a storage error with a description of the failed operation.
+    pub fn storage<C: Into<String>>(context: C) -> impl FnOnce(StorageError) -> Self {
+        let context = context.into();
+        move |source| CaSetupError::CaStorage { context, source }
+    }
+
+    /// Wraps an error about the certificate or key of `ca` provided by the configuration.
+    pub fn invalid_provided_ca(ca: &str) -> impl FnOnce(CaSetupError) -> Self {
+        let ca = ca.to_owned();
+        move |source| CaSetupError::InvalidProvidedCa {
+            ca,
+            source: Box::new(source),
+        }
+    }
+
+    /// Wraps the error of fetching `ca`, expected in storage while the root CA is offline.
+    pub fn missing_offline_ca(ca: &str) -> impl FnOnce(CaSetupError) -> Self {
+        let ca = ca.to_owned();
+        move |source| CaSetupError::MissingOfflineCa {
+            ca,
+            source: Box::new(source),
+        }
+    }
+
+    /// Wraps the error of generating `ca`.
+    pub fn generation(ca: &str) -> impl FnOnce(CaSetupError) -> Self {
+        let ca = ca.to_owned();
+        move |source| CaSetupError::CaGeneration {
+            ca,
+            source: Box::new(source),
+        }
+    }
+
+    /// Error without the CA it is about, to match on the actual cause.
+    pub fn root_cause(&self) -> &CaSetupError {
+        match self {
+            CaSetupError::InvalidProvidedCa { source, .. }
+            | CaSetupError::MissingOfflineCa { source, .. }
+            | CaSetupError::CaGeneration { source, .. } => source.root_cause(),
+            _ => self,
+        }
+    }
+}
+
+/// Server couldn't start, or the configuration couldn't be reloaded
+#[derive(Debug, Snafu)]
+pub enum StartupError {
+    /// configuration couldn't be loaded
+    #[snafu(display("{}", source))]
+    ConfigLoading { source: ConfigLoadError },
+
+    /// configuration is invalid
+    #[snafu(display("{}", source))]
+    InvalidConfig { source: ConfigError },
+
+    /// configured random source couldn't be opened
+    #[snafu(display("{}", source))]
+    RandomSource { source: RandomSourceError },
+
+    /// power-on self-test reported failures
+    #[snafu(display("power-on self-test failed: {}", failures.join(", ")))]
+    SelfTestFailed { failures: Vec<String> },
+
+    /// response signing key couldn't be loaded
+    #[snafu(display("{}", source))]
+    ResponseSigning { source: ResponseSigningError },
+
+    /// storage backend couldn't be opened
+    #[snafu(display("couldn't open storage: {}", source))]
+    StorageUnavailable { source: StorageError },
+
+    /// CA hierarchy couldn't be set up
+    #[snafu(display("{}", source))]
+    CaSetup { source: CaSetupError },
+}
+
+impl From<ConfigLoadError> for StartupError {
+    fn from(source: ConfigLoadError) -> Self {
+        StartupError::ConfigLoading { source }
+    }
+}
+
+impl From<ConfigError> for StartupError {
+    fn from(source: ConfigError) -> Self {
+        StartupError::InvalidConfig { source }
+    }
+}
+
+impl From<RandomSourceError> for StartupError {
+    fn from(source: RandomSourceError) -> Self {
+        StartupError::RandomSource { source }
+    }
+}
+
+impl From<ResponseSigningError> for StartupError {
+    fn from(source: ResponseSigningError) -> Self {
+        StartupError::ResponseSigning { source }
+    }
+}
+
+impl From<CaSetupError> for StartupError {
+    fn from(source: CaSetupError) -> Self {
+        StartupError::CaSetup { source }
+    }
+}
+
+// request parsing errors
+
+impl From<PemError> for ServerError {
+    fn from(e: PemError) -> Self {
+        ServerError::InvalidRequest {
+            description: format!("pem: {}", e),
+        }
+    }
+}
+
+impl From<CsrError> for ServerError {
+    fn from(e: CsrError) -> Self {
+        ServerError::InvalidRequest {
+            description: format!("csr: {}", e),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ServerError {
+    fn from(e: serde_json::Error) -> Self {
+        ServerError::InvalidRequest {
+            description: format!("json: {}", e),
+        }
+    }
+}
+
+impl From<DecodeError> for ServerError {
+    fn from(e: DecodeError) -> Self {
+        ServerError::InvalidRequest {
+            description: format!("base64 decode: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{BackendType, Config},
+        db::get_storage,
+    };
+
+    #[test]
+    fn storage_error_codes() {
+        let mut config = Config::default();
+        config.backend = BackendType::Memory;
+        let storage = get_storage(&config).expect("storage").0;
+
+        let err = storage
+            .get_cert_by_addressing_hash("missing")
+            .map_err(ServerError::storage("couldn't fetch certificate"))
+            .err()
+            .expect("missing certificate");
+        assert_eq!(err.code(), ErrorCode::NotFound);
+        assert_eq!(
+            err.to_string(),
+            "couldn't fetch certificate: memory storage error: cert not found"
+        );
+
+        let err = storage
+            .get_signing_request("0a0b")
+            .map_err(ServerError::from)
+            .err()
+            .expect("missing signing request");
+        assert_eq!(err.code(), ErrorCode::NotFound);
+    }
+
+    #[test]
+    fn request_error_codes() {
+        let err = ServerError::from("not a pem".parse::<picky::pem::Pem>().err().expect("invalid pem"));
+        assert_eq!(err.code(), ErrorCode::InvalidRequest);
+        assert!(err.to_string().starts_with("pem: "));
+
+        assert_eq!(
+            ServerError::Unauthorized {
+                description: "administrator API key is required".to_owned(),
+            }
+            .code()
+            .status(),
+            saphir::StatusCode::UNAUTHORIZED
+        );
+    }
+}
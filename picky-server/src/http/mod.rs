@@ -1,4 +1,10 @@
 pub mod authorization;
+pub mod caching;
 pub mod controller;
+pub mod error;
 pub mod http_server;
+pub mod problem;
+pub mod request_log;
+pub mod response_signing;
 pub mod utils;
+pub mod versioning;
@@ -0,0 +1,202 @@
+use crate::http::utils::SyncRequestUtil;
+use saphir::{header, StatusCode, SyncRequest, SyncResponse};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fmt;
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+pub const PROBLEM_JSON_CONTENT_TYPE: &str = "application/problem+json";
+
+pub fn new_request_id() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+/// Machine-readable error codes reported in problem details responses
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorCode {
+    /// request body or headers couldn't be understood
+    InvalidRequest,
+    /// requested format is not supported by this endpoint
+    UnsupportedFormat,
+    /// authorization header is missing or invalid
+    Unauthorized,
+    /// CSR subject doesn't match the subject allowed by the provided token
+    CsrSubjectMismatch,
+    /// request is well-formed but is rejected by the CA policy
+    PolicyViolation,
+    /// requested resource couldn't be found
+    NotFound,
+    /// storage backend failed or is unavailable
+    StorageUnavailable,
+    /// certificate couldn't be issued
+    IssuanceFailed,
+    /// stored CA hierarchy is broken, issuance is disabled
+    CaUnavailable,
+    /// configuration couldn't be reloaded
+    ConfigReloadFailed,
+    /// signing request was denied by an administrator
+    RequestDenied,
+    /// server is misconfigured or failed unexpectedly
+    InternalError,
+}
+
+impl ErrorCode {
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::InvalidRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::UnsupportedFormat => StatusCode::BAD_REQUEST,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::CsrSubjectMismatch => StatusCode::UNAUTHORIZED,
+            ErrorCode::PolicyViolation => StatusCode::BAD_REQUEST,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::StorageUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::IssuanceFailed => StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorCode::CaUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::ConfigReloadFailed => StatusCode::BAD_REQUEST,
+            ErrorCode::RequestDenied => StatusCode::FORBIDDEN,
+            ErrorCode::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            ErrorCode::InvalidRequest => "Invalid request",
+            ErrorCode::UnsupportedFormat => "Unsupported format",
+            ErrorCode::Unauthorized => "Unauthorized",
+            ErrorCode::CsrSubjectMismatch => "CSR subject mismatch",
+            ErrorCode::PolicyViolation => "Policy violation",
+            ErrorCode::NotFound => "Not found",
+            ErrorCode::StorageUnavailable => "Storage unavailable",
+            ErrorCode::IssuanceFailed => "Issuance failed",
+            ErrorCode::CaUnavailable => "CA unavailable",
+            ErrorCode::ConfigReloadFailed => "Config reload failed",
+            ErrorCode::RequestDenied => "Request denied",
+            ErrorCode::InternalError => "Internal error",
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorCode::InvalidRequest => "invalid-request",
+            ErrorCode::UnsupportedFormat => "unsupported-format",
+            ErrorCode::Unauthorized => "unauthorized",
+            ErrorCode::CsrSubjectMismatch => "csr-subject-mismatch",
+            ErrorCode::PolicyViolation => "policy-violation",
+            ErrorCode::NotFound => "not-found",
+            ErrorCode::StorageUnavailable => "storage-unavailable",
+            ErrorCode::IssuanceFailed => "issuance-failed",
+            ErrorCode::CaUnavailable => "ca-unavailable",
+            ErrorCode::ConfigReloadFailed => "config-reload-failed",
+            ErrorCode::RequestDenied => "request-denied",
+            ErrorCode::InternalError => "internal-error",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Problem details body as defined by [RFC 7807](https://tools.ietf.org/html/rfc7807)
+#[derive(Serialize, Clone, Debug)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub type_uri: String,
+    pub title: &'static str,
+    pub status: u16,
+    pub detail: String,
+    pub code: ErrorCode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// Additional members specific to the error code
+    #[serde(flatten)]
+    pub extensions: Map<String, Value>,
+}
+
+impl Problem {
+    pub fn new(code: ErrorCode, detail: String, request_id: Option<String>) -> Self {
+        Self {
+            type_uri: format!("urn:picky:problem:{}", code),
+            title: code.title(),
+            status: code.status().as_u16(),
+            detail,
+            code,
+            request_id,
+            extensions: Map::new(),
+        }
+    }
+}
+
+/// Fills the response with a problem details body describing the error.
+pub fn write_problem(req: &SyncRequest, res: &mut SyncResponse, code: ErrorCode, detail: String) {
+    write_problem_with_extensions(req, res, code, detail, Map::new());
+}
+
+/// Same as `write_problem`, with additional members in the problem details body.
+pub fn write_problem_with_extensions(
+    req: &SyncRequest,
+    res: &mut SyncResponse,
+    code: ErrorCode,
+    detail: String,
+    extensions: Map<String, Value>,
+) {
+    let mut problem = Problem::new(code, detail, req.get_header_string_value(REQUEST_ID_HEADER));
+    problem.extensions = extensions;
+    match serde_json::to_string(&problem) {
+        Ok(body) => {
+            res.header(header::CONTENT_TYPE, PROBLEM_JSON_CONTENT_TYPE);
+            res.body(body);
+        }
+        Err(e) => log::error!("couldn't serialize problem details: {}", e),
+    }
+    res.status(code.status());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn problem_serialization() {
+        let problem = Problem::new(
+            ErrorCode::CsrSubjectMismatch,
+            "unexpected subject".to_owned(),
+            Some("abcd".to_owned()),
+        );
+        let json = serde_json::to_value(&problem).expect("problem to json");
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "type": "urn:picky:problem:csr-subject-mismatch",
+                "title": "CSR subject mismatch",
+                "status": 401,
+                "detail": "unexpected subject",
+                "code": "csr-subject-mismatch",
+                "request_id": "abcd",
+            })
+        );
+    }
+
+    #[test]
+    fn problem_without_request_id() {
+        let problem = Problem::new(ErrorCode::NotFound, "cert not found".to_owned(), None);
+        let json = serde_json::to_value(&problem).expect("problem to json");
+        assert!(json.get("request_id").is_none());
+        assert_eq!(json["status"], 404);
+    }
+
+    #[test]
+    fn problem_extensions_are_flattened() {
+        let mut problem = Problem::new(ErrorCode::UnsupportedFormat, "format mismatch".to_owned(), None);
+        problem
+            .extensions
+            .insert("detected_format".to_owned(), Value::from("json"));
+        let json = serde_json::to_value(&problem).expect("problem to json");
+        assert_eq!(json["detected_format"], "json");
+        assert_eq!(json["code"], "unsupported-format");
+    }
+}
@@ -0,0 +1,219 @@
+//! Structured request events for traffic analysis, distinct from application logs.
+//!
+//! When `request_log` is configured, a sample of the handled requests is logged as one JSON
+//! object per line under the `request_log` target, which the logger writes to its own appender.
+//! Errors and successes are sampled at their own rates so that failures can be kept exhaustively
+//! while regular traffic is only sampled.
+
+use crate::{
+    config::{Config, ConfigError},
+    http::{
+        authorization::{check_authorization, token_requester, Authorized, API_KEY_REQUESTER},
+        problem::REQUEST_ID_HEADER,
+        utils::SyncRequestUtil,
+    },
+    timings,
+};
+use saphir::{
+    header,
+    hyper::rt::{Future, Stream},
+    StatusCode, SyncRequest, SyncResponse,
+};
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, time::Instant};
+
+/// Target of the request events, routed to the request log appender
+pub const REQUEST_LOG_TARGET: &str = "picky_server::request_log";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RequestLogConfig {
+    /// Share of successful requests (below 400) which are logged, from 0 to 1
+    #[serde(default = "default_sample_rate")]
+    pub success_sample_rate: f64,
+    /// Share of failed requests (400 and above) which are logged, from 0 to 1
+    #[serde(default = "default_sample_rate")]
+    pub error_sample_rate: f64,
+    /// File the events are appended to, they're written on the standard output otherwise
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+}
+
+fn default_sample_rate() -> f64 {
+    1.0
+}
+
+impl RequestLogConfig {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for (field, rate) in &[
+            ("success_sample_rate", self.success_sample_rate),
+            ("error_sample_rate", self.error_sample_rate),
+        ] {
+            if !(0.0..=1.0).contains(rate) {
+                return Err(ConfigError::InvalidRate { field });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn sample_rate(&self, status: StatusCode) -> f64 {
+        if status.is_client_error() || status.is_server_error() {
+            self.error_sample_rate
+        } else {
+            self.success_sample_rate
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RequestEvent {
+    pub request_id: Option<String>,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    pub latency_ms: f64,
+    /// `api-key` or `token:<subject>` for authorized requests
+    pub principal: Option<String>,
+    pub request_body_size: usize,
+    pub response_body_size: usize,
+}
+
+/// Request being handled, to be logged once the response is written
+pub struct RequestLogEntry {
+    started: Instant,
+    /// Drawn before handling, the request is logged if it's below the sample rate of its status
+    sample: f64,
+}
+
+impl RequestLogEntry {
+    pub fn start() -> Self {
+        Self {
+            started: Instant::now(),
+            sample: rand::random::<f64>(),
+        }
+    }
+
+    /// Logs the request if it's sampled.
+    pub fn finish(self, config: &Config, req: &SyncRequest, res: &mut SyncResponse) {
+        let log_config = match &config.request_log {
+            Some(log_config) => log_config,
+            None => return,
+        };
+
+        // the response is only inspected if the request may be sampled, it has to be rebuilt
+        if self.sample >= log_config.success_sample_rate.max(log_config.error_sample_rate) {
+            return;
+        }
+
+        let latency = self.started.elapsed();
+        let (status, response_body_size) = inspect_response(res);
+        if self.sample >= log_config.sample_rate(status) {
+            return;
+        }
+
+        let event = RequestEvent {
+            request_id: req.get_header_string_value(REQUEST_ID_HEADER),
+            method: req.method().to_string(),
+            path: req.uri().path().to_owned(),
+            status: status.as_u16(),
+            latency_ms: timings::millis(latency),
+            principal: principal(config, req),
+            request_body_size: req.body().len(),
+            response_body_size,
+        };
+
+        match serde_json::to_string(&event) {
+            Ok(event) => log::info!(target: REQUEST_LOG_TARGET, "{}", event),
+            Err(e) => log::warn!("couldn't serialize request event: {}", e),
+        }
+    }
+}
+
+/// Identity the request is authorized as, if any.
+fn principal(config: &Config, req: &SyncRequest) -> Option<String> {
+    if !req.headers_map().contains_key(header::AUTHORIZATION) {
+        return None;
+    }
+
+    match check_authorization(config, req).ok()? {
+        Authorized::ApiKey => Some(API_KEY_REQUESTER.to_owned()),
+        Authorized::Token(jwt) => Some(token_requester(&jwt.view_claims().sub)),
+    }
+}
+
+/// Returns the status and body size of the response.
+///
+/// Saphir responses are write-only: the response is built to be inspected and written again.
+fn inspect_response(res: &mut SyncResponse) -> (StatusCode, usize) {
+    let built = std::mem::replace(res, SyncResponse::new()).build_response();
+    let (parts, body) = match built {
+        Ok(response) => response.into_parts(),
+        Err(_) => {
+            // Saphir answers an empty 500 as well
+            res.status(StatusCode::INTERNAL_SERVER_ERROR);
+            return (StatusCode::INTERNAL_SERVER_ERROR, 0);
+        }
+    };
+
+    // responses are built from in-memory bodies, which are available right away
+    let body = body.concat2().wait().map(|chunk| chunk.to_vec()).unwrap_or_default();
+    let body_size = body.len();
+
+    res.status(parts.status).version(parts.version);
+    for (name, value) in &parts.headers {
+        res.header(name, value);
+    }
+    res.body(body);
+
+    (parts.status, body_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log_config(success_sample_rate: f64, error_sample_rate: f64) -> RequestLogConfig {
+        RequestLogConfig {
+            success_sample_rate,
+            error_sample_rate,
+            path: None,
+        }
+    }
+
+    #[test]
+    fn sample_rates() {
+        let log_config = log_config(0.1, 1.0);
+        log_config.validate().expect("valid config");
+        assert_eq!(log_config.sample_rate(StatusCode::OK), 0.1);
+        assert_eq!(log_config.sample_rate(StatusCode::NOT_MODIFIED), 0.1);
+        assert_eq!(log_config.sample_rate(StatusCode::FORBIDDEN), 1.0);
+        assert_eq!(log_config.sample_rate(StatusCode::SERVICE_UNAVAILABLE), 1.0);
+
+        assert!(matches!(
+            log_config(1.5, 1.0).validate(),
+            Err(ConfigError::InvalidRate {
+                field: "success_sample_rate"
+            })
+        ));
+        assert!(log_config(0.5, -0.1).validate().is_err());
+    }
+
+    #[test]
+    fn response_inspection() {
+        let mut res = SyncResponse::new();
+        res.status(StatusCode::CREATED)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(br#"{"status":"ok"}"#.to_vec());
+
+        let (status, body_size) = inspect_response(&mut res);
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body_size, 15);
+
+        // the response is written again as it was
+        let response = res.build_response().expect("response");
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        let body = response.into_body().concat2().wait().expect("body");
+        assert_eq!(body.as_ref(), br#"{"status":"ok"}"#);
+    }
+}
@@ -0,0 +1,118 @@
+use crate::{config::Config, utils::PathOr};
+use picky::{
+    jose::{
+        jwk::{Jwk, JwkError, JwkKeyOps, JwkPubKeyUse},
+        jws::{Jws, JwsError},
+    },
+    key::{KeyError, PrivateKey},
+    pem::{Pem, PemError},
+    signature::SignatureHashType,
+};
+use snafu::Snafu;
+use std::io;
+
+/// Detached JWS (RFC7515 appendix F) over the response body
+pub const JWS_SIGNATURE_HEADER: &str = "X-JWS-Signature";
+
+#[derive(Debug, Snafu)]
+pub enum ResponseSigningError {
+    /// response signing key file couldn't be read
+    #[snafu(display("couldn't read response signing key: {}", source))]
+    ReadKey { source: io::Error },
+
+    /// response signing key file isn't PEM
+    #[snafu(display("couldn't parse response signing key pem: {}", source))]
+    InvalidKeyPem { source: PemError },
+
+    /// response signing key couldn't be decoded
+    #[snafu(display("couldn't parse response signing key: {}", source))]
+    InvalidKey { source: KeyError },
+
+    /// public key can't be published as a JWK
+    #[snafu(display("couldn't convert response signing key to JWK: {}", source))]
+    Jwk { source: JwkError },
+
+    /// JWK thumbprint used as key id couldn't be computed
+    #[snafu(display("couldn't compute response signing key thumbprint: {}", source))]
+    Thumbprint { source: JwkError },
+
+    /// response body couldn't be signed
+    #[snafu(display("couldn't sign response: {}", source))]
+    Signing { source: JwsError },
+}
+
+/// Signs JSON response bodies so that clients can archive tamper-evident proofs of what the CA returned.
+///
+/// The public key is published on the JWKS endpoint under its JWK thumbprint.
+pub struct ResponseSigner {
+    key: PrivateKey,
+    algorithm: SignatureHashType,
+    jwk: Jwk,
+}
+
+impl ResponseSigner {
+    pub fn new(key: PrivateKey, algorithm: SignatureHashType) -> Result<Self, ResponseSigningError> {
+        let jwk = Jwk::from_public_key(&key.to_public_key()).map_err(|source| ResponseSigningError::Jwk { source })?;
+        let key_id = jwk
+            .thumbprint()
+            .map_err(|source| ResponseSigningError::Thumbprint { source })?;
+        let jwk = jwk
+            .with_algorithm(algorithm)
+            .with_pub_key_use(JwkPubKeyUse::Signature)
+            .with_key_operations(vec![JwkKeyOps::Verify])
+            .with_key_id(key_id);
+
+        Ok(Self { key, algorithm, jwk })
+    }
+
+    /// Returns `None` if response signing isn't enabled.
+    pub fn from_config(config: &Config) -> Result<Option<Self>, ResponseSigningError> {
+        let key = match &config.response_signing_key {
+            Some(PathOr::Path(path)) => {
+                let pem_str =
+                    std::fs::read_to_string(path).map_err(|source| ResponseSigningError::ReadKey { source })?;
+                let pem = pem_str
+                    .parse::<Pem>()
+                    .map_err(|source| ResponseSigningError::InvalidKeyPem { source })?;
+                PrivateKey::from_pem(&pem).map_err(|source| ResponseSigningError::InvalidKey { source })?
+            }
+            Some(PathOr::Some(key)) => key.clone(),
+            None => return Ok(None),
+        };
+
+        Self::new(key, config.signing_algorithm).map(Some)
+    }
+
+    pub fn jwk(&self) -> &Jwk {
+        &self.jwk
+    }
+
+    pub fn sign(&self, payload: &[u8]) -> Result<String, ResponseSigningError> {
+        let mut jws = Jws::new(self.algorithm, payload.to_vec());
+        jws.header.kid = self.jwk.key_id.clone();
+        jws.encode_detached(&self.key)
+            .map_err(|source| ResponseSigningError::Signing { source })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESPONSE_SIGNING_KEY: &str = include_str!("../../../test_assets/private_keys/rsa-2048-pk_1.key");
+
+    #[test]
+    fn detached_signature_verifies() {
+        let pem = RESPONSE_SIGNING_KEY.parse::<Pem>().unwrap();
+        let key = PrivateKey::from_pem(&pem).unwrap();
+        let signer = ResponseSigner::new(key.clone(), SignatureHashType::RsaSha256).unwrap();
+
+        let body = br#"{"status":"ok"}"#;
+        let signature = signer.sign(body).unwrap();
+        let jws = Jws::decode_detached(&signature, body, &key.to_public_key()).unwrap();
+        assert_eq!(jws.header.kid, signer.jwk().key_id);
+        assert!(jws.header.kid.is_some());
+
+        assert!(Jws::decode_detached(&signature, br#"{"status":"ko"}"#, &key.to_public_key()).is_err());
+    }
+}
@@ -1,7 +1,10 @@
-use saphir::SyncRequest;
+use crate::config::Config;
+use saphir::{header, SyncRequest};
+use snafu::Snafu;
 
 pub trait SyncRequestUtil {
     fn get_header_string_value(&self, header_name: &str) -> Option<String>;
+    fn get_query_param(&self, param_name: &str) -> Option<String>;
 }
 
 impl SyncRequestUtil for SyncRequest {
@@ -15,4 +18,150 @@ impl SyncRequestUtil for SyncRequest {
         }
         None
     }
+
+    fn get_query_param(&self, param_name: &str) -> Option<String> {
+        self.uri().query()?.split('&').find_map(|pair| {
+            let mut split = pair.splitn(2, '=');
+            match (split.next(), split.next()) {
+                (Some(name), Some(value)) if name == param_name && !value.is_empty() => Some(value.to_owned()),
+                _ => None,
+            }
+        })
+    }
+}
+
+#[derive(Debug, Snafu)]
+pub enum PercentDecodingError {
+    /// input ends in the middle of an escape
+    #[snafu(display("truncated percent-encoding"))]
+    Truncated,
+
+    /// escape isn't two hexadecimal digits
+    #[snafu(display("invalid percent-encoding: %{}", escape))]
+    InvalidEscape { escape: String },
+}
+
+/// Decodes the percent-encoding of a URL component (RFC3986 section 2.1).
+///
+/// `+` is kept as is since it's also used by base64.
+pub fn percent_decode(encoded: &str) -> Result<Vec<u8>, PercentDecodingError> {
+    let mut unescaped = Vec::with_capacity(encoded.len());
+    let mut bytes = encoded.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let escaped = [
+                bytes.next().ok_or(PercentDecodingError::Truncated)?,
+                bytes.next().ok_or(PercentDecodingError::Truncated)?,
+            ];
+            let decoded = std::str::from_utf8(&escaped)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| PercentDecodingError::InvalidEscape {
+                    escape: String::from_utf8_lossy(&escaped).into_owned(),
+                })?;
+            unescaped.push(decoded);
+        } else {
+            unescaped.push(byte);
+        }
+    }
+    Ok(unescaped)
+}
+
+/// Path prefix under which clients reach the routes: the `X-Forwarded-Prefix` of a trusted reverse
+/// proxy followed by `base_path`, without trailing slash.
+pub fn public_path_prefix(config: &Config, req: &SyncRequest) -> String {
+    let forwarded_prefix = forwarded_header(config, req, "X-Forwarded-Prefix")
+        .filter(|prefix| {
+            prefix.starts_with('/') && !prefix.contains(|c: char| c.is_whitespace() || "?#\\\"".contains(c))
+        })
+        .unwrap_or_default();
+    format!(
+        "{}{}",
+        forwarded_prefix.trim_end_matches('/'),
+        config.base_path_prefix()
+    )
+}
+
+/// Base URL of the server as seen by the clients of a trusted reverse proxy, built from the
+/// `X-Forwarded-Proto`, `X-Forwarded-Host` (or `Host`) and `X-Forwarded-Prefix` headers.
+///
+/// `None` unless `trust_forwarded_headers` is enabled.
+pub fn forwarded_base_url(config: &Config, req: &SyncRequest) -> Option<String> {
+    if !config.trust_forwarded_headers {
+        return None;
+    }
+
+    let proto = forwarded_header(config, req, "X-Forwarded-Proto")
+        .map(|proto| proto.to_ascii_lowercase())
+        .filter(|proto| proto == "http" || proto == "https")
+        .unwrap_or_else(|| "http".to_owned());
+    let host = forwarded_header(config, req, "X-Forwarded-Host")
+        .or_else(|| req.get_header_string_value(header::HOST.as_str()))
+        .filter(|host| host.chars().all(|c| c.is_ascii_alphanumeric() || ".-:[]".contains(c)))?;
+
+    Some(format!("{}://{}{}", proto, host, public_path_prefix(config, req)))
+}
+
+/// First value of a forwarded header, if forwarded headers are trusted.
+fn forwarded_header(config: &Config, req: &SyncRequest, header_name: &str) -> Option<String> {
+    if !config.trust_forwarded_headers {
+        return None;
+    }
+
+    // proxies append their own value to the list
+    let value = req.get_header_string_value(header_name)?;
+    let first = value.split(',').next()?.trim();
+    if first.is_empty() {
+        None
+    } else {
+        Some(first.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::request;
+
+    fn request(headers: &[(&str, &str)]) -> SyncRequest {
+        let mut builder = request::Builder::new();
+        builder.uri("/pki/v1/sign");
+        for (name, value) in headers {
+            builder.header(*name, *value);
+        }
+        let (parts, body) = builder.body(vec![]).expect("request").into_parts();
+        SyncRequest::new(parts, body)
+    }
+
+    #[test]
+    fn forwarded_headers() {
+        let mut config = Config::default();
+        config.base_path = "/pki/v1".to_owned();
+        let req = request(&[
+            ("Host", "picky:12345"),
+            ("X-Forwarded-Proto", "https, http"),
+            ("X-Forwarded-Host", "ingress.example.com"),
+            ("X-Forwarded-Prefix", "/tenant-a/"),
+        ]);
+
+        // ignored unless trusted
+        assert_eq!(forwarded_base_url(&config, &req), None);
+        assert_eq!(public_path_prefix(&config, &req), "/pki/v1");
+
+        config.trust_forwarded_headers = true;
+        assert_eq!(
+            forwarded_base_url(&config, &req).as_deref(),
+            Some("https://ingress.example.com/tenant-a/pki/v1")
+        );
+        assert_eq!(public_path_prefix(&config, &req), "/tenant-a/pki/v1");
+
+        let req = request(&[("Host", "picky:12345"), ("X-Forwarded-Prefix", "tenant-a")]);
+        assert_eq!(
+            forwarded_base_url(&config, &req).as_deref(),
+            Some("http://picky:12345/pki/v1")
+        );
+
+        let req = request(&[("X-Forwarded-Host", "evil.example.com/phishing?")]);
+        assert_eq!(forwarded_base_url(&config, &req), None);
+    }
 }
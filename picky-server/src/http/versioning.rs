@@ -0,0 +1,153 @@
+//! API versioning: every route is served under `/v1` as well as on its legacy unprefixed path.
+//!
+//! Legacy routes answer with `Deprecation`, `Sunset` (when scheduled) and successor `Link` headers
+//! until they're disabled. Routes embedded in issued certificates or defined by other
+//! specifications (OCSP, CRLs, CA issuers, JWKS, ACME), as well as health and metrics probes, aren't
+//! versioned and are always served as is.
+
+use crate::{
+    config::{Config, ConfigError},
+    http::utils::public_path_prefix,
+};
+use saphir::{header, SyncRequest, SyncResponse};
+use serde::{Deserialize, Serialize};
+
+/// Prefix of the current API version
+pub const API_VERSION_PREFIX: &str = "/v1";
+
+pub const DEPRECATION_HEADER: &str = "Deprecation";
+pub const SUNSET_HEADER: &str = "Sunset";
+
+/// Routes relying parties reach through URLs embedded in certificates or well-known locations,
+/// and routes polled by the infrastructure
+const UNVERSIONED_ROUTES: &[&str] = &[
+    "/.well-known/jwks.json",
+    "/cacerts",
+    "/crl",
+    "/crl/<partition>",
+    "/ocsp",
+    "/ocsp/<request>",
+    "/health",
+    "/metrics",
+    "/acme/directory",
+    "/acme/new-nonce",
+    "/acme/new-account",
+    "/acme/new-order",
+    "/acme/order/<id>",
+    "/acme/authz/<id>",
+    "/acme/chall/<id>",
+];
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LegacyRoutesConfig {
+    /// Serve routes on their unprefixed paths too, changes are only applied on restart
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// HTTP date after which legacy routes may be removed (e.g. `Sat, 01 Nov 2025 00:00:00 GMT`),
+    /// announced in the `Sunset` header
+    #[serde(default)]
+    pub sunset: Option<String>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl Default for LegacyRoutesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            sunset: None,
+        }
+    }
+}
+
+impl LegacyRoutesConfig {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if let Some(sunset) = &self.sunset {
+            chrono::DateTime::parse_from_rfc2822(sunset).map_err(|source| ConfigError::InvalidHttpDate {
+                field: "sunset",
+                value: sunset.clone(),
+                source,
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether `route` is served unprefixed only and never deprecated.
+pub fn is_unversioned(route: &str) -> bool {
+    UNVERSIONED_ROUTES.contains(&route)
+}
+
+/// Path of `route` in the current API version.
+pub fn versioned(route: &str) -> String {
+    format!("{}{}", API_VERSION_PREFIX, route)
+}
+
+/// Flags the response to a request made on a legacy route as deprecated, pointing to its successor.
+pub fn write_deprecation_headers(config: &Config, req: &SyncRequest, res: &mut SyncResponse) {
+    res.header(DEPRECATION_HEADER, "true");
+
+    if let Some(sunset) = &config.legacy_routes.sunset {
+        res.header(SUNSET_HEADER, sunset.as_str());
+    }
+
+    let path = req.uri().path();
+    let route = path.strip_prefix(config.base_path_prefix()).unwrap_or(path);
+    res.header(
+        header::LINK,
+        format!(
+            "<{}{}>; rel=\"successor-version\"",
+            public_path_prefix(config, req),
+            versioned(route)
+        ),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::request;
+
+    #[test]
+    fn legacy_routes_config() {
+        let mut legacy_routes = LegacyRoutesConfig::default();
+        legacy_routes.validate().expect("valid default");
+
+        legacy_routes.sunset = Some("Sat, 01 Nov 2025 00:00:00 GMT".to_owned());
+        legacy_routes.validate().expect("valid sunset");
+
+        legacy_routes.sunset = Some("2025-11-01".to_owned());
+        assert!(legacy_routes.validate().is_err());
+
+        assert!(is_unversioned("/crl/<partition>"));
+        assert!(!is_unversioned("/sign"));
+        assert_eq!(versioned("/cert/<multihash>"), "/v1/cert/<multihash>");
+    }
+
+    #[test]
+    fn deprecation_headers() {
+        let mut config = Config::default();
+        config.base_path = "/pki".to_owned();
+        config.legacy_routes.sunset = Some("Sat, 01 Nov 2025 00:00:00 GMT".to_owned());
+
+        let (parts, body) = request::Builder::new()
+            .uri("/pki/requests/abc")
+            .body(Vec::new())
+            .expect("request")
+            .into_parts();
+        let req = SyncRequest::new(parts, body);
+
+        let mut res = SyncResponse::new();
+        write_deprecation_headers(&config, &req, &mut res);
+        let response = res.build_response().expect("response");
+        assert_eq!(response.headers()[DEPRECATION_HEADER], "true");
+        assert_eq!(response.headers()[SUNSET_HEADER], "Sat, 01 Nov 2025 00:00:00 GMT");
+        assert_eq!(
+            response.headers()[header::LINK],
+            "</pki/v1/requests/abc>; rel=\"successor-version\""
+        );
+    }
+}
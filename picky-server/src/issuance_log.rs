@@ -0,0 +1,528 @@
+//! Public issuance log: a Merkle tree (RFC 6962 section 2.1) over every certificate issued by this CA.
+//!
+//! Leaves are the certificates recorded by `certificate_issued` audit events, in audit log order, so the
+//! log is append-only as long as the audit log is. Relying parties fetch the tree head on `/log/sth` and
+//! the inclusion proof of a certificate on `/log/proof/<hash>`, then check them against each other.
+//!
+//! Tree heads are signed by the intermediate CA over the RFC 6962 `TreeHeadSignature` structure, and
+//! `/log/consistency` proves that a tree head extends a previously fetched one.
+
+use crate::{
+    audit::AuditEvent,
+    config::Config,
+    db::{PickyStorage, StorageError},
+    signer::{CaSigner, SignerError},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use snafu::Snafu;
+
+const LEAF_HASH_PREFIX: u8 = 0x00;
+const NODE_HASH_PREFIX: u8 = 0x01;
+/// `Version.v1` and `SignatureType.tree_hash` of RFC 6962 section 3.5
+const TREE_HEAD_SIGNATURE_PREFIX: [u8; 2] = [0x00, 0x01];
+
+#[derive(Debug, Snafu)]
+pub enum LogError {
+    /// audit log couldn't be fetched
+    #[snafu(display("couldn't fetch issuance log: {}", source))]
+    Storage { source: StorageError },
+
+    /// audit record of an issued certificate can't be turned into a leaf
+    #[snafu(display("invalid issuance log record {}: {}", sequence, reason))]
+    InvalidRecord { sequence: u64, reason: String },
+
+    /// tree head couldn't be signed
+    #[snafu(display("couldn't sign tree head: {}", source))]
+    Signing { source: SignerError },
+}
+
+/// Issued certificate at some position of the log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLeaf {
+    /// Canonical address of the certificate
+    pub address: String,
+    pub leaf_hash: Vec<u8>,
+    /// Issuance time (seconds since UNIX epoch)
+    pub timestamp: u64,
+}
+
+/// Details of `certificate_issued` audit records used by the log, absent from records written before it
+#[derive(Deserialize)]
+struct IssuedCertificate {
+    address: Option<String>,
+    log_leaf_hash: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct TreeHead {
+    pub tree_size: usize,
+    /// Issuance time of the last certificate, `None` if the log is empty
+    pub timestamp: Option<u64>,
+    /// Base64-encoded Merkle tree hash
+    pub sha256_root_hash: String,
+    /// Base64-encoded signature of the intermediate CA over `tree_head_signature_input`
+    pub tree_head_signature: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ConsistencyProof {
+    pub first: usize,
+    pub second: usize,
+    /// Base64-encoded hashes
+    pub consistency: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct InclusionProof {
+    pub leaf_index: usize,
+    pub tree_size: usize,
+    /// Base64-encoded hashes
+    pub leaf_hash: String,
+    pub audit_path: Vec<String>,
+    pub sha256_root_hash: String,
+}
+
+/// Hash of a certificate leaf: `SHA-256(0x00 || der)`.
+pub fn leaf_hash(cert_der: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.input(&[LEAF_HASH_PREFIX]);
+    hasher.input(cert_der);
+    hasher.result().to_vec()
+}
+
+fn node_hash(left: &[u8], right: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.input(&[NODE_HASH_PREFIX]);
+    hasher.input(left);
+    hasher.input(right);
+    hasher.result().to_vec()
+}
+
+/// Largest power of two smaller than `n` (`n` > 1), where the tree is split.
+fn split_point(n: usize) -> usize {
+    let mut k = 1;
+    while k * 2 < n {
+        k *= 2;
+    }
+    k
+}
+
+/// Merkle tree hash of `leaf_hashes` (RFC 6962 section 2.1).
+pub fn root_hash(leaf_hashes: &[Vec<u8>]) -> Vec<u8> {
+    match leaf_hashes.len() {
+        0 => Sha256::digest(&[]).to_vec(),
+        1 => leaf_hashes[0].clone(),
+        n => {
+            let k = split_point(n);
+            node_hash(&root_hash(&leaf_hashes[..k]), &root_hash(&leaf_hashes[k..]))
+        }
+    }
+}
+
+/// Audit path of the leaf at `index` (RFC 6962 section 2.1.1), from the leaf up to the root.
+pub fn audit_path(leaf_hashes: &[Vec<u8>], index: usize) -> Vec<Vec<u8>> {
+    let n = leaf_hashes.len();
+    if n <= 1 {
+        return Vec::new();
+    }
+
+    let k = split_point(n);
+    let (mut path, sibling) = if index < k {
+        (audit_path(&leaf_hashes[..k], index), root_hash(&leaf_hashes[k..]))
+    } else {
+        (audit_path(&leaf_hashes[k..], index - k), root_hash(&leaf_hashes[..k]))
+    };
+    path.push(sibling);
+    path
+}
+
+/// Consistency proof between the tree made of the first `first` leaves and the whole tree
+/// (RFC 6962 section 2.1.2).
+pub fn consistency_path(leaf_hashes: &[Vec<u8>], first: usize) -> Vec<Vec<u8>> {
+    if first == 0 || first >= leaf_hashes.len() {
+        return Vec::new();
+    }
+
+    subproof(leaf_hashes, first, true)
+}
+
+fn subproof(leaf_hashes: &[Vec<u8>], m: usize, complete_subtree: bool) -> Vec<Vec<u8>> {
+    let n = leaf_hashes.len();
+    if m == n {
+        return if complete_subtree {
+            Vec::new()
+        } else {
+            vec![root_hash(leaf_hashes)]
+        };
+    }
+
+    let k = split_point(n);
+    if m <= k {
+        let mut path = subproof(&leaf_hashes[..k], m, complete_subtree);
+        path.push(root_hash(&leaf_hashes[k..]));
+        path
+    } else {
+        let mut path = subproof(&leaf_hashes[k..], m - k, false);
+        path.push(root_hash(&leaf_hashes[..k]));
+        path
+    }
+}
+
+/// Leaves of the log, oldest first.
+///
+/// Certificates issued before the log was introduced aren't part of it. Any other record of an issued
+/// certificate which can't be decoded fails the whole log, as skipping it would shift every later leaf.
+pub fn leaves(storage: &dyn PickyStorage) -> Result<Vec<LogLeaf>, LogError> {
+    let records = storage
+        .get_audit_records(0)
+        .map_err(|source| LogError::Storage { source })?;
+
+    let mut leaves = Vec::new();
+    for record in records {
+        if record.event != AuditEvent::CertificateIssued.as_str() {
+            continue;
+        }
+
+        let invalid_record = |reason: String| LogError::InvalidRecord {
+            sequence: record.sequence,
+            reason,
+        };
+        let detail = serde_json::from_str::<IssuedCertificate>(&record.detail)
+            .map_err(|e| invalid_record(format!("couldn't decode details: {}", e)))?;
+
+        match (detail.address, detail.log_leaf_hash) {
+            (Some(address), Some(log_leaf_hash)) => leaves.push(LogLeaf {
+                address,
+                leaf_hash: base64::decode(&log_leaf_hash)
+                    .map_err(|e| invalid_record(format!("invalid leaf hash: {}", e)))?,
+                timestamp: record.timestamp,
+            }),
+            (None, None) => {}
+            _ => return Err(invalid_record("address and leaf hash must be both present".to_owned())),
+        }
+    }
+
+    Ok(leaves)
+}
+
+fn leaf_hashes(leaves: &[LogLeaf]) -> Vec<Vec<u8>> {
+    leaves.iter().map(|leaf| leaf.leaf_hash.clone()).collect()
+}
+
+/// Data signed in tree heads: the RFC 6962 `TreeHeadSignature` structure, with the timestamp in seconds
+/// (0 for the empty log).
+pub fn tree_head_signature_input(tree_size: usize, timestamp: Option<u64>, root_hash: &[u8]) -> Vec<u8> {
+    let mut input = TREE_HEAD_SIGNATURE_PREFIX.to_vec();
+    input.extend_from_slice(&timestamp.unwrap_or(0).to_be_bytes());
+    input.extend_from_slice(&(tree_size as u64).to_be_bytes());
+    input.extend_from_slice(root_hash);
+    input
+}
+
+/// Tree head of `leaves`, signed by the intermediate CA.
+pub fn tree_head(
+    config: &Config,
+    storage: &dyn PickyStorage,
+    signer: &CaSigner,
+    leaves: &[LogLeaf],
+) -> Result<TreeHead, LogError> {
+    let tree_size = leaves.len();
+    let timestamp = leaves.last().map(|leaf| leaf.timestamp);
+    let root_hash = root_hash(&leaf_hashes(leaves));
+
+    let ca_name = format!("{} Authority", config.realm);
+    let ca_hash = storage
+        .get_addressing_hash_by_name(&ca_name)
+        .map_err(|source| LogError::Storage { source })?;
+    let signature = signer
+        .sign_tree_head(
+            &ca_hash,
+            &tree_head_signature_input(tree_size, timestamp, &root_hash),
+            config.leaf_signing_algorithm(),
+        )
+        .map_err(|source| LogError::Signing { source })?;
+
+    Ok(TreeHead {
+        tree_size,
+        timestamp,
+        sha256_root_hash: base64::encode(&root_hash),
+        tree_head_signature: base64::encode(&signature),
+    })
+}
+
+/// Proof that the tree made of the first `first` leaves is a prefix of the one made of the first `second`.
+///
+/// Returns `None` unless `first` <= `second` <= number of leaves.
+pub fn consistency_proof(leaves: &[LogLeaf], first: usize, second: usize) -> Option<ConsistencyProof> {
+    if first > second {
+        return None;
+    }
+    let leaves = leaves.get(..second)?;
+
+    Some(ConsistencyProof {
+        first,
+        second,
+        consistency: consistency_path(&leaf_hashes(leaves), first)
+            .iter()
+            .map(base64::encode)
+            .collect(),
+    })
+}
+
+/// Inclusion proof of the certificate at `address` in the tree made of the first `tree_size` leaves.
+///
+/// Returns `None` if the certificate isn't part of that tree.
+pub fn inclusion_proof(leaves: &[LogLeaf], address: &str, tree_size: usize) -> Option<InclusionProof> {
+    let leaves = leaves.get(..tree_size)?;
+    let leaf_index = leaves.iter().position(|leaf| leaf.address == address)?;
+    let leaf_hashes = leaf_hashes(leaves);
+
+    Some(InclusionProof {
+        leaf_index,
+        tree_size,
+        leaf_hash: base64::encode(&leaf_hashes[leaf_index]),
+        audit_path: audit_path(&leaf_hashes, leaf_index)
+            .iter()
+            .map(base64::encode)
+            .collect(),
+        sha256_root_hash: base64::encode(&root_hash(&leaf_hashes)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        audit,
+        config::{BackendType, Config},
+        db::get_storage,
+    };
+    use serde_json::json;
+
+    /// Recomputes the root hash from an audit path, as relying parties do (RFC 9162 section 2.1.3.2).
+    fn verify_inclusion(leaf_hash: &[u8], index: usize, tree_size: usize, path: &[Vec<u8>], root: &[u8]) -> bool {
+        if index >= tree_size {
+            return false;
+        }
+
+        let (mut f_n, mut s_n) = (index, tree_size - 1);
+        let mut r = leaf_hash.to_vec();
+        for p in path {
+            if s_n == 0 {
+                return false;
+            }
+
+            if f_n & 1 == 1 || f_n == s_n {
+                r = node_hash(p, &r);
+                while f_n & 1 == 0 && f_n != 0 {
+                    f_n >>= 1;
+                    s_n >>= 1;
+                }
+            } else {
+                r = node_hash(&r, p);
+            }
+            f_n >>= 1;
+            s_n >>= 1;
+        }
+
+        s_n == 0 && r == root
+    }
+
+    /// Recomputes both root hashes from a consistency proof, as relying parties do (RFC 9162 section 2.1.4.2).
+    fn verify_consistency(
+        first: usize,
+        second: usize,
+        path: &[Vec<u8>],
+        first_root: &[u8],
+        second_root: &[u8],
+    ) -> bool {
+        if first == second {
+            return path.is_empty() && first_root == second_root;
+        }
+        if first == 0 || first > second {
+            return false;
+        }
+
+        // the first tree is a complete subtree, its root is the first node of the proof
+        let mut path = path.to_vec();
+        if first.is_power_of_two() {
+            path.insert(0, first_root.to_vec());
+        }
+        if path.is_empty() {
+            return false;
+        }
+
+        let (mut f_n, mut s_n) = (first - 1, second - 1);
+        while f_n & 1 == 1 {
+            f_n >>= 1;
+            s_n >>= 1;
+        }
+
+        let (mut f_r, mut s_r) = (path[0].clone(), path[0].clone());
+        for c in &path[1..] {
+            if s_n == 0 {
+                return false;
+            }
+
+            if f_n & 1 == 1 || f_n == s_n {
+                f_r = node_hash(c, &f_r);
+                s_r = node_hash(c, &s_r);
+                while f_n & 1 == 0 && f_n != 0 {
+                    f_n >>= 1;
+                    s_n >>= 1;
+                }
+            } else {
+                s_r = node_hash(&s_r, c);
+            }
+            f_n >>= 1;
+            s_n >>= 1;
+        }
+
+        f_r == first_root && s_r == second_root && s_n == 0
+    }
+
+    #[test]
+    fn audit_paths() {
+        let leaf_hashes = (0u8..11).map(|i| leaf_hash(&[i])).collect::<Vec<_>>();
+
+        assert_eq!(
+            hex::encode(root_hash(&[])),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            root_hash(&leaf_hashes[..2]),
+            node_hash(&leaf_hashes[0], &leaf_hashes[1])
+        );
+
+        for tree_size in 1..=leaf_hashes.len() {
+            let tree = &leaf_hashes[..tree_size];
+            let root = root_hash(tree);
+            for index in 0..tree_size {
+                let path = audit_path(tree, index);
+                assert!(verify_inclusion(&tree[index], index, tree_size, &path, &root));
+                assert!(!verify_inclusion(&leaf_hash(b"other"), index, tree_size, &path, &root));
+                if tree_size > 1 {
+                    assert!(!verify_inclusion(
+                        &tree[index],
+                        (index + 1) % tree_size,
+                        tree_size,
+                        &path,
+                        &root
+                    ));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn consistency_paths() {
+        let leaf_hashes = (0u8..11).map(|i| leaf_hash(&[i])).collect::<Vec<_>>();
+
+        for second in 1..=leaf_hashes.len() {
+            let tree = &leaf_hashes[..second];
+            let second_root = root_hash(tree);
+            for first in 1..=second {
+                let first_root = root_hash(&tree[..first]);
+                let path = consistency_path(tree, first);
+                assert!(verify_consistency(first, second, &path, &first_root, &second_root));
+                assert!(!verify_consistency(
+                    first,
+                    second,
+                    &path,
+                    &root_hash(&[leaf_hash(b"other")]),
+                    &second_root
+                ));
+            }
+        }
+
+        // a rewritten leaf breaks consistency with earlier tree heads
+        let mut rewritten = leaf_hashes.clone();
+        rewritten[2] = leaf_hash(b"other");
+        assert!(!verify_consistency(
+            4,
+            11,
+            &consistency_path(&rewritten, 4),
+            &root_hash(&leaf_hashes[..4]),
+            &root_hash(&rewritten)
+        ));
+    }
+
+    #[test]
+    fn leaves_from_audit_log() {
+        let mut config = Config::default();
+        config.backend = BackendType::Memory;
+        let storage = get_storage(&config).expect("storage").0;
+
+        // issued before the log was introduced
+        audit::append(
+            storage.as_ref(),
+            AuditEvent::CertificateIssued,
+            json!({ "serial_number": "01" }),
+        )
+        .unwrap();
+        for der in &[b"first", b"other", b"third"] {
+            audit::append(
+                storage.as_ref(),
+                AuditEvent::CertificateIssued,
+                json!({
+                    "address": format!("address-{}", String::from_utf8_lossy(&der[..])),
+                    "log_leaf_hash": base64::encode(&leaf_hash(&der[..])),
+                }),
+            )
+            .unwrap();
+            audit::append(storage.as_ref(), AuditEvent::CertificateRevoked, json!({})).unwrap();
+        }
+
+        let leaves = leaves(storage.as_ref()).unwrap();
+        assert_eq!(leaves.len(), 3);
+        assert_eq!(leaves[1].address, "address-other");
+
+        let root = root_hash(&leaf_hashes(&leaves));
+
+        let proof = inclusion_proof(&leaves, "address-other", 3).expect("proof");
+        assert_eq!(proof.leaf_index, 1);
+        assert_eq!(proof.sha256_root_hash, base64::encode(&root));
+        let path = proof
+            .audit_path
+            .iter()
+            .map(|hash| base64::decode(hash).unwrap())
+            .collect::<Vec<_>>();
+        assert!(verify_inclusion(&leaf_hash(b"other"), 1, 3, &path, &root));
+
+        // proofs against an earlier tree head
+        assert_eq!(inclusion_proof(&leaves, "address-other", 2).unwrap().tree_size, 2);
+        assert!(inclusion_proof(&leaves, "address-third", 2).is_none());
+        assert!(inclusion_proof(&leaves, "address-unknown", 3).is_none());
+        assert!(inclusion_proof(&leaves, "address-first", 4).is_none());
+
+        assert_eq!(consistency_proof(&leaves, 1, 3).unwrap().consistency.len(), 2);
+        assert!(consistency_proof(&leaves, 3, 2).is_none());
+        assert!(consistency_proof(&leaves, 1, 4).is_none());
+    }
+
+    #[test]
+    fn invalid_records_fail_the_log() {
+        let mut config = Config::default();
+        config.backend = BackendType::Memory;
+        let storage = get_storage(&config).expect("storage").0;
+
+        audit::append(
+            storage.as_ref(),
+            AuditEvent::CertificateIssued,
+            json!({ "address": "address-first", "log_leaf_hash": base64::encode(&leaf_hash(b"first")) }),
+        )
+        .unwrap();
+        audit::append(
+            storage.as_ref(),
+            AuditEvent::CertificateIssued,
+            json!({ "address": "address-other", "log_leaf_hash": "not base64!" }),
+        )
+        .unwrap();
+
+        match leaves(storage.as_ref()) {
+            Err(LogError::InvalidRecord { sequence, .. }) => assert_eq!(sequence, 1),
+            other => panic!("unexpected leaves: {:?}", other),
+        }
+    }
+}
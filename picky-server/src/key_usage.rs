@@ -0,0 +1,415 @@
+//! Signature counters and age of the CA keys.
+//!
+//! Part of the key rotation policy: operators are warned, or issuance is blocked, once a CA key has
+//! performed too many signatures or has been in use for too long.
+//!
+//! Signatures over stored certificates and CRLs are counted by the `SignatureCounter` storage observer,
+//! the other ones (e.g. OCSP responses) are recorded by their signer.
+
+use crate::{
+    addressing::ArtifactNamespace,
+    crl,
+    db::{CertificateEntry, KeyUsageEntry, PickyStorage, StorageError, StorageObserver},
+    notifier::to_chrono,
+    utils,
+};
+use picky::x509::{
+    certificate::{CertError, CertType},
+    Cert,
+};
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::{
+    fmt::Write,
+    sync::{Mutex, PoisonError},
+};
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+lazy_static::lazy_static! {
+    // reading and incrementing a counter must not interleave
+    static ref COUNTER_LOCK: Mutex<()> = Mutex::new(());
+}
+
+#[derive(Debug, Snafu)]
+pub enum KeyUsageError {
+    /// CA certificate has no subject key identifier
+    #[snafu(display("couldn't get CA key identifier: {}", source))]
+    KeyIdentifier { source: CertError },
+
+    /// signature counter couldn't be fetched
+    #[snafu(display("couldn't fetch usage of key {}: {}", key_identifier, source))]
+    UsageUnavailable {
+        key_identifier: String,
+        source: StorageError,
+    },
+
+    /// signature counter couldn't be stored
+    #[snafu(display("couldn't store usage of key {}: {}", key_identifier, source))]
+    UsageStorage {
+        key_identifier: String,
+        source: StorageError,
+    },
+
+    /// CA key reached its usage limits and `block_issuance` is set
+    #[snafu(display(
+        "key of {} reached its usage limits ({}), it should be rotated",
+        ca,
+        exceeded.join(", ")
+    ))]
+    LimitsExceeded { ca: String, exceeded: Vec<&'static str> },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct KeyUsageLimits {
+    /// Number of signatures a CA key may perform
+    #[serde(default)]
+    pub max_signatures: Option<u64>,
+    /// Age of a CA key in days, counted from the start of its certificate validity
+    #[serde(default)]
+    pub max_age_days: Option<u64>,
+    /// Refuse to issue certificates with a CA key which reached a limit instead of only warning
+    #[serde(default)]
+    pub block_issuance: bool,
+}
+
+/// Usage of a CA key, as reported on `/health` and `/metrics`
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct KeyUsageReport {
+    pub ca: String,
+    /// Hex-encoded subject key identifier of the CA certificate
+    pub key_identifier: String,
+    pub signatures: u64,
+    pub age_secs: u64,
+    /// Limits reached by the key, empty if none
+    pub exceeded: Vec<&'static str>,
+}
+
+/// Adds one signature to the counter of the key certified by `ca_cert` and returns the new count.
+pub fn increment(storage: &dyn PickyStorage, ca_cert: &Cert) -> Result<u64, KeyUsageError> {
+    increment_key(storage, key_identifier(ca_cert)?)
+}
+
+fn increment_key(storage: &dyn PickyStorage, key_identifier: String) -> Result<u64, KeyUsageError> {
+    let _guard = COUNTER_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let signatures = signatures(storage, &key_identifier)? + 1;
+    storage
+        .store_key_usage(KeyUsageEntry {
+            key_identifier: key_identifier.clone(),
+            signatures,
+        })
+        .map_err(|source| KeyUsageError::UsageStorage { key_identifier, source })?;
+
+    Ok(signatures)
+}
+
+/// Counts a signature over data which isn't stored, logging failures instead of returning them.
+pub fn record_signature(storage: &dyn PickyStorage, ca_cert: &Cert) {
+    if let Err(e) = increment(storage, ca_cert) {
+        log::error!("couldn't count CA key signature: {}", e);
+    }
+}
+
+/// Storage observer counting the signatures of the stored certificates and CRLs on behalf of their issuer
+pub struct SignatureCounter;
+
+impl SignatureCounter {
+    fn count(&self, storage: &dyn PickyStorage, key_identifier: String) {
+        if let Err(e) = increment_key(storage, key_identifier) {
+            log::error!("couldn't count CA key signature: {}", e);
+        }
+    }
+}
+
+impl StorageObserver for SignatureCounter {
+    fn on_insert(&self, storage: &dyn PickyStorage, entry: &CertificateEntry) {
+        let cert = match Cert::from_der(&entry.cert) {
+            Ok(cert) => cert,
+            Err(e) => {
+                log::error!("couldn't count signature of {}: {}", entry.name, e);
+                return;
+            }
+        };
+
+        // self-signed certificates may not identify their issuer key
+        let issuer_key_identifier = match cert.authority_key_identifier() {
+            Ok(aki) => aki.key_identifier().map(hex::encode),
+            Err(_) if cert.ty() == CertType::Root => Some(entry.key_identifier.clone()),
+            Err(_) => None,
+        };
+
+        // only keys of stored CAs are counted
+        if let Some(key_identifier) = issuer_key_identifier {
+            if storage.get_addressing_hash_by_key_identifier(&key_identifier).is_ok() {
+                self.count(storage, key_identifier);
+            }
+        }
+    }
+
+    fn on_store_artifact(
+        &self,
+        storage: &dyn PickyStorage,
+        namespace: ArtifactNamespace,
+        latest_key: &str,
+        _hash: &str,
+        _artifact: &[u8],
+    ) {
+        if namespace == ArtifactNamespace::Crl {
+            self.count(storage, crl::latest_key_issuer(latest_key).to_owned());
+        }
+    }
+}
+
+/// Usage of the key certified by the CA certificate `ca_cert`.
+pub fn report(
+    storage: &dyn PickyStorage,
+    ca_name: &str,
+    ca_cert: &Cert,
+    limits: &KeyUsageLimits,
+) -> Result<KeyUsageReport, KeyUsageError> {
+    let key_identifier = key_identifier(ca_cert)?;
+    let signatures = signatures(storage, &key_identifier)?;
+    let age_secs = (utils::now() - to_chrono(&ca_cert.valid_not_before()))
+        .num_seconds()
+        .max(0) as u64;
+
+    Ok(KeyUsageReport {
+        ca: ca_name.to_owned(),
+        key_identifier,
+        signatures,
+        age_secs,
+        exceeded: exceeded_limits(signatures, age_secs, limits),
+    })
+}
+
+/// Checks the limits of a CA key about to sign: logs a warning when one is reached, or fails if
+/// `block_issuance` is set.
+pub fn check_before_signing(
+    storage: &dyn PickyStorage,
+    ca_name: &str,
+    ca_cert: &Cert,
+    limits: &KeyUsageLimits,
+) -> Result<(), KeyUsageError> {
+    let report = report(storage, ca_name, ca_cert, limits)?;
+    if report.exceeded.is_empty() {
+        return Ok(());
+    }
+
+    let exceeded = KeyUsageError::LimitsExceeded {
+        ca: ca_name.to_owned(),
+        exceeded: report.exceeded,
+    };
+
+    if limits.block_issuance {
+        Err(exceeded)
+    } else {
+        log::warn!("{}", exceeded);
+        Ok(())
+    }
+}
+
+/// Prometheus text exposition of the given reports.
+pub fn prometheus_metrics(reports: &[KeyUsageReport]) -> String {
+    let mut metrics = String::new();
+
+    let families: [(&str, &str, &str, fn(&KeyUsageReport) -> u64); 3] = [
+        (
+            "picky_ca_key_signatures_total",
+            "counter",
+            "Signatures performed by the CA key.",
+            |report| report.signatures,
+        ),
+        (
+            "picky_ca_key_age_seconds",
+            "gauge",
+            "Age of the CA key, counted from the start of its certificate validity.",
+            |report| report.age_secs,
+        ),
+        (
+            "picky_ca_key_limit_exceeded",
+            "gauge",
+            "Whether the CA key reached one of its usage limits.",
+            |report| u64::from(!report.exceeded.is_empty()),
+        ),
+    ];
+
+    for (name, kind, help, value) in families.iter() {
+        let _ = writeln!(metrics, "# HELP {} {}", name, help);
+        let _ = writeln!(metrics, "# TYPE {} {}", name, kind);
+        for report in reports {
+            let _ = writeln!(
+                metrics,
+                "{}{{ca=\"{}\",key_identifier=\"{}\"}} {}",
+                name,
+                escape_label(&report.ca),
+                report.key_identifier,
+                value(report)
+            );
+        }
+    }
+
+    metrics
+}
+
+fn key_identifier(ca_cert: &Cert) -> Result<String, KeyUsageError> {
+    ca_cert
+        .subject_key_identifier()
+        .map(hex::encode)
+        .map_err(|source| KeyUsageError::KeyIdentifier { source })
+}
+
+fn signatures(storage: &dyn PickyStorage, key_identifier: &str) -> Result<u64, KeyUsageError> {
+    Ok(storage
+        .get_key_usage(key_identifier)
+        .map_err(|source| KeyUsageError::UsageUnavailable {
+            key_identifier: key_identifier.to_owned(),
+            source,
+        })?
+        .map_or(0, |entry| entry.signatures))
+}
+
+fn exceeded_limits(signatures: u64, age_secs: u64, limits: &KeyUsageLimits) -> Vec<&'static str> {
+    let mut exceeded = Vec::new();
+
+    if limits.max_signatures.map_or(false, |max| signatures >= max) {
+        exceeded.push("max_signatures");
+    }
+
+    if limits
+        .max_age_days
+        .map_or(false, |max| age_secs >= max.saturating_mul(SECS_PER_DAY))
+    {
+        exceeded.push("max_age_days");
+    }
+
+    exceeded
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{BackendType, Config},
+        db::get_storage,
+        labels::Labels,
+        picky_controller::{IssuerOptions, Picky},
+    };
+    use picky::{key::PrivateKey, pem::Pem, signature::SignatureHashType};
+
+    #[test]
+    fn limits() {
+        let limits = KeyUsageLimits {
+            max_signatures: Some(10),
+            max_age_days: Some(365),
+            block_issuance: false,
+        };
+
+        assert!(exceeded_limits(9, 364 * SECS_PER_DAY, &limits).is_empty());
+        assert_eq!(exceeded_limits(10, 0, &limits), vec!["max_signatures"]);
+        assert_eq!(
+            exceeded_limits(11, 365 * SECS_PER_DAY, &limits),
+            vec!["max_signatures", "max_age_days"]
+        );
+        assert!(exceeded_limits(u64::max_value(), u64::max_value(), &KeyUsageLimits::default()).is_empty());
+    }
+
+    #[test]
+    fn signature_counting() {
+        let mut config = Config::default();
+        config.backend = BackendType::Memory;
+        let storage = get_storage(&config).expect("storage").0;
+        let storage = storage.as_ref();
+
+        let pem = include_str!("../../test_assets/private_keys/rsa-2048-pk_1.key")
+            .parse::<Pem>()
+            .expect("pem");
+        let pk = PrivateKey::from_pem(&pem).expect("private key");
+        let ca_cert = Picky::generate_root(
+            "Picky Root CA",
+            &pk,
+            SignatureHashType::RsaSha256,
+            IssuerOptions::root(),
+        )
+        .expect("generate root");
+
+        let limits = KeyUsageLimits {
+            max_signatures: Some(2),
+            max_age_days: None,
+            block_issuance: true,
+        };
+
+        check_before_signing(storage, "Picky Root CA", &ca_cert, &limits).expect("within limits");
+        assert_eq!(increment(storage, &ca_cert).expect("count signature"), 1);
+        assert_eq!(increment(storage, &ca_cert).expect("count signature"), 2);
+
+        let report = report(storage, "Picky Root CA", &ca_cert, &limits).expect("report");
+        assert_eq!(report.signatures, 2);
+        assert_eq!(report.exceeded, vec!["max_signatures"]);
+
+        let err = check_before_signing(storage, "Picky Root CA", &ca_cert, &limits)
+            .err()
+            .expect("limit reached");
+        assert!(matches!(&err, KeyUsageError::LimitsExceeded { exceeded, .. } if *exceeded == ["max_signatures"]));
+        assert_eq!(
+            err.to_string(),
+            "key of Picky Root CA reached its usage limits (max_signatures), it should be rotated"
+        );
+
+        let metrics = prometheus_metrics(&[report.clone()]);
+        assert!(metrics.contains("# TYPE picky_ca_key_signatures_total counter\n"));
+        assert!(metrics.contains(&format!(
+            "picky_ca_key_signatures_total{{ca=\"Picky Root CA\",key_identifier=\"{}\"}} 2\n",
+            report.key_identifier
+        )));
+        assert!(metrics.contains(&format!(
+            "picky_ca_key_limit_exceeded{{ca=\"Picky Root CA\",key_identifier=\"{}\"}} 1\n",
+            report.key_identifier
+        )));
+    }
+
+    #[test]
+    fn stored_signatures_counted() {
+        let mut config = Config::default();
+        config.backend = BackendType::Memory;
+        let storage = get_storage(&config).expect("storage").0;
+
+        let pem = include_str!("../../test_assets/private_keys/rsa-2048-pk_1.key")
+            .parse::<Pem>()
+            .expect("pem");
+        let pk = PrivateKey::from_pem(&pem).expect("private key");
+        let root = Picky::generate_root(
+            "Picky Root CA",
+            &pk,
+            SignatureHashType::RsaSha256,
+            IssuerOptions::root(),
+        )
+        .expect("generate root");
+        let key_identifier = key_identifier(&root).expect("key identifier");
+
+        let entry = CertificateEntry {
+            name: "Picky Root CA".to_owned(),
+            cert: root.to_der().expect("root der"),
+            key_identifier: key_identifier.clone(),
+            key: None,
+            requested_by: None,
+            labels: Labels::new(),
+        };
+        storage.store(entry.clone()).expect("store root");
+        // storing the same certificate again isn't another signature
+        storage.store(entry).expect("store root again");
+        assert_eq!(signatures(storage.as_ref(), &key_identifier).expect("signatures"), 1);
+
+        storage
+            .store_artifact(
+                ArtifactNamespace::Crl,
+                &format!("{}-1", key_identifier),
+                b"crl".to_vec(),
+            )
+            .expect("store crl");
+        assert_eq!(signatures(storage.as_ref(), &key_identifier).expect("signatures"), 2);
+    }
+}
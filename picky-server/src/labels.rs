@@ -0,0 +1,138 @@
+//! Key/value labels attached to certificates at issuance (team, service, environment, ...).
+//!
+//! Labels are restricted to a conservative charset so they can be used as-is in query strings
+//! (`?labels=team=payments,env=prod`) and as document keys by every storage backend.
+
+use snafu::Snafu;
+use std::collections::BTreeMap;
+
+const MAX_KEY_LEN: usize = 63;
+const MAX_VALUE_LEN: usize = 63;
+const MAX_LABELS: usize = 32;
+
+pub type Labels = BTreeMap<String, String>;
+
+#[derive(Debug, Snafu)]
+pub enum LabelError {
+    /// more labels than allowed
+    #[snafu(display("too many labels: {} (max {})", count, MAX_LABELS))]
+    TooManyLabels { count: usize },
+
+    /// key is empty, too long or uses characters outside of the allowed charset
+    #[snafu(display("invalid label key '{}'", key))]
+    InvalidKey { key: String },
+
+    /// value is too long or uses characters outside of the allowed charset
+    #[snafu(display("invalid value for label '{}': '{}'", key, value))]
+    InvalidValue { key: String, value: String },
+
+    /// selector pair isn't `key=value`
+    #[snafu(display("invalid label selector '{}', expected key=value", pair))]
+    InvalidSelector { pair: String },
+}
+
+/// Checks label count, keys (`[A-Za-z0-9_-]`) and values (`[A-Za-z0-9_.-]`).
+pub fn validate(labels: &Labels) -> Result<(), LabelError> {
+    if labels.len() > MAX_LABELS {
+        return Err(LabelError::TooManyLabels { count: labels.len() });
+    }
+
+    for (key, value) in labels {
+        if key.is_empty()
+            || key.len() > MAX_KEY_LEN
+            || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(LabelError::InvalidKey { key: key.clone() });
+        }
+
+        if value.len() > MAX_VALUE_LEN
+            || !value
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+        {
+            return Err(LabelError::InvalidValue {
+                key: key.clone(),
+                value: value.clone(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses a `key=value,key=value` selector.
+pub fn parse_selector(selector: &str) -> Result<Labels, LabelError> {
+    let mut labels = Labels::new();
+    for pair in selector.split(',') {
+        let mut split = pair.splitn(2, '=');
+        match (split.next(), split.next()) {
+            (Some(key), Some(value)) => {
+                labels.insert(key.to_owned(), value.to_owned());
+            }
+            _ => return Err(LabelError::InvalidSelector { pair: pair.to_owned() }),
+        }
+    }
+
+    validate(&labels)?;
+    Ok(labels)
+}
+
+/// True if `labels` carries every label of `selector`.
+pub fn matches(labels: &Labels, selector: &Labels) -> bool {
+    selector.iter().all(|(key, value)| labels.get(key) == Some(value))
+}
+
+/// Labels from the request body completed with the ones from the token claims, which take
+/// precedence since they are vouched for by the provisioner.
+pub fn merge(body_labels: Labels, claim_labels: Labels) -> Labels {
+    let mut labels = body_labels;
+    labels.extend(claim_labels);
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> Labels {
+        pairs
+            .iter()
+            .map(|(key, value)| ((*key).to_owned(), (*value).to_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn selector() {
+        let selector = parse_selector("team=payments,env=prod").expect("selector");
+        assert_eq!(selector, labels(&[("team", "payments"), ("env", "prod")]));
+
+        assert!(matches(
+            &labels(&[("team", "payments"), ("env", "prod"), ("tier", "1")]),
+            &selector
+        ));
+        assert!(!matches(&labels(&[("team", "payments"), ("env", "dev")]), &selector));
+        assert!(!matches(&labels(&[("team", "payments")]), &selector));
+
+        assert!(matches!(
+            parse_selector("team"),
+            Err(LabelError::InvalidSelector { pair }) if pair == "team"
+        ));
+        assert!(matches!(
+            parse_selector("value.team=a"),
+            Err(LabelError::InvalidKey { key }) if key == "value.team"
+        ));
+        assert!(matches!(
+            parse_selector("team=a b"),
+            Err(LabelError::InvalidValue { key, value }) if key == "team" && value == "a b"
+        ));
+    }
+
+    #[test]
+    fn claims_take_precedence() {
+        let merged = merge(
+            labels(&[("team", "spoofed"), ("service", "api")]),
+            labels(&[("team", "payments")]),
+        );
+        assert_eq!(merged, labels(&[("team", "payments"), ("service", "api")]));
+    }
+}
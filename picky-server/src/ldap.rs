@@ -0,0 +1,377 @@
+//! Publication of CA certificates and CRLs to an LDAP directory (e.g. Active Directory).
+//!
+//! Every CA certificate and CRL saved to the storage is written by a background thread to the
+//! directory entry given by the matching DN template. Missing entries are created, existing ones
+//! have their certificate or CRL attribute replaced.
+
+use crate::{
+    addressing::ArtifactNamespace,
+    cert_cache,
+    config::Config,
+    db::{CertificateEntry, PickyStorage, StorageObserver},
+};
+use ldap3::{LdapConn, Mod};
+use picky::x509::{
+    certificate::{CertError, CertType},
+    crl::CrlError,
+    name::DirectoryName,
+    Crl,
+};
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::{
+    collections::HashSet,
+    io,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex, PoisonError,
+    },
+};
+
+const LDAP_NO_SUCH_OBJECT: u32 = 32;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LdapPublisherConfig {
+    /// e.g. `ldap://dc01.contoso.local:389` or `ldaps://dc01.contoso.local:636`
+    pub url: String,
+    pub bind_dn: String,
+    pub bind_password: String,
+    /// DN of the root CA certificate entry, e.g.
+    /// `CN={cn},CN=Certification Authorities,CN=Public Key Services,CN=Services,CN=Configuration,DC=contoso,DC=local`
+    #[serde(default)]
+    pub root_dn_template: Option<String>,
+    /// DN of the intermediate CA certificate entry, e.g.
+    /// `CN={cn},CN=AIA,CN=Public Key Services,CN=Services,CN=Configuration,DC=contoso,DC=local`
+    #[serde(default)]
+    pub intermediate_dn_template: Option<String>,
+    /// DN of the CRL entry, e.g.
+    /// `CN={key},CN={cn},CN=CDP,CN=Public Key Services,CN=Services,CN=Configuration,DC=contoso,DC=local`
+    #[serde(default)]
+    pub crl_dn_template: Option<String>,
+}
+
+#[derive(Debug, Snafu)]
+enum PublicationError {
+    /// directory server is unreachable
+    #[snafu(display("couldn't connect to {}: {}", url, source))]
+    Connection { url: String, source: io::Error },
+
+    /// directory server rejected the credentials
+    #[snafu(display("couldn't bind as {}: {}", bind_dn, source))]
+    Bind { bind_dn: String, source: io::Error },
+
+    /// entry couldn't be modified
+    #[snafu(display("couldn't modify entry: {}", source))]
+    Modify { source: io::Error },
+
+    /// missing entry couldn't be created
+    #[snafu(display("couldn't add entry: {}", source))]
+    Add { source: io::Error },
+
+    /// directory server reported an error
+    #[snafu(display("directory error: {}", source))]
+    Directory { source: io::Error },
+
+    /// certificate to publish couldn't be parsed
+    #[snafu(display("couldn't parse certificate: {}", source))]
+    InvalidCertificate { source: CertError },
+
+    /// CA certificate has no subject key identifier
+    #[snafu(display("couldn't get CA key identifier: {}", source))]
+    CaKeyIdentifier { source: CertError },
+
+    /// CRL to publish couldn't be parsed
+    #[snafu(display("couldn't parse CRL: {}", source))]
+    InvalidCrl { source: CrlError },
+
+    /// DN template uses `{cn}` but the name has no common name
+    #[snafu(display("{} has no common name", name))]
+    MissingCommonName { name: String },
+}
+
+/// A directory entry to create or update
+#[derive(Debug, Clone, PartialEq)]
+struct Publication {
+    dn: String,
+    object_class: &'static str,
+    attribute: &'static str,
+    value: Vec<u8>,
+}
+
+/// Returns the observer publishing to the configured directory, if publication is configured.
+pub fn publisher(config: &Config) -> Option<Arc<dyn StorageObserver>> {
+    config.ldap_publisher.as_ref().map(|ldap_config| {
+        let (sender, receiver) = channel();
+        spawn_publisher(ldap_config.clone(), receiver);
+        Arc::new(LdapPublisher {
+            config: ldap_config.clone(),
+            sender: Mutex::new(sender),
+        }) as Arc<dyn StorageObserver>
+    })
+}
+
+fn spawn_publisher(config: LdapPublisherConfig, receiver: Receiver<Publication>) {
+    std::thread::spawn(move || {
+        for publication in receiver {
+            // publications are rare: a connection is opened for each of them
+            if let Err(e) = publish(&config, &publication) {
+                log::error!("couldn't publish {} to LDAP directory: {}", publication.dn, e);
+            }
+        }
+    });
+}
+
+fn publish(config: &LdapPublisherConfig, publication: &Publication) -> Result<(), PublicationError> {
+    let ldap = LdapConn::new(&config.url).map_err(|source| PublicationError::Connection {
+        url: config.url.clone(),
+        source,
+    })?;
+    ldap.simple_bind(&config.bind_dn, &config.bind_password)
+        .and_then(|result| result.success())
+        .map_err(|source| PublicationError::Bind {
+            bind_dn: config.bind_dn.clone(),
+            source,
+        })?;
+
+    let value = values(publication.value.clone());
+    let result = ldap
+        .modify(
+            &publication.dn,
+            vec![Mod::Replace(publication.attribute.as_bytes().to_vec(), value.clone())],
+        )
+        .map_err(|source| PublicationError::Modify { source })?;
+
+    let result = if result.rc == LDAP_NO_SUCH_OBJECT {
+        let mut attributes = vec![
+            (
+                b"objectClass".to_vec(),
+                values(publication.object_class.as_bytes().to_vec()),
+            ),
+            (publication.attribute.as_bytes().to_vec(), value),
+        ];
+        if publication.object_class == "certificationAuthority" {
+            // mandatory attributes of the schema, filled with a placeholder like certutil does
+            attributes.push((b"authorityRevocationList;binary".to_vec(), values(vec![0])));
+            attributes.push((b"certificateRevocationList;binary".to_vec(), values(vec![0])));
+        }
+        ldap.add(&publication.dn, attributes)
+            .map_err(|source| PublicationError::Add { source })?
+    } else {
+        result
+    };
+    result
+        .success()
+        .map_err(|source| PublicationError::Directory { source })?;
+
+    let _ = ldap.unbind();
+    Ok(())
+}
+
+fn values(value: Vec<u8>) -> HashSet<Vec<u8>> {
+    let mut values = HashSet::with_capacity(1);
+    values.insert(value);
+    values
+}
+
+/// Replaces `{cn}` with the common name of `name` and `{key}` with `key`, escaping both as DN
+/// attribute values.
+fn render_dn(template: &str, name: &DirectoryName, key: &str) -> Result<String, PublicationError> {
+    let cn = name
+        .find_common_name()
+        .map(|cn| cn.to_utf8_lossy().into_owned())
+        .ok_or_else(|| PublicationError::MissingCommonName { name: name.to_string() })?;
+    Ok(template
+        .replace("{cn}", &escape_dn_value(&cn))
+        .replace("{key}", &escape_dn_value(key)))
+}
+
+/// https://tools.ietf.org/html/rfc4514#section-2.4
+fn escape_dn_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    let last = value.chars().count().saturating_sub(1);
+    for (idx, c) in value.chars().enumerate() {
+        let needs_escape = match c {
+            ',' | '+' | '"' | '\\' | '<' | '>' | ';' | '=' => true,
+            '#' => idx == 0,
+            ' ' => idx == 0 || idx == last,
+            _ => false,
+        };
+        if needs_escape {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Storage observer queuing CA certificates and CRLs for publication once they've been saved
+struct LdapPublisher {
+    config: LdapPublisherConfig,
+    sender: Mutex<Sender<Publication>>,
+}
+
+impl LdapPublisher {
+    fn send(&self, publication: Publication) {
+        let sender = self.sender.lock().unwrap_or_else(PoisonError::into_inner);
+        if sender.send(publication).is_err() {
+            log::error!("LDAP publisher is gone, CA certificates and CRLs are no longer published");
+        }
+    }
+
+    fn certificate_publication(&self, cert_der: &[u8]) -> Result<Option<Publication>, PublicationError> {
+        let cert = cert_cache::parse(cert_der).map_err(|source| PublicationError::InvalidCertificate { source })?;
+        let template = match cert.ty() {
+            CertType::Root => self.config.root_dn_template.as_ref(),
+            CertType::Intermediate => self.config.intermediate_dn_template.as_ref(),
+            _ => None,
+        };
+        let template = match template {
+            Some(template) => template,
+            None => return Ok(None),
+        };
+        let key_identifier = cert
+            .subject_key_identifier()
+            .map(hex::encode)
+            .map_err(|source| PublicationError::CaKeyIdentifier { source })?;
+
+        Ok(Some(Publication {
+            dn: render_dn(template, &cert.subject_name(), &key_identifier)?,
+            object_class: "certificationAuthority",
+            attribute: "cACertificate;binary",
+            value: cert_der.to_vec(),
+        }))
+    }
+
+    fn crl_publication(&self, latest_key: &str, crl_der: &[u8]) -> Result<Option<Publication>, PublicationError> {
+        let template = match &self.config.crl_dn_template {
+            Some(template) => template,
+            None => return Ok(None),
+        };
+        let crl = Crl::from_der(crl_der).map_err(|source| PublicationError::InvalidCrl { source })?;
+
+        Ok(Some(Publication {
+            dn: render_dn(template, &crl.issuer_name(), latest_key)?,
+            object_class: "cRLDistributionPoint",
+            attribute: "certificateRevocationList;binary",
+            value: crl_der.to_vec(),
+        }))
+    }
+}
+
+impl StorageObserver for LdapPublisher {
+    fn on_store(&self, _: &dyn PickyStorage, entry: &CertificateEntry) {
+        match self.certificate_publication(&entry.cert) {
+            Ok(Some(publication)) => self.send(publication),
+            Ok(None) => {}
+            Err(e) => log::error!("couldn't publish {} to LDAP directory: {}", entry.name, e),
+        }
+    }
+
+    fn on_store_artifact(
+        &self,
+        _: &dyn PickyStorage,
+        namespace: ArtifactNamespace,
+        latest_key: &str,
+        _hash: &str,
+        artifact: &[u8],
+    ) {
+        if namespace != ArtifactNamespace::Crl {
+            return;
+        }
+
+        match self.crl_publication(latest_key, artifact) {
+            Ok(Some(publication)) => self.send(publication),
+            Ok(None) => {}
+            Err(e) => log::error!("couldn't publish CRL {} to LDAP directory: {}", latest_key, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use picky::{
+        key::PrivateKey,
+        pem::Pem,
+        x509::{certificate::CertificateBuilder, date::UTCDate},
+    };
+
+    fn publisher() -> (LdapPublisher, Receiver<Publication>) {
+        let (sender, receiver) = channel();
+        let publisher = LdapPublisher {
+            config: LdapPublisherConfig {
+                url: "ldap://dc01.contoso.local".to_owned(),
+                bind_dn: "CN=picky,CN=Users,DC=contoso,DC=local".to_owned(),
+                bind_password: "password".to_owned(),
+                root_dn_template: Some("CN={cn},CN=Certification Authorities,DC=contoso,DC=local".to_owned()),
+                intermediate_dn_template: None,
+                crl_dn_template: Some("CN={key},CN={cn},CN=CDP,DC=contoso,DC=local".to_owned()),
+            },
+            sender: Mutex::new(sender),
+        };
+        (publisher, receiver)
+    }
+
+    #[test]
+    fn dn_escaping() {
+        assert_eq!(escape_dn_value("Contoso Root CA"), "Contoso Root CA");
+        assert_eq!(escape_dn_value("Contoso, Inc. #1"), "Contoso\\, Inc. #1");
+        assert_eq!(escape_dn_value("#a+b "), "\\#a\\+b\\ ");
+        assert_eq!(escape_dn_value(" x=\"y\""), "\\ x\\=\\\"y\\\"");
+    }
+
+    #[test]
+    fn publications() {
+        let (publisher, _receiver) = publisher();
+        let key = PrivateKey::from_pem(&crate::test_files::RSA_2048_PK_1.parse::<Pem>().unwrap()).unwrap();
+
+        let root = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2020, 1, 1).unwrap(), UTCDate::ymd(2030, 1, 1).unwrap())
+            .self_signed(DirectoryName::new_common_name("Contoso, Root CA"), &key)
+            .ca(true)
+            .build()
+            .unwrap();
+        let root_der = root.to_der().unwrap();
+        let publication = publisher.certificate_publication(&root_der).unwrap().unwrap();
+        assert_eq!(
+            publication,
+            Publication {
+                dn: "CN=Contoso\\, Root CA,CN=Certification Authorities,DC=contoso,DC=local".to_owned(),
+                object_class: "certificationAuthority",
+                attribute: "cACertificate;binary",
+                value: root_der,
+            }
+        );
+
+        let crl_der = Crl::builder()
+            .issuer_cert(&root, &key)
+            .this_update(UTCDate::ymd(2020, 1, 1).unwrap())
+            .build()
+            .unwrap()
+            .to_der()
+            .unwrap();
+        let publication = publisher.crl_publication("0a0b-1", &crl_der).unwrap().unwrap();
+        assert_eq!(
+            publication.dn,
+            "CN=0a0b-1,CN=Contoso\\, Root CA,CN=CDP,DC=contoso,DC=local"
+        );
+        assert_eq!(publication.object_class, "cRLDistributionPoint");
+        assert_eq!(publication.value, crl_der);
+
+        // no template for intermediate certificates
+        let intermediate = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2020, 1, 1).unwrap(), UTCDate::ymd(2030, 1, 1).unwrap())
+            .subject(DirectoryName::new_common_name("Contoso Authority"), key.to_public_key())
+            .issuer_cert(&root, &key)
+            .ca(true)
+            .pathlen(0)
+            .build()
+            .unwrap();
+        assert_eq!(
+            publisher
+                .certificate_publication(&intermediate.to_der().unwrap())
+                .unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,250 @@
+//! zlint-style checks of the CSRs about to be signed and of the leaf certificates pushed on `/cert`.
+//!
+//! Findings are recorded in the audit log along with the issuance or the push. With `enforce`, a
+//! request raising any error-level finding is refused instead.
+
+use crate::{alt_names::AltNames, notifier::to_chrono};
+use picky::{
+    oids,
+    x509::{Cert, Csr},
+    AlgorithmIdentifier,
+};
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LintPolicy {
+    /// Longest accepted validity period, in days
+    #[serde(default = "default_max_validity_days")]
+    pub max_validity_days: u64,
+    /// Refuse requests raising an error-level finding instead of only recording it
+    #[serde(default)]
+    pub enforce: bool,
+}
+
+impl Default for LintPolicy {
+    fn default() -> Self {
+        Self {
+            max_validity_days: default_max_validity_days(),
+            enforce: false,
+        }
+    }
+}
+
+fn default_max_validity_days() -> u64 {
+    398 // CA/Browser Forum limit for TLS server certificates
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct LintFinding {
+    pub lint: &'static str,
+    pub severity: Severity,
+    pub detail: String,
+}
+
+#[derive(Debug, Snafu)]
+pub enum LintError {
+    /// error-level findings while the policy is enforced
+    #[snafu(display(
+        "{}",
+        findings.iter().map(|finding| finding.detail.as_str()).collect::<Vec<_>>().join(", ")
+    ))]
+    Refused { findings: Vec<LintFinding> },
+}
+
+impl LintFinding {
+    fn new(lint: &'static str, severity: Severity, detail: String) -> Self {
+        Self { lint, severity, detail }
+    }
+}
+
+/// Lints a CSR about to be signed for `validity_days` along with the requested `alt_names`.
+pub fn lint_csr(csr: &Csr, alt_names: &AltNames, validity_days: u64, policy: &LintPolicy) -> Vec<LintFinding> {
+    let mut findings = Vec::new();
+
+    let has_common_name = csr.subject_name().find_common_name().is_some();
+    lint_names(has_common_name, !alt_names.is_empty(), &mut findings);
+    lint_signature_algorithm(csr.signature_algorithm(), &mut findings);
+    lint_validity(validity_days, policy, &mut findings);
+
+    findings
+}
+
+/// Lints a pushed leaf certificate.
+pub fn lint_cert(cert: &Cert, policy: &LintPolicy) -> Vec<LintFinding> {
+    let mut findings = Vec::new();
+
+    let has_common_name = cert.subject_name().find_common_name().is_some();
+    let has_alt_names = cert
+        .subject_alt_names()
+        .map_or(false, |names| !names.into_general_names().is_empty());
+    lint_names(has_common_name, has_alt_names, &mut findings);
+    lint_signature_algorithm(cert.signature_algorithm(), &mut findings);
+
+    let validity_secs = (to_chrono(&cert.valid_not_after()) - to_chrono(&cert.valid_not_before()))
+        .num_seconds()
+        .max(0) as u64;
+    // a partial day counts as a whole one
+    lint_validity((validity_secs + SECS_PER_DAY - 1) / SECS_PER_DAY, policy, &mut findings);
+
+    findings
+}
+
+/// Fails with a description of the error-level findings when the policy is enforced.
+pub fn check(findings: &[LintFinding], policy: &LintPolicy) -> Result<(), LintError> {
+    let errors = findings
+        .iter()
+        .filter(|finding| finding.severity == Severity::Error)
+        .cloned()
+        .collect::<Vec<_>>();
+
+    if errors.is_empty() {
+        return Ok(());
+    }
+
+    let error = LintError::Refused { findings: errors };
+    if policy.enforce {
+        Err(error)
+    } else {
+        log::warn!("lint policy not enforced, accepting: {}", error);
+        Ok(())
+    }
+}
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+fn lint_names(has_common_name: bool, has_alt_names: bool, findings: &mut Vec<LintFinding>) {
+    match (has_common_name, has_alt_names) {
+        (_, true) => {}
+        (true, false) => findings.push(LintFinding::new(
+            "cn_only",
+            Severity::Warning,
+            "subject common name without subject alternative names is deprecated".to_owned(),
+        )),
+        (false, false) => findings.push(LintFinding::new(
+            "missing_san",
+            Severity::Error,
+            "no subject alternative name".to_owned(),
+        )),
+    }
+}
+
+fn lint_signature_algorithm(algorithm: &AlgorithmIdentifier, findings: &mut Vec<LintFinding>) {
+    if algorithm.is_a(oids::sha1_with_rsa_encryption()) {
+        findings.push(LintFinding::new(
+            "sha1_signature",
+            Severity::Error,
+            "signed using SHA-1".to_owned(),
+        ));
+    }
+}
+
+fn lint_validity(validity_days: u64, policy: &LintPolicy, findings: &mut Vec<LintFinding>) {
+    if validity_days > policy.max_validity_days {
+        findings.push(LintFinding::new(
+            "validity_too_long",
+            Severity::Error,
+            format!(
+                "validity of {} days exceeds {} days",
+                validity_days, policy.max_validity_days
+            ),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use picky::{
+        key::PrivateKey,
+        pem::Pem,
+        signature::SignatureHashType,
+        x509::{certificate::CertificateBuilder, date::UTCDate, name::DirectoryName},
+    };
+
+    fn private_key() -> PrivateKey {
+        let pem = include_str!("../../test_assets/private_keys/rsa-2048-pk_1.key")
+            .parse::<Pem>()
+            .expect("pem");
+        PrivateKey::from_pem(&pem).expect("private key")
+    }
+
+    fn lints(findings: &[LintFinding]) -> Vec<&'static str> {
+        findings.iter().map(|finding| finding.lint).collect()
+    }
+
+    #[test]
+    fn csr() {
+        let pk = private_key();
+        let policy = LintPolicy::default();
+        let alt_names = AltNames {
+            dns_names: vec!["alt.example.com".to_owned()],
+            ..AltNames::default()
+        };
+
+        let csr = Csr::generate(
+            DirectoryName::new_common_name("leaf.example.com"),
+            &pk,
+            SignatureHashType::RsaSha256,
+        )
+        .expect("csr");
+        assert!(lint_csr(&csr, &alt_names, 365, &policy).is_empty());
+        assert_eq!(
+            lints(&lint_csr(&csr, &AltNames::default(), 365, &policy)),
+            vec!["cn_only"]
+        );
+        check(&lint_csr(&csr, &AltNames::default(), 365, &policy), &policy).expect("warnings are accepted");
+
+        let csr = Csr::generate(DirectoryName::new_empty(), &pk, SignatureHashType::RsaSha1).expect("csr");
+        let findings = lint_csr(&csr, &AltNames::default(), 825, &policy);
+        assert_eq!(
+            lints(&findings),
+            vec!["missing_san", "sha1_signature", "validity_too_long"]
+        );
+
+        check(&findings, &policy).expect("not enforced");
+        let enforced = LintPolicy {
+            enforce: true,
+            ..LintPolicy::default()
+        };
+        let LintError::Refused { findings: refused } = check(&findings, &enforced).unwrap_err();
+        assert_eq!(
+            lints(&refused),
+            vec!["missing_san", "sha1_signature", "validity_too_long"]
+        );
+        assert_eq!(
+            check(&findings, &enforced).unwrap_err().to_string(),
+            "no subject alternative name, signed using SHA-1, validity of 825 days exceeds 398 days"
+        );
+    }
+
+    #[test]
+    fn cert() {
+        let pk = private_key();
+        let policy = LintPolicy::default();
+
+        let cert = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2020, 1, 1).unwrap(), UTCDate::ymd(2023, 1, 1).unwrap())
+            .self_signed(DirectoryName::new_common_name("leaf.example.com"), &pk)
+            .signature_hash_type(SignatureHashType::RsaSha1)
+            .build()
+            .expect("cert");
+        assert_eq!(
+            lints(&lint_cert(&cert, &policy)),
+            vec!["cn_only", "sha1_signature", "validity_too_long"]
+        );
+
+        let relaxed = LintPolicy {
+            max_validity_days: 1100,
+            enforce: true,
+        };
+        assert_eq!(lints(&lint_cert(&cert, &relaxed)), vec!["cn_only", "sha1_signature"]);
+    }
+}
@@ -1,4 +1,4 @@
-use crate::config::Config as ServerConfig;
+use crate::{config::Config as ServerConfig, http::request_log::REQUEST_LOG_TARGET};
 use log::LevelFilter;
 use log4rs::{config::Config as LoggerConfig, Handle};
 
@@ -9,11 +9,37 @@ pub fn init_logs(config: &ServerConfig) -> Handle {
 
 pub fn build_logger_config(config: &ServerConfig) -> Result<LoggerConfig, log4rs::config::Errors> {
     use log4rs::{
-        append::console::ConsoleAppender,
+        append::{console::ConsoleAppender, file::FileAppender},
         config::{Appender, Logger, Root},
+        encode::pattern::PatternEncoder,
     };
 
-    LoggerConfig::builder()
+    let mut builder = LoggerConfig::builder();
+
+    // request events are JSON lines kept apart from application logs
+    if let Some(request_log) = &config.request_log {
+        let encoder = || Box::new(PatternEncoder::new("{m}{n}"));
+        let appender: Box<dyn log4rs::append::Append> = match &request_log.path {
+            Some(path) => match FileAppender::builder().encoder(encoder()).build(path) {
+                Ok(appender) => Box::new(appender),
+                Err(e) => {
+                    eprintln!("couldn't open request log {}: {}", path.display(), e);
+                    Box::new(ConsoleAppender::builder().encoder(encoder()).build())
+                }
+            },
+            None => Box::new(ConsoleAppender::builder().encoder(encoder()).build()),
+        };
+        builder = builder
+            .appender(Appender::builder().build("requests", appender))
+            .logger(
+                Logger::builder()
+                    .appender("requests")
+                    .additive(false)
+                    .build(REQUEST_LOG_TARGET, LevelFilter::Info),
+            );
+    }
+
+    builder
         .appender(Appender::builder().build("stdout", Box::new(ConsoleAppender::builder().build())))
         .logger(Logger::builder().build("poston", LevelFilter::Off))
         .logger(Logger::builder().build("mio", LevelFilter::Off))
@@ -1,15 +1,64 @@
+#![cfg_attr(all(test, feature = "bench"), feature(test))]
+
+mod acme;
 mod addressing;
+mod alt_names;
+mod audit;
+mod cdn;
+mod cert_cache;
 mod config;
+mod crl;
+mod ct_monitor;
 mod db;
+#[cfg(feature = "deterministic")]
+pub mod deterministic;
+mod hierarchy;
 mod http;
+mod issuance_log;
+mod key_usage;
+mod labels;
+mod ldap;
+mod lint;
 mod logging;
+mod notifier;
+mod ocsp;
+mod offline;
 mod picky_controller;
+mod profiles;
+mod random;
+mod self_test;
+mod signer;
+mod spool;
+mod timings;
 mod utils;
 
 use crate::{config::Config, http::http_server::HttpServer};
+use clap::App;
 
 fn main() {
-    let conf = Config::startup_init();
+    let yaml = clap::load_yaml!("cli.yml");
+    let matches = App::from_yaml(yaml).get_matches();
+
+    if let Some(matches) = matches.subcommand_matches("sign-intermediate") {
+        if let Err(e) = offline::sign_intermediate(matches) {
+            eprintln!("couldn't sign intermediate CA: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("check-config") {
+        match Config::check(matches) {
+            Ok(effective_config) => print!("{}", effective_config),
+            Err(e) => {
+                eprintln!("invalid config: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let conf = Config::startup_init(&matches);
     let log_handle = logging::init_logs(&conf);
 
     log::info!("building http server ...");
@@ -0,0 +1,353 @@
+use crate::{
+    cert_cache,
+    config::{Config, ConfigError},
+    db::{CertificateEntry, PickyStorage, StorageObserver},
+    utils,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use lettre::{
+    smtp::{authentication::Credentials, client::net::ClientTlsParameters, ClientSecurity},
+    SmtpClient, Transport,
+};
+use lettre_email::EmailBuilder;
+use native_tls::TlsConnector;
+use picky::x509::{date::UTCDate, Cert};
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+const CA_EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+const fn default_smtp_port() -> u16 {
+    25
+}
+
+const fn default_expiry_warning_days() -> i64 {
+    30
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// Plain SMTP, refused when credentials are configured
+    None,
+    /// Connection upgraded with STARTTLS, failing if the server doesn't support it
+    StartTls,
+    /// Implicit TLS (port 465)
+    Tls,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SmtpNotifierConfig {
+    pub server: String,
+    #[serde(default = "default_smtp_port")]
+    pub port: u16,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Defaults to STARTTLS when credentials are configured, plain SMTP otherwise
+    #[serde(default)]
+    pub security: Option<SmtpSecurity>,
+    pub from: String,
+    pub to: Vec<String>,
+    /// Operators are notified once a CA certificate expires in less than this number of days
+    #[serde(default = "default_expiry_warning_days")]
+    pub expiry_warning_days: i64,
+}
+
+impl SmtpNotifierConfig {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.has_credentials() && self.security() == SmtpSecurity::None {
+            return Err(ConfigError::PlainSmtpCredentials);
+        }
+
+        Ok(())
+    }
+
+    fn has_credentials(&self) -> bool {
+        self.username.is_some() || self.password.is_some()
+    }
+
+    fn security(&self) -> SmtpSecurity {
+        match self.security {
+            Some(security) => security,
+            None if self.has_credentials() => SmtpSecurity::StartTls,
+            None => SmtpSecurity::None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NotificationEvent {
+    CaExpiring {
+        name: String,
+        days_left: i64,
+    },
+    CaIssued {
+        name: String,
+    },
+    ForeignIssuance {
+        domains: Vec<String>,
+        issuer: String,
+        log: String,
+    },
+}
+
+impl NotificationEvent {
+    fn subject(&self, realm: &str) -> String {
+        match self {
+            NotificationEvent::CaExpiring { name, .. } => format!("[{}] {} is about to expire", realm, name),
+            NotificationEvent::CaIssued { name } => format!("[{}] {} was issued", realm, name),
+            NotificationEvent::ForeignIssuance { .. } => format!("[{}] certificate issued by another CA", realm),
+        }
+    }
+
+    fn body(&self) -> String {
+        match self {
+            NotificationEvent::CaExpiring { name, days_left } if *days_left < 0 => {
+                format!("The certificate of {} expired {} day(s) ago.", name, -days_left)
+            }
+            NotificationEvent::CaExpiring { name, days_left } => {
+                format!("The certificate of {} expires in {} day(s).", name, days_left)
+            }
+            NotificationEvent::CaIssued { name } => format!("A new certificate was issued for {}.", name),
+            NotificationEvent::ForeignIssuance { domains, issuer, log } => format!(
+                "A certificate for {} issued by {} was found in CT log {}.",
+                domains.join(", "),
+                issuer,
+                log
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Snafu)]
+enum NotifierError {
+    /// email couldn't be built from the configured addresses
+    #[snafu(display("couldn't build email: {}", source))]
+    Email { source: lettre_email::error::Error },
+
+    /// notifier settings are invalid
+    #[snafu(display("{}", source))]
+    InvalidConfig { source: ConfigError },
+
+    /// TLS connector couldn't be created
+    #[snafu(display("couldn't create tls connector: {}", source))]
+    TlsConnector { source: native_tls::Error },
+
+    /// SMTP client couldn't be created
+    #[snafu(display("couldn't create smtp client: {}", source))]
+    SmtpClient { source: lettre::smtp::error::Error },
+
+    /// SMTP server didn't accept the email
+    #[snafu(display("couldn't send email: {}", source))]
+    Send { source: lettre::smtp::error::Error },
+}
+
+/// Emails configured operators about the given event. Does nothing if no SMTP notifier is configured.
+pub fn notify(config: &Config, event: NotificationEvent) {
+    if let Some(smtp_config) = &config.smtp_notifier {
+        if let Err(e) = send_mail(smtp_config, &event.subject(&config.realm), &event.body()) {
+            log::error!("couldn't send notification for {:?}: {}", event, e);
+        }
+    }
+}
+
+fn send_mail(config: &SmtpNotifierConfig, subject: &str, body: &str) -> Result<(), NotifierError> {
+    let mut builder = EmailBuilder::new()
+        .from(config.from.as_str())
+        .subject(subject)
+        .text(body);
+    for to in config.to.iter() {
+        builder = builder.to(to.as_str());
+    }
+    let email = builder.build().map_err(|source| NotifierError::Email { source })?;
+
+    config
+        .validate()
+        .map_err(|source| NotifierError::InvalidConfig { source })?;
+    let security = match config.security() {
+        SmtpSecurity::None => ClientSecurity::None,
+        security => {
+            let connector = TlsConnector::new().map_err(|source| NotifierError::TlsConnector { source })?;
+            let tls_parameters = ClientTlsParameters::new(config.server.clone(), connector);
+            if security == SmtpSecurity::Tls {
+                ClientSecurity::Wrapper(tls_parameters)
+            } else {
+                ClientSecurity::Required(tls_parameters)
+            }
+        }
+    };
+
+    let mut client = SmtpClient::new((config.server.as_str(), config.port), security)
+        .map_err(|source| NotifierError::SmtpClient { source })?;
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        client = client.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+
+    client
+        .transport()
+        .send(email.into())
+        .map_err(|source| NotifierError::Send { source })?;
+
+    Ok(())
+}
+
+pub fn to_chrono(date: &UTCDate) -> DateTime<Utc> {
+    Utc.ymd(i32::from(date.year()), u32::from(date.month()), u32::from(date.day()))
+        .and_hms(
+            u32::from(date.hour()),
+            u32::from(date.minute()),
+            u32::from(date.second()),
+        )
+}
+
+fn days_left(cert: &Cert, now: DateTime<Utc>) -> i64 {
+    (to_chrono(&cert.valid_not_after()) - now).num_days()
+}
+
+/// Checks root and intermediate CA certificates and notifies operators of the ones about to expire.
+pub fn check_ca_expiry(config: &Config, storage: &dyn PickyStorage) {
+    let smtp_config = match &config.smtp_notifier {
+        Some(smtp_config) => smtp_config,
+        None => return,
+    };
+
+    let now = utils::now();
+    for name in [
+        format!("{} Root CA", config.realm),
+        format!("{} Authority", config.realm),
+    ]
+    .iter()
+    {
+        let cert = match storage
+            .get_addressing_hash_by_name(name)
+            .and_then(|hash| storage.get_cert_by_addressing_hash(&hash))
+        {
+            Ok(der) => match cert_cache::parse(&der) {
+                Ok(cert) => cert,
+                Err(e) => {
+                    log::error!("couldn't parse {} certificate: {}", name, e);
+                    continue;
+                }
+            },
+            Err(e) => {
+                log::error!("couldn't fetch {} certificate: {}", name, e);
+                continue;
+            }
+        };
+
+        let days_left = days_left(&cert, now);
+        if days_left <= smtp_config.expiry_warning_days {
+            log::warn!("{} certificate expires in {} day(s)", name, days_left);
+            notify(
+                config,
+                NotificationEvent::CaExpiring {
+                    name: name.clone(),
+                    days_left,
+                },
+            );
+        }
+    }
+}
+
+/// Periodically checks CA certificates expiry in a background thread.
+pub fn spawn_ca_expiry_watcher(config: Arc<RwLock<Config>>, storage: Arc<dyn PickyStorage>) {
+    std::thread::spawn(move || loop {
+        let config = config.read().expect("config lock").clone();
+
+        // a single instance of a replicated deployment notifies operators
+        match storage.is_leader() {
+            Ok(true) => check_ca_expiry(&config, storage.as_ref()),
+            Ok(false) => {}
+            Err(e) => log::error!("CA expiry watcher: couldn't check leadership: {}", e),
+        }
+
+        std::thread::sleep(CA_EXPIRY_CHECK_INTERVAL);
+    });
+}
+
+/// Returns the observer notifying operators of new CA certificates, if an SMTP notifier is configured.
+pub fn ca_issuance_notifier(config: &Config) -> Option<Arc<dyn StorageObserver>> {
+    config.smtp_notifier.as_ref().map(|_| {
+        Arc::new(CaIssuanceNotifier {
+            config: Arc::new(config.clone()),
+        }) as Arc<dyn StorageObserver>
+    })
+}
+
+/// Storage observer sending a notification once a new CA certificate and its private key have been saved
+struct CaIssuanceNotifier {
+    config: Arc<Config>,
+}
+
+impl StorageObserver for CaIssuanceNotifier {
+    fn on_insert(&self, _: &dyn PickyStorage, entry: &CertificateEntry) {
+        // private keys are only stored along CA certificates
+        if entry.key.is_none() {
+            return;
+        }
+
+        let config = Arc::clone(&self.config);
+        let event = NotificationEvent::CaIssued {
+            name: entry.name.clone(),
+        };
+        std::thread::spawn(move || notify(&config, event));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expiring_event_body() {
+        let event = NotificationEvent::CaExpiring {
+            name: "Picky Authority".to_owned(),
+            days_left: 12,
+        };
+        assert_eq!(event.subject("Picky"), "[Picky] Picky Authority is about to expire");
+        assert_eq!(event.body(), "The certificate of Picky Authority expires in 12 day(s).");
+
+        let event = NotificationEvent::CaExpiring {
+            name: "Picky Authority".to_owned(),
+            days_left: -3,
+        };
+        assert_eq!(event.body(), "The certificate of Picky Authority expired 3 day(s) ago.");
+    }
+
+    #[test]
+    fn smtp_security() {
+        let mut config: SmtpNotifierConfig = serde_yaml::from_str(
+            "server: smtp.example.com\nfrom: picky@example.com\nto: [ops@example.com]\nusername: picky\npassword: secret\n",
+        )
+        .unwrap();
+        assert_eq!(config.security(), SmtpSecurity::StartTls);
+        assert!(config.validate().is_ok());
+
+        config.security = Some(SmtpSecurity::None);
+        assert!(config.validate().is_err());
+
+        config.security = Some(SmtpSecurity::Tls);
+        assert!(config.validate().is_ok());
+
+        config.security = None;
+        config.username = None;
+        config.password = None;
+        assert_eq!(config.security(), SmtpSecurity::None);
+        assert!(config.validate().is_ok());
+
+        let security: SmtpSecurity = serde_yaml::from_str("starttls").unwrap();
+        assert_eq!(security, SmtpSecurity::StartTls);
+    }
+
+    #[test]
+    fn utc_date_to_chrono() {
+        let date = UTCDate::new(2020, 2, 29, 13, 37, 42).unwrap();
+        assert_eq!(to_chrono(&date), Utc.ymd(2020, 2, 29).and_hms(13, 37, 42));
+    }
+}
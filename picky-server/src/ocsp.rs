@@ -0,0 +1,264 @@
+//! OCSP responder (RFC6960) for the certificates issued by the issuing intermediate CAs.
+//!
+//! Responses are signed by the intermediate CA itself and reflect the revocations in storage: a
+//! certificate of the intermediate CA which isn't revoked is reported as good, any other as unknown.
+//! A single responder answers for the default intermediate CA and those of `issuers`.
+
+use crate::{
+    cert_cache,
+    config::{Config, ConfigError},
+    crl,
+    crl::CrlGenerationError,
+    db::{PickyStorage, StorageError},
+    http::utils::{percent_decode, PercentDecodingError},
+    key_usage,
+    signer::{CaSigner, SignerError},
+    utils,
+};
+use chrono::{Duration, TimeZone, Utc};
+use picky::x509::{
+    certificate::CertError,
+    date::UTCDate,
+    extension::CrlReason,
+    ocsp::{CertId, CertStatus, OcspError, OcspRequest, OcspResponse, OcspResponseStatus, SingleResponse},
+    Cert,
+};
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+
+const fn default_validity_secs() -> u64 {
+    60 * 60
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OcspConfig {
+    /// Delay between the production of a response and its announced next update, also used as
+    /// max-age of the responses to GET requests
+    #[serde(default = "default_validity_secs")]
+    pub validity_secs: u64,
+}
+
+impl Default for OcspConfig {
+    fn default() -> Self {
+        Self {
+            validity_secs: default_validity_secs(),
+        }
+    }
+}
+
+impl OcspConfig {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.validity_secs == 0 {
+            return Err(ConfigError::ValueTooSmall {
+                field: "validity_secs",
+                min: 1,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Snafu)]
+pub enum OcspRequestError {
+    /// URL encoding of a GET request is invalid
+    #[snafu(display("{}", source))]
+    PercentEncoding { source: PercentDecodingError },
+
+    /// base64 encoding of a GET request is invalid
+    #[snafu(display("invalid base64: {}", source))]
+    Base64 { source: base64::DecodeError },
+}
+
+#[derive(Debug, Snafu)]
+enum OcspResponderError {
+    /// issuer isn't configured
+    #[snafu(display("{}", source))]
+    UnknownIssuer { source: ConfigError },
+
+    /// storage backend failed
+    #[snafu(display("{}: {}", context, source))]
+    Storage { context: String, source: StorageError },
+
+    /// stored CA certificate couldn't be parsed
+    #[snafu(display("couldn't deserialize CA cert: {}", source))]
+    InvalidCaCert { source: CertError },
+
+    /// CA certificate has no subject key identifier
+    #[snafu(display("{}", source))]
+    CaKeyIdentifier { source: CrlGenerationError },
+
+    /// response couldn't be signed
+    #[snafu(display("{}", source))]
+    Signing { source: SignerError },
+
+    /// response couldn't be encoded
+    #[snafu(display("couldn't encode OCSP response: {}", source))]
+    Encoding { source: OcspError },
+}
+
+/// Answers a DER-encoded OCSP request. Failures are reported by the response status.
+pub fn respond(config: &Config, storage: &dyn PickyStorage, signer: &CaSigner, request_der: &[u8]) -> OcspResponse {
+    let request = match OcspRequest::from_der(request_der) {
+        Ok(request) if request.cert_ids().next().is_some() => request,
+        Ok(_) => {
+            log::debug!("OCSP request without any certificate");
+            return OcspResponse::new_error(OcspResponseStatus::MalformedRequest);
+        }
+        Err(e) => {
+            log::debug!("malformed OCSP request: {}", e);
+            return OcspResponse::new_error(OcspResponseStatus::MalformedRequest);
+        }
+    };
+
+    match sign_response(config, storage, signer, &request) {
+        Ok(response) => response,
+        Err(e) => {
+            log::error!("couldn't answer OCSP request: {}", e);
+            OcspResponse::new_error(OcspResponseStatus::InternalError)
+        }
+    }
+}
+
+/// Decodes the request of a GET, i.e. the url-encoding of its base64 encoding (RFC6960 appendix A).
+pub fn decode_get_request(encoded: &str) -> Result<Vec<u8>, OcspRequestError> {
+    let unescaped = percent_decode(encoded).map_err(|source| OcspRequestError::PercentEncoding { source })?;
+
+    // some clients use the URL-safe alphabet instead of escaping
+    base64::decode(&unescaped)
+        .or_else(|_| base64::decode_config(&unescaped, base64::URL_SAFE))
+        .map_err(|source| OcspRequestError::Base64 { source })
+}
+
+fn sign_response(
+    config: &Config,
+    storage: &dyn PickyStorage,
+    signer: &CaSigner,
+    request: &OcspRequest,
+) -> Result<OcspResponse, OcspResponderError> {
+    // the response is signed by the issuing CA of the first known certificate, the status of
+    // certificates of other CAs is unknown
+    let mut issuing_ca = None;
+    for issuer in config.all_issuers() {
+        let ca_name = config
+            .issuer_ca_name(issuer)
+            .map_err(|source| OcspResponderError::UnknownIssuer { source })?;
+        let ca_hash = storage
+            .get_addressing_hash_by_name(&ca_name)
+            .map_err(|source| OcspResponderError::Storage {
+                context: format!("couldn't fetch CA {}", ca_name),
+                source,
+            })?;
+        let ca_cert_der =
+            storage
+                .get_cert_by_addressing_hash(&ca_hash)
+                .map_err(|source| OcspResponderError::Storage {
+                    context: "couldn't get CA cert der".to_owned(),
+                    source,
+                })?;
+        let ca_cert = cert_cache::parse(&ca_cert_der).map_err(|source| OcspResponderError::InvalidCaCert { source })?;
+        if request.cert_ids().any(|cert_id| is_issued_by(cert_id, &ca_cert)) {
+            issuing_ca = Some((ca_name, ca_hash, ca_cert));
+            break;
+        }
+    }
+
+    let (ca_name, ca_hash, ca_cert) = match issuing_ca {
+        Some(issuing_ca) => issuing_ca,
+        None => return Ok(OcspResponse::new_error(OcspResponseStatus::Unauthorized)),
+    };
+    let ca_key_identifier =
+        crl::ca_key_identifier(&ca_cert).map_err(|source| OcspResponderError::CaKeyIdentifier { source })?;
+
+    let now = utils::now();
+    let this_update = UTCDate::from(now);
+    let next_update = UTCDate::from(now + Duration::seconds(config.ocsp.validity_secs as i64));
+
+    let mut responses = Vec::new();
+    for cert_id in request.cert_ids() {
+        let cert_status = if is_issued_by(cert_id, &ca_cert) {
+            cert_status(storage, &ca_key_identifier, cert_id)?
+        } else {
+            CertStatus::Unknown
+        };
+
+        responses.push(
+            SingleResponse::new(cert_id.clone(), cert_status, this_update.clone())
+                .with_next_update(next_update.clone()),
+        );
+    }
+
+    let basic_response = signer
+        .sign_ocsp_response(&ca_hash, ca_cert.as_ref().clone(), |builder| {
+            builder
+                .produced_at(this_update)
+                .signature_hash_type(config.issuer_leaf_signing_algorithm(&ca_name));
+            if let Some(nonce) = request.nonce() {
+                builder.nonce(nonce);
+            }
+            for response in responses {
+                builder.response(response);
+            }
+        })
+        .map_err(|source| OcspResponderError::Signing { source })?;
+    // responses aren't stored, the storage observer counting signatures doesn't see them
+    key_usage::record_signature(storage, &ca_cert);
+
+    OcspResponse::new_successful(&basic_response).map_err(|source| OcspResponderError::Encoding { source })
+}
+
+/// Certificates identified using an unsupported hash algorithm are unknown as well.
+fn is_issued_by(cert_id: &CertId, ca_cert: &Cert) -> bool {
+    cert_id.is_issued_by(ca_cert).unwrap_or(false)
+}
+
+fn cert_status(
+    storage: &dyn PickyStorage,
+    ca_key_identifier: &str,
+    cert_id: &CertId,
+) -> Result<CertStatus, OcspResponderError> {
+    let serial_number = hex::encode(cert_id.serial_number().as_unsigned_bytes_be());
+    let revocation = storage
+        .get_revocation(ca_key_identifier, &serial_number)
+        .map_err(|source| OcspResponderError::Storage {
+            context: format!("couldn't fetch revocation of {}", serial_number),
+            source,
+        })?;
+
+    Ok(match revocation {
+        Some(entry) => CertStatus::Revoked {
+            revocation_time: UTCDate::from(Utc.timestamp(entry.revoked_at as i64, 0)),
+            reason: entry.reason.and_then(CrlReason::from_code),
+        },
+        None => CertStatus::Good,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_request_decoding() {
+        assert_eq!(
+            decode_get_request("MAMCAQA%3D").unwrap(),
+            vec![0x30, 0x03, 0x02, 0x01, 0x00]
+        );
+        assert_eq!(
+            decode_get_request("MAMCAQA=").unwrap(),
+            vec![0x30, 0x03, 0x02, 0x01, 0x00]
+        );
+        assert_eq!(decode_get_request("%2F%2B8%3D").unwrap(), vec![0xFF, 0xEF]);
+        assert_eq!(decode_get_request("_-8=").unwrap(), vec![0xFF, 0xEF]);
+        assert!(matches!(
+            decode_get_request("MAMCAQA%3"),
+            Err(OcspRequestError::PercentEncoding {
+                source: PercentDecodingError::Truncated
+            })
+        ));
+        assert_eq!(
+            decode_get_request("%ZZ").unwrap_err().to_string(),
+            "invalid percent-encoding: %ZZ"
+        );
+    }
+}
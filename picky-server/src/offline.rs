@@ -0,0 +1,148 @@
+use crate::{
+    config::{selected_overlay, Config, ConfigError},
+    picky_controller::{IssuerOptions, Picky, PickyError},
+};
+use clap::ArgMatches;
+use picky::{
+    key::{KeyError, PrivateKey},
+    pem::{Pem, PemError},
+    x509::{certificate::CertError, csr::CsrError, Cert, Csr},
+};
+use snafu::Snafu;
+use std::io;
+
+#[derive(Debug, Snafu)]
+pub enum OfflineSigningError {
+    /// settings are invalid
+    #[snafu(display("{}", source))]
+    InvalidConfig { source: ConfigError },
+
+    /// input file couldn't be read
+    #[snafu(display("couldn't read {} '{}': {}", what, path, source))]
+    ReadFile {
+        what: &'static str,
+        path: String,
+        source: io::Error,
+    },
+
+    /// input file isn't PEM
+    #[snafu(display("couldn't parse {} pem: {}", what, source))]
+    InvalidPem { what: &'static str, source: PemError },
+
+    /// root CA certificate couldn't be decoded
+    #[snafu(display("couldn't parse root CA cert: {}", source))]
+    InvalidRootCert { source: CertError },
+
+    /// root CA key couldn't be decoded
+    #[snafu(display("couldn't parse root CA key: {}", source))]
+    InvalidRootKey { source: KeyError },
+
+    /// intermediate CSR couldn't be decoded
+    #[snafu(display("couldn't parse intermediate CSR: {}", source))]
+    InvalidCsr { source: CsrError },
+
+    /// root CA certificate isn't the one of the configured realm
+    #[snafu(display("unexpected root CA subject name: {} ; expected: CN={}", actual, expected))]
+    UnexpectedSubjectName { expected: String, actual: String },
+
+    /// root CA key isn't the one certified by the root CA certificate
+    #[snafu(display("root CA key doesn't match root CA certificate"))]
+    KeyMismatch,
+
+    /// intermediate CSR isn't signed by the key it requests a certificate for
+    #[snafu(display("couldn't verify intermediate CSR signature: {}", source))]
+    CsrSignature { source: CsrError },
+
+    /// intermediate CA certificate couldn't be signed
+    #[snafu(display("couldn't generate intermediate certificate: {}", source))]
+    Generation { source: PickyError },
+
+    /// intermediate CA certificate couldn't be encoded
+    #[snafu(display("couldn't encode intermediate certificate to pem: {}", source))]
+    Encoding { source: CertError },
+
+    /// output file couldn't be written
+    #[snafu(display("couldn't write '{}': {}", path, source))]
+    WriteFile { path: String, source: io::Error },
+}
+
+fn read_pem(path: &str, what: &'static str) -> Result<Pem<'static>, OfflineSigningError> {
+    std::fs::read_to_string(path)
+        .map_err(|source| OfflineSigningError::ReadFile {
+            what,
+            path: path.to_owned(),
+            source,
+        })?
+        .parse::<Pem>()
+        .map_err(|source| OfflineSigningError::InvalidPem { what, source })
+}
+
+/// Signs the intermediate CA certificate using the root CA key kept outside of picky storage.
+///
+/// The resulting certificate is then provided to the online server (along with the
+/// intermediate private key) through the regular `intermediate` settings.
+pub fn sign_intermediate(matches: &ArgMatches) -> Result<(), OfflineSigningError> {
+    let mut config = Config::init(selected_overlay(matches).as_deref()).unwrap_or_default();
+    if let Some(realm) = matches.value_of("realm") {
+        config.realm = realm.to_owned();
+    }
+    config
+        .validate_settings()
+        .map_err(|source| OfflineSigningError::InvalidConfig { source })?;
+
+    // these are required by the cli definition
+    let root_cert_path = matches.value_of("root-cert").expect("root-cert argument");
+    let root_key_path = matches.value_of("root-key").expect("root-key argument");
+    let csr_path = matches.value_of("csr").expect("csr argument");
+
+    let root_cert = Cert::from_pem(&read_pem(root_cert_path, "root CA cert")?)
+        .map_err(|source| OfflineSigningError::InvalidRootCert { source })?;
+    let root_key = PrivateKey::from_pem(&read_pem(root_key_path, "root CA key")?)
+        .map_err(|source| OfflineSigningError::InvalidRootKey { source })?;
+    let csr = Csr::from_pem(&read_pem(csr_path, "intermediate CSR")?)
+        .map_err(|source| OfflineSigningError::InvalidCsr { source })?;
+
+    let root_name = format!("{} Root CA", config.realm);
+    match root_cert.subject_name().find_common_name() {
+        Some(name) if name.to_string() == root_name => {}
+        _ => {
+            return Err(OfflineSigningError::UnexpectedSubjectName {
+                expected: root_name,
+                actual: root_cert.subject_name().to_string(),
+            })
+        }
+    }
+
+    if root_cert.public_key() != &root_key.to_public_key() {
+        return Err(OfflineSigningError::KeyMismatch);
+    }
+
+    csr.verify()
+        .map_err(|source| OfflineSigningError::CsrSignature { source })?;
+
+    let (_, intermediate_key) = csr.into_subject_infos();
+    let intermediate_cert = Picky::generate_intermediate(
+        &format!("{} Authority", config.realm),
+        intermediate_key,
+        &root_cert,
+        &root_key,
+        config.intermediate_signing_algorithm(),
+        IssuerOptions::intermediate(),
+    )
+    .map_err(|source| OfflineSigningError::Generation { source })?;
+
+    let pem = intermediate_cert
+        .to_pem()
+        .map_err(|source| OfflineSigningError::Encoding { source })?
+        .to_string();
+
+    match matches.value_of("output") {
+        Some(path) => std::fs::write(path, pem).map_err(|source| OfflineSigningError::WriteFile {
+            path: path.to_owned(),
+            source,
+        })?,
+        None => println!("{}", pem),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,241 @@
+//! Certificate profiles: named shapes of leaf certificates (e.g. `server-tls`, `client-auth`,
+//! `code-signing`) selected per request on `/sign`, with the `profile` query parameter or the
+//! `profile` claim of the bearer token.
+//!
+//! Requests without profile are issued the default leaf shape (see `IssuerOptions::leaf`).
+
+use crate::{
+    alt_names::AltNamePolicy,
+    config::{Config, ConfigError},
+    picky_controller::{IssuerOptions, LEAF_DURATION_DAYS},
+};
+use oid::ObjectIdentifier;
+use picky::x509::extension::{KeyPurpose, KeyUsage};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyUsageFlag {
+    DigitalSignature,
+    ContentCommitment,
+    KeyEncipherment,
+    DataEncipherment,
+    KeyAgreement,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CertificateProfile {
+    #[serde(default = "default_key_usage")]
+    pub key_usage: Vec<KeyUsageFlag>,
+    /// Extended key usages by name (`server_auth`, `client_auth`, `code_signing`, `email_protection`,
+    /// `time_stamping`, `ocsp_signing`) or dotted OID, no extended key usage extension if empty
+    #[serde(default = "default_extended_key_usages")]
+    pub extended_key_usages: Vec<String>,
+    /// Subject alternative names allowed in certificates of this profile, `alt_name_policy` if unset
+    #[serde(default)]
+    pub alt_name_policy: Option<AltNamePolicy>,
+    /// Copy the subject common name into the subject alternative names (as a DNS name or IP address)
+    #[serde(default = "default_true")]
+    pub common_name_alt_name: bool,
+    #[serde(default = "default_validity_days")]
+    pub validity_days: u32,
+    /// Intermediate CA issuing certificates of this profile (one of `issuers`), the default one if unset
+    #[serde(default)]
+    pub issuer: Option<String>,
+}
+
+fn default_key_usage() -> Vec<KeyUsageFlag> {
+    vec![KeyUsageFlag::DigitalSignature, KeyUsageFlag::KeyEncipherment]
+}
+
+fn default_extended_key_usages() -> Vec<String> {
+    vec!["server_auth".to_owned(), "client_auth".to_owned()]
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_validity_days() -> u32 {
+    LEAF_DURATION_DAYS as u32
+}
+
+impl CertificateProfile {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.key_usage.is_empty() {
+            return Err(ConfigError::EmptyList { field: "key_usage" });
+        }
+
+        if self.validity_days == 0 {
+            return Err(ConfigError::ValueTooSmall {
+                field: "validity_days",
+                min: 1,
+            });
+        }
+
+        self.extended_key_usage_oids()?;
+
+        if let Some(alt_name_policy) = &self.alt_name_policy {
+            alt_name_policy
+                .validate()
+                .map_err(|source| ConfigError::InvalidAltNamePolicy {
+                    field: "alt_name_policy".to_owned(),
+                    source,
+                })?;
+        }
+
+        Ok(())
+    }
+
+    pub fn key_usage(&self) -> KeyUsage {
+        self.key_usage
+            .iter()
+            .fold(KeyUsage::builder(), |builder, flag| match flag {
+                KeyUsageFlag::DigitalSignature => builder.digital_signature(),
+                KeyUsageFlag::ContentCommitment => builder.content_commitment(),
+                KeyUsageFlag::KeyEncipherment => builder.key_encipherment(),
+                KeyUsageFlag::DataEncipherment => builder.data_encipherment(),
+                KeyUsageFlag::KeyAgreement => builder.key_agreement(),
+            })
+            .build()
+    }
+
+    pub fn extended_key_usage_oids(&self) -> Result<Vec<ObjectIdentifier>, ConfigError> {
+        self.extended_key_usages
+            .iter()
+            .map(|name| {
+                let purpose = match name.as_str() {
+                    "server_auth" => KeyPurpose::ServerAuth,
+                    "client_auth" => KeyPurpose::ClientAuth,
+                    "code_signing" => KeyPurpose::CodeSigning,
+                    "email_protection" => KeyPurpose::EmailProtection,
+                    "time_stamping" => KeyPurpose::TimeStamping,
+                    "ocsp_signing" => KeyPurpose::OcspSigning,
+                    dotted => {
+                        return ObjectIdentifier::try_from(dotted)
+                            .map_err(|_| ConfigError::UnknownExtendedKeyUsage { name: name.clone() })
+                    }
+                };
+                Ok(purpose.oid())
+            })
+            .collect()
+    }
+
+    /// Options of a leaf certificate of this profile, besides its names, serial number and URLs.
+    pub fn issuer_options<'a>(&self) -> Result<IssuerOptions<'a>, ConfigError> {
+        Ok(IssuerOptions {
+            validity: chrono::Duration::days(i64::from(self.validity_days)),
+            key_usage: self.key_usage(),
+            extended_key_usages: self.extended_key_usage_oids()?,
+            ..IssuerOptions::leaf()
+        })
+    }
+}
+
+/// The profile named `name`, `None` for the default leaf shape.
+pub fn select<'a>(config: &'a Config, name: Option<&str>) -> Result<Option<&'a CertificateProfile>, ConfigError> {
+    match name {
+        Some(name) => config
+            .profiles
+            .get(name)
+            .map(Some)
+            .ok_or_else(|| ConfigError::UnknownProfile { name: name.to_owned() }),
+        None => Ok(None),
+    }
+}
+
+/// Subject alternative names allowed by `profile`, the global policy for the default leaf shape.
+pub fn alt_name_policy<'a>(config: &'a Config, profile: Option<&'a CertificateProfile>) -> &'a AltNamePolicy {
+    profile
+        .and_then(|profile| profile.alt_name_policy.as_ref())
+        .unwrap_or(&config.alt_name_policy)
+}
+
+/// Validity in days of the certificates of `profile`, checked by the lint policy before signing.
+pub fn validity_days(profile: Option<&CertificateProfile>) -> u64 {
+    profile.map_or(LEAF_DURATION_DAYS as u64, |profile| u64::from(profile.validity_days))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use picky::oids;
+
+    #[test]
+    fn profile_from_yaml() {
+        let profile: CertificateProfile = serde_yaml::from_str(
+            r#"
+key_usage: [digital_signature]
+extended_key_usages: [code_signing, 1.3.6.1.4.1.311.10.3.13]
+common_name_alt_name: false
+validity_days: 90
+"#,
+        )
+        .expect("profile");
+        profile.validate().expect("valid profile");
+
+        let key_usage = profile.key_usage();
+        assert!(key_usage.digital_signature());
+        assert!(!key_usage.key_encipherment());
+        assert_eq!(
+            profile.extended_key_usage_oids().unwrap(),
+            vec![
+                oids::kp_code_signing(),
+                ObjectIdentifier::try_from("1.3.6.1.4.1.311.10.3.13").unwrap()
+            ]
+        );
+
+        let options = profile.issuer_options().unwrap();
+        assert_eq!(options.validity, chrono::Duration::days(90));
+
+        let default: CertificateProfile = serde_yaml::from_str("{}").expect("default profile");
+        default.validate().expect("valid default profile");
+        assert_eq!(
+            default.extended_key_usage_oids().unwrap(),
+            vec![oids::kp_server_auth(), oids::kp_client_auth()]
+        );
+        assert!(default.common_name_alt_name);
+    }
+
+    #[test]
+    fn profile_validation() {
+        let mut profile: CertificateProfile = serde_yaml::from_str("{}").unwrap();
+        profile.extended_key_usages = vec!["document_signing".to_owned()];
+        assert!(matches!(
+            profile.validate(),
+            Err(ConfigError::UnknownExtendedKeyUsage { name }) if name == "document_signing"
+        ));
+
+        profile.extended_key_usages = Vec::new();
+        profile.validity_days = 0;
+        assert!(matches!(
+            profile.validate(),
+            Err(ConfigError::ValueTooSmall {
+                field: "validity_days",
+                min: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn profile_selection() {
+        let mut config = Config::default();
+        let mut profile: CertificateProfile = serde_yaml::from_str("{}").unwrap();
+        profile.alt_name_policy = Some(AltNamePolicy {
+            ip_ranges: vec!["10.0.0.0/8".to_owned()],
+            ..AltNamePolicy::default()
+        });
+        config.profiles.insert("server-tls".to_owned(), profile);
+
+        assert!(select(&config, None).unwrap().is_none());
+        let profile = select(&config, Some("server-tls")).unwrap();
+        assert!(profile.is_some());
+        assert_eq!(alt_name_policy(&config, profile).ip_ranges, vec!["10.0.0.0/8"]);
+        assert!(alt_name_policy(&config, None).ip_ranges.is_empty());
+        assert_eq!(
+            select(&config, Some("client-auth")).err().expect("unknown profile"),
+            "unknown certificate profile 'client-auth'"
+        );
+    }
+}
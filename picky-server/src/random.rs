@@ -0,0 +1,164 @@
+//! Random source used to generate private keys and serial numbers.
+//!
+//! Defaults to the operating system generator. Deployments may read from a hardware TRNG or an
+//! entropy daemon instead, and tests may replay a recorded stream of bytes.
+
+use rand::{
+    rngs::{OsRng, StdRng},
+    CryptoRng, Error, RngCore, SeedableRng,
+};
+use snafu::Snafu;
+use std::{
+    fs::File,
+    io::{self, ErrorKind, Read},
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+#[derive(Debug, Snafu)]
+pub enum RandomSourceError {
+    /// device or file couldn't be opened
+    #[snafu(display("couldn't open random source {}: {}", path.display(), source))]
+    Open { path: PathBuf, source: io::Error },
+
+    /// device or file metadata couldn't be read
+    #[snafu(display("couldn't stat random source {}: {}", path.display(), source))]
+    Stat { path: PathBuf, source: io::Error },
+}
+
+pub trait RandomSource: RngCore + CryptoRng + Send {}
+
+impl<T: RngCore + CryptoRng + Send> RandomSource for T {}
+
+lazy_static::lazy_static! {
+    static ref SOURCE: Mutex<Box<dyn RandomSource>> = Mutex::new(Box::new(OsRng));
+}
+
+/// Replaces the random source for the whole process.
+pub fn set_random_source(source: Box<dyn RandomSource>) {
+    *lock_source() = source;
+}
+
+pub(crate) fn with_rng<F, T>(f: F) -> T
+where
+    F: FnOnce(&mut dyn RandomSource) -> T,
+{
+    let mut source = lock_source();
+    f(source.as_mut())
+}
+
+/// Generator seeded from the random source, for long operations (e.g. private key generation)
+/// which shouldn't hold the source for their whole duration.
+pub(crate) fn seeded_rng() -> Result<StdRng, Error> {
+    with_rng(|source| StdRng::from_rng(source))
+}
+
+fn lock_source() -> MutexGuard<'static, Box<dyn RandomSource>> {
+    // a panicking source (e.g. an unreadable device) doesn't leave the mutex in an inconsistent state
+    SOURCE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Reads random bytes from a device or a file (e.g. `/dev/hwrng`, a FIFO fed by an entropy daemon or a recording).
+///
+/// The source is trusted to be cryptographically secure. A FIFO is reopened when its writer closes
+/// it, a regular file is exhausted at its end.
+pub struct DeviceRng {
+    path: PathBuf,
+    file: File,
+    reopenable: bool,
+}
+
+impl DeviceRng {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, RandomSourceError> {
+        let path = path.as_ref().to_path_buf();
+        let file = File::open(&path).map_err(|source| RandomSourceError::Open {
+            path: path.clone(),
+            source,
+        })?;
+        let reopenable = !file
+            .metadata()
+            .map_err(|source| RandomSourceError::Stat {
+                path: path.clone(),
+                source,
+            })?
+            .is_file();
+        Ok(Self { path, file, reopenable })
+    }
+
+    fn error(&self, description: impl std::fmt::Display) -> Error {
+        Error::new(format!(
+            "couldn't read random source {}: {}",
+            self.path.display(),
+            description
+        ))
+    }
+}
+
+impl RngCore for DeviceRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut buf = [0; 4];
+        self.fill_bytes(&mut buf);
+        u32::from_le_bytes(buf)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut buf = [0; 8];
+        self.fill_bytes(&mut buf);
+        u64::from_le_bytes(buf)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        // callers which can't handle errors get bytes from the operating system rather than a panic
+        if let Err(e) = self.try_fill_bytes(dest) {
+            log::error!("{}, falling back to the operating system generator", e);
+            OsRng.fill_bytes(dest);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        let mut filled = 0;
+        let mut reopened = false;
+        while filled < dest.len() {
+            match self.file.read(&mut dest[filled..]) {
+                // the writer of a FIFO went away, wait for the next one unless it didn't write anything either
+                Ok(0) if self.reopenable && !reopened => {
+                    self.file = File::open(&self.path).map_err(|e| self.error(e))?;
+                    reopened = true;
+                }
+                Ok(0) => return Err(self.error("source exhausted")),
+                Ok(read) => {
+                    filled += read;
+                    reopened = false;
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(self.error(e)),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl CryptoRng for DeviceRng {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TestDir;
+
+    #[test]
+    fn device_rng_replays_recording() {
+        let dir = TestDir::new("random_source");
+        let path = dir.join("recording");
+        std::fs::write(&path, [0x01, 0x02, 0x03, 0x04, 0x05]).unwrap();
+
+        let mut rng = DeviceRng::open(&path).unwrap();
+        assert_eq!(rng.next_u32(), 0x0403_0201);
+        assert!(rng.try_fill_bytes(&mut [0; 4]).is_err());
+
+        // the remaining byte was consumed by the failed read, the operating system generator takes over
+        let mut bytes = [0; 32];
+        rng.fill_bytes(&mut bytes);
+        assert_ne!(bytes, [0; 32]);
+    }
+}
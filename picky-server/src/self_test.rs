@@ -0,0 +1,203 @@
+//! Power-on self-tests.
+//!
+//! Known-answer tests are run for every hash and signature algorithm the server may use.
+//! ECDSA and HMAC aren't implemented by picky yet and are therefore not covered.
+
+use picky::{
+    key::{KeyError, PrivateKey},
+    pem::{Pem, PemError},
+    signature::{SignatureError, SignatureHashType},
+};
+use serde::Serialize;
+use snafu::Snafu;
+
+const KAT_RSA_KEY: &str = include_str!("../../test_assets/private_keys/rsa-2048-pk_1.key");
+const KAT_RSA_MESSAGE: &[u8] = b"picky self-test";
+const KAT_HASH_MESSAGE: &[u8] = b"abc";
+
+#[derive(Debug, Snafu)]
+pub enum SelfTestError {
+    /// hash of the known message isn't the expected one
+    #[snafu(display("unexpected digest {}", digest))]
+    UnexpectedDigest { digest: String },
+
+    /// known message couldn't be signed
+    #[snafu(display("couldn't sign: {}", source))]
+    Signing { source: SignatureError },
+
+    /// signature of the known message isn't the expected one
+    #[snafu(display("unexpected signature"))]
+    UnexpectedSignature,
+
+    /// known signature doesn't verify
+    #[snafu(display("couldn't verify known signature: {}", source))]
+    Verification { source: SignatureError },
+
+    /// signature verification doesn't detect tampering
+    #[snafu(display("tampered signature was accepted"))]
+    TamperedSignatureAccepted,
+
+    /// known-answer key isn't PEM
+    #[snafu(display("couldn't parse known-answer key pem: {}", source))]
+    KeyPem { source: PemError },
+
+    /// known-answer key couldn't be parsed
+    #[snafu(display("couldn't parse known-answer key: {}", source))]
+    Key { source: KeyError },
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SelfTestResult {
+    pub name: String,
+    pub passed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SelfTestReport {
+    pub passed: bool,
+    pub results: Vec<SelfTestResult>,
+}
+
+impl SelfTestReport {
+    pub fn failures(&self) -> impl Iterator<Item = &SelfTestResult> {
+        self.results.iter().filter(|result| !result.passed)
+    }
+}
+
+fn expected_digest(algorithm: SignatureHashType) -> &'static str {
+    // FIPS 180 examples
+    match algorithm {
+        SignatureHashType::RsaSha1 => "a9993e364706816aba3e25717850c26c9cd0d89d",
+        SignatureHashType::RsaSha224 => "23097d223405d8228642a477bda255b32aadbce4bda0b3f7e36c9da7",
+        SignatureHashType::RsaSha256 => "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+        SignatureHashType::RsaSha384 => {
+            "cb00753f45a35e8bb5a03d699ac65007272c32ab0eded1631a8b605a43ff5bed8086072ba1e7cc2358baeca134c825a7"
+        }
+        SignatureHashType::RsaSha512 => {
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+             2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+        }
+    }
+}
+
+fn expected_signature(algorithm: SignatureHashType) -> &'static [u8] {
+    match algorithm {
+        SignatureHashType::RsaSha1 => include_bytes!("../../test_assets/self_test/rsa-2048-pk_1-sha1.sig"),
+        SignatureHashType::RsaSha224 => include_bytes!("../../test_assets/self_test/rsa-2048-pk_1-sha224.sig"),
+        SignatureHashType::RsaSha256 => include_bytes!("../../test_assets/self_test/rsa-2048-pk_1-sha256.sig"),
+        SignatureHashType::RsaSha384 => include_bytes!("../../test_assets/self_test/rsa-2048-pk_1-sha384.sig"),
+        SignatureHashType::RsaSha512 => include_bytes!("../../test_assets/self_test/rsa-2048-pk_1-sha512.sig"),
+    }
+}
+
+fn hash_kat(algorithm: SignatureHashType) -> Result<(), SelfTestError> {
+    let digest = hex::encode(algorithm.hash(KAT_HASH_MESSAGE));
+    if digest == expected_digest(algorithm) {
+        Ok(())
+    } else {
+        Err(SelfTestError::UnexpectedDigest { digest })
+    }
+}
+
+fn rsa_kat(algorithm: SignatureHashType, key: &PrivateKey) -> Result<(), SelfTestError> {
+    let expected = expected_signature(algorithm);
+
+    let signature = algorithm
+        .sign(KAT_RSA_MESSAGE, key)
+        .map_err(|source| SelfTestError::Signing { source })?;
+    if signature != expected {
+        return Err(SelfTestError::UnexpectedSignature);
+    }
+
+    algorithm
+        .verify(&key.to_public_key(), KAT_RSA_MESSAGE, expected)
+        .map_err(|source| SelfTestError::Verification { source })?;
+
+    let mut tampered = expected.to_vec();
+    tampered[0] ^= 0x01;
+    if algorithm
+        .verify(&key.to_public_key(), KAT_RSA_MESSAGE, &tampered)
+        .is_ok()
+    {
+        return Err(SelfTestError::TamperedSignatureAccepted);
+    }
+
+    Ok(())
+}
+
+fn kat_rsa_key() -> Result<PrivateKey, SelfTestError> {
+    let pem = KAT_RSA_KEY
+        .parse::<Pem>()
+        .map_err(|source| SelfTestError::KeyPem { source })?;
+    PrivateKey::from_pem(&pem).map_err(|source| SelfTestError::Key { source })
+}
+
+/// Runs hash known-answer tests for every supported hash and RSA known-answer tests for each of `signing_algorithms`.
+pub fn run(signing_algorithms: &[SignatureHashType]) -> SelfTestReport {
+    let mut results = Vec::new();
+
+    let mut record = |name: String, outcome: Result<(), SelfTestError>| {
+        results.push(SelfTestResult {
+            name,
+            passed: outcome.is_ok(),
+            detail: outcome.err().map(|e| e.to_string()),
+        })
+    };
+
+    let hashes = [
+        SignatureHashType::RsaSha1,
+        SignatureHashType::RsaSha224,
+        SignatureHashType::RsaSha256,
+        SignatureHashType::RsaSha384,
+        SignatureHashType::RsaSha512,
+    ];
+    for algorithm in hashes.iter() {
+        record(format!("hash {:?}", algorithm), hash_kat(*algorithm));
+    }
+
+    let mut signing_algorithms = signing_algorithms.to_vec();
+    signing_algorithms.sort_by_key(|algorithm| format!("{:?}", algorithm));
+    signing_algorithms.dedup();
+
+    match kat_rsa_key() {
+        Ok(key) => {
+            for algorithm in signing_algorithms {
+                record(format!("signature {:?}", algorithm), rsa_kat(algorithm, &key));
+            }
+        }
+        Err(e) => record("signature key".to_owned(), Err(e)),
+    }
+
+    SelfTestReport {
+        passed: results.iter().all(|result| result.passed),
+        results,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn all_known_answer_tests_pass() {
+        let report = run(&[
+            SignatureHashType::RsaSha1,
+            SignatureHashType::RsaSha224,
+            SignatureHashType::RsaSha256,
+            SignatureHashType::RsaSha384,
+            SignatureHashType::RsaSha512,
+        ]);
+        assert_eq!(report.failures().collect::<Vec<_>>(), Vec::<&SelfTestResult>::new());
+        assert!(report.passed);
+        assert_eq!(report.results.len(), 10);
+    }
+
+    #[test]
+    fn signing_algorithms_are_deduplicated() {
+        let report = run(&[SignatureHashType::RsaSha256, SignatureHashType::RsaSha256]);
+        assert!(report.passed);
+        assert_eq!(report.results.len(), 6);
+    }
+}
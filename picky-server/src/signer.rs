@@ -0,0 +1,266 @@
+//! Signing subsystem, the only holder of the CA private keys.
+//!
+//! `CaSigner` wraps the `PrivateKeyLocker` of the storage backend and only exposes signing operations:
+//! callers hand it the addressing hash of a CA along with what to sign, and private keys never leave
+//! this module.
+//!
+//! When a storage encryption key is configured, private keys are sealed before being stored using
+//! AES-256-CBC and HMAC-SHA256 (encrypt-then-MAC), both keys being derived from the configured secret.
+//! Keys stored before encryption was enabled are still read in clear.
+
+use crate::{
+    db::{PrivateKeyLocker, StorageError},
+    picky_controller::{IssuerOptions, Picky, PickyError},
+};
+use aes::Aes256;
+use block_modes::{block_padding::Pkcs7, BlockMode, Cbc};
+use hmac::{Hmac, Mac};
+use picky::{
+    key::{PrivateKey, PublicKey},
+    signature::{SignatureError, SignatureHashType},
+    x509::{
+        crl::{Crl, CrlBuilder, CrlError},
+        ocsp::{BasicOcspResponse, BasicOcspResponseBuilder, OcspError},
+        Cert, Csr,
+    },
+};
+use rand::RngCore;
+use sha2::Sha256;
+use snafu::Snafu;
+use std::sync::Arc;
+
+/// Prefix of sealed private keys, DER encoded keys always start with a SEQUENCE tag (0x30)
+const SEALED_KEY_MAGIC: &[u8] = b"PICKYSK1";
+const IV_LEN: usize = 16;
+const TAG_LEN: usize = 32;
+
+#[derive(Debug, Snafu)]
+pub enum SignerError {
+    /// private key couldn't be fetched from storage
+    #[snafu(display("couldn't fetch CA private key: {}", source))]
+    KeyUnavailable { source: StorageError },
+
+    /// stored private key couldn't be parsed
+    #[snafu(display("couldn't parse CA private key: {}", source))]
+    InvalidKey { source: PickyError },
+
+    /// certificate couldn't be signed
+    #[snafu(display("couldn't sign certificate: {}", source))]
+    CertificateSigning { source: PickyError },
+
+    /// CRL couldn't be signed
+    #[snafu(display("couldn't sign CRL: {}", source))]
+    CrlSigning { source: CrlError },
+
+    /// OCSP response couldn't be signed
+    #[snafu(display("couldn't sign OCSP response: {}", source))]
+    OcspSigning { source: OcspError },
+
+    /// issuance log tree head couldn't be signed
+    #[snafu(display("couldn't sign tree head: {}", source))]
+    TreeHeadSigning { source: SignatureError },
+
+    /// private key couldn't be sealed before being stored, or opened after being fetched
+    #[snafu(display("CA private key encryption error: {}", reason))]
+    KeyEncryption { reason: String },
+}
+
+/// Keys used to seal private keys, derived from the storage encryption key.
+struct KeyCipher {
+    encryption_key: Vec<u8>,
+    mac_key: Vec<u8>,
+}
+
+impl KeyCipher {
+    fn new(secret: &str) -> Self {
+        Self {
+            encryption_key: hmac_sha256(secret.as_bytes(), b"picky storage encryption"),
+            mac_key: hmac_sha256(secret.as_bytes(), b"picky storage authentication"),
+        }
+    }
+
+    fn seal(&self, key_der: &[u8]) -> Result<Vec<u8>, SignerError> {
+        let mut iv = [0u8; IV_LEN];
+        crate::random::with_rng(|rng| rng.try_fill_bytes(&mut iv)).map_err(|e| SignerError::KeyEncryption {
+            reason: format!("couldn't generate IV: {}", e),
+        })?;
+
+        let ciphertext = Cbc::<Aes256, Pkcs7>::new_var(&self.encryption_key, &iv)
+            .map_err(|e| SignerError::KeyEncryption {
+                reason: format!("invalid cipher parameters: {:?}", e),
+            })?
+            .encrypt_vec(key_der);
+
+        let mut sealed = [SEALED_KEY_MAGIC, &iv[..], &ciphertext[..]].concat();
+        let tag = hmac_sha256(&self.mac_key, &sealed);
+        sealed.extend_from_slice(&tag);
+        Ok(sealed)
+    }
+
+    fn open(&self, sealed: &[u8]) -> Result<Vec<u8>, SignerError> {
+        if sealed.len() < SEALED_KEY_MAGIC.len() + IV_LEN + TAG_LEN {
+            return Err(SignerError::KeyEncryption {
+                reason: "sealed key is truncated".to_owned(),
+            });
+        }
+
+        let (authenticated, tag) = sealed.split_at(sealed.len() - TAG_LEN);
+        let mut mac = Hmac::<Sha256>::new_varkey(&self.mac_key).expect("hmac accepts keys of any size");
+        mac.input(authenticated);
+        mac.verify(tag).map_err(|_| SignerError::KeyEncryption {
+            reason: "sealed key doesn't authenticate, wrong storage encryption key?".to_owned(),
+        })?;
+
+        let (iv, ciphertext) = authenticated[SEALED_KEY_MAGIC.len()..].split_at(IV_LEN);
+        Cbc::<Aes256, Pkcs7>::new_var(&self.encryption_key, iv)
+            .map_err(|e| SignerError::KeyEncryption {
+                reason: format!("invalid cipher parameters: {:?}", e),
+            })?
+            .decrypt_vec(ciphertext)
+            .map_err(|e| SignerError::KeyEncryption {
+                reason: format!("couldn't decrypt sealed key: {:?}", e),
+            })
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_varkey(key).expect("hmac accepts keys of any size");
+    mac.input(data);
+    mac.result().code().to_vec()
+}
+
+pub struct CaSigner {
+    key_locker: Arc<dyn PrivateKeyLocker>,
+    cipher: Option<KeyCipher>,
+}
+
+impl CaSigner {
+    /// Private keys are stored in clear when `storage_encryption_key` is empty.
+    pub fn new(key_locker: Arc<dyn PrivateKeyLocker>, storage_encryption_key: &str) -> Self {
+        let cipher = if storage_encryption_key.is_empty() {
+            None
+        } else {
+            Some(KeyCipher::new(storage_encryption_key))
+        };
+
+        Self { key_locker, cipher }
+    }
+
+    /// Turns a DER encoded private key into what should be given to the storage.
+    pub fn seal_key(&self, key_der: &[u8]) -> Result<Vec<u8>, SignerError> {
+        match &self.cipher {
+            Some(cipher) => cipher.seal(key_der),
+            None => Ok(key_der.to_vec()),
+        }
+    }
+
+    /// Signs a leaf certificate for `csr` with the key of the CA addressed by `ca_hash`.
+    pub fn sign_leaf(
+        &self,
+        ca_hash: &str,
+        ca_cert: &Cert,
+        csr: Csr,
+        signature_hash_type: SignatureHashType,
+        options: IssuerOptions,
+    ) -> Result<Cert, SignerError> {
+        let ca_key = self.ca_key(ca_hash)?;
+        Picky::generate_leaf_from_csr(csr, ca_cert, &ca_key, signature_hash_type, options)
+            .map_err(|source| SignerError::CertificateSigning { source })
+    }
+
+    /// Signs an intermediate CA certificate for `public_key` with the key of the CA addressed by `ca_hash`.
+    pub fn sign_intermediate(
+        &self,
+        ca_hash: &str,
+        ca_cert: &Cert,
+        intermediate_name: &str,
+        public_key: PublicKey,
+        signature_hash_type: SignatureHashType,
+        options: IssuerOptions,
+    ) -> Result<Cert, SignerError> {
+        let ca_key = self.ca_key(ca_hash)?;
+        Picky::generate_intermediate(
+            intermediate_name,
+            public_key,
+            ca_cert,
+            &ca_key,
+            signature_hash_type,
+            options,
+        )
+        .map_err(|source| SignerError::CertificateSigning { source })
+    }
+
+    /// Signs a CRL issued by the CA addressed by `ca_hash`, whose content is set by `configure`.
+    pub fn sign_crl(
+        &self,
+        ca_hash: &str,
+        ca_cert: &Cert,
+        configure: impl FnOnce(&CrlBuilder),
+    ) -> Result<Crl, SignerError> {
+        let ca_key = self.ca_key(ca_hash)?;
+        let builder = Crl::builder();
+        builder.issuer_cert(ca_cert, &ca_key);
+        configure(&builder);
+        builder.build().map_err(|source| SignerError::CrlSigning { source })
+    }
+
+    /// Signs an OCSP response on behalf of the CA addressed by `ca_hash`, whose content is set by `configure`.
+    pub fn sign_ocsp_response(
+        &self,
+        ca_hash: &str,
+        ca_cert: Cert,
+        configure: impl FnOnce(&BasicOcspResponseBuilder),
+    ) -> Result<BasicOcspResponse, SignerError> {
+        let ca_key = self.ca_key(ca_hash)?;
+        let builder = BasicOcspResponse::builder();
+        builder.responder(ca_cert, &ca_key);
+        configure(&builder);
+        builder.build().map_err(|source| SignerError::OcspSigning { source })
+    }
+
+    /// Signs the `TreeHeadSignature` structure of the issuance log with the key of the CA addressed by
+    /// `ca_hash`, see `issuance_log::tree_head_signature_input`.
+    pub fn sign_tree_head(
+        &self,
+        ca_hash: &str,
+        tree_head: &[u8],
+        signature_hash_type: SignatureHashType,
+    ) -> Result<Vec<u8>, SignerError> {
+        let ca_key = self.ca_key(ca_hash)?;
+        signature_hash_type
+            .sign(tree_head, &ca_key)
+            .map_err(|source| SignerError::TreeHeadSigning { source })
+    }
+
+    /// Checks that the private key of the CA addressed by `ca_hash` matches `public_key`.
+    pub fn key_matches(&self, ca_hash: &str, public_key: &PublicKey) -> Result<bool, SignerError> {
+        Ok(self.ca_key(ca_hash)?.to_public_key() == *public_key)
+    }
+
+    /// Checks whether the private key of the CA addressed by `ca_hash` is stored.
+    pub fn has_key(&self, ca_hash: &str) -> bool {
+        self.key_locker.get_key_by_addressing_hash(ca_hash).is_ok()
+    }
+
+    fn ca_key(&self, ca_hash: &str) -> Result<PrivateKey, SignerError> {
+        let key_der = self
+            .key_locker
+            .get_key_by_addressing_hash(ca_hash)
+            .map_err(|source| SignerError::KeyUnavailable { source })?;
+        let key_der = self.open_key(key_der)?;
+        Picky::parse_pk_from_magic_der(&key_der).map_err(|source| SignerError::InvalidKey { source })
+    }
+
+    fn open_key(&self, stored: Vec<u8>) -> Result<Vec<u8>, SignerError> {
+        if !stored.starts_with(SEALED_KEY_MAGIC) {
+            return Ok(stored);
+        }
+
+        match &self.cipher {
+            Some(cipher) => cipher.open(&stored),
+            None => Err(SignerError::KeyEncryption {
+                reason: "key is sealed but no 'storage_encryption_key' is configured".to_owned(),
+            }),
+        }
+    }
+}
@@ -0,0 +1,332 @@
+//! Local durable spool for issued certificates which couldn't be saved because the storage was
+//! unavailable.
+//!
+//! Issuance keeps working during storage outages: spooled certificates are written to disk and
+//! saved to the storage by a background thread once it's reachable again.
+
+use crate::{
+    addressing::{encode_to_canonical_address, AddressingError},
+    config::Config,
+    db::{CertificateEntry, PickyStorage, StorageError},
+    labels::Labels,
+};
+use base64::DecodeError;
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError, RwLock},
+    time::Duration,
+};
+
+const SPOOL_FILE_EXTENSION: &str = "json";
+
+const fn default_retry_interval_secs() -> u64 {
+    30
+}
+
+lazy_static::lazy_static! {
+    // a certificate must not be saved twice by concurrent flushes
+    static ref FLUSH_LOCK: Mutex<()> = Mutex::new(());
+}
+
+#[derive(Debug, Snafu)]
+pub enum SpoolError {
+    /// private keys are never written outside of the storage
+    #[snafu(display("refusing to spool private key of {}", name))]
+    PrivateKey { name: String },
+
+    /// spool directory or one of its files couldn't be accessed
+    #[snafu(display("couldn't {} {}: {}", operation, path.display(), source))]
+    Io {
+        operation: &'static str,
+        path: PathBuf,
+        source: io::Error,
+    },
+
+    /// certificate couldn't be serialized to be spooled
+    #[snafu(display("couldn't serialize spooled certificate {}: {}", name, source))]
+    Serialization { name: String, source: serde_json::Error },
+
+    /// spool file name couldn't be computed
+    #[snafu(display("couldn't compute address of {}: {}", name, source))]
+    Addressing { name: String, source: AddressingError },
+
+    /// spool file isn't a spooled certificate
+    #[snafu(display("couldn't parse {}: {}", path.display(), source))]
+    InvalidFile { path: PathBuf, source: serde_json::Error },
+
+    /// spooled certificate isn't base64 encoded
+    #[snafu(display("couldn't decode {}: {}", path.display(), source))]
+    InvalidCertificate { path: PathBuf, source: DecodeError },
+
+    /// spooled certificate couldn't be saved, the storage is likely still unavailable
+    #[snafu(display("couldn't save spooled certificate {}: {}", name, source))]
+    Storage { name: String, source: StorageError },
+}
+
+fn io_error(operation: &'static str, path: &Path) -> impl FnOnce(io::Error) -> SpoolError {
+    let path = path.to_owned();
+    move |source| SpoolError::Io {
+        operation,
+        path,
+        source,
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StorageSpoolConfig {
+    /// Directory holding the spooled certificates, should be on a persistent volume
+    pub path: PathBuf,
+    /// Delay between two attempts to save the spooled certificates
+    #[serde(default = "default_retry_interval_secs")]
+    pub retry_interval_secs: u64,
+}
+
+/// On-disk form of a spooled `CertificateEntry`
+#[derive(Serialize, Deserialize, Debug)]
+struct SpooledCertificate {
+    name: String,
+    /// Base64-encoded certificate der
+    cert: String,
+    key_identifier: String,
+    #[serde(default)]
+    requested_by: Option<String>,
+    #[serde(default)]
+    labels: Labels,
+}
+
+/// Writes `entry` to the spool, to be saved by `flush` later on.
+///
+/// Entries holding a private key are refused: keys are never written outside of the storage.
+pub fn spool(config: &StorageSpoolConfig, entry: CertificateEntry) -> Result<(), SpoolError> {
+    if entry.key.is_some() {
+        return Err(SpoolError::PrivateKey { name: entry.name });
+    }
+
+    fs::create_dir_all(&config.path).map_err(io_error("create spool directory", &config.path))?;
+
+    let spooled = SpooledCertificate {
+        name: entry.name,
+        cert: base64::encode(&entry.cert),
+        key_identifier: entry.key_identifier,
+        requested_by: entry.requested_by,
+        labels: entry.labels,
+    };
+    let json = serde_json::to_vec(&spooled).map_err(|source| SpoolError::Serialization {
+        name: spooled.name.clone(),
+        source,
+    })?;
+
+    // keyed by certificate, several certificates may be issued for the same key during an outage
+    let address = encode_to_canonical_address(&entry.cert).map_err(|source| SpoolError::Addressing {
+        name: spooled.name.clone(),
+        source,
+    })?;
+
+    // written then renamed so that flush never reads a partial file
+    let path = config.path.join(address).with_extension(SPOOL_FILE_EXTENSION);
+    let tmp_path = path.with_extension("tmp");
+    let mut file = fs::File::create(&tmp_path).map_err(io_error("create", &tmp_path))?;
+    file.write_all(&json)
+        .and_then(|_| file.sync_all())
+        .map_err(io_error("write", &tmp_path))?;
+    fs::rename(&tmp_path, &path).map_err(io_error("move spooled certificate to", &path))?;
+
+    log::info!("certificate {} spooled to {}", spooled.name, path.display());
+    Ok(())
+}
+
+/// Saves the spooled certificates to `storage`, stopping at the first failure since the storage
+/// is likely still unavailable. Returns the number of certificates saved.
+pub fn flush(config: &StorageSpoolConfig, storage: &dyn PickyStorage) -> Result<usize, SpoolError> {
+    let _guard = FLUSH_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+
+    let mut saved = 0;
+    for path in spooled_files(&config.path)? {
+        let json = fs::read(&path).map_err(io_error("read", &path))?;
+        let spooled: SpooledCertificate = serde_json::from_slice(&json).map_err(|source| SpoolError::InvalidFile {
+            path: path.clone(),
+            source,
+        })?;
+        let cert = base64::decode(&spooled.cert).map_err(|source| SpoolError::InvalidCertificate {
+            path: path.clone(),
+            source,
+        })?;
+
+        let name = spooled.name.clone();
+        storage
+            .store(CertificateEntry {
+                name: spooled.name,
+                cert,
+                key_identifier: spooled.key_identifier,
+                key: None,
+                requested_by: spooled.requested_by,
+                labels: spooled.labels,
+            })
+            .map_err(|source| SpoolError::Storage {
+                name: name.clone(),
+                source,
+            })?;
+
+        fs::remove_file(&path).map_err(io_error("remove", &path))?;
+        log::info!("spooled certificate {} saved", name);
+        saved += 1;
+    }
+
+    Ok(saved)
+}
+
+/// Number of certificates waiting in the spool.
+pub fn pending(config: &StorageSpoolConfig) -> Result<usize, SpoolError> {
+    spooled_files(&config.path).map(|files| files.len())
+}
+
+fn spooled_files(dir: &Path) -> Result<Vec<PathBuf>, SpoolError> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut files = fs::read_dir(dir)
+        .map_err(io_error("read spool directory", dir))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .map_or(false, |extension| extension == SPOOL_FILE_EXTENSION)
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    Ok(files)
+}
+
+/// Periodically saves the spooled certificates in a background thread.
+pub fn spawn_spool_flusher(config: Arc<RwLock<Config>>, storage: Arc<dyn PickyStorage>) {
+    std::thread::spawn(move || loop {
+        let spool_config = config.read().expect("config lock").storage_spool.clone();
+        let retry_interval_secs = match spool_config {
+            Some(spool_config) => {
+                // replicas share the spool directory, a single one flushes it
+                match storage.is_leader() {
+                    Ok(true) => {
+                        if let Err(e) = flush(&spool_config, storage.as_ref()) {
+                            log::warn!("couldn't flush storage spool: {}", e);
+                        }
+                    }
+                    Ok(false) => {}
+                    Err(e) => log::error!("spool flusher: couldn't check leadership: {}", e),
+                }
+                spool_config.retry_interval_secs
+            }
+            None => default_retry_interval_secs(),
+        };
+        std::thread::sleep(Duration::from_secs(retry_interval_secs));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{BackendType, Config},
+        db::get_storage,
+        utils::TestDir,
+    };
+
+    #[test]
+    fn spool_and_flush() {
+        let dir = TestDir::new("spool");
+        let spool_config = StorageSpoolConfig {
+            path: dir.path().to_owned(),
+            retry_interval_secs: default_retry_interval_secs(),
+        };
+
+        let mut labels = Labels::new();
+        labels.insert("team".to_owned(), "payments".to_owned());
+        let entry = CertificateEntry {
+            name: "bushido.example.com".to_owned(),
+            cert: vec![0x30, 0x03, 0x02, 0x01, 0x01],
+            key_identifier: "0a0b0c".to_owned(),
+            key: None,
+            requested_by: Some("token:ci".to_owned()),
+            labels: labels.clone(),
+        };
+        spool(&spool_config, entry.clone()).expect("spool");
+        assert_eq!(pending(&spool_config).expect("pending"), 1);
+
+        let err = spool(
+            &spool_config,
+            CertificateEntry {
+                key: Some(vec![0x01]),
+                ..entry
+            },
+        )
+        .err()
+        .expect("private key");
+        assert!(matches!(err, SpoolError::PrivateKey { name } if name == "bushido.example.com"));
+
+        let mut config = Config::default();
+        config.backend = BackendType::Memory;
+        let storage = get_storage(&config).expect("storage").0;
+
+        assert_eq!(flush(&spool_config, storage.as_ref()).expect("flush"), 1);
+        assert_eq!(pending(&spool_config).expect("pending"), 0);
+        assert_eq!(
+            storage
+                .get_cert_by_addressing_hash(
+                    &storage
+                        .get_addressing_hash_by_key_identifier("0a0b0c")
+                        .expect("addressing hash")
+                )
+                .expect("saved certificate"),
+            vec![0x30, 0x03, 0x02, 0x01, 0x01]
+        );
+        assert_eq!(
+            storage.get_addressing_hashes_by_labels(&labels).expect("labels").len(),
+            1
+        );
+    }
+
+    #[test]
+    fn certificates_for_the_same_key_spooled_apart() {
+        let dir = TestDir::new("spool_same_key");
+        let spool_config = StorageSpoolConfig {
+            path: dir.path().to_owned(),
+            retry_interval_secs: default_retry_interval_secs(),
+        };
+
+        let entry = CertificateEntry {
+            name: "bushido.example.com".to_owned(),
+            cert: vec![0x30, 0x03, 0x02, 0x01, 0x01],
+            key_identifier: "0a0b0c".to_owned(),
+            key: None,
+            requested_by: None,
+            labels: Labels::new(),
+        };
+        spool(&spool_config, entry.clone()).expect("spool");
+        spool(
+            &spool_config,
+            CertificateEntry {
+                cert: vec![0x30, 0x03, 0x02, 0x01, 0x02],
+                ..entry
+            },
+        )
+        .expect("spool renewed certificate");
+        assert_eq!(pending(&spool_config).expect("pending"), 2);
+
+        let mut config = Config::default();
+        config.backend = BackendType::Memory;
+        let storage = get_storage(&config).expect("storage").0;
+        assert_eq!(flush(&spool_config, storage.as_ref()).expect("flush"), 2);
+        for cert in &[vec![0x30, 0x03, 0x02, 0x01, 0x01], vec![0x30, 0x03, 0x02, 0x01, 0x02]] {
+            let address = encode_to_canonical_address(cert).expect("address");
+            assert_eq!(
+                storage
+                    .get_cert_by_addressing_hash(&address)
+                    .expect("saved certificate"),
+                *cert
+            );
+        }
+    }
+}
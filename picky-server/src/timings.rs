@@ -0,0 +1,134 @@
+//! Time spent in each phase of an issuance, reported on `/sign` responses when `issuance_timings`
+//! is enabled so production latency can be diagnosed without attaching a profiler.
+//!
+//! Requests are handled on a single thread: the handler starts a thread-local recorder, the
+//! phases are measured wherever they happen and the handler drains the recorder once signed.
+
+use serde_json::{json, Value};
+use std::{
+    cell::RefCell,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Authorization,
+    Storage,
+    Policy,
+    Signing,
+}
+
+const PHASES: [Phase; 4] = [Phase::Authorization, Phase::Storage, Phase::Policy, Phase::Signing];
+
+impl Phase {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Phase::Authorization => "authorization",
+            Phase::Storage => "storage",
+            Phase::Policy => "policy",
+            Phase::Signing => "signing",
+        }
+    }
+}
+
+/// Cumulated duration of each phase
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Timings {
+    durations: [Duration; 4],
+}
+
+impl Timings {
+    pub fn add(&mut self, phase: Phase, duration: Duration) {
+        self.durations[phase as usize] += duration;
+    }
+
+    pub fn get(&self, phase: Phase) -> Duration {
+        self.durations[phase as usize]
+    }
+
+    /// Value of a `Server-Timing` header (https://www.w3.org/TR/server-timing/), in milliseconds.
+    pub fn server_timing(&self) -> String {
+        PHASES
+            .iter()
+            .map(|phase| format!("{};dur={:.3}", phase.as_str(), millis(self.get(*phase))))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    /// Durations in milliseconds, keyed by phase.
+    pub fn to_json(&self) -> Value {
+        let mut timings = json!({});
+        for phase in PHASES.iter() {
+            timings[phase.as_str()] = json!(millis(self.get(*phase)));
+        }
+        timings
+    }
+}
+
+thread_local! {
+    static RECORDER: RefCell<Option<Timings>> = RefCell::new(None);
+}
+
+/// Starts recording the phases of the current request, discarding a previous recording.
+pub fn start() {
+    RECORDER.with(|recorder| *recorder.borrow_mut() = Some(Timings::default()));
+}
+
+/// Runs `f`, adding its duration to `phase` if a recording was started.
+pub fn measure<T, F: FnOnce() -> T>(phase: Phase, f: F) -> T {
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed();
+
+    RECORDER.with(|recorder| {
+        if let Some(timings) = recorder.borrow_mut().as_mut() {
+            timings.add(phase, elapsed);
+        }
+    });
+
+    result
+}
+
+/// Stops recording and returns the recorded timings, `None` if no recording was started.
+pub fn finish() -> Option<Timings> {
+    RECORDER.with(|recorder| recorder.borrow_mut().take())
+}
+
+pub fn millis(duration: Duration) -> f64 {
+    duration.as_secs() as f64 * 1000.0 + f64::from(duration.subsec_nanos()) / 1_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recording() {
+        assert_eq!(measure(Phase::Signing, || 42), 42);
+        assert_eq!(finish(), None);
+
+        start();
+        measure(Phase::Storage, || std::thread::sleep(Duration::from_millis(2)));
+        measure(Phase::Storage, || std::thread::sleep(Duration::from_millis(2)));
+        let timings = finish().expect("recording");
+        assert!(timings.get(Phase::Storage) >= Duration::from_millis(4));
+        assert_eq!(timings.get(Phase::Signing), Duration::default());
+        assert_eq!(finish(), None);
+    }
+
+    #[test]
+    fn formatting() {
+        let mut timings = Timings::default();
+        timings.add(Phase::Authorization, Duration::from_micros(250));
+        timings.add(Phase::Signing, Duration::from_millis(40));
+
+        assert_eq!(
+            timings.server_timing(),
+            "authorization;dur=0.250, storage;dur=0.000, policy;dur=0.000, signing;dur=40.000"
+        );
+        assert_eq!(
+            timings.to_json(),
+            json!({ "authorization": 0.25, "storage": 0.0, "policy": 0.0, "signing": 40.0 })
+        );
+    }
+}
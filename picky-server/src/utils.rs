@@ -1,72 +1,20 @@
-use base64::DecodeError;
+use chrono::{DateTime, TimeZone, Utc};
 use picky::{
     key::{PrivateKey, PublicKey},
-    pem::{Pem, PemError},
-    x509::{certificate::CertError, csr::CsrError, Cert},
+    pem::Pem,
+    x509::Cert,
 };
 use serde::{de, export::fmt::Debug, ser, Serialize};
-use std::{
-    error::Error,
-    fmt,
-    path::PathBuf,
-    time::{SystemTime, UNIX_EPOCH},
-};
+use std::{fmt, path::PathBuf};
 
+/// Current UNIX timestamp according to `picky::clock`.
 pub fn unix_epoch() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("duration since unix epoch")
-        .as_secs()
+    picky::clock::now() as u64
 }
 
-// === Greedy Error === //
-
-/// I eat and format any error I can
-#[derive(Debug)]
-pub struct GreedyError(pub String);
-
-impl Error for GreedyError {}
-
-impl fmt::Display for GreedyError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.0)
-    }
-}
-
-impl From<String> for GreedyError {
-    fn from(e: String) -> Self {
-        Self(e)
-    }
-}
-
-impl From<PemError> for GreedyError {
-    fn from(e: PemError) -> Self {
-        Self(format!("pem: {}", e))
-    }
-}
-
-impl From<CertError> for GreedyError {
-    fn from(e: CertError) -> Self {
-        Self(format!("cert: {}", e))
-    }
-}
-
-impl From<CsrError> for GreedyError {
-    fn from(e: CsrError) -> Self {
-        Self(format!("csr: {}", e))
-    }
-}
-
-impl From<serde_json::Error> for GreedyError {
-    fn from(e: serde_json::Error) -> Self {
-        Self(format!("json: {}", e))
-    }
-}
-
-impl From<DecodeError> for GreedyError {
-    fn from(e: DecodeError) -> Self {
-        Self(format!("base64 decode: {}", e))
-    }
+/// Current date according to `picky::clock`.
+pub fn now() -> DateTime<Utc> {
+    Utc.timestamp(picky::clock::now(), 0)
 }
 
 /// A path or something else
@@ -131,3 +79,42 @@ macro_rules! path_or_impl_serde {
 path_or_impl_serde!(Cert);
 path_or_impl_serde!(PrivateKey);
 path_or_impl_serde!(PublicKey);
+
+/// Temporary directory of a single test case, removed when dropped.
+#[cfg(test)]
+pub struct TestDir(PathBuf);
+
+#[cfg(test)]
+impl TestDir {
+    pub fn new(name: &str) -> Self {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // test cases run concurrently, and proptest runs the same case many times
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+        let dir = std::env::temp_dir().join(format!(
+            "picky_{}_{}_{}",
+            name,
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).expect("create test dir");
+        Self(dir)
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.0
+    }
+
+    pub fn join<P: AsRef<std::path::Path>>(&self, path: P) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
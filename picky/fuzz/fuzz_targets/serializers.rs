@@ -0,0 +1,39 @@
+#![no_main]
+use arbitrary::{Arbitrary, Unstructured};
+use libfuzzer_sys::fuzz_target;
+use picky::{
+    jose::jwk::Jwk,
+    x509::{certificate::Cert, csr::Csr, Extensions},
+    AlgorithmIdentifier,
+};
+
+fuzz_target!(|data: &[u8]| {
+    let mut u = Unstructured::new(data);
+
+    // x509
+    if let Ok(cert) = Cert::arbitrary(&mut u) {
+        let der = cert.to_der().unwrap();
+        assert_eq!(Cert::from_der(&der).unwrap().to_der().unwrap(), der);
+        let _ = cert.to_pem().unwrap();
+    }
+    if let Ok(csr) = Csr::arbitrary(&mut u) {
+        let der = csr.to_der().unwrap();
+        assert_eq!(Csr::from_der(&der).unwrap().to_der().unwrap(), der);
+    }
+    if let Ok(extensions) = Extensions::arbitrary(&mut u) {
+        let der = picky_asn1_der::to_vec(&extensions).unwrap();
+        let _ = picky_asn1_der::from_bytes::<Extensions>(&der);
+    }
+    if let Ok(algorithm) = AlgorithmIdentifier::arbitrary(&mut u) {
+        let der = picky_asn1_der::to_vec(&algorithm).unwrap();
+        assert_eq!(
+            picky_asn1_der::from_bytes::<AlgorithmIdentifier>(&der).unwrap(),
+            algorithm
+        );
+    }
+
+    // jose
+    if let Ok(jwk) = Jwk::arbitrary(&mut u) {
+        assert_eq!(Jwk::from_json(&jwk.to_json().unwrap()).unwrap(), jwk);
+    }
+});
@@ -3,7 +3,12 @@ use libfuzzer_sys::fuzz_target;
 use picky::{
     key::{PrivateKey, PublicKey},
     pem::{parse_pem, Pem},
-    x509::{certificate::Cert, csr::Csr},
+    x509::{
+        certificate::Cert,
+        crl::Crl,
+        csr::Csr,
+        ocsp::{OcspRequest, OcspResponse},
+    },
 };
 
 fuzz_target!(|data: &[u8]| {
@@ -19,4 +24,9 @@ fuzz_target!(|data: &[u8]| {
     // x509
     let _ = Csr::from_der(data);
     let _ = Cert::from_der(data);
+    let _ = Crl::from_der(data);
+    let _ = OcspRequest::from_der(data);
+    if let Ok(response) = OcspResponse::from_der(data) {
+        let _ = response.basic_response();
+    }
 });
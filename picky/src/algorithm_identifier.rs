@@ -82,6 +82,41 @@ impl AlgorithmIdentifier {
         }
     }
 
+    pub fn new_sha1() -> Self {
+        Self {
+            algorithm: oids::sha1().into(),
+            parameters: AlgorithmIdentifierParameters::Null,
+        }
+    }
+
+    pub fn new_sha224() -> Self {
+        Self {
+            algorithm: oids::sha224().into(),
+            parameters: AlgorithmIdentifierParameters::Null,
+        }
+    }
+
+    pub fn new_sha256() -> Self {
+        Self {
+            algorithm: oids::sha256().into(),
+            parameters: AlgorithmIdentifierParameters::Null,
+        }
+    }
+
+    pub fn new_sha384() -> Self {
+        Self {
+            algorithm: oids::sha384().into(),
+            parameters: AlgorithmIdentifierParameters::Null,
+        }
+    }
+
+    pub fn new_sha512() -> Self {
+        Self {
+            algorithm: oids::sha512().into(),
+            parameters: AlgorithmIdentifierParameters::Null,
+        }
+    }
+
     pub fn new_elliptic_curve<P: Into<ECParameters>>(ec_params: P) -> Self {
         Self {
             algorithm: oids::ec_public_key().into(),
@@ -132,16 +167,27 @@ impl<'de> de::Deserialize<'de> for AlgorithmIdentifier {
                 let oid: ObjectIdentifierAsn1 = seq_next_element!(seq, AlgorithmIdentifier, "algorithm oid");
 
                 let args = match Into::<String>::into(&oid.0).as_str() {
+                    // parameters are either NULL or absent (whichever is found is kept so that
+                    // re-encoding doesn't alter signed data)
                     oids::RSA_ENCRYPTION
                     | oids::SHA1_WITH_RSA_ENCRYPTION
                     | oids::SHA224_WITH_RSA_ENCRYPTION
                     | oids::SHA256_WITH_RSA_ENCRYPTION
                     | oids::SHA384_WITH_RSA_ENCRYPTION
-                    | oids::SHA512_WITH_RSA_ENCRYPTION => {
-                        seq_next_element!(seq, AlgorithmIdentifier, "algorithm identifier parameters (null)");
-                        AlgorithmIdentifierParameters::Null
-                    }
-                    oids::ECDSA_WITH_SHA384 | oids::ECDSA_WITH_SHA256 => AlgorithmIdentifierParameters::None,
+                    | oids::SHA512_WITH_RSA_ENCRYPTION
+                    | oids::ECDSA_WITH_SHA384
+                    | oids::ECDSA_WITH_SHA256
+                    | oids::SHA1
+                    | oids::SHA224
+                    | oids::SHA256
+                    | oids::SHA384
+                    | oids::SHA512 => match seq.next_element::<TagPeeker>()? {
+                        Some(tag_peeker) if tag_peeker.next_tag == Tag::NULL => {
+                            seq_next_element!(seq, AlgorithmIdentifier, "algorithm identifier parameters (null)");
+                            AlgorithmIdentifierParameters::Null
+                        }
+                        _ => AlgorithmIdentifierParameters::None,
+                    },
                     oids::EC_PUBLIC_KEY => AlgorithmIdentifierParameters::EC(seq_next_element!(
                         seq,
                         AlgorithmIdentifier,
@@ -0,0 +1,344 @@
+//! `arbitrary::Arbitrary` implementations for structure-aware fuzzing.
+//!
+//! Values are built through the public constructors and builders from the fuzzer input, so that
+//! fuzz targets exercise the serializers and not only the parsers. Certificates and CSRs are all
+//! signed with the same key, generated once from a fixed seed to keep fuzz cases reproducible.
+
+use crate::{algorithm_identifier::AlgorithmIdentifier, signature::SignatureHashType};
+use arbitrary::{Arbitrary, Error, Result, Unstructured};
+
+impl Arbitrary for SignatureHashType {
+    fn arbitrary(u: &mut Unstructured<'_>) -> Result<Self> {
+        Ok(*u.choose(&[
+            SignatureHashType::RsaSha1,
+            SignatureHashType::RsaSha224,
+            SignatureHashType::RsaSha256,
+            SignatureHashType::RsaSha384,
+            SignatureHashType::RsaSha512,
+        ])?)
+    }
+}
+
+impl Arbitrary for AlgorithmIdentifier {
+    fn arbitrary(u: &mut Unstructured<'_>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=13)? {
+            0 => AlgorithmIdentifier::new_sha1_with_rsa_encryption(),
+            1 => AlgorithmIdentifier::new_sha224_with_rsa_encryption(),
+            2 => AlgorithmIdentifier::new_sha256_with_rsa_encryption(),
+            3 => AlgorithmIdentifier::new_sha384_with_rsa_encryption(),
+            4 => AlgorithmIdentifier::new_sha512_with_rsa_encryption(),
+            5 => AlgorithmIdentifier::new_rsa_encryption(),
+            6 => AlgorithmIdentifier::new_ecdsa_with_sha256(),
+            7 => AlgorithmIdentifier::new_ecdsa_with_sha384(),
+            8 => AlgorithmIdentifier::new_sha1(),
+            9 => AlgorithmIdentifier::new_sha224(),
+            10 => AlgorithmIdentifier::new_sha256(),
+            11 => AlgorithmIdentifier::new_sha384(),
+            12 => AlgorithmIdentifier::new_sha512(),
+            _ => AlgorithmIdentifier::new_elliptic_curve(crate::oids::secp384r1()),
+        })
+    }
+}
+
+#[cfg(feature = "x509")]
+mod x509 {
+    use super::*;
+    use crate::{
+        key::PrivateKey,
+        x509::{
+            certificate::{Cert, CertificateBuilder},
+            csr::Csr,
+            date::UTCDate,
+            extension::{ExtendedKeyUsage, KeyIdentifier, KeyPurpose, KeyUsage, NameConstraints},
+            name::{DirectoryName, GeneralName, GeneralNames, NameAttr},
+            Extension, Extensions,
+        },
+    };
+    use oid::ObjectIdentifier;
+    use once_cell::sync::Lazy;
+    use rand::SeedableRng;
+    use std::convert::TryFrom;
+
+    static SIGNING_KEY: Lazy<PrivateKey> = Lazy::new(|| {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        PrivateKey::generate_rsa_with_rng(&mut rng, 1024).expect("couldn't generate signing key")
+    });
+
+    /// ASCII string, as required by IA5String based names.
+    fn ascii_string(u: &mut Unstructured<'_>) -> Result<String> {
+        Ok(u.arbitrary::<Vec<u8>>()?
+            .into_iter()
+            .map(|byte| char::from(byte & 0x7f))
+            .collect())
+    }
+
+    fn object_identifier(u: &mut Unstructured<'_>) -> Result<ObjectIdentifier> {
+        let dotted = format!("1.3.6.1.4.1.{}.{}", u.arbitrary::<u32>()?, u.arbitrary::<u16>()?);
+        ObjectIdentifier::try_from(dotted.as_str()).map_err(|_| Error::IncorrectFormat)
+    }
+
+    fn date(u: &mut Unstructured<'_>) -> Result<UTCDate> {
+        // both UTCTime (until 2049) and GeneralizedTime encodings
+        UTCDate::new(
+            u.int_in_range(1950..=9999)?,
+            u.int_in_range(1..=12)?,
+            u.int_in_range(1..=28)?,
+            u.int_in_range(0..=23)?,
+            u.int_in_range(0..=59)?,
+            u.int_in_range(0..=59)?,
+        )
+        .ok_or(Error::IncorrectFormat)
+    }
+
+    fn directory_name(u: &mut Unstructured<'_>) -> Result<DirectoryName> {
+        let mut name = DirectoryName::new();
+        for _ in 0..u.arbitrary_len::<(u8, String)>()? {
+            let attr = u.choose(&[
+                NameAttr::CommonName,
+                NameAttr::Surname,
+                NameAttr::SerialNumber,
+                NameAttr::CountryName,
+                NameAttr::LocalityName,
+                NameAttr::StateOrProvinceName,
+                NameAttr::StreetName,
+                NameAttr::OrganisationName,
+                NameAttr::OrganisationalUnitName,
+            ])?;
+            name.add_attr(attr.clone(), u.arbitrary::<String>()?);
+        }
+        Ok(name)
+    }
+
+    fn general_name(u: &mut Unstructured<'_>) -> Result<GeneralName> {
+        let name = match u.int_in_range(0..=5)? {
+            0 => GeneralName::new_dns_name(ascii_string(u)?),
+            1 => GeneralName::new_rfc822_name(ascii_string(u)?),
+            2 => GeneralName::new_uri(ascii_string(u)?),
+            3 => {
+                let len = *u.choose(&[4usize, 8, 16, 32])?;
+                Ok(GeneralName::new_ip_address(u.bytes(len)?.to_vec()))
+            }
+            4 => Ok(GeneralName::new_directory_name(directory_name(u)?)),
+            _ => Ok(GeneralName::new_registered_id(object_identifier(u)?)),
+        };
+        name.map_err(|_| Error::IncorrectFormat)
+    }
+
+    fn general_names(u: &mut Unstructured<'_>) -> Result<GeneralNames> {
+        let mut names = GeneralNames::new(general_name(u)?);
+        for _ in 0..u.arbitrary_len::<(u8, String)>()? {
+            names.add_name(general_name(u)?);
+        }
+        Ok(names)
+    }
+
+    fn key_usage(u: &mut Unstructured<'_>) -> Result<KeyUsage> {
+        let bits = u.arbitrary::<u16>()?;
+        let mut key_usage = KeyUsage::new(usize::from(bits >> 12).min(9));
+        let setters: [fn(&mut KeyUsage, bool); 9] = [
+            KeyUsage::set_digital_signature,
+            KeyUsage::set_content_commitment,
+            KeyUsage::set_key_encipherment,
+            KeyUsage::set_data_encipherment,
+            KeyUsage::set_key_agreement,
+            KeyUsage::set_key_cert_sign,
+            KeyUsage::set_crl_sign,
+            KeyUsage::set_encipher_only,
+            KeyUsage::set_decipher_only,
+        ];
+        for (idx, setter) in setters.iter().enumerate() {
+            if bits & (1 << idx) != 0 {
+                setter(&mut key_usage, true);
+            }
+        }
+        Ok(key_usage)
+    }
+
+    fn extended_key_usage(u: &mut Unstructured<'_>) -> Result<ExtendedKeyUsage> {
+        let mut purpose_oids = Vec::new();
+        for _ in 0..u.arbitrary_len::<u8>()? {
+            let purpose = *u.choose(&[
+                KeyPurpose::ServerAuth,
+                KeyPurpose::ClientAuth,
+                KeyPurpose::CodeSigning,
+                KeyPurpose::EmailProtection,
+                KeyPurpose::IpsecEndSystem,
+                KeyPurpose::IpsecTunnel,
+                KeyPurpose::IpsecUser,
+                KeyPurpose::TimeStamping,
+                KeyPurpose::OcspSigning,
+                KeyPurpose::Any,
+            ])?;
+            purpose_oids.push(purpose.oid());
+        }
+        if u.arbitrary()? {
+            purpose_oids.push(object_identifier(u)?);
+        }
+        Ok(ExtendedKeyUsage::new(purpose_oids))
+    }
+
+    fn name_constraints(u: &mut Unstructured<'_>) -> Result<NameConstraints> {
+        let mut name_constraints = NameConstraints::new();
+        for _ in 0..u.arbitrary_len::<(u8, String)>()? {
+            name_constraints = if u.arbitrary()? {
+                name_constraints.permitted_subtree(general_name(u)?)
+            } else {
+                name_constraints.excluded_subtree(general_name(u)?)
+            };
+        }
+        Ok(name_constraints)
+    }
+
+    impl Arbitrary for Extension {
+        fn arbitrary(u: &mut Unstructured<'_>) -> Result<Self> {
+            let mut extension = match u.int_in_range(0..=7)? {
+                0 => Extension::new_basic_constraints(u.arbitrary::<Option<bool>>()?, u.arbitrary::<Option<u8>>()?),
+                1 => Extension::new_key_usage(key_usage(u)?),
+                2 => Extension::new_extended_key_usage(extended_key_usage(u)?),
+                3 => Extension::new_subject_key_identifier(u.arbitrary::<Vec<u8>>()?),
+                4 => {
+                    Extension::new_authority_key_identifier(KeyIdentifier::from(u.arbitrary::<Vec<u8>>()?), None, None)
+                }
+                5 => Extension::new_subject_alt_names(general_names(u)?),
+                6 => Extension::new_name_constraints(name_constraints(u)?),
+                _ => Extension::new_generic(object_identifier(u)?, u.arbitrary()?, u.arbitrary::<Vec<u8>>()?),
+            };
+            if u.arbitrary()? {
+                extension.set_critical(u.arbitrary()?);
+            }
+            Ok(extension)
+        }
+    }
+
+    impl Arbitrary for Extensions {
+        fn arbitrary(u: &mut Unstructured<'_>) -> Result<Self> {
+            Ok(Extensions::from(u.arbitrary::<Vec<Extension>>()?))
+        }
+    }
+
+    impl Arbitrary for Cert {
+        fn arbitrary(u: &mut Unstructured<'_>) -> Result<Self> {
+            let key: &'static PrivateKey = &SIGNING_KEY;
+            let builder = CertificateBuilder::new();
+            builder.valididy(date(u)?, date(u)?).signature_hash_type(u.arbitrary()?);
+
+            if u.arbitrary()? {
+                builder.self_signed(directory_name(u)?, key);
+            } else {
+                builder
+                    .subject(directory_name(u)?, key.to_public_key())
+                    .issuer(directory_name(u)?, key);
+            }
+
+            if let Some(serial_number) = u.arbitrary::<Option<Vec<u8>>>()? {
+                builder.serial_number(serial_number);
+            }
+            if let Some(ca) = u.arbitrary::<Option<bool>>()? {
+                builder.ca(ca);
+            }
+            if let Some(pathlen) = u.arbitrary::<Option<u8>>()? {
+                builder.pathlen(pathlen);
+            }
+            if u.arbitrary()? {
+                builder.key_usage(key_usage(u)?);
+            }
+            if u.arbitrary()? {
+                builder.extended_key_usage(extended_key_usage(u)?);
+            }
+            if u.arbitrary()? {
+                builder.subject_alt_name(general_names(u)?);
+            }
+            if u.arbitrary()? {
+                builder.name_constraints(name_constraints(u)?);
+            }
+            builder.extensions(u.arbitrary::<Extensions>()?.into_vec());
+
+            // extensions set twice are rejected by the builder
+            builder.build().map_err(|_| Error::IncorrectFormat)
+        }
+    }
+
+    impl Arbitrary for Csr {
+        fn arbitrary(u: &mut Unstructured<'_>) -> Result<Self> {
+            Csr::generate_with_extensions(directory_name(u)?, &SIGNING_KEY, u.arbitrary()?, u.arbitrary()?)
+                .map_err(|_| Error::IncorrectFormat)
+        }
+    }
+}
+
+#[cfg(feature = "jose")]
+mod jose {
+    use super::*;
+    use crate::jose::jwk::{Jwk, JwkKeyOps, JwkKeyType, JwkPubKeyUse};
+
+    impl Arbitrary for Jwk {
+        fn arbitrary(u: &mut Unstructured<'_>) -> Result<Self> {
+            let mut jwk = Jwk::new(JwkKeyType::new_rsa_key(
+                &u.arbitrary::<Vec<u8>>()?,
+                &u.arbitrary::<Vec<u8>>()?,
+            ));
+            jwk.algorithm = u.arbitrary()?;
+            if u.arbitrary()? {
+                jwk.pub_key_use = Some(*u.choose(&[JwkPubKeyUse::Signature, JwkPubKeyUse::Encryption])?);
+            }
+            if u.arbitrary()? {
+                let mut key_operations = Vec::new();
+                for _ in 0..u.arbitrary_len::<u8>()? {
+                    key_operations.push(*u.choose(&[
+                        JwkKeyOps::Sign,
+                        JwkKeyOps::Verify,
+                        JwkKeyOps::Encrypt,
+                        JwkKeyOps::Decrypt,
+                        JwkKeyOps::WrapKey,
+                        JwkKeyOps::UnwrapKey,
+                        JwkKeyOps::DeriveKey,
+                        JwkKeyOps::DeriveBits,
+                    ])?);
+                }
+                jwk.key_operations = Some(key_operations);
+            }
+            jwk.key_id = u.arbitrary()?;
+            Ok(jwk)
+        }
+    }
+}
+
+#[cfg(all(test, feature = "x509", feature = "jose"))]
+mod tests {
+    use super::*;
+    use crate::{
+        jose::jwk::Jwk,
+        x509::{certificate::Cert, csr::Csr},
+    };
+
+    #[test]
+    fn arbitrary_values_round_trip() {
+        let data = (0..4096u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect::<Vec<u8>>();
+        let mut built = 0;
+
+        for offset in (0..512).step_by(16) {
+            let mut u = Unstructured::new(&data[offset..]);
+
+            if let Ok(cert) = Cert::arbitrary(&mut u) {
+                let der = cert.to_der().expect("cert to der");
+                let parsed = Cert::from_der(&der).expect("cert from der");
+                assert_eq!(parsed.to_der().unwrap(), der);
+                built += 1;
+            }
+
+            if let Ok(csr) = Csr::arbitrary(&mut u) {
+                let der = csr.to_der().expect("csr to der");
+                let parsed = Csr::from_der(&der).expect("csr from der");
+                assert_eq!(parsed.to_der().unwrap(), der);
+                built += 1;
+            }
+
+            let jwk = Jwk::arbitrary(&mut u).expect("jwk");
+            assert_eq!(Jwk::from_json(&jwk.to_json().unwrap()).expect("jwk from json"), jwk);
+        }
+
+        assert!(built > 0);
+    }
+}
@@ -0,0 +1,118 @@
+//! Time source used for validation and issuance.
+//!
+//! Defaults to the system clock. Deployments may pin a trusted time source with `set_clock`, and
+//! tests may simulate expiry or rotation scenarios by running code under a `FixedClock` with
+//! `with_clock`.
+
+use once_cell::sync::Lazy;
+use std::{
+    cell::RefCell,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc, PoisonError, RwLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub trait Clock: Send + Sync {
+    /// Seconds elapsed since the UNIX epoch.
+    fn now(&self) -> i64;
+}
+
+/// Operating system clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> i64 {
+        match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(elapsed) => elapsed.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs() as i64),
+        }
+    }
+}
+
+/// Clock only moving when told to
+#[derive(Debug, Default)]
+pub struct FixedClock(AtomicI64);
+
+impl FixedClock {
+    pub fn new(timestamp: i64) -> Self {
+        Self(AtomicI64::new(timestamp))
+    }
+
+    pub fn set(&self, timestamp: i64) {
+        self.0.store(timestamp, Ordering::SeqCst);
+    }
+
+    /// Moves the clock forward (or backward with a negative number of seconds).
+    pub fn advance(&self, seconds: i64) {
+        self.0.fetch_add(seconds, Ordering::SeqCst);
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> i64 {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+static CLOCK: Lazy<RwLock<Arc<dyn Clock>>> = Lazy::new(|| RwLock::new(Arc::new(SystemClock)));
+
+thread_local! {
+    static THREAD_CLOCK: RefCell<Option<Arc<dyn Clock>>> = RefCell::new(None);
+}
+
+/// Replaces the clock for the whole process.
+pub fn set_clock(clock: Arc<dyn Clock>) {
+    *CLOCK.write().unwrap_or_else(PoisonError::into_inner) = clock;
+}
+
+/// Runs `f` with `clock` overriding the process clock on the current thread only.
+///
+/// Threads spawned by `f` keep using the process clock.
+pub fn with_clock<F, T>(clock: Arc<dyn Clock>, f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    struct Restore(Option<Arc<dyn Clock>>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            THREAD_CLOCK.with(|clock| *clock.borrow_mut() = previous);
+        }
+    }
+
+    let _restore = Restore(THREAD_CLOCK.with(|current| current.replace(Some(clock))));
+    f()
+}
+
+/// Current UNIX timestamp according to the active clock.
+pub fn now() -> i64 {
+    if let Some(now) = THREAD_CLOCK.with(|clock| clock.borrow().as_ref().map(|clock| clock.now())) {
+        return now;
+    }
+
+    CLOCK.read().unwrap_or_else(PoisonError::into_inner).now()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thread_clock_override() {
+        let clock = Arc::new(FixedClock::new(1_545_263_000));
+
+        let (before, after) = with_clock(clock.clone(), || {
+            let before = now();
+            clock.advance(3600);
+            (before, now())
+        });
+        assert_eq!(before, 1_545_263_000);
+        assert_eq!(after, 1_545_266_600);
+
+        assert!(now() > 1_545_266_600);
+    }
+}
@@ -0,0 +1,636 @@
+//! Cryptographic Message Syntax (CMS) signed data
+//!
+//! Covers certificate bundles (`.p7b`) as well as signatures over arbitrary content, either
+//! detached or encapsulated.
+//!
+//! https://tools.ietf.org/html/rfc5652
+
+use crate::{
+    key::PrivateKey,
+    oids,
+    pem::Pem,
+    signature::{SignatureError, SignatureHashType},
+    x509::{
+        certificate::Cert,
+        date::UTCDate,
+        private::{
+            cms::{
+                from_implicit_set, to_implicit_set, Attribute, ContentInfo, EncapsulatedContentInfo,
+                IssuerAndSerialNumber, SignedData as SerdeSignedData, SignerIdentifier, SignerInfo,
+            },
+            validity::Time,
+            Certificate,
+        },
+    },
+    AlgorithmIdentifier,
+};
+use oid::ObjectIdentifier;
+use picky_asn1::{
+    date::UTCTime,
+    tag::Tag,
+    wrapper::{ApplicationTag0, Asn1SetOf, ObjectIdentifierAsn1, OctetStringAsn1},
+};
+use picky_asn1_der::{Asn1DerError, Asn1RawDer};
+use serde::Serialize;
+use snafu::{ResultExt, Snafu};
+use std::cell::RefCell;
+
+#[derive(Debug, Snafu)]
+pub enum CmsError {
+    /// asn1 serialization error
+    #[snafu(display("(asn1) couldn't serialize {}: {}", element, source))]
+    Asn1Serialization {
+        element: &'static str,
+        source: Asn1DerError,
+    },
+
+    /// asn1 deserialization error
+    #[snafu(display("(asn1) couldn't deserialize {}: {}", element, source))]
+    Asn1Deserialization {
+        element: &'static str,
+        source: Asn1DerError,
+    },
+
+    /// signature error
+    #[snafu(display("signature error: {}", source))]
+    Signature { source: SignatureError },
+
+    /// invalid PEM label error
+    #[snafu(display("invalid PEM label: {}", label))]
+    InvalidPemLabel { label: String },
+
+    /// content type other than signed data
+    #[snafu(display("unsupported content type: {}", oid))]
+    UnsupportedContentType { oid: String },
+
+    /// unsupported digest algorithm
+    #[snafu(display("unsupported digest algorithm: {}", oid))]
+    UnsupportedDigestAlgorithm { oid: String },
+
+    /// missing required builder argument
+    #[snafu(display("missing required builder argument `{}`", arg))]
+    MissingBuilderArgument { arg: &'static str },
+
+    /// signed data without any signer
+    #[snafu(display("signed data has no signer"))]
+    NoSigner,
+
+    /// the signer certificate isn't embedded in the signed data
+    #[snafu(display("signer certificate not found"))]
+    SignerCertificateNotFound,
+
+    /// signed attributes are missing or inconsistent
+    #[snafu(display("invalid signed attributes: {}", reason))]
+    InvalidSignedAttributes { reason: String },
+
+    /// the content doesn't match the signed message digest
+    #[snafu(display("message digest mismatch"))]
+    MessageDigestMismatch,
+
+    /// detached signature verified without the content
+    #[snafu(display("content is neither encapsulated nor provided"))]
+    MissingContent,
+}
+
+static_assertions::assert_impl_all!(CmsError: Send, Sync);
+
+const PKCS7_PEM_LABEL: &str = "PKCS7";
+
+fn digest_algorithm(hash_type: SignatureHashType) -> AlgorithmIdentifier {
+    match hash_type {
+        SignatureHashType::RsaSha1 => AlgorithmIdentifier::new_sha1(),
+        SignatureHashType::RsaSha224 => AlgorithmIdentifier::new_sha224(),
+        SignatureHashType::RsaSha256 => AlgorithmIdentifier::new_sha256(),
+        SignatureHashType::RsaSha384 => AlgorithmIdentifier::new_sha384(),
+        SignatureHashType::RsaSha512 => AlgorithmIdentifier::new_sha512(),
+    }
+}
+
+fn hash_type_from_digest_algorithm(algorithm: &AlgorithmIdentifier) -> Result<SignatureHashType, CmsError> {
+    let oid_string: String = algorithm.oid().into();
+    match oid_string.as_str() {
+        oids::SHA1 => Ok(SignatureHashType::RsaSha1),
+        oids::SHA224 => Ok(SignatureHashType::RsaSha224),
+        oids::SHA256 => Ok(SignatureHashType::RsaSha256),
+        oids::SHA384 => Ok(SignatureHashType::RsaSha384),
+        oids::SHA512 => Ok(SignatureHashType::RsaSha512),
+        _ => Err(CmsError::UnsupportedDigestAlgorithm { oid: oid_string }),
+    }
+}
+
+fn to_der<T: Serialize>(value: &T, element: &'static str) -> Result<Vec<u8>, CmsError> {
+    picky_asn1_der::to_vec(value).context(Asn1Serialization { element })
+}
+
+/// Signed data wrapped in a content info
+///
+/// https://tools.ietf.org/html/rfc5652#section-5
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignedData(SerdeSignedData);
+
+static_assertions::assert_impl_all!(SignedData: Send, Sync);
+
+impl SignedData {
+    pub fn builder<'a>() -> SignedDataBuilder<'a> {
+        SignedDataBuilder::new()
+    }
+
+    /// Certificates-only signed data, i.e. a `.p7b` bundle.
+    pub fn from_certs(certs: &[Cert]) -> Result<Self, CmsError> {
+        Ok(Self(SerdeSignedData {
+            version: 1,
+            digest_algorithms: Asn1SetOf(Vec::new()),
+            encap_content_info: EncapsulatedContentInfo {
+                e_content_type: oids::pkcs7_data().into(),
+                e_content: None,
+            },
+            certificates: Some(encode_certs(certs)?),
+            crls: None,
+            signer_infos: Asn1SetOf(Vec::new()),
+        }))
+    }
+
+    pub fn from_der<T: ?Sized + AsRef<[u8]>>(der: &T) -> Result<Self, CmsError> {
+        let content_info: ContentInfo = picky_asn1_der::from_bytes(der.as_ref()).context(Asn1Deserialization {
+            element: "content info",
+        })?;
+
+        if content_info.content_type.0 != oids::pkcs7_signed_data() {
+            return Err(CmsError::UnsupportedContentType {
+                oid: (&content_info.content_type.0).into(),
+            });
+        }
+
+        Ok(Self(
+            picky_asn1_der::from_bytes(&(content_info.content.0).0)
+                .context(Asn1Deserialization { element: "signed data" })?,
+        ))
+    }
+
+    pub fn from_pem(pem: &Pem) -> Result<Self, CmsError> {
+        match pem.label() {
+            PKCS7_PEM_LABEL => Self::from_der(pem.data()),
+            _ => Err(CmsError::InvalidPemLabel {
+                label: pem.label().to_owned(),
+            }),
+        }
+    }
+
+    pub fn to_der(&self) -> Result<Vec<u8>, CmsError> {
+        to_der(
+            &ContentInfo {
+                content_type: oids::pkcs7_signed_data().into(),
+                content: ApplicationTag0(Asn1RawDer(to_der(&self.0, "signed data")?)),
+            },
+            "content info",
+        )
+    }
+
+    pub fn to_pem(&self) -> Result<Pem<'static>, CmsError> {
+        Ok(Pem::new(PKCS7_PEM_LABEL, self.to_der()?))
+    }
+
+    pub fn content_type(&self) -> &ObjectIdentifier {
+        &self.0.encap_content_info.e_content_type.0
+    }
+
+    /// Encapsulated content, `None` if the signature is detached.
+    pub fn content(&self) -> Option<&[u8]> {
+        self.0
+            .encap_content_info
+            .e_content
+            .as_ref()
+            .map(|content| (content.0).0.as_slice())
+    }
+
+    /// Embedded certificates, in order.
+    pub fn certs(&self) -> Result<Vec<Cert>, CmsError> {
+        match &self.0.certificates {
+            Some(certificates) => {
+                let certificates: Asn1SetOf<Certificate> = picky_asn1_der::from_bytes(&from_implicit_set(certificates))
+                    .context(Asn1Deserialization {
+                        element: "certificates",
+                    })?;
+                Ok(certificates.0.into_iter().map(Cert::from).collect())
+            }
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Verifies the signatures over the encapsulated content and returns the signer certificates.
+    ///
+    /// Signer certificates must be embedded. Whether they are trusted is up to the caller (see
+    /// `Cert::verify_chain`).
+    pub fn verify(&self) -> Result<Vec<Cert>, CmsError> {
+        let content = self.content().ok_or(CmsError::MissingContent)?;
+        self.verify_content(content)
+    }
+
+    /// Verifies the detached signatures over `content` and returns the signer certificates.
+    ///
+    /// Signer certificates must be embedded. Whether they are trusted is up to the caller (see
+    /// `Cert::verify_chain`).
+    pub fn verify_detached(&self, content: &[u8]) -> Result<Vec<Cert>, CmsError> {
+        self.verify_content(content)
+    }
+
+    fn verify_content(&self, content: &[u8]) -> Result<Vec<Cert>, CmsError> {
+        if self.0.signer_infos.0.is_empty() {
+            return Err(CmsError::NoSigner);
+        }
+
+        let certs = self.certs()?;
+        self.0
+            .signer_infos
+            .0
+            .iter()
+            .map(|signer_info| -> Result<Cert, CmsError> {
+                let signer_cert = find_signer_cert(&certs, &signer_info.sid)?;
+                self.verify_signer(signer_info, signer_cert, content)?;
+                Ok(signer_cert.clone())
+            })
+            .collect()
+    }
+
+    fn verify_signer(&self, signer_info: &SignerInfo, signer_cert: &Cert, content: &[u8]) -> Result<(), CmsError> {
+        let digest_hash_type = hash_type_from_digest_algorithm(&signer_info.digest_algorithm)?;
+
+        // the signature algorithm is either the bare public key algorithm or combined with the digest one
+        let signature_hash_type = if signer_info.signature_algorithm.is_a(oids::rsa_encryption()) {
+            digest_hash_type
+        } else {
+            SignatureHashType::from_algorithm_identifier(&signer_info.signature_algorithm).context(Signature)?
+        };
+
+        let signed_msg = match &signer_info.signed_attrs {
+            Some(signed_attrs) => {
+                let signed_attrs_der = from_implicit_set(signed_attrs);
+                let attributes: Asn1SetOf<Attribute> =
+                    picky_asn1_der::from_bytes(&signed_attrs_der).context(Asn1Deserialization {
+                        element: "signed attributes",
+                    })?;
+
+                let content_type: ObjectIdentifierAsn1 =
+                    single_attribute_value(&attributes.0, oids::content_type(), "content type")?;
+                if content_type.0 != *self.content_type() {
+                    return Err(CmsError::InvalidSignedAttributes {
+                        reason: "content type doesn't match the signed content".to_owned(),
+                    });
+                }
+
+                let message_digest: OctetStringAsn1 =
+                    single_attribute_value(&attributes.0, oids::message_digest(), "message digest")?;
+                if message_digest.0 != digest_hash_type.hash(content) {
+                    return Err(CmsError::MessageDigestMismatch);
+                }
+
+                signed_attrs_der
+            }
+            None => content.to_vec(),
+        };
+
+        signature_hash_type
+            .verify(signer_cert.public_key(), &signed_msg, &signer_info.signature.0)
+            .context(Signature)
+    }
+}
+
+fn encode_certs(certs: &[Cert]) -> Result<Asn1RawDer, CmsError> {
+    let certificates = Asn1SetOf(certs.iter().cloned().map(Certificate::from).collect::<Vec<_>>());
+    Ok(to_implicit_set(Tag::APP_0, to_der(&certificates, "certificates")?))
+}
+
+fn find_signer_cert<'a>(certs: &'a [Cert], sid: &SignerIdentifier) -> Result<&'a Cert, CmsError> {
+    certs
+        .iter()
+        .find(|cert| match sid {
+            SignerIdentifier::IssuerAndSerialNumber(issuer_and_serial_number) => {
+                let certificate = Certificate::from((*cert).clone());
+                certificate.tbs_certificate.issuer == issuer_and_serial_number.issuer
+                    && certificate.tbs_certificate.serial_number == issuer_and_serial_number.serial_number
+            }
+            SignerIdentifier::SubjectKeyIdentifier(key_id) => cert
+                .subject_key_identifier()
+                .map(|cert_key_id| cert_key_id == key_id.0.as_slice())
+                .unwrap_or(false),
+        })
+        .ok_or(CmsError::SignerCertificateNotFound)
+}
+
+/// Value of a signed attribute which must be present exactly once, with a single value.
+fn single_attribute_value<T: serde::de::DeserializeOwned>(
+    attributes: &[Attribute],
+    attr_type: ObjectIdentifier,
+    name: &'static str,
+) -> Result<T, CmsError> {
+    let mut matching = attributes.iter().filter(|attribute| attribute.attr_type.0 == attr_type);
+    let value = match (matching.next(), matching.next()) {
+        (Some(attribute), None) if attribute.attr_values.0.len() == 1 => &attribute.attr_values.0[0],
+        _ => {
+            return Err(CmsError::InvalidSignedAttributes {
+                reason: format!("expected a single {} attribute with a single value", name),
+            })
+        }
+    };
+    picky_asn1_der::from_bytes(&value.0).context(Asn1Deserialization { element: name })
+}
+
+// Statically checks the field actually exists and returns a &'static str of the field name
+macro_rules! field_str {
+    ($field:ident) => {{
+        ::static_assertions::assert_fields!(SignedDataBuilderInner: $field);
+        stringify!($field)
+    }};
+}
+
+#[derive(Default, Clone, Debug)]
+struct SignedDataBuilderInner<'a> {
+    signer_cert: Option<Cert>,
+    signer_key: Option<&'a PrivateKey>,
+    signature_hash_type: Option<SignatureHashType>,
+    content: Option<Vec<u8>>,
+    detached: bool,
+    chain: Vec<Cert>,
+    signing_time: Option<UTCDate>,
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct SignedDataBuilder<'a> {
+    inner: RefCell<SignedDataBuilderInner<'a>>,
+}
+
+impl<'a> SignedDataBuilder<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Required. The signer certificate is embedded in the signed data.
+    #[inline]
+    pub fn signer(&self, signer_cert: Cert, signer_key: &'a PrivateKey) -> &Self {
+        let mut inner_mut = self.inner.borrow_mut();
+        inner_mut.signer_cert = Some(signer_cert);
+        inner_mut.signer_key = Some(signer_key);
+        drop(inner_mut);
+        self
+    }
+
+    /// Required
+    #[inline]
+    pub fn content<C: Into<Vec<u8>>>(&self, content: C) -> &Self {
+        self.inner.borrow_mut().content = Some(content.into());
+        self
+    }
+
+    /// Optional. Leaves the content out of the signed data, default is to encapsulate it.
+    #[inline]
+    pub fn detached(&self, detached: bool) -> &Self {
+        self.inner.borrow_mut().detached = detached;
+        self
+    }
+
+    /// Optional
+    #[inline]
+    pub fn signature_hash_type(&self, signature_hash_type: SignatureHashType) -> &Self {
+        self.inner.borrow_mut().signature_hash_type = Some(signature_hash_type);
+        self
+    }
+
+    /// Optional. Embeds a certificate of the signer chain.
+    #[inline]
+    pub fn chain_cert(&self, cert: Cert) -> &Self {
+        self.inner.borrow_mut().chain.push(cert);
+        self
+    }
+
+    /// Optional. Embeds the signer chain.
+    #[inline]
+    pub fn chain(&self, chain: Vec<Cert>) -> &Self {
+        self.inner.borrow_mut().chain = chain;
+        self
+    }
+
+    /// Optional. Adds a signing time signed attribute.
+    #[inline]
+    pub fn signing_time(&self, signing_time: UTCDate) -> &Self {
+        self.inner.borrow_mut().signing_time = Some(signing_time);
+        self
+    }
+
+    pub fn build(&self) -> Result<SignedData, CmsError> {
+        let mut inner = self.inner.borrow_mut();
+
+        let signer_cert = inner.signer_cert.take().ok_or(CmsError::MissingBuilderArgument {
+            arg: field_str!(signer_cert),
+        })?;
+        let signer_key = inner.signer_key.take().ok_or(CmsError::MissingBuilderArgument {
+            arg: field_str!(signer_key),
+        })?;
+        let content = inner.content.take().ok_or(CmsError::MissingBuilderArgument {
+            arg: field_str!(content),
+        })?;
+        let signature_hash_type = inner.signature_hash_type.take().unwrap_or(SignatureHashType::RsaSha256);
+        let signing_time = inner.signing_time.take();
+        let chain = std::mem::take(&mut inner.chain);
+        let detached = inner.detached;
+
+        drop(inner);
+
+        let mut attributes = vec![
+            Attribute {
+                attr_type: oids::content_type().into(),
+                attr_values: Asn1SetOf(vec![Asn1RawDer(to_der(
+                    &ObjectIdentifierAsn1::from(oids::pkcs7_data()),
+                    "content type",
+                )?)]),
+            },
+            Attribute {
+                attr_type: oids::message_digest().into(),
+                attr_values: Asn1SetOf(vec![Asn1RawDer(to_der(
+                    &OctetStringAsn1(signature_hash_type.hash(&content)),
+                    "message digest",
+                )?)]),
+            },
+        ];
+
+        if let Some(signing_time) = signing_time {
+            // dates between 1950 and 2049 must be encoded as UTCTime
+            let signing_time = if (1950..2050).contains(&signing_time.year()) {
+                let utc_time: UTCTime = signing_time.into();
+                Time::from(utc_time)
+            } else {
+                Time::from(signing_time)
+            };
+            attributes.push(Attribute {
+                attr_type: oids::signing_time().into(),
+                attr_values: Asn1SetOf(vec![Asn1RawDer(to_der(&signing_time, "signing time")?)]),
+            });
+        }
+
+        // DER requires the SET OF elements to be sorted by their encoding
+        let mut encoded_attributes = attributes
+            .iter()
+            .map(|attribute| to_der(attribute, "signed attribute").map(Asn1RawDer))
+            .collect::<Result<Vec<_>, CmsError>>()?;
+        encoded_attributes.sort_by(|a, b| a.0.cmp(&b.0));
+        let signed_attrs_der = to_der(&Asn1SetOf(encoded_attributes), "signed attributes")?;
+
+        let signature = signature_hash_type
+            .sign(&signed_attrs_der, signer_key)
+            .context(Signature)?;
+
+        let signer_certificate = Certificate::from(signer_cert.clone());
+        let signer_info = SignerInfo {
+            version: 1,
+            sid: SignerIdentifier::IssuerAndSerialNumber(IssuerAndSerialNumber {
+                issuer: signer_certificate.tbs_certificate.issuer,
+                serial_number: signer_certificate.tbs_certificate.serial_number,
+            }),
+            digest_algorithm: digest_algorithm(signature_hash_type),
+            signed_attrs: Some(to_implicit_set(Tag::APP_0, signed_attrs_der)),
+            signature_algorithm: signature_hash_type.into(),
+            signature: OctetStringAsn1(signature),
+            unsigned_attrs: None,
+        };
+
+        let mut certs = vec![signer_cert];
+        certs.extend(chain);
+
+        Ok(SignedData(SerdeSignedData {
+            version: 1,
+            digest_algorithms: Asn1SetOf(vec![digest_algorithm(signature_hash_type)]),
+            encap_content_info: EncapsulatedContentInfo {
+                e_content_type: oids::pkcs7_data().into(),
+                e_content: if detached {
+                    None
+                } else {
+                    Some(ApplicationTag0(OctetStringAsn1(content)))
+                },
+            },
+            certificates: Some(encode_certs(&certs)?),
+            crls: None,
+            signer_infos: Asn1SetOf(vec![signer_info]),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pem::parse_pem,
+        x509::{certificate::CertificateBuilder, name::DirectoryName},
+    };
+
+    fn ca() -> (Cert, PrivateKey) {
+        let key = PrivateKey::from_pkcs8(parse_pem(crate::test_files::RSA_2048_PK_1).unwrap().data()).unwrap();
+        let cert = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2020, 1, 1).unwrap(), UTCDate::ymd(2030, 1, 1).unwrap())
+            .self_signed(DirectoryName::new_common_name("Picky CMS CA"), &key)
+            .ca(true)
+            .build()
+            .unwrap();
+        (cert, key)
+    }
+
+    fn signer(ca_cert: &Cert, ca_key: &PrivateKey) -> (Cert, PrivateKey) {
+        let key = PrivateKey::from_pkcs8(parse_pem(crate::test_files::RSA_2048_PK_2).unwrap().data()).unwrap();
+        let cert = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2020, 1, 1).unwrap(), UTCDate::ymd(2021, 1, 1).unwrap())
+            .subject(DirectoryName::new_common_name("Picky CMS Signer"), key.to_public_key())
+            .issuer_cert(ca_cert, ca_key)
+            .build()
+            .unwrap();
+        (cert, key)
+    }
+
+    #[test]
+    fn certs_only() {
+        let (ca_cert, ca_key) = ca();
+        let (signer_cert, _) = signer(&ca_cert, &ca_key);
+
+        let p7b = SignedData::from_certs(&[signer_cert.clone(), ca_cert.clone()]).unwrap();
+        let pem = p7b.to_pem().unwrap();
+        assert_eq!(pem.label(), "PKCS7");
+
+        let parsed = SignedData::from_pem(&pem).unwrap();
+        assert_eq!(parsed, p7b);
+        assert_eq!(parsed.certs().unwrap(), vec![signer_cert, ca_cert]);
+        assert_eq!(parsed.content(), None);
+        match parsed.verify_detached(b"content") {
+            Err(CmsError::NoSigner) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn detached_signature() {
+        let (ca_cert, ca_key) = ca();
+        let (signer_cert, signer_key) = signer(&ca_cert, &ca_key);
+        let content = b"picky signed content";
+
+        let signed_data = SignedData::builder()
+            .signer(signer_cert.clone(), &signer_key)
+            .chain_cert(ca_cert.clone())
+            .content(&content[..])
+            .detached(true)
+            .signing_time(UTCDate::ymd(2020, 6, 1).unwrap())
+            .build()
+            .unwrap();
+
+        let parsed = SignedData::from_der(&signed_data.to_der().unwrap()).unwrap();
+        assert_eq!(parsed, signed_data);
+        assert_eq!(parsed.content(), None);
+        assert_eq!(parsed.content_type(), &oids::pkcs7_data());
+        assert_eq!(parsed.certs().unwrap(), vec![signer_cert.clone(), ca_cert.clone()]);
+
+        let signers = parsed.verify_detached(content).unwrap();
+        assert_eq!(signers, vec![signer_cert.clone()]);
+        signers[0]
+            .verify_chain(std::iter::once(&ca_cert), &UTCDate::ymd(2020, 6, 1).unwrap())
+            .unwrap();
+
+        match parsed.verify_detached(b"tampered content") {
+            Err(CmsError::MessageDigestMismatch) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        match parsed.verify() {
+            Err(CmsError::MissingContent) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn encapsulated_signature() {
+        let (ca_cert, ca_key) = ca();
+        let (signer_cert, signer_key) = signer(&ca_cert, &ca_key);
+
+        let signed_data = SignedData::builder()
+            .signer(signer_cert.clone(), &signer_key)
+            .signature_hash_type(SignatureHashType::RsaSha384)
+            .content(b"encapsulated".to_vec())
+            .build()
+            .unwrap();
+
+        let parsed = SignedData::from_der(&signed_data.to_der().unwrap()).unwrap();
+        assert_eq!(parsed.content(), Some(&b"encapsulated"[..]));
+        assert_eq!(parsed.verify().unwrap(), vec![signer_cert]);
+    }
+
+    #[test]
+    fn signer_certificate_not_embedded() {
+        let (ca_cert, ca_key) = ca();
+        let (signer_cert, signer_key) = signer(&ca_cert, &ca_key);
+
+        let mut signed_data = SignedData::builder()
+            .signer(signer_cert, &signer_key)
+            .content(b"content".to_vec())
+            .build()
+            .unwrap();
+        signed_data.0.certificates = Some(encode_certs(&[ca_cert]).unwrap());
+
+        match signed_data.verify() {
+            Err(CmsError::SignerCertificateNotFound) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,821 @@
+//! JSON Web Encryption using the compact serialization ([RFC7516](https://tools.ietf.org/html/rfc7516)).
+//!
+//! Supported key management algorithms are `RSA-OAEP`, `RSA-OAEP-256` and `ECDH-ES` (direct key
+//! agreement on the P-256 curve), and content is encrypted with `A128GCM` or `A256GCM`.
+
+use crate::{
+    algorithm_identifier::{AlgorithmIdentifierParameters, ECParameters},
+    jose::jwt::{Jwt, JwtError, JwtValidator},
+    key::{PrivateKey, PublicKey},
+    oids,
+    private::{private_key_info, subject_public_key_info::PublicKey as InnerPublicKey, SubjectPublicKeyInfo},
+    AlgorithmIdentifier,
+};
+use aes_gcm::{
+    aead::{generic_array::GenericArray, Aead, NewAead, Payload},
+    Aes128Gcm, Aes256Gcm,
+};
+use base64::DecodeError;
+use p256::{ecdh::EphemeralSecret, elliptic_curve::sec1::ToEncodedPoint};
+use picky_asn1::{
+    bit_string::BitString,
+    wrapper::{BitStringAsn1, BitStringAsn1Container, OctetStringAsn1Container},
+};
+use rand::{rngs::OsRng, RngCore};
+use rsa::{BigUint, PaddingScheme, PublicKey as RsaPublicKeyInterface, RSAPrivateKey, RSAPublicKey};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use snafu::Snafu;
+use std::fmt;
+
+// === error type === //
+
+#[derive(Debug, Snafu)]
+#[non_exhaustive]
+pub enum JweError {
+    /// Json error
+    #[snafu(display("JSON error: {}", source))]
+    Json { source: serde_json::Error },
+
+    /// invalid token encoding
+    #[snafu(display("input isn't a valid token string: {}", input))]
+    InvalidEncoding { input: String },
+
+    /// couldn't decode base64
+    #[snafu(display("couldn't decode base64: {}", source))]
+    Base64Decoding { source: DecodeError },
+
+    /// key can't be used with the key management algorithm
+    #[snafu(display("{} requires {}", alg, expected))]
+    UnsupportedKey { alg: JweAlg, expected: &'static str },
+
+    /// invalid P-256 private key
+    InvalidEcdhKey,
+
+    /// ephemeral public key is missing or invalid
+    InvalidEphemeralKey,
+
+    /// RSA error
+    #[snafu(display("RSA error: {}", context))]
+    Rsa { context: String },
+
+    /// content couldn't be encrypted
+    #[snafu(display("encryption failed: {}", context))]
+    Encryption { context: &'static str },
+
+    /// token couldn't be decrypted, or was altered
+    DecryptionFailed,
+
+    /// payload isn't a nested JWT
+    #[snafu(display("payload isn't a nested JWT (content type: {:?})", cty))]
+    NotNestedJwt { cty: Option<String> },
+
+    /// nested JWT error
+    #[snafu(display("nested JWT error: {}", source))]
+    NestedJwt { source: JwtError },
+}
+
+impl From<serde_json::Error> for JweError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json { source: e }
+    }
+}
+
+impl From<DecodeError> for JweError {
+    fn from(e: DecodeError) -> Self {
+        Self::Base64Decoding { source: e }
+    }
+}
+
+impl From<rsa::errors::Error> for JweError {
+    fn from(e: rsa::errors::Error) -> Self {
+        Self::Rsa { context: e.to_string() }
+    }
+}
+
+impl From<JwtError> for JweError {
+    fn from(e: JwtError) -> Self {
+        Self::NestedJwt { source: e }
+    }
+}
+
+// === algorithms === //
+
+/// Key management algorithm
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum JweAlg {
+    /// RSAES OAEP using SHA-1 and MGF1 with SHA-1
+    #[serde(rename = "RSA-OAEP")]
+    RsaOaep,
+    /// RSAES OAEP using SHA-256 and MGF1 with SHA-256
+    #[serde(rename = "RSA-OAEP-256")]
+    RsaOaep256,
+    /// Elliptic Curve Diffie-Hellman Ephemeral Static key agreement, the agreed key is the content encryption key
+    #[serde(rename = "ECDH-ES")]
+    EcdhEs,
+}
+
+impl fmt::Display for JweAlg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JweAlg::RsaOaep => write!(f, "RSA-OAEP"),
+            JweAlg::RsaOaep256 => write!(f, "RSA-OAEP-256"),
+            JweAlg::EcdhEs => write!(f, "ECDH-ES"),
+        }
+    }
+}
+
+/// Content encryption algorithm
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum JweEnc {
+    #[serde(rename = "A128GCM")]
+    Aes128Gcm,
+    #[serde(rename = "A256GCM")]
+    Aes256Gcm,
+}
+
+impl JweEnc {
+    pub fn key_size(self) -> usize {
+        match self {
+            JweEnc::Aes128Gcm => 16,
+            JweEnc::Aes256Gcm => 32,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            JweEnc::Aes128Gcm => "A128GCM",
+            JweEnc::Aes256Gcm => "A256GCM",
+        }
+    }
+}
+
+const GCM_IV_SIZE: usize = 12;
+const GCM_TAG_SIZE: usize = 16;
+
+// === header === //
+
+const EC_KEY_TYPE: &str = "EC";
+const P256_CURVE: &str = "P-256";
+const P256_FIELD_SIZE: usize = 32;
+
+/// Ephemeral public key of the `ECDH-ES` key agreement, as a JWK.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JweEphemeralKey {
+    pub kty: String,
+    pub crv: String,
+    /// base64url-encoded coordinates
+    pub x: String,
+    pub y: String,
+}
+
+/// JOSE header as defined by [RFC7516](https://tools.ietf.org/html/rfc7516#section-4)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JweHeader {
+    pub alg: JweAlg,
+    pub enc: JweEnc,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub typ: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cty: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+    /// Set when encoding with `ECDH-ES`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub epk: Option<JweEphemeralKey>,
+    /// Agreement PartyUInfo (base64url-encoded), used by `ECDH-ES` only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub apu: Option<String>,
+    /// Agreement PartyVInfo (base64url-encoded), used by `ECDH-ES` only
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub apv: Option<String>,
+}
+
+impl JweHeader {
+    pub fn new(alg: JweAlg, enc: JweEnc) -> Self {
+        Self {
+            alg,
+            enc,
+            typ: None,
+            cty: None,
+            kid: None,
+            epk: None,
+            apu: None,
+            apv: None,
+        }
+    }
+}
+
+// === ECDH key === //
+
+/// P-256 private key a token encrypted with `ECDH-ES` is decrypted with.
+///
+/// The matching public key, used to encrypt, is returned by `to_public_key`.
+pub struct EcdhPrivateKey(p256::SecretKey);
+
+impl fmt::Debug for EcdhPrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("EcdhPrivateKey(..)")
+    }
+}
+
+impl EcdhPrivateKey {
+    pub fn generate() -> Self {
+        Self(p256::SecretKey::random(&mut OsRng))
+    }
+
+    /// Private key from its big-endian scalar.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, JweError> {
+        p256::SecretKey::from_bytes(bytes)
+            .map(Self)
+            .map_err(|_| JweError::InvalidEcdhKey)
+    }
+
+    pub fn to_public_key(&self) -> PublicKey {
+        let point = self.0.public_key().to_encoded_point(false);
+        SubjectPublicKeyInfo {
+            algorithm: AlgorithmIdentifier::new_elliptic_curve(oids::secp256r1()),
+            subject_public_key: InnerPublicKey::EC(BitStringAsn1(BitString::with_bytes(point.as_bytes()))),
+        }
+        .into()
+    }
+}
+
+fn p256_public_key(alg: JweAlg, public_key: &PublicKey) -> Result<p256::PublicKey, JweError> {
+    let unsupported = || JweError::UnsupportedKey {
+        alg,
+        expected: "a P-256 public key",
+    };
+
+    let spki = public_key.as_inner();
+    match spki.algorithm.parameters() {
+        AlgorithmIdentifierParameters::EC(ECParameters::NamedCurve(curve))
+            if Into::<String>::into(&curve.0) == oids::SECP256R1 => {}
+        _ => return Err(unsupported()),
+    }
+
+    match &spki.subject_public_key {
+        InnerPublicKey::EC(point) => {
+            p256::PublicKey::from_sec1_bytes(point.0.payload_view()).map_err(|_| unsupported())
+        }
+        InnerPublicKey::RSA(_) => Err(unsupported()),
+    }
+}
+
+fn ephemeral_key_to_jwk(public_key: &p256::PublicKey) -> JweEphemeralKey {
+    // uncompressed SEC1 encoding: 0x04 || x || y
+    let point = public_key.to_encoded_point(false);
+    let (x, y) = point.as_bytes()[1..].split_at(P256_FIELD_SIZE);
+    JweEphemeralKey {
+        kty: EC_KEY_TYPE.to_owned(),
+        crv: P256_CURVE.to_owned(),
+        x: base64::encode_config(x, base64::URL_SAFE_NO_PAD),
+        y: base64::encode_config(y, base64::URL_SAFE_NO_PAD),
+    }
+}
+
+fn ephemeral_key_from_jwk(jwk: Option<&JweEphemeralKey>) -> Result<p256::PublicKey, JweError> {
+    let jwk = jwk.ok_or(JweError::InvalidEphemeralKey)?;
+    if jwk.kty != EC_KEY_TYPE || jwk.crv != P256_CURVE {
+        return Err(JweError::InvalidEphemeralKey);
+    }
+
+    let x = base64::decode_config(&jwk.x, base64::URL_SAFE_NO_PAD)?;
+    let y = base64::decode_config(&jwk.y, base64::URL_SAFE_NO_PAD)?;
+    if x.len() != P256_FIELD_SIZE || y.len() != P256_FIELD_SIZE {
+        return Err(JweError::InvalidEphemeralKey);
+    }
+
+    let mut point = Vec::with_capacity(1 + 2 * P256_FIELD_SIZE);
+    point.push(0x04);
+    point.extend_from_slice(&x);
+    point.extend_from_slice(&y);
+    p256::PublicKey::from_sec1_bytes(&point).map_err(|_| JweError::InvalidEphemeralKey)
+}
+
+/// Concat KDF with SHA-256 as used by `ECDH-ES` ([RFC7518 section 4.6.2](https://tools.ietf.org/html/rfc7518#section-4.6.2)).
+fn concat_kdf(shared_secret: &[u8], enc: JweEnc, apu: &[u8], apv: &[u8]) -> Vec<u8> {
+    fn length_prefixed(hasher: &mut Sha256, data: &[u8]) {
+        hasher.update(&(data.len() as u32).to_be_bytes());
+        hasher.update(data);
+    }
+
+    let key_size = enc.key_size();
+    let mut key = Vec::with_capacity(key_size + Sha256::output_size());
+    let mut counter = 1u32;
+    while key.len() < key_size {
+        let mut hasher = Sha256::new();
+        hasher.update(&counter.to_be_bytes());
+        hasher.update(shared_secret);
+        length_prefixed(&mut hasher, enc.name().as_bytes());
+        length_prefixed(&mut hasher, apu);
+        length_prefixed(&mut hasher, apv);
+        hasher.update(&((key_size * 8) as u32).to_be_bytes());
+        key.extend_from_slice(&hasher.finalize());
+        counter += 1;
+    }
+
+    key.truncate(key_size);
+    key
+}
+
+fn decode_agreement_info(info: Option<&String>) -> Result<Vec<u8>, JweError> {
+    match info {
+        Some(info) => Ok(base64::decode_config(info, base64::URL_SAFE_NO_PAD)?),
+        None => Ok(Vec::new()),
+    }
+}
+
+// === RSA-OAEP === //
+
+fn oaep_padding(alg: JweAlg) -> PaddingScheme {
+    match alg {
+        JweAlg::RsaOaep256 => PaddingScheme::new_oaep::<Sha256>(),
+        _ => PaddingScheme::new_oaep::<Sha1>(),
+    }
+}
+
+fn oaep_encrypt(alg: JweAlg, public_key: &PublicKey, cek: &[u8]) -> Result<Vec<u8>, JweError> {
+    let rsa_public_key = match &public_key.as_inner().subject_public_key {
+        InnerPublicKey::RSA(BitStringAsn1Container(key)) => RSAPublicKey::new(
+            BigUint::from_bytes_be(key.modulus.as_unsigned_bytes_be()),
+            BigUint::from_bytes_be(key.public_exponent.as_unsigned_bytes_be()),
+        )?,
+        InnerPublicKey::EC(_) => {
+            return Err(JweError::UnsupportedKey {
+                alg,
+                expected: "a RSA public key",
+            })
+        }
+    };
+
+    Ok(rsa_public_key.encrypt(&mut OsRng, oaep_padding(alg), cek)?)
+}
+
+/// Returns `None` on any decryption error, without telling what went wrong.
+fn oaep_decrypt(alg: JweAlg, private_key: &PrivateKey, encrypted_key: &[u8]) -> Option<Vec<u8>> {
+    let rsa_private_key = match &private_key.as_inner().private_key {
+        private_key_info::PrivateKeyValue::RSA(OctetStringAsn1Container(key)) => RSAPrivateKey::from_components(
+            BigUint::from_bytes_be(key.modulus().as_unsigned_bytes_be()),
+            BigUint::from_bytes_be(key.public_exponent().as_unsigned_bytes_be()),
+            BigUint::from_bytes_be(key.private_exponent().as_unsigned_bytes_be()),
+            key.primes()
+                .iter()
+                .map(|p| BigUint::from_bytes_be(p.as_unsigned_bytes_be()))
+                .collect(),
+        ),
+    };
+
+    rsa_private_key
+        .decrypt_blinded(&mut OsRng, oaep_padding(alg), encrypted_key)
+        .ok()
+}
+
+// === AES-GCM === //
+
+/// Returns ciphertext and authentication tag.
+fn gcm_encrypt(
+    enc: JweEnc,
+    cek: &[u8],
+    iv: &[u8],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>), JweError> {
+    let payload = Payload { msg: plaintext, aad };
+    let nonce = GenericArray::from_slice(iv);
+    let mut sealed = match enc {
+        JweEnc::Aes128Gcm => Aes128Gcm::new(GenericArray::clone_from_slice(cek)).encrypt(nonce, payload),
+        JweEnc::Aes256Gcm => Aes256Gcm::new(GenericArray::clone_from_slice(cek)).encrypt(nonce, payload),
+    }
+    .map_err(|_| JweError::Encryption {
+        context: "AES-GCM error",
+    })?;
+
+    let tag = sealed.split_off(sealed.len() - GCM_TAG_SIZE);
+    Ok((sealed, tag))
+}
+
+fn gcm_decrypt(
+    enc: JweEnc,
+    cek: &[u8],
+    iv: &[u8],
+    aad: &[u8],
+    ciphertext: &[u8],
+    tag: &[u8],
+) -> Result<Vec<u8>, JweError> {
+    if cek.len() != enc.key_size() || iv.len() != GCM_IV_SIZE || tag.len() != GCM_TAG_SIZE {
+        return Err(JweError::DecryptionFailed);
+    }
+
+    let mut sealed = Vec::with_capacity(ciphertext.len() + tag.len());
+    sealed.extend_from_slice(ciphertext);
+    sealed.extend_from_slice(tag);
+    let payload = Payload { msg: &sealed, aad };
+    let nonce = GenericArray::from_slice(iv);
+
+    match enc {
+        JweEnc::Aes128Gcm => Aes128Gcm::new(GenericArray::clone_from_slice(cek)).decrypt(nonce, payload),
+        JweEnc::Aes256Gcm => Aes256Gcm::new(GenericArray::clone_from_slice(cek)).decrypt(nonce, payload),
+    }
+    .map_err(|_| JweError::DecryptionFailed)
+}
+
+// === json web encryption === //
+
+/// JSON Web Encryption using the compact serialization.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Jwe {
+    pub header: JweHeader,
+    pub payload: Vec<u8>,
+}
+
+impl Jwe {
+    pub fn new(alg: JweAlg, enc: JweEnc, payload: Vec<u8>) -> Self {
+        Self {
+            header: JweHeader::new(alg, enc),
+            payload,
+        }
+    }
+
+    /// Encrypt for the owner of `public_key`: a RSA key for `RSA-OAEP` and `RSA-OAEP-256`, a P-256 key for `ECDH-ES`.
+    pub fn encode(&self, public_key: &PublicKey) -> Result<String, JweError> {
+        let mut header = self.header.clone();
+        let enc = header.enc;
+
+        let (cek, encrypted_key) = match header.alg {
+            JweAlg::RsaOaep | JweAlg::RsaOaep256 => {
+                let mut cek = vec![0u8; enc.key_size()];
+                OsRng.fill_bytes(&mut cek);
+                let encrypted_key = oaep_encrypt(header.alg, public_key, &cek)?;
+                (cek, encrypted_key)
+            }
+            JweAlg::EcdhEs => {
+                let recipient_key = p256_public_key(header.alg, public_key)?;
+                let ephemeral_secret = EphemeralSecret::random(&mut OsRng);
+                let shared_secret = ephemeral_secret.diffie_hellman(&recipient_key);
+                header.epk = Some(ephemeral_key_to_jwk(&ephemeral_secret.public_key()));
+
+                let apu = decode_agreement_info(header.apu.as_ref())?;
+                let apv = decode_agreement_info(header.apv.as_ref())?;
+                (concat_kdf(shared_secret.as_bytes(), enc, &apu, &apv), Vec::new())
+            }
+        };
+
+        let header_base64 = base64::encode_config(&serde_json::to_vec(&header)?, base64::URL_SAFE_NO_PAD);
+        let mut iv = [0u8; GCM_IV_SIZE];
+        OsRng.fill_bytes(&mut iv);
+        let (ciphertext, tag) = gcm_encrypt(enc, &cek, &iv, header_base64.as_bytes(), &self.payload)?;
+
+        Ok([
+            header_base64,
+            base64::encode_config(&encrypted_key, base64::URL_SAFE_NO_PAD),
+            base64::encode_config(&iv, base64::URL_SAFE_NO_PAD),
+            base64::encode_config(&ciphertext, base64::URL_SAFE_NO_PAD),
+            base64::encode_config(&tag, base64::URL_SAFE_NO_PAD),
+        ]
+        .join("."))
+    }
+
+    /// Decrypt a token encrypted with `RSA-OAEP` or `RSA-OAEP-256`.
+    pub fn decode(encoded_token: &str, private_key: &PrivateKey) -> Result<Self, JweError> {
+        let parts = TokenParts::split(encoded_token)?;
+
+        let cek = match parts.header.alg {
+            JweAlg::RsaOaep | JweAlg::RsaOaep256 => {
+                let cek = oaep_decrypt(parts.header.alg, private_key, &parts.encrypted_key);
+
+                // RFC7516 section 11.5: carry on with a random key so that an invalid encrypted key
+                // can't be told apart from an invalid ciphertext
+                cek.unwrap_or_else(|| {
+                    let mut cek = vec![0u8; parts.header.enc.key_size()];
+                    OsRng.fill_bytes(&mut cek);
+                    cek
+                })
+            }
+            JweAlg::EcdhEs => {
+                return Err(JweError::UnsupportedKey {
+                    alg: JweAlg::EcdhEs,
+                    expected: "a P-256 private key",
+                })
+            }
+        };
+
+        parts.decrypt(&cek)
+    }
+
+    /// Decrypt a token encrypted with `ECDH-ES`.
+    pub fn decode_with_ecdh_key(encoded_token: &str, private_key: &EcdhPrivateKey) -> Result<Self, JweError> {
+        let parts = TokenParts::split(encoded_token)?;
+
+        if parts.header.alg != JweAlg::EcdhEs {
+            return Err(JweError::UnsupportedKey {
+                alg: parts.header.alg,
+                expected: "a RSA private key",
+            });
+        }
+
+        // direct key agreement: there is no encrypted key
+        if !parts.encrypted_key.is_empty() {
+            return Err(JweError::InvalidEncoding {
+                input: encoded_token.to_owned(),
+            });
+        }
+
+        let ephemeral_key = ephemeral_key_from_jwk(parts.header.epk.as_ref())?;
+        let shared_secret = p256::ecdh::diffie_hellman(private_key.0.secret_scalar(), ephemeral_key.as_affine());
+        let apu = decode_agreement_info(parts.header.apu.as_ref())?;
+        let apv = decode_agreement_info(parts.header.apv.as_ref())?;
+        let cek = concat_kdf(shared_secret.as_bytes(), parts.header.enc, &apu, &apv);
+
+        parts.decrypt(&cek)
+    }
+}
+
+struct TokenParts<'a> {
+    header: JweHeader,
+    header_base64: &'a str,
+    encrypted_key: Vec<u8>,
+    iv: Vec<u8>,
+    ciphertext: Vec<u8>,
+    tag: Vec<u8>,
+}
+
+impl<'a> TokenParts<'a> {
+    fn split(encoded_token: &'a str) -> Result<Self, JweError> {
+        let parts = encoded_token.split('.').collect::<Vec<_>>();
+        if parts.len() != 5 || parts[0].is_empty() || parts[2].is_empty() || parts[4].is_empty() {
+            return Err(JweError::InvalidEncoding {
+                input: encoded_token.to_owned(),
+            });
+        }
+
+        let header_json = base64::decode_config(parts[0], base64::URL_SAFE_NO_PAD)?;
+        Ok(Self {
+            header: serde_json::from_slice(&header_json)?,
+            header_base64: parts[0],
+            encrypted_key: base64::decode_config(parts[1], base64::URL_SAFE_NO_PAD)?,
+            iv: base64::decode_config(parts[2], base64::URL_SAFE_NO_PAD)?,
+            ciphertext: base64::decode_config(parts[3], base64::URL_SAFE_NO_PAD)?,
+            tag: base64::decode_config(parts[4], base64::URL_SAFE_NO_PAD)?,
+        })
+    }
+
+    fn decrypt(self, cek: &[u8]) -> Result<Jwe, JweError> {
+        let payload = gcm_decrypt(
+            self.header.enc,
+            cek,
+            &self.iv,
+            self.header_base64.as_bytes(),
+            &self.ciphertext,
+            &self.tag,
+        )?;
+
+        Ok(Jwe {
+            header: self.header,
+            payload,
+        })
+    }
+}
+
+// === nested JWT === //
+
+const NESTED_JWT_CONTENT_TYPE: &str = "JWT";
+
+impl<'a, C: Serialize> Jwt<'a, C> {
+    /// Sign with `signing_key` then encrypt the signed token for the owner of `recipient_key`
+    /// ([RFC7519 section 5.2](https://tools.ietf.org/html/rfc7519#section-5.2)).
+    pub fn encode_nested(
+        &self,
+        signing_key: &PrivateKey,
+        alg: JweAlg,
+        enc: JweEnc,
+        recipient_key: &PublicKey,
+    ) -> Result<String, JweError> {
+        let signed = self.encode(signing_key)?;
+        let mut jwe = Jwe::new(alg, enc, signed.into_bytes());
+        jwe.header.cty = Some(NESTED_JWT_CONTENT_TYPE.to_owned());
+        jwe.encode(recipient_key)
+    }
+}
+
+impl<'a, C: DeserializeOwned> Jwt<'a, C> {
+    /// Validate the signed JWT nested in a decrypted `jwe`.
+    pub fn decode_nested(jwe: &Jwe, validator: &JwtValidator) -> Result<Self, JweError> {
+        match &jwe.header.cty {
+            Some(cty) if cty.eq_ignore_ascii_case(NESTED_JWT_CONTENT_TYPE) => {}
+            cty => return Err(JweError::NotNestedJwt { cty: cty.clone() }),
+        }
+
+        let signed = std::str::from_utf8(&jwe.payload).map_err(|_| JweError::NotNestedJwt {
+            cty: jwe.header.cty.clone(),
+        })?;
+        Ok(Self::decode(signed, validator)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pem::Pem, signature::SignatureHashType};
+
+    const PLAINTEXT: &[u8] = b"The true sign of intelligence is not knowledge but imagination.";
+
+    fn get_private_key() -> PrivateKey {
+        let pk_pem = crate::test_files::RSA_2048_PK_1.parse::<Pem>().unwrap();
+        PrivateKey::from_pkcs8(pk_pem.data()).unwrap()
+    }
+
+    #[test]
+    fn rsa_oaep_roundtrip() {
+        let private_key = get_private_key();
+        let public_key = private_key.to_public_key();
+
+        for &(alg, enc) in &[
+            (JweAlg::RsaOaep, JweEnc::Aes128Gcm),
+            (JweAlg::RsaOaep, JweEnc::Aes256Gcm),
+            (JweAlg::RsaOaep256, JweEnc::Aes256Gcm),
+        ] {
+            let jwe = Jwe::new(alg, enc, PLAINTEXT.to_vec());
+            let encoded = jwe.encode(&public_key).unwrap();
+            assert_eq!(encoded.split('.').count(), 5);
+
+            let decoded = Jwe::decode(&encoded, &private_key).unwrap();
+            assert_eq!(decoded, jwe);
+        }
+    }
+
+    #[test]
+    fn ecdh_es_roundtrip() {
+        let private_key = EcdhPrivateKey::generate();
+        let public_key = private_key.to_public_key();
+        assert_eq!(PublicKey::from_der(&public_key.to_der().unwrap()).unwrap(), public_key);
+
+        let mut jwe = Jwe::new(JweAlg::EcdhEs, JweEnc::Aes128Gcm, b"Live long and prosper.".to_vec());
+        jwe.header.apu = Some(base64::encode_config(b"Alice", base64::URL_SAFE_NO_PAD));
+        let encoded = jwe.encode(&public_key).unwrap();
+        assert!(encoded.contains(".."), "ECDH-ES token has no encrypted key");
+
+        let decoded = Jwe::decode_with_ecdh_key(&encoded, &private_key).unwrap();
+        assert_eq!(decoded.payload, jwe.payload);
+        assert_eq!(decoded.header.apu, jwe.header.apu);
+        assert_eq!(decoded.header.epk.unwrap().crv, "P-256");
+
+        let err = Jwe::decode_with_ecdh_key(&encoded, &EcdhPrivateKey::generate()).unwrap_err();
+        match err {
+            JweError::DecryptionFailed => {}
+            err => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn concat_kdf_rfc7518_example() {
+        // RFC7518 appendix C
+        let shared_secret = [
+            158, 86, 217, 29, 129, 113, 53, 211, 114, 131, 66, 131, 191, 132, 38, 156, 251, 49, 110, 163, 218, 128,
+            106, 72, 246, 218, 167, 121, 140, 254, 144, 196,
+        ];
+        let cek = concat_kdf(&shared_secret, JweEnc::Aes128Gcm, b"Alice", b"Bob");
+        assert_eq!(
+            base64::encode_config(&cek, base64::URL_SAFE_NO_PAD),
+            "VqqN6vgjbSBcIijNcacQGg"
+        );
+    }
+
+    #[test]
+    fn rfc7516_a1_content_decryption() {
+        // RFC7516 appendix A.1 with the content encryption key given there (the RSA key isn't one of our test keys)
+        let cek = [
+            177, 161, 244, 128, 84, 143, 225, 115, 63, 180, 3, 255, 107, 154, 212, 246, 138, 7, 110, 91, 112, 46, 34,
+            105, 47, 130, 203, 46, 122, 234, 64, 252,
+        ];
+        let token = "eyJhbGciOiJSU0EtT0FFUCIsImVuYyI6IkEyNTZHQ00ifQ..48V1_ALb6US04U3b.\
+                     5eym8TW_c8SuK0ltJ3rpYIzOeDQz7TALvtu6UG9oMo4vpzs9tX_EFShS8iB7j6jiSdiwkIr3ajwQzaBtQD_A.\
+                     XFBoMYUZodetZdvTiFvSkQ";
+
+        let decoded = TokenParts::split(token).unwrap().decrypt(&cek).unwrap();
+        assert_eq!(decoded.header, JweHeader::new(JweAlg::RsaOaep, JweEnc::Aes256Gcm));
+        assert_eq!(decoded.payload, PLAINTEXT);
+    }
+
+    #[test]
+    fn rsa_oaep_decryption_vectors() {
+        // encrypted for RSA_2048_PK_1 by another implementation (pyca/cryptography)
+        let private_key = get_private_key();
+        for &(token, alg, enc) in &[
+            (
+                include_str!("../../../test_assets/jose/jwe_rsa_oaep.txt"),
+                JweAlg::RsaOaep,
+                JweEnc::Aes256Gcm,
+            ),
+            (
+                include_str!("../../../test_assets/jose/jwe_rsa_oaep_256.txt"),
+                JweAlg::RsaOaep256,
+                JweEnc::Aes128Gcm,
+            ),
+        ] {
+            let decoded = Jwe::decode(token, &private_key).unwrap();
+            assert_eq!(decoded.header, JweHeader::new(alg, enc));
+            assert_eq!(decoded.payload, PLAINTEXT);
+        }
+    }
+
+    #[test]
+    fn ecdh_es_decryption_vector() {
+        // Bob's key and Alice's ephemeral key of RFC7518 appendix C, with the same agreement info
+        let bob_key = EcdhPrivateKey::from_bytes(
+            &base64::decode_config("VEmDZpDXXK8p8N0Cndsxs924q6nS1RXFASRl6BfUqdw", base64::URL_SAFE_NO_PAD).unwrap(),
+        )
+        .unwrap();
+        let token = include_str!("../../../test_assets/jose/jwe_ecdh_es.txt");
+
+        let decoded = Jwe::decode_with_ecdh_key(token, &bob_key).unwrap();
+        assert_eq!(decoded.header.apu.as_deref(), Some("QWxpY2U"));
+        assert_eq!(
+            decoded.header.epk.unwrap().x,
+            "gI0GAILBdu7T53akrFmMyGcsF3n5dO7MmwNBHKW5SV0"
+        );
+        assert_eq!(decoded.payload, PLAINTEXT);
+    }
+
+    #[test]
+    fn altered_token_is_rejected() {
+        let private_key = get_private_key();
+        let jwe = Jwe::new(JweAlg::RsaOaep, JweEnc::Aes128Gcm, b"payload".to_vec());
+        let encoded = jwe.encode(&private_key.to_public_key()).unwrap();
+
+        let mut parts = encoded.split('.').map(str::to_owned).collect::<Vec<_>>();
+        let mut ciphertext = base64::decode_config(&parts[3], base64::URL_SAFE_NO_PAD).unwrap();
+        ciphertext[0] ^= 1;
+        parts[3] = base64::encode_config(&ciphertext, base64::URL_SAFE_NO_PAD);
+        let err = Jwe::decode(&parts.join("."), &private_key).unwrap_err();
+        match err {
+            JweError::DecryptionFailed => {}
+            err => panic!("unexpected error: {}", err),
+        }
+
+        let mut parts = encoded.split('.').map(str::to_owned).collect::<Vec<_>>();
+        let mut encrypted_key = base64::decode_config(&parts[1], base64::URL_SAFE_NO_PAD).unwrap();
+        encrypted_key[10] ^= 1;
+        parts[1] = base64::encode_config(&encrypted_key, base64::URL_SAFE_NO_PAD);
+        let err = Jwe::decode(&parts.join("."), &private_key).unwrap_err();
+        match err {
+            JweError::DecryptionFailed => {}
+            err => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn key_type_mismatch() {
+        let rsa_key = get_private_key();
+        let ecdh_key = EcdhPrivateKey::generate();
+
+        let err = Jwe::new(JweAlg::EcdhEs, JweEnc::Aes128Gcm, Vec::new())
+            .encode(&rsa_key.to_public_key())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "ECDH-ES requires a P-256 public key");
+
+        let err = Jwe::new(JweAlg::RsaOaep, JweEnc::Aes128Gcm, Vec::new())
+            .encode(&ecdh_key.to_public_key())
+            .unwrap_err();
+        assert_eq!(err.to_string(), "RSA-OAEP requires a RSA public key");
+
+        let encoded = Jwe::new(JweAlg::EcdhEs, JweEnc::Aes128Gcm, Vec::new())
+            .encode(&ecdh_key.to_public_key())
+            .unwrap();
+        let err = Jwe::decode(&encoded, &rsa_key).unwrap_err();
+        assert_eq!(err.to_string(), "ECDH-ES requires a P-256 private key");
+    }
+
+    #[test]
+    fn nested_jwt_roundtrip() {
+        let signing_key = get_private_key();
+        let recipient_key = EcdhPrivateKey::generate();
+        let claims = serde_json::json!({ "sub": "1234567890", "admin": true });
+
+        let jwt = Jwt::new(SignatureHashType::RsaSha256, claims.clone());
+        let encoded = jwt
+            .encode_nested(
+                &signing_key,
+                JweAlg::EcdhEs,
+                JweEnc::Aes256Gcm,
+                &recipient_key.to_public_key(),
+            )
+            .unwrap();
+
+        let jwe = Jwe::decode_with_ecdh_key(&encoded, &recipient_key).unwrap();
+        assert_eq!(jwe.header.cty.as_deref(), Some("JWT"));
+
+        let public_key = signing_key.to_public_key();
+        let validator = JwtValidator::signature_only(&public_key);
+        let decoded = Jwt::<serde_json::Value>::decode_nested(&jwe, &validator).unwrap();
+        assert_eq!(decoded.view_claims(), &claims);
+
+        let plain = Jwe::new(JweAlg::EcdhEs, JweEnc::Aes256Gcm, b"not a JWT".to_vec());
+        let err = Jwt::<serde_json::Value>::decode_nested(&plain, &validator).unwrap_err();
+        match err {
+            JweError::NotNestedJwt { cty: None } => {}
+            err => panic!("unexpected error: {}", err),
+        }
+    }
+}
@@ -1,4 +1,8 @@
-use crate::{key::PublicKey, private::SubjectPublicKeyInfo, signature::SignatureHashType};
+use crate::{
+    key::{KeyError, PrivateKey, PublicKey},
+    private::SubjectPublicKeyInfo,
+    signature::SignatureHashType,
+};
 use base64::DecodeError;
 use serde::{Deserialize, Serialize};
 use snafu::Snafu;
@@ -18,6 +22,10 @@ pub enum JwkError {
     /// unsupported algorithm
     #[snafu(display("unsupported algorithm: {}", algorithm))]
     UnsupportedAlgorithm { algorithm: &'static str },
+
+    /// key error
+    #[snafu(display("key error: {}", source))]
+    Key { source: KeyError },
 }
 
 impl From<serde_json::Error> for JwkError {
@@ -32,6 +40,12 @@ impl From<DecodeError> for JwkError {
     }
 }
 
+impl From<KeyError> for JwkError {
+    fn from(e: KeyError) -> Self {
+        Self::Key { source: e }
+    }
+}
+
 // === key type === //
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -146,23 +160,68 @@ impl Jwk {
         }
     }
 
+    /// Generates a RSA private key and its JWK identified by its thumbprint.
+    ///
+    /// **Beware**: this is insanely slow in debug builds.
+    pub fn generate_rsa(bits: usize) -> Result<(PrivateKey, Self), JwkError> {
+        let private_key = PrivateKey::generate_rsa(bits)?;
+        let jwk = Self::from_public_key(&private_key.to_public_key())?;
+        let key_id = jwk.thumbprint()?;
+        Ok((private_key, jwk.with_key_id(key_id)))
+    }
+
+    pub fn with_algorithm(self, algorithm: SignatureHashType) -> Self {
+        Self {
+            algorithm: Some(algorithm),
+            ..self
+        }
+    }
+
+    pub fn with_pub_key_use(self, pub_key_use: JwkPubKeyUse) -> Self {
+        Self {
+            pub_key_use: Some(pub_key_use),
+            ..self
+        }
+    }
+
+    pub fn with_key_operations(self, key_operations: Vec<JwkKeyOps>) -> Self {
+        Self {
+            key_operations: Some(key_operations),
+            ..self
+        }
+    }
+
+    pub fn with_key_id<S: Into<String>>(self, key_id: S) -> Self {
+        Self {
+            key_id: Some(key_id.into()),
+            ..self
+        }
+    }
+
+    /// JWK SHA-256 thumbprint as defined by [RFC7638](https://tools.ietf.org/html/rfc7638)
+    pub fn thumbprint(&self) -> Result<String, JwkError> {
+        use sha2::{Digest, Sha256};
+
+        // required members only, in lexicographic order and without whitespace
+        let canonical_json = match &self.key {
+            JwkKeyType::Rsa(rsa) => serde_json::to_string(&serde_json::json!({
+                "e": rsa.e,
+                "kty": "RSA",
+                "n": rsa.n,
+            }))?,
+        };
+
+        let digest = Sha256::digest(canonical_json.as_bytes());
+        Ok(base64::encode_config(&digest, base64::URL_SAFE_NO_PAD))
+    }
+
     pub fn from_json(json: &str) -> Result<Self, JwkError> {
         Ok(serde_json::from_str(json)?)
     }
 
+    /// Shorthand for `public_key.export().jwk()`.
     pub fn from_public_key(public_key: &PublicKey) -> Result<Self, JwkError> {
-        use crate::private::subject_public_key_info::PublicKey as SerdePublicKey;
-        use picky_asn1::wrapper::BitStringAsn1Container;
-
-        match &public_key.as_inner().subject_public_key {
-            SerdePublicKey::RSA(BitStringAsn1Container(rsa)) => Ok(Self::new(JwkKeyType::new_rsa_key(
-                rsa.modulus.as_signed_bytes_be(),
-                rsa.public_exponent.as_signed_bytes_be(),
-            ))),
-            SerdePublicKey::EC(_) => Err(JwkError::UnsupportedAlgorithm {
-                algorithm: "elliptic curves",
-            }),
-        }
+        Ok(public_key.export().jwk()?)
     }
 
     pub fn to_json(&self) -> Result<String, JwkError> {
@@ -318,6 +377,47 @@ mod tests {
         }
     }
 
+    #[test]
+    fn builder_and_thumbprint() {
+        let jwk = Jwk::new(JwkKeyType::new_rsa_key_from_base64_url(
+            RSA_MODULUS.into(),
+            RSA_PUBLIC_EXPONENT.into(),
+        ))
+        .with_algorithm(SignatureHashType::RsaSha256)
+        .with_pub_key_use(JwkPubKeyUse::Signature)
+        .with_key_operations(vec![JwkKeyOps::Verify]);
+
+        let thumbprint = jwk.thumbprint().unwrap();
+        assert_eq!(thumbprint.len(), 43);
+
+        let jwk = jwk.with_key_id(thumbprint.clone());
+        let json = serde_json::to_value(&jwk).unwrap();
+        assert_eq!(json["alg"], "RS256");
+        assert_eq!(json["use"], "sig");
+        assert_eq!(json["key_ops"], serde_json::json!(["verify"]));
+        assert_eq!(json["kid"], thumbprint.as_str());
+
+        // metadata isn't part of the thumbprint
+        assert_eq!(jwk.thumbprint().unwrap(), thumbprint);
+    }
+
+    #[test]
+    fn rfc7638_thumbprint() {
+        // example of RFC7638 section 3.1
+        let jwk = Jwk::new(JwkKeyType::new_rsa_key_from_base64_url(
+            "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4\
+             n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZH\
+             zu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8a\
+             wapJzKnqDKgw"
+                .into(),
+            "AQAB".into(),
+        ))
+        .with_algorithm(SignatureHashType::RsaSha256)
+        .with_key_id("2011-04-29");
+
+        assert_eq!(jwk.thumbprint().unwrap(), "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs");
+    }
+
     #[test]
     fn rsa_key() {
         let expected = get_jwk_set();
@@ -0,0 +1,343 @@
+use crate::{
+    key::{PrivateKey, PublicKey},
+    signature::{SignatureError, SignatureHashType},
+};
+use base64::DecodeError;
+use serde::{Deserialize, Serialize};
+use snafu::Snafu;
+
+// === error type === //
+
+#[derive(Debug, Snafu)]
+#[non_exhaustive]
+pub enum JwsError {
+    /// Json error
+    #[snafu(display("JSON error: {}", source))]
+    Json { source: serde_json::Error },
+
+    /// signature error
+    #[snafu(display("signature error: {}", source))]
+    Signature { source: SignatureError },
+
+    /// invalid token encoding
+    #[snafu(display("input isn't a valid token string: {}", input))]
+    InvalidEncoding { input: String },
+
+    /// couldn't decode base64
+    #[snafu(display("couldn't decode base64: {}", source))]
+    Base64Decoding { source: DecodeError },
+
+    /// a critical header parameter isn't understood or is missing
+    #[snafu(display("critical header parameter `{}` is not supported or missing", parameter))]
+    UnsupportedCriticalParameter { parameter: String },
+
+    /// `b64` header parameter is present but not listed as critical
+    #[snafu(display("`b64` header parameter must be listed in `crit`"))]
+    B64NotCritical,
+
+    /// unencoded payload can't be embedded in the compact serialization
+    #[snafu(display("unencoded payload can't be embedded: {}", reason))]
+    UnencodedPayloadNotEmbeddable { reason: &'static str },
+}
+
+impl From<serde_json::Error> for JwsError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json { source: e }
+    }
+}
+
+impl From<SignatureError> for JwsError {
+    fn from(e: SignatureError) -> Self {
+        Self::Signature { source: e }
+    }
+}
+
+impl From<DecodeError> for JwsError {
+    fn from(e: DecodeError) -> Self {
+        Self::Base64Decoding { source: e }
+    }
+}
+
+// === header === //
+
+const B64_PARAMETER: &str = "b64";
+
+/// Header parameters this implementation understands when listed in `crit`
+const SUPPORTED_CRITICAL_PARAMETERS: &[&str] = &[B64_PARAMETER];
+
+/// JOSE header as defined by [RFC7515](https://tools.ietf.org/html/rfc7515#section-4)
+/// extended with the `b64` parameter from [RFC7797](https://tools.ietf.org/html/rfc7797).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JwsHeader {
+    pub alg: SignatureHashType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub typ: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cty: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+    /// Whether the payload is base64url-encoded (defaults to `true` when absent)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub b64: Option<bool>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub crit: Vec<String>,
+}
+
+impl JwsHeader {
+    pub fn new(alg: SignatureHashType) -> Self {
+        Self {
+            alg,
+            typ: None,
+            cty: None,
+            kid: None,
+            b64: None,
+            crit: Vec::new(),
+        }
+    }
+
+    pub fn is_payload_encoded(&self) -> bool {
+        self.b64.unwrap_or(true)
+    }
+
+    fn check_critical_parameters(&self) -> Result<(), JwsError> {
+        for parameter in &self.crit {
+            let present = match parameter.as_str() {
+                B64_PARAMETER => self.b64.is_some(),
+                _ => false,
+            };
+
+            if !present || !SUPPORTED_CRITICAL_PARAMETERS.contains(&parameter.as_str()) {
+                return Err(JwsError::UnsupportedCriticalParameter {
+                    parameter: parameter.clone(),
+                });
+            }
+        }
+
+        // RFC7797 section 6: b64 must always be understood, hence must be listed as critical
+        if self.b64.is_some() && !self.crit.iter().any(|p| p == B64_PARAMETER) {
+            return Err(JwsError::B64NotCritical);
+        }
+
+        Ok(())
+    }
+}
+
+// === json web signature === //
+
+/// JSON Web Signature using the compact serialization.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Jws {
+    pub header: JwsHeader,
+    pub payload: Vec<u8>,
+}
+
+impl Jws {
+    pub fn new(alg: SignatureHashType, payload: Vec<u8>) -> Self {
+        Self {
+            header: JwsHeader::new(alg),
+            payload,
+        }
+    }
+
+    /// Sign the payload as-is instead of its base64url encoding (`b64: false`).
+    pub fn unencoded_payload(mut self) -> Self {
+        self.header.b64 = Some(false);
+        if !self.header.crit.iter().any(|p| p == B64_PARAMETER) {
+            self.header.crit.push(B64_PARAMETER.to_owned());
+        }
+        self
+    }
+
+    /// Encode with the payload embedded in the compact serialization.
+    ///
+    /// An unencoded payload must be valid UTF-8 and must not contain any period character.
+    pub fn encode(&self, private_key: &PrivateKey) -> Result<String, JwsError> {
+        let payload = if self.header.is_payload_encoded() {
+            base64::encode_config(&self.payload, base64::URL_SAFE_NO_PAD)
+        } else {
+            let payload = std::str::from_utf8(&self.payload).map_err(|_| JwsError::UnencodedPayloadNotEmbeddable {
+                reason: "payload isn't valid utf8",
+            })?;
+
+            if payload.contains('.') {
+                return Err(JwsError::UnencodedPayloadNotEmbeddable {
+                    reason: "payload contains a period character",
+                });
+            }
+
+            payload.to_owned()
+        };
+
+        let (header_base64, signature_base64) = self.sign(private_key)?;
+        Ok([header_base64, payload, signature_base64].join("."))
+    }
+
+    /// Encode with a detached payload as described in [RFC7515 appendix F](https://tools.ietf.org/html/rfc7515#appendix-F).
+    pub fn encode_detached(&self, private_key: &PrivateKey) -> Result<String, JwsError> {
+        let (header_base64, signature_base64) = self.sign(private_key)?;
+        Ok([header_base64, String::new(), signature_base64].join("."))
+    }
+
+    /// Decode and check signature of a token with an embedded payload.
+    pub fn decode(encoded_token: &str, public_key: &PublicKey) -> Result<Self, JwsError> {
+        let (header_base64, payload, signature_base64) = split_token(encoded_token)?;
+
+        let header = decode_header(header_base64)?;
+        let payload = if header.is_payload_encoded() {
+            base64::decode_config(payload, base64::URL_SAFE_NO_PAD)?
+        } else {
+            payload.as_bytes().to_vec()
+        };
+
+        verify(&header, header_base64, &payload, signature_base64, public_key)?;
+
+        Ok(Self { header, payload })
+    }
+
+    /// Decode and check signature of a token with a detached payload.
+    pub fn decode_detached(encoded_token: &str, payload: &[u8], public_key: &PublicKey) -> Result<Self, JwsError> {
+        let (header_base64, embedded_payload, signature_base64) = split_token(encoded_token)?;
+
+        if !embedded_payload.is_empty() {
+            return Err(JwsError::InvalidEncoding {
+                input: encoded_token.to_owned(),
+            });
+        }
+
+        let header = decode_header(header_base64)?;
+        verify(&header, header_base64, payload, signature_base64, public_key)?;
+
+        Ok(Self {
+            header,
+            payload: payload.to_vec(),
+        })
+    }
+
+    fn sign(&self, private_key: &PrivateKey) -> Result<(String, String), JwsError> {
+        self.header.check_critical_parameters()?;
+        let header_base64 = base64::encode_config(&serde_json::to_vec(&self.header)?, base64::URL_SAFE_NO_PAD);
+        let signing_input = signing_input(&self.header, &header_base64, &self.payload);
+        let signature = self.header.alg.sign(&signing_input, private_key)?;
+        let signature_base64 = base64::encode_config(&signature, base64::URL_SAFE_NO_PAD);
+        Ok((header_base64, signature_base64))
+    }
+}
+
+fn split_token(encoded_token: &str) -> Result<(&str, &str, &str), JwsError> {
+    let mut parts = encoded_token.splitn(3, '.');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(header), Some(payload), Some(signature))
+            if !header.is_empty() && !signature.is_empty() && !signature.contains('.') =>
+        {
+            Ok((header, payload, signature))
+        }
+        _ => Err(JwsError::InvalidEncoding {
+            input: encoded_token.to_owned(),
+        }),
+    }
+}
+
+fn decode_header(header_base64: &str) -> Result<JwsHeader, JwsError> {
+    let header_json = base64::decode_config(header_base64, base64::URL_SAFE_NO_PAD)?;
+    let header = serde_json::from_slice::<JwsHeader>(&header_json)?;
+    header.check_critical_parameters()?;
+    Ok(header)
+}
+
+fn signing_input(header: &JwsHeader, header_base64: &str, payload: &[u8]) -> Vec<u8> {
+    let mut signing_input = Vec::with_capacity(header_base64.len() + 1 + payload.len());
+    signing_input.extend_from_slice(header_base64.as_bytes());
+    signing_input.push(b'.');
+    if header.is_payload_encoded() {
+        signing_input.extend_from_slice(base64::encode_config(payload, base64::URL_SAFE_NO_PAD).as_bytes());
+    } else {
+        signing_input.extend_from_slice(payload);
+    }
+    signing_input
+}
+
+fn verify(
+    header: &JwsHeader,
+    header_base64: &str,
+    payload: &[u8],
+    signature_base64: &str,
+    public_key: &PublicKey,
+) -> Result<(), JwsError> {
+    let signature = base64::decode_config(signature_base64, base64::URL_SAFE_NO_PAD)?;
+    let signing_input = signing_input(header, header_base64, payload);
+    header.alg.verify(public_key, &signing_input, &signature)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pem::Pem;
+
+    fn get_private_key() -> PrivateKey {
+        let pk_pem = crate::test_files::RSA_2048_PK_1.parse::<Pem>().unwrap();
+        PrivateKey::from_pkcs8(pk_pem.data()).unwrap()
+    }
+
+    #[test]
+    fn encoded_payload_roundtrip() {
+        let private_key = get_private_key();
+        let jws = Jws::new(SignatureHashType::RsaSha256, b"$.02".to_vec());
+        let encoded = jws.encode(&private_key).unwrap();
+        let decoded = Jws::decode(&encoded, &private_key.to_public_key()).unwrap();
+        assert_eq!(decoded, jws);
+    }
+
+    #[test]
+    fn unencoded_payload_roundtrip() {
+        let private_key = get_private_key();
+        let jws = Jws::new(SignatureHashType::RsaSha256, b"hello world".to_vec()).unencoded_payload();
+        let encoded = jws.encode(&private_key).unwrap();
+
+        let mut parts = encoded.split('.');
+        let header_json = base64::decode_config(parts.next().unwrap(), base64::URL_SAFE_NO_PAD).unwrap();
+        assert_eq!(header_json, br#"{"alg":"RS256","b64":false,"crit":["b64"]}"#.to_vec());
+        assert_eq!(parts.next().unwrap(), "hello world");
+
+        let decoded = Jws::decode(&encoded, &private_key.to_public_key()).unwrap();
+        assert_eq!(decoded, jws);
+    }
+
+    #[test]
+    fn unencoded_detached_payload() {
+        let private_key = get_private_key();
+        let public_key = private_key.to_public_key();
+        let jws = Jws::new(SignatureHashType::RsaSha256, b"$.02".to_vec()).unencoded_payload();
+
+        let err = jws.encode(&private_key).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "unencoded payload can't be embedded: payload contains a period character"
+        );
+
+        let encoded = jws.encode_detached(&private_key).unwrap();
+        assert!(encoded.contains(".."));
+        Jws::decode_detached(&encoded, b"$.02", &public_key).unwrap();
+
+        let err = Jws::decode_detached(&encoded, b"$.03", &public_key).err().unwrap();
+        assert_eq!(err.to_string(), "signature error: invalid signature");
+    }
+
+    #[test]
+    fn critical_parameters_check() {
+        let private_key = get_private_key();
+
+        let mut jws = Jws::new(SignatureHashType::RsaSha256, b"payload".to_vec());
+        jws.header.b64 = Some(false);
+        let err = jws.encode(&private_key).err().unwrap();
+        assert_eq!(err.to_string(), "`b64` header parameter must be listed in `crit`");
+
+        let mut jws = Jws::new(SignatureHashType::RsaSha256, b"payload".to_vec());
+        jws.header.crit.push("exp".to_owned());
+        let err = jws.encode(&private_key).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "critical header parameter `exp` is not supported or missing"
+        );
+    }
+}
@@ -1,11 +1,15 @@
 use crate::{
+    clock,
     key::{PrivateKey, PublicKey},
     signature::{SignatureError, SignatureHashType},
 };
 use base64::DecodeError;
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde::{
+    de::{self, DeserializeOwned, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, Visitor},
+    Deserialize, Serialize,
+};
 use snafu::Snafu;
-use std::{borrow::Cow, fmt};
+use std::{borrow::Cow, collections::HashMap, fmt};
 
 // === error type === //
 
@@ -62,6 +66,14 @@ pub enum JwtError {
     /// validator is invalid
     #[snafu(display("invalid validator: {}", description))]
     InvalidValidator { description: &'static str },
+
+    /// a claim required by the validator is missing
+    #[snafu(display("claim `{}` is missing", claim))]
+    ClaimMissing { claim: String },
+
+    /// a claim doesn't have the value expected by the validator
+    #[snafu(display("claim `{}` has an unexpected value: {}", claim, value))]
+    UnexpectedClaimValue { claim: String, value: serde_json::Value },
 }
 
 impl From<rsa::errors::Error> for JwtError {
@@ -112,6 +124,15 @@ impl JwtDate {
         Self { numeric_date, leeway }
     }
 
+    /// Current date according to the active `clock::Clock`.
+    pub fn now() -> Self {
+        Self::new(clock::now())
+    }
+
+    pub fn now_with_leeway(leeway: u16) -> Self {
+        Self::new_with_leeway(clock::now(), leeway)
+    }
+
     pub const fn is_before(&self, other_numeric_date: i64) -> bool {
         self.numeric_date <= other_numeric_date + self.leeway as i64
     }
@@ -138,12 +159,85 @@ enum CheckStrictness {
     Required,
 }
 
+#[derive(Debug, Clone)]
+enum ClaimCheck<'a> {
+    Present(&'a str),
+    Equals(&'a str, serde_json::Value),
+    Contains(&'a str, &'a str),
+}
+
+impl<'a> ClaimCheck<'a> {
+    fn claim(&self) -> &'a str {
+        match self {
+            ClaimCheck::Present(claim) | ClaimCheck::Equals(claim, _) | ClaimCheck::Contains(claim, _) => *claim,
+        }
+    }
+}
+
+/// Deserializes the values of the given claims only, other claims are skipped.
+struct SelectedClaims<'n, 'c>(&'n [&'c str]);
+
+impl<'de, 'n, 'c> DeserializeSeed<'de> for SelectedClaims<'n, 'c> {
+    type Value = HashMap<&'c str, serde_json::Value>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, 'n, 'c> Visitor<'de> for SelectedClaims<'n, 'c> {
+    type Value = HashMap<&'c str, serde_json::Value>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a JSON object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut claims = HashMap::with_capacity(self.0.len());
+        while let Some(name) = map.next_key_seed(ClaimName(self.0))? {
+            match name {
+                Some(name) => {
+                    claims.insert(name, map.next_value()?);
+                }
+                None => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(claims)
+    }
+}
+
+/// Claim name, if it is one of the given names.
+struct ClaimName<'n, 'c>(&'n [&'c str]);
+
+impl<'de, 'n, 'c> DeserializeSeed<'de> for ClaimName<'n, 'c> {
+    type Value = Option<&'c str>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_str(self)
+    }
+}
+
+impl<'de, 'n, 'c> Visitor<'de> for ClaimName<'n, 'c> {
+    type Value = Option<&'c str>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a claim name")
+    }
+
+    fn visit_str<E: de::Error>(self, name: &str) -> Result<Self::Value, E> {
+        Ok(self.0.iter().copied().find(|claim| *claim == name))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct JwtValidator<'a> {
     public_key: Option<&'a PublicKey>,
     current_date: Option<&'a JwtDate>,
     expiration_claim: CheckStrictness,
     not_before_claim: CheckStrictness,
+    claim_checks: Vec<ClaimCheck<'a>>,
 }
 
 pub const DANGEROUS_VALIDATOR: JwtValidator<'static> = JwtValidator::dangerous();
@@ -156,6 +250,7 @@ impl<'a> JwtValidator<'a> {
             current_date: Some(current_date),
             expiration_claim: CheckStrictness::Required,
             not_before_claim: CheckStrictness::Required,
+            claim_checks: Vec::new(),
         }
     }
 
@@ -166,6 +261,7 @@ impl<'a> JwtValidator<'a> {
             current_date: Some(current_date),
             expiration_claim: CheckStrictness::Optional,
             not_before_claim: CheckStrictness::Optional,
+            claim_checks: Vec::new(),
         }
     }
 
@@ -176,6 +272,7 @@ impl<'a> JwtValidator<'a> {
             current_date: None,
             expiration_claim: CheckStrictness::Ignored,
             not_before_claim: CheckStrictness::Ignored,
+            claim_checks: Vec::new(),
         }
     }
 
@@ -186,6 +283,7 @@ impl<'a> JwtValidator<'a> {
             current_date: None,
             expiration_claim: CheckStrictness::Ignored,
             not_before_claim: CheckStrictness::Ignored,
+            claim_checks: Vec::new(),
         }
     }
 
@@ -246,6 +344,26 @@ impl<'a> JwtValidator<'a> {
             ..self
         }
     }
+
+    /// Reject token if given claim is missing.
+    pub fn required_claim(mut self, claim: &'a str) -> Self {
+        self.claim_checks.push(ClaimCheck::Present(claim));
+        self
+    }
+
+    /// Reject token if given claim is missing or isn't equal to `value`.
+    pub fn claim_equals<V: Into<serde_json::Value>>(mut self, claim: &'a str, value: V) -> Self {
+        self.claim_checks.push(ClaimCheck::Equals(claim, value.into()));
+        self
+    }
+
+    /// Reject token if given claim doesn't contain `value`.
+    ///
+    /// Claim is either an array of strings or a space-delimited string (e.g. OAuth 2.0 `scope`).
+    pub fn claim_contains(mut self, claim: &'a str, value: &'a str) -> Self {
+        self.claim_checks.push(ClaimCheck::Contains(claim, value));
+        self
+    }
 }
 
 // === json web token === //
@@ -334,9 +452,12 @@ impl<'a, C: Serialize> Jwt<'a, C> {
     }
 }
 
-impl<'a, C: DeserializeOwned> Jwt<'a, C> {
-    /// Validate using validator and returns decoded JWT.
-    pub fn decode(encoded_token: &str, validator: &JwtValidator) -> Result<Self, JwtError> {
+impl<'a, C> Jwt<'a, C> {
+    /// Checks token encoding, header and signature then returns the header and the decoded claims json.
+    fn decode_header_and_claims(
+        encoded_token: &str,
+        validator: &JwtValidator,
+    ) -> Result<(Header<'static>, Vec<u8>), JwtError> {
         let first_dot_idx = encoded_token.find('.').ok_or_else(|| JwtError::InvalidEncoding {
             input: encoded_token.to_owned(),
         })?;
@@ -369,21 +490,34 @@ impl<'a, C: DeserializeOwned> Jwt<'a, C> {
         let claims_json =
             base64::decode_config(&encoded_token[first_dot_idx + 1..last_dot_idx], base64::URL_SAFE_NO_PAD)?;
 
-        let claims = match (
+        Ok((header, claims_json))
+    }
+
+    /// Checks registered date claims without deserializing the whole claims set.
+    fn validate_registered_claims(claims_json: &[u8], validator: &JwtValidator) -> Result<(), JwtError> {
+        // other claims are skipped by the deserializer
+        #[derive(Deserialize)]
+        struct RegisteredDateClaims {
+            #[serde(default)]
+            nbf: Option<serde_json::Value>,
+            #[serde(default)]
+            exp: Option<serde_json::Value>,
+        }
+
+        match (
             validator.current_date,
             validator.not_before_claim,
             validator.expiration_claim,
         ) {
             (None, CheckStrictness::Required, _) | (None, _, CheckStrictness::Required) => {
-                return Err(JwtError::InvalidValidator {
+                Err(JwtError::InvalidValidator {
                     description: "current date is missing",
                 })
             }
             (Some(current_date), nbf_strictness, exp_strictness) => {
-                let claims = serde_json::from_slice::<serde_json::Value>(&claims_json)?;
+                let claims = serde_json::from_slice::<RegisteredDateClaims>(claims_json)?;
 
-                let nbf_opt = claims.get(NOT_BEFORE_CLAIM);
-                match (nbf_strictness, nbf_opt) {
+                match (nbf_strictness, claims.nbf) {
                     (CheckStrictness::Ignored, _) | (CheckStrictness::Optional, None) => {}
                     (CheckStrictness::Required, None) => {
                         return Err(JwtError::RequiredClaimMissing {
@@ -403,8 +537,7 @@ impl<'a, C: DeserializeOwned> Jwt<'a, C> {
                     }
                 }
 
-                let exp_opt = claims.get(EXPIRATION_TIME_CLAIM);
-                match (exp_strictness, exp_opt) {
+                match (exp_strictness, claims.exp) {
                     (CheckStrictness::Ignored, _) | (CheckStrictness::Optional, None) => {}
                     (CheckStrictness::Required, None) => {
                         return Err(JwtError::RequiredClaimMissing {
@@ -424,11 +557,62 @@ impl<'a, C: DeserializeOwned> Jwt<'a, C> {
                     }
                 }
 
-                serde_json::value::from_value(claims)?
+                Ok(())
+            }
+            (None, _, _) => Ok(()),
+        }
+    }
+
+    fn validate_custom_claims(claims_json: &[u8], validator: &JwtValidator) -> Result<(), JwtError> {
+        if validator.claim_checks.is_empty() {
+            return Ok(());
+        }
+
+        let names = validator
+            .claim_checks
+            .iter()
+            .map(ClaimCheck::claim)
+            .collect::<Vec<&str>>();
+        let mut deserializer = serde_json::Deserializer::from_slice(claims_json);
+        let claims = SelectedClaims(&names).deserialize(&mut deserializer)?;
+        deserializer.end()?;
+
+        for check in &validator.claim_checks {
+            let claim = check.claim();
+
+            let value = claims.get(claim).ok_or_else(|| JwtError::ClaimMissing {
+                claim: claim.to_owned(),
+            })?;
+
+            let is_valid = match check {
+                ClaimCheck::Present(_) => true,
+                ClaimCheck::Equals(_, expected) => value == expected,
+                ClaimCheck::Contains(_, expected) => match value {
+                    serde_json::Value::String(s) => s.split(' ').any(|item| item == *expected),
+                    serde_json::Value::Array(items) => items.iter().any(|item| item.as_str() == Some(*expected)),
+                    _ => false,
+                },
+            };
+
+            if !is_valid {
+                return Err(JwtError::UnexpectedClaimValue {
+                    claim: claim.to_owned(),
+                    value: value.clone(),
+                });
             }
-            (None, _, _) => serde_json::from_slice(&claims_json)?,
-        };
+        }
+
+        Ok(())
+    }
+}
 
+impl<'a, C: DeserializeOwned> Jwt<'a, C> {
+    /// Validate using validator and returns decoded JWT.
+    pub fn decode(encoded_token: &str, validator: &JwtValidator) -> Result<Self, JwtError> {
+        let (header, claims_json) = Self::decode_header_and_claims(encoded_token, validator)?;
+        Self::validate_registered_claims(&claims_json, validator)?;
+        Self::validate_custom_claims(&claims_json, validator)?;
+        let claims = serde_json::from_slice(&claims_json)?;
         Ok(Jwt { header, claims })
     }
 
@@ -438,6 +622,70 @@ impl<'a, C: DeserializeOwned> Jwt<'a, C> {
     }
 }
 
+/// Raw parts of an encoded token, see `Jwt::decode_dangerous_parts`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JwtParts {
+    /// header JSON
+    pub header: String,
+    /// claims JSON
+    pub claims: String,
+    pub signature: Vec<u8>,
+}
+
+impl Jwt<'static, serde_json::Value> {
+    /// Unsafe JWT decoding method. Nothing is checked: neither the signature, nor the header, nor the claims.
+    ///
+    /// Meant for debugging tools, or to select the key a token should be validated with before
+    /// decoding it again with `Jwt::decode`. Returned parts must not be trusted.
+    pub fn decode_dangerous_parts(encoded_token: &str) -> Result<JwtParts, JwtError> {
+        fn decode_json(part_base64: &str) -> Result<String, JwtError> {
+            let json = base64::decode_config(part_base64, base64::URL_SAFE_NO_PAD)?;
+            String::from_utf8(json).map_err(|e| JwtError::InvalidUtf8 {
+                input: e.as_bytes().to_vec(),
+                source: e,
+            })
+        }
+
+        let mut parts = encoded_token.splitn(3, '.');
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(header), Some(claims), Some(signature))
+                if !header.is_empty() && !signature.is_empty() && !signature.contains('.') =>
+            {
+                Ok(JwtParts {
+                    header: decode_json(header)?,
+                    claims: decode_json(claims)?,
+                    signature: base64::decode_config(signature, base64::URL_SAFE_NO_PAD)?,
+                })
+            }
+            _ => Err(JwtError::InvalidEncoding {
+                input: encoded_token.to_owned(),
+            }),
+        }
+    }
+}
+
+impl<'a, C: Deserialize<'a>> Jwt<'a, C> {
+    /// Validate using validator and returns decoded JWT whose claims may borrow from `buffer`.
+    ///
+    /// Claims are base64url-encoded in the token, hence `buffer` is used to hold the decoded claims json
+    /// for as long as the returned JWT lives.
+    pub fn decode_borrowed(
+        encoded_token: &str,
+        buffer: &'a mut Vec<u8>,
+        validator: &JwtValidator,
+    ) -> Result<Self, JwtError> {
+        let (header, claims_json) = Self::decode_header_and_claims(encoded_token, validator)?;
+        Self::validate_registered_claims(&claims_json, validator)?;
+        Self::validate_custom_claims(&claims_json, validator)?;
+
+        *buffer = claims_json;
+        let claims_json: &'a [u8] = buffer;
+
+        let claims = serde_json::from_slice(claims_json)?;
+        Ok(Jwt { header, claims })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -530,6 +778,38 @@ mod tests {
         assert_eq!(claims["iat"].as_i64().expect("iat"), 1516239022);
     }
 
+    #[test]
+    fn decode_dangerous_parts() {
+        let parts = Jwt::decode_dangerous_parts(crate::test_files::JOSE_JWT_EXAMPLE).unwrap();
+        assert_eq!(parts.header, r#"{"alg":"RS256","typ":"JWT"}"#);
+        assert_eq!(
+            parts.claims,
+            r#"{"sub":"1234567890","name":"John Doe","admin":true,"iat":1516239022}"#
+        );
+
+        let public_key = get_private_key_1().to_public_key();
+        let signed_input_len = crate::test_files::JOSE_JWT_EXAMPLE.rfind('.').unwrap();
+        SignatureHashType::RsaSha256
+            .verify(
+                &public_key,
+                crate::test_files::JOSE_JWT_EXAMPLE[..signed_input_len].as_bytes(),
+                &parts.signature,
+            )
+            .expect("signature");
+
+        // nothing is validated
+        let parts = Jwt::decode_dangerous_parts("eyJ0eXAiOiJOT1QgSldUIn0.bm90IGpzb24.AQID").unwrap();
+        assert_eq!(parts.header, r#"{"typ":"NOT JWT"}"#);
+        assert_eq!(parts.claims, "not json");
+        assert_eq!(parts.signature, vec![1, 2, 3]);
+
+        let err = Jwt::decode_dangerous_parts("eyJ0eXAiOiJKV1QifQ.e30").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "input isn't a valid token string: eyJ0eXAiOiJKV1QifQ.e30"
+        );
+    }
+
     #[test]
     fn decode_rsa_sha256_delayed_signature_check() {
         let jwt = Jwt::<MyClaims>::decode_without_validation(crate::test_files::JOSE_JWT_EXAMPLE).unwrap();
@@ -612,6 +892,64 @@ mod tests {
         assert_eq!(err.to_string(), "input isn\'t a valid token string: abc");
     }
 
+    #[test]
+    fn decode_custom_claims_checks() {
+        let public_key = get_private_key_1().to_public_key();
+        let validator = JwtValidator::signature_only(&public_key)
+            .required_claim("iat")
+            .claim_equals("admin", true)
+            .claim_contains("name", "Doe");
+        Jwt::<MyClaims>::decode(crate::test_files::JOSE_JWT_EXAMPLE, &validator).unwrap();
+
+        let validator = JwtValidator::signature_only(&public_key).required_claim("scope");
+        let err = Jwt::<MyClaims>::decode(crate::test_files::JOSE_JWT_EXAMPLE, &validator)
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "claim `scope` is missing");
+
+        let validator = JwtValidator::signature_only(&public_key).claim_equals("sub", "0987654321");
+        let err = Jwt::<MyClaims>::decode(crate::test_files::JOSE_JWT_EXAMPLE, &validator)
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "claim `sub` has an unexpected value: \"1234567890\"");
+    }
+
+    #[test]
+    fn custom_claims_selected() {
+        let names = ["sub", "scope"];
+        let json = br#"{"s\u0075b":"a","nested":{"scope":"b"},"list":[1,{"sub":"c"}]}"#;
+        let claims = SelectedClaims(&names)
+            .deserialize(&mut serde_json::Deserializer::from_slice(json))
+            .unwrap();
+        assert_eq!(claims.len(), 1);
+        assert_eq!(claims["sub"], "a");
+    }
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct MyBorrowedClaims<'a> {
+        sub: &'a str,
+        name: &'a str,
+        admin: bool,
+    }
+
+    #[test]
+    fn decode_rsa_sha256_borrowed_claims() {
+        let public_key = get_private_key_1().to_public_key();
+        let validator = JwtValidator::signature_only(&public_key);
+        let mut buffer = Vec::new();
+        let jwt =
+            Jwt::<MyBorrowedClaims>::decode_borrowed(crate::test_files::JOSE_JWT_EXAMPLE, &mut buffer, &validator)
+                .unwrap();
+        assert_eq!(
+            jwt.view_claims(),
+            &MyBorrowedClaims {
+                sub: "1234567890",
+                name: "John Doe",
+                admin: true,
+            }
+        );
+    }
+
     #[derive(Serialize, Deserialize)]
     struct MyExpirableClaims {
         exp: i64,
@@ -646,6 +984,14 @@ mod tests {
             &JwtValidator::strict(&public_key, &JwtDate::new_with_leeway(1545262999, 10)),
         )
         .expect("couldn't decode jwt with leeway for nbf");
+
+        let mut buffer = Vec::new();
+        Jwt::<MyExpirableClaims>::decode_borrowed(
+            crate::test_files::JOSE_JWT_WITH_EXP,
+            &mut buffer,
+            &JwtValidator::strict(&public_key, &JwtDate::new(1545263999)),
+        )
+        .expect("couldn't decode borrowed jwt");
     }
 
     #[test]
@@ -676,4 +1022,33 @@ mod tests {
             "token not yet valid (not before: 1545263000, now: 1545262998 [leeway: 1])"
         );
     }
+
+    #[test]
+    fn decode_jwt_with_clock() {
+        use crate::clock::{self, FixedClock};
+        use std::sync::Arc;
+
+        let public_key = get_private_key_1().to_public_key();
+        let clock = Arc::new(FixedClock::new(1545263999));
+
+        clock::with_clock(clock.clone(), || {
+            Jwt::<MyExpirableClaims>::decode(
+                crate::test_files::JOSE_JWT_WITH_EXP,
+                &JwtValidator::strict(&public_key, &JwtDate::now()),
+            )
+            .expect("couldn't decode jwt before expiration");
+
+            clock.advance(2);
+            let err = Jwt::<MyExpirableClaims>::decode(
+                crate::test_files::JOSE_JWT_WITH_EXP,
+                &JwtValidator::strict(&public_key, &JwtDate::now()),
+            )
+            .err()
+            .unwrap();
+            assert_eq!(
+                err.to_string(),
+                "token expired (not after: 1545264000, now: 1545264001 [leeway: 0])"
+            );
+        });
+    }
 }
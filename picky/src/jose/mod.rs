@@ -1,2 +1,5 @@
+#[cfg(feature = "jwe")]
+pub mod jwe;
 pub mod jwk;
+pub mod jws;
 pub mod jwt;
@@ -1,8 +1,11 @@
 use crate::{
     pem::{to_pem, Pem},
-    private::{private_key_info::PrivateKeyValue, PrivateKeyInfo, SubjectPublicKeyInfo},
+    private::{
+        private_key_info::{PrivateKeyValue, RSAPrivateKey},
+        PrivateKeyInfo, SubjectPublicKeyInfo,
+    },
 };
-use picky_asn1::wrapper::{IntegerAsn1, OctetStringAsn1Container};
+use picky_asn1::wrapper::{BitStringAsn1Container, IntegerAsn1, OctetStringAsn1Container};
 use picky_asn1_der::Asn1DerError;
 use snafu::{ResultExt, Snafu};
 
@@ -29,6 +32,10 @@ pub enum KeyError {
     /// invalid PEM label error
     #[snafu(display("invalid PEM label: {}", label))]
     InvalidPemLabel { label: String },
+
+    /// key can't be exported to the requested format
+    #[snafu(display("{} key can't be exported to {}", key, format))]
+    UnsupportedExport { key: &'static str, format: &'static str },
 }
 
 impl From<rsa::errors::Error> for KeyError {
@@ -45,6 +52,10 @@ const RSA_PRIVATE_KEY_PEM_LABEL: &str = "RSA PRIVATE KEY";
 #[derive(Debug, Clone, PartialEq)]
 pub struct PrivateKey(PrivateKeyInfo);
 
+// keys are meant to be shared between threads (e.g. behind an `Arc` in server state)
+static_assertions::assert_impl_all!(PrivateKey: Send, Sync);
+static_assertions::assert_impl_all!(KeyError: Send, Sync);
+
 impl From<PrivateKeyInfo> for PrivateKey {
     fn from(key: PrivateKeyInfo) -> Self {
         Self(key)
@@ -88,7 +99,7 @@ impl PrivateKey {
     }
 
     pub fn from_rsa_der<T: ?Sized + AsRef<[u8]>>(der: &T) -> Result<Self, KeyError> {
-        use crate::{private::private_key_info::RSAPrivateKey, AlgorithmIdentifier};
+        use crate::AlgorithmIdentifier;
 
         let private_key = picky_asn1_der::from_bytes::<RSAPrivateKey>(der.as_ref()).context(Asn1Deserialization {
             element: "rsa private key",
@@ -101,14 +112,19 @@ impl PrivateKey {
         }))
     }
 
+    /// Exports this key to any of the supported formats.
+    pub fn export(&self) -> KeyExport<'_> {
+        KeyExport::new(ExportedKey::Private(self))
+    }
+
+    /// Shorthand for `export().pkcs8_der()`.
     pub fn to_pkcs8(&self) -> Result<Vec<u8>, KeyError> {
-        picky_asn1_der::to_vec(&self.0).context(Asn1Serialization {
-            element: "private key info (pkcs8)",
-        })
+        self.export().pkcs8_der()
     }
 
+    /// Shorthand for `export().pkcs8_pem()`.
     pub fn to_pem(&self) -> Result<String, KeyError> {
-        Ok(to_pem(PRIVATE_KEY_PEM_LABEL, &self.to_pkcs8()?))
+        self.export().pkcs8_pem()
     }
 
     pub fn to_public_key(&self) -> PublicKey {
@@ -121,10 +137,16 @@ impl PrivateKey {
 
     /// **Beware**: this is insanely slow in debug builds.
     pub fn generate_rsa(bits: usize) -> Result<Self, KeyError> {
-        use rand::rngs::OsRng;
-        use rsa::{PublicKey, RSAPrivateKey};
+        Self::generate_rsa_with_rng(&mut rand::rngs::OsRng, bits)
+    }
 
-        let key = RSAPrivateKey::new(&mut OsRng, bits)?;
+    /// Same as `generate_rsa` using the provided random number generator.
+    ///
+    /// **Beware**: this is insanely slow in debug builds.
+    pub fn generate_rsa_with_rng<R: rand::Rng + rand::CryptoRng>(rng: &mut R, bits: usize) -> Result<Self, KeyError> {
+        use rsa::{PublicKeyParts, RSAPrivateKey};
+
+        let key = RSAPrivateKey::new(rng, bits)?;
         let modulus = IntegerAsn1::from_signed_bytes_be(key.n().to_bytes_be());
         let public_exponent = IntegerAsn1::from_signed_bytes_be(key.e().to_bytes_be());
         let private_exponent = IntegerAsn1::from_signed_bytes_be(key.d().to_bytes_be());
@@ -154,6 +176,8 @@ const RSA_PUBLIC_KEY_PEM_LABEL: &str = "RSA PUBLIC KEY";
 #[repr(transparent)]
 pub struct PublicKey(SubjectPublicKeyInfo);
 
+static_assertions::assert_impl_all!(PublicKey: Send, Sync);
+
 impl<'a> From<&'a SubjectPublicKeyInfo> for &'a PublicKey {
     #[inline]
     fn from(spki: &'a SubjectPublicKeyInfo) -> Self {
@@ -204,14 +228,19 @@ impl AsRef<PublicKey> for PublicKey {
 }
 
 impl PublicKey {
+    /// Exports this key to any of the supported formats.
+    pub fn export(&self) -> KeyExport<'_> {
+        KeyExport::new(ExportedKey::Public(self))
+    }
+
+    /// Shorthand for `export().spki_der()`.
     pub fn to_der(&self) -> Result<Vec<u8>, KeyError> {
-        picky_asn1_der::to_vec(&self.0).context(Asn1Serialization {
-            element: "subject public key info",
-        })
+        self.export().spki_der()
     }
 
+    /// Shorthand for `export().spki_pem()`.
     pub fn to_pem(&self) -> Result<String, KeyError> {
-        Ok(to_pem(PUBLIC_KEY_PEM_LABEL, &self.to_der()?))
+        self.export().spki_pem()
     }
 
     pub fn from_pem(pem: &Pem) -> Result<Self, KeyError> {
@@ -253,6 +282,264 @@ impl PublicKey {
     }
 }
 
+// === export === //
+
+const OPENSSH_PRIVATE_KEY_PEM_LABEL: &str = "OPENSSH PRIVATE KEY";
+const OPENSSH_AUTH_MAGIC: &[u8] = b"openssh-key-v1\0";
+const SSH_RSA_KEY_TYPE: &str = "ssh-rsa";
+
+#[derive(Debug, Clone, Copy)]
+enum ExportedKey<'a> {
+    Private(&'a PrivateKey),
+    Public(&'a PublicKey),
+}
+
+/// Exports a private or public key to PKCS#8, PKCS#1, SubjectPublicKeyInfo, JWK or OpenSSH.
+///
+/// Obtained with `PrivateKey::export` or `PublicKey::export`. Exporting the public part of a
+/// private key (SubjectPublicKeyInfo) is supported, but not the other way around.
+///
+/// ```no_run
+/// # use picky::key::PrivateKey;
+/// # let private_key = PrivateKey::generate_rsa(2048).unwrap();
+/// let pkcs8_pem = private_key.export().pkcs8_pem().unwrap();
+/// let spki_der = private_key.export().spki_der().unwrap();
+/// let authorized_key = private_key
+///     .to_public_key()
+///     .export()
+///     .with_comment("john@example.com")
+///     .openssh()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct KeyExport<'a> {
+    key: ExportedKey<'a>,
+    comment: String,
+}
+
+impl<'a> KeyExport<'a> {
+    fn new(key: ExportedKey<'a>) -> Self {
+        Self {
+            key,
+            comment: String::new(),
+        }
+    }
+
+    /// Comment of OpenSSH exports, empty by default.
+    pub fn with_comment<S: Into<String>>(self, comment: S) -> Self {
+        Self {
+            comment: comment.into(),
+            ..self
+        }
+    }
+
+    /// DER-encoded PKCS#8 `PrivateKeyInfo`, private keys only.
+    pub fn pkcs8_der(&self) -> Result<Vec<u8>, KeyError> {
+        match self.key {
+            ExportedKey::Private(key) => picky_asn1_der::to_vec(&key.0).context(Asn1Serialization {
+                element: "private key info (pkcs8)",
+            }),
+            ExportedKey::Public(_) => Err(KeyError::UnsupportedExport {
+                key: "public",
+                format: "PKCS#8",
+            }),
+        }
+    }
+
+    /// PEM-encoded PKCS#8 `PrivateKeyInfo` (`PRIVATE KEY` label), private keys only.
+    pub fn pkcs8_pem(&self) -> Result<String, KeyError> {
+        Ok(to_pem(PRIVATE_KEY_PEM_LABEL, &self.pkcs8_der()?))
+    }
+
+    /// DER-encoded PKCS#1 `RSAPrivateKey` or `RSAPublicKey`, RSA keys only.
+    pub fn pkcs1_der(&self) -> Result<Vec<u8>, KeyError> {
+        use crate::private::subject_public_key_info::PublicKey as SerdePublicKey;
+
+        match self.key {
+            ExportedKey::Private(key) => match &key.0.private_key {
+                PrivateKeyValue::RSA(OctetStringAsn1Container(rsa)) => {
+                    picky_asn1_der::to_vec(rsa).context(Asn1Serialization {
+                        element: "rsa private key",
+                    })
+                }
+            },
+            ExportedKey::Public(key) => match &key.0.subject_public_key {
+                SerdePublicKey::RSA(BitStringAsn1Container(rsa)) => {
+                    picky_asn1_der::to_vec(rsa).context(Asn1Serialization {
+                        element: "rsa public key",
+                    })
+                }
+                SerdePublicKey::EC(_) => Err(KeyError::UnsupportedExport {
+                    key: "elliptic curves",
+                    format: "PKCS#1",
+                }),
+            },
+        }
+    }
+
+    /// PEM-encoded PKCS#1 `RSAPrivateKey` (`RSA PRIVATE KEY` label) or `RSAPublicKey`
+    /// (`RSA PUBLIC KEY` label), RSA keys only.
+    pub fn pkcs1_pem(&self) -> Result<String, KeyError> {
+        let label = match self.key {
+            ExportedKey::Private(_) => RSA_PRIVATE_KEY_PEM_LABEL,
+            ExportedKey::Public(_) => RSA_PUBLIC_KEY_PEM_LABEL,
+        };
+        Ok(to_pem(label, &self.pkcs1_der()?))
+    }
+
+    /// DER-encoded `SubjectPublicKeyInfo` of the public key.
+    pub fn spki_der(&self) -> Result<Vec<u8>, KeyError> {
+        match self.key {
+            ExportedKey::Private(key) => key.to_public_key().export().spki_der(),
+            ExportedKey::Public(key) => picky_asn1_der::to_vec(&key.0).context(Asn1Serialization {
+                element: "subject public key info",
+            }),
+        }
+    }
+
+    /// PEM-encoded `SubjectPublicKeyInfo` (`PUBLIC KEY` label) of the public key.
+    pub fn spki_pem(&self) -> Result<String, KeyError> {
+        Ok(to_pem(PUBLIC_KEY_PEM_LABEL, &self.spki_der()?))
+    }
+
+    /// JSON Web Key, RSA public keys only.
+    #[cfg(feature = "jose")]
+    pub fn jwk(&self) -> Result<crate::jose::jwk::Jwk, KeyError> {
+        use crate::{
+            jose::jwk::{Jwk, JwkKeyType},
+            private::subject_public_key_info::PublicKey as SerdePublicKey,
+        };
+
+        match self.key {
+            ExportedKey::Private(_) => Err(KeyError::UnsupportedExport {
+                key: "private",
+                format: "JWK",
+            }),
+            ExportedKey::Public(key) => match &key.0.subject_public_key {
+                SerdePublicKey::RSA(BitStringAsn1Container(rsa)) => Ok(Jwk::new(JwkKeyType::new_rsa_key(
+                    rsa.modulus.as_signed_bytes_be(),
+                    rsa.public_exponent.as_signed_bytes_be(),
+                ))),
+                SerdePublicKey::EC(_) => Err(KeyError::UnsupportedExport {
+                    key: "elliptic curves",
+                    format: "JWK",
+                }),
+            },
+        }
+    }
+
+    /// OpenSSH key, RSA keys only.
+    ///
+    /// Public keys are exported as an `authorized_keys` line (`ssh-rsa AAAA... comment`),
+    /// private keys as an unencrypted `openssh-key-v1` (`OPENSSH PRIVATE KEY` label).
+    pub fn openssh(&self) -> Result<String, KeyError> {
+        use crate::private::subject_public_key_info::PublicKey as SerdePublicKey;
+
+        match self.key {
+            ExportedKey::Private(key) => match &key.0.private_key {
+                PrivateKeyValue::RSA(OctetStringAsn1Container(rsa)) => self.openssh_rsa_private_key(rsa),
+            },
+            ExportedKey::Public(key) => match &key.0.subject_public_key {
+                SerdePublicKey::RSA(BitStringAsn1Container(rsa)) => {
+                    let blob = ssh_rsa_public_key_blob(&rsa.modulus, &rsa.public_exponent);
+                    let mut line = format!("{} {}", SSH_RSA_KEY_TYPE, base64::encode(&blob));
+                    if !self.comment.is_empty() {
+                        line.push(' ');
+                        line.push_str(&self.comment);
+                    }
+                    Ok(line)
+                }
+                SerdePublicKey::EC(_) => Err(KeyError::UnsupportedExport {
+                    key: "elliptic curves",
+                    format: "OpenSSH",
+                }),
+            },
+        }
+    }
+
+    // https://cvsweb.openbsd.org/src/usr.bin/ssh/PROTOCOL.key
+    fn openssh_rsa_private_key(&self, rsa: &RSAPrivateKey) -> Result<String, KeyError> {
+        use rsa::BigUint;
+
+        let (prime_1, prime_2) = match rsa.primes() {
+            [prime_1, prime_2, ..] => (prime_1, prime_2),
+            _ => {
+                return Err(KeyError::Rsa {
+                    context: "private key is missing its prime factors".to_owned(),
+                })
+            }
+        };
+
+        // coefficient (inverse of q mod p), computed since not all private keys store it
+        let p = BigUint::from_bytes_be(prime_1.as_unsigned_bytes_be());
+        let q = BigUint::from_bytes_be(prime_2.as_unsigned_bytes_be());
+        let iqmp = q.modpow(&(&p - &BigUint::from(2u32)), &p);
+
+        let check = rand::random::<u32>();
+        let mut private_section = Vec::new();
+        private_section.extend_from_slice(&check.to_be_bytes());
+        private_section.extend_from_slice(&check.to_be_bytes());
+        write_ssh_string(&mut private_section, SSH_RSA_KEY_TYPE.as_bytes());
+        write_ssh_mpint(&mut private_section, rsa.modulus().as_unsigned_bytes_be());
+        write_ssh_mpint(&mut private_section, rsa.public_exponent().as_unsigned_bytes_be());
+        write_ssh_mpint(&mut private_section, rsa.private_exponent().as_unsigned_bytes_be());
+        write_ssh_mpint(&mut private_section, &iqmp.to_bytes_be());
+        write_ssh_mpint(&mut private_section, prime_1.as_unsigned_bytes_be());
+        write_ssh_mpint(&mut private_section, prime_2.as_unsigned_bytes_be());
+        write_ssh_string(&mut private_section, self.comment.as_bytes());
+
+        // padded to the cipher block size, 8 for "none"
+        let mut padding = 1u8;
+        while private_section.len() % 8 != 0 {
+            private_section.push(padding);
+            padding += 1;
+        }
+
+        let mut data = OPENSSH_AUTH_MAGIC.to_vec();
+        write_ssh_string(&mut data, b"none"); // cipher name
+        write_ssh_string(&mut data, b"none"); // kdf name
+        write_ssh_string(&mut data, b""); // kdf options
+        data.extend_from_slice(&1u32.to_be_bytes()); // number of keys
+        write_ssh_string(
+            &mut data,
+            &ssh_rsa_public_key_blob(rsa.modulus(), rsa.public_exponent()),
+        );
+        write_ssh_string(&mut data, &private_section);
+
+        Ok(to_pem(OPENSSH_PRIVATE_KEY_PEM_LABEL, &data))
+    }
+}
+
+// https://tools.ietf.org/html/rfc4253#section-6.6
+fn ssh_rsa_public_key_blob(modulus: &IntegerAsn1, public_exponent: &IntegerAsn1) -> Vec<u8> {
+    let mut blob = Vec::new();
+    write_ssh_string(&mut blob, SSH_RSA_KEY_TYPE.as_bytes());
+    write_ssh_mpint(&mut blob, public_exponent.as_unsigned_bytes_be());
+    write_ssh_mpint(&mut blob, modulus.as_unsigned_bytes_be());
+    blob
+}
+
+// https://tools.ietf.org/html/rfc4251#section-5
+fn write_ssh_string(buf: &mut Vec<u8>, data: &[u8]) {
+    buf.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    buf.extend_from_slice(data);
+}
+
+fn write_ssh_mpint(buf: &mut Vec<u8>, unsigned_be: &[u8]) {
+    let first_non_zero = unsigned_be
+        .iter()
+        .position(|&byte| byte != 0)
+        .unwrap_or(unsigned_be.len());
+    let magnitude = &unsigned_be[first_non_zero..];
+    if magnitude.first().map_or(false, |&byte| byte & 0x80 != 0) {
+        buf.extend_from_slice(&(magnitude.len() as u32 + 1).to_be_bytes());
+        buf.push(0);
+        buf.extend_from_slice(magnitude);
+    } else {
+        write_ssh_string(buf, magnitude);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -358,6 +645,84 @@ mod tests {
         assert_eq!(err.to_string(), "invalid PEM label: GARBAGE");
     }
 
+    const RSA_OPENSSH_PUBLIC_KEY: &str =
+        "ssh-rsa AAAAB3NzaC1yc2EAAAADAQABAAABAQDkrPiL/5dmGIT5/KuC3H/jIjeLoLoddsLhAlikO5\
+         JQQo3Zs71GwT4Wd2z8WLMe0lVZu/Jr2S28p0M8F3Lnz4IgzjocQomFgucFWWQRyD03ZE2BHfEeel\
+         Fsp+/4GZaM6lKZauYlIMtjR1vDlflgvxNTr0iaii4JR9K3IKCunCRy1HQYPcZ9waNtlG5xXtW9Uf\
+         1tLWPJpP/3I5HLM85JPBv4r286vpeUlfQIa/NB4g5w6KZ6MfEAIU4KeEQpeLAyyYvwUzPR2uQZ4y\
+         4I4Nj84dWYB1cMTlSGugvSgOFKYit1nwLGeA7EevVYPbILRfSMBU/+avGNJJ8HCaaqFIyY42W9 \
+         john@example.com";
+
+    #[test]
+    fn export_private_key() {
+        let pem = RSA_PRIVATE_KEY_PEM.parse::<Pem>().expect("pem");
+        let private_key = PrivateKey::from_pem(&pem).expect("private key");
+        let export = private_key.export();
+
+        assert_eq!(export.pkcs1_der().expect("pkcs1"), pem.data());
+        assert_eq!(export.pkcs1_pem().expect("pkcs1 pem").parse::<Pem>().expect("pem"), pem);
+
+        let pkcs8 = export.pkcs8_pem().expect("pkcs8 pem").parse::<Pem>().expect("pem");
+        assert_eq!(pkcs8.label(), "PRIVATE KEY");
+        assert_eq!(PrivateKey::from_pem(&pkcs8).expect("from pkcs8"), private_key);
+
+        let spki = export.spki_der().expect("spki");
+        assert_eq!(
+            PublicKey::from_der(&spki).expect("from spki"),
+            private_key.to_public_key()
+        );
+
+        #[cfg(feature = "jose")]
+        match export.jwk() {
+            Err(KeyError::UnsupportedExport { key: "private", .. }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let openssh = private_key
+            .export()
+            .with_comment("john@example.com")
+            .openssh()
+            .expect("openssh")
+            .parse::<Pem>()
+            .expect("pem");
+        assert_eq!(openssh.label(), "OPENSSH PRIVATE KEY");
+        assert!(openssh.data().starts_with(b"openssh-key-v1\0"));
+        let public_key_blob = base64::decode(RSA_OPENSSH_PUBLIC_KEY.split(' ').nth(1).unwrap()).unwrap();
+        assert!(openssh
+            .data()
+            .windows(public_key_blob.len())
+            .any(|window| window == public_key_blob.as_slice()));
+    }
+
+    #[test]
+    fn export_public_key() {
+        let public_key = PrivateKey::from_pem(&RSA_PRIVATE_KEY_PEM.parse::<Pem>().expect("pem"))
+            .expect("private key")
+            .to_public_key();
+        let export = public_key.export().with_comment("john@example.com");
+
+        assert_eq!(export.openssh().expect("openssh"), RSA_OPENSSH_PUBLIC_KEY);
+
+        let pkcs1 = export.pkcs1_pem().expect("pkcs1 pem").parse::<Pem>().expect("pem");
+        assert_eq!(pkcs1.label(), "RSA PUBLIC KEY");
+        assert_eq!(PublicKey::from_pem(&pkcs1).expect("from pkcs1"), public_key);
+
+        let spki = export.spki_pem().expect("spki pem").parse::<Pem>().expect("pem");
+        assert_eq!(spki.label(), "PUBLIC KEY");
+        assert_eq!(PublicKey::from_pem(&spki).expect("from spki"), public_key);
+
+        #[cfg(feature = "jose")]
+        assert_eq!(
+            export.jwk().expect("jwk").to_public_key().expect("from jwk"),
+            public_key
+        );
+
+        match export.pkcs8_der() {
+            Err(KeyError::UnsupportedExport { key: "public", .. }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
     fn check_pk(pem_str: &str) {
         const MSG: &'static [u8] = b"abcde";
 
@@ -2,6 +2,12 @@
 mod macros;
 mod private;
 
+#[cfg(feature = "arbitrary")]
+mod arbitrary_support;
+
+#[cfg(feature = "x509")]
+pub mod cms;
+
 #[cfg(feature = "http_signature")]
 pub mod http;
 
@@ -12,17 +18,22 @@ pub mod jose;
 pub mod x509;
 
 pub mod algorithm_identifier;
+pub mod clock;
 pub mod key;
 pub mod oids;
 pub mod pem;
 pub mod signature;
 
+#[cfg(feature = "proptest_support")]
+pub mod proptest_support;
+
 pub use algorithm_identifier::AlgorithmIdentifier;
 
 #[cfg(test)]
 mod test_files {
     pub const RSA_2048_PK_7: &str = include_str!("../../test_assets/private_keys/rsa-2048-pk_7.key");
     pub const RSA_4096_PK_3: &str = include_str!("../../test_assets/private_keys/rsa-4096-pk_3.key");
+    pub const RSA_2048_PK_7_SIGNATURES: &str = include_str!("../../test_assets/signatures/rsa-2048-pk_7_pkcs1v15.txt");
 
     cfg_if::cfg_if! { if #[cfg(feature = "x509")] {
         pub const RSA_2048_PK_1: &str =
@@ -42,6 +42,7 @@ macro_rules! define_oid {
 define_oid! {
     // ANSI-X962
     EC_PUBLIC_KEY => ec_public_key => "1.2.840.10045.2.1",
+    SECP256R1 => secp256r1 => "1.2.840.10045.3.1.7",
     ECDSA_WITH_SHA256 => ecdsa_with_sha256 => "1.2.840.10045.4.3.2",
     ECDSA_WITH_SHA384 => ecdsa_with_sha384 => "1.2.840.10045.4.3.3",
 
@@ -54,6 +55,45 @@ define_oid! {
     SHA224_WITH_RSA_ENCRYPTION => sha224_with_rsa_encryption => "1.2.840.113549.1.1.14",
     EMAIL_ADDRESS => email_address => "1.2.840.113549.1.9.1", // deprecated
 
+    // hash algorithms
+    SHA1 => sha1 => "1.3.14.3.2.26",
+    SHA256 => sha256 => "2.16.840.1.101.3.4.2.1",
+    SHA384 => sha384 => "2.16.840.1.101.3.4.2.2",
+    SHA512 => sha512 => "2.16.840.1.101.3.4.2.3",
+    SHA224 => sha224 => "2.16.840.1.101.3.4.2.4",
+
+    // PKCS#5 password-based encryption
+    PBKDF2 => pbkdf2 => "1.2.840.113549.1.5.12",
+    PBES2 => pbes2 => "1.2.840.113549.1.5.13",
+    HMAC_WITH_SHA1 => hmac_with_sha1 => "1.2.840.113549.2.7",
+    HMAC_WITH_SHA256 => hmac_with_sha256 => "1.2.840.113549.2.9",
+    AES128_CBC => aes128_cbc => "2.16.840.1.101.3.4.1.2",
+    AES192_CBC => aes192_cbc => "2.16.840.1.101.3.4.1.22",
+    AES256_CBC => aes256_cbc => "2.16.840.1.101.3.4.1.42",
+
+    // PKCS#7 content types
+    PKCS7_DATA => pkcs7_data => "1.2.840.113549.1.7.1",
+    PKCS7_SIGNED_DATA => pkcs7_signed_data => "1.2.840.113549.1.7.2",
+    PKCS7_ENCRYPTED_DATA => pkcs7_encrypted_data => "1.2.840.113549.1.7.6",
+
+    // PKCS#9 attributes
+    CONTENT_TYPE => content_type => "1.2.840.113549.1.9.3",
+    MESSAGE_DIGEST => message_digest => "1.2.840.113549.1.9.4",
+    SIGNING_TIME => signing_time => "1.2.840.113549.1.9.5",
+    EXTENSION_REQUEST => extension_request => "1.2.840.113549.1.9.14",
+    FRIENDLY_NAME => friendly_name => "1.2.840.113549.1.9.20",
+    LOCAL_KEY_ID => local_key_id => "1.2.840.113549.1.9.21",
+    X509_CERTIFICATE => x509_certificate => "1.2.840.113549.1.9.22.1",
+
+    // PKCS#12
+    PBE_WITH_SHA_AND_128_BIT_RC2_CBC => pbe_with_sha_and_128_bit_rc2_cbc => "1.2.840.113549.1.12.1.5",
+    PBE_WITH_SHA_AND_3_KEY_TRIPLE_DES_CBC => pbe_with_sha_and_3_key_triple_des_cbc => "1.2.840.113549.1.12.1.3",
+    PBE_WITH_SHA_AND_40_BIT_RC2_CBC => pbe_with_sha_and_40_bit_rc2_cbc => "1.2.840.113549.1.12.1.6",
+    KEY_BAG => key_bag => "1.2.840.113549.1.12.10.1.1",
+    PKCS8_SHROUDED_KEY_BAG => pkcs8_shrouded_key_bag => "1.2.840.113549.1.12.10.1.2",
+    CERT_BAG => cert_bag => "1.2.840.113549.1.12.10.1.3",
+    SAFE_CONTENTS_BAG => safe_contents_bag => "1.2.840.113549.1.12.10.1.6",
+
     // Certicom Object Identifiers
     SECP384R1 => secp384r1 => "1.3.132.0.34",
 
@@ -86,6 +126,33 @@ define_oid! {
     SUBJECT_ALTERNATIVE_NAME => subject_alternative_name => "2.5.29.17",
     ISSUER_ALTERNATIVE_NAME => issuer_alternative_name => "2.5.29.18",
     BASIC_CONSTRAINTS => basic_constraints => "2.5.29.19",
+    NAME_CONSTRAINTS => name_constraints => "2.5.29.30",
+    CERTIFICATE_POLICIES => certificate_policies => "2.5.29.32",
+    POLICY_MAPPINGS => policy_mappings => "2.5.29.33",
     AUTHORITY_KEY_IDENTIFIER => authority_key_identifier => "2.5.29.35",
     EXTENDED_KEY_USAGE => extended_key_usage => "2.5.29.37",
+    CRL_DISTRIBUTION_POINTS => crl_distribution_points => "2.5.29.31",
+    FRESHEST_CRL => freshest_crl => "2.5.29.46",
+    AUTHORITY_INFO_ACCESS => authority_info_access => "1.3.6.1.5.5.7.1.1",
+
+    // crl extensions
+    CRL_NUMBER => crl_number => "2.5.29.20",
+    DELTA_CRL_INDICATOR => delta_crl_indicator => "2.5.29.27",
+    CRL_REASON_CODE => crl_reason_code => "2.5.29.21",
+    INVALIDITY_DATE => invalidity_date => "2.5.29.24",
+    ISSUING_DISTRIBUTION_POINT => issuing_distribution_point => "2.5.29.28",
+    CERTIFICATE_ISSUER => certificate_issuer => "2.5.29.29",
+
+    // certificate policies
+    ANY_POLICY => any_policy => "2.5.29.32.0",
+    QT_CPS => qt_cps => "1.3.6.1.5.5.7.2.1",
+    QT_UNOTICE => qt_unotice => "1.3.6.1.5.5.7.2.2",
+
+    // access descriptors
+    AD_OCSP => ad_ocsp => "1.3.6.1.5.5.7.48.1",
+    AD_CA_ISSUERS => ad_ca_issuers => "1.3.6.1.5.5.7.48.2",
+
+    // ocsp
+    OCSP_BASIC => ocsp_basic => "1.3.6.1.5.5.7.48.1.1",
+    OCSP_NONCE => ocsp_nonce => "1.3.6.1.5.5.7.48.1.2",
 }
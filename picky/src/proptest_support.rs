@@ -0,0 +1,240 @@
+//! Property-based testing utilities.
+//!
+//! Strategies generating random valid keys, certificates and JWTs, and checks asserting that
+//! they survive an encode/decode round-trip unchanged. Downstream crates can use them to verify
+//! that their own storage or transport doesn't alter picky values:
+//!
+//! ```
+//! use picky::{proptest_support::certificate, x509::Cert};
+//! use proptest::prelude::*;
+//!
+//! proptest!(|(cert in certificate())| {
+//!     let der = cert.to_der().unwrap();
+//!     let stored = der.clone(); // e.g. written to and read back from a database
+//!     prop_assert_eq!(Cert::from_der(&stored).unwrap(), cert);
+//! });
+//! ```
+//!
+//! Keys are drawn from a small pool generated once from fixed seeds, which is slow in debug builds.
+
+use crate::{
+    jose::jwt::{Jwt, JwtValidator},
+    key::{PrivateKey, PublicKey},
+    pem::Pem,
+    signature::SignatureHashType,
+    x509::{
+        certificate::{Cert, CertificateBuilder},
+        date::UTCDate,
+        name::{DirectoryName, GeneralName, GeneralNames, NameAttr},
+    },
+};
+use once_cell::sync::Lazy;
+use proptest::{collection, prelude::*, sample, test_runner::TestCaseError};
+use rand::SeedableRng;
+use std::fmt::Display;
+
+const KEY_POOL_SIZE: u64 = 2;
+
+static KEYS: Lazy<Vec<PrivateKey>> = Lazy::new(|| {
+    (0..KEY_POOL_SIZE)
+        .map(|seed| {
+            let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+            PrivateKey::generate_rsa_with_rng(&mut rng, 1024).expect("couldn't generate RSA key")
+        })
+        .collect()
+});
+
+/// RSA private keys.
+pub fn private_key() -> impl Strategy<Value = PrivateKey> {
+    sample::select(KEYS.clone())
+}
+
+pub fn signature_hash_type() -> impl Strategy<Value = SignatureHashType> {
+    prop_oneof![
+        Just(SignatureHashType::RsaSha1),
+        Just(SignatureHashType::RsaSha224),
+        Just(SignatureHashType::RsaSha256),
+        Just(SignatureHashType::RsaSha384),
+        Just(SignatureHashType::RsaSha512),
+    ]
+}
+
+/// Dates encoded either as UTCTime (until 2049) or GeneralizedTime, to the second.
+pub fn utc_date() -> impl Strategy<Value = UTCDate> {
+    (1950u16..2200, 1u8..=12, 1u8..=28, 0u8..24, 0u8..60, 0u8..60).prop_map(
+        |(year, month, day, hour, minute, second)| {
+            UTCDate::new(year, month, day, hour, minute, second).expect("valid date")
+        },
+    )
+}
+
+/// Names with a common name and optionally an organisation and a country.
+pub fn directory_name() -> impl Strategy<Value = DirectoryName> {
+    (
+        "[a-zA-Z0-9][a-zA-Z0-9 .,'-]{0,31}",
+        proptest::option::of("[a-zA-Z0-9][a-zA-Z0-9 .,-]{0,31}"),
+        proptest::option::of("[A-Z]{2}"),
+    )
+        .prop_map(|(common_name, organisation, country)| {
+            let mut name = DirectoryName::new_common_name(common_name);
+            if let Some(organisation) = organisation {
+                name.add_attr(NameAttr::OrganisationName, organisation);
+            }
+            if let Some(country) = country {
+                name.add_attr(NameAttr::CountryName, country);
+            }
+            name
+        })
+}
+
+pub fn dns_name() -> impl Strategy<Value = String> {
+    "[a-z0-9]([a-z0-9-]{0,14}[a-z0-9])?(\\.[a-z0-9]([a-z0-9-]{0,14}[a-z0-9])?){1,3}"
+}
+
+prop_compose! {
+    /// Certificates with random names, validity, serial number and subject alternative names,
+    /// either self-signed CAs or leaves issued by another key.
+    pub fn certificate()(
+        subject_key in private_key(),
+        issuer_key in private_key(),
+        subject in directory_name(),
+        issuer in directory_name(),
+        validity in (utc_date(), utc_date()),
+        serial_number in collection::vec(any::<u8>(), 1..20),
+        signature_hash_type in signature_hash_type(),
+        self_signed in any::<bool>(),
+        dns_names in collection::vec(dns_name(), 0..4),
+    ) -> Cert {
+        let (valid_from, valid_to) = validity;
+        let builder = CertificateBuilder::new();
+        builder
+            .valididy(valid_from, valid_to)
+            .serial_number(serial_number)
+            .signature_hash_type(signature_hash_type);
+
+        if self_signed {
+            builder.self_signed(subject, &subject_key).ca(true);
+        } else {
+            builder
+                .subject(subject, subject_key.to_public_key())
+                .issuer(issuer, &issuer_key);
+        }
+
+        let mut dns_names = dns_names.into_iter();
+        if let Some(first) = dns_names.next() {
+            let mut san = GeneralNames::new(GeneralName::new_dns_name(first).expect("ASCII name"));
+            for dns_name in dns_names {
+                san.add_name(GeneralName::new_dns_name(dns_name).expect("ASCII name"));
+            }
+            builder.subject_alt_name(san);
+        }
+
+        builder.build().expect("couldn't build certificate")
+    }
+}
+
+/// JWT claims: objects of strings, integers and booleans.
+pub fn jwt_claims() -> impl Strategy<Value = serde_json::Value> {
+    let value = prop_oneof![
+        any::<String>().prop_map(serde_json::Value::from),
+        any::<i64>().prop_map(serde_json::Value::from),
+        any::<bool>().prop_map(serde_json::Value::from),
+    ];
+    collection::btree_map("[a-z_]{1,12}", value, 0..8)
+        .prop_map(|claims| serde_json::Value::Object(claims.into_iter().collect()))
+}
+
+pub fn jwt() -> impl Strategy<Value = Jwt<'static, serde_json::Value>> {
+    (signature_hash_type(), jwt_claims())
+        .prop_map(|(signature_hash_type, claims)| Jwt::new(signature_hash_type, claims))
+}
+
+fn fail<E: Display>(context: &'static str) -> impl FnOnce(E) -> TestCaseError {
+    move |e| TestCaseError::fail(format!("{}: {}", context, e))
+}
+
+/// Checks that `key` is unchanged after a PKCS#8 (DER and PEM) round-trip, as well as its public key.
+pub fn check_key_round_trip(key: &PrivateKey) -> Result<(), TestCaseError> {
+    let der = key.to_pkcs8().map_err(fail("couldn't encode private key"))?;
+    prop_assert_eq!(
+        &PrivateKey::from_pkcs8(&der).map_err(fail("couldn't decode private key"))?,
+        key
+    );
+
+    let pem = key
+        .to_pem()
+        .map_err(fail("couldn't encode private key"))?
+        .parse::<Pem>()
+        .map_err(fail("couldn't parse private key PEM"))?;
+    prop_assert_eq!(
+        &PrivateKey::from_pem(&pem).map_err(fail("couldn't decode private key"))?,
+        key
+    );
+
+    let public_key = key.to_public_key();
+    let der = public_key.to_der().map_err(fail("couldn't encode public key"))?;
+    prop_assert_eq!(
+        PublicKey::from_der(&der).map_err(fail("couldn't decode public key"))?,
+        public_key
+    );
+
+    Ok(())
+}
+
+/// Checks that `cert` is unchanged after a DER and PEM round-trip, and encoded back to the same DER.
+pub fn check_cert_round_trip(cert: &Cert) -> Result<(), TestCaseError> {
+    let der = cert.to_der().map_err(fail("couldn't encode certificate"))?;
+    let decoded = Cert::from_der(&der).map_err(fail("couldn't decode certificate"))?;
+    prop_assert_eq!(&decoded, cert);
+    prop_assert_eq!(decoded.to_der().map_err(fail("couldn't encode certificate"))?, der);
+
+    let pem = cert
+        .to_pem()
+        .map_err(fail("couldn't encode certificate"))?
+        .to_string()
+        .parse::<Pem>()
+        .map_err(fail("couldn't parse certificate PEM"))?;
+    prop_assert_eq!(
+        &Cert::from_pem(&pem).map_err(fail("couldn't decode certificate"))?,
+        cert
+    );
+
+    Ok(())
+}
+
+/// Checks that `jwt` signed with `key` is decoded and verified back to the same header and claims.
+pub fn check_jwt_round_trip(jwt: &Jwt<'_, serde_json::Value>, key: &PrivateKey) -> Result<(), TestCaseError> {
+    let encoded = jwt.encode(key).map_err(fail("couldn't encode JWT"))?;
+
+    let public_key = key.to_public_key();
+    let validator = JwtValidator::signature_only(&public_key);
+    let decoded = Jwt::<serde_json::Value>::decode(&encoded, &validator).map_err(fail("couldn't decode JWT"))?;
+    prop_assert_eq!(decoded.view_claims(), jwt.view_claims());
+    prop_assert_eq!(decoded.encode(key).map_err(fail("couldn't encode JWT"))?, encoded);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(16))]
+
+        #[test]
+        fn keys_round_trip(key in private_key()) {
+            check_key_round_trip(&key)?;
+        }
+
+        #[test]
+        fn certificates_round_trip(cert in certificate()) {
+            check_cert_round_trip(&cert)?;
+        }
+
+        #[test]
+        fn jwts_round_trip(jwt in jwt(), key in private_key()) {
+            check_jwt_round_trip(&jwt, &key)?;
+        }
+    }
+}
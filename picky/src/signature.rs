@@ -5,7 +5,7 @@ use crate::{
     AlgorithmIdentifier,
 };
 use picky_asn1::wrapper::{BitStringAsn1Container, OctetStringAsn1Container};
-use rsa::{hash::Hashes, BigUint, PaddingScheme, PublicKey as RsaPublicKeyInterface, RSAPrivateKey, RSAPublicKey};
+use rsa::{hash::Hash, BigUint, PaddingScheme, PublicKey as RsaPublicKeyInterface, RSAPrivateKey, RSAPublicKey};
 use serde::{Deserialize, Serialize};
 use sha1::{Digest, Sha1};
 use sha2::{Sha224, Sha256, Sha384, Sha512};
@@ -48,8 +48,8 @@ pub enum SignatureHashType {
 macro_rules! hash {
     ($algorithm:ident, $input:ident) => {{
         let mut digest = $algorithm::new();
-        digest.input($input);
-        digest.result().as_slice().to_vec()
+        digest.update($input);
+        digest.finalize().as_slice().to_vec()
     }};
 }
 
@@ -92,17 +92,16 @@ impl SignatureHashType {
         let digest = self.hash(msg);
 
         let hash_algo = match self {
-            Self::RsaSha1 => &Hashes::SHA1,
-            Self::RsaSha224 => &Hashes::SHA2_224,
-            Self::RsaSha256 => &Hashes::SHA2_256,
-            Self::RsaSha384 => &Hashes::SHA2_384,
-            Self::RsaSha512 => &Hashes::SHA2_512,
+            Self::RsaSha1 => Hash::SHA1,
+            Self::RsaSha224 => Hash::SHA2_224,
+            Self::RsaSha256 => Hash::SHA2_256,
+            Self::RsaSha384 => Hash::SHA2_384,
+            Self::RsaSha512 => Hash::SHA2_512,
         };
 
         let signature = rsa_private_key.sign_blinded(
             &mut rand::rngs::OsRng,
-            PaddingScheme::PKCS1v15,
-            Some(hash_algo),
+            PaddingScheme::new_pkcs1v15_sign(Some(hash_algo)),
             &digest,
         )?;
 
@@ -125,17 +124,21 @@ impl SignatureHashType {
         };
 
         let hash_algorithm = match self {
-            Self::RsaSha1 => &Hashes::SHA1,
-            Self::RsaSha224 => &Hashes::SHA2_224,
-            Self::RsaSha256 => &Hashes::SHA2_256,
-            Self::RsaSha384 => &Hashes::SHA2_384,
-            Self::RsaSha512 => &Hashes::SHA2_512,
+            Self::RsaSha1 => Hash::SHA1,
+            Self::RsaSha224 => Hash::SHA2_224,
+            Self::RsaSha256 => Hash::SHA2_256,
+            Self::RsaSha384 => Hash::SHA2_384,
+            Self::RsaSha512 => Hash::SHA2_512,
         };
 
         let digest = self.hash(msg);
 
         public_key
-            .verify(PaddingScheme::PKCS1v15, Some(hash_algorithm), &digest, signature)
+            .verify(
+                PaddingScheme::new_pkcs1v15_sign(Some(hash_algorithm)),
+                &digest,
+                signature,
+            )
             .map_err(|_| SignatureError::BadSignature)?;
 
         Ok(())
@@ -153,3 +156,69 @@ impl From<SignatureHashType> for AlgorithmIdentifier {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pem::Pem;
+
+    const MESSAGE: &[u8] = b"picky signature regression";
+
+    fn private_key() -> PrivateKey {
+        let pem = crate::test_files::RSA_2048_PK_7.parse::<Pem>().unwrap();
+        PrivateKey::from_pem(&pem).unwrap()
+    }
+
+    /// Signatures of `MESSAGE` computed by an independent implementation (PKCS#1 v1.5 is deterministic)
+    fn expected_signatures() -> Vec<(SignatureHashType, Vec<u8>)> {
+        crate::test_files::RSA_2048_PK_7_SIGNATURES
+            .lines()
+            .map(|line| {
+                let mut parts = line.split(' ');
+                let algorithm = match parts.next().unwrap() {
+                    "RS1" => SignatureHashType::RsaSha1,
+                    "RS224" => SignatureHashType::RsaSha224,
+                    "RS256" => SignatureHashType::RsaSha256,
+                    "RS384" => SignatureHashType::RsaSha384,
+                    "RS512" => SignatureHashType::RsaSha512,
+                    unexpected => panic!("unexpected algorithm: {}", unexpected),
+                };
+                (algorithm, base64::decode(parts.next().unwrap()).unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sign_known_answers() {
+        let private_key = private_key();
+        let expected = expected_signatures();
+        assert_eq!(expected.len(), 5);
+
+        for (algorithm, signature) in expected {
+            assert_eq!(
+                algorithm.sign(MESSAGE, &private_key).unwrap(),
+                signature,
+                "{:?}",
+                algorithm
+            );
+        }
+    }
+
+    #[test]
+    fn verify_known_answers() {
+        let public_key = private_key().to_public_key();
+
+        for (algorithm, mut signature) in expected_signatures() {
+            algorithm.verify(&public_key, MESSAGE, &signature).unwrap();
+            assert!(algorithm.verify(&public_key, b"other message", &signature).is_err());
+
+            signature[0] ^= 1;
+            assert!(algorithm.verify(&public_key, MESSAGE, &signature).is_err());
+        }
+
+        let (_, signature) = &expected_signatures()[2];
+        assert!(SignatureHashType::RsaSha512
+            .verify(&public_key, MESSAGE, signature)
+            .is_err());
+    }
+}
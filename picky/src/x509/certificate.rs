@@ -7,19 +7,24 @@ use crate::{
         csr::{Csr, CsrError},
         date::UTCDate,
         extension::{
-            AuthorityKeyIdentifier, BasicConstraints, ExtendedKeyUsage, ExtensionView, KeyIdentifier, KeyUsage,
+            AuthorityInfoAccess, AuthorityKeyIdentifier, BasicConstraints, CertificatePolicies, CrlDistributionPoints,
+            ExtendedKeyUsage, ExtensionView, KeyIdentifier, KeyUsage, NameConstraints, PolicyMappings,
         },
+        hostname::{dns_name_matches, normalize_dns_name},
         key_id_gen_method::{KeyIdGenError, KeyIdGenMethod, KeyIdHashAlgo},
-        name::{DirectoryName, GeneralNames},
+        name::{DirectoryName, GeneralName, GeneralNames},
         private::{certificate::TBSCertificate, Certificate, Validity, Version},
+        revocation::{RevocationProvider, RevocationStatus},
+        trust_store::TrustStore,
         Extension, Extensions,
     },
     AlgorithmIdentifier,
 };
+use oid::ObjectIdentifier;
 use picky_asn1::{bit_string::BitString, wrapper::IntegerAsn1};
 use picky_asn1_der::Asn1DerError;
 use snafu::{ResultExt, Snafu};
-use std::cell::RefCell;
+use std::{cell::RefCell, net::IpAddr};
 
 #[derive(Debug, Snafu)]
 pub enum CertError {
@@ -72,6 +77,18 @@ pub enum CertError {
     #[snafu(display("extension not found: {}", name))]
     ExtensionNotFound { name: &'static str },
 
+    /// extension value couldn't be decoded
+    #[snafu(display("invalid extension: {}", name))]
+    InvalidExtension { name: &'static str },
+
+    /// critical extension isn't known, so the certificate can't be processed (RFC 5280, section 4.2)
+    #[snafu(display("critical extension {} isn't supported", oid))]
+    UnsupportedCriticalExtension { oid: String },
+
+    /// value of a critical extension couldn't be decoded
+    #[snafu(display("invalid critical extension: {}", oid))]
+    InvalidCriticalExtension { oid: String },
+
     /// missing required builder argument
     #[snafu(display("missing required builder argument `{}`", arg))]
     MissingBuilderArgument { arg: &'static str },
@@ -84,9 +101,25 @@ pub enum CertError {
     #[snafu(display("certificate expired (not after: {}, now: {})", not_after, now))]
     CertificateExpired { not_after: UTCDate, now: UTCDate },
 
+    /// certificate is revoked
+    #[snafu(display("certificate '{}' is revoked (since: {})", id, revocation_time))]
+    CertificateRevoked { id: String, revocation_time: UTCDate },
+
     /// invalid PEM label error
     #[snafu(display("invalid PEM label: {}", label))]
     InvalidPemLabel { label: String },
+
+    /// subject name is empty and no subject alternative name identifies the subject
+    #[snafu(display("subject name is empty but no subject alternative name is provided"))]
+    MissingSubjectAltName,
+
+    /// an additional extension is already set by the builder
+    #[snafu(display("extension {} is set more than once", oid))]
+    DuplicatedExtension { oid: String },
+
+    /// no DNS subject alternative name matches the hostname
+    #[snafu(display("certificate isn't valid for hostname '{}'", hostname))]
+    HostnameMismatch { hostname: String },
 }
 
 #[derive(Debug, Snafu)]
@@ -102,6 +135,13 @@ pub enum CaChainError {
     /// chain is missing a root certificate
     NoRoot,
 
+    /// no chain from the certificate to a trusted root could be built
+    NoTrustedPath,
+
+    /// no available certificate issued the certificate
+    #[snafu(display("issuer '{}' of certificate '{}' is unknown", issuer_id, cert_id))]
+    UnknownIssuer { cert_id: String, issuer_id: String },
+
     /// issuer certificate is not a CA
     #[snafu(display("issuer certificate '{}' is not a CA", issuer_id))]
     IssuerIsNotCA { issuer_id: String },
@@ -113,6 +153,29 @@ pub enum CaChainError {
     /// issuer name doesn't match
     #[snafu(display("issuer name doesn't match (expected: {}, got: {})", expected, actual))]
     IssuerNameMismatch { expected: String, actual: String },
+
+    /// name isn't permitted by the name constraints of an issuer
+    #[snafu(display(
+        "name '{}' of certificate '{}' isn't permitted by name constraints of '{}'",
+        name,
+        cert_id,
+        issuer_id
+    ))]
+    NameNotPermitted {
+        cert_id: String,
+        issuer_id: String,
+        name: String,
+    },
+}
+
+/// Why a certificate doesn't satisfy the name constraints of an issuer, see `Cert::check_name_constraints`.
+pub(crate) enum NameConstraintsViolation {
+    /// name constraints extension of the issuer couldn't be decoded
+    InvalidIssuer(CertError),
+    /// subject alternative name extension of the certificate couldn't be decoded
+    InvalidCert(CertError),
+    /// name isn't permitted, or is of a form constrained by a critical extension that isn't enforced
+    NameNotPermitted(String),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -125,26 +188,49 @@ pub enum CertType {
 
 const CERT_PEM_LABEL: &str = "CERTIFICATE";
 
+/// Maximum number of intermediate certificates in a path built by `Cert::verify_against`
+const MAX_PATH_INTERMEDIATES: usize = 8;
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Cert(Certificate);
 
+// parsed certificates are plain data: they can be shared between threads behind an `Arc`
+// (CA material in server state, parallel chain verification)
+static_assertions::assert_impl_all!(Cert: Send, Sync);
+static_assertions::assert_impl_all!(CertError: Send, Sync);
+
 impl From<Certificate> for Cert {
     fn from(certificate: Certificate) -> Self {
         Self(certificate)
     }
 }
 
+impl From<Cert> for Certificate {
+    fn from(cert: Cert) -> Self {
+        cert.0
+    }
+}
+
 macro_rules! find_ext {
     ($oid:expr, $certificate:ident, $ext_name:literal) => {{
         let key_identifier_oid = $oid;
         ($certificate.tbs_certificate.extensions.0)
-            .0
-            .iter()
-            .find(|ext| ext.extn_id() == &key_identifier_oid)
+            .get(&key_identifier_oid)
             .ok_or(CertError::ExtensionNotFound { name: $ext_name })
     }};
 }
 
+impl CertError {
+    /// Error without the context wrapped around it (e.g. which certificate of a chain is invalid),
+    /// to match on the actual cause.
+    pub fn root_cause(&self) -> &CertError {
+        match self {
+            CertError::CertGeneration { source } | CertError::InvalidCertificate { source, .. } => source.root_cause(),
+            _ => self,
+        }
+    }
+}
+
 impl Cert {
     pub fn from_der<T: ?Sized + AsRef<[u8]>>(der: &T) -> Result<Self, CertError> {
         Ok(Self(
@@ -161,6 +247,8 @@ impl Cert {
         }
     }
 
+    /// A parsed certificate is encoded back to the exact same DER (extension order, unknown
+    /// attributes and string types are preserved), keeping its signature valid.
     pub fn to_der(&self) -> Result<Vec<u8>, CertError> {
         picky_asn1_der::to_vec(&self.0).context(Asn1Serialization { element: "certificate" })
     }
@@ -207,7 +295,9 @@ impl Cert {
         let ext = find_ext!(oids::subject_key_identifier(), certificate, "subject key identifier")?;
         match ext.extn_value() {
             ExtensionView::SubjectKeyIdentifier(ski) => Ok(&ski.0),
-            _ => unreachable!("invalid extension (expected subject key identifier)"),
+            _ => Err(CertError::InvalidExtension {
+                name: "subject key identifier",
+            }),
         }
     }
 
@@ -221,7 +311,9 @@ impl Cert {
         )?;
         match ext.extn_value() {
             ExtensionView::AuthorityKeyIdentifier(aki) => Ok(aki),
-            _ => unreachable!("invalid extension (expected authority key identifier)"),
+            _ => Err(CertError::InvalidExtension {
+                name: "authority key identifier",
+            }),
         }
     }
 
@@ -230,10 +322,135 @@ impl Cert {
         let ext = find_ext!(oids::basic_constraints(), certificate, "basic constraints")?;
         match ext.extn_value() {
             ExtensionView::BasicConstraints(bc) => Ok(bc),
-            _ => unreachable!("invalid extension (expected basic constraints)"),
+            _ => Err(CertError::InvalidExtension {
+                name: "basic constraints",
+            }),
+        }
+    }
+
+    pub fn name_constraints(&self) -> Result<&NameConstraints, CertError> {
+        let certificate = &self.0;
+        let ext = find_ext!(oids::name_constraints(), certificate, "name constraints")?;
+        match ext.extn_value() {
+            ExtensionView::NameConstraints(nc) => Ok(nc),
+            _ => Err(CertError::InvalidExtension {
+                name: "name constraints",
+            }),
+        }
+    }
+
+    pub fn key_usage(&self) -> Result<&KeyUsage, CertError> {
+        let certificate = &self.0;
+        let ext = find_ext!(oids::key_usage(), certificate, "key usage")?;
+        match ext.extn_value() {
+            ExtensionView::KeyUsage(ku) => Ok(ku),
+            _ => Err(CertError::InvalidExtension { name: "key usage" }),
+        }
+    }
+
+    pub fn extended_key_usage(&self) -> Result<&ExtendedKeyUsage, CertError> {
+        let certificate = &self.0;
+        let ext = find_ext!(oids::extended_key_usage(), certificate, "extended key usage")?;
+        match ext.extn_value() {
+            ExtensionView::ExtendedKeyUsage(eku) => Ok(eku),
+            _ => Err(CertError::InvalidExtension {
+                name: "extended key usage",
+            }),
+        }
+    }
+
+    pub fn certificate_policies(&self) -> Result<&CertificatePolicies, CertError> {
+        let certificate = &self.0;
+        let ext = find_ext!(oids::certificate_policies(), certificate, "certificate policies")?;
+        match ext.extn_value() {
+            ExtensionView::CertificatePolicies(policies) => Ok(policies),
+            _ => Err(CertError::InvalidExtension {
+                name: "certificate policies",
+            }),
+        }
+    }
+
+    pub fn policy_mappings(&self) -> Result<&PolicyMappings, CertError> {
+        let certificate = &self.0;
+        let ext = find_ext!(oids::policy_mappings(), certificate, "policy mappings")?;
+        match ext.extn_value() {
+            ExtensionView::PolicyMappings(mappings) => Ok(mappings),
+            _ => Err(CertError::InvalidExtension {
+                name: "policy mappings",
+            }),
+        }
+    }
+
+    pub fn subject_alt_names(&self) -> Result<GeneralNames, CertError> {
+        let certificate = &self.0;
+        let ext = find_ext!(
+            oids::subject_alternative_name(),
+            certificate,
+            "subject alternative name"
+        )?;
+        match ext.extn_value() {
+            ExtensionView::SubjectAltName(san) => Ok(san),
+            _ => Err(CertError::InvalidExtension {
+                name: "subject alternative name",
+            }),
+        }
+    }
+
+    pub fn issuer_alt_names(&self) -> Result<GeneralNames, CertError> {
+        let certificate = &self.0;
+        let ext = find_ext!(oids::issuer_alternative_name(), certificate, "issuer alternative name")?;
+        match ext.extn_value() {
+            ExtensionView::IssuerAltName(ian) => Ok(ian),
+            _ => Err(CertError::InvalidExtension {
+                name: "issuer alternative name",
+            }),
+        }
+    }
+
+    pub fn authority_info_access(&self) -> Result<AuthorityInfoAccess, CertError> {
+        let certificate = &self.0;
+        let ext = find_ext!(oids::authority_info_access(), certificate, "authority info access")?;
+        match ext.extn_value() {
+            ExtensionView::Generic(value) => {
+                AuthorityInfoAccess::from_der(&value.0).ok_or(CertError::InvalidExtension {
+                    name: "authority info access",
+                })
+            }
+            _ => Err(CertError::InvalidExtension {
+                name: "authority info access",
+            }),
+        }
+    }
+
+    pub fn crl_distribution_points(&self) -> Result<&CrlDistributionPoints, CertError> {
+        let certificate = &self.0;
+        let ext = find_ext!(oids::crl_distribution_points(), certificate, "crl distribution points")?;
+        match ext.extn_value() {
+            ExtensionView::CrlDistributionPoints(crl_dp) => Ok(crl_dp),
+            _ => Err(CertError::InvalidExtension {
+                name: "crl distribution points",
+            }),
+        }
+    }
+
+    pub fn freshest_crl(&self) -> Result<&CrlDistributionPoints, CertError> {
+        let certificate = &self.0;
+        let ext = find_ext!(oids::freshest_crl(), certificate, "freshest crl")?;
+        match ext.extn_value() {
+            ExtensionView::FreshestCrl(freshest_crl) => Ok(freshest_crl),
+            _ => Err(CertError::InvalidExtension { name: "freshest crl" }),
         }
     }
 
+    /// Looks up an extension using its OID.
+    ///
+    /// Extensions unknown to picky are returned as `ExtensionView::Generic`.
+    pub fn extension(&self, oid: &ObjectIdentifier) -> Option<ExtensionView<'_>> {
+        (self.0.tbs_certificate.extensions.0)
+            .get(oid)
+            .map(Extension::extn_value)
+    }
+
     pub fn subject_name(&self) -> DirectoryName {
         self.0.tbs_certificate.subject.clone().into()
     }
@@ -243,13 +460,15 @@ impl Cert {
     }
 
     pub fn extensions(&self) -> &[Extension] {
-        (self.0.tbs_certificate.extensions.0).0.as_slice()
+        (self.0.tbs_certificate.extensions.0).as_slice()
     }
 
     pub fn public_key(&self) -> &PublicKey {
         (&self.0.tbs_certificate.subject_public_key_info).into()
     }
 
+    /// Checks that the certificate is valid at `now`, and that its critical extensions are all
+    /// known and well-formed.
     pub fn verify(&self, now: &UTCDate) -> Result<(), CertError> {
         let validity = &self.0.tbs_certificate.validity;
         let not_before: UTCDate = validity.not_before.clone().into();
@@ -269,9 +488,88 @@ impl Cert {
             });
         }
 
+        self.check_critical_extensions()
+    }
+
+    fn check_critical_extensions(&self) -> Result<(), CertError> {
+        for extension in self.extensions().iter().filter(|extension| extension.critical()) {
+            let oid = || Into::<String>::into(&extension.extn_id().0);
+            if !extension.is_known() {
+                return Err(CertError::UnsupportedCriticalExtension { oid: oid() });
+            }
+            if let ExtensionView::Generic(_) = extension.extn_value() {
+                return Err(CertError::InvalidCriticalExtension { oid: oid() });
+            }
+        }
+
         Ok(())
     }
 
+    /// Checks that `hostname` is matched by a DNS subject alternative name of this certificate.
+    ///
+    /// Comparison is case-insensitive, ignores trailing dots and accepts internationalized names
+    /// in either Unicode or punycode form.
+    pub fn verify_hostname(&self, hostname: &str) -> Result<(), CertError> {
+        let matches = match self.subject_alt_names() {
+            Ok(san) => san.into_general_names().into_iter().any(|name| match name {
+                GeneralName::DNSName(dns_name) => dns_name_matches(&dns_name.to_string(), hostname),
+                _ => false,
+            }),
+            Err(_) => false,
+        };
+
+        if matches {
+            Ok(())
+        } else {
+            Err(CertError::HostnameMismatch {
+                hostname: hostname.to_owned(),
+            })
+        }
+    }
+
+    /// Whether this certificate identifies `hostname`, following RFC 6125.
+    ///
+    /// `hostname` is matched against the DNS subject alternative names, which may be wildcards.
+    /// The subject common name is only considered when the certificate has no subject alternative
+    /// name extension at all. IP address literals are matched using `matches_ip`.
+    pub fn matches_hostname(&self, hostname: &str) -> bool {
+        if let Ok(ip_address) = hostname.parse::<IpAddr>() {
+            return self.matches_ip(ip_address);
+        }
+
+        match self.subject_alt_names() {
+            Ok(san) => san.into_general_names().into_iter().any(|name| match name {
+                GeneralName::DNSName(dns_name) => dns_name_matches(&dns_name.to_string(), hostname),
+                _ => false,
+            }),
+            // https://tools.ietf.org/html/rfc6125#section-6.4.4
+            Err(CertError::ExtensionNotFound { .. }) => self
+                .subject_name()
+                .find_common_name()
+                .map(|common_name| dns_name_matches(&common_name.to_utf8_lossy(), hostname))
+                .unwrap_or(false),
+            Err(_) => false,
+        }
+    }
+
+    /// Whether `ip_address` is one of the IP address subject alternative names of this certificate.
+    ///
+    /// The subject common name is never considered.
+    pub fn matches_ip(&self, ip_address: IpAddr) -> bool {
+        let octets = match ip_address {
+            IpAddr::V4(ip_address) => ip_address.octets().to_vec(),
+            IpAddr::V6(ip_address) => ip_address.octets().to_vec(),
+        };
+
+        match self.subject_alt_names() {
+            Ok(san) => san.into_general_names().into_iter().any(|name| match name {
+                GeneralName::IpAddress(address) => address == octets,
+                _ => false,
+            }),
+            Err(_) => false,
+        }
+    }
+
     pub fn is_parent_of(&self, other: &Cert) -> Result<(), CertError> {
         if let Ok(other_aki) = other.authority_key_identifier() {
             if let Some(other_aki) = other_aki.key_identifier() {
@@ -313,65 +611,187 @@ impl Cert {
         chain: Chain,
         now: &UTCDate,
     ) -> Result<(), CertError> {
-        self.verify(now).with_context(|| InvalidCertificate {
-            id: self.subject_name().to_string(),
-        })?;
+        self.verify_chain_from_depth(chain, now, 0)
+    }
+
+    /// Same as `verify_chain`, additionally checking that no certificate of the chain is revoked
+    /// according to `provider`. Certificates whose revocation status is unknown are accepted.
+    pub fn verify_chain_with_revocation<'a, Chain: Iterator<Item = &'a Cert>>(
+        &self,
+        chain: Chain,
+        now: &UTCDate,
+        provider: &dyn RevocationProvider,
+    ) -> Result<(), CertError> {
+        let chain = chain.collect::<Vec<&Cert>>();
+        self.verify_chain(chain.iter().copied(), now)?;
 
         let mut current_cert = self;
+        for issuer_cert in chain {
+            if let RevocationStatus::Revoked { revocation_time, .. } =
+                provider.revocation_status(current_cert, issuer_cert, now)
+            {
+                return Err(CertError::CertificateRevoked {
+                    id: current_cert.subject_name().to_string(),
+                    revocation_time,
+                });
+            }
+            current_cert = issuer_cert;
+        }
 
-        for (number_certs, parent_cert) in chain.enumerate() {
-            // check basic constraints
-            match parent_cert
-                .basic_constraints()
-                .map(|bc| (bc.ca(), bc.pathlen()))
-                .unwrap_or((None, None))
+        Ok(())
+    }
+
+    /// Verifies `self` up to one of the roots of `trust_store`, building the chain from
+    /// `intermediates`, which may be given in any order and contain unrelated certificates.
+    ///
+    /// Candidate chains are tried until one passes `verify_chain`, which is returned (from the
+    /// issuer of `self` to the trusted root). Otherwise the error of the last candidate is
+    /// returned, `CaChainError::UnknownIssuer` if the issuer of a certificate of the chain isn't
+    /// available, or `CaChainError::NoTrustedPath` if no chain reaches a trusted root. Self-signed
+    /// certificates among `intermediates` are never trusted.
+    pub fn verify_against(
+        &self,
+        trust_store: &TrustStore,
+        intermediates: &[Cert],
+        now: &UTCDate,
+    ) -> Result<Vec<Cert>, CertError> {
+        if trust_store.contains(self) {
+            self.verify(now).with_context(|| InvalidCertificate {
+                id: self.subject_name().to_string(),
+            })?;
+            return Ok(Vec::new());
+        }
+
+        let mut path = Vec::new();
+        let mut last_error = None;
+        if self.build_trusted_path(trust_store, intermediates, now, &mut path, &mut last_error) {
+            Ok(path.into_iter().cloned().collect())
+        } else {
+            Err(last_error.unwrap_or(CertError::InvalidChain {
+                source: CaChainError::NoTrustedPath,
+            }))
+        }
+    }
+
+    /// Depth-first search of a chain from `self` to a trusted root. `path` holds the issuers
+    /// picked so far, and the chain found if any.
+    fn build_trusted_path<'a>(
+        &self,
+        trust_store: &'a TrustStore,
+        intermediates: &'a [Cert],
+        now: &UTCDate,
+        path: &mut Vec<&'a Cert>,
+        last_error: &mut Option<CertError>,
+    ) -> bool {
+        let current_cert = path.last().copied().unwrap_or(self);
+        let mut issuer_found = false;
+
+        for root in trust_store.roots() {
+            if root.is_parent_of(current_cert).is_err() {
+                continue;
+            }
+
+            issuer_found = true;
+            path.push(root);
+            match self.verify_chain(path.iter().copied(), now) {
+                Ok(()) => return true,
+                Err(e) => *last_error = Some(e),
+            }
+            path.pop();
+        }
+
+        if path.len() == MAX_PATH_INTERMEDIATES {
+            return false;
+        }
+
+        for intermediate in intermediates {
+            if intermediate == self || path.contains(&intermediate) || intermediate.is_parent_of(current_cert).is_err()
             {
-                (Some(false), _) => {
-                    return Err(CaChainError::IssuerIsNotCA {
-                        issuer_id: parent_cert.subject_name().to_string(),
-                    })
-                    .context(InvalidChain);
-                }
-                (_, Some(pathlen)) if usize::from(pathlen) < number_certs => {
-                    return Err(CaChainError::TooDeep {
-                        cert_id: parent_cert.subject_name().to_string(),
-                        pathlen,
+                continue;
+            }
+
+            issuer_found = true;
+            path.push(intermediate);
+            if self.build_trusted_path(trust_store, intermediates, now, path, last_error) {
+                return true;
+            }
+            path.pop();
+        }
+
+        // a self-issued certificate is an untrusted root rather than a dead end
+        if !issuer_found && last_error.is_none() && current_cert.subject_name() != current_cert.issuer_name() {
+            *last_error = Some(CertError::InvalidChain {
+                source: CaChainError::UnknownIssuer {
+                    cert_id: current_cert.subject_name().to_string(),
+                    issuer_id: current_cert.issuer_name().to_string(),
+                },
+            });
+        }
+
+        false
+    }
+
+    /// Verifies many leaf certificates issued by the same CA concurrently.
+    ///
+    /// `chain` starts with the issuing CA and ends with the root CA. It is verified once, failing
+    /// the whole call, then each leaf is verified against the issuing CA. Results are in the order
+    /// of `leaves`, each one matching what `verify_chain` would return for that leaf.
+    #[cfg(feature = "rayon")]
+    pub fn verify_chain_par(
+        leaves: &[Cert],
+        chain: &[Cert],
+        now: &UTCDate,
+    ) -> Result<Vec<Result<(), CertError>>, CertError> {
+        use rayon::prelude::*;
+
+        match chain.split_first() {
+            Some((issuer_cert, ca_chain)) => {
+                // the issuer sits one level above the leaves
+                issuer_cert.verify_chain_from_depth(ca_chain.iter(), now, 1)?;
+
+                Ok(leaves
+                    .par_iter()
+                    .map(|leaf| {
+                        leaf.verify(now).with_context(|| InvalidCertificate {
+                            id: leaf.subject_name().to_string(),
+                        })?;
+                        leaf.verify_issued_by(issuer_cert, 0, now)?;
+                        chain.iter().try_for_each(|ca| leaf.verify_name_constraints(ca))
                     })
-                    .context(InvalidChain);
-                }
-                _ => {}
+                    .collect())
             }
+            None => Ok(leaves
+                .par_iter()
+                .map(|leaf| leaf.verify_chain(std::iter::empty(), now))
+                .collect()),
+        }
+    }
 
-            // verify parent
-            parent_cert.verify(now).with_context(|| InvalidCertificate {
-                id: parent_cert.subject_name().to_string(),
-            })?;
+    /// `depth` is the number of certificates below `self` in the complete chain.
+    fn verify_chain_from_depth<'a, Chain: Iterator<Item = &'a Cert>>(
+        &self,
+        chain: Chain,
+        now: &UTCDate,
+        depth: usize,
+    ) -> Result<(), CertError> {
+        self.verify(now).with_context(|| InvalidCertificate {
+            id: self.subject_name().to_string(),
+        })?;
 
-            // check parent_cert is the parent of current_cert
-            parent_cert.is_parent_of(current_cert)?;
+        let mut current_cert = self;
+        // certificates subject to the name constraints of the following issuers
+        let mut constrained_certs = vec![self];
 
-            // validate current cert signature using parent public key
-            let hash_type =
-                SignatureHashType::from_algorithm_identifier(&current_cert.0.signature_algorithm).context(Signature)?;
-            let public_key = &parent_cert.0.tbs_certificate.subject_public_key_info;
-            let msg = picky_asn1_der::to_vec(&current_cert.0.tbs_certificate)
-                .context(Asn1Serialization {
-                    element: "tbs certificate",
-                })
-                .with_context(|| InvalidCertificate {
-                    id: current_cert.subject_name().to_string(),
-                })?;
-            hash_type
-                .verify(
-                    &public_key.clone().into(),
-                    &msg,
-                    current_cert.0.signature_value.0.payload_view(),
-                )
-                .context(Signature)
-                .with_context(|| InvalidCertificate {
-                    id: current_cert.subject_name().to_string(),
-                })?;
+        for (number_certs, parent_cert) in chain.enumerate() {
+            current_cert.verify_issued_by(parent_cert, depth + number_certs, now)?;
+            for cert in &constrained_certs {
+                cert.verify_name_constraints(parent_cert)?;
+            }
 
+            // self-issued intermediate certificates are exempted (RFC 5280, section 6.1.3)
+            if parent_cert.subject_name() != parent_cert.issuer_name() {
+                constrained_certs.push(parent_cert);
+            }
             current_cert = parent_cert;
         }
 
@@ -382,6 +802,143 @@ impl Cert {
 
         Ok(())
     }
+
+    /// Checks that `parent_cert`, with `number_certs` certificates between it and `self`
+    /// excluded, is a currently valid CA which signed `self`.
+    fn verify_issued_by(&self, parent_cert: &Cert, number_certs: usize, now: &UTCDate) -> Result<(), CertError> {
+        // check basic constraints
+        let basic_constraints = match parent_cert.basic_constraints() {
+            Ok(bc) => (bc.ca(), bc.pathlen()),
+            Err(CertError::ExtensionNotFound { .. }) => (None, None),
+            Err(e) => {
+                return Err(e).with_context(|| InvalidCertificate {
+                    id: parent_cert.subject_name().to_string(),
+                })
+            }
+        };
+        match basic_constraints {
+            (Some(false), _) => {
+                return Err(CaChainError::IssuerIsNotCA {
+                    issuer_id: parent_cert.subject_name().to_string(),
+                })
+                .context(InvalidChain);
+            }
+            (_, Some(pathlen)) if usize::from(pathlen) < number_certs => {
+                return Err(CaChainError::TooDeep {
+                    cert_id: parent_cert.subject_name().to_string(),
+                    pathlen,
+                })
+                .context(InvalidChain);
+            }
+            _ => {}
+        }
+
+        // verify parent
+        parent_cert.verify(now).with_context(|| InvalidCertificate {
+            id: parent_cert.subject_name().to_string(),
+        })?;
+
+        // check parent_cert is the parent of current_cert
+        parent_cert.is_parent_of(self)?;
+
+        // validate current cert signature using parent public key
+        self.verify_signature(parent_cert).with_context(|| InvalidCertificate {
+            id: self.subject_name().to_string(),
+        })?;
+
+        Ok(())
+    }
+
+    /// Checks the signature of `self` using the public key of `parent_cert`.
+    pub(crate) fn verify_signature(&self, parent_cert: &Cert) -> Result<(), CertError> {
+        let hash_type = SignatureHashType::from_algorithm_identifier(&self.0.signature_algorithm).context(Signature)?;
+        let public_key = &parent_cert.0.tbs_certificate.subject_public_key_info;
+        let msg = picky_asn1_der::to_vec(&self.0.tbs_certificate).context(Asn1Serialization {
+            element: "tbs certificate",
+        })?;
+        hash_type
+            .verify(
+                &public_key.clone().into(),
+                &msg,
+                self.0.signature_value.0.payload_view(),
+            )
+            .context(Signature)
+    }
+
+    fn verify_name_constraints(&self, issuer_cert: &Cert) -> Result<(), CertError> {
+        match self.check_name_constraints(issuer_cert) {
+            Ok(()) => Ok(()),
+            Err(NameConstraintsViolation::InvalidIssuer(e)) => Err(e).with_context(|| InvalidCertificate {
+                id: issuer_cert.subject_name().to_string(),
+            }),
+            Err(NameConstraintsViolation::InvalidCert(e)) => Err(e).with_context(|| InvalidCertificate {
+                id: self.subject_name().to_string(),
+            }),
+            Err(NameConstraintsViolation::NameNotPermitted(name)) => Err(CaChainError::NameNotPermitted {
+                cert_id: self.subject_name().to_string(),
+                issuer_id: issuer_cert.subject_name().to_string(),
+                name,
+            })
+            .context(InvalidChain),
+        }
+    }
+
+    /// Checks the names of `self` against the name constraints of `issuer_cert`, shared by
+    /// `verify_chain` and `ChainValidator`.
+    ///
+    /// DNS subject alternative names are checked against the DNS subtrees, as is the subject common
+    /// name when there is no subject alternative name extension and it looks like a DNS name. Other
+    /// name forms aren't enforced: when a critical extension constrains one of them, names of that
+    /// form are rejected instead of ignored (RFC 5280, section 4.2.1.10).
+    pub(crate) fn check_name_constraints(&self, issuer_cert: &Cert) -> Result<(), NameConstraintsViolation> {
+        let issuer = &issuer_cert.0;
+        let critical = match find_ext!(oids::name_constraints(), issuer, "name constraints") {
+            Ok(ext) => ext.critical(),
+            Err(_) => return Ok(()),
+        };
+        let name_constraints = issuer_cert
+            .name_constraints()
+            .map_err(NameConstraintsViolation::InvalidIssuer)?;
+
+        let mut names = match self.subject_alt_names() {
+            Ok(san) => san.into_general_names(),
+            Err(CertError::ExtensionNotFound { .. }) => {
+                let common_name = self.subject_name().find_common_name().map(ToString::to_string);
+                match common_name {
+                    Some(common_name) if is_dns_like(&common_name) => {
+                        if !name_constraints.is_dns_name_permitted(&common_name) {
+                            return Err(NameConstraintsViolation::NameNotPermitted(common_name));
+                        }
+                        Vec::new()
+                    }
+                    _ => Vec::new(),
+                }
+            }
+            Err(e) => return Err(NameConstraintsViolation::InvalidCert(e)),
+        };
+        if !self.subject_name().is_empty() {
+            names.push(GeneralName::DirectoryName(self.subject_name()));
+        }
+
+        for name in names {
+            match name {
+                GeneralName::DNSName(dns_name) => {
+                    let dns_name = dns_name.to_string();
+                    if !name_constraints.is_dns_name_permitted(&dns_name) {
+                        return Err(NameConstraintsViolation::NameNotPermitted(dns_name));
+                    }
+                }
+                name if critical && name_constraints.constrains_other_form(&name) => {
+                    return Err(NameConstraintsViolation::NameNotPermitted(general_name_to_string(
+                        &name,
+                    )));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -420,8 +977,55 @@ struct CertificateBuilderInner<'a> {
     extended_key_usage: Option<ExtendedKeyUsage>,
     subject_alt_name: Option<GeneralNames>,
     issuer_alt_name: Option<GeneralNames>,
+    authority_info_access: Option<AuthorityInfoAccess>,
+    crl_distribution_points: Option<CrlDistributionPoints>,
+    freshest_crl: Option<CrlDistributionPoints>,
+    name_constraints: Option<NameConstraints>,
+    certificate_policies: Option<CertificatePolicies>,
+    policy_mappings: Option<PolicyMappings>,
+    serial_number: Option<Vec<u8>>,
+    extensions: Vec<Extension>,
 }
 
+/// Builds certificates of any profile: serial number, validity period, subject and issuer names,
+/// extensions and signature algorithm can all be set.
+///
+/// Basic constraints, subject key identifier and authority key identifier extensions are always
+/// included.
+///
+/// ```
+/// use picky::{
+///     key::PrivateKey,
+///     oids,
+///     signature::SignatureHashType,
+///     x509::{date::UTCDate, extension::KeyUsage, name::DirectoryName, CertificateBuilder},
+/// };
+///
+/// let ca_key = PrivateKey::generate_rsa(2048).unwrap();
+/// let ca = CertificateBuilder::new()
+///     .valididy(UTCDate::ymd(2020, 1, 1).unwrap(), UTCDate::ymd(2030, 1, 1).unwrap())
+///     .self_signed(DirectoryName::new_common_name("Contoso Code Signing CA"), &ca_key)
+///     .ca(true)
+///     .build()
+///     .unwrap();
+///
+/// let signer_key = PrivateKey::generate_rsa(2048).unwrap();
+/// let code_signing = CertificateBuilder::new()
+///     .serial_number(vec![0x0a, 0x1b, 0x2c])
+///     .valididy(UTCDate::ymd(2021, 1, 1).unwrap(), UTCDate::ymd(2022, 1, 1).unwrap())
+///     .subject(DirectoryName::new_common_name("Contoso Release"), signer_key.to_public_key())
+///     .issuer_cert(&ca, &ca_key)
+///     .key_usage(KeyUsage::builder().digital_signature().build())
+///     .extended_key_usage(vec![oids::kp_code_signing()].into())
+///     .signature_hash_type(SignatureHashType::RsaSha384)
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(code_signing.serial_number().as_unsigned_bytes_be(), &[0x0a, 0x1b, 0x2c]);
+/// code_signing
+///     .verify_chain(std::iter::once(&ca), &UTCDate::ymd(2021, 6, 1).unwrap())
+///     .unwrap();
+/// ```
 #[derive(Default, Clone, Debug)]
 pub struct CertificateBuilder<'a> {
     inner: RefCell<CertificateBuilderInner<'a>>,
@@ -556,6 +1160,65 @@ impl<'a> CertificateBuilder<'a> {
         self
     }
 
+    /// Optional
+    #[inline]
+    pub fn authority_info_access(&self, authority_info_access: AuthorityInfoAccess) -> &Self {
+        self.inner.borrow_mut().authority_info_access = Some(authority_info_access);
+        self
+    }
+
+    /// Optional
+    #[inline]
+    pub fn crl_distribution_points(&self, crl_distribution_points: CrlDistributionPoints) -> &Self {
+        self.inner.borrow_mut().crl_distribution_points = Some(crl_distribution_points);
+        self
+    }
+
+    /// Optional
+    #[inline]
+    pub fn freshest_crl(&self, freshest_crl: CrlDistributionPoints) -> &Self {
+        self.inner.borrow_mut().freshest_crl = Some(freshest_crl);
+        self
+    }
+
+    /// Optional (CA certificates only)
+    #[inline]
+    pub fn name_constraints(&self, name_constraints: NameConstraints) -> &Self {
+        self.inner.borrow_mut().name_constraints = Some(name_constraints);
+        self
+    }
+
+    /// Optional
+    #[inline]
+    pub fn certificate_policies(&self, certificate_policies: CertificatePolicies) -> &Self {
+        self.inner.borrow_mut().certificate_policies = Some(certificate_policies);
+        self
+    }
+
+    /// Optional (CA certificates only)
+    #[inline]
+    pub fn policy_mappings(&self, policy_mappings: PolicyMappings) -> &Self {
+        self.inner.borrow_mut().policy_mappings = Some(policy_mappings);
+        self
+    }
+
+    /// Optional (randomly generated if omitted)
+    ///
+    /// Unsigned big-endian bytes: a leading zero byte is added if needed to keep the serial number
+    /// positive.
+    #[inline]
+    pub fn serial_number(&self, serial_number: Vec<u8>) -> &Self {
+        self.inner.borrow_mut().serial_number = Some(serial_number);
+        self
+    }
+
+    /// Optional (additional extensions, appended after the ones set by the builder)
+    #[inline]
+    pub fn extensions(&self, extensions: Vec<Extension>) -> &Self {
+        self.inner.borrow_mut().extensions = extensions;
+        self
+    }
+
     pub fn build(&self) -> Result<Cert, CertError> {
         let mut inner = self.inner.borrow_mut();
 
@@ -617,10 +1280,21 @@ impl<'a> CertificateBuilder<'a> {
         let extended_key_usage_opt = inner.extended_key_usage.take();
         let subject_alt_name_opt = inner.subject_alt_name.take();
         let issuer_alt_name_opt = inner.issuer_alt_name.take();
+        let authority_info_access_opt = inner.authority_info_access.take();
+        let crl_distribution_points_opt = inner.crl_distribution_points.take();
+        let freshest_crl_opt = inner.freshest_crl.take();
+        let name_constraints_opt = inner.name_constraints.take();
+        let certificate_policies_opt = inner.certificate_policies.take();
+        let policy_mappings_opt = inner.policy_mappings.take();
+        let serial_number = inner.serial_number.take().unwrap_or_else(generate_serial_number);
+        let additional_extensions = std::mem::take(&mut inner.extensions);
 
         drop(inner);
 
-        let serial_number = generate_serial_number();
+        // RFC 5280: subjects with an empty name are identified by a critical subject alt name
+        if subject_name.is_empty() && subject_alt_name_opt.is_none() {
+            return Err(CertError::MissingSubjectAltName);
+        }
 
         let validity = Validity {
             not_before: valid_from.into(),
@@ -657,6 +1331,42 @@ impl<'a> CertificateBuilder<'a> {
                 extensions.push(Extension::new_issuer_alt_name(ian));
             }
 
+            // name constraints
+            if let Some(name_constraints) = name_constraints_opt {
+                extensions.push(Extension::new_name_constraints(name_constraints));
+            }
+
+            // certificate policies
+            if let Some(certificate_policies) = certificate_policies_opt {
+                extensions.push(Extension::new_certificate_policies(certificate_policies));
+            }
+
+            // policy mappings
+            if let Some(policy_mappings) = policy_mappings_opt {
+                extensions.push(Extension::new_policy_mappings(policy_mappings));
+            }
+
+            // aia
+            if let Some(aia) = authority_info_access_opt {
+                extensions.push(
+                    Extension::new_authority_info_access(&aia)
+                        .context(Asn1Serialization {
+                            element: "authority info access",
+                        })
+                        .context(CertGeneration)?,
+                );
+            }
+
+            // crl dp
+            if let Some(crl_dp) = crl_distribution_points_opt {
+                extensions.push(Extension::new_crl_distribution_points(crl_dp));
+            }
+
+            // freshest crl
+            if let Some(freshest_crl) = freshest_crl_opt {
+                extensions.push(Extension::new_freshest_crl(freshest_crl));
+            }
+
             // ski
             let ski = key_id_gen_method
                 .generate_from(&subject_public_key)
@@ -671,17 +1381,27 @@ impl<'a> CertificateBuilder<'a> {
                 None,
             ));
 
-            Extensions(extensions)
+            let mut extensions = Extensions::from(extensions);
+            for extension in additional_extensions {
+                let oid = Into::<String>::into(&extension.extn_id().0);
+                if !extensions.add(extension) {
+                    return Err(CertError::DuplicatedExtension { oid });
+                }
+            }
+
+            extensions
         };
 
         let tbs_certificate = TBSCertificate {
             version: Version::V3.into(),
-            serial_number: serial_number.into(),
+            serial_number: IntegerAsn1::from_unsigned_bytes_be(serial_number),
             signature: signature_hash_type.into(),
             issuer: issuer_name.into(),
             validity,
             subject: subject_name.into(),
             subject_public_key_info: subject_public_key.into(),
+            issuer_unique_id: None,
+            subject_unique_id: None,
             extensions: extensions.into(),
         };
 
@@ -714,6 +1434,30 @@ fn generate_serial_number() -> Vec<u8> {
     vec![b1, b2, b3, b4]
 }
 
+/// Whether a subject common name stands for a DNS name, as opposed to e.g. "Picky Root CA".
+fn is_dns_like(common_name: &str) -> bool {
+    common_name.contains('.') && !common_name.contains(char::is_whitespace) && normalize_dns_name(common_name).is_ok()
+}
+
+/// Name reported when it isn't permitted by name constraints.
+fn general_name_to_string(name: &GeneralName) -> String {
+    match name {
+        GeneralName::RFC822Name(name) | GeneralName::DNSName(name) | GeneralName::URI(name) => name.to_string(),
+        GeneralName::DirectoryName(name) => name.to_string(),
+        GeneralName::IpAddress(address) if address.len() == 4 => {
+            let mut octets = [0; 4];
+            octets.copy_from_slice(address);
+            IpAddr::from(octets).to_string()
+        }
+        GeneralName::IpAddress(address) if address.len() == 16 => {
+            let mut octets = [0; 16];
+            octets.copy_from_slice(address);
+            IpAddr::from(octets).to_string()
+        }
+        name => format!("{:?}", name),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -773,6 +1517,327 @@ mod tests {
         pretty_assertions::assert_eq!(hex::encode(&key_id), kid);
     }
 
+    #[test]
+    fn extension_lookup() {
+        let key = parse_key(crate::test_files::RSA_2048_PK_1);
+
+        let mut key_usage = KeyUsage::default();
+        key_usage.set_digital_signature(true);
+
+        let cert = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2019, 10, 10).unwrap(), UTCDate::ymd(2019, 10, 11).unwrap())
+            .self_signed(DirectoryName::new_common_name("test"), &key)
+            .key_usage(key_usage.clone())
+            .extended_key_usage(vec![oids::kp_server_auth()].into())
+            .subject_alt_name(GeneralNames::new_dns_name(
+                picky_asn1::restricted_string::IA5String::from_string("test.example.com".into()).unwrap(),
+            ))
+            .build()
+            .expect("couldn't build certificate");
+
+        assert_eq!(cert.key_usage().unwrap(), &key_usage);
+        assert!(cert.extended_key_usage().unwrap().contains(oids::kp_server_auth()));
+        assert_eq!(
+            cert.subject_alt_names().unwrap().find_dns_name().unwrap().to_string(),
+            "test.example.com"
+        );
+        assert!(cert.basic_constraints().is_ok());
+        assert!(cert.issuer_alt_names().is_err());
+
+        match cert.extension(&oids::key_usage()) {
+            Some(ExtensionView::KeyUsage(ku)) => assert_eq!(ku, &key_usage),
+            _ => panic!("expected key usage"),
+        }
+        assert!(cert.extension(&oids::issuer_alternative_name()).is_none());
+    }
+
+    #[test]
+    fn san_only_certificate() {
+        let key = parse_key(crate::test_files::RSA_2048_PK_1);
+        let ca = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2019, 10, 10).unwrap(), UTCDate::ymd(2019, 10, 11).unwrap())
+            .self_signed(DirectoryName::new_common_name("test CA"), &key)
+            .ca(true)
+            .build()
+            .expect("couldn't build CA certificate");
+
+        let err = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2019, 10, 10).unwrap(), UTCDate::ymd(2019, 10, 11).unwrap())
+            .subject(DirectoryName::new_empty(), key.to_public_key())
+            .issuer_cert(&ca, &key)
+            .build()
+            .err()
+            .expect("empty subject without SAN");
+        assert_eq!(
+            err.to_string(),
+            "subject name is empty but no subject alternative name is provided"
+        );
+
+        let cert = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2019, 10, 10).unwrap(), UTCDate::ymd(2019, 10, 11).unwrap())
+            .subject(DirectoryName::new_empty(), key.to_public_key())
+            .issuer_cert(&ca, &key)
+            .subject_alt_name(GeneralNames::new_dns_name(
+                picky_asn1::restricted_string::IA5String::from_string("test.example.com".into()).unwrap(),
+            ))
+            .build()
+            .expect("couldn't build SAN-only certificate");
+
+        assert!(cert.subject_name().is_empty());
+        let san = cert
+            .extensions()
+            .iter()
+            .find(|extension| extension.extn_id().0 == oids::subject_alternative_name())
+            .expect("SAN extension");
+        assert!(san.critical());
+
+        let cert = Cert::from_der(&cert.to_der().unwrap()).expect("couldn't parse SAN-only certificate");
+        assert!(cert.subject_name().is_empty());
+        assert_eq!(
+            cert.subject_alt_names().unwrap().find_dns_name().unwrap().to_string(),
+            "test.example.com"
+        );
+    }
+
+    #[test]
+    fn hostname_verification() {
+        let key = parse_key(crate::test_files::RSA_2048_PK_1);
+
+        let mut san = GeneralNames::new(GeneralName::new_dns_name("Test.Example.com").unwrap());
+        san.add_name(GeneralName::new_dns_name("*.xn--bcher-kva.example").unwrap());
+        let cert = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2019, 10, 10).unwrap(), UTCDate::ymd(2019, 10, 11).unwrap())
+            .self_signed(DirectoryName::new_common_name("other.example.com"), &key)
+            .subject_alt_name(san)
+            .build()
+            .expect("couldn't build certificate");
+
+        cert.verify_hostname("test.example.com")
+            .expect("same name, different case");
+        cert.verify_hostname("test.example.com.").expect("fully qualified name");
+        cert.verify_hostname("shop.bücher.example").expect("unicode name");
+        cert.verify_hostname("shop.xn--bcher-kva.example")
+            .expect("punycode name");
+
+        match cert.verify_hostname("other.example.com") {
+            Err(CertError::HostnameMismatch { .. }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(cert.verify_hostname("bücher.example").is_err());
+    }
+
+    #[test]
+    fn hostname_matching() {
+        let key = parse_key(crate::test_files::RSA_2048_PK_1);
+        let build = |common_name: &str, san: Option<GeneralNames>| {
+            let builder = CertificateBuilder::new();
+            builder
+                .valididy(UTCDate::ymd(2019, 10, 10).unwrap(), UTCDate::ymd(2019, 10, 11).unwrap())
+                .self_signed(DirectoryName::new_common_name(common_name), &key);
+            if let Some(san) = san {
+                builder.subject_alt_name(san);
+            }
+            builder.build().expect("couldn't build certificate")
+        };
+
+        let mut san = GeneralNames::new(GeneralName::new_dns_name("*.example.com").unwrap());
+        san.add_name(GeneralName::new_ip_address(vec![10, 1, 0, 1]));
+        san.add_name(GeneralName::new_ip_address(
+            "fd00::1".parse::<std::net::Ipv6Addr>().unwrap().octets().to_vec(),
+        ));
+        let cert = build("legacy.example.org", Some(san));
+        assert!(cert.matches_hostname("www.example.com"));
+        assert!(!cert.matches_hostname("example.com"));
+        assert!(!cert.matches_hostname("a.b.example.com"));
+        // the common name is ignored when subject alternative names are present
+        assert!(!cert.matches_hostname("legacy.example.org"));
+        assert!(cert.matches_hostname("10.1.0.1"));
+        assert!(cert.matches_ip("fd00::1".parse().unwrap()));
+        assert!(!cert.matches_ip("10.1.0.2".parse().unwrap()));
+
+        let cert = build("Legacy.Example.org", None);
+        assert!(cert.matches_hostname("legacy.example.org"));
+        assert!(!cert.matches_hostname("www.example.org"));
+
+        // IP addresses are never matched against the common name
+        let cert = build("10.1.0.1", None);
+        assert!(!cert.matches_hostname("10.1.0.1"));
+
+        // a subject alternative name extension without DNS names doesn't fall back either
+        let cert = build(
+            "legacy.example.org",
+            Some(GeneralNames::new(
+                GeneralName::new_uri("spiffe://example.org/legacy").unwrap(),
+            )),
+        );
+        assert!(!cert.matches_hostname("legacy.example.org"));
+    }
+
+    #[test]
+    fn certificate_policies() {
+        use crate::x509::extension::{PolicyInformation, UserNotice};
+        use std::convert::TryFrom;
+
+        let key = parse_key(crate::test_files::RSA_2048_PK_1);
+        let policy_oid = ObjectIdentifier::try_from("1.3.6.1.4.1.55555.1.1").unwrap();
+        let cert = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2019, 10, 10).unwrap(), UTCDate::ymd(2019, 10, 11).unwrap())
+            .self_signed(DirectoryName::new_common_name("test CA"), &key)
+            .ca(true)
+            .certificate_policies(
+                CertificatePolicies::new().policy(
+                    PolicyInformation::new(policy_oid.clone())
+                        .cps_uri("https://pki.example.com/cps")
+                        .unwrap()
+                        .user_notice(UserNotice::new().with_explicit_text("Internal use")),
+                ),
+            )
+            .build()
+            .expect("couldn't build certificate");
+
+        let cert = Cert::from_der(&cert.to_der().unwrap()).expect("couldn't parse certificate");
+        let certificate_policies = cert.certificate_policies().unwrap();
+        assert!(certificate_policies.contains(policy_oid));
+        assert_eq!(certificate_policies.iter().next().unwrap().qualifiers().len(), 2);
+        let extension = cert
+            .extensions()
+            .iter()
+            .find(|extension| extension.extn_id().0 == oids::certificate_policies())
+            .expect("certificate policies extension");
+        assert!(!extension.critical());
+
+        match cert.policy_mappings() {
+            Err(CertError::ExtensionNotFound { .. }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn additional_extensions() {
+        use std::convert::TryFrom;
+
+        let key = parse_key(crate::test_files::RSA_2048_PK_1);
+        let private_oid = ObjectIdentifier::try_from("1.3.6.1.4.1.53453.1").unwrap();
+
+        let cert = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2019, 10, 10).unwrap(), UTCDate::ymd(2019, 10, 11).unwrap())
+            .self_signed(DirectoryName::new_common_name("test"), &key)
+            .extensions(vec![Extension::new_generic(
+                private_oid.clone(),
+                false,
+                vec![0x05, 0x00],
+            )])
+            .build()
+            .expect("couldn't build certificate");
+        let cert = Cert::from_der(&cert.to_der().unwrap()).expect("couldn't parse certificate");
+        match cert.extension(&private_oid) {
+            Some(ExtensionView::Generic(value)) => assert_eq!(value.0, vec![0x05, 0x00]),
+            _ => panic!("expected generic extension"),
+        }
+
+        let err = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2019, 10, 10).unwrap(), UTCDate::ymd(2019, 10, 11).unwrap())
+            .self_signed(DirectoryName::new_common_name("test"), &key)
+            .extensions(vec![Extension::new_generic(
+                oids::basic_constraints(),
+                true,
+                vec![0x30, 0x00],
+            )])
+            .build()
+            .err()
+            .expect("duplicated basic constraints");
+        assert_eq!(err.to_string(), "extension 2.5.29.19 is set more than once");
+    }
+
+    #[test]
+    fn critical_extensions() {
+        use std::convert::TryFrom;
+
+        let key = parse_key(crate::test_files::RSA_2048_PK_1);
+        let now = UTCDate::ymd(2019, 10, 10).unwrap();
+        let build_with = |extension: Extension| {
+            let cert = CertificateBuilder::new()
+                .valididy(UTCDate::ymd(2019, 10, 9).unwrap(), UTCDate::ymd(2019, 10, 11).unwrap())
+                .self_signed(DirectoryName::new_common_name("test"), &key)
+                .extensions(vec![extension])
+                .build()
+                .expect("couldn't build certificate");
+            Cert::from_der(&cert.to_der().unwrap()).expect("couldn't parse certificate")
+        };
+
+        let private_oid = ObjectIdentifier::try_from("1.3.6.1.4.1.53453.1").unwrap();
+        build_with(Extension::new_generic(private_oid.clone(), false, vec![0x05, 0x00]))
+            .verify(&now)
+            .expect("non-critical unknown extension is ignored");
+
+        let err = build_with(Extension::new_generic(private_oid, true, vec![0x05, 0x00]))
+            .verify(&now)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "critical extension 1.3.6.1.4.1.53453.1 isn't supported"
+        );
+
+        let err = build_with(Extension::new_generic(
+            oids::extended_key_usage(),
+            true,
+            vec![0x04, 0x00],
+        ))
+        .verify(&now)
+        .unwrap_err();
+        assert_eq!(err.to_string(), "invalid critical extension: 2.5.29.37");
+    }
+
+    #[test]
+    fn malformed_issuer_basic_constraints() {
+        let root_key = parse_key(crate::test_files::RSA_2048_PK_1);
+        let leaf_key = parse_key(crate::test_files::RSA_2048_PK_2);
+
+        let mut root = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2019, 10, 9).unwrap(), UTCDate::ymd(2019, 10, 11).unwrap())
+            .self_signed(DirectoryName::new_common_name("test root"), &root_key)
+            .ca(true)
+            .build()
+            .expect("couldn't build root ca");
+        let leaf = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2019, 10, 9).unwrap(), UTCDate::ymd(2019, 10, 11).unwrap())
+            .subject(DirectoryName::new_common_name("test leaf"), leaf_key.to_public_key())
+            .issuer_cert(&root, &root_key)
+            .build()
+            .expect("couldn't build leaf");
+
+        // a malformed value must not be taken for a missing extension
+        (root.0.tbs_certificate.extensions.0).replace(Extension::new_generic(
+            oids::basic_constraints(),
+            false,
+            vec![0x04, 0x00],
+        ));
+
+        let err = leaf
+            .verify_chain(std::iter::once(&root), &UTCDate::ymd(2019, 10, 10).unwrap())
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid certificate 'CN=test root': invalid extension: basic constraints"
+        );
+    }
+
+    #[test]
+    fn positive_serial_number() {
+        let key = parse_key(crate::test_files::RSA_2048_PK_1);
+
+        let cert = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2019, 10, 10).unwrap(), UTCDate::ymd(2019, 10, 11).unwrap())
+            .self_signed(DirectoryName::new_common_name("test"), &key)
+            .serial_number(vec![0x80, 0x01])
+            .build()
+            .expect("couldn't build certificate");
+        let cert = Cert::from_der(&cert.to_der().unwrap()).expect("couldn't parse certificate");
+
+        assert!(cert.serial_number().is_positive());
+        assert_eq!(cert.serial_number().as_unsigned_bytes_be(), &[0x80, 0x01]);
+    }
+
     fn parse_key(pem_str: &str) -> PrivateKey {
         let pem = pem_str.parse::<Pem>().unwrap();
         PrivateKey::from_pkcs8(pem.data()).unwrap()
@@ -870,6 +1935,80 @@ mod tests {
         );
     }
 
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_chain_verification() {
+        let root_key = parse_key(crate::test_files::RSA_2048_PK_1);
+        let intermediate_key = parse_key(crate::test_files::RSA_2048_PK_2);
+        let leaf_key = parse_key(crate::test_files::RSA_2048_PK_3);
+        let now = UTCDate::ymd(2069, 10, 1).unwrap();
+
+        let build_root = |pathlen: Option<u8>| {
+            let builder = CertificateBuilder::new();
+            builder
+                .valididy(UTCDate::ymd(2065, 6, 15).unwrap(), UTCDate::ymd(2070, 6, 15).unwrap())
+                .self_signed(DirectoryName::new_common_name("TheFuture.usodakedo Root CA"), &root_key)
+                .ca(true);
+            if let Some(pathlen) = pathlen {
+                builder.pathlen(pathlen);
+            }
+            builder.build().expect("couldn't build root ca")
+        };
+        let build_intermediate = |root: &Cert| {
+            CertificateBuilder::new()
+                .valididy(UTCDate::ymd(2068, 1, 1).unwrap(), UTCDate::ymd(2071, 1, 1).unwrap())
+                .subject(
+                    DirectoryName::new_common_name("TheFuture.usodakedo Authority"),
+                    intermediate_key.to_public_key(),
+                )
+                .issuer_cert(root, &root_key)
+                .ca(true)
+                .pathlen(0)
+                .build()
+                .expect("couldn't build intermediate ca")
+        };
+        let build_leaf = |name: &str, issuer: &Cert, issuer_key: &PrivateKey| {
+            CertificateBuilder::new()
+                .valididy(UTCDate::ymd(2069, 1, 1).unwrap(), UTCDate::ymd(2072, 1, 1).unwrap())
+                .subject(DirectoryName::new_common_name(name), leaf_key.to_public_key())
+                .issuer_cert(issuer, issuer_key)
+                .build()
+                .expect("couldn't build leaf")
+        };
+
+        let root = build_root(None);
+        let intermediate = build_intermediate(&root);
+        let leaves = [
+            build_leaf("leaf 1", &intermediate, &intermediate_key),
+            // same issuer name and key identifier, but not signed by the intermediate key
+            build_leaf("leaf 2", &intermediate, &root_key),
+            build_leaf("leaf 3", &intermediate, &intermediate_key),
+        ];
+        let chain = [intermediate, root];
+
+        let results = Cert::verify_chain_par(&leaves, &chain, &now).expect("valid chain");
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+        assert!(results[2].is_ok());
+        for (leaf, result) in leaves.iter().zip(results.iter()) {
+            assert_eq!(leaf.verify_chain(chain.iter(), &now).is_ok(), result.is_ok());
+        }
+
+        // leaves count in the root path length
+        let root = build_root(Some(0));
+        let intermediate = build_intermediate(&root);
+        let leaf = build_leaf("leaf", &intermediate, &intermediate_key);
+        let chain = [intermediate, root];
+        assert!(leaf.verify_chain(chain.iter(), &now).is_err());
+        let err = Cert::verify_chain_par(&[leaf], &chain, &now).err().expect("too deep");
+        assert_eq!(
+            err.to_string(),
+            "CA chain error: chain depth doesn't satisfy basic constraints extension: \
+             certificate 'CN=TheFuture.usodakedo Root CA' has pathlen of 0"
+        );
+    }
+
     #[test]
     fn malicious_ca_chain() {
         let root_key = parse_key(crate::test_files::RSA_2048_PK_1);
@@ -934,6 +2073,12 @@ mod tests {
             invalid_sig_err.to_string(),
             "invalid certificate \'CN=V.E.R.Y Legitimate VerySafe Authority\': signature error: invalid signature"
         );
+        match invalid_sig_err.root_cause() {
+            CertError::Signature {
+                source: SignatureError::BadSignature,
+            } => {}
+            err => panic!("unexpected error: {}", err),
+        }
     }
 
     #[test]
@@ -1004,4 +2149,91 @@ mod tests {
             "CA chain error: issuer certificate \'CN=I Trust This V.E.R.Y Legitimate Intermediate Certificate\' is not a CA"
         );
     }
+
+    #[test]
+    fn name_constrained_ca_chain() {
+        let root_key = parse_key(crate::test_files::RSA_2048_PK_1);
+        let intermediate_key = parse_key(crate::test_files::RSA_2048_PK_2);
+        let leaf_key = parse_key(crate::test_files::RSA_2048_PK_3);
+
+        let root = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2065, 6, 15).unwrap(), UTCDate::ymd(2070, 6, 15).unwrap())
+            .self_signed(DirectoryName::new_common_name("Example Root CA"), &root_key)
+            .ca(true)
+            .build()
+            .expect("couldn't build root ca");
+
+        let intermediate = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2068, 1, 1).unwrap(), UTCDate::ymd(2071, 1, 1).unwrap())
+            .subject(
+                DirectoryName::new_common_name("Example Authority"),
+                intermediate_key.to_public_key(),
+            )
+            .issuer_cert(&root, &root_key)
+            .ca(true)
+            .name_constraints(
+                NameConstraints::new()
+                    .permitted_subtree(GeneralName::new_dns_name("example.com").unwrap())
+                    .excluded_subtree(GeneralName::new_dns_name("internal.example.com").unwrap()),
+            )
+            .build()
+            .expect("couldn't build intermediate ca");
+        assert!(intermediate
+            .name_constraints()
+            .unwrap()
+            .is_dns_name_permitted("www.example.com"));
+
+        let build_leaf = |dns_name: &str| {
+            CertificateBuilder::new()
+                .valididy(UTCDate::ymd(2069, 1, 1).unwrap(), UTCDate::ymd(2072, 1, 1).unwrap())
+                .subject(DirectoryName::new_common_name(dns_name), leaf_key.to_public_key())
+                .subject_alt_name(GeneralNames::new(GeneralName::new_dns_name(dns_name).unwrap()))
+                .issuer_cert(&intermediate, &intermediate_key)
+                .build()
+                .expect("couldn't build signed leaf")
+        };
+
+        let chain = [intermediate.clone(), root.clone()];
+        let now = UTCDate::ymd(2069, 10, 1).unwrap();
+
+        build_leaf("www.example.com")
+            .verify_chain(chain.iter(), &now)
+            .expect("couldn't verify chain");
+
+        let not_permitted_err = build_leaf("www.example.org")
+            .verify_chain(chain.iter(), &now)
+            .unwrap_err();
+        assert_eq!(
+            not_permitted_err.to_string(),
+            "CA chain error: name \'www.example.org\' of certificate \'CN=www.example.org\' \
+             isn\'t permitted by name constraints of \'CN=Example Authority\'"
+        );
+
+        let excluded_err = build_leaf("db.internal.example.com")
+            .verify_chain(chain.iter(), &now)
+            .unwrap_err();
+        assert_eq!(
+            excluded_err.to_string(),
+            "CA chain error: name \'db.internal.example.com\' of certificate \'CN=db.internal.example.com\' \
+             isn\'t permitted by name constraints of \'CN=Example Authority\'"
+        );
+
+        // without subject alternative names, the common name is checked instead
+        let common_name_only_err = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2069, 1, 1).unwrap(), UTCDate::ymd(2072, 1, 1).unwrap())
+            .subject(
+                DirectoryName::new_common_name("www.example.org"),
+                leaf_key.to_public_key(),
+            )
+            .issuer_cert(&intermediate, &intermediate_key)
+            .build()
+            .expect("couldn't build signed leaf")
+            .verify_chain(chain.iter(), &now)
+            .unwrap_err();
+        assert_eq!(
+            common_name_only_err.to_string(),
+            "CA chain error: name \'www.example.org\' of certificate \'CN=www.example.org\' \
+             isn\'t permitted by name constraints of \'CN=Example Authority\'"
+        );
+    }
 }
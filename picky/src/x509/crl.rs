@@ -0,0 +1,648 @@
+use crate::{
+    key::{PrivateKey, PublicKey},
+    oids,
+    pem::Pem,
+    signature::{SignatureError, SignatureHashType},
+    x509::{
+        certificate::Cert,
+        date::UTCDate,
+        extension::{CrlReason, ExtensionView, IssuingDistributionPoint, KeyIdentifier},
+        name::DirectoryName,
+        private::{
+            certificate_list::{CertificateList, TBSCertList},
+            validity::Time,
+        },
+        Extension, Extensions,
+    },
+    AlgorithmIdentifier,
+};
+use picky_asn1::{
+    bit_string::BitString,
+    tag::TagPeeker,
+    wrapper::{ApplicationTag0, Asn1SequenceOf, IntegerAsn1},
+};
+use picky_asn1_der::Asn1DerError;
+use serde::{de, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{cell::RefCell, fmt};
+
+#[derive(Debug, Snafu)]
+pub enum CrlError {
+    /// asn1 serialization error
+    #[snafu(display("(asn1) couldn't serialize {}: {}", element, source))]
+    Asn1Serialization {
+        element: &'static str,
+        source: Asn1DerError,
+    },
+
+    /// asn1 deserialization error
+    #[snafu(display("(asn1) couldn't deserialize {}: {}", element, source))]
+    Asn1Deserialization {
+        element: &'static str,
+        source: Asn1DerError,
+    },
+
+    /// signature error
+    #[snafu(display("signature error: {}", source))]
+    Signature { source: SignatureError },
+
+    /// invalid PEM label error
+    #[snafu(display("invalid PEM label: {}", label))]
+    InvalidPemLabel { label: String },
+
+    /// missing required builder argument
+    #[snafu(display("missing required builder argument `{}`", arg))]
+    MissingBuilderArgument { arg: &'static str },
+
+    /// CRL isn't issued by the given certificate
+    #[snafu(display("CRL issuer name mismatch (expected {}, got {})", expected, actual))]
+    IssuerNameMismatch { expected: String, actual: String },
+
+    /// issuing distribution point extension is malformed or uses unsupported fields
+    #[snafu(display("unsupported issuing distribution point extension"))]
+    UnsupportedIssuingDistributionPoint,
+}
+
+const CRL_PEM_LABEL: &str = "X509 CRL";
+
+/// Certificate Revocation List
+///
+/// https://tools.ietf.org/html/rfc5280#section-5
+#[derive(Clone, Debug, PartialEq)]
+pub struct Crl(CertificateList);
+
+static_assertions::assert_impl_all!(Crl: Send, Sync);
+static_assertions::assert_impl_all!(CrlError: Send, Sync);
+
+impl From<CertificateList> for Crl {
+    fn from(certificate_list: CertificateList) -> Self {
+        Self(certificate_list)
+    }
+}
+
+impl Crl {
+    pub fn builder<'a>() -> CrlBuilder<'a> {
+        CrlBuilder::new()
+    }
+
+    pub fn from_der<T: ?Sized + AsRef<[u8]>>(der: &T) -> Result<Self, CrlError> {
+        Ok(Self(picky_asn1_der::from_bytes(der.as_ref()).context(
+            Asn1Deserialization {
+                element: "certificate list",
+            },
+        )?))
+    }
+
+    pub fn from_pem(pem: &Pem) -> Result<Self, CrlError> {
+        match pem.label() {
+            CRL_PEM_LABEL => Self::from_der(pem.data()),
+            _ => Err(CrlError::InvalidPemLabel {
+                label: pem.label().to_owned(),
+            }),
+        }
+    }
+
+    pub fn to_der(&self) -> Result<Vec<u8>, CrlError> {
+        picky_asn1_der::to_vec(&self.0).context(Asn1Serialization {
+            element: "certificate list",
+        })
+    }
+
+    pub fn to_pem(&self) -> Result<Pem<'static>, CrlError> {
+        Ok(Pem::new(CRL_PEM_LABEL, self.to_der()?))
+    }
+
+    pub fn issuer_name(&self) -> DirectoryName {
+        self.0.tbs_cert_list.issuer.clone().into()
+    }
+
+    pub fn this_update(&self) -> UTCDate {
+        self.0.tbs_cert_list.this_update.clone().into()
+    }
+
+    pub fn next_update(&self) -> Option<UTCDate> {
+        self.0.tbs_cert_list.next_update.clone().map(UTCDate::from)
+    }
+
+    pub fn revoked_certificates(&self) -> &[RevokedCertificate] {
+        match &self.0.tbs_cert_list.revoked_certificates {
+            Some(revoked_certificates) => revoked_certificates.0.as_slice(),
+            None => &[],
+        }
+    }
+
+    /// Looks up the entry of a certificate using its serial number.
+    pub fn find_revoked(&self, serial_number: &IntegerAsn1) -> Option<&RevokedCertificate> {
+        self.revoked_certificates()
+            .iter()
+            .find(|entry| entry.serial_number() == serial_number)
+    }
+
+    pub fn extensions(&self) -> &[Extension] {
+        match &self.0.tbs_cert_list.crl_extensions {
+            Some(extensions) => extensions.0.as_slice(),
+            None => &[],
+        }
+    }
+
+    /// Monotonically increasing sequence number of the CRL, if any.
+    pub fn crl_number(&self) -> Option<IntegerAsn1> {
+        let extension = self
+            .extensions()
+            .iter()
+            .find(|ext| ext.extn_id().0 == oids::crl_number())?;
+        match extension.extn_value() {
+            ExtensionView::Generic(value) => picky_asn1_der::from_bytes(&value.0).ok(),
+            _ => None,
+        }
+    }
+
+    /// Scope of the CRL, `None` when it covers every certificate of its issuer.
+    pub fn issuing_distribution_point(&self) -> Result<Option<IssuingDistributionPoint>, CrlError> {
+        let extension = match self
+            .extensions()
+            .iter()
+            .find(|ext| ext.extn_id().0 == oids::issuing_distribution_point())
+        {
+            Some(extension) => extension,
+            None => return Ok(None),
+        };
+        match extension.extn_value() {
+            ExtensionView::Generic(value) => IssuingDistributionPoint::from_der(&value.0)
+                .map(Some)
+                .ok_or(CrlError::UnsupportedIssuingDistributionPoint),
+            _ => Err(CrlError::UnsupportedIssuingDistributionPoint),
+        }
+    }
+
+    /// CRL number of the base CRL if this is a delta CRL, listing only the changes since its base.
+    pub fn base_crl_number(&self) -> Option<IntegerAsn1> {
+        let extension = self
+            .extensions()
+            .iter()
+            .find(|ext| ext.extn_id().0 == oids::delta_crl_indicator())?;
+        match extension.extn_value() {
+            ExtensionView::Generic(value) => picky_asn1_der::from_bytes(&value.0).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn authority_key_identifier(&self) -> Option<&[u8]> {
+        let extension = self
+            .extensions()
+            .iter()
+            .find(|ext| ext.extn_id().0 == oids::authority_key_identifier())?;
+        match extension.extn_value() {
+            ExtensionView::AuthorityKeyIdentifier(aki) => aki.key_identifier(),
+            _ => None,
+        }
+    }
+
+    pub fn signature_algorithm(&self) -> &AlgorithmIdentifier {
+        &self.0.signature_algorithm
+    }
+
+    /// Verifies the CRL signature using the issuer public key.
+    pub fn verify_signature(&self, public_key: &PublicKey) -> Result<(), CrlError> {
+        let hash_type = SignatureHashType::from_algorithm_identifier(&self.0.signature_algorithm).context(Signature)?;
+        let msg = picky_asn1_der::to_vec(&self.0.tbs_cert_list).context(Asn1Serialization {
+            element: "tbs cert list",
+        })?;
+        hash_type
+            .verify(public_key, &msg, self.0.signature_value.0.payload_view())
+            .context(Signature)
+    }
+
+    /// Verifies that the CRL is issued and signed by `issuer`.
+    pub fn verify_issuer(&self, issuer: &Cert) -> Result<(), CrlError> {
+        let expected = issuer.subject_name();
+        let actual = self.issuer_name();
+        if expected != actual {
+            return Err(CrlError::IssuerNameMismatch {
+                expected: expected.to_string(),
+                actual: actual.to_string(),
+            });
+        }
+
+        self.verify_signature(issuer.public_key())
+    }
+}
+
+/// Entry of a certificate revocation list
+///
+/// https://tools.ietf.org/html/rfc5280#section-5.1.2.6
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct RevokedCertificate {
+    user_certificate: IntegerAsn1,
+    revocation_date: Time,
+    crl_entry_extensions: Option<Extensions>,
+}
+
+impl RevokedCertificate {
+    pub fn new(serial_number: IntegerAsn1, revocation_date: UTCDate) -> Self {
+        Self {
+            user_certificate: serial_number,
+            revocation_date: revocation_date.into(),
+            crl_entry_extensions: None,
+        }
+    }
+
+    /// Adds a reason code entry extension. `CrlReason::Unspecified` is omitted as recommended by RFC5280.
+    pub fn with_reason(self, reason: CrlReason) -> Self {
+        if reason == CrlReason::Unspecified {
+            self
+        } else {
+            self.with_extension(Extension::new_crl_reason(reason))
+        }
+    }
+
+    pub fn with_extension(mut self, extension: Extension) -> Self {
+        let mut extensions = self.crl_entry_extensions.take().unwrap_or_default();
+        extensions.replace(extension);
+        self.crl_entry_extensions = Some(extensions);
+        self
+    }
+
+    pub fn serial_number(&self) -> &IntegerAsn1 {
+        &self.user_certificate
+    }
+
+    pub fn revocation_date(&self) -> UTCDate {
+        self.revocation_date.clone().into()
+    }
+
+    /// Reason code entry extension, `None` if absent or invalid.
+    pub fn reason(&self) -> Option<CrlReason> {
+        let extension = self
+            .extensions()
+            .iter()
+            .find(|ext| ext.extn_id().0 == oids::crl_reason_code())?;
+        match extension.extn_value() {
+            // ENUMERATED
+            ExtensionView::Generic(value) => match value.0.as_slice() {
+                [0x0A, 0x01, code] => CrlReason::from_code(*code),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn extensions(&self) -> &[Extension] {
+        match &self.crl_entry_extensions {
+            Some(extensions) => extensions.as_slice(),
+            None => &[],
+        }
+    }
+}
+
+// Implement Deserialize manually: entry extensions are optional
+impl<'de> de::Deserialize<'de> for RevokedCertificate {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = RevokedCertificate;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("struct RevokedCertificate")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<Self::Value, V::Error>
+            where
+                V: de::SeqAccess<'de>,
+            {
+                let user_certificate = seq_next_element!(seq, RevokedCertificate, "user certificate");
+                let revocation_date = seq_next_element!(seq, RevokedCertificate, "revocation date");
+                let crl_entry_extensions = match seq.next_element::<TagPeeker>()? {
+                    Some(_) => Some(seq_next_element!(seq, RevokedCertificate, "crl entry extensions")),
+                    None => None,
+                };
+
+                Ok(RevokedCertificate {
+                    user_certificate,
+                    revocation_date,
+                    crl_entry_extensions,
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+// Statically checks the field actually exists and returns a &'static str of the field name
+macro_rules! field_str {
+    ($field:ident) => {{
+        ::static_assertions::assert_fields!(CrlBuilderInner: $field);
+        stringify!($field)
+    }};
+}
+
+#[derive(Default, Clone, Debug)]
+struct CrlBuilderInner<'a> {
+    issuer_name: Option<DirectoryName>,
+    issuer_key: Option<&'a PrivateKey>,
+    authority_key_identifier: Option<Vec<u8>>,
+    this_update: Option<UTCDate>,
+    next_update: Option<UTCDate>,
+    signature_hash_type: Option<SignatureHashType>,
+    crl_number: Option<Vec<u8>>,
+    base_crl_number: Option<Vec<u8>>,
+    issuing_distribution_point: Option<IssuingDistributionPoint>,
+    revoked_certificates: Vec<RevokedCertificate>,
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct CrlBuilder<'a> {
+    inner: RefCell<CrlBuilderInner<'a>>,
+}
+
+impl<'a> CrlBuilder<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Required (alternative: `issuer_cert`)
+    #[inline]
+    pub fn issuer(&self, issuer_name: DirectoryName, issuer_key: &'a PrivateKey) -> &Self {
+        let mut inner_mut = self.inner.borrow_mut();
+        inner_mut.issuer_name = Some(issuer_name);
+        inner_mut.issuer_key = Some(issuer_key);
+        drop(inner_mut);
+        self
+    }
+
+    /// Required (alternative: `issuer`)
+    #[inline]
+    pub fn issuer_cert(&self, issuer_cert: &Cert, issuer_key: &'a PrivateKey) -> &Self {
+        let builder = self.issuer(issuer_cert.subject_name(), issuer_key);
+
+        if let Ok(issuer_ski) = issuer_cert.subject_key_identifier() {
+            self.authority_key_identifier(issuer_ski.to_vec())
+        } else {
+            builder
+        }
+    }
+
+    /// Optional (alternative: `issuer_cert`)
+    #[inline]
+    pub fn authority_key_identifier(&self, aki: Vec<u8>) -> &Self {
+        self.inner.borrow_mut().authority_key_identifier = Some(aki);
+        self
+    }
+
+    /// Required
+    #[inline]
+    pub fn this_update(&self, this_update: UTCDate) -> &Self {
+        self.inner.borrow_mut().this_update = Some(this_update);
+        self
+    }
+
+    /// Optional
+    #[inline]
+    pub fn next_update(&self, next_update: UTCDate) -> &Self {
+        self.inner.borrow_mut().next_update = Some(next_update);
+        self
+    }
+
+    /// Optional
+    #[inline]
+    pub fn signature_hash_type(&self, signature_hash_type: SignatureHashType) -> &Self {
+        self.inner.borrow_mut().signature_hash_type = Some(signature_hash_type);
+        self
+    }
+
+    /// Optional
+    #[inline]
+    pub fn crl_number(&self, crl_number: Vec<u8>) -> &Self {
+        self.inner.borrow_mut().crl_number = Some(crl_number);
+        self
+    }
+
+    /// Optional, makes the CRL a delta CRL of the CRL whose number is given
+    #[inline]
+    pub fn base_crl_number(&self, base_crl_number: Vec<u8>) -> &Self {
+        self.inner.borrow_mut().base_crl_number = Some(base_crl_number);
+        self
+    }
+
+    /// Optional
+    #[inline]
+    pub fn issuing_distribution_point(&self, issuing_distribution_point: IssuingDistributionPoint) -> &Self {
+        self.inner.borrow_mut().issuing_distribution_point = Some(issuing_distribution_point);
+        self
+    }
+
+    /// Optional
+    #[inline]
+    pub fn revoked_certificate(&self, revoked_certificate: RevokedCertificate) -> &Self {
+        self.inner.borrow_mut().revoked_certificates.push(revoked_certificate);
+        self
+    }
+
+    /// Optional
+    #[inline]
+    pub fn revoked_certificates(&self, revoked_certificates: Vec<RevokedCertificate>) -> &Self {
+        self.inner
+            .borrow_mut()
+            .revoked_certificates
+            .extend(revoked_certificates);
+        self
+    }
+
+    pub fn build(&self) -> Result<Crl, CrlError> {
+        let mut inner = self.inner.borrow_mut();
+
+        let issuer_name = inner.issuer_name.take().ok_or(CrlError::MissingBuilderArgument {
+            arg: field_str!(issuer_name),
+        })?;
+        let issuer_key = inner.issuer_key.take().ok_or(CrlError::MissingBuilderArgument {
+            arg: field_str!(issuer_key),
+        })?;
+        let this_update = inner.this_update.take().ok_or(CrlError::MissingBuilderArgument {
+            arg: field_str!(this_update),
+        })?;
+        let next_update = inner.next_update.take();
+        let signature_hash_type = inner.signature_hash_type.take().unwrap_or(SignatureHashType::RsaSha256);
+        let aki_opt = inner.authority_key_identifier.take();
+        let crl_number_opt = inner.crl_number.take();
+        let base_crl_number_opt = inner.base_crl_number.take();
+        let idp_opt = inner.issuing_distribution_point.take();
+        let revoked_certificates = std::mem::take(&mut inner.revoked_certificates);
+
+        drop(inner);
+
+        let extensions = {
+            let mut extensions = Vec::new();
+
+            if let Some(aki) = aki_opt {
+                extensions.push(Extension::new_authority_key_identifier(
+                    KeyIdentifier::from(aki),
+                    None,
+                    None,
+                ));
+            }
+
+            if let Some(crl_number) = crl_number_opt {
+                extensions.push(
+                    Extension::new_crl_number(crl_number.into())
+                        .context(Asn1Serialization { element: "crl number" })?,
+                );
+            }
+
+            if let Some(base_crl_number) = base_crl_number_opt {
+                extensions.push(Extension::new_delta_crl_indicator(base_crl_number.into()).context(
+                    Asn1Serialization {
+                        element: "delta crl indicator",
+                    },
+                )?);
+            }
+
+            if let Some(idp) = idp_opt {
+                extensions.push(
+                    Extension::new_issuing_distribution_point(&idp).context(Asn1Serialization {
+                        element: "issuing distribution point",
+                    })?,
+                );
+            }
+
+            extensions
+        };
+
+        let tbs_cert_list = TBSCertList {
+            // v2, required by the extensions
+            version: Some(1),
+            signature: signature_hash_type.into(),
+            issuer: issuer_name.into(),
+            this_update: this_update.into(),
+            next_update: next_update.map(Time::from),
+            revoked_certificates: if revoked_certificates.is_empty() {
+                None
+            } else {
+                Some(Asn1SequenceOf(revoked_certificates))
+            },
+            crl_extensions: if extensions.is_empty() {
+                None
+            } else {
+                Some(ApplicationTag0(Extensions::from(extensions)))
+            },
+        };
+
+        let tbs_der = picky_asn1_der::to_vec(&tbs_cert_list).context(Asn1Serialization {
+            element: "tbs cert list",
+        })?;
+        let signature_value = BitString::with_bytes(signature_hash_type.sign(&tbs_der, issuer_key).context(Signature)?);
+
+        Ok(Crl(CertificateList {
+            tbs_cert_list,
+            signature_algorithm: signature_hash_type.into(),
+            signature_value: signature_value.into(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pem::parse_pem, x509::certificate::CertificateBuilder};
+
+    fn issuer() -> (Cert, PrivateKey) {
+        let pem = parse_pem(crate::test_files::RSA_2048_PK_1).unwrap();
+        let key = PrivateKey::from_pkcs8(pem.data()).unwrap();
+        let cert = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2020, 1, 1).unwrap(), UTCDate::ymd(2030, 1, 1).unwrap())
+            .self_signed(DirectoryName::new_common_name("Picky CRL Issuer"), &key)
+            .ca(true)
+            .build()
+            .unwrap();
+        (cert, key)
+    }
+
+    #[test]
+    fn generate_and_parse() {
+        let (issuer_cert, issuer_key) = issuer();
+
+        let crl = Crl::builder()
+            .issuer_cert(&issuer_cert, &issuer_key)
+            .this_update(UTCDate::ymd(2020, 6, 1).unwrap())
+            .next_update(UTCDate::ymd(2020, 6, 8).unwrap())
+            .crl_number(vec![0x05])
+            .revoked_certificate(
+                RevokedCertificate::new(vec![0x0A, 0x0B].into(), UTCDate::ymd(2020, 5, 20).unwrap())
+                    .with_reason(CrlReason::KeyCompromise),
+            )
+            .revoked_certificate(RevokedCertificate::new(
+                vec![0x0C].into(),
+                UTCDate::ymd(2020, 5, 21).unwrap(),
+            ))
+            .build()
+            .unwrap();
+
+        let pem = crl.to_pem().unwrap();
+        assert_eq!(pem.label(), "X509 CRL");
+        let parsed = Crl::from_pem(&pem).unwrap();
+        assert_eq!(parsed, crl);
+
+        assert_eq!(parsed.issuer_name(), issuer_cert.subject_name());
+        assert_eq!(parsed.this_update(), UTCDate::ymd(2020, 6, 1).unwrap());
+        assert_eq!(parsed.next_update(), Some(UTCDate::ymd(2020, 6, 8).unwrap()));
+        assert_eq!(parsed.crl_number(), Some(vec![0x05].into()));
+        assert_eq!(
+            parsed.authority_key_identifier(),
+            Some(issuer_cert.subject_key_identifier().unwrap())
+        );
+
+        let revoked = parsed.revoked_certificates();
+        assert_eq!(revoked.len(), 2);
+        assert_eq!(revoked[0].reason(), Some(CrlReason::KeyCompromise));
+        assert_eq!(revoked[0].revocation_date(), UTCDate::ymd(2020, 5, 20).unwrap());
+        assert_eq!(revoked[1].reason(), None);
+        assert!(revoked[1].extensions().is_empty());
+        assert!(parsed.find_revoked(&vec![0x0C].into()).is_some());
+        assert!(parsed.find_revoked(&vec![0x0D].into()).is_none());
+
+        parsed.verify_issuer(&issuer_cert).unwrap();
+    }
+
+    #[test]
+    fn empty_crl() {
+        let (issuer_cert, issuer_key) = issuer();
+
+        let crl = Crl::builder()
+            .issuer(issuer_cert.subject_name(), &issuer_key)
+            .this_update(UTCDate::ymd(2020, 6, 1).unwrap())
+            .build()
+            .unwrap();
+
+        let parsed = Crl::from_der(&crl.to_der().unwrap()).unwrap();
+        assert!(parsed.revoked_certificates().is_empty());
+        assert!(parsed.extensions().is_empty());
+        assert_eq!(parsed.next_update(), None);
+        parsed.verify_signature(issuer_cert.public_key()).unwrap();
+
+        let err = Crl::builder()
+            .issuer(issuer_cert.subject_name(), &issuer_key)
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "missing required builder argument `this_update`");
+    }
+
+    #[test]
+    fn issuer_mismatch() {
+        let (issuer_cert, issuer_key) = issuer();
+        let crl = Crl::builder()
+            .issuer(DirectoryName::new_common_name("Someone Else"), &issuer_key)
+            .this_update(UTCDate::ymd(2020, 6, 1).unwrap())
+            .build()
+            .unwrap();
+
+        match crl.verify_issuer(&issuer_cert) {
+            Err(CrlError::IssuerNameMismatch { .. }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
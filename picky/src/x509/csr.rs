@@ -1,11 +1,15 @@
 use crate::{
     key::{PrivateKey, PublicKey},
+    oids,
     pem::Pem,
     signature::{SignatureError, SignatureHashType},
     x509::{
-        name::DirectoryName,
+        extension::{Extension, ExtensionView},
+        name::{DirectoryName, GeneralNames},
         private::{certification_request::CertificationRequestInfo, CertificationRequest},
+        Extensions,
     },
+    AlgorithmIdentifier,
 };
 use picky_asn1::bit_string::BitString;
 use picky_asn1_der::Asn1DerError;
@@ -42,6 +46,9 @@ const CSR_PEM_LABEL: &str = "CERTIFICATE REQUEST";
 #[derive(Clone, Debug, PartialEq)]
 pub struct Csr(CertificationRequest);
 
+static_assertions::assert_impl_all!(Csr: Send, Sync);
+static_assertions::assert_impl_all!(CsrError: Send, Sync);
+
 impl From<CertificationRequest> for Csr {
     fn from(certification_request: CertificationRequest) -> Self {
         Self(certification_request)
@@ -82,6 +89,34 @@ impl Csr {
         signature_hash_type: SignatureHashType,
     ) -> Result<Self, CsrError> {
         let info = CertificationRequestInfo::new(subject.into(), private_key.to_public_key().into());
+        Self::sign(info, private_key, signature_hash_type)
+    }
+
+    /// Generates a CSR requesting the given extensions (PKCS#9 extensionRequest attribute).
+    ///
+    /// Proprietary extensions can be requested using `Extension::new_generic`.
+    pub fn generate_with_extensions(
+        subject: DirectoryName,
+        private_key: &PrivateKey,
+        signature_hash_type: SignatureHashType,
+        extensions: Extensions,
+    ) -> Result<Self, CsrError> {
+        let info = CertificationRequestInfo::new_with_extensions(
+            subject.into(),
+            private_key.to_public_key().into(),
+            &extensions,
+        )
+        .context(Asn1Serialization {
+            element: "extension request",
+        })?;
+        Self::sign(info, private_key, signature_hash_type)
+    }
+
+    fn sign(
+        info: CertificationRequestInfo,
+        private_key: &PrivateKey,
+        signature_hash_type: SignatureHashType,
+    ) -> Result<Self, CsrError> {
         let info_der = picky_asn1_der::to_vec(&info).context(Asn1Serialization {
             element: "certification request info",
         })?;
@@ -102,6 +137,34 @@ impl Csr {
         (&self.0.certification_request_info.subject_public_key_info).into()
     }
 
+    pub fn signature_algorithm(&self) -> &AlgorithmIdentifier {
+        &self.0.signature_algorithm
+    }
+
+    /// Extensions requested through the PKCS#9 extensionRequest attribute (empty if there is none).
+    pub fn extensions(&self) -> Result<Extensions, CsrError> {
+        let extensions = self
+            .0
+            .certification_request_info
+            .extensions()
+            .context(Asn1Deserialization {
+                element: "extension request",
+            })?;
+        Ok(extensions.unwrap_or_default())
+    }
+
+    /// Subject alternative names requested through the extensionRequest attribute, if any.
+    pub fn subject_alt_names(&self) -> Result<Option<GeneralNames>, CsrError> {
+        let extensions = self.extensions()?;
+        match extensions
+            .get(&oids::subject_alternative_name())
+            .map(Extension::extn_value)
+        {
+            Some(ExtensionView::SubjectAltName(names)) => Ok(Some(names)),
+            _ => Ok(None),
+        }
+    }
+
     pub fn into_subject_infos(self) -> (DirectoryName, PublicKey) {
         (
             self.0.certification_request_info.subject.into(),
@@ -18,10 +18,12 @@ impl UTCDate {
         Some(Self(GeneralizedTime::new(year, month, day, 0, 0, 0)?))
     }
 
+    /// Current date according to the active `clock::Clock`.
     #[cfg(feature = "chrono_conversion")]
     #[inline]
     pub fn now() -> Self {
-        Self(chrono::offset::Utc::now().into())
+        use chrono::TimeZone;
+        Self(Utc.timestamp(crate::clock::now(), 0).into())
     }
 
     #[inline]
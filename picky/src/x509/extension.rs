@@ -6,24 +6,140 @@ use picky_asn1::{
 
 use crate::{
     oids,
-    x509::private::name::{GeneralName, GeneralNames},
+    x509::{
+        date::UTCDate,
+        hostname::{dns_name_in_subtree, normalize_dns_name},
+        private::name::{from_implicit_sequence, implicit_sequence, GeneralName, GeneralNames},
+    },
 };
-use picky_asn1::wrapper::{
-    ApplicationTag1, ContextTag0, ContextTag2, Implicit, IntegerAsn1, ObjectIdentifierAsn1, OctetStringAsn1,
-    OctetStringAsn1Container,
+use once_cell::sync::OnceCell;
+use picky_asn1::{
+    restricted_string::{CharSetError, IA5String},
+    tag::{Tag, TagPeeker},
+    wrapper::{
+        ApplicationTag0, ApplicationTag1, ContextTag0, ContextTag1, ContextTag2, ContextTag4, GeneralizedTimeAsn1,
+        IA5StringAsn1, Implicit, IntegerAsn1, ObjectIdentifierAsn1, OctetStringAsn1, OctetStringAsn1Container,
+    },
 };
+use picky_asn1_der::{Asn1DerError, Asn1RawDer};
 use serde::{de, ser, Deserialize, Serialize};
 use std::fmt;
 
 /// https://tools.ietf.org/html/rfc5280#section-4.1.2.9
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
-pub struct Extensions(pub Vec<Extension>);
+///
+/// Extensions keep their insertion order. `add` and `replace` never introduce a second extension
+/// with the same OID, while parsed or converted lists are kept as-is so that they round-trip.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct Extensions(Vec<Extension>);
+
+impl Extensions {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-#[derive(Debug, PartialEq, Clone)]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> Iter<Extension> {
+        self.0.iter()
+    }
+
+    pub fn as_slice(&self) -> &[Extension] {
+        self.0.as_slice()
+    }
+
+    pub fn into_vec(self) -> Vec<Extension> {
+        self.0
+    }
+
+    pub fn get(&self, oid: &oid::ObjectIdentifier) -> Option<&Extension> {
+        self.0.iter().find(|ext| ext.extn_id() == oid)
+    }
+
+    pub fn contains(&self, oid: &oid::ObjectIdentifier) -> bool {
+        self.get(oid).is_some()
+    }
+
+    /// Appends the extension unless one with the same OID is already present.
+    ///
+    /// Returns `false` if the extension was not added.
+    pub fn add(&mut self, extension: Extension) -> bool {
+        if self.contains(&extension.extn_id().0) {
+            false
+        } else {
+            self.0.push(extension);
+            true
+        }
+    }
+
+    /// Replaces the extension with the same OID in place, or appends it if there is none.
+    ///
+    /// Returns the replaced extension.
+    pub fn replace(&mut self, extension: Extension) -> Option<Extension> {
+        match self.0.iter().position(|ext| ext.extn_id() == extension.extn_id()) {
+            Some(idx) => Some(std::mem::replace(&mut self.0[idx], extension)),
+            None => {
+                self.0.push(extension);
+                None
+            }
+        }
+    }
+
+    /// Removes the extension with the given OID (and any duplicate of it).
+    ///
+    /// Returns the first removed extension.
+    pub fn remove_by_oid(&mut self, oid: &oid::ObjectIdentifier) -> Option<Extension> {
+        let idx = self.0.iter().position(|ext| ext.extn_id() == oid)?;
+        let removed = self.0.remove(idx);
+        self.0.retain(|ext| ext.extn_id() != oid);
+        Some(removed)
+    }
+
+    /// Removes duplicated extensions, keeping the first occurrence of each OID.
+    pub fn dedup(&mut self) {
+        let mut seen = Vec::with_capacity(self.0.len());
+        self.0.retain(|ext| {
+            if seen.contains(&ext.extn_id().0) {
+                false
+            } else {
+                seen.push(ext.extn_id().0.clone());
+                true
+            }
+        });
+    }
+}
+
+impl From<Vec<Extension>> for Extensions {
+    fn from(extensions: Vec<Extension>) -> Self {
+        Self(extensions)
+    }
+}
+
+impl<'a> IntoIterator for &'a Extensions {
+    type Item = &'a Extension;
+    type IntoIter = Iter<'a, Extension>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+/// Extension values of parsed certificates are kept encoded and only decoded when first accessed,
+/// so that reading the subject, issuer or serial number of many certificates doesn't pay for
+/// decoding every extension. Consequently, a malformed value of a known extension doesn't
+/// prevent parsing: it's reported as `ExtensionView::Generic` instead.
+#[derive(Debug, Clone)]
 pub struct Extension {
     extn_id: ObjectIdentifierAsn1,
     critical: Implicit<bool>,
-    extn_value: ExtensionValue,
+    /// `critical` was explicitly encoded with its default value (not DER, but found in the wild)
+    explicit_default_critical: bool,
+    extn_value: LazyExtensionValue,
 }
 
 impl Extension {
@@ -35,22 +151,36 @@ impl Extension {
         self.critical.0
     }
 
+    /// Whether picky knows this type of extension, regardless of whether its value is well-formed.
+    pub fn is_known(&self) -> bool {
+        KNOWN_EXTENSIONS.contains(&Into::<String>::into(&self.extn_id.0).as_str())
+    }
+
     pub fn extn_value(&self) -> ExtensionView<'_> {
-        ExtensionView::from(&self.extn_value)
+        ExtensionView::from(self.decoded_value())
+    }
+
+    fn decoded_value(&self) -> &ExtensionValue {
+        let LazyExtensionValue { encoded, decoded } = &self.extn_value;
+        decoded.get_or_init(|| {
+            let encoded = encoded.as_ref().expect("either encoded or decoded value is set");
+            ExtensionValue::decode(&self.extn_id, encoded)
+        })
     }
 
     pub fn into_critical(mut self) -> Self {
-        self.critical = true.into();
+        self.set_critical(true);
         self
     }
 
     pub fn into_non_critical(mut self) -> Self {
-        self.critical = false.into();
+        self.set_critical(false);
         self
     }
 
     pub fn set_critical(&mut self, critical: bool) {
         self.critical = critical.into();
+        self.explicit_default_critical = false;
     }
 
     /// When present, conforming CAs SHOULD mark this extension as critical
@@ -60,7 +190,8 @@ impl Extension {
         Self {
             extn_id: oids::key_usage().into(),
             critical: true.into(),
-            extn_value: ExtensionValue::KeyUsage(key_usage.into()),
+            explicit_default_critical: false,
+            extn_value: ExtensionValue::KeyUsage(key_usage.into()).into(),
         }
     }
 
@@ -71,7 +202,8 @@ impl Extension {
         Self {
             extn_id: oids::subject_key_identifier().into(),
             critical: false.into(),
-            extn_value: ExtensionValue::SubjectKeyIdentifier(OctetStringAsn1(ski.into()).into()),
+            explicit_default_critical: false,
+            extn_value: ExtensionValue::SubjectKeyIdentifier(OctetStringAsn1(ski.into()).into()).into(),
         }
     }
 
@@ -91,6 +223,7 @@ impl Extension {
         Self {
             extn_id: oids::authority_key_identifier().into(),
             critical: false.into(),
+            explicit_default_critical: false,
             extn_value: ExtensionValue::AuthorityKeyIdentifier(
                 AuthorityKeyIdentifier {
                     key_identifier: key_identifier.into().map(ContextTag0),
@@ -98,7 +231,8 @@ impl Extension {
                     authority_cert_serial_number: authority_cert_serial_number.into().map(ContextTag2),
                 }
                 .into(),
-            ),
+            )
+            .into(),
         }
     }
 
@@ -114,13 +248,15 @@ impl Extension {
         Self {
             extn_id: oids::basic_constraints().into(),
             critical: true.into(),
+            explicit_default_critical: false,
             extn_value: ExtensionValue::BasicConstraints(
                 BasicConstraints {
                     ca: Implicit(ca.into()),
                     path_len_constraint: Implicit(path_len_constraints.into()),
                 }
                 .into(),
-            ),
+            )
+            .into(),
         }
     }
 
@@ -137,7 +273,8 @@ impl Extension {
         Self {
             extn_id: oids::extended_key_usage().into(),
             critical: Implicit(!eku.contains(oids::kp_any_extended_key_usage())),
-            extn_value: ExtensionValue::ExtendedKeyUsage(eku.into()),
+            explicit_default_critical: false,
+            extn_value: ExtensionValue::ExtendedKeyUsage(eku.into()).into(),
         }
     }
 
@@ -153,7 +290,211 @@ impl Extension {
         Self {
             extn_id: oids::subject_alternative_name().into(),
             critical: true.into(),
-            extn_value: ExtensionValue::SubjectAltName(name.into()),
+            explicit_default_critical: false,
+            extn_value: ExtensionValue::SubjectAltName(name.into()).into(),
+        }
+    }
+
+    /// Subject alternative names, e.g. to request them in a CSR (see `Csr::generate_with_extensions`).
+    ///
+    /// Default is non-critical.
+    pub fn new_subject_alt_names(names: super::name::GeneralNames) -> Self {
+        Self::new_subject_alt_name(names).into_non_critical()
+    }
+
+    /// Conforming CAs MUST mark this extension as non-critical.
+    ///
+    /// Default is non-critical.
+    pub(crate) fn new_authority_info_access(aia: &AuthorityInfoAccess) -> Result<Self, Asn1DerError> {
+        let ocsp = aia.ocsp.iter().map(|uri| (oids::ad_ocsp(), uri));
+        let ca_issuers = aia.ca_issuers.iter().map(|uri| (oids::ad_ca_issuers(), uri));
+        let access_descriptions = ocsp
+            .chain(ca_issuers)
+            .map(|(access_method, uri)| AccessDescription {
+                access_method: access_method.into(),
+                access_location: GeneralName::URI(uri.clone().into()),
+            })
+            .collect::<Vec<AccessDescription>>();
+
+        Ok(Self {
+            extn_id: oids::authority_info_access().into(),
+            critical: false.into(),
+            explicit_default_critical: false,
+            extn_value: ExtensionValue::Generic(OctetStringAsn1(picky_asn1_der::to_vec(&Asn1SequenceOf(
+                access_descriptions,
+            ))?))
+            .into(),
+        })
+    }
+
+    /// The extension SHOULD be non-critical.
+    ///
+    /// Default is non-critical.
+    pub fn new_crl_distribution_points(crl_dp: CrlDistributionPoints) -> Self {
+        Self {
+            extn_id: oids::crl_distribution_points().into(),
+            critical: false.into(),
+            explicit_default_critical: false,
+            extn_value: ExtensionValue::CrlDistributionPoints(crl_dp.into()).into(),
+        }
+    }
+
+    /// Also known as Delta CRL Distribution Point. Conforming CAs MUST mark this extension as non-critical.
+    ///
+    /// Default is non-critical.
+    pub fn new_freshest_crl(freshest_crl: CrlDistributionPoints) -> Self {
+        Self {
+            extn_id: oids::freshest_crl().into(),
+            critical: false.into(),
+            explicit_default_critical: false,
+            extn_value: ExtensionValue::FreshestCrl(freshest_crl.into()).into(),
+        }
+    }
+
+    /// CRL extension. Conforming CRL issuers MUST mark this extension as critical.
+    ///
+    /// Default is critical.
+    pub fn new_issuing_distribution_point(idp: &IssuingDistributionPoint) -> Result<Self, Asn1DerError> {
+        let flag = |set: bool| if set { Some(true) } else { None };
+        let encoded = IssuingDistributionPointRepr {
+            distribution_point: idp
+                .uri
+                .as_ref()
+                .map(|uri| ApplicationTag0(ApplicationTag0(GeneralName::URI(uri.clone().into())))),
+            only_contains_user_certs: flag(idp.only_contains_user_certs).map(ContextTag1),
+            only_contains_ca_certs: flag(idp.only_contains_ca_certs).map(ContextTag2),
+            indirect_crl: flag(idp.indirect_crl).map(ContextTag4),
+        };
+
+        Ok(Self {
+            extn_id: oids::issuing_distribution_point().into(),
+            critical: true.into(),
+            explicit_default_critical: false,
+            extn_value: ExtensionValue::Generic(OctetStringAsn1(picky_asn1_der::to_vec(&encoded)?)).into(),
+        })
+    }
+
+    /// CRL extension.
+    ///
+    /// Default is non-critical.
+    pub fn new_crl_number(crl_number: IntegerAsn1) -> Result<Self, Asn1DerError> {
+        Ok(Self {
+            extn_id: oids::crl_number().into(),
+            critical: false.into(),
+            explicit_default_critical: false,
+            extn_value: ExtensionValue::Generic(OctetStringAsn1(picky_asn1_der::to_vec(&crl_number)?)).into(),
+        })
+    }
+
+    /// CRL extension identifying a delta CRL by the CRL number of its base CRL. Conforming CRL
+    /// issuers MUST mark this extension as critical.
+    ///
+    /// Default is critical.
+    pub fn new_delta_crl_indicator(base_crl_number: IntegerAsn1) -> Result<Self, Asn1DerError> {
+        Ok(Self {
+            extn_id: oids::delta_crl_indicator().into(),
+            critical: true.into(),
+            explicit_default_critical: false,
+            extn_value: ExtensionValue::Generic(OctetStringAsn1(picky_asn1_der::to_vec(&base_crl_number)?)).into(),
+        })
+    }
+
+    /// CRL entry extension. The reason code `unspecified` SHOULD NOT be used: omit the extension instead.
+    ///
+    /// Default is non-critical.
+    pub fn new_crl_reason(reason: CrlReason) -> Self {
+        // ENUMERATED
+        let encoded = vec![0x0A, 0x01, reason as u8];
+        Self {
+            extn_id: oids::crl_reason_code().into(),
+            critical: false.into(),
+            explicit_default_critical: false,
+            extn_value: ExtensionValue::Generic(OctetStringAsn1(encoded)).into(),
+        }
+    }
+
+    /// CRL entry extension.
+    ///
+    /// Default is non-critical.
+    pub fn new_invalidity_date(date: UTCDate) -> Result<Self, Asn1DerError> {
+        let date = GeneralizedTimeAsn1(date.into());
+        Ok(Self {
+            extn_id: oids::invalidity_date().into(),
+            critical: false.into(),
+            explicit_default_critical: false,
+            extn_value: ExtensionValue::Generic(OctetStringAsn1(picky_asn1_der::to_vec(&date)?)).into(),
+        })
+    }
+
+    /// CRL entry extension used by indirect CRLs. CRL issuers MUST mark this extension as critical.
+    ///
+    /// Default is critical.
+    pub fn new_certificate_issuer(issuer: super::name::GeneralNames) -> Result<Self, Asn1DerError> {
+        let issuer: GeneralNames = issuer.into();
+        Ok(Self {
+            extn_id: oids::certificate_issuer().into(),
+            critical: true.into(),
+            explicit_default_critical: false,
+            extn_value: ExtensionValue::Generic(OctetStringAsn1(picky_asn1_der::to_vec(&issuer)?)).into(),
+        })
+    }
+
+    /// OCSP request or response extension binding a response to its request (RFC8954).
+    ///
+    /// Default is non-critical.
+    pub fn new_ocsp_nonce(nonce: Vec<u8>) -> Result<Self, Asn1DerError> {
+        Ok(Self {
+            extn_id: oids::ocsp_nonce().into(),
+            critical: false.into(),
+            explicit_default_critical: false,
+            extn_value: ExtensionValue::Generic(OctetStringAsn1(picky_asn1_der::to_vec(&OctetStringAsn1(nonce))?))
+                .into(),
+        })
+    }
+
+    /// Extension of any type, whose value is given DER-encoded (e.g. a private extension).
+    pub fn new_generic<V: Into<Vec<u8>>>(extn_id: oid::ObjectIdentifier, critical: bool, extn_value: V) -> Self {
+        Self {
+            extn_id: extn_id.into(),
+            critical: critical.into(),
+            explicit_default_critical: false,
+            extn_value: ExtensionValue::Generic(OctetStringAsn1(extn_value.into())).into(),
+        }
+    }
+
+    /// Conforming CAs MUST mark this extension as critical.
+    ///
+    /// Default is critical.
+    pub fn new_name_constraints(name_constraints: NameConstraints) -> Self {
+        Self {
+            extn_id: oids::name_constraints().into(),
+            critical: true.into(),
+            explicit_default_critical: false,
+            extn_value: ExtensionValue::NameConstraints(name_constraints.into()).into(),
+        }
+    }
+
+    /// This extension MAY be critical or non-critical.
+    ///
+    /// Default is non-critical.
+    pub fn new_certificate_policies(certificate_policies: CertificatePolicies) -> Self {
+        Self {
+            extn_id: oids::certificate_policies().into(),
+            critical: false.into(),
+            explicit_default_critical: false,
+            extn_value: ExtensionValue::CertificatePolicies(certificate_policies.into()).into(),
+        }
+    }
+
+    /// Conforming CAs SHOULD mark this extension as critical.
+    ///
+    /// Default is critical.
+    pub fn new_policy_mappings(policy_mappings: PolicyMappings) -> Self {
+        Self {
+            extn_id: oids::policy_mappings().into(),
+            critical: true.into(),
+            explicit_default_critical: false,
+            extn_value: ExtensionValue::PolicyMappings(policy_mappings.into()).into(),
         }
     }
 
@@ -165,7 +506,21 @@ impl Extension {
         Self {
             extn_id: oids::issuer_alternative_name().into(),
             critical: false.into(),
-            extn_value: ExtensionValue::IssuerAltName(name.into()),
+            explicit_default_critical: false,
+            extn_value: ExtensionValue::IssuerAltName(name.into()).into(),
+        }
+    }
+}
+
+impl PartialEq for Extension {
+    fn eq(&self, other: &Self) -> bool {
+        if self.extn_id != other.extn_id || self.critical != other.critical {
+            return false;
+        }
+
+        match (&self.extn_value.encoded, &other.extn_value.encoded) {
+            (Some(encoded), Some(other_encoded)) => encoded == other_encoded,
+            _ => self.decoded_value() == other.decoded_value(),
         }
     }
 }
@@ -179,7 +534,7 @@ impl ser::Serialize for Extension {
         let mut seq = serializer.serialize_seq(Some(3))?;
         seq.serialize_element(&self.extn_id)?;
 
-        if self.critical.0 != bool::default() {
+        if self.critical.0 != bool::default() || self.explicit_default_critical {
             seq.serialize_element(&self.critical)?;
         }
 
@@ -208,36 +563,19 @@ impl<'de> de::Deserialize<'de> for Extension {
                 A: de::SeqAccess<'de>,
             {
                 let id: ObjectIdentifierAsn1 = seq_next_element!(seq, Extension, "id");
+                let tag_peeker: TagPeeker = seq_next_element!(seq, Extension, "critical or value tag");
+                let explicit_critical = tag_peeker.next_tag == Tag::BOOLEAN;
                 let critical: Implicit<bool> = seq_next_element!(seq, Extension, "critical");
-                let value = match Into::<String>::into(&id.0).as_str() {
-                    oids::AUTHORITY_KEY_IDENTIFIER => ExtensionValue::AuthorityKeyIdentifier(seq_next_element!(
-                        seq,
-                        Extension,
-                        "AuthorityKeyIdentifier"
-                    )),
-                    oids::SUBJECT_KEY_IDENTIFIER => {
-                        ExtensionValue::SubjectKeyIdentifier(seq_next_element!(seq, Extension, "SubjectKeyIdentifier"))
-                    }
-                    oids::KEY_USAGE => ExtensionValue::KeyUsage(seq_next_element!(seq, Extension, "KeyUsage")),
-                    oids::SUBJECT_ALTERNATIVE_NAME => {
-                        ExtensionValue::SubjectAltName(seq_next_element!(seq, Extension, "SubjectAltName"))
-                    }
-                    oids::ISSUER_ALTERNATIVE_NAME => {
-                        ExtensionValue::IssuerAltName(seq_next_element!(seq, Extension, "IssuerAltName"))
-                    }
-                    oids::BASIC_CONSTRAINTS => {
-                        ExtensionValue::BasicConstraints(seq_next_element!(seq, Extension, "BasicConstraints"))
-                    }
-                    oids::EXTENDED_KEY_USAGE => {
-                        ExtensionValue::ExtendedKeyUsage(seq_next_element!(seq, Extension, "ExtendedKeyUsage"))
-                    }
-                    _ => ExtensionValue::Generic(seq_next_element!(seq, Extension, "Generic")),
-                };
+                let value: OctetStringAsn1 = seq_next_element!(seq, Extension, "value");
 
                 Ok(Extension {
                     extn_id: id,
+                    explicit_default_critical: explicit_critical && !critical.0,
                     critical,
-                    extn_value: value,
+                    extn_value: LazyExtensionValue {
+                        encoded: Some(value),
+                        decoded: OnceCell::new(),
+                    },
                 })
             }
         }
@@ -252,10 +590,15 @@ pub enum ExtensionView<'a> {
     AuthorityKeyIdentifier(&'a AuthorityKeyIdentifier),
     SubjectKeyIdentifier(&'a SubjectKeyIdentifier),
     KeyUsage(&'a KeyUsage),
+    CertificatePolicies(&'a CertificatePolicies),
+    PolicyMappings(&'a PolicyMappings),
     SubjectAltName(super::name::GeneralNames),
     IssuerAltName(super::name::GeneralNames),
     BasicConstraints(&'a BasicConstraints),
+    NameConstraints(&'a NameConstraints),
     ExtendedKeyUsage(&'a ExtendedKeyUsage),
+    CrlDistributionPoints(&'a CrlDistributionPoints),
+    FreshestCrl(&'a CrlDistributionPoints),
     Generic(&'a OctetStringAsn1),
 }
 
@@ -265,35 +608,118 @@ impl<'a> From<&'a ExtensionValue> for ExtensionView<'a> {
             ExtensionValue::AuthorityKeyIdentifier(OctetStringAsn1Container(val)) => Self::AuthorityKeyIdentifier(val),
             ExtensionValue::SubjectKeyIdentifier(OctetStringAsn1Container(val)) => Self::SubjectKeyIdentifier(val),
             ExtensionValue::KeyUsage(OctetStringAsn1Container(val)) => Self::KeyUsage(val),
+            ExtensionValue::CertificatePolicies(OctetStringAsn1Container(val)) => Self::CertificatePolicies(val),
+            ExtensionValue::PolicyMappings(OctetStringAsn1Container(val)) => Self::PolicyMappings(val),
             ExtensionValue::SubjectAltName(OctetStringAsn1Container(val)) => Self::SubjectAltName(val.clone().into()),
             ExtensionValue::IssuerAltName(OctetStringAsn1Container(val)) => Self::IssuerAltName(val.clone().into()),
             ExtensionValue::BasicConstraints(OctetStringAsn1Container(val)) => Self::BasicConstraints(val),
+            ExtensionValue::NameConstraints(OctetStringAsn1Container(val)) => Self::NameConstraints(val),
             ExtensionValue::ExtendedKeyUsage(OctetStringAsn1Container(val)) => Self::ExtendedKeyUsage(val),
+            ExtensionValue::CrlDistributionPoints(OctetStringAsn1Container(val)) => Self::CrlDistributionPoints(val),
+            ExtensionValue::FreshestCrl(OctetStringAsn1Container(val)) => Self::FreshestCrl(val),
             ExtensionValue::Generic(val) => Self::Generic(val),
         }
     }
 }
 
+/// Value of an extension, either as found in the encoded extension or as built.
+#[derive(Debug, Clone)]
+struct LazyExtensionValue {
+    encoded: Option<OctetStringAsn1>,
+    decoded: OnceCell<ExtensionValue>,
+}
+
+impl From<ExtensionValue> for LazyExtensionValue {
+    fn from(value: ExtensionValue) -> Self {
+        Self {
+            encoded: None,
+            decoded: OnceCell::from(value),
+        }
+    }
+}
+
+impl ser::Serialize for LazyExtensionValue {
+    fn serialize<S>(&self, serializer: S) -> Result<<S as ser::Serializer>::Ok, <S as ser::Serializer>::Error>
+    where
+        S: ser::Serializer,
+    {
+        // parsed values are written back untouched
+        match (&self.encoded, self.decoded.get()) {
+            (Some(encoded), _) => encoded.serialize(serializer),
+            (None, Some(decoded)) => decoded.serialize(serializer),
+            (None, None) => unreachable!("either encoded or decoded value is set"),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 enum ExtensionValue {
     AuthorityKeyIdentifier(OctetStringAsn1Container<AuthorityKeyIdentifier>),
     SubjectKeyIdentifier(OctetStringAsn1Container<SubjectKeyIdentifier>),
     KeyUsage(OctetStringAsn1Container<KeyUsage>),
-    //CertificatePolicies(OctetStringAsn1Container<Asn1SequenceOf<PolicyInformation>>),
-    //PolicyMappings(OctetStringAsn1Container<Asn1SequenceOfPolicyMapping>>),
+    CertificatePolicies(OctetStringAsn1Container<CertificatePolicies>),
+    PolicyMappings(OctetStringAsn1Container<PolicyMappings>),
     SubjectAltName(OctetStringAsn1Container<SubjectAltName>),
     IssuerAltName(OctetStringAsn1Container<IssuerAltName>),
     //SubjectDirectoryAttributes(OctetStringAsn1Container<Asn1SequenceOf<Attribute>>),
     BasicConstraints(OctetStringAsn1Container<BasicConstraints>),
-    //NameConstraints(…),
+    NameConstraints(OctetStringAsn1Container<NameConstraints>),
     //PolicyConstraints(…),
     ExtendedKeyUsage(OctetStringAsn1Container<ExtendedKeyUsage>),
-    //CRLDistributionPoints(…),
+    CrlDistributionPoints(OctetStringAsn1Container<CrlDistributionPoints>),
     //InhibitAnyPolicy(…),
-    //FreshestCRL(…),
+    FreshestCrl(OctetStringAsn1Container<CrlDistributionPoints>),
     Generic(OctetStringAsn1),
 }
 
+/// Extensions decoded by `ExtensionValue::decode`, any other one is kept generic.
+const KNOWN_EXTENSIONS: [&str; 12] = [
+    oids::AUTHORITY_KEY_IDENTIFIER,
+    oids::SUBJECT_KEY_IDENTIFIER,
+    oids::KEY_USAGE,
+    oids::CERTIFICATE_POLICIES,
+    oids::POLICY_MAPPINGS,
+    oids::SUBJECT_ALTERNATIVE_NAME,
+    oids::ISSUER_ALTERNATIVE_NAME,
+    oids::BASIC_CONSTRAINTS,
+    oids::NAME_CONSTRAINTS,
+    oids::EXTENDED_KEY_USAGE,
+    oids::CRL_DISTRIBUTION_POINTS,
+    oids::FRESHEST_CRL,
+];
+
+impl ExtensionValue {
+    /// Decodes the value of a known extension, falling back to `Generic` for unknown or malformed ones.
+    fn decode(id: &ObjectIdentifierAsn1, encoded: &OctetStringAsn1) -> Self {
+        fn decode_as<T>(encoded: &OctetStringAsn1) -> Option<OctetStringAsn1Container<T>>
+        where
+            T: de::DeserializeOwned,
+        {
+            picky_asn1_der::from_bytes(&encoded.0)
+                .ok()
+                .map(OctetStringAsn1Container)
+        }
+
+        let decoded = match Into::<String>::into(&id.0).as_str() {
+            oids::AUTHORITY_KEY_IDENTIFIER => decode_as(encoded).map(ExtensionValue::AuthorityKeyIdentifier),
+            oids::SUBJECT_KEY_IDENTIFIER => decode_as(encoded).map(ExtensionValue::SubjectKeyIdentifier),
+            oids::KEY_USAGE => decode_as(encoded).map(ExtensionValue::KeyUsage),
+            oids::CERTIFICATE_POLICIES => decode_as(encoded).map(ExtensionValue::CertificatePolicies),
+            oids::POLICY_MAPPINGS => decode_as(encoded).map(ExtensionValue::PolicyMappings),
+            oids::SUBJECT_ALTERNATIVE_NAME => decode_as(encoded).map(ExtensionValue::SubjectAltName),
+            oids::ISSUER_ALTERNATIVE_NAME => decode_as(encoded).map(ExtensionValue::IssuerAltName),
+            oids::BASIC_CONSTRAINTS => decode_as(encoded).map(ExtensionValue::BasicConstraints),
+            oids::NAME_CONSTRAINTS => decode_as(encoded).map(ExtensionValue::NameConstraints),
+            oids::EXTENDED_KEY_USAGE => decode_as(encoded).map(ExtensionValue::ExtendedKeyUsage),
+            oids::CRL_DISTRIBUTION_POINTS => decode_as(encoded).map(ExtensionValue::CrlDistributionPoints),
+            oids::FRESHEST_CRL => decode_as(encoded).map(ExtensionValue::FreshestCrl),
+            _ => None,
+        };
+
+        decoded.unwrap_or_else(|| ExtensionValue::Generic(encoded.clone()))
+    }
+}
+
 impl ser::Serialize for ExtensionValue {
     fn serialize<S>(&self, serializer: S) -> Result<<S as ser::Serializer>::Ok, <S as ser::Serializer>::Error>
     where
@@ -303,10 +729,15 @@ impl ser::Serialize for ExtensionValue {
             ExtensionValue::AuthorityKeyIdentifier(aki) => aki.serialize(serializer),
             ExtensionValue::SubjectKeyIdentifier(ski) => ski.serialize(serializer),
             ExtensionValue::KeyUsage(key_usage) => key_usage.serialize(serializer),
+            ExtensionValue::CertificatePolicies(policies) => policies.serialize(serializer),
+            ExtensionValue::PolicyMappings(mappings) => mappings.serialize(serializer),
             ExtensionValue::SubjectAltName(san) => san.serialize(serializer),
             ExtensionValue::IssuerAltName(ian) => ian.serialize(serializer),
             ExtensionValue::BasicConstraints(basic_constraints) => basic_constraints.serialize(serializer),
+            ExtensionValue::NameConstraints(name_constraints) => name_constraints.serialize(serializer),
             ExtensionValue::ExtendedKeyUsage(eku) => eku.serialize(serializer),
+            ExtensionValue::CrlDistributionPoints(crl_dp) => crl_dp.serialize(serializer),
+            ExtensionValue::FreshestCrl(freshest_crl) => freshest_crl.serialize(serializer),
             ExtensionValue::Generic(octet_string) => octet_string.serialize(serializer),
         }
     }
@@ -402,6 +833,10 @@ impl KeyUsage {
         Self(BitString::with_len(num_bits).into())
     }
 
+    pub fn builder() -> KeyUsageBuilder {
+        KeyUsageBuilder::default()
+    }
+
     pub fn as_bytes(&self) -> &[u8] {
         self.0.payload_view()
     }
@@ -419,30 +854,141 @@ impl KeyUsage {
     }
 }
 
-/// https://tools.ietf.org/html/rfc5280#section-4.2.1.6
-type SubjectAltName = GeneralNames;
+macro_rules! key_usage_builder_flags {
+    ( $( $flag:ident , $setter:ident ; )+ ) => {
+        $(
+            pub fn $flag(mut self) -> Self {
+                self.0.$setter(true);
+                self
+            }
+        )+
+    };
+}
 
-/// https://tools.ietf.org/html/rfc5280#section-4.2.1.7
-type IssuerAltName = GeneralNames;
+/// Builds a `KeyUsage` without dealing with bit indices.
+///
+/// ```
+/// use picky::x509::extension::KeyUsage;
+///
+/// let key_usage = KeyUsage::builder().digital_signature().key_encipherment().build();
+/// assert!(key_usage.digital_signature());
+/// assert!(!key_usage.key_cert_sign());
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct KeyUsageBuilder(KeyUsage);
+
+impl KeyUsageBuilder {
+    key_usage_builder_flags! {
+        digital_signature, set_digital_signature;
+        content_commitment, set_content_commitment;
+        key_encipherment, set_key_encipherment;
+        data_encipherment, set_data_encipherment;
+        key_agreement, set_key_agreement;
+        key_cert_sign, set_key_cert_sign;
+        crl_sign, set_crl_sign;
+        encipher_only, set_encipher_only;
+        decipher_only, set_decipher_only;
+    }
 
-/// https://tools.ietf.org/html/rfc5280#section-4.2.1.9
+    pub fn build(self) -> KeyUsage {
+        self.0
+    }
+}
+
+/// https://tools.ietf.org/html/rfc5280#section-4.2.1.4
+///
+/// ```
+/// use oid::ObjectIdentifier;
+/// use picky::x509::extension::{CertificatePolicies, PolicyInformation, UserNotice};
+/// use std::convert::TryFrom;
+///
+/// let policy_oid = ObjectIdentifier::try_from("1.3.6.1.4.1.55555.1.1").unwrap();
+/// let certificate_policies = CertificatePolicies::new().policy(
+///     PolicyInformation::new(policy_oid.clone())
+///         .cps_uri("https://pki.example.com/cps")
+///         .unwrap()
+///         .user_notice(UserNotice::new().with_explicit_text("Issued for internal use only")),
+/// );
+/// assert!(certificate_policies.contains(policy_oid));
+/// ```
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct CertificatePolicies(Asn1SequenceOf<PolicyInformation>);
+
+impl Default for CertificatePolicies {
+    fn default() -> Self {
+        Self(Asn1SequenceOf(Vec::new()))
+    }
+}
+
+impl CertificatePolicies {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn policy(mut self, policy_information: PolicyInformation) -> Self {
+        (self.0).0.push(policy_information);
+        self
+    }
+
+    pub fn iter(&self) -> Iter<PolicyInformation> {
+        (self.0).0.iter()
+    }
+
+    pub fn contains<C: PartialEq<oid::ObjectIdentifier>>(&self, policy_identifier: C) -> bool {
+        (self.0)
+            .0
+            .iter()
+            .any(|policy| policy_identifier.eq(&policy.policy_identifier.0))
+    }
+}
+
+// PolicyInformation ::= SEQUENCE {
+//      policyIdentifier   CertPolicyId,
+//      policyQualifiers   SEQUENCE SIZE (1..MAX) OF PolicyQualifierInfo OPTIONAL }
 #[derive(Serialize, Debug, PartialEq, Clone)]
-pub struct BasicConstraints {
-    ca: Implicit<Option<bool>>, // default is false
-    path_len_constraint: Implicit<Option<u8>>,
+pub struct PolicyInformation {
+    policy_identifier: ObjectIdentifierAsn1,
+    policy_qualifiers: Option<Asn1SequenceOf<PolicyQualifierInfo>>,
 }
 
-impl BasicConstraints {
-    pub fn ca(&self) -> Option<bool> {
-        self.ca.0
+impl PolicyInformation {
+    pub fn new<OID: Into<ObjectIdentifierAsn1>>(policy_identifier: OID) -> Self {
+        Self {
+            policy_identifier: policy_identifier.into(),
+            policy_qualifiers: None,
+        }
     }
 
-    pub fn pathlen(&self) -> Option<u8> {
-        self.path_len_constraint.0
+    pub fn cps_uri<S: Into<String>>(self, uri: S) -> Result<Self, CharSetError> {
+        let uri = IA5String::from_string(uri.into())?;
+        Ok(self.qualifier(PolicyQualifierInfo::CpsUri(uri)))
+    }
+
+    pub fn user_notice(self, user_notice: UserNotice) -> Self {
+        self.qualifier(PolicyQualifierInfo::UserNotice(user_notice))
+    }
+
+    pub fn qualifier(mut self, qualifier: PolicyQualifierInfo) -> Self {
+        self.policy_qualifiers
+            .get_or_insert_with(|| Asn1SequenceOf(Vec::new()))
+            .0
+            .push(qualifier);
+        self
+    }
+
+    pub fn policy_identifier(&self) -> &ObjectIdentifierAsn1 {
+        &self.policy_identifier
+    }
+
+    pub fn qualifiers(&self) -> &[PolicyQualifierInfo] {
+        self.policy_qualifiers
+            .as_ref()
+            .map(|qualifiers| qualifiers.0.as_slice())
+            .unwrap_or(&[])
     }
 }
 
-impl<'de> de::Deserialize<'de> for BasicConstraints {
+impl<'de> de::Deserialize<'de> for PolicyInformation {
     fn deserialize<D>(deserializer: D) -> Result<Self, <D as de::Deserializer<'de>>::Error>
     where
         D: de::Deserializer<'de>,
@@ -450,19 +996,25 @@ impl<'de> de::Deserialize<'de> for BasicConstraints {
         struct Visitor;
 
         impl<'de> de::Visitor<'de> for Visitor {
-            type Value = BasicConstraints;
+            type Value = PolicyInformation;
 
             fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                formatter.write_str("a valid DER-encoded basic constraints extension")
+                formatter.write_str("a valid DER-encoded policy information")
             }
 
             fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
             where
                 A: de::SeqAccess<'de>,
             {
-                Ok(BasicConstraints {
-                    ca: Implicit(seq.next_element().unwrap_or(Some(None)).unwrap_or(None)),
-                    path_len_constraint: Implicit(seq.next_element().unwrap_or(Some(None)).unwrap_or(None)),
+                let policy_identifier = seq_next_element!(seq, PolicyInformation, "policy identifier");
+                let policy_qualifiers = match seq.next_element::<TagPeeker>()? {
+                    Some(_) => Some(seq_next_element!(seq, PolicyInformation, "policy qualifiers")),
+                    None => None,
+                };
+
+                Ok(PolicyInformation {
+                    policy_identifier,
+                    policy_qualifiers,
                 })
             }
         }
@@ -471,45 +1023,1144 @@ impl<'de> de::Deserialize<'de> for BasicConstraints {
     }
 }
 
-/// https://tools.ietf.org/html/rfc5280#section-4.2.1.12
-#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
-pub struct ExtendedKeyUsage(Asn1SequenceOf<ObjectIdentifierAsn1>);
-
-impl<OID: Into<ObjectIdentifierAsn1>> From<Vec<OID>> for ExtendedKeyUsage {
-    fn from(purpose_oids: Vec<OID>) -> Self {
-        ExtendedKeyUsage::new(purpose_oids)
-    }
+// PolicyQualifierInfo ::= SEQUENCE {
+//      policyQualifierId  PolicyQualifierId,
+//      qualifier          ANY DEFINED BY policyQualifierId }
+//
+/// Qualifier of a certificate policy.
+///
+/// Qualifiers of unknown types (or which couldn't be decoded) are kept DER-encoded.
+#[derive(Debug, PartialEq, Clone)]
+pub enum PolicyQualifierInfo {
+    CpsUri(IA5String),
+    UserNotice(UserNotice),
+    Other {
+        policy_qualifier_id: ObjectIdentifierAsn1,
+        qualifier: Asn1RawDer,
+    },
 }
 
-impl ExtendedKeyUsage {
-    pub fn new<OID: Into<ObjectIdentifierAsn1>>(purpose_oids: Vec<OID>) -> Self {
-        Self(
-            purpose_oids
-                .into_iter()
-                .map(|oid| oid.into())
-                .collect::<Vec<_>>()
-                .into(),
-        )
-    }
+impl ser::Serialize for PolicyQualifierInfo {
+    fn serialize<S>(&self, serializer: S) -> Result<<S as ser::Serializer>::Ok, <S as ser::Serializer>::Error>
+    where
+        S: ser::Serializer,
+    {
+        use ser::SerializeSeq;
 
-    pub fn iter(&self) -> Iter<ObjectIdentifierAsn1> {
-        (self.0).0.iter()
+        let mut seq = serializer.serialize_seq(Some(2))?;
+        match self {
+            PolicyQualifierInfo::CpsUri(uri) => {
+                seq.serialize_element(&ObjectIdentifierAsn1::from(oids::qt_cps()))?;
+                seq.serialize_element(&IA5StringAsn1::from(uri.clone()))?;
+            }
+            PolicyQualifierInfo::UserNotice(user_notice) => {
+                seq.serialize_element(&ObjectIdentifierAsn1::from(oids::qt_unotice()))?;
+                seq.serialize_element(user_notice)?;
+            }
+            PolicyQualifierInfo::Other {
+                policy_qualifier_id,
+                qualifier,
+            } => {
+                seq.serialize_element(policy_qualifier_id)?;
+                seq.serialize_element(qualifier)?;
+            }
+        }
+        seq.end()
     }
+}
 
-    pub fn iter_mut(&mut self) -> IterMut<ObjectIdentifierAsn1> {
+impl<'de> de::Deserialize<'de> for PolicyQualifierInfo {
+    fn deserialize<D>(deserializer: D) -> Result<Self, <D as de::Deserializer<'de>>::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = PolicyQualifierInfo;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a valid DER-encoded policy qualifier info")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::SeqAccess<'de>,
+            {
+                let policy_qualifier_id: ObjectIdentifierAsn1 =
+                    seq_next_element!(seq, PolicyQualifierInfo, "policy qualifier id");
+                let qualifier: Asn1RawDer = seq_next_element!(seq, PolicyQualifierInfo, "qualifier");
+
+                let decoded = match Into::<String>::into(&policy_qualifier_id.0).as_str() {
+                    oids::QT_CPS => picky_asn1_der::from_bytes::<IA5StringAsn1>(&qualifier.0)
+                        .ok()
+                        .map(|uri| PolicyQualifierInfo::CpsUri(uri.0)),
+                    oids::QT_UNOTICE => picky_asn1_der::from_bytes(&qualifier.0)
+                        .ok()
+                        .map(PolicyQualifierInfo::UserNotice),
+                    _ => None,
+                };
+
+                Ok(decoded.unwrap_or(PolicyQualifierInfo::Other {
+                    policy_qualifier_id,
+                    qualifier,
+                }))
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+// UserNotice ::= SEQUENCE {
+//      noticeRef        NoticeReference OPTIONAL,
+//      explicitText     DisplayText OPTIONAL }
+//
+/// Notice displayed to relying parties when the certificate is used.
+///
+/// RFC 5280 recommends to use an explicit text only: conforming CAs SHOULD NOT use `noticeRef`.
+#[derive(Serialize, Debug, PartialEq, Clone, Default)]
+pub struct UserNotice {
+    notice_ref: Option<NoticeReference>,
+    explicit_text: Option<DisplayText>,
+}
+
+impl UserNotice {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_notice_ref(mut self, notice_ref: NoticeReference) -> Self {
+        self.notice_ref = Some(notice_ref);
+        self
+    }
+
+    pub fn with_explicit_text<T: Into<DisplayText>>(mut self, explicit_text: T) -> Self {
+        self.explicit_text = Some(explicit_text.into());
+        self
+    }
+
+    pub fn notice_ref(&self) -> Option<&NoticeReference> {
+        self.notice_ref.as_ref()
+    }
+
+    pub fn explicit_text(&self) -> Option<&DisplayText> {
+        self.explicit_text.as_ref()
+    }
+}
+
+impl<'de> de::Deserialize<'de> for UserNotice {
+    fn deserialize<D>(deserializer: D) -> Result<Self, <D as de::Deserializer<'de>>::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = UserNotice;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a valid DER-encoded user notice")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::SeqAccess<'de>,
+            {
+                let mut user_notice = UserNotice::default();
+
+                while let Some(tag_peeker) = seq.next_element::<TagPeeker>()? {
+                    if tag_peeker.next_tag == Tag::SEQUENCE {
+                        user_notice.notice_ref = Some(seq_next_element!(seq, UserNotice, "notice reference"));
+                    } else {
+                        user_notice.explicit_text = Some(seq_next_element!(seq, UserNotice, "explicit text"));
+                    }
+                }
+
+                Ok(user_notice)
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+// NoticeReference ::= SEQUENCE {
+//      organization     DisplayText,
+//      noticeNumbers    SEQUENCE OF INTEGER }
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct NoticeReference {
+    organization: DisplayText,
+    notice_numbers: Asn1SequenceOf<IntegerAsn1>,
+}
+
+impl NoticeReference {
+    pub fn new<T: Into<DisplayText>>(organization: T, notice_numbers: Vec<IntegerAsn1>) -> Self {
+        Self {
+            organization: organization.into(),
+            notice_numbers: notice_numbers.into(),
+        }
+    }
+
+    pub fn organization(&self) -> &DisplayText {
+        &self.organization
+    }
+
+    pub fn notice_numbers(&self) -> &[IntegerAsn1] {
+        &self.notice_numbers.0
+    }
+}
+
+// DisplayText ::= CHOICE {
+//      ia5String        IA5String      (SIZE (1..200)),
+//      visibleString    VisibleString  (SIZE (1..200)),
+//      bmpString        BMPString      (SIZE (1..200)),
+//      utf8String       UTF8String     (SIZE (1..200)) }
+#[derive(Debug, PartialEq, Clone)]
+pub enum DisplayText {
+    Ia5String(IA5StringAsn1),
+    //VisibleString,
+    //BmpString,
+    Utf8String(String),
+}
+
+impl fmt::Display for DisplayText {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisplayText::Ia5String(text) => write!(f, "{}", String::from_utf8_lossy(text.as_bytes())),
+            DisplayText::Utf8String(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+impl From<&str> for DisplayText {
+    fn from(text: &str) -> Self {
+        Self::Utf8String(text.to_owned())
+    }
+}
+
+impl From<String> for DisplayText {
+    fn from(text: String) -> Self {
+        Self::Utf8String(text)
+    }
+}
+
+impl From<IA5String> for DisplayText {
+    fn from(text: IA5String) -> Self {
+        Self::Ia5String(text.into())
+    }
+}
+
+impl ser::Serialize for DisplayText {
+    fn serialize<S>(&self, serializer: S) -> Result<<S as ser::Serializer>::Ok, <S as ser::Serializer>::Error>
+    where
+        S: ser::Serializer,
+    {
+        match self {
+            DisplayText::Ia5String(text) => text.serialize(serializer),
+            DisplayText::Utf8String(text) => text.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> de::Deserialize<'de> for DisplayText {
+    fn deserialize<D>(deserializer: D) -> Result<Self, <D as de::Deserializer<'de>>::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = DisplayText;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a valid DER-encoded DisplayText")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::SeqAccess<'de>,
+            {
+                let tag_peeker: TagPeeker = seq_next_element!(seq, DisplayText, "choice tag");
+                match tag_peeker.next_tag {
+                    Tag::UTF8_STRING => Ok(DisplayText::Utf8String(seq_next_element!(
+                        seq,
+                        DisplayText,
+                        "Utf8String"
+                    ))),
+                    Tag::IA5_STRING => Ok(DisplayText::Ia5String(seq_next_element!(seq, DisplayText, "IA5String"))),
+                    _ => Err(serde_invalid_value!(
+                        DisplayText,
+                        "unknown string type",
+                        "a known supported string type"
+                    )),
+                }
+            }
+        }
+
+        deserializer.deserialize_enum("DisplayText", &["Ia5String", "Utf8String"], Visitor)
+    }
+}
+
+/// https://tools.ietf.org/html/rfc5280#section-4.2.1.5
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct PolicyMappings(Asn1SequenceOf<PolicyMapping>);
+
+impl Default for PolicyMappings {
+    fn default() -> Self {
+        Self(Asn1SequenceOf(Vec::new()))
+    }
+}
+
+impl PolicyMappings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `issuer_domain_policy` of the issuing CA is considered equivalent to `subject_domain_policy`.
+    pub fn mapping<I, S>(mut self, issuer_domain_policy: I, subject_domain_policy: S) -> Self
+    where
+        I: Into<ObjectIdentifierAsn1>,
+        S: Into<ObjectIdentifierAsn1>,
+    {
+        (self.0).0.push(PolicyMapping {
+            issuer_domain_policy: issuer_domain_policy.into(),
+            subject_domain_policy: subject_domain_policy.into(),
+        });
+        self
+    }
+
+    pub fn iter(&self) -> Iter<PolicyMapping> {
+        (self.0).0.iter()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct PolicyMapping {
+    issuer_domain_policy: ObjectIdentifierAsn1,
+    subject_domain_policy: ObjectIdentifierAsn1,
+}
+
+impl PolicyMapping {
+    pub fn issuer_domain_policy(&self) -> &ObjectIdentifierAsn1 {
+        &self.issuer_domain_policy
+    }
+
+    pub fn subject_domain_policy(&self) -> &ObjectIdentifierAsn1 {
+        &self.subject_domain_policy
+    }
+}
+
+/// https://tools.ietf.org/html/rfc5280#section-4.2.1.6
+type SubjectAltName = GeneralNames;
+
+/// https://tools.ietf.org/html/rfc5280#section-4.2.1.7
+type IssuerAltName = GeneralNames;
+
+/// https://tools.ietf.org/html/rfc5280#section-4.2.1.9
+#[derive(Serialize, Debug, PartialEq, Clone)]
+pub struct BasicConstraints {
+    ca: Implicit<Option<bool>>, // default is false
+    path_len_constraint: Implicit<Option<u8>>,
+}
+
+impl BasicConstraints {
+    pub fn ca(&self) -> Option<bool> {
+        self.ca.0
+    }
+
+    pub fn pathlen(&self) -> Option<u8> {
+        self.path_len_constraint.0
+    }
+}
+
+impl<'de> de::Deserialize<'de> for BasicConstraints {
+    fn deserialize<D>(deserializer: D) -> Result<Self, <D as de::Deserializer<'de>>::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = BasicConstraints;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a valid DER-encoded basic constraints extension")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::SeqAccess<'de>,
+            {
+                Ok(BasicConstraints {
+                    ca: Implicit(seq.next_element().unwrap_or(Some(None)).unwrap_or(None)),
+                    path_len_constraint: Implicit(seq.next_element().unwrap_or(Some(None)).unwrap_or(None)),
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+/// https://tools.ietf.org/html/rfc5280#section-4.2.1.10
+///
+/// Only subtrees whose base is a DNS name are enforced when verifying a chain, see
+/// `Cert::verify_chain`: when a critical extension constrains other name forms, names of those forms
+/// are rejected.
+///
+/// ```
+/// use picky::x509::{extension::NameConstraints, name::GeneralName};
+///
+/// let name_constraints = NameConstraints::new()
+///     .permitted_subtree(GeneralName::new_dns_name("example.com").unwrap())
+///     .excluded_subtree(GeneralName::new_dns_name("internal.example.com").unwrap());
+/// assert!(name_constraints.is_dns_name_permitted("www.example.com"));
+/// assert!(!name_constraints.is_dns_name_permitted("db.internal.example.com"));
+/// assert!(!name_constraints.is_dns_name_permitted("example.org"));
+/// ```
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct NameConstraints {
+    permitted_subtrees: Vec<GeneralSubtree>,
+    excluded_subtrees: Vec<GeneralSubtree>,
+}
+
+impl NameConstraints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn permitted_subtree<GN: Into<super::name::GeneralName>>(mut self, base: GN) -> Self {
+        let base: super::name::GeneralName = base.into();
+        self.permitted_subtrees.push(GeneralSubtree::new(base.into()));
+        self
+    }
+
+    pub fn excluded_subtree<GN: Into<super::name::GeneralName>>(mut self, base: GN) -> Self {
+        let base: super::name::GeneralName = base.into();
+        self.excluded_subtrees.push(GeneralSubtree::new(base.into()));
+        self
+    }
+
+    /// Bases of the permitted subtrees.
+    pub fn permitted_subtrees(&self) -> Vec<super::name::GeneralName> {
+        self.permitted_subtrees
+            .iter()
+            .map(|subtree| subtree.base.clone().into())
+            .collect()
+    }
+
+    /// Bases of the excluded subtrees.
+    pub fn excluded_subtrees(&self) -> Vec<super::name::GeneralName> {
+        self.excluded_subtrees
+            .iter()
+            .map(|subtree| subtree.base.clone().into())
+            .collect()
+    }
+
+    /// Whether `dns_name` is within a permitted DNS subtree (if any) and outside all excluded ones.
+    ///
+    /// Names which aren't valid DNS names are only permitted when there is no DNS name constraint.
+    pub fn is_dns_name_permitted(&self, dns_name: &str) -> bool {
+        fn dns_subtrees(subtrees: &[GeneralSubtree]) -> Vec<String> {
+            subtrees
+                .iter()
+                .filter_map(|subtree| match &subtree.base {
+                    GeneralName::DNSName(name) => Some(name.0.to_string()),
+                    _ => None,
+                })
+                .collect()
+        }
+
+        let permitted = dns_subtrees(&self.permitted_subtrees);
+        let excluded = dns_subtrees(&self.excluded_subtrees);
+
+        if permitted.is_empty() && excluded.is_empty() {
+            return true;
+        }
+
+        if normalize_dns_name(dns_name).is_err() {
+            return false;
+        }
+
+        (permitted.is_empty() || permitted.iter().any(|subtree| dns_name_in_subtree(dns_name, subtree)))
+            && !excluded.iter().any(|subtree| dns_name_in_subtree(dns_name, subtree))
+    }
+
+    /// Whether a subtree of the same form as `name` constrains it, DNS names excepted.
+    ///
+    /// Constraints on those forms aren't enforced by `is_dns_name_permitted`.
+    pub fn constrains_other_form(&self, name: &super::name::GeneralName) -> bool {
+        let name: GeneralName = name.clone().into();
+        if let GeneralName::DNSName(_) = name {
+            return false;
+        }
+
+        self.permitted_subtrees
+            .iter()
+            .chain(&self.excluded_subtrees)
+            .any(|subtree| std::mem::discriminant(&subtree.base) == std::mem::discriminant(&name))
+    }
+}
+
+// NameConstraints ::= SEQUENCE {
+//      permittedSubtrees       [0]     GeneralSubtrees OPTIONAL,
+//      excludedSubtrees        [1]     GeneralSubtrees OPTIONAL }
+//
+// GeneralSubtrees ::= SEQUENCE SIZE (1..MAX) OF GeneralSubtree
+impl ser::Serialize for NameConstraints {
+    fn serialize<S>(&self, serializer: S) -> Result<<S as ser::Serializer>::Ok, <S as ser::Serializer>::Error>
+    where
+        S: ser::Serializer,
+    {
+        use ser::SerializeSeq;
+
+        let implicit_subtrees = |tag: Tag, subtrees: &[GeneralSubtree]| -> Result<Asn1RawDer, S::Error> {
+            implicit_sequence(tag, &Asn1SequenceOf(subtrees.to_vec())).map_err(ser::Error::custom)
+        };
+
+        let mut seq = serializer.serialize_seq(Some(2))?;
+        if !self.permitted_subtrees.is_empty() {
+            seq.serialize_element(&implicit_subtrees(Tag::APP_0, &self.permitted_subtrees)?)?;
+        }
+        if !self.excluded_subtrees.is_empty() {
+            seq.serialize_element(&implicit_subtrees(Tag::APP_1, &self.excluded_subtrees)?)?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de> de::Deserialize<'de> for NameConstraints {
+    fn deserialize<D>(deserializer: D) -> Result<Self, <D as de::Deserializer<'de>>::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = NameConstraints;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a valid DER-encoded name constraints extension")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::SeqAccess<'de>,
+            {
+                let mut name_constraints = NameConstraints::default();
+
+                while let Some(tag_peeker) = seq.next_element::<TagPeeker>()? {
+                    let subtrees = match tag_peeker.next_tag {
+                        Tag::APP_0 => &mut name_constraints.permitted_subtrees,
+                        Tag::APP_1 => &mut name_constraints.excluded_subtrees,
+                        _ => {
+                            return Err(serde_invalid_value!(
+                                NameConstraints,
+                                "unknown field",
+                                "permitted or excluded subtrees"
+                            ))
+                        }
+                    };
+                    let raw: Asn1RawDer = seq_next_element!(seq, NameConstraints, "subtrees");
+                    *subtrees = from_implicit_sequence::<Asn1SequenceOf<GeneralSubtree>>(raw)
+                        .map_err(de::Error::custom)?
+                        .0;
+                }
+
+                Ok(name_constraints)
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+// GeneralSubtree ::= SEQUENCE {
+//      base                    GeneralName,
+//      minimum         [0]     BaseDistance DEFAULT 0,
+//      maximum         [1]     BaseDistance OPTIONAL }
+//
+// (minimum and maximum are not used with any name form of RFC 5280)
+#[derive(Serialize, Debug, PartialEq, Clone)]
+struct GeneralSubtree {
+    base: GeneralName,
+    minimum: Option<ContextTag0<IntegerAsn1>>,
+    maximum: Option<ContextTag1<IntegerAsn1>>,
+}
+
+impl GeneralSubtree {
+    fn new(base: GeneralName) -> Self {
+        Self {
+            base,
+            minimum: None,
+            maximum: None,
+        }
+    }
+}
+
+impl<'de> de::Deserialize<'de> for GeneralSubtree {
+    fn deserialize<D>(deserializer: D) -> Result<Self, <D as de::Deserializer<'de>>::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = GeneralSubtree;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a valid DER-encoded general subtree")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::SeqAccess<'de>,
+            {
+                let mut subtree = GeneralSubtree::new(seq_next_element!(seq, GeneralSubtree, "base"));
+
+                while let Some(tag_peeker) = seq.next_element::<TagPeeker>()? {
+                    match tag_peeker.next_tag {
+                        Tag::CTX_0 => subtree.minimum = Some(seq_next_element!(seq, GeneralSubtree, "minimum")),
+                        Tag::CTX_1 => subtree.maximum = Some(seq_next_element!(seq, GeneralSubtree, "maximum")),
+                        _ => {
+                            return Err(serde_invalid_value!(
+                                GeneralSubtree,
+                                "unknown field",
+                                "minimum or maximum"
+                            ))
+                        }
+                    }
+                }
+
+                Ok(subtree)
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+/// https://tools.ietf.org/html/rfc5280#section-4.2.1.12
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct ExtendedKeyUsage(Asn1SequenceOf<ObjectIdentifierAsn1>);
+
+impl<OID: Into<ObjectIdentifierAsn1>> From<Vec<OID>> for ExtendedKeyUsage {
+    fn from(purpose_oids: Vec<OID>) -> Self {
+        ExtendedKeyUsage::new(purpose_oids)
+    }
+}
+
+impl ExtendedKeyUsage {
+    pub fn new<OID: Into<ObjectIdentifierAsn1>>(purpose_oids: Vec<OID>) -> Self {
+        Self(
+            purpose_oids
+                .into_iter()
+                .map(|oid| oid.into())
+                .collect::<Vec<_>>()
+                .into(),
+        )
+    }
+
+    pub fn iter(&self) -> Iter<ObjectIdentifierAsn1> {
+        (self.0).0.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<ObjectIdentifierAsn1> {
         (self.0).0.iter_mut()
     }
 
     pub fn contains<C: PartialEq<oid::ObjectIdentifier>>(&self, item: C) -> bool {
         (self.0).0.iter().any(|id| item.eq(&id.0))
     }
+
+    /// ```
+    /// use picky::x509::extension::{ExtendedKeyUsage, KeyPurpose};
+    ///
+    /// let eku = ExtendedKeyUsage::from_purposes(&[KeyPurpose::ServerAuth, KeyPurpose::ClientAuth]);
+    /// assert!(eku.contains(picky::oids::kp_client_auth()));
+    /// ```
+    pub fn from_purposes(purposes: &[KeyPurpose]) -> Self {
+        Self::new(purposes.iter().map(|purpose| purpose.oid()).collect::<Vec<_>>())
+    }
+}
+
+/// Standard key purposes for the extended key usage extension
+///
+/// https://tools.ietf.org/html/rfc5280#section-4.2.1.12
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyPurpose {
+    ServerAuth,
+    ClientAuth,
+    CodeSigning,
+    EmailProtection,
+    IpsecEndSystem,
+    IpsecTunnel,
+    IpsecUser,
+    TimeStamping,
+    OcspSigning,
+    Any,
+}
+
+impl KeyPurpose {
+    pub fn oid(self) -> oid::ObjectIdentifier {
+        match self {
+            KeyPurpose::ServerAuth => oids::kp_server_auth(),
+            KeyPurpose::ClientAuth => oids::kp_client_auth(),
+            KeyPurpose::CodeSigning => oids::kp_code_signing(),
+            KeyPurpose::EmailProtection => oids::kp_email_protection(),
+            KeyPurpose::IpsecEndSystem => oids::kp_ipsec_end_system(),
+            KeyPurpose::IpsecTunnel => oids::kp_ipsec_tunnel(),
+            KeyPurpose::IpsecUser => oids::kp_ipsec_user(),
+            KeyPurpose::TimeStamping => oids::kp_time_stamping(),
+            KeyPurpose::OcspSigning => oids::kp_ocsp_signing(),
+            KeyPurpose::Any => oids::kp_any_extended_key_usage(),
+        }
+    }
+}
+
+impl From<KeyPurpose> for ObjectIdentifierAsn1 {
+    fn from(purpose: KeyPurpose) -> Self {
+        purpose.oid().into()
+    }
+}
+
+/// https://tools.ietf.org/html/rfc5280#section-4.2.2.1
+///
+/// Only URI access locations are supported. The extension is read back as a generic extension,
+/// see `Cert::authority_info_access`.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct AuthorityInfoAccess {
+    ocsp: Vec<IA5String>,
+    ca_issuers: Vec<IA5String>,
+}
+
+// id-ad-ocsp and id-ad-caIssuers OBJECT IDENTIFIER contents
+const AD_OCSP_CONTENTS: &[u8] = &[0x2B, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01];
+const AD_CA_ISSUERS_CONTENTS: &[u8] = &[0x2B, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x02];
+
+impl AuthorityInfoAccess {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the DER value of the extension, skipping access locations which aren't URIs.
+    pub fn from_der(der: &[u8]) -> Option<Self> {
+        let mut aia = Self::default();
+
+        for (tag, access_description) in der_sequence(der)? {
+            if tag != TAG_SEQUENCE {
+                return None;
+            }
+
+            let mut fields = der_values(access_description);
+            let (access_method, (location_tag, location)) = match (fields.next()?, fields.next()?) {
+                ((TAG_OID, access_method), location) => (access_method, location),
+                _ => return None,
+            };
+            if location_tag != TAG_URI {
+                continue;
+            }
+
+            let uri = IA5String::new(location).ok()?;
+            match access_method {
+                AD_OCSP_CONTENTS => aia.ocsp.push(uri),
+                AD_CA_ISSUERS_CONTENTS => aia.ca_issuers.push(uri),
+                _ => {}
+            }
+        }
+
+        Some(aia)
+    }
+
+    pub fn ocsp_uris(&self) -> &[IA5String] {
+        &self.ocsp
+    }
+
+    pub fn ca_issuers_uris(&self) -> &[IA5String] {
+        &self.ca_issuers
+    }
+
+    pub fn ocsp<S: Into<String>>(mut self, uri: S) -> Result<Self, CharSetError> {
+        self.ocsp.push(IA5String::from_string(uri.into())?);
+        Ok(self)
+    }
+
+    pub fn ca_issuers<S: Into<String>>(mut self, uri: S) -> Result<Self, CharSetError> {
+        self.ca_issuers.push(IA5String::from_string(uri.into())?);
+        Ok(self)
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Clone)]
+struct AccessDescription {
+    access_method: ObjectIdentifierAsn1,
+    access_location: GeneralName,
+}
+
+/// https://tools.ietf.org/html/rfc5280#section-4.2.1.13
+///
+/// Also used by the freshest CRL extension (https://tools.ietf.org/html/rfc5280#section-4.2.1.15).
+///
+/// Each distribution point is written using the full name form. When decoding, reasons and CRL
+/// issuers are ignored and distribution points named relative to the CRL issuer are skipped.
+///
+/// ```
+/// use picky::x509::{extension::CrlDistributionPoints, name::{GeneralName, GeneralNames}};
+///
+/// let crl_dp = CrlDistributionPoints::new()
+///     .uri("http://crl.example.com/ca.crl")
+///     .unwrap()
+///     .full_name(GeneralNames::new(GeneralName::new_uri("ldap://ldap.example.com/cn=CA").unwrap()));
+/// assert_eq!(crl_dp.uris().len(), 2);
+/// ```
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct CrlDistributionPoints {
+    full_names: Vec<GeneralNames>,
+}
+
+impl CrlDistributionPoints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the DER value of the extension.
+    pub fn from_der(der: &[u8]) -> Option<Self> {
+        picky_asn1_der::from_bytes(der).ok()
+    }
+
+    /// URIs of all the distribution points.
+    pub fn uris(&self) -> Vec<IA5String> {
+        self.full_names
+            .iter()
+            .flat_map(|full_name| full_name.0.iter())
+            .filter_map(|name| match name {
+                GeneralName::URI(uri) => Some(uri.0.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Full names of the distribution points.
+    pub fn full_names(&self) -> Vec<super::name::GeneralNames> {
+        self.full_names.iter().cloned().map(Into::into).collect()
+    }
+
+    /// Adds a distribution point named by `uri`.
+    pub fn uri<S: Into<String>>(mut self, uri: S) -> Result<Self, CharSetError> {
+        let uri = GeneralName::URI(IA5String::from_string(uri.into())?.into());
+        self.full_names.push(Asn1SequenceOf(vec![uri]));
+        Ok(self)
+    }
+
+    /// Adds a distribution point whose full name is `full_name`.
+    pub fn full_name(mut self, full_name: super::name::GeneralNames) -> Self {
+        self.full_names.push(full_name.into());
+        self
+    }
+}
+
+impl ser::Serialize for CrlDistributionPoints {
+    fn serialize<S>(&self, serializer: S) -> Result<<S as ser::Serializer>::Ok, <S as ser::Serializer>::Error>
+    where
+        S: ser::Serializer,
+    {
+        let distribution_points = self
+            .full_names
+            .iter()
+            .map(|full_name| {
+                Ok(DistributionPoint {
+                    distribution_point: Some(ApplicationTag0(implicit_sequence(Tag::APP_0, full_name)?)),
+                })
+            })
+            .collect::<Result<Vec<DistributionPoint>, Asn1DerError>>()
+            .map_err(ser::Error::custom)?;
+
+        Asn1SequenceOf(distribution_points).serialize(serializer)
+    }
+}
+
+impl<'de> de::Deserialize<'de> for CrlDistributionPoints {
+    fn deserialize<D>(deserializer: D) -> Result<Self, <D as de::Deserializer<'de>>::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let distribution_points = Asn1SequenceOf::<DistributionPoint>::deserialize(deserializer)?.0;
+
+        let mut full_names = Vec::with_capacity(distribution_points.len());
+        for distribution_point in distribution_points {
+            match distribution_point.distribution_point {
+                Some(ApplicationTag0(name)) if name.0.first() == Some(&Tag::APP_0.number()) => {
+                    full_names.push(from_implicit_sequence(name).map_err(de::Error::custom)?);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self { full_names })
+    }
+}
+
+const TAG_OID: u8 = 0x06;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_URI: u8 = 0x86;
+
+/// Reads a DER element, returning its tag, its contents and the remaining input.
+///
+/// Extensions picky writes but doesn't model are read back by walking their elements.
+fn read_der_value(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, input) = input.split_first()?;
+    let (&length, input) = input.split_first()?;
+    let (length, input) = if length < 0x80 {
+        (usize::from(length), input)
+    } else {
+        let num_bytes = usize::from(length & 0x7F);
+        if num_bytes == 0 || num_bytes > std::mem::size_of::<usize>() || input.len() < num_bytes {
+            return None;
+        }
+        let (length, input) = input.split_at(num_bytes);
+        let length = length.iter().fold(0, |length, byte| (length << 8) | usize::from(*byte));
+        (length, input)
+    };
+
+    if input.len() < length {
+        return None;
+    }
+    Some((tag, &input[..length], &input[length..]))
+}
+
+/// Elements of constructed contents, stopping at the first malformed one.
+fn der_values(mut contents: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    std::iter::from_fn(move || {
+        let (tag, value, remaining) = read_der_value(contents)?;
+        contents = remaining;
+        Some((tag, value))
+    })
+}
+
+/// Elements of a DER-encoded SEQUENCE, `None` if `der` isn't one.
+fn der_sequence(der: &[u8]) -> Option<impl Iterator<Item = (u8, &[u8])>> {
+    match read_der_value(der)? {
+        (TAG_SEQUENCE, contents, []) => Some(der_values(contents)),
+        _ => None,
+    }
+}
+
+// DistributionPoint ::= SEQUENCE {
+//      distributionPoint       [0]     DistributionPointName OPTIONAL,
+//      reasons                 [1]     ReasonFlags OPTIONAL,
+//      cRLIssuer               [2]     GeneralNames OPTIONAL }
+//
+// DistributionPointName ::= CHOICE {
+//      fullName                [0]     GeneralNames,
+//      nameRelativeToCRLIssuer [1]     RelativeDistinguishedName }
+//
+// (reasons and cRLIssuer are never written, and skipped when reading)
+#[derive(Serialize, Debug, PartialEq, Clone)]
+struct DistributionPoint {
+    distribution_point: Option<ApplicationTag0<Asn1RawDer>>,
+}
+
+impl<'de> de::Deserialize<'de> for DistributionPoint {
+    fn deserialize<D>(deserializer: D) -> Result<Self, <D as de::Deserializer<'de>>::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = DistributionPoint;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a valid DER-encoded distribution point")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::SeqAccess<'de>,
+            {
+                let mut distribution_point = DistributionPoint {
+                    distribution_point: None,
+                };
+
+                while let Some(tag_peeker) = seq.next_element::<TagPeeker>()? {
+                    match tag_peeker.next_tag {
+                        Tag::APP_0 => {
+                            distribution_point.distribution_point =
+                                Some(seq_next_element!(seq, DistributionPoint, "distribution point name"))
+                        }
+                        Tag::CTX_1 | Tag::APP_2 => {
+                            let _: Asn1RawDer = seq_next_element!(seq, DistributionPoint, "reasons or crl issuer");
+                        }
+                        _ => {
+                            return Err(serde_invalid_value!(
+                                DistributionPoint,
+                                "unknown field",
+                                "distribution point name, reasons or crl issuer"
+                            ))
+                        }
+                    }
+                }
+
+                Ok(distribution_point)
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+/// https://tools.ietf.org/html/rfc5280#section-5.2.5
+///
+/// Only a single URI full name is supported. The extension is read back as a generic extension,
+/// see `Crl::issuing_distribution_point`.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct IssuingDistributionPoint {
+    uri: Option<IA5String>,
+    only_contains_user_certs: bool,
+    only_contains_ca_certs: bool,
+    indirect_crl: bool,
+}
+
+impl IssuingDistributionPoint {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn uri<S: Into<String>>(mut self, uri: S) -> Result<Self, CharSetError> {
+        self.uri = Some(IA5String::from_string(uri.into())?);
+        Ok(self)
+    }
+
+    pub fn only_contains_user_certs(mut self, value: bool) -> Self {
+        self.only_contains_user_certs = value;
+        self
+    }
+
+    pub fn only_contains_ca_certs(mut self, value: bool) -> Self {
+        self.only_contains_ca_certs = value;
+        self
+    }
+
+    pub fn indirect_crl(mut self, value: bool) -> Self {
+        self.indirect_crl = value;
+        self
+    }
+
+    /// Decodes the DER value of the extension.
+    ///
+    /// `None` if it's malformed or uses what isn't supported: a distribution point named relative to
+    /// the CRL issuer or by anything but a single URI, `onlySomeReasons` or `onlyContainsAttributeCerts`.
+    pub fn from_der(der: &[u8]) -> Option<Self> {
+        let mut idp = Self::default();
+
+        for (tag, value) in der_sequence(der)? {
+            match tag {
+                TAG_IDP_DISTRIBUTION_POINT => {
+                    let full_name = match read_der_value(value)? {
+                        (TAG_IDP_DISTRIBUTION_POINT, full_name, []) => full_name,
+                        _ => return None,
+                    };
+                    match read_der_value(full_name)? {
+                        (TAG_URI, uri, []) => idp.uri = Some(IA5String::new(uri).ok()?),
+                        _ => return None,
+                    }
+                }
+                TAG_IDP_ONLY_CONTAINS_USER_CERTS => idp.only_contains_user_certs = der_boolean(value)?,
+                TAG_IDP_ONLY_CONTAINS_CA_CERTS => idp.only_contains_ca_certs = der_boolean(value)?,
+                TAG_IDP_INDIRECT_CRL => idp.indirect_crl = der_boolean(value)?,
+                _ => return None,
+            }
+        }
+
+        Some(idp)
+    }
+
+    pub fn distribution_point_uri(&self) -> Option<&IA5String> {
+        self.uri.as_ref()
+    }
+
+    pub fn contains_only_user_certs(&self) -> bool {
+        self.only_contains_user_certs
+    }
+
+    pub fn contains_only_ca_certs(&self) -> bool {
+        self.only_contains_ca_certs
+    }
+
+    pub fn is_indirect_crl(&self) -> bool {
+        self.indirect_crl
+    }
+}
+
+// IssuingDistributionPoint fields are implicitly tagged
+const TAG_IDP_DISTRIBUTION_POINT: u8 = 0xA0;
+const TAG_IDP_ONLY_CONTAINS_USER_CERTS: u8 = 0x81;
+const TAG_IDP_ONLY_CONTAINS_CA_CERTS: u8 = 0x82;
+const TAG_IDP_INDIRECT_CRL: u8 = 0x84;
+
+fn der_boolean(contents: &[u8]) -> Option<bool> {
+    match contents {
+        [0x00] => Some(false),
+        [0xFF] => Some(true),
+        _ => None,
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq, Clone)]
+struct IssuingDistributionPointRepr {
+    distribution_point: Option<ApplicationTag0<ApplicationTag0<GeneralName>>>,
+    only_contains_user_certs: Option<ContextTag1<bool>>,
+    only_contains_ca_certs: Option<ContextTag2<bool>>,
+    // onlySomeReasons [3] is not supported
+    indirect_crl: Option<ContextTag4<bool>>,
+}
+
+/// https://tools.ietf.org/html/rfc5280#section-5.3.1
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CrlReason {
+    Unspecified = 0,
+    KeyCompromise = 1,
+    CaCompromise = 2,
+    AffiliationChanged = 3,
+    Superseded = 4,
+    CessationOfOperation = 5,
+    CertificateHold = 6,
+    RemoveFromCrl = 8,
+    PrivilegeWithdrawn = 9,
+    AaCompromise = 10,
+}
+
+impl CrlReason {
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Self::Unspecified),
+            1 => Some(Self::KeyCompromise),
+            2 => Some(Self::CaCompromise),
+            3 => Some(Self::AffiliationChanged),
+            4 => Some(Self::Superseded),
+            5 => Some(Self::CessationOfOperation),
+            6 => Some(Self::CertificateHold),
+            8 => Some(Self::RemoveFromCrl),
+            9 => Some(Self::PrivilegeWithdrawn),
+            10 => Some(Self::AaCompromise),
+            _ => None,
+        }
+    }
+
+    pub fn code(self) -> u8 {
+        self as u8
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{pem::Pem, x509::private::name::GeneralName};
-    use picky_asn1::restricted_string::IA5String;
+    use crate::{
+        pem::Pem,
+        x509::{name, private::name::GeneralName},
+    };
+    use std::convert::TryFrom;
 
     #[test]
     fn key_usage() {
@@ -572,4 +2223,458 @@ mod tests {
 
         check_serde!(extensions: Extensions in encoded);
     }
+
+    #[test]
+    fn lazy_extension_value() {
+        let malformed = [0x30, 0x08, 0x06, 0x03, 0x55, 0x1D, 0x11, 0x04, 0x01, 0x01];
+        let extension: Extension = picky_asn1_der::from_bytes(&malformed).expect("malformed value is kept encoded");
+        assert!(extension.extn_value.decoded.get().is_none());
+        assert_eq!(
+            extension.extn_value(),
+            ExtensionView::Generic(&OctetStringAsn1(vec![0x01]))
+        );
+        assert_eq!(picky_asn1_der::to_vec(&extension).expect("serialize"), malformed);
+
+        let san = Extension::new_subject_alt_name(vec![GeneralName::DNSName(
+            IA5String::from_string("devel.example.com".into()).unwrap().into(),
+        )]);
+        let encoded = picky_asn1_der::to_vec(&san).expect("serialize");
+        let parsed: Extension = picky_asn1_der::from_bytes(&encoded).expect("deserialize");
+        assert!(parsed.extn_value.decoded.get().is_none());
+        assert_eq!(parsed, san);
+        assert!(parsed.extn_value.decoded.get().is_some());
+        assert_eq!(picky_asn1_der::to_vec(&parsed).expect("serialize"), encoded);
+    }
+
+    #[test]
+    fn explicit_non_critical() {
+        #[rustfmt::skip]
+        let encoded = [
+            0x30, 0x0C,
+                0x06, 0x03, 0x55, 0x1D, 0x13, // basic constraints oid
+                0x01, 0x01, 0x00, // critical: false
+                0x04, 0x02, 0x30, 0x00, // value
+        ];
+        let mut extension: Extension = picky_asn1_der::from_bytes(&encoded).expect("deserialize");
+        assert!(!extension.critical());
+        assert_eq!(picky_asn1_der::to_vec(&extension).expect("serialize"), encoded.to_vec());
+
+        extension.set_critical(false);
+        assert_eq!(
+            picky_asn1_der::to_vec(&extension).expect("serialize"),
+            [0x30, 0x09, 0x06, 0x03, 0x55, 0x1D, 0x13, 0x04, 0x02, 0x30, 0x00]
+        );
+    }
+
+    #[test]
+    fn extensions_mutation() {
+        let mut ku = KeyUsage::default();
+        ku.set_digital_signature(true);
+
+        let mut extensions = Extensions::new();
+        assert!(extensions.add(Extension::new_basic_constraints(None, None)));
+        assert!(extensions.add(Extension::new_key_usage(ku.clone())));
+        assert!(!extensions.add(Extension::new_key_usage(KeyUsage::default())));
+        assert_eq!(extensions.len(), 2);
+
+        let replaced = extensions
+            .replace(Extension::new_basic_constraints(true, 0).into_critical())
+            .expect("replaced basic constraints");
+        assert_eq!(replaced, Extension::new_basic_constraints(None, None));
+        assert_eq!(extensions.as_slice()[0].extn_id(), &oids::basic_constraints());
+        assert!(extensions.as_slice()[0].critical());
+
+        assert!(extensions
+            .replace(Extension::new_extended_key_usage(vec![oids::kp_server_auth()].into()))
+            .is_none());
+        assert_eq!(
+            extensions.iter().map(|ext| ext.extn_id().0.clone()).collect::<Vec<_>>(),
+            vec![oids::basic_constraints(), oids::key_usage(), oids::extended_key_usage()]
+        );
+
+        assert_eq!(
+            extensions.remove_by_oid(&oids::key_usage()),
+            Some(Extension::new_key_usage(ku))
+        );
+        assert!(!extensions.contains(&oids::key_usage()));
+        assert!(extensions.remove_by_oid(&oids::key_usage()).is_none());
+    }
+
+    #[test]
+    fn extensions_dedup() {
+        let mut extensions = Extensions::from(vec![
+            Extension::new_basic_constraints(None, None),
+            Extension::new_key_usage(KeyUsage::default()),
+            Extension::new_basic_constraints(true, None),
+        ]);
+        extensions.dedup();
+        assert_eq!(
+            extensions.into_vec(),
+            vec![
+                Extension::new_basic_constraints(None, None),
+                Extension::new_key_usage(KeyUsage::default()),
+            ]
+        );
+    }
+
+    #[test]
+    fn key_usage_builder() {
+        let mut expected = KeyUsage::default();
+        expected.set_digital_signature(true);
+        expected.set_key_encipherment(true);
+
+        let key_usage = KeyUsage::builder().digital_signature().key_encipherment().build();
+        assert_eq!(key_usage, expected);
+    }
+
+    #[test]
+    fn name_constraints() {
+        #[rustfmt::skip]
+        let encoded = [
+            &[
+                0x30, 0x37,
+                    0x06, 0x03, 0x55, 0x1D, 0x1E,
+                    0x01, 0x01, 0xFF,
+                    0x04, 0x2D,
+                        0x30, 0x2B,
+                            0xA0, 0x0F, // permitted subtrees
+                                0x30, 0x0D,
+                                    0x82, 0x0B,
+            ][..],
+            &b"example.com"[..],
+            &[
+                            0xA1, 0x18, // excluded subtrees
+                                0x30, 0x16,
+                                    0x82, 0x14,
+            ][..],
+            &b"internal.example.com"[..],
+        ]
+        .concat();
+
+        let name_constraints = NameConstraints::new()
+            .permitted_subtree(crate::x509::name::GeneralName::new_dns_name("example.com").unwrap())
+            .excluded_subtree(crate::x509::name::GeneralName::new_dns_name("internal.example.com").unwrap());
+        let extension = Extension::new_name_constraints(name_constraints.clone());
+        assert_eq!(picky_asn1_der::to_vec(&extension).unwrap(), encoded);
+
+        let decoded: Extension = picky_asn1_der::from_bytes(&encoded).unwrap();
+        assert_eq!(decoded.extn_value(), ExtensionView::NameConstraints(&name_constraints));
+        assert!(name_constraints.is_dns_name_permitted("Example.com"));
+        assert!(!name_constraints.is_dns_name_permitted("www.internal.example.com"));
+        assert!(!name_constraints.is_dns_name_permitted("example.org"));
+        assert!(!name_constraints.is_dns_name_permitted("bad..example.com"));
+        assert!(NameConstraints::new().is_dns_name_permitted("example.org"));
+
+        // explicit minimum, IP address subtree
+        #[rustfmt::skip]
+        let encoded = [
+            0x30, 0x11,
+                0xA1, 0x0F,
+                    0x30, 0x0D,
+                        0x87, 0x08, 0x0A, 0x00, 0x00, 0x00, 0xFF, 0x00, 0x00, 0x00,
+                        0x80, 0x01, 0x00,
+        ];
+        let decoded: NameConstraints = picky_asn1_der::from_bytes(&encoded).unwrap();
+        assert_eq!(
+            decoded.excluded_subtrees(),
+            vec![crate::x509::name::GeneralName::new_ip_address(vec![
+                0x0A, 0x00, 0x00, 0x00, 0xFF, 0x00, 0x00, 0x00
+            ])]
+        );
+        assert!(decoded.is_dns_name_permitted("example.org"));
+    }
+
+    #[test]
+    fn certificate_policies() {
+        #[rustfmt::skip]
+        let encoded = [
+            &[
+                0x30, 0x66,
+                    0x06, 0x03, 0x55, 0x1D, 0x20,
+                    0x04, 0x5F,
+                        0x30, 0x5D,
+                            0x30, 0x53,
+                                0x06, 0x0A, 0x2B, 0x06, 0x01, 0x04, 0x01, 0x83, 0xB2, 0x03, 0x01, 0x01,
+                                0x30, 0x45,
+                                    0x30, 0x27, // CPS pointer
+                                        0x06, 0x08, 0x2B, 0x06, 0x01, 0x05, 0x05, 0x07, 0x02, 0x01,
+                                        0x16, 0x1B,
+            ][..],
+            &b"https://pki.example.com/cps"[..],
+            &[
+                                    0x30, 0x1A, // user notice
+                                        0x06, 0x08, 0x2B, 0x06, 0x01, 0x05, 0x05, 0x07, 0x02, 0x02,
+                                        0x30, 0x0E,
+                                            0x0C, 0x0C,
+            ][..],
+            &b"Internal use"[..],
+            &[
+                            0x30, 0x06, // anyPolicy
+                                0x06, 0x04, 0x55, 0x1D, 0x20, 0x00,
+            ][..],
+        ]
+        .concat();
+
+        let policy_oid = oid::ObjectIdentifier::try_from("1.3.6.1.4.1.55555.1.1").unwrap();
+        let certificate_policies = CertificatePolicies::new()
+            .policy(
+                PolicyInformation::new(policy_oid.clone())
+                    .cps_uri("https://pki.example.com/cps")
+                    .unwrap()
+                    .user_notice(UserNotice::new().with_explicit_text("Internal use")),
+            )
+            .policy(PolicyInformation::new(oids::any_policy()));
+        let extension = Extension::new_certificate_policies(certificate_policies.clone());
+        assert_eq!(picky_asn1_der::to_vec(&extension).unwrap(), encoded);
+
+        let decoded: Extension = picky_asn1_der::from_bytes(&encoded).unwrap();
+        assert_eq!(
+            decoded.extn_value(),
+            ExtensionView::CertificatePolicies(&certificate_policies)
+        );
+        assert!(certificate_policies.contains(policy_oid));
+        assert!(certificate_policies.contains(oids::any_policy()));
+
+        let qualifiers = certificate_policies.iter().next().unwrap().qualifiers();
+        match &qualifiers[1] {
+            PolicyQualifierInfo::UserNotice(user_notice) => {
+                assert_eq!(user_notice.explicit_text().unwrap().to_string(), "Internal use");
+                assert!(user_notice.notice_ref().is_none());
+            }
+            other => panic!("unexpected qualifier: {:?}", other),
+        }
+
+        // user notice with a VisibleString explicit text is kept encoded
+        #[rustfmt::skip]
+        let encoded = [
+            0x30, 0x12,
+                0x06, 0x08, 0x2B, 0x06, 0x01, 0x05, 0x05, 0x07, 0x02, 0x02,
+                0x30, 0x06,
+                    0x1A, 0x04, b'T', b'e', b'x', b't',
+        ];
+        let decoded: PolicyQualifierInfo = picky_asn1_der::from_bytes(&encoded).unwrap();
+        match &decoded {
+            PolicyQualifierInfo::Other {
+                policy_qualifier_id,
+                qualifier,
+            } => {
+                assert_eq!(policy_qualifier_id.0, oids::qt_unotice());
+                assert_eq!(qualifier.0, encoded[12..]);
+            }
+            other => panic!("unexpected qualifier: {:?}", other),
+        }
+        assert_eq!(picky_asn1_der::to_vec(&decoded).unwrap(), encoded);
+    }
+
+    #[test]
+    fn policy_mappings() {
+        #[rustfmt::skip]
+        let encoded = [
+            0x30, 0x26,
+                0x06, 0x03, 0x55, 0x1D, 0x21,
+                0x01, 0x01, 0xFF,
+                0x04, 0x1C,
+                    0x30, 0x1A,
+                        0x30, 0x18,
+                            0x06, 0x0A, 0x2B, 0x06, 0x01, 0x04, 0x01, 0x83, 0xB2, 0x03, 0x01, 0x01,
+                            0x06, 0x0A, 0x2B, 0x06, 0x01, 0x04, 0x01, 0x83, 0xB2, 0x03, 0x02, 0x01,
+        ];
+
+        let issuer_domain_policy = oid::ObjectIdentifier::try_from("1.3.6.1.4.1.55555.1.1").unwrap();
+        let subject_domain_policy = oid::ObjectIdentifier::try_from("1.3.6.1.4.1.55555.2.1").unwrap();
+        let policy_mappings = PolicyMappings::new().mapping(issuer_domain_policy.clone(), subject_domain_policy);
+        let extension = Extension::new_policy_mappings(policy_mappings.clone());
+        assert_eq!(picky_asn1_der::to_vec(&extension).unwrap(), encoded);
+
+        let decoded: Extension = picky_asn1_der::from_bytes(&encoded).unwrap();
+        assert_eq!(decoded.extn_value(), ExtensionView::PolicyMappings(&policy_mappings));
+        assert_eq!(
+            policy_mappings.iter().next().unwrap().issuer_domain_policy().0,
+            issuer_domain_policy
+        );
+    }
+
+    #[test]
+    fn extended_key_usage_from_purposes() {
+        let eku = ExtendedKeyUsage::from_purposes(&[KeyPurpose::ServerAuth, KeyPurpose::ClientAuth]);
+        assert_eq!(
+            eku,
+            ExtendedKeyUsage::new(vec![oids::kp_server_auth(), oids::kp_client_auth()])
+        );
+        assert!(!eku.contains(oids::kp_code_signing()));
+    }
+
+    #[test]
+    fn authority_info_access() {
+        #[rustfmt::skip]
+        let encoded = [
+            0x30, 0x27,
+                0x06, 0x08, 0x2B, 0x06, 0x01, 0x05, 0x05, 0x07, 0x01, 0x01,
+                0x04, 0x1B,
+                    0x30, 0x19,
+                        0x30, 0x17,
+                            0x06, 0x08, 0x2B, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01,
+                            0x86, 0x0B, b'h', b't', b't', b'p', b':', b'/', b'/', b'o', b'c', b's', b'p',
+        ];
+
+        let aia = AuthorityInfoAccess::new().ocsp("http://ocsp").unwrap();
+        let extension = Extension::new_authority_info_access(&aia).unwrap();
+        assert_eq!(picky_asn1_der::to_vec(&extension).unwrap(), encoded.to_vec());
+
+        assert_eq!(AuthorityInfoAccess::from_der(&encoded[14..]), Some(aia));
+        // truncated
+        assert_eq!(AuthorityInfoAccess::from_der(&encoded[14..40]), None);
+    }
+
+    #[test]
+    fn crl_distribution_points() {
+        #[rustfmt::skip]
+        let encoded = [
+            0x30, 0x1C,
+                0x06, 0x03, 0x55, 0x1D, 0x1F,
+                0x04, 0x15,
+                    0x30, 0x13,
+                        0x30, 0x11,
+                            0xA0, 0x0F,
+                                0xA0, 0x0D,
+                                    0x86, 0x0B, b'h', b't', b't', b'p', b':', b'/', b'/', b'c', b'r', b'l', b'/',
+        ];
+
+        let crl_dp = CrlDistributionPoints::new().uri("http://crl/").unwrap();
+        let extension = Extension::new_crl_distribution_points(crl_dp.clone());
+        check_serde!(extension: Extension in encoded);
+
+        let decoded = CrlDistributionPoints::from_der(&encoded[9..]).unwrap();
+        assert_eq!(decoded, crl_dp);
+        assert_eq!(decoded.uris()[0].to_string(), "http://crl/");
+    }
+
+    #[test]
+    fn crl_distribution_points_with_reasons_and_relative_name() {
+        #[rustfmt::skip]
+        let encoded = [
+            0x30, 0x20,
+                // relative name only
+                0x30, 0x06,
+                    0xA0, 0x04,
+                        0xA1, 0x02, 0x30, 0x00,
+                // full name along with reasons
+                0x30, 0x16,
+                    0xA0, 0x0F,
+                        0xA0, 0x0D,
+                            0x86, 0x0B, b'h', b't', b't', b'p', b':', b'/', b'/', b'c', b'r', b'l', b'/',
+                    0x81, 0x03, 0x07, 0x80, 0x00,
+        ];
+
+        let decoded = CrlDistributionPoints::from_der(&encoded).unwrap();
+        assert_eq!(decoded, CrlDistributionPoints::new().uri("http://crl/").unwrap());
+    }
+
+    #[test]
+    fn freshest_crl() {
+        #[rustfmt::skip]
+        let encoded = [
+            0x30, 0x34,
+                0x06, 0x03, 0x55, 0x1D, 0x2E,
+                0x04, 0x2D,
+                    0x30, 0x2B,
+                        0x30, 0x29,
+                            0xA0, 0x27,
+                                0xA0, 0x25,
+                                    0x86, 0x0D, b'h', b't', b't', b'p', b':', b'/', b'/', b'd', b'e', b'l', b't', b'a', b'/',
+                                    0xA4, 0x14,
+                                        0x30, 0x12,
+                                            0x31, 0x10,
+                                                0x30, 0x0E,
+                                                    0x06, 0x03, 0x55, 0x04, 0x03,
+                                                    0x0C, 0x07, b'd', b'e', b'l', b't', b'a', b'C', b'A',
+        ];
+
+        let mut full_name = name::GeneralNames::new(name::GeneralName::new_uri("http://delta/").unwrap());
+        full_name.add_name(name::GeneralName::new_directory_name(
+            name::DirectoryName::new_common_name("deltaCA"),
+        ));
+        let freshest_crl = CrlDistributionPoints::new().full_name(full_name.clone());
+        let extension = Extension::new_freshest_crl(freshest_crl.clone());
+        check_serde!(extension: Extension in encoded);
+
+        match extension.extn_value() {
+            ExtensionView::FreshestCrl(decoded) => {
+                assert_eq!(decoded, &freshest_crl);
+                assert_eq!(decoded.full_names(), vec![full_name]);
+                assert_eq!(decoded.uris()[0].to_string(), "http://delta/");
+            }
+            _ => panic!("expected freshest crl"),
+        }
+    }
+
+    #[test]
+    fn issuing_distribution_point() {
+        #[rustfmt::skip]
+        let encoded = [
+            0x30, 0x1F,
+                0x06, 0x03, 0x55, 0x1D, 0x1C,
+                0x01, 0x01, 0xFF,
+                0x04, 0x15,
+                    0x30, 0x13,
+                        0xA0, 0x0E,
+                            0xA0, 0x0C,
+                                0x86, 0x0A, b'h', b't', b't', b'p', b':', b'/', b'/', b'c', b'r', b'l',
+                        0x81, 0x01, 0xFF,
+        ];
+
+        let idp = IssuingDistributionPoint::new()
+            .uri("http://crl")
+            .unwrap()
+            .only_contains_user_certs(true);
+        let extension = Extension::new_issuing_distribution_point(&idp).unwrap();
+        assert_eq!(picky_asn1_der::to_vec(&extension).unwrap(), encoded.to_vec());
+
+        let decoded = IssuingDistributionPoint::from_der(&encoded[12..]).unwrap();
+        assert_eq!(decoded, idp);
+        assert_eq!(decoded.distribution_point_uri().unwrap().to_string(), "http://crl");
+        assert!(decoded.contains_only_user_certs());
+
+        // onlySomeReasons
+        assert_eq!(
+            IssuingDistributionPoint::from_der(&[0x30, 0x04, 0x83, 0x02, 0x07, 0x80]),
+            None
+        );
+    }
+
+    #[test]
+    fn crl_entry_extensions() {
+        #[rustfmt::skip]
+        let reason_encoded = [
+            0x30, 0x0A,
+                0x06, 0x03, 0x55, 0x1D, 0x15,
+                0x04, 0x03,
+                    0x0A, 0x01, 0x01,
+        ];
+        let reason = Extension::new_crl_reason(CrlReason::KeyCompromise);
+        assert_eq!(picky_asn1_der::to_vec(&reason).unwrap(), reason_encoded.to_vec());
+        assert_eq!(CrlReason::from_code(1), Some(CrlReason::KeyCompromise));
+        assert_eq!(CrlReason::from_code(7), None);
+
+        #[rustfmt::skip]
+        let invalidity_date_encoded = [
+            0x30, 0x18,
+                0x06, 0x03, 0x55, 0x1D, 0x18,
+                0x04, 0x11,
+                    0x18, 0x0F, b'2', b'0', b'2', b'0', b'0', b'1', b'0', b'2', b'0', b'3', b'0', b'4', b'0', b'5', b'Z',
+        ];
+        let date = UTCDate::new(2020, 1, 2, 3, 4, 5).unwrap();
+        let invalidity_date = Extension::new_invalidity_date(date).unwrap();
+        assert_eq!(
+            picky_asn1_der::to_vec(&invalidity_date).unwrap(),
+            invalidity_date_encoded.to_vec()
+        );
+
+        let issuer = crate::x509::name::GeneralNames::new(
+            crate::x509::name::GeneralName::new_dns_name("ca.example.com").unwrap(),
+        );
+        let certificate_issuer = Extension::new_certificate_issuer(issuer).unwrap();
+        assert!(certificate_issuer.critical());
+        assert_eq!(certificate_issuer.extn_id(), &oids::certificate_issuer());
+    }
 }
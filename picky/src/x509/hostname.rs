@@ -0,0 +1,157 @@
+//! DNS name matching used for hostname verification
+//!
+//! Names are compared in their ASCII form: internationalized labels are converted to punycode
+//! (IDNA), letters are lowercased and the trailing dot of fully qualified names is dropped.
+//!
+//! https://tools.ietf.org/html/rfc6125#section-6.4
+
+use snafu::Snafu;
+
+#[derive(Debug, Snafu)]
+pub enum HostnameError {
+    /// name isn't a valid (possibly internationalized) DNS name
+    #[snafu(display("invalid DNS name '{}'", name))]
+    InvalidDnsName { name: String },
+}
+
+/// ASCII form of a DNS name, lowercased and without trailing dot.
+///
+/// A leading `*` wildcard label is preserved so that subject alternative names can be normalized too.
+pub fn normalize_dns_name(name: &str) -> Result<String, HostnameError> {
+    let invalid = || HostnameError::InvalidDnsName { name: name.to_owned() };
+
+    let name_without_dot = if name.ends_with('.') {
+        &name[..name.len() - 1]
+    } else {
+        name
+    };
+
+    let (wildcard, domain) = if name_without_dot.starts_with("*.") {
+        (true, &name_without_dot[2..])
+    } else {
+        (false, name_without_dot)
+    };
+
+    let ascii = idna::domain_to_ascii(domain).map_err(|_| invalid())?;
+    if ascii.split('.').any(|label| label.is_empty() || label.len() > 63) || ascii.len() > 253 {
+        return Err(invalid());
+    }
+
+    if wildcard {
+        Ok(format!("*.{}", ascii))
+    } else {
+        Ok(ascii)
+    }
+}
+
+/// Whether `hostname` is matched by `dns_name`.
+///
+/// `dns_name` may start with a `*` wildcard label matching exactly one label, but neither the
+/// parent domain nor a top-level domain.
+pub fn dns_name_matches(dns_name: &str, hostname: &str) -> bool {
+    if hostname.starts_with('*') {
+        return false;
+    }
+
+    let (dns_name, hostname) = match (normalize_dns_name(dns_name), normalize_dns_name(hostname)) {
+        (Ok(dns_name), Ok(hostname)) => (dns_name, hostname),
+        _ => return false,
+    };
+
+    if dns_name.starts_with("*.") {
+        // suffix including the leading dot, e.g. `.example.com`
+        let suffix = &dns_name[1..];
+        if !suffix[1..].contains('.') {
+            return false;
+        }
+
+        match hostname.find('.') {
+            Some(dot) => dot > 0 && &hostname[dot..] == suffix,
+            None => false,
+        }
+    } else {
+        dns_name == hostname
+    }
+}
+
+/// Whether `dns_name` belongs to `subtree`, the base of a name constraints subtree.
+///
+/// Any name built by adding labels to the left of the subtree belongs to it (e.g. `www.example.com`
+/// to `example.com`). With a leading dot (`.example.com`), only subdomains do. An empty subtree
+/// contains every name.
+///
+/// https://tools.ietf.org/html/rfc5280#section-4.2.1.10
+pub fn dns_name_in_subtree(dns_name: &str, subtree: &str) -> bool {
+    let (subdomains_only, subtree) = if subtree.starts_with('.') {
+        (true, &subtree[1..])
+    } else {
+        (false, subtree)
+    };
+
+    if subtree.is_empty() {
+        return true;
+    }
+
+    let (dns_name, subtree) = match (normalize_dns_name(dns_name), normalize_dns_name(subtree)) {
+        (Ok(dns_name), Ok(subtree)) => (dns_name, subtree),
+        _ => return false,
+    };
+
+    if dns_name.len() > subtree.len() {
+        let (labels, suffix) = dns_name.split_at(dns_name.len() - subtree.len());
+        suffix == subtree && labels.ends_with('.')
+    } else {
+        !subdomains_only && dns_name == subtree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize() {
+        assert_eq!(normalize_dns_name("WWW.Example.COM.").unwrap(), "www.example.com");
+        assert_eq!(normalize_dns_name("bücher.example").unwrap(), "xn--bcher-kva.example");
+        assert_eq!(
+            normalize_dns_name("*.Bücher.example").unwrap(),
+            "*.xn--bcher-kva.example"
+        );
+        assert_eq!(
+            normalize_dns_name("xn--bcher-kva.example").unwrap(),
+            "xn--bcher-kva.example"
+        );
+        assert!(normalize_dns_name("").is_err());
+        assert!(normalize_dns_name("example..com").is_err());
+        assert!(normalize_dns_name("example.com..").is_err());
+    }
+
+    #[test]
+    fn matches() {
+        assert!(dns_name_matches("example.com", "EXAMPLE.com."));
+        assert!(dns_name_matches("xn--bcher-kva.example", "Bücher.example"));
+        assert!(dns_name_matches("bücher.example", "xn--bcher-kva.example"));
+        assert!(dns_name_matches("*.example.com", "www.example.com"));
+        assert!(dns_name_matches("*.bücher.example", "shop.xn--bcher-kva.example."));
+        assert!(!dns_name_matches("*.example.com", "example.com"));
+        assert!(!dns_name_matches("*.example.com", "a.b.example.com"));
+        assert!(!dns_name_matches("*.com", "example.com"));
+        assert!(!dns_name_matches("www.example.com", "*.example.com"));
+        assert!(!dns_name_matches("example.com", "example.org"));
+    }
+
+    #[test]
+    fn subtree() {
+        assert!(dns_name_in_subtree("example.com", "example.com"));
+        assert!(dns_name_in_subtree("WWW.Example.com.", "example.com"));
+        assert!(dns_name_in_subtree("a.b.example.com", "example.com"));
+        assert!(dns_name_in_subtree("*.example.com", "example.com"));
+        assert!(dns_name_in_subtree("shop.bücher.example", "xn--bcher-kva.example"));
+        assert!(dns_name_in_subtree("www.example.com", ".example.com"));
+        assert!(dns_name_in_subtree("example.org", ""));
+        assert!(!dns_name_in_subtree("example.com", ".example.com"));
+        assert!(!dns_name_in_subtree("badexample.com", "example.com"));
+        assert!(!dns_name_in_subtree("example.com", "www.example.com"));
+        assert!(!dns_name_in_subtree("example..com", "example.com"));
+    }
+}
@@ -45,8 +45,8 @@ pub enum KeyIdGenMethod {
 macro_rules! hash {
     ( @ $algorithm:ident, $input:ident) => {{
         let mut digest = $algorithm::new();
-        digest.input($input);
-        digest.result().as_slice().to_vec()
+        digest.update($input);
+        digest.finalize().as_slice().to_vec()
     }};
     ($hash_algo:ident, $input:ident) => {
         match $hash_algo {
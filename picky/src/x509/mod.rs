@@ -1,15 +1,29 @@
-mod private;
+pub(crate) mod private;
 
 pub mod certificate;
+pub mod crl;
 pub mod csr;
 pub mod date;
 pub mod directory_string;
 pub mod extension;
+pub mod hostname;
 pub mod key_id_gen_method;
 pub mod name;
+pub mod ocsp;
+#[cfg(feature = "pkcs12")]
+pub mod pkcs12;
+pub mod revocation;
+pub mod trust_store;
+pub mod validation;
 
-pub use certificate::Cert;
+#[cfg(feature = "revocation_client")]
+pub mod revocation_client;
+
+pub use certificate::{Cert, CertificateBuilder};
+pub use crl::Crl;
 pub use csr::Csr;
 pub use directory_string::DirectoryString;
 pub use extension::{Extension, Extensions};
 pub use key_id_gen_method::KeyIdGenMethod;
+pub use trust_store::TrustStore;
+pub use validation::ChainValidator;
@@ -1,7 +1,10 @@
 use crate::x509::{
     private::{
         attribute_type_and_value::AttributeTypeAndValueParameters,
-        name::{GeneralName as SerdeGeneralName, GeneralNames as SerdeGeneralNames, NamePrettyFormatter},
+        name::{
+            GeneralName as SerdeGeneralName, GeneralNames as SerdeGeneralNames, NamePrettyFormatter,
+            OtherName as SerdeOtherName,
+        },
         AttributeTypeAndValue, Name,
     },
     DirectoryString,
@@ -9,8 +12,9 @@ use crate::x509::{
 use oid::ObjectIdentifier;
 use picky_asn1::{
     restricted_string::{CharSetError, IA5String},
-    wrapper::{Asn1SequenceOf, Asn1SetOf},
+    wrapper::{ApplicationTag0, Asn1SequenceOf, Asn1SetOf},
 };
+use picky_asn1_der::Asn1RawDer;
 use std::fmt;
 
 // === DirectoryName ===
@@ -42,6 +46,12 @@ impl DirectoryName {
         Self(Asn1SequenceOf(vec![Asn1SetOf(vec![])]))
     }
 
+    /// Name without any relative distinguished name, for certificates identified by their
+    /// subject alternative names only (RFC 5280, section 4.1.2.6).
+    pub fn new_empty() -> Self {
+        Self(Asn1SequenceOf(vec![]))
+    }
+
     pub fn new_common_name<S: Into<DirectoryString>>(name: S) -> Self {
         let mut dn = Self::default();
         dn.add_attr(NameAttr::CommonName, name);
@@ -60,6 +70,13 @@ impl DirectoryName {
         None
     }
 
+    /// True if this name doesn't hold any attribute
+    pub fn is_empty(&self) -> bool {
+        ((self.0).0)
+            .iter()
+            .all(|relative_distinguished_name| relative_distinguished_name.0.is_empty())
+    }
+
     pub fn add_attr<S: Into<DirectoryString>>(&mut self, attr: NameAttr, value: S) {
         let ty_val = match attr {
             NameAttr::CommonName => AttributeTypeAndValue::new_common_name(value),
@@ -72,6 +89,9 @@ impl DirectoryName {
             NameAttr::OrganisationName => AttributeTypeAndValue::new_organisation_name(value),
             NameAttr::OrganisationalUnitName => AttributeTypeAndValue::new_organisational_unit_name(value),
         };
+        if ((self.0).0).is_empty() {
+            ((self.0).0).push(Asn1SetOf(vec![]));
+        }
         ((self.0).0)[0].0.push(ty_val);
     }
 }
@@ -98,8 +118,15 @@ impl From<DirectoryName> for Name {
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum GeneralName {
+    OtherName {
+        type_id: ObjectIdentifier,
+        /// DER encoding of the value
+        value: Vec<u8>,
+    },
     RFC822Name(IA5String),
     DNSName(IA5String),
+    /// DER encoding of the ORAddress
+    X400Address(Vec<u8>),
     DirectoryName(DirectoryName),
     EDIPartyName {
         name_assigner: Option<DirectoryString>,
@@ -111,6 +138,18 @@ pub enum GeneralName {
 }
 
 impl GeneralName {
+    /// `value` is the DER encoding of the value defined by `type_id`
+    pub fn new_other_name<OID, V>(type_id: OID, value: V) -> Self
+    where
+        OID: Into<ObjectIdentifier>,
+        V: Into<Vec<u8>>,
+    {
+        Self::OtherName {
+            type_id: type_id.into(),
+            value: value.into(),
+        }
+    }
+
     pub fn new_rfc822_name<S: Into<String>>(name: S) -> Result<Self, CharSetError> {
         Ok(Self::RFC822Name(IA5String::from_string(name.into())?))
     }
@@ -119,6 +158,11 @@ impl GeneralName {
         Ok(Self::DNSName(IA5String::from_string(name.into())?))
     }
 
+    /// `address` is the DER encoding of the ORAddress
+    pub fn new_x400_address<ADDR: Into<Vec<u8>>>(address: ADDR) -> Self {
+        Self::X400Address(address.into())
+    }
+
     pub fn new_directory_name<N: Into<DirectoryName>>(name: N) -> Self {
         Self::DirectoryName(name.into())
     }
@@ -150,11 +194,16 @@ impl GeneralName {
 impl From<SerdeGeneralName> for GeneralName {
     fn from(gn: SerdeGeneralName) -> Self {
         match gn {
+            SerdeGeneralName::OtherName(other_name) => Self::OtherName {
+                type_id: other_name.type_id.0,
+                value: (other_name.value.0).0,
+            },
             SerdeGeneralName::RFC822Name(name) => Self::RFC822Name(name.0),
             SerdeGeneralName::DNSName(name) => Self::DNSName(name.0),
+            SerdeGeneralName::X400Address(address) => Self::X400Address(address.0),
             SerdeGeneralName::DirectoryName(name) => Self::DirectoryName(name.into()),
             SerdeGeneralName::EDIPartyName(edi_pn) => Self::EDIPartyName {
-                name_assigner: edi_pn.name_assigner.0.map(|na| na.0),
+                name_assigner: edi_pn.name_assigner.map(|na| na.0),
                 party_name: edi_pn.party_name.0,
             },
             SerdeGeneralName::URI(uri) => Self::URI(uri.0),
@@ -167,8 +216,13 @@ impl From<SerdeGeneralName> for GeneralName {
 impl From<GeneralName> for SerdeGeneralName {
     fn from(gn: GeneralName) -> Self {
         match gn {
+            GeneralName::OtherName { type_id, value } => SerdeGeneralName::OtherName(SerdeOtherName {
+                type_id: type_id.into(),
+                value: ApplicationTag0(Asn1RawDer(value)),
+            }),
             GeneralName::RFC822Name(name) => SerdeGeneralName::RFC822Name(name.into()),
             GeneralName::DNSName(name) => SerdeGeneralName::DNSName(name.into()),
+            GeneralName::X400Address(address) => SerdeGeneralName::X400Address(Asn1RawDer(address)),
             GeneralName::DirectoryName(name) => SerdeGeneralName::DirectoryName(name.into()),
             GeneralName::EDIPartyName {
                 name_assigner,
@@ -262,4 +316,16 @@ mod tests {
         let cn = my_name.find_common_name().unwrap();
         assert_eq!(cn.to_utf8_lossy(), "CommonName");
     }
+
+    #[test]
+    fn empty_directory_name() {
+        let mut name = DirectoryName::new_empty();
+        assert!(name.is_empty());
+        assert!(DirectoryName::new().is_empty());
+        assert_eq!(picky_asn1_der::to_vec(&Name::from(name.clone())).unwrap(), [0x30, 0x00]);
+
+        name.add_attr(NameAttr::CommonName, "CommonName");
+        assert!(!name.is_empty());
+        assert_eq!(name.to_string(), "CN=CommonName");
+    }
 }
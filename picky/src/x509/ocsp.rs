@@ -0,0 +1,1057 @@
+use crate::{
+    key::{PrivateKey, PublicKey},
+    oids,
+    signature::{SignatureError, SignatureHashType},
+    x509::{
+        certificate::Cert,
+        date::UTCDate,
+        extension::{CrlReason, ExtensionView},
+        key_id_gen_method::{KeyIdGenError, KeyIdGenMethod, KeyIdHashAlgo},
+        name::DirectoryName,
+        private::{
+            ocsp::{
+                BasicOCSPResponse, OCSPRequest, OCSPResponse, Request, ResponderId, ResponseBytes, ResponseData,
+                TBSRequest,
+            },
+            Certificate, Name,
+        },
+        Extension, Extensions,
+    },
+    AlgorithmIdentifier,
+};
+use picky_asn1::{
+    bit_string::BitString,
+    date::GeneralizedTime,
+    tag::{Tag, TagPeeker},
+    wrapper::{
+        ApplicationTag0, ApplicationTag1, ApplicationTag2, Asn1SequenceOf, BitStringAsn1Container, GeneralizedTimeAsn1,
+        IntegerAsn1, OctetStringAsn1,
+    },
+};
+use picky_asn1_der::{Asn1DerError, Asn1RawDer};
+use serde::{de, ser, Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+use snafu::{ResultExt, Snafu};
+use std::{cell::RefCell, fmt};
+
+#[derive(Debug, Snafu)]
+pub enum OcspError {
+    /// asn1 serialization error
+    #[snafu(display("(asn1) couldn't serialize {}: {}", element, source))]
+    Asn1Serialization {
+        element: &'static str,
+        source: Asn1DerError,
+    },
+
+    /// asn1 deserialization error
+    #[snafu(display("(asn1) couldn't deserialize {}: {}", element, source))]
+    Asn1Deserialization {
+        element: &'static str,
+        source: Asn1DerError,
+    },
+
+    /// signature error
+    #[snafu(display("signature error: {}", source))]
+    Signature { source: SignatureError },
+
+    /// couldn't hash the issuer public key
+    #[snafu(display("couldn't hash issuer public key: {}", source))]
+    KeyHash { source: KeyIdGenError },
+
+    /// missing required builder argument
+    #[snafu(display("missing required builder argument `{}`", arg))]
+    MissingBuilderArgument { arg: &'static str },
+
+    /// unsupported CertID hash algorithm
+    #[snafu(display("unsupported CertID hash algorithm: {}", oid))]
+    UnsupportedHashAlgorithm { oid: String },
+
+    /// unknown response status code
+    #[snafu(display("invalid OCSP response status"))]
+    InvalidResponseStatus,
+
+    /// response isn't successful and carries no response bytes
+    #[snafu(display("unsuccessful OCSP response: {:?}", status))]
+    Unsuccessful { status: OcspResponseStatus },
+
+    /// response type other than id-pkix-ocsp-basic
+    #[snafu(display("unsupported OCSP response type: {}", oid))]
+    UnsupportedResponseType { oid: String },
+
+    /// response isn't signed by the issuer nor by a responder it authorized
+    #[snafu(display("unauthorized OCSP responder: {}", reason))]
+    UnauthorizedResponder { reason: String },
+}
+
+static_assertions::assert_impl_all!(OcspError: Send, Sync);
+
+/// Hash algorithm used to identify certificates in requests and responses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OcspHashAlgorithm {
+    Sha1,
+    Sha256,
+}
+
+impl OcspHashAlgorithm {
+    fn from_algorithm_identifier(algorithm: &AlgorithmIdentifier) -> Result<Self, OcspError> {
+        if algorithm.is_a(oids::sha1()) {
+            Ok(Self::Sha1)
+        } else if algorithm.is_a(oids::sha256()) {
+            Ok(Self::Sha256)
+        } else {
+            Err(OcspError::UnsupportedHashAlgorithm {
+                oid: algorithm.oid().into(),
+            })
+        }
+    }
+
+    fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Sha1 => Sha1::digest(data).to_vec(),
+            Self::Sha256 => Sha256::digest(data).to_vec(),
+        }
+    }
+}
+
+impl From<OcspHashAlgorithm> for AlgorithmIdentifier {
+    fn from(hash_algorithm: OcspHashAlgorithm) -> Self {
+        match hash_algorithm {
+            OcspHashAlgorithm::Sha1 => AlgorithmIdentifier::new_sha1(),
+            OcspHashAlgorithm::Sha256 => AlgorithmIdentifier::new_sha256(),
+        }
+    }
+}
+
+/// Value of the subjectPublicKey BIT STRING (excluding the tag, length, and number of unused bits)
+fn public_key_value(public_key: &PublicKey) -> Result<Vec<u8>, OcspError> {
+    use crate::private::subject_public_key_info::PublicKey as InnerPublicKey;
+    match &public_key.as_inner().subject_public_key {
+        InnerPublicKey::RSA(BitStringAsn1Container(rsa_pk)) => {
+            picky_asn1_der::to_vec(rsa_pk).context(Asn1Serialization {
+                element: "RSA public key",
+            })
+        }
+        InnerPublicKey::EC(bitstring) => Ok(bitstring.0.payload_view().to_vec()),
+    }
+}
+
+/// Identifies a certificate by its issuer and serial number
+///
+/// https://tools.ietf.org/html/rfc6960#section-4.1.1
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CertId {
+    hash_algorithm: AlgorithmIdentifier,
+    issuer_name_hash: OctetStringAsn1,
+    issuer_key_hash: OctetStringAsn1,
+    serial_number: IntegerAsn1,
+}
+
+impl CertId {
+    /// Identifies `cert` using SHA-1, as expected by most responders.
+    pub fn new(cert: &Cert, issuer: &Cert) -> Result<Self, OcspError> {
+        Self::from_serial_number(issuer, cert.serial_number().clone(), OcspHashAlgorithm::Sha1)
+    }
+
+    pub fn from_serial_number(
+        issuer: &Cert,
+        serial_number: IntegerAsn1,
+        hash_algorithm: OcspHashAlgorithm,
+    ) -> Result<Self, OcspError> {
+        let (issuer_name_hash, issuer_key_hash) = Self::issuer_hashes(issuer, hash_algorithm)?;
+        Ok(Self {
+            hash_algorithm: hash_algorithm.into(),
+            issuer_name_hash: issuer_name_hash.into(),
+            issuer_key_hash: issuer_key_hash.into(),
+            serial_number,
+        })
+    }
+
+    fn issuer_hashes(issuer: &Cert, hash_algorithm: OcspHashAlgorithm) -> Result<(Vec<u8>, Vec<u8>), OcspError> {
+        let issuer_name = picky_asn1_der::to_vec(&Name::from(issuer.subject_name()))
+            .context(Asn1Serialization { element: "issuer name" })?;
+        let issuer_key = public_key_value(issuer.public_key())?;
+        Ok((hash_algorithm.digest(&issuer_name), hash_algorithm.digest(&issuer_key)))
+    }
+
+    pub fn hash_algorithm(&self) -> &AlgorithmIdentifier {
+        &self.hash_algorithm
+    }
+
+    pub fn issuer_name_hash(&self) -> &[u8] {
+        &self.issuer_name_hash.0
+    }
+
+    pub fn issuer_key_hash(&self) -> &[u8] {
+        &self.issuer_key_hash.0
+    }
+
+    pub fn serial_number(&self) -> &IntegerAsn1 {
+        &self.serial_number
+    }
+
+    /// Checks whether the identified certificate is issued by `issuer`.
+    pub fn is_issued_by(&self, issuer: &Cert) -> Result<bool, OcspError> {
+        let hash_algorithm = OcspHashAlgorithm::from_algorithm_identifier(&self.hash_algorithm)?;
+        let (issuer_name_hash, issuer_key_hash) = Self::issuer_hashes(issuer, hash_algorithm)?;
+        Ok(self.issuer_name_hash.0 == issuer_name_hash && self.issuer_key_hash.0 == issuer_key_hash)
+    }
+}
+
+fn find_nonce(extensions: &[Extension]) -> Option<Vec<u8>> {
+    let extension = extensions.iter().find(|ext| ext.extn_id().0 == oids::ocsp_nonce())?;
+    match extension.extn_value() {
+        ExtensionView::Generic(value) => picky_asn1_der::from_bytes::<OctetStringAsn1>(&value.0)
+            .ok()
+            .map(|nonce| nonce.0),
+        _ => None,
+    }
+}
+
+/// OCSP request
+///
+/// https://tools.ietf.org/html/rfc6960#section-4.1
+#[derive(Clone, Debug, PartialEq)]
+pub struct OcspRequest(OCSPRequest);
+
+static_assertions::assert_impl_all!(OcspRequest: Send, Sync);
+
+impl OcspRequest {
+    /// Unsigned request for the status of `cert_ids`, carrying `nonce` if any.
+    pub fn new(cert_ids: Vec<CertId>, nonce: Option<Vec<u8>>) -> Result<Self, OcspError> {
+        let request_extensions = match nonce {
+            Some(nonce) => Some(ApplicationTag2(Extensions::from(vec![Extension::new_ocsp_nonce(
+                nonce,
+            )
+            .context(Asn1Serialization { element: "nonce" })?]))),
+            None => None,
+        };
+
+        Ok(Self(OCSPRequest {
+            tbs_request: TBSRequest {
+                version: None,
+                requestor_name: None,
+                request_list: Asn1SequenceOf(
+                    cert_ids
+                        .into_iter()
+                        .map(|req_cert| Request {
+                            req_cert,
+                            single_request_extensions: None,
+                        })
+                        .collect(),
+                ),
+                request_extensions,
+            },
+            optional_signature: None,
+        }))
+    }
+
+    pub fn from_der<T: ?Sized + AsRef<[u8]>>(der: &T) -> Result<Self, OcspError> {
+        Ok(Self(picky_asn1_der::from_bytes(der.as_ref()).context(
+            Asn1Deserialization {
+                element: "ocsp request",
+            },
+        )?))
+    }
+
+    pub fn to_der(&self) -> Result<Vec<u8>, OcspError> {
+        picky_asn1_der::to_vec(&self.0).context(Asn1Serialization {
+            element: "ocsp request",
+        })
+    }
+
+    pub fn cert_ids(&self) -> impl Iterator<Item = &CertId> {
+        self.0
+            .tbs_request
+            .request_list
+            .0
+            .iter()
+            .map(|request| &request.req_cert)
+    }
+
+    pub fn extensions(&self) -> &[Extension] {
+        match &self.0.tbs_request.request_extensions {
+            Some(extensions) => extensions.0.as_slice(),
+            None => &[],
+        }
+    }
+
+    pub fn nonce(&self) -> Option<Vec<u8>> {
+        find_nonce(self.extensions())
+    }
+
+    pub fn is_signed(&self) -> bool {
+        self.0.optional_signature.is_some()
+    }
+}
+
+/// https://tools.ietf.org/html/rfc6960#section-4.2.1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OcspResponseStatus {
+    Successful = 0,
+    MalformedRequest = 1,
+    InternalError = 2,
+    TryLater = 3,
+    SigRequired = 5,
+    Unauthorized = 6,
+}
+
+impl OcspResponseStatus {
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Self::Successful),
+            1 => Some(Self::MalformedRequest),
+            2 => Some(Self::InternalError),
+            3 => Some(Self::TryLater),
+            5 => Some(Self::SigRequired),
+            6 => Some(Self::Unauthorized),
+            _ => None,
+        }
+    }
+}
+
+/// OCSP response
+///
+/// Only the basic response type is supported.
+///
+/// https://tools.ietf.org/html/rfc6960#section-4.2
+#[derive(Clone, Debug, PartialEq)]
+pub struct OcspResponse(OCSPResponse);
+
+static_assertions::assert_impl_all!(OcspResponse: Send, Sync);
+
+impl OcspResponse {
+    /// Unsuccessful response, e.g. `OcspResponseStatus::MalformedRequest`.
+    pub fn new_error(status: OcspResponseStatus) -> Self {
+        Self(OCSPResponse {
+            response_status: Asn1RawDer(vec![0x0A, 0x01, status as u8]),
+            response_bytes: None,
+        })
+    }
+
+    pub fn new_successful(basic_response: &BasicOcspResponse) -> Result<Self, OcspError> {
+        let response = picky_asn1_der::to_vec(&basic_response.0).context(Asn1Serialization {
+            element: "basic ocsp response",
+        })?;
+        Ok(Self(OCSPResponse {
+            response_status: Asn1RawDer(vec![0x0A, 0x01, OcspResponseStatus::Successful as u8]),
+            response_bytes: Some(ApplicationTag0(ResponseBytes {
+                response_type: oids::ocsp_basic().into(),
+                response: response.into(),
+            })),
+        }))
+    }
+
+    pub fn from_der<T: ?Sized + AsRef<[u8]>>(der: &T) -> Result<Self, OcspError> {
+        Ok(Self(picky_asn1_der::from_bytes(der.as_ref()).context(
+            Asn1Deserialization {
+                element: "ocsp response",
+            },
+        )?))
+    }
+
+    pub fn to_der(&self) -> Result<Vec<u8>, OcspError> {
+        picky_asn1_der::to_vec(&self.0).context(Asn1Serialization {
+            element: "ocsp response",
+        })
+    }
+
+    pub fn status(&self) -> Result<OcspResponseStatus, OcspError> {
+        match self.0.response_status.0.as_slice() {
+            // ENUMERATED
+            [0x0A, 0x01, code] => OcspResponseStatus::from_code(*code).ok_or(OcspError::InvalidResponseStatus),
+            _ => Err(OcspError::InvalidResponseStatus),
+        }
+    }
+
+    /// Decodes the basic response carried by a successful response.
+    pub fn basic_response(&self) -> Result<BasicOcspResponse, OcspError> {
+        let response_bytes = match &self.0.response_bytes {
+            Some(response_bytes) => &response_bytes.0,
+            None => return Err(OcspError::Unsuccessful { status: self.status()? }),
+        };
+
+        if response_bytes.response_type.0 != oids::ocsp_basic() {
+            return Err(OcspError::UnsupportedResponseType {
+                oid: (&response_bytes.response_type.0).into(),
+            });
+        }
+
+        Ok(BasicOcspResponse(
+            picky_asn1_der::from_bytes(&response_bytes.response.0).context(Asn1Deserialization {
+                element: "basic ocsp response",
+            })?,
+        ))
+    }
+}
+
+/// Signed basic OCSP response
+///
+/// https://tools.ietf.org/html/rfc6960#section-4.2.1
+#[derive(Clone, Debug, PartialEq)]
+pub struct BasicOcspResponse(BasicOCSPResponse);
+
+static_assertions::assert_impl_all!(BasicOcspResponse: Send, Sync);
+
+impl BasicOcspResponse {
+    pub fn builder<'a>() -> BasicOcspResponseBuilder<'a> {
+        BasicOcspResponseBuilder::new()
+    }
+
+    pub fn produced_at(&self) -> UTCDate {
+        self.0.tbs_response_data.produced_at.0.clone().into()
+    }
+
+    /// Responder name, `None` if the responder is identified by its key hash.
+    pub fn responder_name(&self) -> Option<DirectoryName> {
+        match &self.0.tbs_response_data.responder_id {
+            ResponderId::ByName(name) => Some(name.clone().into()),
+            ResponderId::ByKey(_) => None,
+        }
+    }
+
+    /// SHA-1 hash of the responder public key, `None` if the responder is identified by its name.
+    pub fn responder_key_hash(&self) -> Option<&[u8]> {
+        match &self.0.tbs_response_data.responder_id {
+            ResponderId::ByName(_) => None,
+            ResponderId::ByKey(key_hash) => Some(&key_hash.0),
+        }
+    }
+
+    pub fn responses(&self) -> &[SingleResponse] {
+        &self.0.tbs_response_data.responses.0
+    }
+
+    /// Looks up the status of a certificate.
+    pub fn find_response(&self, cert_id: &CertId) -> Option<&SingleResponse> {
+        self.responses().iter().find(|response| &response.cert_id == cert_id)
+    }
+
+    pub fn extensions(&self) -> &[Extension] {
+        match &self.0.tbs_response_data.response_extensions {
+            Some(extensions) => extensions.0.as_slice(),
+            None => &[],
+        }
+    }
+
+    pub fn nonce(&self) -> Option<Vec<u8>> {
+        find_nonce(self.extensions())
+    }
+
+    /// Certificates embedded to help verifying the response (e.g. a delegated responder certificate).
+    pub fn certs(&self) -> Vec<Cert> {
+        match &self.0.certs {
+            Some(certs) => (certs.0).0.iter().cloned().map(Cert::from).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn signature_algorithm(&self) -> &AlgorithmIdentifier {
+        &self.0.signature_algorithm
+    }
+
+    /// Verifies the response signature using the responder public key.
+    pub fn verify_signature(&self, public_key: &PublicKey) -> Result<(), OcspError> {
+        let hash_type = SignatureHashType::from_algorithm_identifier(&self.0.signature_algorithm).context(Signature)?;
+        let msg = picky_asn1_der::to_vec(&self.0.tbs_response_data).context(Asn1Serialization {
+            element: "tbs response data",
+        })?;
+        hash_type
+            .verify(public_key, &msg, self.0.signature.0.payload_view())
+            .context(Signature)
+    }
+
+    fn is_responder(&self, cert: &Cert) -> Result<bool, OcspError> {
+        match &self.0.tbs_response_data.responder_id {
+            ResponderId::ByName(name) => Ok(DirectoryName::from(name.clone()) == cert.subject_name()),
+            ResponderId::ByKey(key_hash) => {
+                let cert_key_hash = KeyIdGenMethod::SPKValueHashedLeftmost160(KeyIdHashAlgo::Sha1)
+                    .generate_from(cert.public_key())
+                    .context(KeyHash)?;
+                Ok(key_hash.0 == cert_key_hash)
+            }
+        }
+    }
+
+    /// Verifies that the response is signed either by `issuer` itself or by a responder
+    /// certificate, embedded in the response and valid at `now`, which `issuer` authorized for OCSP signing.
+    pub fn verify_issuer(&self, issuer: &Cert, now: &UTCDate) -> Result<(), OcspError> {
+        if self.is_responder(issuer)? {
+            return self.verify_signature(issuer.public_key());
+        }
+
+        let mut delegated = None;
+        for cert in self.certs() {
+            if self.is_responder(&cert)? {
+                delegated = Some(cert);
+                break;
+            }
+        }
+        let delegated = delegated.ok_or_else(|| OcspError::UnauthorizedResponder {
+            reason: "responder is neither the issuer nor an embedded certificate".to_owned(),
+        })?;
+
+        issuer
+            .is_parent_of(&delegated)
+            .map_err(|e| OcspError::UnauthorizedResponder { reason: e.to_string() })?;
+
+        let not_before = delegated.valid_not_before();
+        let not_after = delegated.valid_not_after();
+        if *now < not_before || not_after < *now {
+            return Err(OcspError::UnauthorizedResponder {
+                reason: format!(
+                    "responder certificate isn't valid at {} (not before: {}, not after: {})",
+                    now, not_before, not_after
+                ),
+            });
+        }
+
+        let is_ocsp_signer = delegated
+            .extended_key_usage()
+            .map(|eku| eku.contains(oids::kp_ocsp_signing()))
+            .unwrap_or(false);
+        if !is_ocsp_signer {
+            return Err(OcspError::UnauthorizedResponder {
+                reason: "responder certificate lacks the OCSPSigning extended key usage".to_owned(),
+            });
+        }
+
+        // the delegated responder certificate must be signed by the issuer
+        let delegated_certificate = Certificate::from(delegated.clone());
+        let hash_type = SignatureHashType::from_algorithm_identifier(&delegated_certificate.signature_algorithm)
+            .context(Signature)?;
+        let msg = picky_asn1_der::to_vec(&delegated_certificate.tbs_certificate).context(Asn1Serialization {
+            element: "tbs certificate",
+        })?;
+        hash_type
+            .verify(
+                issuer.public_key(),
+                &msg,
+                delegated_certificate.signature_value.0.payload_view(),
+            )
+            .context(Signature)?;
+
+        self.verify_signature(delegated.public_key())
+    }
+}
+
+/// Revocation status of a certificate
+///
+/// https://tools.ietf.org/html/rfc6960#section-4.2.1
+#[derive(Clone, Debug, PartialEq)]
+pub enum CertStatus {
+    Good,
+    Revoked {
+        revocation_time: UTCDate,
+        reason: Option<CrlReason>,
+    },
+    Unknown,
+}
+
+// CertStatus ::= CHOICE {
+//      good        [0]     IMPLICIT NULL,
+//      revoked     [1]     IMPLICIT RevokedInfo,
+//      unknown     [2]     IMPLICIT UnknownInfo }
+//
+// RevokedInfo ::= SEQUENCE {
+//      revocationTime              GeneralizedTime,
+//      revocationReason    [0]     EXPLICIT CRLReason OPTIONAL }
+//
+// Implicitly tagged constructed types aren't supported by picky-asn1-der: encoded by hand.
+impl ser::Serialize for CertStatus {
+    fn serialize<S>(&self, serializer: S) -> Result<<S as ser::Serializer>::Ok, <S as ser::Serializer>::Error>
+    where
+        S: ser::Serializer,
+    {
+        let encoded = match self {
+            CertStatus::Good => vec![0x80, 0x00],
+            CertStatus::Revoked {
+                revocation_time,
+                reason,
+            } => {
+                let revocation_time: GeneralizedTime = revocation_time.clone().into();
+                let mut revoked_info =
+                    picky_asn1_der::to_vec(&GeneralizedTimeAsn1::from(revocation_time)).map_err(ser::Error::custom)?;
+                if let Some(reason) = reason {
+                    revoked_info.extend_from_slice(&[0xA0, 0x03, 0x0A, 0x01, *reason as u8]);
+                }
+                let mut encoded = vec![0xA1, revoked_info.len() as u8];
+                encoded.extend_from_slice(&revoked_info);
+                encoded
+            }
+            CertStatus::Unknown => vec![0x82, 0x00],
+        };
+        Asn1RawDer(encoded).serialize(serializer)
+    }
+}
+
+impl<'de> de::Deserialize<'de> for CertStatus {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let encoded = Asn1RawDer::deserialize(deserializer)?.0;
+        match encoded.as_slice() {
+            [0x80, 0x00] => Ok(CertStatus::Good),
+            [0x82, 0x00] => Ok(CertStatus::Unknown),
+            [0xA1, len, revoked_info @ ..] if usize::from(*len) == revoked_info.len() => {
+                let time_len = match revoked_info {
+                    [0x18, time_len, ..] if usize::from(*time_len) + 2 <= revoked_info.len() => usize::from(*time_len),
+                    _ => {
+                        return Err(serde_invalid_value!(
+                            CertStatus,
+                            "invalid revocation time",
+                            "a GeneralizedTime"
+                        ))
+                    }
+                };
+                let revocation_time: GeneralizedTimeAsn1 =
+                    picky_asn1_der::from_bytes(&revoked_info[..time_len + 2]).map_err(de::Error::custom)?;
+                let reason = match &revoked_info[time_len + 2..] {
+                    [] => None,
+                    [0xA0, 0x03, 0x0A, 0x01, code] => Some(CrlReason::from_code(*code).ok_or_else(|| {
+                        serde_invalid_value!(CertStatus, "unknown revocation reason", "a CRLReason code")
+                    })?),
+                    _ => {
+                        return Err(serde_invalid_value!(
+                            CertStatus,
+                            "invalid revocation reason",
+                            "an explicitly tagged CRLReason"
+                        ))
+                    }
+                };
+
+                Ok(CertStatus::Revoked {
+                    revocation_time: revocation_time.0.into(),
+                    reason,
+                })
+            }
+            _ => Err(serde_invalid_value!(
+                CertStatus,
+                "unknown choice value",
+                "a supported CertStatus choice"
+            )),
+        }
+    }
+}
+
+/// Status of a single certificate in a basic OCSP response
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct SingleResponse {
+    cert_id: CertId,
+    cert_status: CertStatus,
+    this_update: GeneralizedTimeAsn1,
+    next_update: Option<ApplicationTag0<GeneralizedTimeAsn1>>,
+    single_extensions: Option<ApplicationTag1<Extensions>>,
+}
+
+impl SingleResponse {
+    pub fn new(cert_id: CertId, cert_status: CertStatus, this_update: UTCDate) -> Self {
+        let this_update: GeneralizedTime = this_update.into();
+        Self {
+            cert_id,
+            cert_status,
+            this_update: this_update.into(),
+            next_update: None,
+            single_extensions: None,
+        }
+    }
+
+    pub fn with_next_update(mut self, next_update: UTCDate) -> Self {
+        let next_update: GeneralizedTime = next_update.into();
+        self.next_update = Some(ApplicationTag0(next_update.into()));
+        self
+    }
+
+    pub fn with_extension(mut self, extension: Extension) -> Self {
+        let mut extensions = self.single_extensions.take().map(|ext| ext.0).unwrap_or_default();
+        extensions.replace(extension);
+        self.single_extensions = Some(ApplicationTag1(extensions));
+        self
+    }
+
+    pub fn cert_id(&self) -> &CertId {
+        &self.cert_id
+    }
+
+    pub fn cert_status(&self) -> &CertStatus {
+        &self.cert_status
+    }
+
+    pub fn this_update(&self) -> UTCDate {
+        self.this_update.0.clone().into()
+    }
+
+    pub fn next_update(&self) -> Option<UTCDate> {
+        self.next_update
+            .as_ref()
+            .map(|next_update| (next_update.0).0.clone().into())
+    }
+
+    pub fn extensions(&self) -> &[Extension] {
+        match &self.single_extensions {
+            Some(extensions) => extensions.0.as_slice(),
+            None => &[],
+        }
+    }
+}
+
+// Implement Deserialize manually: next update and extensions are optional
+impl<'de> de::Deserialize<'de> for SingleResponse {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = SingleResponse;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("struct SingleResponse")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<Self::Value, V::Error>
+            where
+                V: de::SeqAccess<'de>,
+            {
+                let cert_id = seq_next_element!(seq, SingleResponse, "cert id");
+                let cert_status = seq_next_element!(seq, SingleResponse, "cert status");
+                let this_update = seq_next_element!(seq, SingleResponse, "this update");
+
+                let mut next_tag = seq.next_element::<TagPeeker>()?.map(|peeker| peeker.next_tag);
+
+                let next_update = match next_tag {
+                    Some(Tag::APP_0) => {
+                        let next_update = seq_next_element!(seq, SingleResponse, "next update");
+                        next_tag = seq.next_element::<TagPeeker>()?.map(|peeker| peeker.next_tag);
+                        Some(next_update)
+                    }
+                    _ => None,
+                };
+
+                let single_extensions = match next_tag {
+                    Some(Tag::APP_1) => Some(seq_next_element!(seq, SingleResponse, "single extensions")),
+                    Some(_) => {
+                        return Err(serde_invalid_value!(
+                            SingleResponse,
+                            "unexpected trailing element",
+                            "single extensions"
+                        ))
+                    }
+                    None => None,
+                };
+
+                Ok(SingleResponse {
+                    cert_id,
+                    cert_status,
+                    this_update,
+                    next_update,
+                    single_extensions,
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+// Statically checks the field actually exists and returns a &'static str of the field name
+macro_rules! field_str {
+    ($field:ident) => {{
+        ::static_assertions::assert_fields!(BasicOcspResponseBuilderInner: $field);
+        stringify!($field)
+    }};
+}
+
+#[derive(Default, Clone, Debug)]
+struct BasicOcspResponseBuilderInner<'a> {
+    responder_cert: Option<Cert>,
+    responder_key: Option<&'a PrivateKey>,
+    responder_by_key: bool,
+    embed_responder_cert: bool,
+    produced_at: Option<UTCDate>,
+    signature_hash_type: Option<SignatureHashType>,
+    nonce: Option<Vec<u8>>,
+    responses: Vec<SingleResponse>,
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct BasicOcspResponseBuilder<'a> {
+    inner: RefCell<BasicOcspResponseBuilderInner<'a>>,
+}
+
+impl<'a> BasicOcspResponseBuilder<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Required. Either the issuer itself or a certificate it authorized for OCSP signing.
+    #[inline]
+    pub fn responder(&self, responder_cert: Cert, responder_key: &'a PrivateKey) -> &Self {
+        let mut inner_mut = self.inner.borrow_mut();
+        inner_mut.responder_cert = Some(responder_cert);
+        inner_mut.responder_key = Some(responder_key);
+        drop(inner_mut);
+        self
+    }
+
+    /// Optional. Identifies the responder by its key hash instead of its name.
+    #[inline]
+    pub fn responder_by_key(&self, by_key: bool) -> &Self {
+        self.inner.borrow_mut().responder_by_key = by_key;
+        self
+    }
+
+    /// Optional. Embeds the responder certificate, required for delegated responders.
+    #[inline]
+    pub fn embed_responder_cert(&self, embed: bool) -> &Self {
+        self.inner.borrow_mut().embed_responder_cert = embed;
+        self
+    }
+
+    /// Required
+    #[inline]
+    pub fn produced_at(&self, produced_at: UTCDate) -> &Self {
+        self.inner.borrow_mut().produced_at = Some(produced_at);
+        self
+    }
+
+    /// Optional
+    #[inline]
+    pub fn signature_hash_type(&self, signature_hash_type: SignatureHashType) -> &Self {
+        self.inner.borrow_mut().signature_hash_type = Some(signature_hash_type);
+        self
+    }
+
+    /// Optional. Nonce of the request being answered.
+    #[inline]
+    pub fn nonce(&self, nonce: Vec<u8>) -> &Self {
+        self.inner.borrow_mut().nonce = Some(nonce);
+        self
+    }
+
+    /// Optional
+    #[inline]
+    pub fn response(&self, response: SingleResponse) -> &Self {
+        self.inner.borrow_mut().responses.push(response);
+        self
+    }
+
+    pub fn build(&self) -> Result<BasicOcspResponse, OcspError> {
+        let mut inner = self.inner.borrow_mut();
+
+        let responder_cert = inner.responder_cert.take().ok_or(OcspError::MissingBuilderArgument {
+            arg: field_str!(responder_cert),
+        })?;
+        let responder_key = inner.responder_key.take().ok_or(OcspError::MissingBuilderArgument {
+            arg: field_str!(responder_key),
+        })?;
+        let produced_at = inner.produced_at.take().ok_or(OcspError::MissingBuilderArgument {
+            arg: field_str!(produced_at),
+        })?;
+        let signature_hash_type = inner.signature_hash_type.take().unwrap_or(SignatureHashType::RsaSha256);
+        let nonce_opt = inner.nonce.take();
+        let responses = std::mem::take(&mut inner.responses);
+        let responder_by_key = inner.responder_by_key;
+        let embed_responder_cert = inner.embed_responder_cert;
+
+        drop(inner);
+
+        let responder_id = if responder_by_key {
+            ResponderId::ByKey(
+                KeyIdGenMethod::SPKValueHashedLeftmost160(KeyIdHashAlgo::Sha1)
+                    .generate_from(responder_cert.public_key())
+                    .context(KeyHash)?
+                    .into(),
+            )
+        } else {
+            ResponderId::ByName(responder_cert.subject_name().into())
+        };
+
+        let response_extensions = match nonce_opt {
+            Some(nonce) => Some(ApplicationTag1(Extensions::from(vec![Extension::new_ocsp_nonce(
+                nonce,
+            )
+            .context(Asn1Serialization { element: "nonce" })?]))),
+            None => None,
+        };
+
+        let produced_at: GeneralizedTime = produced_at.into();
+        let tbs_response_data = ResponseData {
+            version: None,
+            responder_id,
+            produced_at: produced_at.into(),
+            responses: Asn1SequenceOf(responses),
+            response_extensions,
+        };
+
+        let tbs_der = picky_asn1_der::to_vec(&tbs_response_data).context(Asn1Serialization {
+            element: "tbs response data",
+        })?;
+        let signature = BitString::with_bytes(signature_hash_type.sign(&tbs_der, responder_key).context(Signature)?);
+
+        let certs = if embed_responder_cert {
+            Some(ApplicationTag0(Asn1SequenceOf(vec![Certificate::from(responder_cert)])))
+        } else {
+            None
+        };
+
+        Ok(BasicOcspResponse(BasicOCSPResponse {
+            tbs_response_data,
+            signature_algorithm: signature_hash_type.into(),
+            signature: signature.into(),
+            certs,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pem::parse_pem, x509::certificate::CertificateBuilder};
+
+    fn issuer() -> (Cert, PrivateKey) {
+        let pem = parse_pem(crate::test_files::RSA_2048_PK_1).unwrap();
+        let key = PrivateKey::from_pkcs8(pem.data()).unwrap();
+        let cert = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2020, 1, 1).unwrap(), UTCDate::ymd(2030, 1, 1).unwrap())
+            .self_signed(DirectoryName::new_common_name("Picky OCSP Issuer"), &key)
+            .ca(true)
+            .build()
+            .unwrap();
+        (cert, key)
+    }
+
+    #[test]
+    fn request_round_trip() {
+        let (issuer_cert, _) = issuer();
+        let cert_id = CertId::from_serial_number(&issuer_cert, vec![0x0A].into(), OcspHashAlgorithm::Sha1).unwrap();
+        assert!(cert_id.is_issued_by(&issuer_cert).unwrap());
+        assert_eq!(cert_id.issuer_name_hash().len(), 20);
+
+        let request = OcspRequest::new(vec![cert_id.clone()], Some(vec![0x42; 16])).unwrap();
+        let parsed = OcspRequest::from_der(&request.to_der().unwrap()).unwrap();
+        assert_eq!(parsed, request);
+        assert_eq!(parsed.cert_ids().collect::<Vec<_>>(), vec![&cert_id]);
+        assert_eq!(parsed.nonce(), Some(vec![0x42; 16]));
+        assert!(!parsed.is_signed());
+    }
+
+    #[test]
+    fn response_signed_by_issuer() {
+        let (issuer_cert, issuer_key) = issuer();
+        let good = CertId::from_serial_number(&issuer_cert, vec![0x0A].into(), OcspHashAlgorithm::Sha256).unwrap();
+        let revoked = CertId::from_serial_number(&issuer_cert, vec![0x0B].into(), OcspHashAlgorithm::Sha1).unwrap();
+
+        let basic = BasicOcspResponse::builder()
+            .responder(issuer_cert.clone(), &issuer_key)
+            .produced_at(UTCDate::ymd(2020, 6, 1).unwrap())
+            .nonce(vec![0x01, 0x02])
+            .response(
+                SingleResponse::new(good.clone(), CertStatus::Good, UTCDate::ymd(2020, 6, 1).unwrap())
+                    .with_next_update(UTCDate::ymd(2020, 6, 2).unwrap()),
+            )
+            .response(SingleResponse::new(
+                revoked.clone(),
+                CertStatus::Revoked {
+                    revocation_time: UTCDate::ymd(2020, 5, 20).unwrap(),
+                    reason: Some(CrlReason::KeyCompromise),
+                },
+                UTCDate::ymd(2020, 6, 1).unwrap(),
+            ))
+            .build()
+            .unwrap();
+
+        let response = OcspResponse::new_successful(&basic).unwrap();
+        let parsed = OcspResponse::from_der(&response.to_der().unwrap()).unwrap();
+        assert_eq!(parsed.status().unwrap(), OcspResponseStatus::Successful);
+
+        let parsed_basic = parsed.basic_response().unwrap();
+        assert_eq!(parsed_basic, basic);
+        assert_eq!(parsed_basic.nonce(), Some(vec![0x01, 0x02]));
+        assert_eq!(parsed_basic.responder_name(), Some(issuer_cert.subject_name()));
+        assert_eq!(parsed_basic.produced_at(), UTCDate::ymd(2020, 6, 1).unwrap());
+
+        let good_response = parsed_basic.find_response(&good).unwrap();
+        assert_eq!(good_response.cert_status(), &CertStatus::Good);
+        assert_eq!(good_response.next_update(), Some(UTCDate::ymd(2020, 6, 2).unwrap()));
+        assert_eq!(
+            parsed_basic.find_response(&revoked).unwrap().cert_status(),
+            &CertStatus::Revoked {
+                revocation_time: UTCDate::ymd(2020, 5, 20).unwrap(),
+                reason: Some(CrlReason::KeyCompromise),
+            }
+        );
+
+        parsed_basic
+            .verify_issuer(&issuer_cert, &UTCDate::ymd(2020, 6, 1).unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn delegated_responder() {
+        let (issuer_cert, issuer_key) = issuer();
+        let responder_key =
+            PrivateKey::from_pkcs8(parse_pem(crate::test_files::RSA_2048_PK_2).unwrap().data()).unwrap();
+        let responder_cert = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2020, 1, 1).unwrap(), UTCDate::ymd(2021, 1, 1).unwrap())
+            .subject(
+                DirectoryName::new_common_name("Picky OCSP Responder"),
+                responder_key.to_public_key(),
+            )
+            .issuer_cert(&issuer_cert, &issuer_key)
+            .extended_key_usage(vec![oids::kp_ocsp_signing()].into())
+            .build()
+            .unwrap();
+
+        let cert_id = CertId::from_serial_number(&issuer_cert, vec![0x0A].into(), OcspHashAlgorithm::Sha1).unwrap();
+        let build = |embed: bool| {
+            BasicOcspResponse::builder()
+                .responder(responder_cert.clone(), &responder_key)
+                .responder_by_key(true)
+                .embed_responder_cert(embed)
+                .produced_at(UTCDate::ymd(2020, 6, 1).unwrap())
+                .response(SingleResponse::new(
+                    cert_id.clone(),
+                    CertStatus::Unknown,
+                    UTCDate::ymd(2020, 6, 1).unwrap(),
+                ))
+                .build()
+                .unwrap()
+        };
+
+        let basic = build(true);
+        assert_eq!(basic.responder_name(), None);
+        assert_eq!(basic.certs(), vec![responder_cert.clone()]);
+        let now = UTCDate::ymd(2020, 6, 1).unwrap();
+        basic.verify_issuer(&issuer_cert, &now).unwrap();
+
+        match build(false).verify_issuer(&issuer_cert, &now) {
+            Err(OcspError::UnauthorizedResponder { .. }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // the responder certificate expired
+        match basic.verify_issuer(&issuer_cert, &UTCDate::ymd(2021, 6, 1).unwrap()) {
+            Err(OcspError::UnauthorizedResponder { .. }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn error_response() {
+        let response = OcspResponse::new_error(OcspResponseStatus::TryLater);
+        let der = response.to_der().unwrap();
+        assert_eq!(der, [0x30, 0x03, 0x0A, 0x01, 0x03]);
+
+        let parsed = OcspResponse::from_der(&der).unwrap();
+        assert_eq!(parsed.status().unwrap(), OcspResponseStatus::TryLater);
+        match parsed.basic_response() {
+            Err(OcspError::Unsuccessful {
+                status: OcspResponseStatus::TryLater,
+            }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,1089 @@
+//! PKCS#12 archives (.p12/.pfx) bundling a certificate, its chain and its private key.
+//!
+//! https://tools.ietf.org/html/rfc7292
+//!
+//! Archives are protected by a password, used both for the encryption of their contents and for
+//! their integrity MAC. Parsing supports the PBES2 (PBKDF2 with AES-CBC) scheme and the legacy
+//! PKCS#12 schemes (3DES, RC2) still produced by many tools. Only DER-encoded archives are supported.
+
+use crate::{
+    key::{KeyError, PrivateKey},
+    oids,
+    x509::{
+        certificate::{Cert, CertError},
+        private::pkcs12::{
+            decode_primitive, encode_primitive, AuthenticatedSafe, CertBag, ContentInfo, DigestInfo,
+            EncryptedContentInfo, EncryptedData, EncryptedPrivateKeyInfo, MacData, Pbes2Params, Pbkdf2Params, Pfx,
+            Pkcs12Attribute, Pkcs12PbeParams, RawAlgorithmIdentifier, SafeBag, SafeContents,
+        },
+    },
+    AlgorithmIdentifier,
+};
+use block_modes::{block_padding::Pkcs7, BlockMode, Cbc};
+use hmac::{Hmac, Mac, NewMac};
+use picky_asn1::{
+    tag::Tag,
+    wrapper::{ApplicationTag0, Asn1SequenceOf, Asn1SetOf, IntegerAsn1, ObjectIdentifierAsn1, OctetStringAsn1},
+};
+use picky_asn1_der::{Asn1DerError, Asn1RawDer};
+use serde::{de::DeserializeOwned, Serialize};
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+use snafu::{ResultExt, Snafu};
+use std::cell::RefCell;
+
+#[derive(Debug, Snafu)]
+pub enum Pkcs12Error {
+    /// asn1 serialization error
+    #[snafu(display("(asn1) couldn't serialize {}: {}", element, source))]
+    Asn1Serialization {
+        element: &'static str,
+        source: Asn1DerError,
+    },
+
+    /// asn1 deserialization error
+    #[snafu(display("(asn1) couldn't deserialize {}: {}", element, source))]
+    Asn1Deserialization {
+        element: &'static str,
+        source: Asn1DerError,
+    },
+
+    /// unsupported PFX version
+    #[snafu(display("unsupported PFX version: {}", version))]
+    UnsupportedVersion { version: u8 },
+
+    /// unsupported content type
+    #[snafu(display("unsupported content type: {}", oid))]
+    UnsupportedContentType { oid: String },
+
+    /// unsupported algorithm
+    #[snafu(display("unsupported algorithm: {}", oid))]
+    UnsupportedAlgorithm { oid: String },
+
+    /// invalid algorithm parameters
+    #[snafu(display("invalid parameters for {}", algorithm))]
+    InvalidParameters { algorithm: &'static str },
+
+    /// MAC verification failed
+    #[snafu(display("MAC verification failed (wrong password?)"))]
+    InvalidMac,
+
+    /// decryption failed
+    #[snafu(display("couldn't decrypt {} (wrong password?)", element))]
+    Decryption { element: &'static str },
+
+    /// invalid private key
+    #[snafu(display("invalid private key: {}", source))]
+    InvalidPrivateKey { source: KeyError },
+
+    /// invalid certificate
+    #[snafu(display("invalid certificate: {}", source))]
+    InvalidCertificate { source: CertError },
+
+    /// private key doesn't match the certificate
+    #[snafu(display("private key doesn't match the certificate"))]
+    KeyMismatch,
+
+    /// missing required builder argument
+    #[snafu(display("missing required builder argument `{}`", arg))]
+    MissingBuilderArgument { arg: &'static str },
+}
+
+/// Iteration count of the key derivations, unless set with the builder
+pub const DEFAULT_ITERATIONS: u32 = 2048;
+
+const SALT_LEN: usize = 16;
+
+/// Encryption of the archives generated by `Pkcs12::to_der`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pkcs12Encryption {
+    /// PBES2 with PBKDF2-HMAC-SHA256 and AES-256-CBC, HMAC-SHA256 integrity
+    Aes256Cbc,
+    /// pbeWithSHAAnd3-KeyTripleDES-CBC, HMAC-SHA1 integrity
+    ///
+    /// For compatibility with tools predating PBES2 support (e.g. Windows before Server 2019).
+    TripleDesCbc,
+}
+
+impl Default for Pkcs12Encryption {
+    fn default() -> Self {
+        Pkcs12Encryption::Aes256Cbc
+    }
+}
+
+/// Decrypted contents of a PKCS#12 archive
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pkcs12 {
+    private_key: Option<PrivateKey>,
+    cert: Option<Cert>,
+    chain: Vec<Cert>,
+    friendly_name: Option<String>,
+    encryption: Pkcs12Encryption,
+    iterations: u32,
+}
+
+impl Pkcs12 {
+    pub fn builder() -> Pkcs12Builder {
+        Pkcs12Builder::default()
+    }
+
+    /// Parses and decrypts an archive.
+    ///
+    /// The certificate is the one associated to the private key (using their local key ID, or
+    /// their public key), all the other certificates form the chain.
+    pub fn from_der<T: ?Sized + AsRef<[u8]>>(der: &T, password: &str) -> Result<Self, Pkcs12Error> {
+        let pfx: Pfx = from_der(der.as_ref(), "pfx")?;
+        if pfx.version != 3 {
+            return Err(Pkcs12Error::UnsupportedVersion { version: pfx.version });
+        }
+
+        let auth_safe_der = data_content(&pfx.auth_safe)?;
+        if let Some(mac_data) = &pfx.mac_data {
+            verify_mac(mac_data, password, auth_safe_der)?;
+        }
+
+        let auth_safe: AuthenticatedSafe = from_der(auth_safe_der, "authenticated safe")?;
+        let mut bags = Vec::new();
+        for content_info in &auth_safe.0 {
+            let safe_contents_der = if content_info.content_type.0 == oids::pkcs7_data() {
+                data_content(content_info)?.to_vec()
+            } else if content_info.content_type.0 == oids::pkcs7_encrypted_data() {
+                let encrypted_data: EncryptedData = from_der(&(content_info.content.0).0, "encrypted data")?;
+                decrypt_content(&encrypted_data.encrypted_content_info, password)?
+            } else {
+                return Err(Pkcs12Error::UnsupportedContentType {
+                    oid: oid_string(&content_info.content_type),
+                });
+            };
+
+            let safe_contents: SafeContents = from_der(&safe_contents_der, "safe contents")?;
+            collect_bags(safe_contents, password, &mut bags)?;
+        }
+
+        Self::from_bags(bags)
+    }
+
+    /// Encodes and encrypts the archive.
+    pub fn to_der(&self, password: &str) -> Result<Vec<u8>, Pkcs12Error> {
+        let local_key_id = match &self.cert {
+            Some(cert) => Some(Sha1::digest(&cert.to_der().context(InvalidCertificate)?).to_vec()),
+            None => None,
+        };
+
+        let mut cert_bags = Vec::with_capacity(self.chain.len() + 1);
+        if let Some(cert) = &self.cert {
+            cert_bags.push(cert_bag(cert, local_key_id.as_deref(), self.friendly_name.as_deref())?);
+        }
+        for cert in &self.chain {
+            cert_bags.push(cert_bag(cert, None, None)?);
+        }
+
+        let mut content_infos = Vec::with_capacity(2);
+
+        // certificates are encrypted as a whole...
+        let cert_contents_der = to_der(&Asn1SequenceOf(cert_bags), "safe contents")?;
+        let (algorithm, encrypted_content) = encrypt(self.encryption, self.iterations, password, &cert_contents_der)?;
+        let encrypted_data = EncryptedData {
+            version: 0,
+            encrypted_content_info: EncryptedContentInfo {
+                content_type: oids::pkcs7_data().into(),
+                content_encryption_algorithm: algorithm,
+                encrypted_content: Some(encode_primitive(Tag::CTX_0, &encrypted_content)),
+            },
+        };
+        content_infos.push(ContentInfo {
+            content_type: oids::pkcs7_encrypted_data().into(),
+            content: ApplicationTag0(Asn1RawDer(to_der(&encrypted_data, "encrypted data")?)),
+        });
+
+        // ...while the private key is shrouded in its own bag
+        if let Some(private_key) = &self.private_key {
+            let pkcs8 = private_key.to_pkcs8().context(InvalidPrivateKey)?;
+            let (encryption_algorithm, encrypted_key) = encrypt(self.encryption, self.iterations, password, &pkcs8)?;
+            let shrouded_key = EncryptedPrivateKeyInfo {
+                encryption_algorithm,
+                encrypted_data: OctetStringAsn1(encrypted_key),
+            };
+            let key_bag = SafeBag {
+                bag_id: oids::pkcs8_shrouded_key_bag().into(),
+                bag_value: ApplicationTag0(Asn1RawDer(to_der(&shrouded_key, "shrouded key bag")?)),
+                bag_attributes: bag_attributes(local_key_id.as_deref(), self.friendly_name.as_deref())?,
+            };
+            let key_contents_der = to_der(&Asn1SequenceOf(vec![key_bag]), "safe contents")?;
+            content_infos.push(data_content_info(&key_contents_der)?);
+        }
+
+        let auth_safe_der = to_der(&Asn1SequenceOf(content_infos), "authenticated safe")?;
+        let mac_data = compute_mac_data(self.encryption, self.iterations, password, &auth_safe_der);
+
+        to_der(
+            &Pfx {
+                version: 3,
+                auth_safe: data_content_info(&auth_safe_der)?,
+                mac_data: Some(mac_data),
+            },
+            "pfx",
+        )
+    }
+
+    pub fn private_key(&self) -> Option<&PrivateKey> {
+        self.private_key.as_ref()
+    }
+
+    pub fn cert(&self) -> Option<&Cert> {
+        self.cert.as_ref()
+    }
+
+    /// Certificates other than `cert`, e.g. the issuing and root CAs.
+    pub fn chain(&self) -> &[Cert] {
+        &self.chain
+    }
+
+    pub fn friendly_name(&self) -> Option<&str> {
+        self.friendly_name.as_deref()
+    }
+
+    fn from_bags(bags: Vec<Bag>) -> Result<Self, Pkcs12Error> {
+        let mut private_key = None;
+        let mut key_id = None;
+        let mut key_friendly_name = None;
+        let mut certs = Vec::new();
+
+        for bag in bags {
+            match bag.content {
+                BagContent::PrivateKey(key) => {
+                    // only the first key is kept, archives bundle a single one in practice
+                    if private_key.is_none() {
+                        private_key = Some(key);
+                        key_id = bag.local_key_id;
+                        key_friendly_name = bag.friendly_name;
+                    }
+                }
+                BagContent::Cert(cert) => certs.push((cert, bag.local_key_id, bag.friendly_name)),
+            }
+        }
+
+        let cert_position = match &private_key {
+            Some(private_key) => certs
+                .iter()
+                .position(|(_, id, _)| key_id.is_some() && *id == key_id)
+                .or_else(|| {
+                    let public_key = private_key.to_public_key();
+                    certs.iter().position(|(cert, _, _)| *cert.public_key() == public_key)
+                }),
+            None if certs.is_empty() => None,
+            None => Some(0),
+        };
+
+        let (cert, friendly_name) = match cert_position {
+            Some(position) => {
+                let (cert, _, cert_friendly_name) = certs.remove(position);
+                (Some(cert), key_friendly_name.or(cert_friendly_name))
+            }
+            None => (None, key_friendly_name),
+        };
+
+        Ok(Self {
+            private_key,
+            cert,
+            chain: certs.into_iter().map(|(cert, _, _)| cert).collect(),
+            friendly_name,
+            encryption: Pkcs12Encryption::default(),
+            iterations: DEFAULT_ITERATIONS,
+        })
+    }
+}
+
+// === builder === //
+
+// Statically checks the field actually exists and returns a &'static str of the field name
+macro_rules! field_str {
+    ($field:ident) => {{
+        ::static_assertions::assert_fields!(Pkcs12BuilderInner: $field);
+        stringify!($field)
+    }};
+}
+
+#[derive(Default, Clone, Debug)]
+struct Pkcs12BuilderInner {
+    cert: Option<Cert>,
+    private_key: Option<PrivateKey>,
+    chain: Vec<Cert>,
+    friendly_name: Option<String>,
+    encryption: Option<Pkcs12Encryption>,
+    iterations: Option<u32>,
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct Pkcs12Builder {
+    inner: RefCell<Pkcs12BuilderInner>,
+}
+
+impl Pkcs12Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Required
+    #[inline]
+    pub fn cert(&self, cert: Cert) -> &Self {
+        self.inner.borrow_mut().cert = Some(cert);
+        self
+    }
+
+    /// Optional, must match `cert`
+    #[inline]
+    pub fn private_key(&self, private_key: PrivateKey) -> &Self {
+        self.inner.borrow_mut().private_key = Some(private_key);
+        self
+    }
+
+    /// Optional
+    #[inline]
+    pub fn chain_cert(&self, cert: Cert) -> &Self {
+        self.inner.borrow_mut().chain.push(cert);
+        self
+    }
+
+    /// Optional
+    #[inline]
+    pub fn chain(&self, chain: Vec<Cert>) -> &Self {
+        self.inner.borrow_mut().chain.extend(chain);
+        self
+    }
+
+    /// Optional, name displayed by certificate stores
+    #[inline]
+    pub fn friendly_name<S: Into<String>>(&self, friendly_name: S) -> &Self {
+        self.inner.borrow_mut().friendly_name = Some(friendly_name.into());
+        self
+    }
+
+    /// Optional
+    #[inline]
+    pub fn encryption(&self, encryption: Pkcs12Encryption) -> &Self {
+        self.inner.borrow_mut().encryption = Some(encryption);
+        self
+    }
+
+    /// Optional
+    #[inline]
+    pub fn iterations(&self, iterations: u32) -> &Self {
+        self.inner.borrow_mut().iterations = Some(iterations);
+        self
+    }
+
+    pub fn build(&self) -> Result<Pkcs12, Pkcs12Error> {
+        let mut inner = self.inner.borrow_mut();
+
+        let cert = inner
+            .cert
+            .take()
+            .ok_or(Pkcs12Error::MissingBuilderArgument { arg: field_str!(cert) })?;
+        let private_key = inner.private_key.take();
+        let chain = std::mem::take(&mut inner.chain);
+        let friendly_name = inner.friendly_name.take();
+        let encryption = inner.encryption.take().unwrap_or_default();
+        let iterations = inner.iterations.take().unwrap_or(DEFAULT_ITERATIONS).max(1);
+
+        drop(inner);
+
+        if let Some(private_key) = &private_key {
+            if *cert.public_key() != private_key.to_public_key() {
+                return Err(Pkcs12Error::KeyMismatch);
+            }
+        }
+
+        Ok(Pkcs12 {
+            private_key,
+            cert: Some(cert),
+            chain,
+            friendly_name,
+            encryption,
+            iterations,
+        })
+    }
+}
+
+// === safe bags === //
+
+enum BagContent {
+    PrivateKey(PrivateKey),
+    Cert(Cert),
+}
+
+struct Bag {
+    content: BagContent,
+    local_key_id: Option<Vec<u8>>,
+    friendly_name: Option<String>,
+}
+
+fn collect_bags(safe_contents: SafeContents, password: &str, bags: &mut Vec<Bag>) -> Result<(), Pkcs12Error> {
+    for safe_bag in safe_contents.0 {
+        let bag_id = &safe_bag.bag_id.0;
+        let bag_value = &(safe_bag.bag_value.0).0;
+
+        let content = if *bag_id == oids::key_bag() {
+            BagContent::PrivateKey(PrivateKey::from_pkcs8(bag_value).context(InvalidPrivateKey)?)
+        } else if *bag_id == oids::pkcs8_shrouded_key_bag() {
+            let shrouded_key: EncryptedPrivateKeyInfo = from_der(bag_value, "shrouded key bag")?;
+            let pkcs8 = decrypt(
+                &shrouded_key.encryption_algorithm,
+                password,
+                &shrouded_key.encrypted_data.0,
+                "shrouded key bag",
+            )?;
+            BagContent::PrivateKey(PrivateKey::from_pkcs8(&pkcs8).context(InvalidPrivateKey)?)
+        } else if *bag_id == oids::cert_bag() {
+            let cert_bag: CertBag = from_der(bag_value, "cert bag")?;
+            if cert_bag.cert_id.0 != oids::x509_certificate() {
+                // e.g. SDSI certificates
+                continue;
+            }
+            BagContent::Cert(Cert::from_der(&(cert_bag.cert_value.0).0).context(InvalidCertificate)?)
+        } else if *bag_id == oids::safe_contents_bag() {
+            let nested: SafeContents = from_der(bag_value, "safe contents bag")?;
+            collect_bags(nested, password, bags)?;
+            continue;
+        } else {
+            // CRL and secret bags aren't supported
+            continue;
+        };
+
+        let mut local_key_id = None;
+        let mut friendly_name = None;
+        for attribute in safe_bag
+            .bag_attributes
+            .iter()
+            .flat_map(|attributes| attributes.0.iter())
+        {
+            let value = match attribute.attr_values.0.first() {
+                Some(value) => value,
+                None => continue,
+            };
+
+            if attribute.attr_id.0 == oids::local_key_id() {
+                local_key_id = decode_primitive(Tag::OCTET_STRING, value).map(<[u8]>::to_vec);
+            } else if attribute.attr_id.0 == oids::friendly_name() {
+                friendly_name = decode_primitive(Tag::from(BMP_STRING_TAG), value).and_then(decode_bmp_string);
+            }
+        }
+
+        bags.push(Bag {
+            content,
+            local_key_id,
+            friendly_name,
+        });
+    }
+
+    Ok(())
+}
+
+fn cert_bag(cert: &Cert, local_key_id: Option<&[u8]>, friendly_name: Option<&str>) -> Result<SafeBag, Pkcs12Error> {
+    let cert_bag = CertBag {
+        cert_id: oids::x509_certificate().into(),
+        cert_value: ApplicationTag0(OctetStringAsn1(cert.to_der().context(InvalidCertificate)?)),
+    };
+
+    Ok(SafeBag {
+        bag_id: oids::cert_bag().into(),
+        bag_value: ApplicationTag0(Asn1RawDer(to_der(&cert_bag, "cert bag")?)),
+        bag_attributes: bag_attributes(local_key_id, friendly_name)?,
+    })
+}
+
+fn bag_attributes(
+    local_key_id: Option<&[u8]>,
+    friendly_name: Option<&str>,
+) -> Result<Option<Asn1SetOf<Pkcs12Attribute>>, Pkcs12Error> {
+    let mut attributes = Vec::new();
+
+    if let Some(friendly_name) = friendly_name {
+        attributes.push(Pkcs12Attribute {
+            attr_id: oids::friendly_name().into(),
+            attr_values: Asn1SetOf(vec![encode_primitive(
+                Tag::from(BMP_STRING_TAG),
+                &encode_bmp_string(friendly_name, false),
+            )]),
+        });
+    }
+
+    if let Some(local_key_id) = local_key_id {
+        attributes.push(Pkcs12Attribute {
+            attr_id: oids::local_key_id().into(),
+            attr_values: Asn1SetOf(vec![Asn1RawDer(to_der(
+                &OctetStringAsn1(local_key_id.to_vec()),
+                "local key id",
+            )?)]),
+        });
+    }
+
+    Ok(if attributes.is_empty() {
+        None
+    } else {
+        Some(Asn1SetOf(attributes))
+    })
+}
+
+fn data_content(content_info: &ContentInfo) -> Result<&[u8], Pkcs12Error> {
+    if content_info.content_type.0 != oids::pkcs7_data() {
+        return Err(Pkcs12Error::UnsupportedContentType {
+            oid: oid_string(&content_info.content_type),
+        });
+    }
+
+    decode_primitive(Tag::OCTET_STRING, &content_info.content.0).ok_or(Pkcs12Error::InvalidParameters {
+        algorithm: "data content",
+    })
+}
+
+fn data_content_info(data: &[u8]) -> Result<ContentInfo, Pkcs12Error> {
+    Ok(ContentInfo {
+        content_type: oids::pkcs7_data().into(),
+        content: ApplicationTag0(Asn1RawDer(to_der(&OctetStringAsn1(data.to_vec()), "data")?)),
+    })
+}
+
+// === integrity === //
+
+fn verify_mac(mac_data: &MacData, password: &str, data: &[u8]) -> Result<(), Pkcs12Error> {
+    let algorithm = &mac_data.mac.digest_algorithm;
+    let hash = if algorithm.is_a(oids::sha1()) {
+        KdfHash::Sha1
+    } else if algorithm.is_a(oids::sha256()) {
+        KdfHash::Sha256
+    } else {
+        return Err(Pkcs12Error::UnsupportedAlgorithm {
+            oid: algorithm.oid().into(),
+        });
+    };
+    let iterations = match &mac_data.iterations {
+        Some(iterations) => integer_to_u32(iterations, "mac data")?,
+        None => 1,
+    };
+
+    let key = pkcs12_kdf(
+        hash,
+        &encode_bmp_string(password, true),
+        &mac_data.mac_salt.0,
+        KDF_ID_MAC,
+        iterations,
+        hash.output_len(),
+    );
+
+    let verified = match hash {
+        KdfHash::Sha1 => hmac_sha1(&key, data).verify(&mac_data.mac.digest.0).is_ok(),
+        KdfHash::Sha256 => hmac_sha256(&key, data).verify(&mac_data.mac.digest.0).is_ok(),
+    };
+
+    if verified {
+        Ok(())
+    } else {
+        Err(Pkcs12Error::InvalidMac)
+    }
+}
+
+fn compute_mac_data(encryption: Pkcs12Encryption, iterations: u32, password: &str, data: &[u8]) -> MacData {
+    let (hash, digest_algorithm) = match encryption {
+        Pkcs12Encryption::Aes256Cbc => (KdfHash::Sha256, AlgorithmIdentifier::new_sha256()),
+        Pkcs12Encryption::TripleDesCbc => (KdfHash::Sha1, AlgorithmIdentifier::new_sha1()),
+    };
+    let salt = rand::random::<[u8; SALT_LEN]>().to_vec();
+
+    let key = pkcs12_kdf(
+        hash,
+        &encode_bmp_string(password, true),
+        &salt,
+        KDF_ID_MAC,
+        iterations,
+        hash.output_len(),
+    );
+    let digest = match hash {
+        KdfHash::Sha1 => hmac_sha1(&key, data).finalize().into_bytes().to_vec(),
+        KdfHash::Sha256 => hmac_sha256(&key, data).finalize().into_bytes().to_vec(),
+    };
+
+    MacData {
+        mac: DigestInfo {
+            digest_algorithm,
+            digest: OctetStringAsn1(digest),
+        },
+        mac_salt: OctetStringAsn1(salt),
+        iterations: Some(IntegerAsn1::from_unsigned_bytes_be(iterations.to_be_bytes().to_vec())),
+    }
+}
+
+fn hmac_sha1(key: &[u8], data: &[u8]) -> Hmac<Sha1> {
+    let mut mac = Hmac::<Sha1>::new_varkey(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_varkey(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac
+}
+
+// === encryption === //
+
+type TdesCbc = Cbc<des::TdesEde3, Pkcs7>;
+type Rc2Cbc = Cbc<rc2::Rc2, Pkcs7>;
+type Aes128Cbc = Cbc<aes::Aes128, Pkcs7>;
+type Aes192Cbc = Cbc<aes::Aes192, Pkcs7>;
+type Aes256Cbc = Cbc<aes::Aes256, Pkcs7>;
+
+fn decrypt_content(content_info: &EncryptedContentInfo, password: &str) -> Result<Vec<u8>, Pkcs12Error> {
+    let encrypted_content = content_info
+        .encrypted_content
+        .as_ref()
+        .and_then(|raw| decode_primitive(Tag::CTX_0, raw))
+        .ok_or(Pkcs12Error::InvalidParameters {
+            algorithm: "encrypted content",
+        })?;
+
+    decrypt(
+        &content_info.content_encryption_algorithm,
+        password,
+        encrypted_content,
+        "encrypted content",
+    )
+}
+
+fn decrypt(
+    algorithm: &RawAlgorithmIdentifier,
+    password: &str,
+    data: &[u8],
+    element: &'static str,
+) -> Result<Vec<u8>, Pkcs12Error> {
+    let oid = &algorithm.algorithm.0;
+
+    let decrypted = if *oid == oids::pbes2() {
+        let params: Pbes2Params = algorithm_parameters(algorithm, "PBES2")?;
+        let (cipher, iv) = aes_cipher(&params.encryption_scheme)?;
+        let key = pbkdf2_key(&params.key_derivation_func, password, cipher.key_len())?;
+        match cipher {
+            AesCipher::Aes128 => Aes128Cbc::new_var(&key, iv).map(|mode| mode.decrypt_vec(data)),
+            AesCipher::Aes192 => Aes192Cbc::new_var(&key, iv).map(|mode| mode.decrypt_vec(data)),
+            AesCipher::Aes256 => Aes256Cbc::new_var(&key, iv).map(|mode| mode.decrypt_vec(data)),
+        }
+    } else {
+        let key_len = if *oid == oids::pbe_with_sha_and_3_key_triple_des_cbc() {
+            24
+        } else if *oid == oids::pbe_with_sha_and_128_bit_rc2_cbc() {
+            16
+        } else if *oid == oids::pbe_with_sha_and_40_bit_rc2_cbc() {
+            5
+        } else {
+            return Err(Pkcs12Error::UnsupportedAlgorithm {
+                oid: oid_string(&algorithm.algorithm),
+            });
+        };
+
+        let params: Pkcs12PbeParams = algorithm_parameters(algorithm, "PKCS#12 PBE")?;
+        let iterations = integer_to_u32(&params.iterations, "PKCS#12 PBE")?;
+        let password = encode_bmp_string(password, true);
+        let key = pkcs12_kdf(
+            KdfHash::Sha1,
+            &password,
+            &params.salt.0,
+            KDF_ID_KEY,
+            iterations,
+            key_len,
+        );
+        let iv = pkcs12_kdf(KdfHash::Sha1, &password, &params.salt.0, KDF_ID_IV, iterations, 8);
+
+        if key_len == 24 {
+            TdesCbc::new_var(&key, &iv).map(|mode| mode.decrypt_vec(data))
+        } else {
+            Rc2Cbc::new_var(&key, &iv).map(|mode| mode.decrypt_vec(data))
+        }
+    };
+
+    decrypted
+        .ok()
+        .and_then(Result::ok)
+        .ok_or(Pkcs12Error::Decryption { element })
+}
+
+/// Returns the algorithm identifier and the encrypted data.
+fn encrypt(
+    encryption: Pkcs12Encryption,
+    iterations: u32,
+    password: &str,
+    data: &[u8],
+) -> Result<(RawAlgorithmIdentifier, Vec<u8>), Pkcs12Error> {
+    let salt = rand::random::<[u8; SALT_LEN]>().to_vec();
+    let iterations_asn1 = IntegerAsn1::from_unsigned_bytes_be(iterations.to_be_bytes().to_vec());
+
+    match encryption {
+        Pkcs12Encryption::Aes256Cbc => {
+            let iv = rand::random::<[u8; 16]>();
+            let mut key = vec![0; AesCipher::Aes256.key_len()];
+            pbkdf2::pbkdf2::<Hmac<Sha256>>(password.as_bytes(), &salt, iterations, &mut key);
+            let encrypted = Aes256Cbc::new_var(&key, &iv)
+                .expect("valid AES-256 key and IV lengths")
+                .encrypt_vec(data);
+
+            let params = Pbes2Params {
+                key_derivation_func: RawAlgorithmIdentifier {
+                    algorithm: oids::pbkdf2().into(),
+                    parameters: Some(Asn1RawDer(to_der(
+                        &Pbkdf2Params {
+                            salt: OctetStringAsn1(salt),
+                            iteration_count: iterations_asn1,
+                            key_length: None,
+                            prf: Some(RawAlgorithmIdentifier {
+                                algorithm: oids::hmac_with_sha256().into(),
+                                parameters: Some(Asn1RawDer(vec![0x05, 0x00])),
+                            }),
+                        },
+                        "PBKDF2 parameters",
+                    )?)),
+                },
+                encryption_scheme: RawAlgorithmIdentifier {
+                    algorithm: oids::aes256_cbc().into(),
+                    parameters: Some(encode_primitive(Tag::OCTET_STRING, &iv)),
+                },
+            };
+
+            Ok((
+                RawAlgorithmIdentifier {
+                    algorithm: oids::pbes2().into(),
+                    parameters: Some(Asn1RawDer(to_der(&params, "PBES2 parameters")?)),
+                },
+                encrypted,
+            ))
+        }
+        Pkcs12Encryption::TripleDesCbc => {
+            let password = encode_bmp_string(password, true);
+            let key = pkcs12_kdf(KdfHash::Sha1, &password, &salt, KDF_ID_KEY, iterations, 24);
+            let iv = pkcs12_kdf(KdfHash::Sha1, &password, &salt, KDF_ID_IV, iterations, 8);
+            let encrypted = TdesCbc::new_var(&key, &iv)
+                .expect("valid 3DES key and IV lengths")
+                .encrypt_vec(data);
+
+            let params = Pkcs12PbeParams {
+                salt: OctetStringAsn1(salt),
+                iterations: iterations_asn1,
+            };
+
+            Ok((
+                RawAlgorithmIdentifier {
+                    algorithm: oids::pbe_with_sha_and_3_key_triple_des_cbc().into(),
+                    parameters: Some(Asn1RawDer(to_der(&params, "PKCS#12 PBE parameters")?)),
+                },
+                encrypted,
+            ))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AesCipher {
+    Aes128,
+    Aes192,
+    Aes256,
+}
+
+impl AesCipher {
+    fn key_len(self) -> usize {
+        match self {
+            AesCipher::Aes128 => 16,
+            AesCipher::Aes192 => 24,
+            AesCipher::Aes256 => 32,
+        }
+    }
+}
+
+/// Returns the cipher of a PBES2 encryption scheme along with its IV.
+fn aes_cipher(scheme: &RawAlgorithmIdentifier) -> Result<(AesCipher, &[u8]), Pkcs12Error> {
+    let oid = &scheme.algorithm.0;
+    let cipher = if *oid == oids::aes128_cbc() {
+        AesCipher::Aes128
+    } else if *oid == oids::aes192_cbc() {
+        AesCipher::Aes192
+    } else if *oid == oids::aes256_cbc() {
+        AesCipher::Aes256
+    } else {
+        return Err(Pkcs12Error::UnsupportedAlgorithm {
+            oid: oid_string(&scheme.algorithm),
+        });
+    };
+
+    let iv = scheme
+        .parameters
+        .as_ref()
+        .and_then(|parameters| decode_primitive(Tag::OCTET_STRING, parameters))
+        .ok_or(Pkcs12Error::InvalidParameters { algorithm: "AES-CBC" })?;
+
+    Ok((cipher, iv))
+}
+
+fn pbkdf2_key(kdf: &RawAlgorithmIdentifier, password: &str, key_len: usize) -> Result<Vec<u8>, Pkcs12Error> {
+    if kdf.algorithm.0 != oids::pbkdf2() {
+        return Err(Pkcs12Error::UnsupportedAlgorithm {
+            oid: oid_string(&kdf.algorithm),
+        });
+    }
+
+    let params: Pbkdf2Params = algorithm_parameters(kdf, "PBKDF2")?;
+    let iterations = integer_to_u32(&params.iteration_count, "PBKDF2")?;
+    if let Some(key_length) = &params.key_length {
+        if integer_to_u32(key_length, "PBKDF2")? as usize != key_len {
+            return Err(Pkcs12Error::InvalidParameters { algorithm: "PBKDF2" });
+        }
+    }
+
+    let mut key = vec![0; key_len];
+    match params.prf.as_ref().map(|prf| &prf.algorithm) {
+        None => pbkdf2::pbkdf2::<Hmac<Sha1>>(password.as_bytes(), &params.salt.0, iterations, &mut key),
+        Some(prf) if prf.0 == oids::hmac_with_sha1() => {
+            pbkdf2::pbkdf2::<Hmac<Sha1>>(password.as_bytes(), &params.salt.0, iterations, &mut key)
+        }
+        Some(prf) if prf.0 == oids::hmac_with_sha256() => {
+            pbkdf2::pbkdf2::<Hmac<Sha256>>(password.as_bytes(), &params.salt.0, iterations, &mut key)
+        }
+        Some(prf) => return Err(Pkcs12Error::UnsupportedAlgorithm { oid: oid_string(prf) }),
+    }
+
+    Ok(key)
+}
+
+// === key derivation === //
+
+const KDF_ID_KEY: u8 = 1;
+const KDF_ID_IV: u8 = 2;
+const KDF_ID_MAC: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KdfHash {
+    Sha1,
+    Sha256,
+}
+
+impl KdfHash {
+    fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            KdfHash::Sha1 => Sha1::digest(data).to_vec(),
+            KdfHash::Sha256 => Sha256::digest(data).to_vec(),
+        }
+    }
+
+    fn output_len(self) -> usize {
+        match self {
+            KdfHash::Sha1 => 20,
+            KdfHash::Sha256 => 32,
+        }
+    }
+}
+
+/// Key derivation of the PKCS#12 password-based schemes.
+///
+/// https://tools.ietf.org/html/rfc7292#appendix-B.2
+fn pkcs12_kdf(hash: KdfHash, password: &[u8], salt: &[u8], id: u8, iterations: u32, len: usize) -> Vec<u8> {
+    // block size of both SHA-1 and SHA-256
+    const V: usize = 64;
+
+    fn fill(data: &[u8]) -> Vec<u8> {
+        let len = V * ((data.len() + V - 1) / V);
+        data.iter().cycle().take(len).copied().collect()
+    }
+
+    let mut input = fill(salt);
+    input.extend(fill(password));
+
+    let mut output = Vec::with_capacity(len + hash.output_len());
+    while output.len() < len {
+        let mut block = vec![id; V];
+        block.extend_from_slice(&input);
+        let mut a = hash.digest(&block);
+        for _ in 1..iterations {
+            a = hash.digest(&a);
+        }
+
+        // I_j = (I_j + B + 1) mod 2^(8 * V)
+        let b = a.iter().cycle().take(V).copied().collect::<Vec<u8>>();
+        for chunk in input.chunks_mut(V) {
+            let mut carry = 1u16;
+            for (byte, b_byte) in chunk.iter_mut().zip(b.iter()).rev() {
+                let sum = u16::from(*byte) + u16::from(*b_byte) + carry;
+                *byte = sum as u8;
+                carry = sum >> 8;
+            }
+        }
+
+        output.extend(a);
+    }
+
+    output.truncate(len);
+    output
+}
+
+// === encoding helpers === //
+
+const BMP_STRING_TAG: u8 = 0x1E;
+
+/// UTF-16BE encoding, NUL-terminated when used as a PKCS#12 password.
+fn encode_bmp_string(s: &str, nul_terminated: bool) -> Vec<u8> {
+    let mut encoded = s
+        .encode_utf16()
+        .flat_map(|c| c.to_be_bytes().to_vec())
+        .collect::<Vec<u8>>();
+    if nul_terminated {
+        encoded.extend_from_slice(&[0x00, 0x00]);
+    }
+    encoded
+}
+
+fn decode_bmp_string(encoded: &[u8]) -> Option<String> {
+    if encoded.len() % 2 != 0 {
+        return None;
+    }
+
+    let utf16 = encoded
+        .chunks(2)
+        .map(|c| u16::from_be_bytes([c[0], c[1]]))
+        .collect::<Vec<u16>>();
+    String::from_utf16(&utf16).ok()
+}
+
+fn algorithm_parameters<T: DeserializeOwned>(
+    algorithm: &RawAlgorithmIdentifier,
+    name: &'static str,
+) -> Result<T, Pkcs12Error> {
+    algorithm
+        .parameters
+        .as_ref()
+        .and_then(|parameters| picky_asn1_der::from_bytes(&parameters.0).ok())
+        .ok_or(Pkcs12Error::InvalidParameters { algorithm: name })
+}
+
+fn integer_to_u32(integer: &IntegerAsn1, name: &'static str) -> Result<u32, Pkcs12Error> {
+    let bytes = integer.as_unsigned_bytes_be();
+    if integer.is_negative() || bytes.len() > 4 {
+        return Err(Pkcs12Error::InvalidParameters { algorithm: name });
+    }
+
+    Ok(bytes.iter().fold(0, |value, byte| (value << 8) | u32::from(*byte)))
+}
+
+fn oid_string(oid: &ObjectIdentifierAsn1) -> String {
+    String::from(&oid.0)
+}
+
+fn from_der<T: DeserializeOwned>(der: &[u8], element: &'static str) -> Result<T, Pkcs12Error> {
+    picky_asn1_der::from_bytes(der).context(Asn1Deserialization { element })
+}
+
+fn to_der<T: Serialize>(value: &T, element: &'static str) -> Result<Vec<u8>, Pkcs12Error> {
+    picky_asn1_der::to_vec(value).context(Asn1Serialization { element })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pem::parse_pem,
+        x509::{certificate::CertificateBuilder, date::UTCDate, name::DirectoryName},
+    };
+
+    fn key(pem: &str) -> PrivateKey {
+        PrivateKey::from_pkcs8(parse_pem(pem).unwrap().data()).unwrap()
+    }
+
+    fn certs() -> (Cert, PrivateKey, Cert) {
+        let root_key = key(crate::test_files::RSA_2048_PK_1);
+        let root = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2020, 1, 1).unwrap(), UTCDate::ymd(2030, 1, 1).unwrap())
+            .self_signed(DirectoryName::new_common_name("Picky PKCS12 Root"), &root_key)
+            .ca(true)
+            .build()
+            .unwrap();
+
+        let leaf_key = key(crate::test_files::RSA_2048_PK_2);
+        let leaf = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2020, 1, 1).unwrap(), UTCDate::ymd(2021, 1, 1).unwrap())
+            .subject(
+                DirectoryName::new_common_name("Picky PKCS12 Leaf"),
+                leaf_key.to_public_key(),
+            )
+            .issuer_cert(&root, &root_key)
+            .build()
+            .unwrap();
+
+        (leaf, leaf_key, root)
+    }
+
+    #[test]
+    fn kdf_test_vectors() {
+        // from the test vectors used by OpenSSL and Bouncy Castle
+        let password = encode_bmp_string("smeg", true);
+        let salt = [0x0A, 0x58, 0xCF, 0x64, 0x53, 0x0D, 0x82, 0x3F];
+        assert_eq!(
+            pkcs12_kdf(KdfHash::Sha1, &password, &salt, KDF_ID_KEY, 1, 24),
+            vec![
+                0x8A, 0xAA, 0xE6, 0x29, 0x7B, 0x6C, 0xB0, 0x46, 0x42, 0xAB, 0x5B, 0x07, 0x78, 0x51, 0x28, 0x4E, 0xB7,
+                0x12, 0x8F, 0x1A, 0x2A, 0x7F, 0xBC, 0xA3,
+            ]
+        );
+        assert_eq!(
+            pkcs12_kdf(KdfHash::Sha1, &password, &salt, KDF_ID_IV, 1, 8),
+            vec![0x79, 0x99, 0x3D, 0xFE, 0x04, 0x8D, 0x3B, 0x76]
+        );
+    }
+
+    #[test]
+    fn bmp_strings() {
+        assert_eq!(encode_bmp_string("pé", false), vec![0x00, 0x70, 0x00, 0xE9]);
+        assert_eq!(encode_bmp_string("", true), vec![0x00, 0x00]);
+        assert_eq!(decode_bmp_string(&[0x00, 0x70, 0x00, 0xE9]), Some("pé".to_owned()));
+        assert_eq!(decode_bmp_string(&[0x00]), None);
+    }
+
+    #[test]
+    fn round_trip() {
+        let (leaf, leaf_key, root) = certs();
+
+        for encryption in &[Pkcs12Encryption::Aes256Cbc, Pkcs12Encryption::TripleDesCbc] {
+            let pkcs12 = Pkcs12::builder()
+                .cert(leaf.clone())
+                .private_key(leaf_key.clone())
+                .chain_cert(root.clone())
+                .friendly_name("Picky Leaf")
+                .encryption(*encryption)
+                .iterations(16)
+                .build()
+                .unwrap();
+
+            let der = pkcs12.to_der("p@ssw0rd").unwrap();
+            let parsed = Pkcs12::from_der(&der, "p@ssw0rd").unwrap();
+            assert_eq!(parsed.cert(), Some(&leaf));
+            assert_eq!(parsed.private_key(), Some(&leaf_key));
+            assert_eq!(parsed.chain(), &[root.clone()][..]);
+            assert_eq!(parsed.friendly_name(), Some("Picky Leaf"));
+
+            match Pkcs12::from_der(&der, "wrong") {
+                Err(Pkcs12Error::InvalidMac) => {}
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn key_mismatch() {
+        let (leaf, _, root) = certs();
+        let root_key = key(crate::test_files::RSA_2048_PK_1);
+
+        match Pkcs12::builder().cert(leaf).private_key(root_key).build() {
+            Err(Pkcs12Error::KeyMismatch) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let cert_only = Pkcs12::builder().cert(root.clone()).build().unwrap();
+        let parsed = Pkcs12::from_der(&cert_only.to_der("").unwrap(), "").unwrap();
+        assert_eq!(parsed.cert(), Some(&root));
+        assert_eq!(parsed.private_key(), None);
+        assert!(parsed.chain().is_empty());
+    }
+}
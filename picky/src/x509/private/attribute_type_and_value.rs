@@ -1,5 +1,9 @@
 use crate::{oids, x509::DirectoryString};
-use picky_asn1::wrapper::ObjectIdentifierAsn1;
+use picky_asn1::{
+    tag::{Tag, TagPeeker},
+    wrapper::ObjectIdentifierAsn1,
+};
+use picky_asn1_der::Asn1RawDer;
 use serde::{de, ser};
 use std::fmt;
 
@@ -14,6 +18,9 @@ pub(crate) enum AttributeTypeAndValueParameters {
     StreetName(DirectoryString),
     OrganisationName(DirectoryString),
     OrganisationalUnitName(DirectoryString),
+    /// Attribute of another type (e.g. e-mailAddress), or whose string type isn't supported
+    /// (e.g. TeletexString): the encoded value is kept as is.
+    Custom(Asn1RawDer),
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -123,6 +130,9 @@ impl ser::Serialize for AttributeTypeAndValue {
             AttributeTypeAndValueParameters::OrganisationalUnitName(name) => {
                 seq.serialize_element(name)?;
             }
+            AttributeTypeAndValueParameters::Custom(der) => {
+                seq.serialize_element(der)?;
+            }
         }
         seq.end()
     }
@@ -148,6 +158,18 @@ impl<'de> de::Deserialize<'de> for AttributeTypeAndValue {
             {
                 let ty: ObjectIdentifierAsn1 = seq_next_element!(seq, AttributeTypeAndValue, "type oid");
 
+                let tag_peeker: TagPeeker = seq_next_element!(seq, AttributeTypeAndValue, "value tag");
+                if tag_peeker.next_tag != Tag::UTF8_STRING && tag_peeker.next_tag != Tag::PRINTABLE_STRING {
+                    return Ok(AttributeTypeAndValue {
+                        ty,
+                        value: AttributeTypeAndValueParameters::Custom(seq_next_element!(
+                            seq,
+                            AttributeTypeAndValue,
+                            "raw value"
+                        )),
+                    });
+                }
+
                 let value =
                     match Into::<String>::into(&ty.0).as_str() {
                         oids::AT_COMMON_NAME => AttributeTypeAndValueParameters::CommonName(seq_next_element!(
@@ -189,21 +211,11 @@ impl<'de> de::Deserialize<'de> for AttributeTypeAndValue {
                         oids::AT_ORGANISATIONAL_UNIT_NAME => AttributeTypeAndValueParameters::OrganisationalUnitName(
                             seq_next_element!(seq, AttributeTypeAndValue, "at organisational unit name"),
                         ),
-                        oids::EMAIL_ADDRESS => {
-                            return Err(serde_invalid_value!(
-                                AttributeTypeAndValue,
-                                "1.2.840.113549.1.9.1 (e-mailAddress) \
-                                 attribute is deprecated and won't be supported",
-                                "a supported type"
-                            ));
-                        }
-                        _ => {
-                            return Err(serde_invalid_value!(
-                                AttributeTypeAndValue,
-                                "unsupported type (unknown oid)",
-                                "a supported type"
-                            ));
-                        }
+                        _ => AttributeTypeAndValueParameters::Custom(seq_next_element!(
+                            seq,
+                            AttributeTypeAndValue,
+                            "raw value"
+                        )),
                     };
 
                 Ok(AttributeTypeAndValue { ty, value })
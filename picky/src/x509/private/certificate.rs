@@ -6,7 +6,10 @@ use crate::{
     },
     AlgorithmIdentifier,
 };
-use picky_asn1::wrapper::{ApplicationTag0, ApplicationTag3, BitStringAsn1, IntegerAsn1};
+use picky_asn1::{
+    tag::{Tag, TagPeeker},
+    wrapper::{ApplicationTag0, ApplicationTag3, BitStringAsn1, ContextTag1, ContextTag2, IntegerAsn1},
+};
 use serde::{de, Deserialize, Serialize};
 use std::fmt;
 
@@ -26,8 +29,8 @@ pub(crate) struct TBSCertificate {
     pub validity: Validity,
     pub subject: Name,
     pub subject_public_key_info: SubjectPublicKeyInfo,
-    // issuer_unique_id
-    // subject_unique_id
+    pub issuer_unique_id: Option<ContextTag1<BitStringAsn1>>,
+    pub subject_unique_id: Option<ContextTag2<BitStringAsn1>>,
     pub extensions: ApplicationTag3<Extensions>,
 }
 
@@ -70,6 +73,14 @@ impl<'de> de::Deserialize<'de> for TBSCertificate {
                     validity: seq.next_element()?.ok_or_else(|| de::Error::invalid_length(4, &self))?,
                     subject: seq.next_element()?.ok_or_else(|| de::Error::invalid_length(5, &self))?,
                     subject_public_key_info: seq.next_element()?.ok_or_else(|| de::Error::invalid_length(6, &self))?,
+                    issuer_unique_id: match seq.next_element::<TagPeeker>()? {
+                        Some(tag_peeker) if tag_peeker.next_tag == Tag::CTX_1 => seq.next_element()?,
+                        _ => None,
+                    },
+                    subject_unique_id: match seq.next_element::<TagPeeker>()? {
+                        Some(tag_peeker) if tag_peeker.next_tag == Tag::CTX_2 => seq.next_element()?,
+                        _ => None,
+                    },
                     extensions: seq.next_element()?.ok_or_else(|| de::Error::invalid_length(7, &self))?,
                 })
             }
@@ -153,7 +164,7 @@ mod tests {
         key_usage.set_digital_signature(true);
         key_usage.set_key_encipherment(true);
 
-        let extensions = Extensions(vec![
+        let extensions = Extensions::from(vec![
             Extension::new_basic_constraints(None, None).into_non_critical(),
             Extension::new_key_usage(key_usage),
             Extension::new_subject_key_identifier(&encoded[469..489]),
@@ -176,6 +187,8 @@ mod tests {
             validity,
             subject,
             subject_public_key_info,
+            issuer_unique_id: None,
+            subject_unique_id: None,
             extensions: extensions.into(),
         };
         check_serde!(tbs_certificate: TBSCertificate in encoded[4..522]);
@@ -190,6 +203,21 @@ mod tests {
         check_serde!(certificate: Certificate in encoded);
     }
 
+    #[test]
+    fn unique_ids() {
+        let pem = parse_pem(crate::test_files::INTERMEDIATE_CA).unwrap();
+        let mut tbs_certificate = Certificate::from(Cert::from_der(pem.data()).unwrap()).tbs_certificate;
+        tbs_certificate.issuer_unique_id = Some(ContextTag1(BitString::with_bytes(vec![0x01, 0x02]).into()));
+        tbs_certificate.subject_unique_id = Some(ContextTag2(BitString::with_bytes(vec![0x03]).into()));
+
+        let encoded = picky_asn1_der::to_vec(&tbs_certificate).expect("serialize");
+        let unique_ids = [0x81, 0x03, 0x00, 0x01, 0x02, 0x82, 0x02, 0x00, 0x03, 0xA3];
+        assert!(encoded.windows(unique_ids.len()).any(|window| window == unique_ids));
+
+        let decoded: TBSCertificate = picky_asn1_der::from_bytes(&encoded).expect("deserialize");
+        pretty_assertions::assert_eq!(decoded, tbs_certificate);
+    }
+
     #[test]
     fn key_id() {
         let intermediate_cert_pem = parse_pem(crate::test_files::INTERMEDIATE_CA).unwrap();
@@ -0,0 +1,109 @@
+use crate::{
+    x509::{crl::RevokedCertificate, private::validity::Time, private::Name, Extensions},
+    AlgorithmIdentifier,
+};
+use picky_asn1::{
+    tag::{Tag, TagPeeker},
+    wrapper::{ApplicationTag0, Asn1SequenceOf, BitStringAsn1},
+};
+use serde::{de, Deserialize, Serialize};
+use std::fmt;
+
+/// https://tools.ietf.org/html/rfc5280#section-5.1
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct CertificateList {
+    pub tbs_cert_list: TBSCertList,
+    pub signature_algorithm: AlgorithmIdentifier,
+    pub signature_value: BitStringAsn1,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub(crate) struct TBSCertList {
+    /// MUST be v2 (1) when present
+    pub version: Option<u8>,
+    pub signature: AlgorithmIdentifier,
+    pub issuer: Name,
+    pub this_update: Time,
+    pub next_update: Option<Time>,
+    /// Omitted when no certificate is revoked
+    pub revoked_certificates: Option<Asn1SequenceOf<RevokedCertificate>>,
+    pub crl_extensions: Option<ApplicationTag0<Extensions>>,
+}
+
+// Implement Deserialize manually: optional fields are identified by peeking their tag
+impl<'de> de::Deserialize<'de> for TBSCertList {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = TBSCertList;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("struct TBSCertList")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<Self::Value, V::Error>
+            where
+                V: de::SeqAccess<'de>,
+            {
+                let tag_peeker: TagPeeker = seq_next_element!(seq, TBSCertList, "version or signature");
+                let version = if tag_peeker.next_tag == Tag::INTEGER {
+                    Some(seq_next_element!(seq, TBSCertList, "version"))
+                } else {
+                    None
+                };
+
+                let signature = seq_next_element!(seq, TBSCertList, "signature");
+                let issuer = seq_next_element!(seq, TBSCertList, "issuer");
+                let this_update = seq_next_element!(seq, TBSCertList, "this update");
+
+                let mut next_tag = seq.next_element::<TagPeeker>()?.map(|peeker| peeker.next_tag);
+
+                let next_update = match next_tag {
+                    Some(Tag::UTC_TIME) | Some(Tag::GENERALIZED_TIME) => {
+                        let next_update = seq_next_element!(seq, TBSCertList, "next update");
+                        next_tag = seq.next_element::<TagPeeker>()?.map(|peeker| peeker.next_tag);
+                        Some(next_update)
+                    }
+                    _ => None,
+                };
+
+                let revoked_certificates = match next_tag {
+                    Some(Tag::SEQUENCE) => {
+                        let revoked_certificates = seq_next_element!(seq, TBSCertList, "revoked certificates");
+                        next_tag = seq.next_element::<TagPeeker>()?.map(|peeker| peeker.next_tag);
+                        Some(revoked_certificates)
+                    }
+                    _ => None,
+                };
+
+                let crl_extensions = match next_tag {
+                    Some(Tag::APP_0) => Some(seq_next_element!(seq, TBSCertList, "crl extensions")),
+                    Some(_) => {
+                        return Err(serde_invalid_value!(
+                            TBSCertList,
+                            "unexpected trailing element",
+                            "crl extensions"
+                        ))
+                    }
+                    None => None,
+                };
+
+                Ok(TBSCertList {
+                    version,
+                    signature,
+                    issuer,
+                    this_update,
+                    next_update,
+                    revoked_certificates,
+                    crl_extensions,
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
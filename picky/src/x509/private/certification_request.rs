@@ -1,5 +1,20 @@
-use crate::{private::SubjectPublicKeyInfo, x509::private::Name, AlgorithmIdentifier};
-use picky_asn1::wrapper::{ApplicationTag0, BitStringAsn1, HeaderOnly, Implicit};
+use crate::{
+    oids,
+    private::SubjectPublicKeyInfo,
+    x509::{
+        private::{
+            cms::{from_implicit_set, to_implicit_set, Attribute},
+            Name,
+        },
+        Extensions,
+    },
+    AlgorithmIdentifier,
+};
+use picky_asn1::{
+    tag::Tag,
+    wrapper::{Asn1SetOf, BitStringAsn1, Implicit},
+};
+use picky_asn1_der::{Asn1DerError, Asn1RawDer};
 use serde::{Deserialize, Serialize};
 
 /// https://tools.ietf.org/html/rfc2986#section-4
@@ -8,7 +23,8 @@ pub(crate) struct CertificationRequestInfo {
     pub version: u8,
     pub subject: Name,
     pub subject_public_key_info: SubjectPublicKeyInfo,
-    pub attributes: Implicit<Option<HeaderOnly<ApplicationTag0<()>>>>, // unsupported.
+    /// [0] IMPLICIT SET OF Attribute, kept as-is since the signature covers their exact encoding
+    pub attributes: Implicit<Option<Asn1RawDer>>,
 }
 
 impl CertificationRequestInfo {
@@ -18,7 +34,45 @@ impl CertificationRequestInfo {
             version: 0,
             subject,
             subject_public_key_info,
-            attributes: Implicit(Some(HeaderOnly::<ApplicationTag0<()>>::default())),
+            attributes: Implicit(Some(to_implicit_set(Tag::APP_0, vec![Tag::SET.number(), 0x00]))),
+        }
+    }
+
+    /// Same as `new`, requesting `extensions` through the PKCS#9 extensionRequest attribute.
+    pub fn new_with_extensions(
+        subject: Name,
+        subject_public_key_info: SubjectPublicKeyInfo,
+        extensions: &Extensions,
+    ) -> Result<Self, Asn1DerError> {
+        let extension_request = Attribute {
+            attr_type: oids::extension_request().into(),
+            attr_values: Asn1SetOf(vec![Asn1RawDer(picky_asn1_der::to_vec(extensions)?)]),
+        };
+        let attributes = picky_asn1_der::to_vec(&Asn1SetOf(vec![extension_request]))?;
+
+        Ok(Self {
+            attributes: Implicit(Some(to_implicit_set(Tag::APP_0, attributes))),
+            ..Self::new(subject, subject_public_key_info)
+        })
+    }
+
+    /// Extensions of the extensionRequest attribute, `None` if there is no such attribute.
+    pub fn extensions(&self) -> Result<Option<Extensions>, Asn1DerError> {
+        let attributes = match &self.attributes.0 {
+            Some(attributes) => attributes,
+            None => return Ok(None),
+        };
+
+        let attributes: Asn1SetOf<Attribute> = picky_asn1_der::from_bytes(&from_implicit_set(attributes))?;
+        let extension_request = attributes
+            .0
+            .into_iter()
+            .find(|attribute| attribute.attr_type.0 == oids::extension_request())
+            .and_then(|attribute| attribute.attr_values.0.into_iter().next());
+
+        match extension_request {
+            Some(extensions) => Ok(Some(picky_asn1_der::from_bytes(&extensions.0)?)),
+            None => Ok(None),
         }
     }
 }
@@ -33,9 +87,12 @@ pub(crate) struct CertificationRequest {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{pem::Pem, x509::name::DirectoryName};
+    use crate::{
+        pem::Pem,
+        x509::{name::DirectoryName, Extension},
+    };
     use picky_asn1::{bit_string::BitString, restricted_string::PrintableString, wrapper::IntegerAsn1};
-    use std::str::FromStr;
+    use std::{convert::TryFrom, str::FromStr};
 
     #[test]
     fn deserialize_csr() {
@@ -61,4 +118,33 @@ mod tests {
 
         check_serde!(csr: CertificationRequest in encoded);
     }
+
+    #[test]
+    fn extension_request() {
+        let subject: Name =
+            DirectoryName::new_common_name(PrintableString::from_str("test.contoso.local").unwrap()).into();
+        let public_key = SubjectPublicKeyInfo::new_rsa_key(
+            IntegerAsn1::from(vec![0x00, 0xc5]),
+            IntegerAsn1::from(vec![0x01, 0x00, 0x01]),
+        );
+
+        let info = CertificationRequestInfo::new(subject.clone(), public_key.clone());
+        assert_eq!(info.extensions().unwrap(), None);
+
+        let extensions: Extensions = vec![
+            Extension::new_generic(
+                oid::ObjectIdentifier::try_from("1.3.6.1.4.1.311.20.2").unwrap(),
+                false,
+                vec![0x05, 0x00],
+            ),
+            Extension::new_basic_constraints(None, None),
+        ]
+        .into();
+        let info = CertificationRequestInfo::new_with_extensions(subject, public_key, &extensions).unwrap();
+
+        let encoded = picky_asn1_der::to_vec(&info).unwrap();
+        let decoded: CertificationRequestInfo = picky_asn1_der::from_bytes(&encoded).unwrap();
+        assert_eq!(decoded, info);
+        assert_eq!(decoded.extensions().unwrap(), Some(extensions));
+    }
 }
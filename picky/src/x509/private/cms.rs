@@ -0,0 +1,363 @@
+use crate::{x509::private::Name, AlgorithmIdentifier};
+use picky_asn1::{
+    tag::{Tag, TagPeeker},
+    wrapper::{ApplicationTag0, Asn1SetOf, ContextTag0, IntegerAsn1, ObjectIdentifierAsn1, OctetStringAsn1},
+};
+use picky_asn1_der::Asn1RawDer;
+use serde::{de, ser, Deserialize, Serialize};
+use std::fmt;
+
+/// https://tools.ietf.org/html/rfc5652#section-3
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct ContentInfo {
+    pub content_type: ObjectIdentifierAsn1,
+    /// DER-encoded content of type `content_type`
+    pub content: ApplicationTag0<Asn1RawDer>,
+}
+
+/// https://tools.ietf.org/html/rfc5652#section-5.1
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub(crate) struct SignedData {
+    /// v1 (1), or v3 (3) when a signer is identified by its subject key identifier
+    pub version: u8,
+    pub digest_algorithms: Asn1SetOf<AlgorithmIdentifier>,
+    pub encap_content_info: EncapsulatedContentInfo,
+    /// [0] IMPLICIT CertificateSet, kept as-is (see `from_implicit_set`)
+    pub certificates: Option<Asn1RawDer>,
+    /// [1] IMPLICIT RevocationInfoChoices, kept as-is
+    pub crls: Option<Asn1RawDer>,
+    pub signer_infos: Asn1SetOf<SignerInfo>,
+}
+
+impl<'de> de::Deserialize<'de> for SignedData {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = SignedData;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("struct SignedData")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<Self::Value, V::Error>
+            where
+                V: de::SeqAccess<'de>,
+            {
+                let version = seq_next_element!(seq, SignedData, "version");
+                let digest_algorithms = seq_next_element!(seq, SignedData, "digest algorithms");
+                let encap_content_info = seq_next_element!(seq, SignedData, "encapsulated content info");
+
+                let mut next_tag = seq_next_element!(seq, TagPeeker, SignedData, "signer infos").next_tag;
+
+                let certificates = if next_tag == Tag::APP_0 {
+                    let certificates = seq_next_element!(seq, SignedData, "certificates");
+                    next_tag = seq_next_element!(seq, TagPeeker, SignedData, "signer infos").next_tag;
+                    Some(certificates)
+                } else {
+                    None
+                };
+
+                let crls = if next_tag == Tag::APP_1 {
+                    Some(seq_next_element!(seq, SignedData, "crls"))
+                } else {
+                    None
+                };
+
+                Ok(SignedData {
+                    version,
+                    digest_algorithms,
+                    encap_content_info,
+                    certificates,
+                    crls,
+                    signer_infos: seq_next_element!(seq, SignedData, "signer infos"),
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+/// https://tools.ietf.org/html/rfc5652#section-5.2
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub(crate) struct EncapsulatedContentInfo {
+    pub e_content_type: ObjectIdentifierAsn1,
+    /// Absent when the signature is detached
+    pub e_content: Option<ApplicationTag0<OctetStringAsn1>>,
+}
+
+impl<'de> de::Deserialize<'de> for EncapsulatedContentInfo {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = EncapsulatedContentInfo;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("struct EncapsulatedContentInfo")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<Self::Value, V::Error>
+            where
+                V: de::SeqAccess<'de>,
+            {
+                let e_content_type = seq_next_element!(seq, EncapsulatedContentInfo, "content type");
+                let e_content = match seq.next_element::<TagPeeker>()? {
+                    Some(_) => Some(seq_next_element!(seq, EncapsulatedContentInfo, "content")),
+                    None => None,
+                };
+
+                Ok(EncapsulatedContentInfo {
+                    e_content_type,
+                    e_content,
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+/// https://tools.ietf.org/html/rfc5652#section-5.3
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub(crate) struct SignerInfo {
+    /// v1 (1) for an issuer and serial number identifier, v3 (3) for a subject key identifier
+    pub version: u8,
+    pub sid: SignerIdentifier,
+    pub digest_algorithm: AlgorithmIdentifier,
+    /// [0] IMPLICIT SignedAttributes, kept as-is since the signature covers their exact encoding
+    pub signed_attrs: Option<Asn1RawDer>,
+    pub signature_algorithm: AlgorithmIdentifier,
+    pub signature: OctetStringAsn1,
+    /// [1] IMPLICIT UnsignedAttributes, kept as-is
+    pub unsigned_attrs: Option<Asn1RawDer>,
+}
+
+impl<'de> de::Deserialize<'de> for SignerInfo {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = SignerInfo;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("struct SignerInfo")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<Self::Value, V::Error>
+            where
+                V: de::SeqAccess<'de>,
+            {
+                let version = seq_next_element!(seq, SignerInfo, "version");
+                let sid = seq_next_element!(seq, SignerInfo, "signer identifier");
+                let digest_algorithm = seq_next_element!(seq, SignerInfo, "digest algorithm");
+
+                let signed_attrs =
+                    if seq_next_element!(seq, TagPeeker, SignerInfo, "signature algorithm").next_tag == Tag::APP_0 {
+                        Some(seq_next_element!(seq, SignerInfo, "signed attributes"))
+                    } else {
+                        None
+                    };
+
+                let signature_algorithm = seq_next_element!(seq, SignerInfo, "signature algorithm");
+                let signature = seq_next_element!(seq, SignerInfo, "signature");
+
+                let unsigned_attrs = match seq.next_element::<TagPeeker>()?.map(|peeker| peeker.next_tag) {
+                    Some(Tag::APP_1) => Some(seq_next_element!(seq, SignerInfo, "unsigned attributes")),
+                    Some(_) => {
+                        return Err(serde_invalid_value!(
+                            SignerInfo,
+                            "unexpected trailing element",
+                            "unsigned attributes"
+                        ))
+                    }
+                    None => None,
+                };
+
+                Ok(SignerInfo {
+                    version,
+                    sid,
+                    digest_algorithm,
+                    signed_attrs,
+                    signature_algorithm,
+                    signature,
+                    unsigned_attrs,
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+// SignerIdentifier ::= CHOICE {
+//      issuerAndSerialNumber IssuerAndSerialNumber,
+//      subjectKeyIdentifier [0] SubjectKeyIdentifier }
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum SignerIdentifier {
+    IssuerAndSerialNumber(IssuerAndSerialNumber),
+    SubjectKeyIdentifier(OctetStringAsn1),
+}
+
+impl ser::Serialize for SignerIdentifier {
+    fn serialize<S>(&self, serializer: S) -> Result<<S as ser::Serializer>::Ok, <S as ser::Serializer>::Error>
+    where
+        S: ser::Serializer,
+    {
+        match &self {
+            SignerIdentifier::IssuerAndSerialNumber(issuer_and_serial_number) => {
+                issuer_and_serial_number.serialize(serializer)
+            }
+            SignerIdentifier::SubjectKeyIdentifier(key_id) => ContextTag0(key_id).serialize(serializer),
+        }
+    }
+}
+
+impl<'de> de::Deserialize<'de> for SignerIdentifier {
+    fn deserialize<D>(deserializer: D) -> Result<Self, <D as de::Deserializer<'de>>::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = SignerIdentifier;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a valid DER-encoded SignerIdentifier")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::SeqAccess<'de>,
+            {
+                let tag_peeker: TagPeeker = seq_next_element!(seq, SignerIdentifier, "choice tag");
+                match tag_peeker.next_tag {
+                    Tag::SEQUENCE => Ok(SignerIdentifier::IssuerAndSerialNumber(seq_next_element!(
+                        seq,
+                        SignerIdentifier,
+                        "IssuerAndSerialNumber"
+                    ))),
+                    Tag::CTX_0 => Ok(SignerIdentifier::SubjectKeyIdentifier(
+                        seq_next_element!(
+                            seq,
+                            ContextTag0<OctetStringAsn1>,
+                            SignerIdentifier,
+                            "SubjectKeyIdentifier"
+                        )
+                        .0,
+                    )),
+                    _ => Err(serde_invalid_value!(
+                        SignerIdentifier,
+                        "unknown choice value",
+                        "a supported SignerIdentifier choice"
+                    )),
+                }
+            }
+        }
+
+        deserializer.deserialize_enum(
+            "SignerIdentifier",
+            &["IssuerAndSerialNumber", "SubjectKeyIdentifier"],
+            Visitor,
+        )
+    }
+}
+
+/// https://tools.ietf.org/html/rfc5652#section-10.2.4
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct IssuerAndSerialNumber {
+    pub issuer: Name,
+    pub serial_number: IntegerAsn1,
+}
+
+/// https://tools.ietf.org/html/rfc5652#section-5.3
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct Attribute {
+    pub attr_type: ObjectIdentifierAsn1,
+    pub attr_values: Asn1SetOf<Asn1RawDer>,
+}
+
+/// Encodes a `SET OF` with the constructed context-specific `tag` in place of its own (IMPLICIT
+/// tagging).
+pub(crate) fn to_implicit_set(tag: Tag, set_der: Vec<u8>) -> Asn1RawDer {
+    retag(set_der, tag)
+}
+
+/// Recovers the `SET OF` encoding of an IMPLICIT tagged field.
+pub(crate) fn from_implicit_set(raw: &Asn1RawDer) -> Vec<u8> {
+    retag(raw.0.clone(), Tag::SET)
+}
+
+fn retag(mut der: Vec<u8>, tag: Tag) -> Asn1RawDer {
+    if let Some(first) = der.first_mut() {
+        *first = tag.number();
+    }
+    Asn1RawDer(der)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::x509::private::AttributeTypeAndValue;
+    use picky_asn1::wrapper::Asn1SequenceOf;
+
+    #[test]
+    fn signer_info() {
+        #[rustfmt::skip]
+        let encoded = [
+            0x30, 0x43,
+                0x02, 0x01, 0x01, // version
+                0x30, 0x15, // issuer and serial number
+                    0x30, 0x10, // issuer
+                        0x31, 0x0E,
+                            0x30, 0x0C,
+                                0x06, 0x03, 0x55, 0x04, 0x03,
+                                0x0C, 0x05, 0x50, 0x69, 0x63, 0x6B, 0x79,
+                    0x02, 0x01, 0x0A, // serial number
+                0x30, 0x0D, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05, 0x00, // sha256
+                0xA0, 0x05, // signed attributes
+                    0x30, 0x03, 0x06, 0x01, 0x2A,
+                0x30, 0x0D, 0x06, 0x09, 0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x0B, 0x05, 0x00, // sha256WithRSAEncryption
+                0x04, 0x02, 0x01, 0x02, // signature
+        ];
+        let signer_info = SignerInfo {
+            version: 1,
+            sid: SignerIdentifier::IssuerAndSerialNumber(IssuerAndSerialNumber {
+                issuer: Asn1SequenceOf(vec![Asn1SetOf(vec![AttributeTypeAndValue::new_common_name("Picky")])]),
+                serial_number: IntegerAsn1(vec![0x0A]),
+            }),
+            digest_algorithm: AlgorithmIdentifier::new_sha256(),
+            signed_attrs: Some(Asn1RawDer(vec![0xA0, 0x05, 0x30, 0x03, 0x06, 0x01, 0x2A])),
+            signature_algorithm: AlgorithmIdentifier::new_sha256_with_rsa_encryption(),
+            signature: OctetStringAsn1(vec![0x01, 0x02]),
+            unsigned_attrs: None,
+        };
+        check_serde!(signer_info: SignerInfo in encoded);
+    }
+
+    #[test]
+    fn subject_key_identifier() {
+        let encoded = [0x80, 0x03, 0x01, 0x02, 0x03];
+        let sid = SignerIdentifier::SubjectKeyIdentifier(OctetStringAsn1(vec![0x01, 0x02, 0x03]));
+        check_serde!(sid: SignerIdentifier in encoded);
+    }
+
+    #[test]
+    fn implicit_set() {
+        let set = vec![0x31, 0x03, 0x02, 0x01, 0x01];
+        let implicit = to_implicit_set(Tag::APP_0, set.clone());
+        assert_eq!(implicit.0, [0xA0, 0x03, 0x02, 0x01, 0x01]);
+        assert_eq!(from_implicit_set(&implicit), set);
+    }
+}
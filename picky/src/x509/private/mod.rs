@@ -1,7 +1,12 @@
 pub(crate) mod attribute_type_and_value;
 pub(crate) mod certificate;
+pub(crate) mod certificate_list;
 pub(crate) mod certification_request;
+pub(crate) mod cms;
 pub(crate) mod name;
+pub(crate) mod ocsp;
+#[cfg(feature = "pkcs12")]
+pub(crate) mod pkcs12;
 pub(crate) mod validity;
 pub(crate) mod version;
 
@@ -5,11 +5,12 @@ use crate::x509::{
 use picky_asn1::{
     tag::{Tag, TagPeeker},
     wrapper::{
-        ApplicationTag1, ApplicationTag2, ApplicationTag4, ApplicationTag5, ApplicationTag6, ApplicationTag7,
-        ApplicationTag8, Asn1SequenceOf, Asn1SetOf, ContextTag0, ContextTag1, ContextTag2, ContextTag4, ContextTag5,
-        ContextTag6, ContextTag7, ContextTag8, IA5StringAsn1, Implicit, ObjectIdentifierAsn1, OctetStringAsn1,
+        ApplicationTag0, ApplicationTag1, ApplicationTag2, ApplicationTag4, ApplicationTag6, ApplicationTag7,
+        ApplicationTag8, Asn1SequenceOf, Asn1SetOf, ContextTag1, ContextTag2, ContextTag4, ContextTag6, ContextTag7,
+        ContextTag8, IA5StringAsn1, ObjectIdentifierAsn1, OctetStringAsn1,
     },
 };
+use picky_asn1_der::Asn1RawDer;
 use serde::{de, ser, Deserialize, Serialize};
 use std::fmt;
 
@@ -61,6 +62,13 @@ impl fmt::Display for NamePrettyFormatter<'_> {
                     AttributeTypeAndValueParameters::OrganisationalUnitName(name) => {
                         write!(f, "OU={}", name)?;
                     }
+                    AttributeTypeAndValueParameters::Custom(der) => {
+                        // RFC 4514 form for attributes without string representation
+                        write!(f, "{}=#", Into::<String>::into(&attr.ty.0))?;
+                        for byte in &der.0 {
+                            write!(f, "{:02X}", byte)?;
+                        }
+                    }
                 }
             }
         }
@@ -84,10 +92,11 @@ pub(crate) type GeneralNames = Asn1SequenceOf<GeneralName>;
 //      registeredID                    [8]     OBJECT IDENTIFIER }
 #[derive(Debug, PartialEq, Clone)]
 pub(crate) enum GeneralName {
-    //OtherName(OtherName),
+    OtherName(OtherName),
     RFC822Name(IA5StringAsn1),
     DNSName(IA5StringAsn1),
-    //X400Address(ORAddress),
+    /// DER encoding of the ORAddress, kept as-is
+    X400Address(Asn1RawDer),
     DirectoryName(Name),
     EDIPartyName(EDIPartyName),
     URI(IA5StringAsn1),
@@ -102,8 +111,8 @@ impl GeneralName {
         NA: Into<DirectoryString>,
     {
         Self::EDIPartyName(EDIPartyName {
-            name_assigner: Implicit(name_assigner.map(Into::into).map(ContextTag0)),
-            party_name: ContextTag1(party_name.into()),
+            name_assigner: name_assigner.map(Into::into).map(ApplicationTag0),
+            party_name: ApplicationTag1(party_name.into()),
         })
     }
 }
@@ -120,10 +129,17 @@ impl ser::Serialize for GeneralName {
         S: ser::Serializer,
     {
         match &self {
+            GeneralName::OtherName(name) => implicit_sequence(Tag::APP_0, name)
+                .map_err(ser::Error::custom)?
+                .serialize(serializer),
             GeneralName::RFC822Name(name) => ContextTag1(name).serialize(serializer),
             GeneralName::DNSName(name) => ContextTag2(name).serialize(serializer),
-            GeneralName::DirectoryName(name) => ContextTag4(name).serialize(serializer),
-            GeneralName::EDIPartyName(name) => ContextTag5(name).serialize(serializer),
+            GeneralName::X400Address(address) => retag(address.clone(), Tag::APP_3).serialize(serializer),
+            // Name is a CHOICE, hence explicitly tagged
+            GeneralName::DirectoryName(name) => ApplicationTag4(name).serialize(serializer),
+            GeneralName::EDIPartyName(name) => implicit_sequence(Tag::APP_5, name)
+                .map_err(ser::Error::custom)?
+                .serialize(serializer),
             GeneralName::URI(name) => ContextTag6(name).serialize(serializer),
             GeneralName::IpAddress(name) => ContextTag7(name).serialize(serializer),
             GeneralName::RegisteredId(name) => ContextTag8(name).serialize(serializer),
@@ -151,10 +167,9 @@ impl<'de> de::Deserialize<'de> for GeneralName {
             {
                 let tag_peeker: TagPeeker = seq_next_element!(seq, DirectoryString, "choice tag");
                 match tag_peeker.next_tag {
-                    Tag::CTX_0 | Tag::APP_0 => Err(serde_invalid_value!(
-                        GeneralName,
-                        "OtherName not supported",
-                        "a supported choice"
+                    Tag::APP_0 => Ok(GeneralName::OtherName(
+                        from_implicit_sequence(seq_next_element!(seq, GeneralName, "OtherName"))
+                            .map_err(de::Error::custom)?,
                     )),
                     Tag::CTX_1 => Ok(GeneralName::RFC822Name(
                         seq_next_element!(seq, ContextTag1<IA5StringAsn1>, GeneralName, "RFC822Name").0,
@@ -168,22 +183,19 @@ impl<'de> de::Deserialize<'de> for GeneralName {
                     Tag::APP_2 => Ok(GeneralName::DNSName(
                         seq_next_element!(seq, ApplicationTag2<IA5StringAsn1>, GeneralName, "DNSName").0,
                     )),
-                    Tag::CTX_3 | Tag::APP_3 => Err(serde_invalid_value!(
-                        GeneralName,
-                        "X400Address not supported",
-                        "a supported choice"
-                    )),
+                    Tag::APP_3 => Ok(GeneralName::X400Address(retag(
+                        seq_next_element!(seq, GeneralName, "X400Address"),
+                        Tag::SEQUENCE,
+                    ))),
                     Tag::CTX_4 => Ok(GeneralName::DirectoryName(
                         seq_next_element!(seq, ContextTag4<Name>, GeneralName, "DirectoryName").0,
                     )),
                     Tag::APP_4 => Ok(GeneralName::DirectoryName(
                         seq_next_element!(seq, ApplicationTag4<Name>, GeneralName, "DirectoryName").0,
                     )),
-                    Tag::CTX_5 => Ok(GeneralName::EDIPartyName(
-                        seq_next_element!(seq, ContextTag5<EDIPartyName>, GeneralName, "EDIPartyName").0,
-                    )),
                     Tag::APP_5 => Ok(GeneralName::EDIPartyName(
-                        seq_next_element!(seq, ApplicationTag5<EDIPartyName>, GeneralName, "EDIPartyName").0,
+                        from_implicit_sequence(seq_next_element!(seq, GeneralName, "EDIPartyName"))
+                            .map_err(de::Error::custom)?,
                     )),
                     Tag::CTX_6 => Ok(GeneralName::URI(
                         seq_next_element!(seq, ContextTag6<IA5StringAsn1>, GeneralName, "URI").0,
@@ -215,8 +227,10 @@ impl<'de> de::Deserialize<'de> for GeneralName {
         deserializer.deserialize_enum(
             "GeneralName",
             &[
+                "OtherName",
                 "RFC822Name",
                 "DNSName",
+                "X400Address",
                 "DirectoryName",
                 "EDIPartyName",
                 "URI",
@@ -231,21 +245,81 @@ impl<'de> de::Deserialize<'de> for GeneralName {
 // OtherName ::= SEQUENCE {
 //      type-id    OBJECT IDENTIFIER,
 //      value      [0] EXPLICIT ANY DEFINED BY type-id }
-//pub(crate) struct OtherName { ... }
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub(crate) struct OtherName {
+    pub type_id: ObjectIdentifierAsn1,
+    pub value: ApplicationTag0<Asn1RawDer>,
+}
 
 // EDIPartyName ::= SEQUENCE {
 //      nameAssigner            [0]     DirectoryString OPTIONAL,
 //      partyName               [1]     DirectoryString }
-#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+// (DirectoryString is a CHOICE, hence explicitly tagged)
+#[derive(Serialize, Debug, PartialEq, Clone)]
 pub(crate) struct EDIPartyName {
-    pub name_assigner: Implicit<Option<ContextTag0<DirectoryString>>>,
-    pub party_name: ContextTag1<DirectoryString>,
+    pub name_assigner: Option<ApplicationTag0<DirectoryString>>,
+    pub party_name: ApplicationTag1<DirectoryString>,
+}
+
+impl<'de> de::Deserialize<'de> for EDIPartyName {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = EDIPartyName;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("struct EDIPartyName")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::SeqAccess<'de>,
+            {
+                let tag_peeker: TagPeeker = seq_next_element!(seq, EDIPartyName, "party name");
+                let name_assigner = match tag_peeker.next_tag {
+                    Tag::APP_0 => Some(seq_next_element!(seq, EDIPartyName, "name assigner")),
+                    _ => None,
+                };
+
+                Ok(EDIPartyName {
+                    name_assigner,
+                    party_name: seq_next_element!(seq, EDIPartyName, "party name"),
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+// GeneralName choices which are SEQUENCEs are IMPLICIT tagged: the SEQUENCE tag is replaced by the
+// (constructed) choice tag.
+
+pub(crate) fn implicit_sequence<T: Serialize>(tag: Tag, value: &T) -> picky_asn1_der::Result<Asn1RawDer> {
+    Ok(retag(Asn1RawDer(picky_asn1_der::to_vec(value)?), tag))
+}
+
+pub(crate) fn from_implicit_sequence<T: de::DeserializeOwned>(raw: Asn1RawDer) -> picky_asn1_der::Result<T> {
+    picky_asn1_der::from_bytes(&retag(raw, Tag::SEQUENCE).0)
+}
+
+fn retag(mut raw: Asn1RawDer, tag: Tag) -> Asn1RawDer {
+    if let Some(first) = raw.0.first_mut() {
+        *first = tag.number();
+    }
+    raw
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use oid::ObjectIdentifier;
     use picky_asn1::restricted_string::IA5String;
+    use std::convert::TryFrom;
 
     #[test]
     fn common_name() {
@@ -264,6 +338,37 @@ mod tests {
         check_serde!(expected: Name in encoded);
     }
 
+    #[test]
+    fn custom_attributes() {
+        #[rustfmt::skip]
+        let encoded = [
+            0x30, 0x2D, // sequence
+                0x31, 0x1B, // set
+                    0x30, 0x19, // sequence
+                        0x06, 0x09, 0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x09, 0x01, // e-mailAddress oid
+                        0x16, 0x0C, 0x70, 0x6B, 0x69, 0x40, 0x74, 0x65, 0x73, 0x74, 0x2E, 0x63, 0x6F, 0x6D, // ia5 string
+                0x31, 0x0E, // set
+                    0x30, 0x0C, // sequence
+                        0x06, 0x03, 0x55, 0x04, 0x0B, // organisational unit name oid
+                        0x14, 0x05, 0x52, 0xE9, 0x73, 0x65, 0x61, // teletex string
+        ];
+        let expected = Asn1SequenceOf(vec![
+            Asn1SetOf(vec![AttributeTypeAndValue {
+                ty: ObjectIdentifier::try_from("1.2.840.113549.1.9.1").unwrap().into(),
+                value: AttributeTypeAndValueParameters::Custom(Asn1RawDer(encoded[17..31].to_vec())),
+            }]),
+            Asn1SetOf(vec![AttributeTypeAndValue {
+                ty: ObjectIdentifier::try_from("2.5.4.11").unwrap().into(),
+                value: AttributeTypeAndValueParameters::Custom(Asn1RawDer(encoded[40..].to_vec())),
+            }]),
+        ]);
+        check_serde!(expected: Name in encoded);
+        assert_eq!(
+            NamePrettyFormatter(&expected).to_string(),
+            "1.2.840.113549.1.9.1=#160C706B6940746573742E636F6D,2.5.4.11=#140552E9736561"
+        );
+    }
+
     #[test]
     fn general_name_dns() {
         #[rustfmt::skip]
@@ -274,4 +379,76 @@ mod tests {
         let expected = GeneralName::DNSName(IA5String::from_string("devel.example.com".into()).unwrap().into());
         check_serde!(expected: GeneralName in encoded);
     }
+
+    #[test]
+    fn general_name_other_name() {
+        #[rustfmt::skip]
+        let encoded = [
+            0xA0, 0x13,
+                0x06, 0x0A, 0x2B, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x14, 0x02, 0x03, // oid
+                0xA0, 0x05,
+                    0x0C, 0x03, 0x61, 0x40, 0x62, // utf8 string
+        ];
+        let expected = GeneralName::OtherName(OtherName {
+            type_id: ObjectIdentifier::try_from("1.3.6.1.4.1.311.20.2.3").unwrap().into(),
+            value: ApplicationTag0(Asn1RawDer(vec![0x0C, 0x03, 0x61, 0x40, 0x62])),
+        });
+        check_serde!(expected: GeneralName in encoded);
+    }
+
+    #[test]
+    fn general_name_x400_address() {
+        #[rustfmt::skip]
+        let encoded = [
+            0xA3, 0x08,
+                0x30, 0x06, // built-in standard attributes
+                    0x61, 0x04, 0x13, 0x02, 0x55, 0x53, // country name
+        ];
+        let expected = GeneralName::X400Address(Asn1RawDer(vec![
+            0x30, 0x08, 0x30, 0x06, 0x61, 0x04, 0x13, 0x02, 0x55, 0x53,
+        ]));
+        check_serde!(expected: GeneralName in encoded);
+    }
+
+    #[test]
+    fn general_name_directory_name() {
+        #[rustfmt::skip]
+        let encoded = [
+            0xA4, 0x11,
+                0x30, 0x0F, // sequence
+                    0x31, 0x0D, // set
+                        0x30, 0x0B, // sequence
+                            0x06, 0x03, 0x55, 0x04, 0x03, // oid
+                            0x0C, 0x04, 0x74, 0x65, 0x73, 0x74, // utf8 string
+        ];
+        let expected = GeneralName::DirectoryName(Asn1SequenceOf(vec![Asn1SetOf(vec![
+            AttributeTypeAndValue::new_common_name("test"),
+        ])]));
+        check_serde!(expected: GeneralName in encoded);
+    }
+
+    #[test]
+    fn general_name_edi_party_name() {
+        #[rustfmt::skip]
+        let encoded = [
+            0xA5, 0x0C,
+                0xA0, 0x03,
+                    0x0C, 0x01, 0x58, // name assigner
+                0xA1, 0x05,
+                    0x0C, 0x03, 0x45, 0x44, 0x49, // party name
+        ];
+        let expected = GeneralName::new_edi_party_name("EDI", Some("X"));
+        check_serde!(expected: GeneralName in encoded);
+
+        let encoded = [0xA5, 0x07, 0xA1, 0x05, 0x0C, 0x03, 0x45, 0x44, 0x49];
+        let expected = GeneralName::new_edi_party_name("EDI", None::<&str>);
+        check_serde!(expected: GeneralName in encoded);
+    }
+
+    #[test]
+    fn general_name_registered_id() {
+        let encoded = [0x88, 0x03, 0x2A, 0x03, 0x04];
+        let expected = GeneralName::RegisteredId(ObjectIdentifier::try_from("1.2.3.4").unwrap().into());
+        check_serde!(expected: GeneralName in encoded);
+    }
 }
@@ -0,0 +1,395 @@
+use crate::{
+    x509::{
+        ocsp::{CertId, SingleResponse},
+        private::{name::GeneralName, Certificate, Name},
+        Extensions,
+    },
+    AlgorithmIdentifier,
+};
+use picky_asn1::{
+    tag::{Tag, TagPeeker},
+    wrapper::{
+        ApplicationTag0, ApplicationTag1, ApplicationTag2, Asn1SequenceOf, BitStringAsn1, GeneralizedTimeAsn1,
+        ObjectIdentifierAsn1, OctetStringAsn1,
+    },
+};
+use picky_asn1_der::Asn1RawDer;
+use serde::{de, ser, Deserialize, Serialize};
+use std::fmt;
+
+/// https://tools.ietf.org/html/rfc6960#section-4.1.1
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub(crate) struct OCSPRequest {
+    pub tbs_request: TBSRequest,
+    /// Signed requests are not supported, the signature is kept as-is
+    pub optional_signature: Option<ApplicationTag0<Asn1RawDer>>,
+}
+
+impl<'de> de::Deserialize<'de> for OCSPRequest {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = OCSPRequest;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("struct OCSPRequest")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<Self::Value, V::Error>
+            where
+                V: de::SeqAccess<'de>,
+            {
+                let tbs_request = seq_next_element!(seq, OCSPRequest, "tbs request");
+                let optional_signature = match seq.next_element::<TagPeeker>()? {
+                    Some(_) => Some(seq_next_element!(seq, OCSPRequest, "optional signature")),
+                    None => None,
+                };
+
+                Ok(OCSPRequest {
+                    tbs_request,
+                    optional_signature,
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub(crate) struct TBSRequest {
+    /// v1 (0) is the default and omitted
+    pub version: Option<ApplicationTag0<u8>>,
+    pub requestor_name: Option<ApplicationTag1<GeneralName>>,
+    pub request_list: Asn1SequenceOf<Request>,
+    pub request_extensions: Option<ApplicationTag2<Extensions>>,
+}
+
+impl<'de> de::Deserialize<'de> for TBSRequest {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = TBSRequest;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("struct TBSRequest")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<Self::Value, V::Error>
+            where
+                V: de::SeqAccess<'de>,
+            {
+                let mut next_tag = seq.next_element::<TagPeeker>()?.map(|peeker| peeker.next_tag);
+
+                let version = match next_tag {
+                    Some(Tag::APP_0) => {
+                        let version = seq_next_element!(seq, TBSRequest, "version");
+                        next_tag = seq.next_element::<TagPeeker>()?.map(|peeker| peeker.next_tag);
+                        Some(version)
+                    }
+                    _ => None,
+                };
+
+                let requestor_name = match next_tag {
+                    Some(Tag::APP_1) => Some(seq_next_element!(seq, TBSRequest, "requestor name")),
+                    _ => None,
+                };
+
+                let request_list = seq_next_element!(seq, TBSRequest, "request list");
+
+                let request_extensions = match seq.next_element::<TagPeeker>()?.map(|peeker| peeker.next_tag) {
+                    Some(Tag::APP_2) => Some(seq_next_element!(seq, TBSRequest, "request extensions")),
+                    Some(_) => {
+                        return Err(serde_invalid_value!(
+                            TBSRequest,
+                            "unexpected trailing element",
+                            "request extensions"
+                        ))
+                    }
+                    None => None,
+                };
+
+                Ok(TBSRequest {
+                    version,
+                    requestor_name,
+                    request_list,
+                    request_extensions,
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub(crate) struct Request {
+    pub req_cert: CertId,
+    pub single_request_extensions: Option<ApplicationTag0<Extensions>>,
+}
+
+impl<'de> de::Deserialize<'de> for Request {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = Request;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("struct Request")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<Self::Value, V::Error>
+            where
+                V: de::SeqAccess<'de>,
+            {
+                let req_cert = seq_next_element!(seq, Request, "req cert");
+                let single_request_extensions = match seq.next_element::<TagPeeker>()? {
+                    Some(_) => Some(seq_next_element!(seq, Request, "single request extensions")),
+                    None => None,
+                };
+
+                Ok(Request {
+                    req_cert,
+                    single_request_extensions,
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+/// https://tools.ietf.org/html/rfc6960#section-4.2.1
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub(crate) struct OCSPResponse {
+    /// ENUMERATED
+    pub response_status: Asn1RawDer,
+    pub response_bytes: Option<ApplicationTag0<ResponseBytes>>,
+}
+
+impl<'de> de::Deserialize<'de> for OCSPResponse {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = OCSPResponse;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("struct OCSPResponse")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<Self::Value, V::Error>
+            where
+                V: de::SeqAccess<'de>,
+            {
+                let response_status = seq_next_element!(seq, OCSPResponse, "response status");
+                let response_bytes = match seq.next_element::<TagPeeker>()? {
+                    Some(_) => Some(seq_next_element!(seq, OCSPResponse, "response bytes")),
+                    None => None,
+                };
+
+                Ok(OCSPResponse {
+                    response_status,
+                    response_bytes,
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct ResponseBytes {
+    pub response_type: ObjectIdentifierAsn1,
+    /// DER-encoded response of type `response_type`
+    pub response: OctetStringAsn1,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub(crate) struct BasicOCSPResponse {
+    pub tbs_response_data: ResponseData,
+    pub signature_algorithm: AlgorithmIdentifier,
+    pub signature: BitStringAsn1,
+    pub certs: Option<ApplicationTag0<Asn1SequenceOf<Certificate>>>,
+}
+
+impl<'de> de::Deserialize<'de> for BasicOCSPResponse {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = BasicOCSPResponse;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("struct BasicOCSPResponse")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<Self::Value, V::Error>
+            where
+                V: de::SeqAccess<'de>,
+            {
+                let tbs_response_data = seq_next_element!(seq, BasicOCSPResponse, "tbs response data");
+                let signature_algorithm = seq_next_element!(seq, BasicOCSPResponse, "signature algorithm");
+                let signature = seq_next_element!(seq, BasicOCSPResponse, "signature");
+                let certs = match seq.next_element::<TagPeeker>()? {
+                    Some(_) => Some(seq_next_element!(seq, BasicOCSPResponse, "certs")),
+                    None => None,
+                };
+
+                Ok(BasicOCSPResponse {
+                    tbs_response_data,
+                    signature_algorithm,
+                    signature,
+                    certs,
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub(crate) struct ResponseData {
+    /// v1 (0) is the default and omitted
+    pub version: Option<ApplicationTag0<u8>>,
+    pub responder_id: ResponderId,
+    pub produced_at: GeneralizedTimeAsn1,
+    pub responses: Asn1SequenceOf<SingleResponse>,
+    pub response_extensions: Option<ApplicationTag1<Extensions>>,
+}
+
+impl<'de> de::Deserialize<'de> for ResponseData {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = ResponseData;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("struct ResponseData")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<Self::Value, V::Error>
+            where
+                V: de::SeqAccess<'de>,
+            {
+                let tag_peeker: TagPeeker = seq_next_element!(seq, ResponseData, "version or responder id");
+                let version = if tag_peeker.next_tag == Tag::APP_0 {
+                    Some(seq_next_element!(seq, ResponseData, "version"))
+                } else {
+                    None
+                };
+
+                let responder_id = seq_next_element!(seq, ResponseData, "responder id");
+                let produced_at = seq_next_element!(seq, ResponseData, "produced at");
+                let responses = seq_next_element!(seq, ResponseData, "responses");
+
+                let response_extensions = match seq.next_element::<TagPeeker>()?.map(|peeker| peeker.next_tag) {
+                    Some(Tag::APP_1) => Some(seq_next_element!(seq, ResponseData, "response extensions")),
+                    Some(_) => {
+                        return Err(serde_invalid_value!(
+                            ResponseData,
+                            "unexpected trailing element",
+                            "response extensions"
+                        ))
+                    }
+                    None => None,
+                };
+
+                Ok(ResponseData {
+                    version,
+                    responder_id,
+                    produced_at,
+                    responses,
+                    response_extensions,
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+// ResponderID ::= CHOICE {
+//      byName   [1] Name,
+//      byKey    [2] KeyHash }
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum ResponderId {
+    ByName(Name),
+    /// SHA-1 hash of the responder's public key
+    ByKey(OctetStringAsn1),
+}
+
+impl ser::Serialize for ResponderId {
+    fn serialize<S>(&self, serializer: S) -> Result<<S as ser::Serializer>::Ok, <S as ser::Serializer>::Error>
+    where
+        S: ser::Serializer,
+    {
+        match &self {
+            ResponderId::ByName(name) => ApplicationTag1(name).serialize(serializer),
+            ResponderId::ByKey(key_hash) => ApplicationTag2(key_hash).serialize(serializer),
+        }
+    }
+}
+
+impl<'de> de::Deserialize<'de> for ResponderId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, <D as de::Deserializer<'de>>::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = ResponderId;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a valid DER-encoded ResponderID")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::SeqAccess<'de>,
+            {
+                let tag_peeker: TagPeeker = seq_next_element!(seq, ResponderId, "choice tag");
+                match tag_peeker.next_tag {
+                    Tag::APP_1 => Ok(ResponderId::ByName(
+                        seq_next_element!(seq, ApplicationTag1<Name>, ResponderId, "byName").0,
+                    )),
+                    Tag::APP_2 => Ok(ResponderId::ByKey(
+                        seq_next_element!(seq, ApplicationTag2<OctetStringAsn1>, ResponderId, "byKey").0,
+                    )),
+                    _ => Err(serde_invalid_value!(
+                        ResponderId,
+                        "unknown choice value",
+                        "a supported ResponderID choice"
+                    )),
+                }
+            }
+        }
+
+        deserializer.deserialize_enum("ResponderId", &["ByName", "ByKey"], Visitor)
+    }
+}
@@ -0,0 +1,444 @@
+use crate::AlgorithmIdentifier;
+use picky_asn1::{
+    tag::{Tag, TagPeeker},
+    wrapper::{ApplicationTag0, Asn1SequenceOf, Asn1SetOf, IntegerAsn1, ObjectIdentifierAsn1, OctetStringAsn1},
+};
+use picky_asn1_der::Asn1RawDer;
+use serde::{de, Deserialize, Serialize};
+use std::fmt;
+
+/// https://tools.ietf.org/html/rfc7292#section-4
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub(crate) struct Pfx {
+    /// v3 (3)
+    pub version: u8,
+    pub auth_safe: ContentInfo,
+    pub mac_data: Option<MacData>,
+}
+
+impl<'de> de::Deserialize<'de> for Pfx {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = Pfx;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("struct Pfx")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<Self::Value, V::Error>
+            where
+                V: de::SeqAccess<'de>,
+            {
+                let version = seq_next_element!(seq, Pfx, "version");
+                let auth_safe = seq_next_element!(seq, Pfx, "auth safe");
+                let mac_data = match seq.next_element::<TagPeeker>()? {
+                    Some(_) => Some(seq_next_element!(seq, Pfx, "mac data")),
+                    None => None,
+                };
+
+                Ok(Pfx {
+                    version,
+                    auth_safe,
+                    mac_data,
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+/// https://tools.ietf.org/html/rfc2315#section-7
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct ContentInfo {
+    pub content_type: ObjectIdentifierAsn1,
+    /// DER-encoded content of type `content_type`
+    pub content: ApplicationTag0<Asn1RawDer>,
+}
+
+/// https://tools.ietf.org/html/rfc2315#section-13
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct EncryptedData {
+    /// v0 (0)
+    pub version: u8,
+    pub encrypted_content_info: EncryptedContentInfo,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub(crate) struct EncryptedContentInfo {
+    pub content_type: ObjectIdentifierAsn1,
+    pub content_encryption_algorithm: RawAlgorithmIdentifier,
+    /// [0] IMPLICIT OCTET STRING, kept as-is (see `decode_primitive`)
+    pub encrypted_content: Option<Asn1RawDer>,
+}
+
+impl<'de> de::Deserialize<'de> for EncryptedContentInfo {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = EncryptedContentInfo;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("struct EncryptedContentInfo")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<Self::Value, V::Error>
+            where
+                V: de::SeqAccess<'de>,
+            {
+                let content_type = seq_next_element!(seq, EncryptedContentInfo, "content type");
+                let content_encryption_algorithm =
+                    seq_next_element!(seq, EncryptedContentInfo, "content encryption algorithm");
+                let encrypted_content = match seq.next_element::<TagPeeker>()? {
+                    Some(_) => Some(seq_next_element!(seq, EncryptedContentInfo, "encrypted content")),
+                    None => None,
+                };
+
+                Ok(EncryptedContentInfo {
+                    content_type,
+                    content_encryption_algorithm,
+                    encrypted_content,
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+/// Algorithm identifier with parameters of any type, as used by the password-based encryption schemes.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub(crate) struct RawAlgorithmIdentifier {
+    pub algorithm: ObjectIdentifierAsn1,
+    pub parameters: Option<Asn1RawDer>,
+}
+
+impl<'de> de::Deserialize<'de> for RawAlgorithmIdentifier {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = RawAlgorithmIdentifier;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("struct RawAlgorithmIdentifier")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<Self::Value, V::Error>
+            where
+                V: de::SeqAccess<'de>,
+            {
+                let algorithm = seq_next_element!(seq, RawAlgorithmIdentifier, "algorithm");
+                let parameters = match seq.next_element::<TagPeeker>()? {
+                    Some(_) => Some(seq_next_element!(seq, RawAlgorithmIdentifier, "parameters")),
+                    None => None,
+                };
+
+                Ok(RawAlgorithmIdentifier { algorithm, parameters })
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+/// https://tools.ietf.org/html/rfc7292#appendix-C
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct Pkcs12PbeParams {
+    pub salt: OctetStringAsn1,
+    pub iterations: IntegerAsn1,
+}
+
+/// https://tools.ietf.org/html/rfc8018#appendix-A.4
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct Pbes2Params {
+    pub key_derivation_func: RawAlgorithmIdentifier,
+    pub encryption_scheme: RawAlgorithmIdentifier,
+}
+
+/// https://tools.ietf.org/html/rfc8018#appendix-A.2
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub(crate) struct Pbkdf2Params {
+    pub salt: OctetStringAsn1,
+    pub iteration_count: IntegerAsn1,
+    pub key_length: Option<IntegerAsn1>,
+    /// hmacWithSHA1 when absent
+    pub prf: Option<RawAlgorithmIdentifier>,
+}
+
+impl<'de> de::Deserialize<'de> for Pbkdf2Params {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = Pbkdf2Params;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("struct Pbkdf2Params")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<Self::Value, V::Error>
+            where
+                V: de::SeqAccess<'de>,
+            {
+                let salt = seq_next_element!(seq, Pbkdf2Params, "salt");
+                let iteration_count = seq_next_element!(seq, Pbkdf2Params, "iteration count");
+
+                let mut next_tag = seq.next_element::<TagPeeker>()?.map(|peeker| peeker.next_tag);
+
+                let key_length = match next_tag {
+                    Some(Tag::INTEGER) => {
+                        let key_length = seq_next_element!(seq, Pbkdf2Params, "key length");
+                        next_tag = seq.next_element::<TagPeeker>()?.map(|peeker| peeker.next_tag);
+                        Some(key_length)
+                    }
+                    _ => None,
+                };
+
+                let prf = match next_tag {
+                    Some(Tag::SEQUENCE) => Some(seq_next_element!(seq, Pbkdf2Params, "prf")),
+                    Some(_) => return Err(serde_invalid_value!(Pbkdf2Params, "unexpected trailing element", "prf")),
+                    None => None,
+                };
+
+                Ok(Pbkdf2Params {
+                    salt,
+                    iteration_count,
+                    key_length,
+                    prf,
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+/// https://tools.ietf.org/html/rfc7292#section-4
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub(crate) struct MacData {
+    pub mac: DigestInfo,
+    pub mac_salt: OctetStringAsn1,
+    /// 1 when absent
+    pub iterations: Option<IntegerAsn1>,
+}
+
+impl<'de> de::Deserialize<'de> for MacData {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = MacData;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("struct MacData")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<Self::Value, V::Error>
+            where
+                V: de::SeqAccess<'de>,
+            {
+                let mac = seq_next_element!(seq, MacData, "mac");
+                let mac_salt = seq_next_element!(seq, MacData, "mac salt");
+                let iterations = match seq.next_element::<TagPeeker>()? {
+                    Some(_) => Some(seq_next_element!(seq, MacData, "iterations")),
+                    None => None,
+                };
+
+                Ok(MacData {
+                    mac,
+                    mac_salt,
+                    iterations,
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+/// https://tools.ietf.org/html/rfc2315#section-9.4
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct DigestInfo {
+    pub digest_algorithm: AlgorithmIdentifier,
+    pub digest: OctetStringAsn1,
+}
+
+pub(crate) type AuthenticatedSafe = Asn1SequenceOf<ContentInfo>;
+
+pub(crate) type SafeContents = Asn1SequenceOf<SafeBag>;
+
+/// https://tools.ietf.org/html/rfc7292#section-4.2
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub(crate) struct SafeBag {
+    pub bag_id: ObjectIdentifierAsn1,
+    /// DER-encoded bag of type `bag_id`
+    pub bag_value: ApplicationTag0<Asn1RawDer>,
+    pub bag_attributes: Option<Asn1SetOf<Pkcs12Attribute>>,
+}
+
+impl<'de> de::Deserialize<'de> for SafeBag {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = SafeBag;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("struct SafeBag")
+            }
+
+            fn visit_seq<V>(self, mut seq: V) -> Result<Self::Value, V::Error>
+            where
+                V: de::SeqAccess<'de>,
+            {
+                let bag_id = seq_next_element!(seq, SafeBag, "bag id");
+                let bag_value = seq_next_element!(seq, SafeBag, "bag value");
+                let bag_attributes = match seq.next_element::<TagPeeker>()? {
+                    Some(_) => Some(seq_next_element!(seq, SafeBag, "bag attributes")),
+                    None => None,
+                };
+
+                Ok(SafeBag {
+                    bag_id,
+                    bag_value,
+                    bag_attributes,
+                })
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct Pkcs12Attribute {
+    pub attr_id: ObjectIdentifierAsn1,
+    pub attr_values: Asn1SetOf<Asn1RawDer>,
+}
+
+/// https://tools.ietf.org/html/rfc7292#section-4.2.3
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct CertBag {
+    pub cert_id: ObjectIdentifierAsn1,
+    /// DER-encoded certificate of type `cert_id`
+    pub cert_value: ApplicationTag0<OctetStringAsn1>,
+}
+
+/// https://tools.ietf.org/html/rfc5958#section-3
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub(crate) struct EncryptedPrivateKeyInfo {
+    pub encryption_algorithm: RawAlgorithmIdentifier,
+    pub encrypted_data: OctetStringAsn1,
+}
+
+/// Encodes a primitive `tag` and its content, e.g. an implicitly tagged or a BMP string.
+pub(crate) fn encode_primitive(tag: Tag, content: &[u8]) -> Asn1RawDer {
+    let mut der = vec![tag.number()];
+    let len = content.len();
+    if len < 0x80 {
+        der.push(len as u8);
+    } else {
+        let len_bytes = len.to_be_bytes();
+        let start = len_bytes
+            .iter()
+            .position(|byte| *byte != 0)
+            .unwrap_or(len_bytes.len() - 1);
+        der.push(0x80 | (len_bytes.len() - start) as u8);
+        der.extend_from_slice(&len_bytes[start..]);
+    }
+    der.extend_from_slice(content);
+    Asn1RawDer(der)
+}
+
+/// Content of a primitive `tag`, `None` if the raw DER is of another tag or malformed.
+pub(crate) fn decode_primitive(tag: Tag, raw: &Asn1RawDer) -> Option<&[u8]> {
+    let (first, rest) = raw.0.split_first()?;
+    if *first != tag.number() {
+        return None;
+    }
+
+    let (len_byte, rest) = rest.split_first()?;
+    let (len, content) = if len_byte & 0x80 == 0 {
+        (usize::from(*len_byte), rest)
+    } else {
+        let num_bytes = usize::from(len_byte & 0x7F);
+        if num_bytes == 0 || num_bytes > std::mem::size_of::<usize>() || rest.len() < num_bytes {
+            return None;
+        }
+        let len = rest[..num_bytes]
+            .iter()
+            .fold(0usize, |len, byte| (len << 8) | usize::from(*byte));
+        (len, &rest[num_bytes..])
+    };
+
+    if content.len() == len {
+        Some(content)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn primitive_encoding() {
+        let short = encode_primitive(Tag::CTX_0, &[0x01, 0x02]);
+        assert_eq!(short.0, vec![0x80, 0x02, 0x01, 0x02]);
+        assert_eq!(decode_primitive(Tag::CTX_0, &short), Some(&[0x01, 0x02][..]));
+        assert_eq!(decode_primitive(Tag::OCTET_STRING, &short), None);
+
+        let long = encode_primitive(Tag::OCTET_STRING, &[0xAB; 300]);
+        assert_eq!(&long.0[..4], &[0x04, 0x82, 0x01, 0x2C]);
+        assert_eq!(decode_primitive(Tag::OCTET_STRING, &long), Some(&[0xAB; 300][..]));
+
+        let truncated = Asn1RawDer(long.0[..100].to_vec());
+        assert_eq!(decode_primitive(Tag::OCTET_STRING, &truncated), None);
+    }
+
+    #[test]
+    fn pbkdf2_params_optional_fields() {
+        let params = Pbkdf2Params {
+            salt: OctetStringAsn1(vec![0x01; 8]),
+            iteration_count: IntegerAsn1::from_unsigned_bytes_be(vec![0x08, 0x00]),
+            key_length: None,
+            prf: Some(RawAlgorithmIdentifier {
+                algorithm: crate::oids::hmac_with_sha256().into(),
+                parameters: Some(Asn1RawDer(vec![0x05, 0x00])),
+            }),
+        };
+        let encoded = picky_asn1_der::to_vec(&params).unwrap();
+        assert_eq!(picky_asn1_der::from_bytes::<Pbkdf2Params>(&encoded).unwrap(), params);
+
+        let params = Pbkdf2Params {
+            key_length: Some(IntegerAsn1::from_unsigned_bytes_be(vec![0x20])),
+            prf: None,
+            ..params
+        };
+        let encoded = picky_asn1_der::to_vec(&params).unwrap();
+        assert_eq!(picky_asn1_der::from_bytes::<Pbkdf2Params>(&encoded).unwrap(), params);
+    }
+}
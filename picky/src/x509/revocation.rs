@@ -0,0 +1,493 @@
+//! Revocation status of certificates, as reported by CRLs and OCSP responses.
+//!
+//! `Cert::verify_chain_with_revocation` and `ChainValidator` check a chain against any
+//! `RevocationProvider`: a set of CRLs, an OCSP lookup function or any closure returning a status.
+//! The same trait serves both, there's no separate revocation checker trait for the validator.
+//! With the `revocation_client` feature, `RevocationClient` fetches and caches the CRLs and OCSP
+//! responses advertised by the certificates themselves.
+
+use crate::{
+    oids,
+    x509::{
+        certificate::Cert,
+        crl::Crl,
+        date::UTCDate,
+        extension::CrlReason,
+        ocsp::{BasicOcspResponse, CertStatus},
+    },
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RevocationStatus {
+    Good,
+    Revoked {
+        revocation_time: UTCDate,
+        reason: Option<CrlReason>,
+    },
+    /// No current revocation information is available
+    Unknown,
+}
+
+/// Source of revocation information used when verifying a chain.
+pub trait RevocationProvider {
+    /// Revocation status of `cert`, issued by `issuer`, at `now`.
+    fn revocation_status(&self, cert: &Cert, issuer: &Cert, now: &UTCDate) -> RevocationStatus;
+}
+
+impl<F> RevocationProvider for F
+where
+    F: Fn(&Cert, &Cert, &UTCDate) -> RevocationStatus,
+{
+    fn revocation_status(&self, cert: &Cert, issuer: &Cert, now: &UTCDate) -> RevocationStatus {
+        self(cert, issuer, now)
+    }
+}
+
+/// A certificate is revoked if any of the CRLs current and signed by its issuer revokes it.
+impl RevocationProvider for Vec<Crl> {
+    fn revocation_status(&self, cert: &Cert, issuer: &Cert, now: &UTCDate) -> RevocationStatus {
+        let mut status = RevocationStatus::Unknown;
+        for crl in self {
+            match crl_status(crl, cert, issuer, now) {
+                revoked @ RevocationStatus::Revoked { .. } => return revoked,
+                RevocationStatus::Good => status = RevocationStatus::Good,
+                RevocationStatus::Unknown => {}
+            }
+        }
+        status
+    }
+}
+
+/// Revocation information from the OCSP responses returned by a lookup function, such as a query
+/// to the responder of the issuer or to a local cache. The status is unknown when the function
+/// returns no response.
+pub struct OcspLookup<F>(F);
+
+impl<F> OcspLookup<F>
+where
+    F: Fn(&Cert, &Cert) -> Option<BasicOcspResponse>,
+{
+    /// `lookup` is given the certificate and its issuer.
+    pub fn new(lookup: F) -> Self {
+        Self(lookup)
+    }
+}
+
+impl<F> RevocationProvider for OcspLookup<F>
+where
+    F: Fn(&Cert, &Cert) -> Option<BasicOcspResponse>,
+{
+    fn revocation_status(&self, cert: &Cert, issuer: &Cert, now: &UTCDate) -> RevocationStatus {
+        (self.0)(cert, issuer).map_or(RevocationStatus::Unknown, |response| {
+            ocsp_status(&response, cert, issuer, now)
+        })
+    }
+}
+
+/// Revocation status of `cert` according to `crl`.
+///
+/// The status is unknown if the CRL isn't signed by `issuer`, isn't current at `now` or its scope
+/// doesn't cover `cert` (see `crl_covers`). A delta CRL only lists the changes since its base CRL:
+/// it tells whether `cert` is revoked, never that it's good.
+pub fn crl_status(crl: &Crl, cert: &Cert, issuer: &Cert, now: &UTCDate) -> RevocationStatus {
+    if crl.verify_issuer(issuer).is_err()
+        || !is_current(crl.this_update(), crl.next_update(), now)
+        || !crl_covers(crl, cert)
+    {
+        return RevocationStatus::Unknown;
+    }
+
+    match crl.find_revoked(cert.serial_number()) {
+        // removed from the base CRL by a delta CRL, the status is the one before the hold
+        Some(revoked) if revoked.reason() == Some(CrlReason::RemoveFromCrl) => RevocationStatus::Unknown,
+        Some(revoked) if revoked.revocation_date() <= *now => RevocationStatus::Revoked {
+            revocation_time: revoked.revocation_date(),
+            reason: revoked.reason(),
+        },
+        _ if crl.base_crl_number().is_some() => RevocationStatus::Unknown,
+        _ => RevocationStatus::Good,
+    }
+}
+
+/// Whether the scope of `crl` covers `cert` ([RFC5280 section 6.3.3](https://tools.ietf.org/html/rfc5280#section-6.3.3)).
+///
+/// The issuing distribution point of the CRL, if any, must name one of the CRL distribution points of
+/// `cert` and not exclude its kind (CA or end entity). CRLs with critical extensions that can't be
+/// processed, including indirect CRLs and CRLs limited to some revocation reasons, cover nothing.
+fn crl_covers(crl: &Crl, cert: &Cert) -> bool {
+    let unsupported_critical_extension = crl.extensions().iter().any(|extension| {
+        let id = &extension.extn_id().0;
+        extension.critical() && *id != oids::issuing_distribution_point() && *id != oids::delta_crl_indicator()
+    });
+    if unsupported_critical_extension {
+        return false;
+    }
+
+    let idp = match crl.issuing_distribution_point() {
+        Ok(Some(idp)) => idp,
+        Ok(None) => return true,
+        Err(_) => return false,
+    };
+    if idp.is_indirect_crl() {
+        return false;
+    }
+
+    let is_ca = cert
+        .basic_constraints()
+        .ok()
+        .and_then(|basic_constraints| basic_constraints.ca())
+        .unwrap_or(false);
+    if (idp.contains_only_user_certs() && is_ca) || (idp.contains_only_ca_certs() && !is_ca) {
+        return false;
+    }
+
+    match idp.distribution_point_uri() {
+        Some(uri) => cert
+            .crl_distribution_points()
+            .map_or(false, |crl_dp| crl_dp.uris().contains(uri)),
+        None => true,
+    }
+}
+
+/// Revocation status of `cert` according to `response`.
+///
+/// The status is unknown if the response isn't signed by `issuer` (or a responder it delegated, valid at `now`),
+/// doesn't cover `cert` or isn't current at `now`.
+pub fn ocsp_status(response: &BasicOcspResponse, cert: &Cert, issuer: &Cert, now: &UTCDate) -> RevocationStatus {
+    if response.verify_issuer(issuer, now).is_err() {
+        return RevocationStatus::Unknown;
+    }
+
+    // the response may identify the certificate using any hash algorithm
+    let single_response = response.responses().iter().find(|single_response| {
+        let cert_id = single_response.cert_id();
+        cert_id.serial_number() == cert.serial_number() && cert_id.is_issued_by(issuer).unwrap_or(false)
+    });
+
+    match single_response {
+        Some(single_response) if is_current(single_response.this_update(), single_response.next_update(), now) => {
+            match single_response.cert_status() {
+                CertStatus::Good => RevocationStatus::Good,
+                CertStatus::Revoked {
+                    revocation_time,
+                    reason,
+                } => RevocationStatus::Revoked {
+                    revocation_time: revocation_time.clone(),
+                    reason: *reason,
+                },
+                CertStatus::Unknown => RevocationStatus::Unknown,
+            }
+        }
+        _ => RevocationStatus::Unknown,
+    }
+}
+
+/// Information without next update is only current when produced.
+pub(crate) fn is_current(this_update: UTCDate, next_update: Option<UTCDate>, now: &UTCDate) -> bool {
+    this_update <= *now && next_update.map_or(false, |next_update| *now < next_update)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        key::PrivateKey,
+        pem::parse_pem,
+        x509::{
+            certificate::{CertError, CertificateBuilder},
+            crl::RevokedCertificate,
+            extension::{CrlDistributionPoints, IssuingDistributionPoint},
+            name::DirectoryName,
+            ocsp::{CertId, OcspHashAlgorithm, SingleResponse},
+        },
+    };
+    use std::collections::HashMap;
+
+    fn issuer() -> (Cert, PrivateKey) {
+        let pem = parse_pem(crate::test_files::RSA_2048_PK_1).unwrap();
+        let key = PrivateKey::from_pkcs8(pem.data()).unwrap();
+        let cert = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2020, 1, 1).unwrap(), UTCDate::ymd(2030, 1, 1).unwrap())
+            .self_signed(DirectoryName::new_common_name("Picky Revocation Issuer"), &key)
+            .ca(true)
+            .build()
+            .unwrap();
+        (cert, key)
+    }
+
+    fn leaf(issuer_cert: &Cert, issuer_key: &PrivateKey, serial_number: u8) -> Cert {
+        CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2020, 1, 1).unwrap(), UTCDate::ymd(2021, 1, 1).unwrap())
+            .subject(
+                DirectoryName::new_common_name(format!("Leaf {}", serial_number)),
+                issuer_key.to_public_key(),
+            )
+            .issuer_cert(issuer_cert, issuer_key)
+            .serial_number(vec![serial_number])
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn crl() {
+        let (issuer_cert, issuer_key) = issuer();
+        let good = leaf(&issuer_cert, &issuer_key, 0x0A);
+        let revoked = leaf(&issuer_cert, &issuer_key, 0x0B);
+
+        let crl = Crl::builder()
+            .issuer_cert(&issuer_cert, &issuer_key)
+            .this_update(UTCDate::ymd(2020, 6, 1).unwrap())
+            .next_update(UTCDate::ymd(2020, 6, 8).unwrap())
+            .revoked_certificate(
+                RevokedCertificate::new(vec![0x0B].into(), UTCDate::ymd(2020, 5, 20).unwrap())
+                    .with_reason(CrlReason::KeyCompromise),
+            )
+            .build()
+            .unwrap();
+
+        let now = UTCDate::ymd(2020, 6, 2).unwrap();
+        assert_eq!(crl_status(&crl, &good, &issuer_cert, &now), RevocationStatus::Good);
+        assert_eq!(
+            crl_status(&crl, &revoked, &issuer_cert, &now),
+            RevocationStatus::Revoked {
+                revocation_time: UTCDate::ymd(2020, 5, 20).unwrap(),
+                reason: Some(CrlReason::KeyCompromise),
+            }
+        );
+
+        let expired = UTCDate::ymd(2020, 6, 9).unwrap();
+        assert_eq!(
+            crl_status(&crl, &revoked, &issuer_cert, &expired),
+            RevocationStatus::Unknown
+        );
+
+        // not signed by the issuer
+        assert_eq!(crl_status(&crl, &revoked, &good, &now), RevocationStatus::Unknown);
+
+        let outdated_crl = Crl::builder()
+            .issuer_cert(&issuer_cert, &issuer_key)
+            .this_update(UTCDate::ymd(2020, 5, 1).unwrap())
+            .next_update(UTCDate::ymd(2020, 5, 8).unwrap())
+            .build()
+            .unwrap();
+        let crls = vec![outdated_crl, crl];
+        assert_eq!(
+            crls.revocation_status(&good, &issuer_cert, &now),
+            RevocationStatus::Good
+        );
+        assert_eq!(
+            crls.revocation_status(&revoked, &issuer_cert, &now),
+            RevocationStatus::Revoked {
+                revocation_time: UTCDate::ymd(2020, 5, 20).unwrap(),
+                reason: Some(CrlReason::KeyCompromise),
+            }
+        );
+        assert_eq!(
+            crls.revocation_status(&good, &issuer_cert, &expired),
+            RevocationStatus::Unknown
+        );
+    }
+
+    #[test]
+    fn crl_scope() {
+        let (issuer_cert, issuer_key) = issuer();
+        let leaf_with_crl_dp = |serial_number: u8, crl_url: &str| {
+            CertificateBuilder::new()
+                .valididy(UTCDate::ymd(2020, 1, 1).unwrap(), UTCDate::ymd(2021, 1, 1).unwrap())
+                .subject(
+                    DirectoryName::new_common_name(format!("Leaf {}", serial_number)),
+                    issuer_key.to_public_key(),
+                )
+                .issuer_cert(&issuer_cert, &issuer_key)
+                .serial_number(vec![serial_number])
+                .crl_distribution_points(CrlDistributionPoints::new().uri(crl_url).unwrap())
+                .build()
+                .unwrap()
+        };
+        let in_partition = leaf_with_crl_dp(0x0A, "http://crl.example.com/1");
+        let in_other_partition = leaf_with_crl_dp(0x0B, "http://crl.example.com/2");
+        let without_crl_dp = leaf(&issuer_cert, &issuer_key, 0x0C);
+
+        let partition_crl = Crl::builder()
+            .issuer_cert(&issuer_cert, &issuer_key)
+            .this_update(UTCDate::ymd(2020, 6, 1).unwrap())
+            .next_update(UTCDate::ymd(2020, 6, 8).unwrap())
+            .issuing_distribution_point(
+                IssuingDistributionPoint::new()
+                    .uri("http://crl.example.com/1")
+                    .unwrap()
+                    .only_contains_user_certs(true),
+            )
+            .build()
+            .unwrap();
+
+        let now = UTCDate::ymd(2020, 6, 2).unwrap();
+        assert_eq!(
+            crl_status(&partition_crl, &in_partition, &issuer_cert, &now),
+            RevocationStatus::Good
+        );
+        assert_eq!(
+            crl_status(&partition_crl, &in_other_partition, &issuer_cert, &now),
+            RevocationStatus::Unknown
+        );
+        assert_eq!(
+            crl_status(&partition_crl, &without_crl_dp, &issuer_cert, &now),
+            RevocationStatus::Unknown
+        );
+        // CA certificates aren't listed
+        assert_eq!(
+            crl_status(&partition_crl, &issuer_cert, &issuer_cert, &now),
+            RevocationStatus::Unknown
+        );
+
+        let delta_crl = Crl::builder()
+            .issuer_cert(&issuer_cert, &issuer_key)
+            .this_update(UTCDate::ymd(2020, 6, 1).unwrap())
+            .next_update(UTCDate::ymd(2020, 6, 8).unwrap())
+            .crl_number(vec![0x02])
+            .base_crl_number(vec![0x01])
+            .revoked_certificate(RevokedCertificate::new(
+                vec![0x0B].into(),
+                UTCDate::ymd(2020, 5, 20).unwrap(),
+            ))
+            .revoked_certificate(
+                RevokedCertificate::new(vec![0x0C].into(), UTCDate::ymd(2020, 5, 20).unwrap())
+                    .with_reason(CrlReason::RemoveFromCrl),
+            )
+            .build()
+            .unwrap();
+        assert_eq!(
+            crl_status(&delta_crl, &in_partition, &issuer_cert, &now),
+            RevocationStatus::Unknown
+        );
+        assert_eq!(
+            crl_status(&delta_crl, &in_other_partition, &issuer_cert, &now),
+            RevocationStatus::Revoked {
+                revocation_time: UTCDate::ymd(2020, 5, 20).unwrap(),
+                reason: None,
+            }
+        );
+        assert_eq!(
+            crl_status(&delta_crl, &without_crl_dp, &issuer_cert, &now),
+            RevocationStatus::Unknown
+        );
+    }
+
+    #[test]
+    fn ocsp() {
+        let (issuer_cert, issuer_key) = issuer();
+        let good = leaf(&issuer_cert, &issuer_key, 0x0A);
+        let revoked = leaf(&issuer_cert, &issuer_key, 0x0B);
+        let absent = leaf(&issuer_cert, &issuer_key, 0x0C);
+
+        let response = BasicOcspResponse::builder()
+            .responder(issuer_cert.clone(), &issuer_key)
+            .produced_at(UTCDate::ymd(2020, 6, 1).unwrap())
+            .response(
+                SingleResponse::new(
+                    CertId::new(&good, &issuer_cert).unwrap(),
+                    CertStatus::Good,
+                    UTCDate::ymd(2020, 6, 1).unwrap(),
+                )
+                .with_next_update(UTCDate::ymd(2020, 6, 2).unwrap()),
+            )
+            .response(
+                SingleResponse::new(
+                    CertId::from_serial_number(&issuer_cert, vec![0x0B].into(), OcspHashAlgorithm::Sha256).unwrap(),
+                    CertStatus::Revoked {
+                        revocation_time: UTCDate::ymd(2020, 5, 20).unwrap(),
+                        reason: None,
+                    },
+                    UTCDate::ymd(2020, 6, 1).unwrap(),
+                )
+                .with_next_update(UTCDate::ymd(2020, 6, 2).unwrap()),
+            )
+            .build()
+            .unwrap();
+
+        let now = UTCDate::new(2020, 6, 1, 12, 0, 0).unwrap();
+        assert_eq!(
+            ocsp_status(&response, &good, &issuer_cert, &now),
+            RevocationStatus::Good
+        );
+        assert_eq!(
+            ocsp_status(&response, &revoked, &issuer_cert, &now),
+            RevocationStatus::Revoked {
+                revocation_time: UTCDate::ymd(2020, 5, 20).unwrap(),
+                reason: None,
+            }
+        );
+        assert_eq!(
+            ocsp_status(&response, &absent, &issuer_cert, &now),
+            RevocationStatus::Unknown
+        );
+
+        let expired = UTCDate::ymd(2020, 6, 3).unwrap();
+        assert_eq!(
+            ocsp_status(&response, &good, &issuer_cert, &expired),
+            RevocationStatus::Unknown
+        );
+
+        let lookup = OcspLookup::new(|cert: &Cert, _: &Cert| {
+            if cert.serial_number() == absent.serial_number() {
+                None
+            } else {
+                Some(response.clone())
+            }
+        });
+        assert_eq!(
+            lookup.revocation_status(&revoked, &issuer_cert, &now),
+            RevocationStatus::Revoked {
+                revocation_time: UTCDate::ymd(2020, 5, 20).unwrap(),
+                reason: None,
+            }
+        );
+        assert_eq!(
+            lookup.revocation_status(&absent, &issuer_cert, &now),
+            RevocationStatus::Unknown
+        );
+    }
+
+    struct StaticProvider(HashMap<Vec<u8>, RevocationStatus>);
+
+    impl RevocationProvider for StaticProvider {
+        fn revocation_status(&self, cert: &Cert, _: &Cert, _: &UTCDate) -> RevocationStatus {
+            self.0
+                .get(cert.serial_number().as_unsigned_bytes_be())
+                .cloned()
+                .unwrap_or(RevocationStatus::Unknown)
+        }
+    }
+
+    #[test]
+    fn chain_with_revocation() {
+        let (issuer_cert, issuer_key) = issuer();
+        let good = leaf(&issuer_cert, &issuer_key, 0x0A);
+        let revoked = leaf(&issuer_cert, &issuer_key, 0x0B);
+        let unknown = leaf(&issuer_cert, &issuer_key, 0x0C);
+
+        let mut statuses = HashMap::new();
+        statuses.insert(vec![0x0A], RevocationStatus::Good);
+        statuses.insert(
+            vec![0x0B],
+            RevocationStatus::Revoked {
+                revocation_time: UTCDate::ymd(2020, 5, 20).unwrap(),
+                reason: None,
+            },
+        );
+        let provider = StaticProvider(statuses);
+
+        let now = UTCDate::ymd(2020, 6, 1).unwrap();
+        good.verify_chain_with_revocation(std::iter::once(&issuer_cert), &now, &provider)
+            .expect("good certificate");
+        unknown
+            .verify_chain_with_revocation(std::iter::once(&issuer_cert), &now, &provider)
+            .expect("unknown status is accepted");
+        match revoked.verify_chain_with_revocation(std::iter::once(&issuer_cert), &now, &provider) {
+            Err(CertError::CertificateRevoked { revocation_time, .. }) => {
+                assert_eq!(revocation_time, UTCDate::ymd(2020, 5, 20).unwrap())
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
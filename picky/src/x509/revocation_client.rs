@@ -0,0 +1,347 @@
+//! Asynchronous client fetching the revocation information advertised by certificates.
+//!
+//! OCSP responders listed in the authority information access extension are queried first, then
+//! the CRLs listed in the CRL distribution points extension are downloaded. Verified responses
+//! and CRLs are cached on disk and reused until their next update.
+
+use crate::x509::{
+    certificate::Cert,
+    crl::{Crl, CrlError},
+    date::UTCDate,
+    ocsp::{CertId, OcspError, OcspRequest, OcspResponse},
+    revocation::{crl_status, ocsp_status, RevocationProvider, RevocationStatus},
+};
+use picky_asn1::restricted_string::IA5String;
+use sha2::{Digest, Sha256};
+use snafu::{ResultExt, Snafu};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const OCSP_REQUEST_CONTENT_TYPE: &str = "application/ocsp-request";
+
+#[derive(Debug, Snafu)]
+pub enum RevocationClientError {
+    /// couldn't reach the server
+    #[snafu(display("couldn't fetch {}: {}", url, source))]
+    Fetch { url: String, source: reqwest::Error },
+
+    /// server answered with an error
+    #[snafu(display("{} answered with HTTP status {}", url, status))]
+    HttpStatus { url: String, status: u16 },
+
+    /// downloaded CRL is invalid
+    #[snafu(display("invalid CRL from {}: {}", url, source))]
+    InvalidCrl { url: String, source: CrlError },
+
+    /// OCSP response is invalid or unsuccessful
+    #[snafu(display("invalid OCSP response from {}: {}", url, source))]
+    InvalidOcspResponse { url: String, source: OcspError },
+
+    /// couldn't generate the OCSP request
+    #[snafu(display("couldn't generate OCSP request: {}", source))]
+    OcspRequestGeneration { source: OcspError },
+
+    /// couldn't write to the cache
+    #[snafu(display("couldn't write cache entry {}: {}", path.display(), source))]
+    Cache { path: PathBuf, source: std::io::Error },
+}
+
+/// Fetches and caches CRLs and OCSP responses.
+///
+/// As a `RevocationProvider`, the client only answers from its cache: `prefetch` the chain
+/// before verifying it.
+pub struct RevocationClient {
+    http_client: reqwest::Client,
+    cache_dir: PathBuf,
+}
+
+impl RevocationClient {
+    /// Client caching into `cache_dir`, which is created when missing.
+    pub fn new<P: Into<PathBuf>>(cache_dir: P) -> Self {
+        Self {
+            http_client: reqwest::Client::new(),
+            cache_dir: cache_dir.into(),
+        }
+    }
+
+    /// Uses the given HTTP client, e.g. to configure a proxy or timeouts.
+    pub fn with_http_client(mut self, http_client: reqwest::Client) -> Self {
+        self.http_client = http_client;
+        self
+    }
+
+    /// Revocation status of `cert`, issued by `issuer`, at `now`.
+    ///
+    /// Cached information is used while current. Otherwise each OCSP responder then each CRL
+    /// distribution point is tried until one of them knows the status. An error is only returned
+    /// if all the advertised sources failed.
+    pub async fn status(
+        &self,
+        cert: &Cert,
+        issuer: &Cert,
+        now: &UTCDate,
+    ) -> Result<RevocationStatus, RevocationClientError> {
+        let cached = self.cached_status(cert, issuer, now);
+        if cached != RevocationStatus::Unknown {
+            return Ok(cached);
+        }
+
+        let mut last_error = None;
+        let mut answered = false;
+
+        for url in ocsp_urls(cert) {
+            match self.fetch_ocsp(&url, cert, issuer, now).await {
+                Ok(RevocationStatus::Unknown) => answered = true,
+                Ok(status) => return Ok(status),
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        for url in crl_urls(cert) {
+            match self.fetch_crl(&url, cert, issuer, now).await {
+                Ok(RevocationStatus::Unknown) => answered = true,
+                Ok(status) => return Ok(status),
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        match last_error {
+            Some(e) if !answered => Err(e),
+            _ => Ok(RevocationStatus::Unknown),
+        }
+    }
+
+    /// Fetches the revocation information of each certificate of `cert` and its `chain`.
+    pub async fn prefetch(&self, cert: &Cert, chain: &[Cert], now: &UTCDate) -> Result<(), RevocationClientError> {
+        let mut current_cert = cert;
+        for issuer_cert in chain {
+            self.status(current_cert, issuer_cert, now).await?;
+            current_cert = issuer_cert;
+        }
+        Ok(())
+    }
+
+    async fn fetch_ocsp(
+        &self,
+        url: &str,
+        cert: &Cert,
+        issuer: &Cert,
+        now: &UTCDate,
+    ) -> Result<RevocationStatus, RevocationClientError> {
+        let request_der = CertId::new(cert, issuer)
+            .and_then(|cert_id| OcspRequest::new(vec![cert_id], None))
+            .and_then(|request| request.to_der())
+            .context(OcspRequestGeneration)?;
+
+        let response = self
+            .http_client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, OCSP_REQUEST_CONTENT_TYPE)
+            .body(request_der)
+            .send()
+            .await
+            .context(Fetch { url })?;
+        let response_der = read_body(url, response).await?;
+
+        let basic_response = OcspResponse::from_der(&response_der)
+            .and_then(|response| response.basic_response())
+            .context(InvalidOcspResponse { url })?;
+        let status = ocsp_status(&basic_response, cert, issuer, now);
+        if status != RevocationStatus::Unknown {
+            self.store(&ocsp_cache_path(&self.cache_dir, cert, issuer), &response_der)?;
+        }
+
+        Ok(status)
+    }
+
+    async fn fetch_crl(
+        &self,
+        url: &str,
+        cert: &Cert,
+        issuer: &Cert,
+        now: &UTCDate,
+    ) -> Result<RevocationStatus, RevocationClientError> {
+        let response = self.http_client.get(url).send().await.context(Fetch { url })?;
+        let crl_der = read_body(url, response).await?;
+
+        let crl = Crl::from_der(&crl_der).context(InvalidCrl { url })?;
+        let status = crl_status(&crl, cert, issuer, now);
+        if status != RevocationStatus::Unknown {
+            self.store(&crl_cache_path(&self.cache_dir, url), &crl_der)?;
+        }
+
+        Ok(status)
+    }
+
+    fn cached_status(&self, cert: &Cert, issuer: &Cert, now: &UTCDate) -> RevocationStatus {
+        let cached_ocsp = fs::read(ocsp_cache_path(&self.cache_dir, cert, issuer))
+            .ok()
+            .and_then(|der| OcspResponse::from_der(&der).ok())
+            .and_then(|response| response.basic_response().ok())
+            .map(|basic_response| ocsp_status(&basic_response, cert, issuer, now));
+        if let Some(status) = cached_ocsp.filter(|status| *status != RevocationStatus::Unknown) {
+            return status;
+        }
+
+        crl_urls(cert)
+            .into_iter()
+            .filter_map(|url| fs::read(crl_cache_path(&self.cache_dir, &url)).ok())
+            .filter_map(|der| Crl::from_der(&der).ok())
+            .map(|crl| crl_status(&crl, cert, issuer, now))
+            .find(|status| *status != RevocationStatus::Unknown)
+            .unwrap_or(RevocationStatus::Unknown)
+    }
+
+    /// Writes a cache entry, through a temporary file so readers never see a partial entry.
+    fn store(&self, path: &Path, der: &[u8]) -> Result<(), RevocationClientError> {
+        let tmp_path = path.with_extension("tmp");
+        fs::create_dir_all(&self.cache_dir)
+            .and_then(|_| fs::write(&tmp_path, der))
+            .and_then(|_| fs::rename(&tmp_path, path))
+            .context(Cache { path })
+    }
+}
+
+impl RevocationProvider for RevocationClient {
+    fn revocation_status(&self, cert: &Cert, issuer: &Cert, now: &UTCDate) -> RevocationStatus {
+        self.cached_status(cert, issuer, now)
+    }
+}
+
+async fn read_body(url: &str, response: reqwest::Response) -> Result<Vec<u8>, RevocationClientError> {
+    let status = response.status();
+    if !status.is_success() {
+        return Err(RevocationClientError::HttpStatus {
+            url: url.to_owned(),
+            status: status.as_u16(),
+        });
+    }
+
+    let body = response.bytes().await.context(Fetch { url })?;
+    Ok(body.to_vec())
+}
+
+fn ocsp_urls(cert: &Cert) -> Vec<String> {
+    cert.authority_info_access()
+        .map(|aia| http_urls(aia.ocsp_uris()))
+        .unwrap_or_default()
+}
+
+fn crl_urls(cert: &Cert) -> Vec<String> {
+    cert.crl_distribution_points()
+        .map(|crldp| http_urls(&crldp.uris()))
+        .unwrap_or_default()
+}
+
+/// Only HTTP URLs are supported (LDAP distribution points are ignored).
+fn http_urls(uris: &[IA5String]) -> Vec<String> {
+    uris.iter()
+        .filter_map(|uri| std::str::from_utf8(uri.as_bytes()).ok())
+        .filter(|uri| uri.starts_with("http://") || uri.starts_with("https://"))
+        .map(str::to_owned)
+        .collect()
+}
+
+fn crl_cache_path(cache_dir: &Path, url: &str) -> PathBuf {
+    cache_dir.join(format!("crl-{}.der", sha256_hex(url.as_bytes())))
+}
+
+fn ocsp_cache_path(cache_dir: &Path, cert: &Cert, issuer: &Cert) -> PathBuf {
+    // cached responses are verified again when read, the key only has to be distinctive
+    let mut key = issuer.subject_name().to_string().into_bytes();
+    key.extend_from_slice(issuer.serial_number().as_unsigned_bytes_be());
+    key.extend_from_slice(cert.serial_number().as_unsigned_bytes_be());
+    cache_dir.join(format!("ocsp-{}.der", sha256_hex(&key)))
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        key::PrivateKey,
+        pem::parse_pem,
+        x509::{
+            certificate::CertificateBuilder,
+            crl::RevokedCertificate,
+            extension::{CrlDistributionPoints, CrlReason},
+            name::DirectoryName,
+        },
+    };
+
+    const CRL_URL: &str = "http://crl.example.com/issuer.crl";
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("picky-revocation-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn cached_crl() {
+        let pem = parse_pem(crate::test_files::RSA_2048_PK_1).unwrap();
+        let key = PrivateKey::from_pkcs8(pem.data()).unwrap();
+        let issuer_cert = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2020, 1, 1).unwrap(), UTCDate::ymd(2030, 1, 1).unwrap())
+            .self_signed(DirectoryName::new_common_name("Picky Revocation Issuer"), &key)
+            .ca(true)
+            .build()
+            .unwrap();
+        let leaf = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2020, 1, 1).unwrap(), UTCDate::ymd(2021, 1, 1).unwrap())
+            .subject(DirectoryName::new_common_name("Picky Leaf"), key.to_public_key())
+            .issuer_cert(&issuer_cert, &key)
+            .serial_number(vec![0x0B])
+            .crl_distribution_points(CrlDistributionPoints::new().uri(CRL_URL).unwrap())
+            .build()
+            .unwrap();
+        assert_eq!(crl_urls(&leaf), vec![CRL_URL.to_owned()]);
+        assert!(ocsp_urls(&leaf).is_empty());
+
+        let crl = Crl::builder()
+            .issuer_cert(&issuer_cert, &key)
+            .this_update(UTCDate::ymd(2020, 6, 1).unwrap())
+            .next_update(UTCDate::ymd(2020, 6, 8).unwrap())
+            .revoked_certificate(
+                RevokedCertificate::new(vec![0x0B].into(), UTCDate::ymd(2020, 5, 20).unwrap())
+                    .with_reason(CrlReason::Superseded),
+            )
+            .build()
+            .unwrap();
+
+        let client = RevocationClient::new(cache_dir("cached-crl"));
+        let now = UTCDate::ymd(2020, 6, 2).unwrap();
+        assert_eq!(
+            client.revocation_status(&leaf, &issuer_cert, &now),
+            RevocationStatus::Unknown
+        );
+
+        client
+            .store(&crl_cache_path(&client.cache_dir, CRL_URL), &crl.to_der().unwrap())
+            .unwrap();
+        assert_eq!(
+            client.revocation_status(&leaf, &issuer_cert, &now),
+            RevocationStatus::Revoked {
+                revocation_time: UTCDate::ymd(2020, 5, 20).unwrap(),
+                reason: Some(CrlReason::Superseded),
+            }
+        );
+
+        // past the next update, the CRL has to be fetched again
+        let later = UTCDate::ymd(2020, 6, 9).unwrap();
+        assert_eq!(
+            client.revocation_status(&leaf, &issuer_cert, &later),
+            RevocationStatus::Unknown
+        );
+
+        fs::remove_dir_all(&client.cache_dir).unwrap();
+    }
+}
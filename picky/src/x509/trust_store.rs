@@ -0,0 +1,317 @@
+//! Trusted root certificates.
+//!
+//! A `TrustStore` holds the roots `Cert::verify_against` builds certification paths to. It can be
+//! filled in memory, loaded from PEM bundles or from a directory of PEM files, or (with the
+//! `system_trust_store` feature) from the bundle installed on the platform.
+
+use crate::{
+    pem::{parse_pem, PemError},
+    x509::certificate::{Cert, CertError},
+};
+use snafu::{ResultExt, Snafu};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const PEM_HEADER_START: &str = "-----BEGIN";
+const CERT_PEM_LABEL: &str = "CERTIFICATE";
+
+#[derive(Debug, Snafu)]
+pub enum TrustStoreError {
+    /// couldn't read a file or directory of roots
+    #[snafu(display("couldn't read '{}': {}", path.display(), source))]
+    Io { path: PathBuf, source: std::io::Error },
+
+    /// invalid PEM block
+    #[snafu(display("invalid PEM block: {}", source))]
+    InvalidPem { source: PemError },
+
+    /// PEM block couldn't be decoded as a certificate
+    #[snafu(display("invalid root certificate: {}", source))]
+    InvalidRoot { source: CertError },
+
+    /// no root bundle found on this platform
+    #[cfg(feature = "system_trust_store")]
+    NoSystemRoots,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrustStore {
+    roots: Vec<Cert>,
+}
+
+impl From<Vec<Cert>> for TrustStore {
+    fn from(roots: Vec<Cert>) -> Self {
+        let mut store = Self::new();
+        roots.into_iter().for_each(|root| store.add(root));
+        store
+    }
+}
+
+impl TrustStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trusts `root`, unless it already is.
+    pub fn add(&mut self, root: Cert) {
+        if !self.contains(&root) {
+            self.roots.push(root);
+        }
+    }
+
+    pub fn contains(&self, cert: &Cert) -> bool {
+        self.roots.contains(cert)
+    }
+
+    pub fn roots(&self) -> &[Cert] {
+        &self.roots
+    }
+
+    /// Trusts every certificate of a PEM bundle.
+    ///
+    /// Text around PEM blocks (such as comments of the Mozilla bundle) and blocks which aren't
+    /// certificates are ignored.
+    pub fn from_pem_bundle(bundle: &str) -> Result<Self, TrustStoreError> {
+        let mut store = Self::new();
+        store.add_pem_bundle(bundle, false)?;
+        Ok(store)
+    }
+
+    /// Trusts every certificate of the `.pem` and `.crt` files of `dir`, other files are ignored.
+    pub fn from_pem_dir<P: AsRef<Path>>(dir: P) -> Result<Self, TrustStoreError> {
+        let mut store = Self::new();
+        store.add_pem_dir(dir.as_ref(), false)?;
+        Ok(store)
+    }
+
+    /// Roots installed on this platform, found the way OpenSSL does (including the
+    /// `SSL_CERT_FILE` and `SSL_CERT_DIR` environment variables).
+    ///
+    /// Certificates this crate can't decode (e.g. X.509 v1 roots) are skipped.
+    #[cfg(feature = "system_trust_store")]
+    pub fn system() -> Result<Self, TrustStoreError> {
+        let probe = openssl_probe::probe();
+
+        let mut store = Self::new();
+        if let Some(file) = &probe.cert_file {
+            let bundle = fs::read_to_string(file).context(Io { path: file })?;
+            store.add_pem_bundle(&bundle, true)?;
+        }
+        if let Some(dir) = &probe.cert_dir {
+            store.add_pem_dir(dir, true)?;
+        }
+
+        if store.roots.is_empty() {
+            Err(TrustStoreError::NoSystemRoots)
+        } else {
+            Ok(store)
+        }
+    }
+
+    fn add_pem_bundle(&mut self, bundle: &str, skip_invalid: bool) -> Result<(), TrustStoreError> {
+        let mut cursor = 0;
+        while let Some(offset) = bundle[cursor..].find(PEM_HEADER_START) {
+            let block_start = cursor + offset;
+            cursor = block_start + PEM_HEADER_START.len();
+
+            let root = parse_pem(&bundle[block_start..]).context(InvalidPem).and_then(|pem| {
+                if pem.label() == CERT_PEM_LABEL {
+                    Cert::from_der(pem.data()).map(Some).context(InvalidRoot)
+                } else {
+                    Ok(None)
+                }
+            });
+
+            match root {
+                Ok(Some(root)) => self.add(root),
+                Ok(None) => {}
+                Err(_) if skip_invalid => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add_pem_dir(&mut self, dir: &Path, skip_invalid: bool) -> Result<(), TrustStoreError> {
+        let mut paths = fs::read_dir(dir)
+            .context(Io { path: dir })?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<PathBuf>, _>>()
+            .context(Io { path: dir })?;
+        paths.sort();
+
+        for path in paths {
+            match path.extension().and_then(|extension| extension.to_str()) {
+                Some("pem") | Some("crt") if path.is_file() => {}
+                _ => continue,
+            }
+
+            let bundle = fs::read_to_string(&path).context(Io { path: &path })?;
+            self.add_pem_bundle(&bundle, skip_invalid)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        key::PrivateKey,
+        pem::Pem,
+        x509::{
+            certificate::{CaChainError, CertificateBuilder},
+            date::UTCDate,
+            name::DirectoryName,
+        },
+    };
+
+    fn parse_key(pem_str: &str) -> PrivateKey {
+        let pem = pem_str.parse::<Pem>().unwrap();
+        PrivateKey::from_pem(&pem).unwrap()
+    }
+
+    fn self_signed_root(name: &str, key: &PrivateKey) -> Cert {
+        CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2065, 6, 15).unwrap(), UTCDate::ymd(2070, 6, 15).unwrap())
+            .self_signed(DirectoryName::new_common_name(name), key)
+            .ca(true)
+            .build()
+            .expect("couldn't build root ca")
+    }
+
+    fn issue(
+        subject: &str,
+        subject_key: &PrivateKey,
+        issuer: &Cert,
+        issuer_key: &PrivateKey,
+        valid_to: UTCDate,
+        ca: bool,
+    ) -> Cert {
+        CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2066, 1, 1).unwrap(), valid_to)
+            .subject(DirectoryName::new_common_name(subject), subject_key.to_public_key())
+            .issuer_cert(issuer, issuer_key)
+            .ca(ca)
+            .build()
+            .expect("couldn't build certificate")
+    }
+
+    fn now() -> UTCDate {
+        UTCDate::ymd(2069, 10, 1).unwrap()
+    }
+
+    #[test]
+    fn path_building() {
+        let root_key = parse_key(crate::test_files::RSA_2048_PK_1);
+        let policy_key = parse_key(crate::test_files::RSA_2048_PK_2);
+        let issuing_key = parse_key(crate::test_files::RSA_2048_PK_3);
+        let leaf_key = parse_key(crate::test_files::RSA_2048_PK_4);
+        let valid_to = UTCDate::ymd(2070, 1, 1).unwrap();
+
+        let root = self_signed_root("Example Root CA", &root_key);
+        let policy_ca = issue(
+            "Example Policy CA",
+            &policy_key,
+            &root,
+            &root_key,
+            valid_to.clone(),
+            true,
+        );
+        let issuing_ca = issue(
+            "Example Issuing CA",
+            &issuing_key,
+            &policy_ca,
+            &policy_key,
+            valid_to.clone(),
+            true,
+        );
+        // same name and key as the issuing CA, but expired: path building has to backtrack
+        let expired_issuing_ca = issue(
+            "Example Issuing CA",
+            &issuing_key,
+            &policy_ca,
+            &policy_key,
+            UTCDate::ymd(2068, 1, 1).unwrap(),
+            true,
+        );
+        let leaf = issue("www.example.com", &leaf_key, &issuing_ca, &issuing_key, valid_to, false);
+
+        let store = TrustStore::from(vec![root.clone()]);
+        let intermediates = [policy_ca.clone(), expired_issuing_ca, issuing_ca.clone()];
+        let chain = leaf
+            .verify_against(&store, &intermediates, &now())
+            .expect("couldn't build path");
+        assert_eq!(chain, vec![issuing_ca.clone(), policy_ca.clone(), root.clone()]);
+
+        // trusted roots verify against themselves
+        assert!(root.verify_against(&store, &[], &now()).unwrap().is_empty());
+
+        // issuing CA missing from the intermediates
+        let err = leaf.verify_against(&store, &[policy_ca.clone()], &now()).unwrap_err();
+        match err {
+            CertError::InvalidChain {
+                source: CaChainError::UnknownIssuer { cert_id, issuer_id },
+            } => {
+                assert_eq!(cert_id, "CN=www.example.com");
+                assert_eq!(issuer_id, "CN=Example Issuing CA");
+            }
+            err => panic!("unexpected error: {}", err),
+        }
+
+        // a self-signed root supplied among the intermediates isn't trusted
+        let other_root = self_signed_root("Other Root CA", &root_key);
+        let err = leaf
+            .verify_against(
+                &TrustStore::from(vec![other_root]),
+                &[policy_ca, issuing_ca, root],
+                &now(),
+            )
+            .unwrap_err();
+        match err {
+            CertError::InvalidChain {
+                source: CaChainError::NoTrustedPath,
+            } => {}
+            err => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn pem_loading() {
+        let key = parse_key(crate::test_files::RSA_2048_PK_1);
+        let first = self_signed_root("First Root CA", &key);
+        let second = self_signed_root("Second Root CA", &key);
+
+        let bundle = format!(
+            "# First Root CA\n{}\n# Second Root CA\n{}\n{}\n",
+            first.to_pem().unwrap(),
+            second.to_pem().unwrap(),
+            key.to_pem().unwrap(),
+        );
+        let store = TrustStore::from_pem_bundle(&bundle).expect("couldn't load bundle");
+        assert_eq!(store.roots(), &[first.clone(), second.clone()]);
+
+        let dir = std::env::temp_dir().join(format!("picky-trust-store-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("first.pem"), first.to_pem().unwrap().to_string()).unwrap();
+        fs::write(dir.join("second.crt"), bundle).unwrap();
+        fs::write(dir.join("README"), "not a certificate").unwrap();
+
+        let store = TrustStore::from_pem_dir(&dir).expect("couldn't load directory");
+        assert_eq!(store.roots(), &[first, second]);
+
+        fs::write(
+            dir.join("garbage.pem"),
+            "-----BEGIN CERTIFICATE-----GARBAGE-----END CERTIFICATE-----",
+        )
+        .unwrap();
+        assert!(TrustStore::from_pem_dir(&dir).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
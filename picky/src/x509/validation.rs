@@ -0,0 +1,771 @@
+//! Certificate chain validation policy.
+//!
+//! `Cert::verify_chain` checks validity periods, signatures, path length and name constraints.
+//! `ChainValidator` additionally requires issuers to be CAs allowed to sign certificates, can
+//! require an extended key usage, restrict the signature algorithms and reject revoked
+//! certificates, and reports which certificate of the chain is rejected.
+
+use crate::{
+    oids,
+    signature::SignatureHashType,
+    x509::{
+        certificate::{Cert, CertError, CertType, NameConstraintsViolation},
+        date::UTCDate,
+        extension::CrlReason,
+        revocation::{RevocationProvider, RevocationStatus},
+    },
+};
+use oid::ObjectIdentifier;
+use snafu::Snafu;
+use std::fmt;
+
+#[derive(Debug, Snafu)]
+pub enum ChainValidationError {
+    /// certificate is out of its validity period, isn't issued by the next certificate of the
+    /// chain or has an undecodable extension
+    #[snafu(display("certificate '{}' (depth {}) is invalid: {}", cert_id, depth, source))]
+    InvalidCertificate {
+        cert_id: String,
+        depth: usize,
+        #[snafu(source(from(CertError, Box::new)))]
+        source: Box<CertError>,
+    },
+
+    /// issuer certificate is not a CA
+    #[snafu(display("issuer certificate '{}' (depth {}) is not a CA", cert_id, depth))]
+    IssuerIsNotCa { cert_id: String, depth: usize },
+
+    /// chain depth doesn't satisfy the path length constraint of an issuer
+    #[snafu(display(
+        "chain depth doesn't satisfy basic constraints extension: certificate '{}' (depth {}) has pathlen of {}",
+        cert_id,
+        depth,
+        pathlen
+    ))]
+    PathLenExceeded { cert_id: String, depth: usize, pathlen: u8 },
+
+    /// key usage of an issuer certificate doesn't allow certificate signing
+    #[snafu(display(
+        "key usage of issuer certificate '{}' (depth {}) doesn't allow certificate signing",
+        cert_id,
+        depth
+    ))]
+    MissingKeyCertSign { cert_id: String, depth: usize },
+
+    /// extended key usage of a certificate doesn't include the required purpose
+    #[snafu(display(
+        "extended key usage of certificate '{}' (depth {}) doesn't include {}",
+        cert_id,
+        depth,
+        purpose
+    ))]
+    ExtendedKeyUsageMismatch {
+        cert_id: String,
+        depth: usize,
+        purpose: String,
+    },
+
+    /// name isn't permitted by the name constraints of an issuer
+    #[snafu(display(
+        "name '{}' of certificate '{}' (depth {}) isn't permitted by name constraints of '{}'",
+        name,
+        cert_id,
+        depth,
+        issuer_id
+    ))]
+    NameNotPermitted {
+        cert_id: String,
+        depth: usize,
+        issuer_id: String,
+        name: String,
+    },
+
+    /// certificate is signed with an algorithm rejected by the validator
+    #[snafu(display(
+        "certificate '{}' (depth {}) is signed with disallowed algorithm {}",
+        cert_id,
+        depth,
+        algorithm
+    ))]
+    SignatureAlgorithmNotAllowed {
+        cert_id: String,
+        depth: usize,
+        algorithm: String,
+    },
+
+    /// chain doesn't end with a root certificate
+    #[snafu(display("chain ends with certificate '{}' (depth {}) which isn't a root CA", cert_id, depth))]
+    NoRoot { cert_id: String, depth: usize },
+
+    /// certificate is revoked
+    #[snafu(display(
+        "certificate '{}' (depth {}) is revoked (since: {})",
+        cert_id,
+        depth,
+        revocation_time
+    ))]
+    Revoked {
+        cert_id: String,
+        depth: usize,
+        revocation_time: UTCDate,
+        reason: Option<CrlReason>,
+    },
+
+    /// no current revocation information is available for a certificate
+    #[snafu(display("revocation status of certificate '{}' (depth {}) is unknown", cert_id, depth))]
+    RevocationStatusUnknown { cert_id: String, depth: usize },
+}
+
+static_assertions::assert_impl_all!(ChainValidationError: Send, Sync);
+
+impl ChainValidationError {
+    /// Subject name of the rejected certificate
+    pub fn cert_id(&self) -> &str {
+        match self {
+            ChainValidationError::InvalidCertificate { cert_id, .. }
+            | ChainValidationError::IssuerIsNotCa { cert_id, .. }
+            | ChainValidationError::PathLenExceeded { cert_id, .. }
+            | ChainValidationError::MissingKeyCertSign { cert_id, .. }
+            | ChainValidationError::ExtendedKeyUsageMismatch { cert_id, .. }
+            | ChainValidationError::NameNotPermitted { cert_id, .. }
+            | ChainValidationError::SignatureAlgorithmNotAllowed { cert_id, .. }
+            | ChainValidationError::NoRoot { cert_id, .. }
+            | ChainValidationError::Revoked { cert_id, .. }
+            | ChainValidationError::RevocationStatusUnknown { cert_id, .. } => cert_id,
+        }
+    }
+
+    /// Position of the rejected certificate in the chain, the leaf being at depth 0
+    pub fn depth(&self) -> usize {
+        match self {
+            ChainValidationError::InvalidCertificate { depth, .. }
+            | ChainValidationError::IssuerIsNotCa { depth, .. }
+            | ChainValidationError::PathLenExceeded { depth, .. }
+            | ChainValidationError::MissingKeyCertSign { depth, .. }
+            | ChainValidationError::ExtendedKeyUsageMismatch { depth, .. }
+            | ChainValidationError::NameNotPermitted { depth, .. }
+            | ChainValidationError::SignatureAlgorithmNotAllowed { depth, .. }
+            | ChainValidationError::NoRoot { depth, .. }
+            | ChainValidationError::Revoked { depth, .. }
+            | ChainValidationError::RevocationStatusUnknown { depth, .. } => *depth,
+        }
+    }
+}
+
+/// ```
+/// use picky::{
+///     key::PrivateKey,
+///     oids,
+///     signature::SignatureHashType,
+///     x509::{date::UTCDate, name::DirectoryName, validation::ChainValidator, CertificateBuilder},
+/// };
+///
+/// let key = PrivateKey::generate_rsa(2048).unwrap();
+/// let ca = CertificateBuilder::new()
+///     .valididy(UTCDate::ymd(2020, 1, 1).unwrap(), UTCDate::ymd(2030, 1, 1).unwrap())
+///     .self_signed(DirectoryName::new_common_name("Example Root CA"), &key)
+///     .ca(true)
+///     .build()
+///     .unwrap();
+/// let leaf = CertificateBuilder::new()
+///     .valididy(UTCDate::ymd(2021, 1, 1).unwrap(), UTCDate::ymd(2022, 1, 1).unwrap())
+///     .subject(DirectoryName::new_common_name("www.example.com"), key.to_public_key())
+///     .issuer_cert(&ca, &key)
+///     .extended_key_usage(vec![oids::kp_server_auth()].into())
+///     .build()
+///     .unwrap();
+///
+/// let now = UTCDate::ymd(2021, 6, 1).unwrap();
+/// ChainValidator::new(&now)
+///     .required_extended_key_usage(oids::kp_server_auth())
+///     .allowed_signature_algorithms(&[SignatureHashType::RsaSha256, SignatureHashType::RsaSha384])
+///     .validate(&leaf, std::iter::once(&ca))
+///     .expect("valid chain");
+/// ```
+#[derive(Clone)]
+pub struct ChainValidator<'a> {
+    now: &'a UTCDate,
+    extended_key_usage: Option<ObjectIdentifier>,
+    signature_algorithms: Option<&'a [SignatureHashType]>,
+    revocation_provider: Option<&'a dyn RevocationProvider>,
+    require_revocation_status: bool,
+}
+
+impl fmt::Debug for ChainValidator<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChainValidator")
+            .field("now", &self.now)
+            .field("extended_key_usage", &self.extended_key_usage)
+            .field("signature_algorithms", &self.signature_algorithms)
+            .field("revocation_checked", &self.revocation_provider.is_some())
+            .field("require_revocation_status", &self.require_revocation_status)
+            .finish()
+    }
+}
+
+impl<'a> ChainValidator<'a> {
+    /// Checks validity periods at `now`, signatures, basic constraints, key usage of issuers and
+    /// name constraints. No extended key usage is required and any signature algorithm is allowed.
+    pub fn new(now: &'a UTCDate) -> Self {
+        Self {
+            now,
+            extended_key_usage: None,
+            signature_algorithms: None,
+            revocation_provider: None,
+            require_revocation_status: false,
+        }
+    }
+
+    /// Reject chains whose certificates have an extended key usage extension including neither
+    /// `purpose` nor `anyExtendedKeyUsage`.
+    ///
+    /// The extended key usages of CA certificates restrict the purposes they may issue for.
+    pub fn required_extended_key_usage(self, purpose: ObjectIdentifier) -> Self {
+        Self {
+            extended_key_usage: Some(purpose),
+            ..self
+        }
+    }
+
+    /// Reject certificates signed with an algorithm not in `algorithms` (e.g. to reject SHA-1).
+    ///
+    /// The self-signature of the root CA isn't verified and its algorithm isn't checked either.
+    pub fn allowed_signature_algorithms(self, algorithms: &'a [SignatureHashType]) -> Self {
+        Self {
+            signature_algorithms: Some(algorithms),
+            ..self
+        }
+    }
+
+    /// Reject certificates revoked according to `provider` (e.g. a `Vec<Crl>` or an `OcspLookup`).
+    ///
+    /// Revocation is checked once the rest of the chain is valid, for every certificate but the
+    /// root CA. Certificates whose revocation status is unknown are accepted unless
+    /// `require_revocation_status` is set.
+    pub fn revocation_provider(self, provider: &'a dyn RevocationProvider) -> Self {
+        Self {
+            revocation_provider: Some(provider),
+            ..self
+        }
+    }
+
+    /// Reject certificates whose revocation status is unknown to the revocation provider.
+    pub fn require_revocation_status(self, required: bool) -> Self {
+        Self {
+            require_revocation_status: required,
+            ..self
+        }
+    }
+
+    /// Validates `leaf` against `chain`, which starts with the issuer of `leaf` and ends with the
+    /// root CA.
+    pub fn validate<'b, Chain: Iterator<Item = &'b Cert>>(
+        &self,
+        leaf: &Cert,
+        chain: Chain,
+    ) -> Result<(), ChainValidationError> {
+        self.validate_cert(leaf, 0)?;
+
+        let mut current_cert = leaf;
+        let mut depth = 0;
+        let mut certs = vec![leaf];
+        // certificates subject to the name constraints of the following issuers, with their depth
+        let mut constrained_certs = vec![(leaf, 0)];
+
+        for parent_cert in chain {
+            depth += 1;
+            self.validate_cert(parent_cert, depth)?;
+            self.validate_issuer(parent_cert, depth)?;
+            self.validate_issued_by(current_cert, depth - 1, parent_cert)?;
+            for (cert, cert_depth) in &constrained_certs {
+                validate_name_constraints(cert, *cert_depth, parent_cert, depth)?;
+            }
+
+            // self-issued intermediate certificates are exempted (RFC 5280, section 6.1.3)
+            if parent_cert.subject_name() != parent_cert.issuer_name() {
+                constrained_certs.push((parent_cert, depth));
+            }
+            certs.push(parent_cert);
+            current_cert = parent_cert;
+        }
+
+        if current_cert.ty() != CertType::Root {
+            return Err(ChainValidationError::NoRoot {
+                cert_id: current_cert.subject_name().to_string(),
+                depth,
+            });
+        }
+
+        if let Some(provider) = self.revocation_provider {
+            for (depth, pair) in certs.windows(2).enumerate() {
+                self.validate_revocation_status(provider, pair[0], depth, pair[1])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the certificate at `depth`, issued by `issuer_cert`, isn't revoked.
+    fn validate_revocation_status(
+        &self,
+        provider: &dyn RevocationProvider,
+        cert: &Cert,
+        depth: usize,
+        issuer_cert: &Cert,
+    ) -> Result<(), ChainValidationError> {
+        match provider.revocation_status(cert, issuer_cert, self.now) {
+            RevocationStatus::Good => Ok(()),
+            RevocationStatus::Revoked {
+                revocation_time,
+                reason,
+            } => Err(ChainValidationError::Revoked {
+                cert_id: cert.subject_name().to_string(),
+                depth,
+                revocation_time,
+                reason,
+            }),
+            RevocationStatus::Unknown if self.require_revocation_status => {
+                Err(ChainValidationError::RevocationStatusUnknown {
+                    cert_id: cert.subject_name().to_string(),
+                    depth,
+                })
+            }
+            RevocationStatus::Unknown => Ok(()),
+        }
+    }
+
+    /// Checks the validity period and extended key usage of the certificate at `depth`.
+    fn validate_cert(&self, cert: &Cert, depth: usize) -> Result<(), ChainValidationError> {
+        cert.verify(self.now).map_err(|e| invalid_certificate(cert, depth, e))?;
+
+        if let Some(purpose) = &self.extended_key_usage {
+            let extended_key_usage = match cert.extended_key_usage() {
+                Ok(extended_key_usage) => extended_key_usage,
+                Err(CertError::ExtensionNotFound { .. }) => return Ok(()),
+                Err(e) => return Err(invalid_certificate(cert, depth, e)),
+            };
+
+            if !extended_key_usage.contains(purpose.clone())
+                && !extended_key_usage.contains(oids::kp_any_extended_key_usage())
+            {
+                return Err(ChainValidationError::ExtendedKeyUsageMismatch {
+                    cert_id: cert.subject_name().to_string(),
+                    depth,
+                    purpose: purpose.into(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the certificate at `depth` is a CA allowed to sign the certificates below it.
+    fn validate_issuer(&self, issuer_cert: &Cert, depth: usize) -> Result<(), ChainValidationError> {
+        let basic_constraints = issuer_cert.basic_constraints().map_err(|e| match e {
+            CertError::ExtensionNotFound { .. } => ChainValidationError::IssuerIsNotCa {
+                cert_id: issuer_cert.subject_name().to_string(),
+                depth,
+            },
+            e => invalid_certificate(issuer_cert, depth, e),
+        })?;
+
+        if basic_constraints.ca() != Some(true) {
+            return Err(ChainValidationError::IssuerIsNotCa {
+                cert_id: issuer_cert.subject_name().to_string(),
+                depth,
+            });
+        }
+
+        // `depth - 1` certificates are between the issuer and the leaf
+        if let Some(pathlen) = basic_constraints.pathlen() {
+            if usize::from(pathlen) < depth - 1 {
+                return Err(ChainValidationError::PathLenExceeded {
+                    cert_id: issuer_cert.subject_name().to_string(),
+                    depth,
+                    pathlen,
+                });
+            }
+        }
+
+        match issuer_cert.key_usage() {
+            Ok(key_usage) if !key_usage.key_cert_sign() => Err(ChainValidationError::MissingKeyCertSign {
+                cert_id: issuer_cert.subject_name().to_string(),
+                depth,
+            }),
+            Ok(_) | Err(CertError::ExtensionNotFound { .. }) => Ok(()),
+            Err(e) => Err(invalid_certificate(issuer_cert, depth, e)),
+        }
+    }
+
+    /// Checks that the certificate at `depth` is signed by `parent_cert` with an allowed algorithm.
+    fn validate_issued_by(&self, cert: &Cert, depth: usize, parent_cert: &Cert) -> Result<(), ChainValidationError> {
+        parent_cert
+            .is_parent_of(cert)
+            .map_err(|e| invalid_certificate(cert, depth, e))?;
+
+        if let Some(algorithms) = self.signature_algorithms {
+            let allowed = SignatureHashType::from_algorithm_identifier(cert.signature_algorithm())
+                .map(|hash_type| algorithms.contains(&hash_type))
+                .unwrap_or(false);
+            if !allowed {
+                return Err(ChainValidationError::SignatureAlgorithmNotAllowed {
+                    cert_id: cert.subject_name().to_string(),
+                    depth,
+                    algorithm: cert.signature_algorithm().oid().into(),
+                });
+            }
+        }
+
+        cert.verify_signature(parent_cert)
+            .map_err(|e| invalid_certificate(cert, depth, e))
+    }
+}
+
+/// Checks the names of the certificate at `depth` against the name constraints of the issuer at
+/// `issuer_depth`, the same way `Cert::verify_chain` does.
+fn validate_name_constraints(
+    cert: &Cert,
+    depth: usize,
+    issuer_cert: &Cert,
+    issuer_depth: usize,
+) -> Result<(), ChainValidationError> {
+    match cert.check_name_constraints(issuer_cert) {
+        Ok(()) => Ok(()),
+        Err(NameConstraintsViolation::InvalidIssuer(e)) => Err(invalid_certificate(issuer_cert, issuer_depth, e)),
+        Err(NameConstraintsViolation::InvalidCert(e)) => Err(invalid_certificate(cert, depth, e)),
+        Err(NameConstraintsViolation::NameNotPermitted(name)) => Err(ChainValidationError::NameNotPermitted {
+            cert_id: cert.subject_name().to_string(),
+            depth,
+            issuer_id: issuer_cert.subject_name().to_string(),
+            name,
+        }),
+    }
+}
+
+fn invalid_certificate(cert: &Cert, depth: usize, source: CertError) -> ChainValidationError {
+    ChainValidationError::InvalidCertificate {
+        cert_id: cert.subject_name().to_string(),
+        depth,
+        source: Box::new(source),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        key::PrivateKey,
+        pem::Pem,
+        x509::{
+            certificate::CertificateBuilder,
+            crl::{Crl, RevokedCertificate},
+            extension::{KeyUsage, NameConstraints},
+            name::{DirectoryName, GeneralName, GeneralNames},
+        },
+    };
+
+    fn parse_key(pem_str: &str) -> PrivateKey {
+        let pem = pem_str.parse::<Pem>().unwrap();
+        PrivateKey::from_pem(&pem).unwrap()
+    }
+
+    struct Keys {
+        root: PrivateKey,
+        intermediate: PrivateKey,
+        leaf: PrivateKey,
+    }
+
+    fn keys() -> Keys {
+        Keys {
+            root: parse_key(crate::test_files::RSA_2048_PK_1),
+            intermediate: parse_key(crate::test_files::RSA_2048_PK_2),
+            leaf: parse_key(crate::test_files::RSA_2048_PK_3),
+        }
+    }
+
+    fn root(keys: &Keys) -> Cert {
+        CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2065, 6, 15).unwrap(), UTCDate::ymd(2070, 6, 15).unwrap())
+            .self_signed(DirectoryName::new_common_name("Example Root CA"), &keys.root)
+            .ca(true)
+            .pathlen(1)
+            .signature_hash_type(SignatureHashType::RsaSha1)
+            .build()
+            .expect("couldn't build root ca")
+    }
+
+    fn leaf(keys: &Keys, issuer: &Cert, configure: impl Fn(&CertificateBuilder)) -> Cert {
+        let builder = CertificateBuilder::new();
+        builder
+            .valididy(UTCDate::ymd(2069, 1, 1).unwrap(), UTCDate::ymd(2072, 1, 1).unwrap())
+            .subject(
+                DirectoryName::new_common_name("www.example.com"),
+                keys.leaf.to_public_key(),
+            )
+            .subject_alt_name(GeneralNames::new(GeneralName::new_dns_name("www.example.com").unwrap()))
+            .issuer_cert(issuer, &keys.intermediate);
+        configure(&builder);
+        builder.build().expect("couldn't build leaf")
+    }
+
+    fn intermediate(keys: &Keys, root: &Cert, configure: impl Fn(&CertificateBuilder)) -> Cert {
+        let builder = CertificateBuilder::new();
+        builder
+            .valididy(UTCDate::ymd(2068, 1, 1).unwrap(), UTCDate::ymd(2071, 1, 1).unwrap())
+            .subject(
+                DirectoryName::new_common_name("Example Authority"),
+                keys.intermediate.to_public_key(),
+            )
+            .issuer_cert(root, &keys.root)
+            .ca(true);
+        configure(&builder);
+        builder.build().expect("couldn't build intermediate ca")
+    }
+
+    fn now() -> UTCDate {
+        UTCDate::ymd(2069, 10, 1).unwrap()
+    }
+
+    #[test]
+    fn valid_chain() {
+        let keys = keys();
+        let root = root(&keys);
+        let intermediate = intermediate(&keys, &root, |builder| {
+            builder
+                .key_usage(KeyUsage::builder().key_cert_sign().crl_sign().build())
+                .extended_key_usage(vec![oids::kp_server_auth(), oids::kp_client_auth()].into());
+        });
+        let leaf = leaf(&keys, &intermediate, |builder| {
+            builder.extended_key_usage(vec![oids::kp_server_auth()].into());
+        });
+        let chain = [intermediate, root];
+        let now = now();
+
+        ChainValidator::new(&now)
+            .required_extended_key_usage(oids::kp_server_auth())
+            .allowed_signature_algorithms(&[SignatureHashType::RsaSha256])
+            .validate(&leaf, chain.iter())
+            .expect("valid chain");
+
+        let err = ChainValidator::new(&now)
+            .required_extended_key_usage(oids::kp_code_signing())
+            .validate(&leaf, chain.iter())
+            .unwrap_err();
+        assert_eq!(err.cert_id(), "CN=www.example.com");
+        assert_eq!(err.depth(), 0);
+        assert_eq!(
+            err.to_string(),
+            "extended key usage of certificate 'CN=www.example.com' (depth 0) doesn't include 1.3.6.1.5.5.7.3.3"
+        );
+
+        let err = ChainValidator::new(&now)
+            .validate(&leaf, chain[..1].iter())
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "chain ends with certificate 'CN=Example Authority' (depth 1) which isn't a root CA"
+        );
+
+        let later = UTCDate::ymd(2071, 6, 1).unwrap();
+        let err = ChainValidator::new(&later).validate(&leaf, chain.iter()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "certificate 'CN=Example Authority' (depth 1) is invalid: \
+             certificate expired (not after: 2071-01-01 00:00:00, now: 2071-06-01 00:00:00)"
+        );
+    }
+
+    #[test]
+    fn issuer_constraints() {
+        let keys = keys();
+        let root = root(&keys);
+        let now = now();
+
+        let intermediate_without_key_cert_sign = intermediate(&keys, &root, |builder| {
+            builder.key_usage(KeyUsage::builder().digital_signature().crl_sign().build());
+        });
+        let leaf_cert = leaf(&keys, &intermediate_without_key_cert_sign, |_| {});
+        let err = ChainValidator::new(&now)
+            .validate(&leaf_cert, [intermediate_without_key_cert_sign, root.clone()].iter())
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "key usage of issuer certificate 'CN=Example Authority' (depth 1) doesn't allow certificate signing"
+        );
+
+        let not_ca = intermediate(&keys, &root, |builder| {
+            builder.ca(false);
+        });
+        let leaf_cert = leaf(&keys, &not_ca, |_| {});
+        let err = ChainValidator::new(&now)
+            .validate(&leaf_cert, [not_ca, root.clone()].iter())
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "issuer certificate 'CN=Example Authority' (depth 1) is not a CA"
+        );
+
+        // server authentication isn't among the purposes the intermediate may issue for
+        let code_signing_ca = intermediate(&keys, &root, |builder| {
+            builder.extended_key_usage(vec![oids::kp_code_signing()].into());
+        });
+        let leaf_cert = leaf(&keys, &code_signing_ca, |_| {});
+        let err = ChainValidator::new(&now)
+            .required_extended_key_usage(oids::kp_server_auth())
+            .validate(&leaf_cert, [code_signing_ca, root.clone()].iter())
+            .unwrap_err();
+        assert_eq!(err.cert_id(), "CN=Example Authority");
+        assert_eq!(err.depth(), 1);
+
+        // root has a pathlen of 1: only one intermediate may follow
+        let intermediate_cert = intermediate(&keys, &root, |_| {});
+        let second_intermediate = CertificateBuilder::new()
+            .valididy(UTCDate::ymd(2068, 1, 1).unwrap(), UTCDate::ymd(2071, 1, 1).unwrap())
+            .subject(
+                DirectoryName::new_common_name("Example Sub Authority"),
+                keys.intermediate.to_public_key(),
+            )
+            .issuer_cert(&intermediate_cert, &keys.intermediate)
+            .ca(true)
+            .build()
+            .expect("couldn't build second intermediate ca");
+        let leaf_cert = leaf(&keys, &second_intermediate, |_| {});
+        let err = ChainValidator::new(&now)
+            .validate(&leaf_cert, [second_intermediate, intermediate_cert, root].iter())
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "chain depth doesn't satisfy basic constraints extension: \
+             certificate 'CN=Example Root CA' (depth 3) has pathlen of 1"
+        );
+    }
+
+    #[test]
+    fn name_constraints_and_algorithms() {
+        let keys = keys();
+        let root = root(&keys);
+        let now = now();
+
+        let intermediate_cert = intermediate(&keys, &root, |builder| {
+            builder
+                .name_constraints(
+                    NameConstraints::new().permitted_subtree(GeneralName::new_dns_name("example.org").unwrap()),
+                )
+                .signature_hash_type(SignatureHashType::RsaSha1);
+        });
+        let leaf_cert = leaf(&keys, &intermediate_cert, |_| {});
+        let chain = [intermediate_cert, root];
+
+        let err = ChainValidator::new(&now)
+            .validate(&leaf_cert, chain.iter())
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "name 'www.example.com' of certificate 'CN=www.example.com' (depth 0) \
+             isn't permitted by name constraints of 'CN=Example Authority'"
+        );
+
+        // the intermediate is signed with SHA-1, the SHA-1 self-signature of the root isn't checked
+        let leaf_cert = leaf(&keys, &chain[0], |builder| {
+            builder.subject_alt_name(GeneralNames::new(GeneralName::new_dns_name("www.example.org").unwrap()));
+        });
+        ChainValidator::new(&now)
+            .validate(&leaf_cert, chain.iter())
+            .expect("valid chain");
+        let err = ChainValidator::new(&now)
+            .allowed_signature_algorithms(&[SignatureHashType::RsaSha256, SignatureHashType::RsaSha384])
+            .validate(&leaf_cert, chain.iter())
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "certificate 'CN=Example Authority' (depth 1) is signed with disallowed algorithm 1.2.840.113549.1.1.5"
+        );
+    }
+
+    #[test]
+    fn unenforced_critical_name_constraints() {
+        let keys = keys();
+        let root = root(&keys);
+        let now = now();
+
+        // permits 10.0.0.0/8, IP address constraints aren't enforced
+        let intermediate_cert = intermediate(&keys, &root, |builder| {
+            builder.name_constraints(
+                NameConstraints::new().permitted_subtree(GeneralName::IpAddress(vec![10, 0, 0, 0, 255, 0, 0, 0])),
+            );
+        });
+        let chain = [intermediate_cert, root];
+
+        // names of other forms aren't affected
+        let dns_leaf = leaf(&keys, &chain[0], |_| {});
+        ChainValidator::new(&now)
+            .validate(&dns_leaf, chain.iter())
+            .expect("valid chain");
+
+        let ip_leaf = leaf(&keys, &chain[0], |builder| {
+            builder.subject_alt_name(GeneralNames::new(GeneralName::IpAddress(vec![10, 1, 2, 3])));
+        });
+        let err = ChainValidator::new(&now).validate(&ip_leaf, chain.iter()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "name '10.1.2.3' of certificate 'CN=www.example.com' (depth 0) \
+             isn't permitted by name constraints of 'CN=Example Authority'"
+        );
+        assert_eq!(
+            ip_leaf.verify_chain(chain.iter(), &now).unwrap_err().to_string(),
+            "CA chain error: name '10.1.2.3' of certificate 'CN=www.example.com' \
+             isn't permitted by name constraints of 'CN=Example Authority'"
+        );
+    }
+
+    #[test]
+    fn revocation() {
+        let keys = keys();
+        let root = root(&keys);
+        let intermediate_cert = intermediate(&keys, &root, |_| {});
+        let good = leaf(&keys, &intermediate_cert, |builder| {
+            builder.serial_number(vec![0x0A]);
+        });
+        let revoked = leaf(&keys, &intermediate_cert, |builder| {
+            builder.serial_number(vec![0x0B]);
+        });
+        let chain = [intermediate_cert, root];
+        let now = now();
+
+        let crls = vec![Crl::builder()
+            .issuer_cert(&chain[0], &keys.intermediate)
+            .this_update(UTCDate::ymd(2069, 9, 1).unwrap())
+            .next_update(UTCDate::ymd(2069, 11, 1).unwrap())
+            .revoked_certificate(
+                RevokedCertificate::new(vec![0x0B].into(), UTCDate::ymd(2069, 9, 15).unwrap())
+                    .with_reason(CrlReason::KeyCompromise),
+            )
+            .build()
+            .unwrap()];
+
+        // the intermediate itself isn't covered by a CRL of the root
+        ChainValidator::new(&now)
+            .revocation_provider(&crls)
+            .validate(&good, chain.iter())
+            .expect("good certificate");
+        let err = ChainValidator::new(&now)
+            .revocation_provider(&crls)
+            .require_revocation_status(true)
+            .validate(&good, chain.iter())
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "revocation status of certificate 'CN=Example Authority' (depth 1) is unknown"
+        );
+
+        let err = ChainValidator::new(&now)
+            .revocation_provider(&crls)
+            .validate(&revoked, chain.iter())
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "certificate 'CN=www.example.com' (depth 0) is revoked (since: 2069-09-15 00:00:00)"
+        );
+        assert_eq!(err.depth(), 0);
+    }
+}
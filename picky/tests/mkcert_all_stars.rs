@@ -1,6 +1,9 @@
 #![cfg(feature = "x509")]
 //! This test attempts to parse all root certificate provided by https://mkcert.org/
 //! Data fetched on the 2019/10 are in a file named `mkcert_all_root_ca_2019_10.txt`.
+//!
+//! Parsed certificates must re-encode to the exact same DER: third-party certificates are
+//! re-published and any change would break their signature.
 
 use picky::{pem::parse_pem, x509::Cert};
 use std::{cmp::min, fs};
@@ -54,14 +57,17 @@ fn all_stars_parsing() {
         match Cert::from_der(pem.data()) {
             Ok(cert) => {
                 println!("Decoded CA: {}", cert.issuer_name().to_string());
+                let der = cert.to_der().expect("couldn't re-encode certificate");
+                if der != pem.data() {
+                    eprintln!("Certificate at cursor = {} doesn't round-trip", cursor);
+                    print_context(&contents, cursor);
+                    panic!("re-encoded certificate differs from the original");
+                }
                 number_decoded += 1;
             }
             Err(e) => {
                 let formatted_str = e.to_string();
-                if formatted_str.contains("1.2.840.113549.1.9.1 (e-mailAddress) attribute is deprecated")
-                    || formatted_str.contains("TeletexString not supported")
-                    || formatted_str.contains("V1 certificates unsupported")
-                {
+                if formatted_str.contains("V1 certificates unsupported") {
                     // these won't be supported
                     eprintln!(
                         "Couldn't parse certificate (cursor = {}) [won't support]: {}",
@@ -84,6 +90,6 @@ fn all_stars_parsing() {
         number_decoded, total_certificates
     );
 
-    // we currently support 132 certificates out of the 136.
-    assert!(number_decoded >= 132);
+    // we currently support 135 certificates out of the 136.
+    assert!(number_decoded >= 135);
 }